@@ -0,0 +1,332 @@
+#![deny(clippy::all)]
+//! Builds procedural stress scenes and times the engine over a fixed number of frames, so
+//! performance regressions across the ECS and paint path show up as numbers.
+//!
+//! `cargo run --release -p fluster_bench -- --containers=20000 --tweens=5000 --depth=200
+//! --layers=8 --frames=600`
+
+use fluster_core::actions::{
+    BoundsKindDefinition, ContainerCreationDefintition, ContainerCreationProperty,
+    ContainerUpdateDefintition, ContainerUpdateProperty,
+};
+use fluster_core::ecs::resources::{
+    FrameTime, Library, QuadTreeLayer, QuadTreeLayerOptions, QuadTrees, RenderQuality,
+};
+use fluster_core::engine::Engine;
+use fluster_core::rendering::{paint, BackgroundLayer, Renderer};
+use fluster_core::tween::Easing;
+use fluster_core::types::{
+    basic::{ContainerId, LibraryId, ScaleRotationTranslation},
+    coloring::{ColorSpace, Coloring},
+    shapes::{Contour, Edge, Shape},
+};
+use palette::LinSrgba;
+use pathfinder_content::pattern::Pattern;
+use pathfinder_geometry::rect::RectF;
+use pathfinder_geometry::transform2d::Transform2F;
+use pathfinder_geometry::vector::Vector2F;
+use std::{
+    collections::HashMap,
+    env, process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const STAGE_SIZE: f32 = 4096.0;
+const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+
+struct StressOptions {
+    /// Containers in the scene, not counting the root.
+    containers: usize,
+    /// Containers whose transform is tweened for the whole run.
+    tweens: usize,
+    /// Length of the single chain of nested containers. The rest are parented to the root.
+    depth: usize,
+    /// Quad tree layers the containers are spread across.
+    layers: u32,
+    frames: usize,
+    render_quality: RenderQuality,
+}
+
+impl Default for StressOptions {
+    fn default() -> Self {
+        Self {
+            containers: 10_000,
+            tweens: 2_000,
+            depth: 100,
+            layers: 4,
+            frames: 300,
+            render_quality: RenderQuality::default(),
+        }
+    }
+}
+
+fn find_arg(prefix: &str) -> Option<String> {
+    env::args()
+        .skip(1)
+        .find(|arg| arg.starts_with(prefix))
+        .map(|arg| arg[prefix.len()..].to_string())
+}
+
+fn parse_arg<T: std::str::FromStr>(prefix: &str, default: T) -> Result<T, String> {
+    match find_arg(prefix) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid value for {}: {:?}", prefix, value)),
+        None => Ok(default),
+    }
+}
+
+fn options_from_args() -> Result<StressOptions, String> {
+    let default = StressOptions::default();
+    let options = StressOptions {
+        containers: parse_arg("--containers=", default.containers)?,
+        tweens: parse_arg("--tweens=", default.tweens)?,
+        depth: parse_arg("--depth=", default.depth)?,
+        layers: parse_arg("--layers=", default.layers)?,
+        frames: parse_arg("--frames=", default.frames)?,
+        render_quality: if env::args().skip(1).any(|arg| arg == "--low-quality") {
+            RenderQuality::low()
+        } else {
+            default.render_quality
+        },
+    };
+    if options.depth > options.containers {
+        return Err("--depth can't be larger than --containers".to_string());
+    }
+    if options.layers == 0 {
+        return Err("--layers must be at least 1".to_string());
+    }
+    Ok(options)
+}
+
+// A few shapes of different complexity, so batching and outline building both get exercised
+fn define_shapes(library: &mut Library, render_quality: RenderQuality) -> Vec<LibraryId> {
+    let color = LinSrgba::new(0.2, 0.4, 0.8, 1.0);
+    let shapes = vec![
+        Shape::Fill {
+            contours: Contour::from_edges(&Edge::new_rect(
+                Vector2F::splat(16.0),
+                Transform2F::default(),
+            )),
+            color,
+        },
+        Shape::Fill {
+            contours: Contour::from_edges(&Edge::new_ellipse(
+                Vector2F::splat(8.0),
+                Transform2F::default(),
+            )),
+            color,
+        },
+        Shape::Fill {
+            contours: Contour::from_edges(&Edge::new_superellipse_with_steps(
+                Vector2F::splat(24.0),
+                4.0,
+                render_quality.procedural_steps,
+                Transform2F::default(),
+            )),
+            color,
+        },
+    ];
+    shapes
+        .into_iter()
+        .map(|shape| {
+            let id = LibraryId::new();
+            library.add_shape(id, shape);
+            id
+        })
+        .collect()
+}
+
+// Spread containers over the stage deterministically, so runs are comparable
+fn position(index: usize) -> Vector2F {
+    let columns = 128;
+    let spacing = STAGE_SIZE / columns as f32;
+    Vector2F::new(
+        (index % columns) as f32 * spacing,
+        (index / columns % columns) as f32 * spacing,
+    )
+}
+
+fn build_scene(options: &StressOptions) -> Result<(Engine, Vec<ContainerId>), String> {
+    let root_container_id = ContainerId::new();
+    let mut library = Library::default();
+    let shape_ids = define_shapes(&mut library, options.render_quality);
+    let mut engine = Engine::new(root_container_id, library, QuadTrees::default());
+    engine.set_render_quality(options.render_quality);
+    let stage = RectF::new(Vector2F::zero(), Vector2F::splat(STAGE_SIZE));
+    for layer in 0..options.layers {
+        engine.declare_quad_tree_layer(
+            &format!("stress_{}", layer),
+            QuadTreeLayer::new(layer),
+            stage,
+            QuadTreeLayerOptions::default(),
+        )?;
+    }
+    let mut container_ids = Vec::with_capacity(options.containers);
+    let mut chain_parent = root_container_id;
+    for index in 0..options.containers {
+        let container_id = ContainerId::new();
+        // The chain is built first, each link nudged so the offsets accumulate down the hierarchy
+        let (parent, translation) = if index < options.depth {
+            let parent = chain_parent;
+            chain_parent = container_id;
+            (parent, Vector2F::splat(1.0))
+        } else {
+            (root_container_id, position(index))
+        };
+        engine.create_container(&ContainerCreationDefintition::new(
+            parent,
+            container_id,
+            vec![
+                ContainerCreationProperty::Transform(ScaleRotationTranslation::new(
+                    Vector2F::splat(1.0),
+                    0.0,
+                    translation,
+                )),
+                ContainerCreationProperty::Display(shape_ids[index % shape_ids.len()]),
+                ContainerCreationProperty::Layer(QuadTreeLayer::new(index as u32 % options.layers)),
+                ContainerCreationProperty::Bounds(BoundsKindDefinition::Display),
+            ],
+        ));
+        container_ids.push(container_id);
+    }
+    // The chain is tweened first, so every moving link dirties the world transforms below it
+    for (index, container_id) in container_ids.iter().take(options.tweens).enumerate() {
+        engine.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![
+                ContainerUpdateProperty::Transform(
+                    ScaleRotationTranslation::new(
+                        Vector2F::splat(2.0),
+                        1.0,
+                        position(index) + Vector2F::splat(32.0),
+                    ),
+                    Easing::CubicInOut,
+                    options.frames as u32,
+                ),
+                ContainerUpdateProperty::Coloring(
+                    Coloring::Color(LinSrgba::new(1.0, 0.5, 0.0, 1.0)),
+                    ColorSpace::Linear,
+                    Easing::Linear,
+                    options.frames as u32,
+                ),
+            ],
+        ));
+    }
+    Ok((engine, container_ids))
+}
+
+// Counts what would be drawn, so paint is timed without a GPU in the way
+#[derive(Default)]
+struct CountingRenderer {
+    shapes: usize,
+    rasters: usize,
+}
+
+impl Renderer for CountingRenderer {
+    fn start_frame(&mut self, _stage_size: Vector2F) {}
+    fn set_background(&mut self, _color: LinSrgba) {}
+    fn draw_background(&mut self, _layer: BackgroundLayer) {}
+    fn draw_shape(
+        &mut self,
+        _shape: Arc<Shape>,
+        _transform: Transform2F,
+        _color_override: Option<Coloring>,
+        _morph_index: f32,
+    ) {
+        self.shapes += 1;
+    }
+    fn draw_raster(
+        &mut self,
+        _pattern: Arc<Pattern>,
+        _view_rect: Option<RectF>,
+        _transform: Transform2F,
+        _tint: Option<Coloring>,
+    ) {
+        self.rasters += 1;
+    }
+    fn end_frame(&mut self) {}
+}
+
+struct Timings(Vec<Duration>);
+
+impl Timings {
+    fn summary(&mut self) -> String {
+        if self.0.is_empty() {
+            return "no samples".to_string();
+        }
+        self.0.sort();
+        let total: Duration = self.0.iter().sum();
+        let percentile = |percent: usize| self.0[(self.0.len() - 1) * percent / 100];
+        format!(
+            "mean {:>9.3?}  p50 {:>9.3?}  p95 {:>9.3?}  max {:>9.3?}",
+            total / self.0.len() as u32,
+            percentile(50),
+            percentile(95),
+            self.0[self.0.len() - 1],
+        )
+    }
+}
+
+fn run(options: &StressOptions) -> Result<(), String> {
+    let build_start = Instant::now();
+    let (mut engine, _) = build_scene(options)?;
+    println!(
+        "Built {} containers ({} tweened, chain of {}, {} layers) in {:?}",
+        options.containers,
+        options.tweens.min(options.containers),
+        options.depth,
+        options.layers,
+        build_start.elapsed()
+    );
+
+    let mut update_times = Timings(Vec::with_capacity(options.frames));
+    let mut paint_times = Timings(Vec::with_capacity(options.frames));
+    let mut frame_times = Timings(Vec::with_capacity(options.frames));
+    let mut system_times: HashMap<&'static str, Timings> = HashMap::new();
+    let mut renderer = CountingRenderer::default();
+    for _ in 0..options.frames {
+        let frame_start = Instant::now();
+        // The scene dirty flag is never taken, so the engine never skips an update as quiescent
+        engine.update(FrameTime::new(FRAME_DURATION, 1));
+        let update_time = frame_start.elapsed();
+        for (system, elapsed) in engine.get_frame_profile().system_times() {
+            system_times
+                .entry(system)
+                .or_insert_with(|| Timings(vec![]))
+                .0
+                .push(elapsed);
+        }
+        let paint_start = Instant::now();
+        renderer.start_frame(Vector2F::splat(STAGE_SIZE));
+        paint(&mut renderer, &engine);
+        renderer.end_frame();
+        paint_times.0.push(paint_start.elapsed());
+        update_times.0.push(update_time);
+        frame_times.0.push(frame_start.elapsed());
+    }
+
+    println!(
+        "{} frames, {} shapes and {} rasters painted",
+        options.frames, renderer.shapes, renderer.rasters
+    );
+    println!("{:<32}{}", "frame", frame_times.summary());
+    println!("{:<32}{}", "  update", update_times.summary());
+    println!("{:<32}{}", "  paint", paint_times.summary());
+    let mut systems: Vec<(&'static str, Timings)> = system_times.into_iter().collect();
+    // Most expensive first
+    systems.sort_by_key(|(_, timings)| std::cmp::Reverse(timings.0.iter().sum::<Duration>()));
+    for (system, mut timings) in systems {
+        println!("{:<32}{}", format!("    {}", system), timings.summary());
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = options_from_args().and_then(|options| run(&options));
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
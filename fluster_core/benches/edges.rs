@@ -0,0 +1,72 @@
+use criterion::{black_box, criterion_group, criterion_main, Bencher, Criterion};
+use fluster_core::types::{
+    edge_pool::EdgePool,
+    shapes::{AugmentedShape, Contour, Edge, Shape},
+};
+use palette::LinSrgba;
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+
+const SHAPES: usize = 50;
+const EDGES_PER_SHAPE: usize = 100;
+
+// A group of closed, wobbly polygons, like traced artwork. Thousands of edges in all.
+fn detailed_group() -> Shape {
+    let shapes = (0..SHAPES)
+        .map(|shape| {
+            let offset = Vector2F::splat(shape as f32 * 10.0);
+            let mut edges = vec![Edge::Move(offset)];
+            edges.extend((1..EDGES_PER_SHAPE).map(|index| {
+                let angle = index as f32 / EDGES_PER_SHAPE as f32 * std::f32::consts::PI * 2.0;
+                let radius = if index % 2 == 0 { 40.0 } else { 36.0 };
+                let to = offset + Vector2F::new(angle.cos(), angle.sin()) * radius;
+                if index % 3 == 0 {
+                    Edge::Quadratic {
+                        control: to + Vector2F::splat(2.0),
+                        to,
+                    }
+                } else {
+                    Edge::Line(to)
+                }
+            }));
+            edges.push(Edge::Close);
+            AugmentedShape {
+                shape: Shape::Fill {
+                    contours: Contour::from_edges(&edges),
+                    color: LinSrgba::new(0.0, 0.0, 0.0, 1.0),
+                },
+                transform: Transform2F::default(),
+            }
+        })
+        .collect();
+    Shape::Group { shapes }
+}
+
+fn bench_edge_list(bencher: &mut Bencher) {
+    let shape = detailed_group();
+    bencher.iter(|| black_box(shape.edge_list(0.0)));
+}
+
+// What the editor's tools do on every pointer move
+fn bench_pooled_edge_list(bencher: &mut Bencher) {
+    let shape = detailed_group();
+    let mut pool = EdgePool::new();
+    bencher.iter(|| {
+        let edges = pool.take_edge_list(&shape, 0.0);
+        black_box(&edges);
+        pool.give_back(edges);
+    });
+}
+
+fn bench_contours_from_edges(bencher: &mut Bencher) {
+    let edges = detailed_group().edge_list(0.0);
+    bencher.iter(|| black_box(Contour::from_edges(&edges)));
+}
+
+fn benches(criterion: &mut Criterion) {
+    criterion.bench_function("edge_list", bench_edge_list);
+    criterion.bench_function("pooled_edge_list", bench_pooled_edge_list);
+    criterion.bench_function("contours_from_edges", bench_contours_from_edges);
+}
+
+criterion_group!(edges, benches);
+criterion_main!(edges);
@@ -0,0 +1,80 @@
+use criterion::{black_box, criterion_group, criterion_main, Bencher, Criterion};
+use fluster_core::ecs::resources::SceneGraph;
+use specs::{Builder, Entity, World, WorldExt};
+use std::collections::VecDeque;
+
+const DEEP_SCENE_DEPTH: usize = 1_000;
+const WIDE_SCENE_WIDTH: usize = 10_000;
+
+fn create_entity(world: &mut World) -> Entity {
+    world.create_entity().build()
+}
+
+// A single chain of containers, like deeply nested groups
+fn deep_scene() -> (SceneGraph, Entity) {
+    let mut world = World::new();
+    let root = create_entity(&mut world);
+    let mut scene_graph = SceneGraph::new(root);
+    let mut parent = root;
+    for _ in 0..DEEP_SCENE_DEPTH {
+        let entity = create_entity(&mut world);
+        scene_graph.add_entity(&parent, &entity);
+        parent = entity;
+    }
+    (scene_graph, parent)
+}
+
+// Every container parented to the root, like a particle field
+fn wide_scene() -> SceneGraph {
+    let mut world = World::new();
+    let root = create_entity(&mut world);
+    let mut scene_graph = SceneGraph::new(root);
+    for _ in 0..WIDE_SCENE_WIDTH {
+        let entity = create_entity(&mut world);
+        scene_graph.add_entity(&root, &entity);
+    }
+    scene_graph
+}
+
+// Mirrors the breadth first walk in UpdateWorldTransform
+fn traverse(scene_graph: &SceneGraph) -> usize {
+    let mut visited = 0;
+    let mut queue = VecDeque::new();
+    queue.push_back(*scene_graph.root());
+    while let Some(entity) = queue.pop_front() {
+        visited += 1;
+        if let Some(children) = scene_graph.get_children(&entity) {
+            queue.extend(children.iter().copied());
+        }
+    }
+    visited
+}
+
+fn bench_traverse_deep(bencher: &mut Bencher) {
+    let (scene_graph, _) = deep_scene();
+    bencher.iter(|| black_box(traverse(&scene_graph)));
+}
+
+fn bench_traverse_wide(bencher: &mut Bencher) {
+    let scene_graph = wide_scene();
+    bencher.iter(|| black_box(traverse(&scene_graph)));
+}
+
+fn bench_parents_of_deepest(bencher: &mut Bencher) {
+    let (scene_graph, leaf) = deep_scene();
+    bencher.iter(|| black_box(scene_graph.get_parent_iter(&leaf).count()));
+}
+
+fn bench_build_wide(bencher: &mut Bencher) {
+    bencher.iter(|| black_box(wide_scene()));
+}
+
+fn benches(criterion: &mut Criterion) {
+    criterion.bench_function("traverse_deep", bench_traverse_deep);
+    criterion.bench_function("traverse_wide", bench_traverse_wide);
+    criterion.bench_function("parents_of_deepest", bench_parents_of_deepest);
+    criterion.bench_function("build_wide", bench_build_wide);
+}
+
+criterion_group!(scene_graph, benches);
+criterion_main!(scene_graph);
@@ -0,0 +1,105 @@
+use criterion::{black_box, criterion_group, criterion_main, Bencher, Criterion};
+use fluster_core::ecs::{
+    components::{LocalTransform, Morph, TimeScale, Tweens},
+    resources::{EngineConfig, FrameTime, SceneGraph},
+    systems::{ApplyMorphTweens, ApplyTransformTweens, UpdateTweens},
+};
+use fluster_core::tween::{Easing, PropertyTween, TweenDuration};
+use fluster_core::types::basic::ScaleRotationTranslation;
+use pathfinder_geometry::vector::Vector2F;
+use specs::{Builder, RunNow, System, World, WorldExt};
+use std::time::Duration;
+
+const TWEENED_CONTAINERS: usize = 20_000;
+
+fn transform_tween(index: usize) -> PropertyTween {
+    let offset = index as f32;
+    PropertyTween::new_transform(
+        ScaleRotationTranslation::new(Vector2F::splat(1.0), 0.0, Vector2F::splat(offset)),
+        ScaleRotationTranslation::new(Vector2F::splat(2.0), 1.0, Vector2F::splat(-offset)),
+        // Long enough that nothing completes while benchmarking
+        TweenDuration::new_frame(std::u32::MAX),
+        Easing::CubicInOut,
+    )
+}
+
+fn morph_tween() -> PropertyTween {
+    PropertyTween::new_morph_index(
+        0.0,
+        1.0,
+        TweenDuration::new_frame(std::u32::MAX),
+        Easing::Linear,
+    )
+}
+
+// A flat scene where every container animates its transform and morph, like a particle field.
+// Systems have to be set up before the entities are built, so their storages are registered.
+fn tweened_world(setup: impl FnOnce(&mut World)) -> World {
+    let mut world = World::new();
+    setup(&mut world);
+    world.register::<LocalTransform>();
+    world.register::<Morph>();
+    world.register::<Tweens>();
+    world.register::<TimeScale>();
+    world.insert(EngineConfig::default());
+    world.insert(FrameTime::new(Duration::from_millis(16), 1));
+    let root = world.create_entity().build();
+    let mut scene_graph = SceneGraph::new(root);
+    for index in 0..TWEENED_CONTAINERS {
+        let entity = world
+            .create_entity()
+            .with(LocalTransform::default())
+            .with(Morph::default())
+            .with(Tweens(vec![transform_tween(index), morph_tween()]))
+            .build();
+        scene_graph.add_entity(&root, &entity);
+    }
+    world.insert(scene_graph);
+    world
+}
+
+fn bench_system<S>(bencher: &mut Bencher, mut system: S, time_scaled: bool)
+where
+    S: for<'a> System<'a>,
+{
+    let mut world = tweened_world(|world| System::setup(&mut system, world));
+    if time_scaled {
+        let root = *world.read_resource::<SceneGraph>().root();
+        world
+            .write_storage::<TimeScale>()
+            .insert(root, TimeScale(0.5))
+            .unwrap();
+    }
+    bencher.iter(|| {
+        system.run_now(&world);
+        world.maintain();
+        black_box(&world);
+    });
+}
+
+fn bench_update_tweens(bencher: &mut Bencher) {
+    bench_system(bencher, UpdateTweens::default(), false);
+}
+
+// Every container has to walk its parents for the combined time scale
+fn bench_update_tweens_time_scaled(bencher: &mut Bencher) {
+    bench_system(bencher, UpdateTweens::default(), true);
+}
+
+fn bench_apply_transform_tweens(bencher: &mut Bencher) {
+    bench_system(bencher, ApplyTransformTweens, false);
+}
+
+fn bench_apply_morph_tweens(bencher: &mut Bencher) {
+    bench_system(bencher, ApplyMorphTweens, false);
+}
+
+fn benches(criterion: &mut Criterion) {
+    criterion.bench_function("update_tweens", bench_update_tweens);
+    criterion.bench_function("update_tweens_time_scaled", bench_update_tweens_time_scaled);
+    criterion.bench_function("apply_transform_tweens", bench_apply_transform_tweens);
+    criterion.bench_function("apply_morph_tweens", bench_apply_morph_tweens);
+}
+
+criterion_group!(tweens, benches);
+criterion_main!(tweens);
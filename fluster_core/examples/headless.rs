@@ -0,0 +1,95 @@
+//! Plays a tiny action list without a window or GPU, recording what would have been drawn.
+//!
+//! This is the whole embedding API in one place: build an `ActionList`, initialize a `Runner`
+//! from it, then call `next_frame` with a `Renderer` once per frame. Run it with
+//! `cargo run -p fluster_core --example headless`; it panics if the pipeline draws the wrong
+//! thing, so it doubles as an end to end test.
+
+use fluster_core::prelude::*;
+use fluster_core::rendering::{RecordedCall, RecordingRenderer, ShapeInstance};
+use fluster_core::types::shapes::Contour;
+use palette::{LinSrgb, LinSrgba};
+use pathfinder_geometry::transform2d::Transform2F;
+use pathfinder_geometry::vector::Vector2F;
+use std::time::Duration;
+
+const FRAMES: u32 = 10;
+
+fn main() -> Result<(), String> {
+    let root_id = ContainerId::new();
+    let square_id = LibraryId::new();
+    let container_id = ContainerId::new();
+    let end = ScaleRotationTranslation::new(Vector2F::splat(1.0), 0.0, Vector2F::new(90.0, 0.0));
+    let actions = vec![
+        // Everything up to EndInitialization sets up the stage and library
+        Action::CreateRoot(root_id),
+        Action::SetBackground {
+            color: LinSrgb::new(0.0, 0.0, 0.0),
+        },
+        Action::DefineShape {
+            id: square_id,
+            shape: Shape::Fill {
+                contours: Contour::from_edges(&Edge::new_rect(
+                    Vector2F::splat(10.0),
+                    Transform2F::default(),
+                )),
+                color: LinSrgba::new(1.0, 0.0, 0.0, 1.0),
+            },
+        },
+        Action::EndInitialization,
+        // Then come the frames: a square that slides to the right over FRAMES frames
+        Action::CreateContainer(ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![
+                ContainerCreationProperty::Transform(ScaleRotationTranslation::new(
+                    Vector2F::splat(1.0),
+                    0.0,
+                    Vector2F::zero(),
+                )),
+                ContainerCreationProperty::Display(square_id),
+            ],
+        )),
+        Action::UpdateContainer(ContainerUpdateDefintition::new(
+            container_id,
+            vec![ContainerUpdateProperty::Transform(
+                end,
+                Easing::Linear,
+                FRAMES,
+            )],
+        )),
+        Action::PresentFrame(0, FRAMES),
+    ];
+    let mut action_list = ActionList::new(Box::new(|| None), Some(&actions));
+
+    let mut runner = Runner::initialize(
+        &mut action_list,
+        Duration::from_secs_f64(1.0 / 60.0),
+        Vector2F::new(200.0, 100.0),
+    )?;
+    let mut renderer = RecordingRenderer::default();
+    let mut last_x = -1.0;
+    for frame in 0..FRAMES {
+        // Timed pacing steps one frame per call, so the wait it asks for can be skipped here.
+        // A real host would sleep until the returned instant.
+        if let FrameResult::Quit = runner.next_frame(&mut renderer, &mut action_list)? {
+            break;
+        }
+        let shapes: Vec<&ShapeInstance> = renderer
+            .last_frame()
+            .iter()
+            .filter_map(|call| match call {
+                RecordedCall::DrawShape(_, instance) => Some(instance),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(shapes.len(), 1, "frame {} should draw the square", frame);
+        let x = shapes[0].transform.translation().x();
+        assert!(x >= last_x, "frame {} moved the square backwards", frame);
+        last_x = x;
+        println!("Frame {}: square at x = {}", frame, x);
+    }
+    assert_eq!(renderer.frame_count(), FRAMES as usize);
+    assert!(last_x > 0.0 && last_x <= end.translation.x() + 0.001);
+    Ok(())
+}
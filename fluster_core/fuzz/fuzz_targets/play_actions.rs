@@ -0,0 +1,77 @@
+#![no_main]
+use fluster_core::{
+    linking::{AssetResolver, LinkedAsset},
+    rendering::{BackgroundLayer, Renderer},
+    runner::{FrameResult, Runner},
+    serialization::deserialize_action_list,
+    types::{coloring::Coloring, shapes::Shape},
+};
+use libfuzzer_sys::fuzz_target;
+use palette::LinSrgba;
+use pathfinder_content::pattern::Pattern;
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use std::{io::Cursor, sync::Arc, time::Duration};
+
+// Long enough to reach tweens, labels and state machines, short enough to keep runs fast
+const MAX_FRAMES: usize = 240;
+
+// Linked assets would read from disk, which a fuzzer has no business doing
+struct NoAssets;
+
+impl AssetResolver for NoAssets {
+    fn resolve(&self, location: &str) -> Result<LinkedAsset, String> {
+        Err(format!("Not resolving {:?} while fuzzing", location))
+    }
+}
+
+// Accepts everything, so the fuzzer exercises paint without a GPU
+struct NullRenderer;
+
+impl Renderer for NullRenderer {
+    fn start_frame(&mut self, _stage_size: Vector2F) {}
+    fn set_background(&mut self, _color: LinSrgba) {}
+    fn draw_background(&mut self, _layer: BackgroundLayer) {}
+    fn draw_shape(
+        &mut self,
+        _shape: Arc<Shape>,
+        _transform: Transform2F,
+        _color_override: Option<Coloring>,
+        _morph_index: f32,
+    ) {
+    }
+    fn draw_raster(
+        &mut self,
+        _pattern: Arc<Pattern>,
+        _view_rect: Option<RectF>,
+        _transform: Transform2F,
+        _tint: Option<Coloring>,
+    ) {
+    }
+    fn end_frame(&mut self) {}
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (stage_size, frames_per_second, mut action_list) =
+        match deserialize_action_list(Cursor::new(data.to_vec())) {
+            Ok(stream) => stream,
+            Err(_) => return,
+        };
+    let frame_duration = Duration::from_secs(1) / u32::from(frames_per_second.max(1));
+    let mut runner = match Runner::initialize_with_resolver(
+        &mut action_list,
+        frame_duration,
+        stage_size.to_f32(),
+        Box::new(NoAssets),
+    ) {
+        Ok(runner) => runner,
+        Err(_) => return,
+    };
+    let mut renderer = NullRenderer;
+    // Timed pacing steps one frame per call, so the waits it asks for can be skipped
+    for _ in 0..MAX_FRAMES {
+        match runner.next_frame(&mut renderer, &mut action_list) {
+            Ok(FrameResult::Quit) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+});
@@ -0,0 +1,19 @@
+#![no_main]
+use fluster_core::serialization::{deserialize_action, deserialize_action_list};
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+use streaming_iterator::StreamingIterator;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = deserialize_action(data, 0);
+    if let Ok((_, _, mut action_list)) = deserialize_action_list(Cursor::new(data.to_vec())) {
+        // The list stays on its last action once the stream runs dry
+        loop {
+            let action_index = action_list.action_index();
+            action_list.advance();
+            if action_list.action_index() == action_index {
+                break;
+            }
+        }
+    }
+});
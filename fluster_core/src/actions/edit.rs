@@ -0,0 +1,348 @@
+//! Assembles action streams from reusable clips: splicing one stream into another, shifting and
+//! stripping frames, and retiming tweens.
+//!
+//! Everything here works on the actions a stream is serialized from, not on a playing
+//! `ActionList`. Actions between two `PresentFrame`s are applied before the second is presented,
+//! so they move with it.
+
+use super::{Action, ContainerUpdateDefintition, ContainerUpdateProperty};
+use crate::serialization::{remap_colliding_ids, DefinedIds};
+use crate::types::basic::ContainerId;
+use std::ops::Range;
+
+/// One past the last frame the stream presents.
+pub fn frame_count(actions: &[Action]) -> u32 {
+    actions
+        .iter()
+        .filter_map(|action| match action {
+            Action::PresentFrame(start, count) => Some(start + count),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn first_frame(actions: &[Action]) -> Option<u32> {
+    actions
+        .iter()
+        .filter_map(|action| match action {
+            Action::PresentFrame(start, _) => Some(*start),
+            _ => None,
+        })
+        .min()
+}
+
+/// Shifts every `PresentFrame` by `offset` frames. Fails, leaving `actions` untouched, if a frame
+/// would move before frame 0.
+pub fn offset_frames(actions: &mut [Action], offset: i64) -> Result<(), String> {
+    if let Some(first) = first_frame(actions) {
+        if first as i64 + offset < 0 {
+            return Err(format!(
+                "Can't move frame {} back by {} frames",
+                first, -offset
+            ));
+        }
+    }
+    for action in actions.iter_mut() {
+        if let Action::PresentFrame(start, _) = action {
+            *start = (*start as i64 + offset) as u32;
+        }
+    }
+    Ok(())
+}
+
+/// Removes `frames` from the stream, moving the frames after them back to close the gap. Only
+/// presentation is removed: updates made on the removed frames are applied on the next frame
+/// that is kept, so the scene still ends up in the same state.
+pub fn strip_frames(actions: &mut Vec<Action>, frames: Range<u32>) {
+    if frames.start >= frames.end {
+        return;
+    }
+    let removed = frames.end - frames.start;
+    actions.retain(|action| match action {
+        Action::PresentFrame(start, count) => {
+            let overlap = (start + count)
+                .min(frames.end)
+                .saturating_sub((*start).max(frames.start));
+            overlap < *count
+        }
+        _ => true,
+    });
+    for action in actions.iter_mut() {
+        if let Action::PresentFrame(start, count) = action {
+            let overlap = (*start + *count)
+                .min(frames.end)
+                .saturating_sub((*start).max(frames.start));
+            *count -= overlap;
+            if *start >= frames.end {
+                *start -= removed;
+            } else if *start > frames.start {
+                *start = frames.start;
+            }
+        }
+    }
+}
+
+/// Scales the duration, delay and blend in of every tween started by an `UpdateContainer` by
+/// `factor`, rounded to whole frames. Tweens never shrink to 0 frames, which would turn them into
+/// cuts. Frame ranges are left as they are.
+pub fn retime(actions: &mut [Action], factor: f32) -> Result<(), String> {
+    if !(factor > 0.0 && factor.is_finite()) {
+        return Err(format!("Can't retime by {}", factor));
+    }
+    for action in actions.iter_mut() {
+        if let Action::UpdateContainer(definition) = action {
+            retime_update(definition, factor);
+        }
+    }
+    Ok(())
+}
+
+fn retime_update(definition: &mut ContainerUpdateDefintition, factor: f32) {
+    let scale = |frames: &mut u32| {
+        if *frames > 0 {
+            *frames = ((*frames as f32 * factor).round() as u32).max(1);
+        }
+    };
+    // Unlike a duration, a delay can round down to nothing
+    definition.delay_frames = (definition.delay_frames as f32 * factor).round() as u32;
+    if let Some((_, duration_frames)) = &mut definition.blend_in {
+        scale(duration_frames);
+    }
+    for property in definition.properties.iter_mut() {
+        match property {
+            ContainerUpdateProperty::Transform(_, _, duration_frames)
+            | ContainerUpdateProperty::TransformMatrix(_, _, duration_frames)
+            | ContainerUpdateProperty::MorphIndex(_, _, duration_frames)
+            | ContainerUpdateProperty::MorphIndices(_, _, duration_frames)
+            | ContainerUpdateProperty::Coloring(_, _, _, duration_frames)
+            | ContainerUpdateProperty::ColorTransform(_, _, duration_frames)
+            | ContainerUpdateProperty::ViewRect(_, _, duration_frames)
+            | ContainerUpdateProperty::Order(_, _, duration_frames)
+            | ContainerUpdateProperty::PathTrim(_, _, duration_frames) => scale(duration_frames),
+            _ => (),
+        }
+    }
+}
+
+fn root_of(actions: &[Action]) -> Option<ContainerId> {
+    actions.iter().find_map(|action| match action {
+        Action::CreateRoot(id) => Some(*id),
+        _ => None,
+    })
+}
+
+// The stage belongs to the stream a clip is spliced into
+fn is_stage_setup(action: &Action) -> bool {
+    matches!(
+        action,
+        Action::CreateRoot(..)
+            | Action::SetBackground { .. }
+            | Action::SetBackgroundLayers(..)
+            | Action::SetSeed(..)
+            | Action::EndInitialization
+    )
+}
+
+// Moves what the clip put under its own root to the host's root
+fn reparent_to_root(action: &mut Action, clip_root: &ContainerId, root: &ContainerId) {
+    match action {
+        Action::CreateContainer(definition) if definition.parent == *clip_root => {
+            definition.parent = *root;
+        }
+        Action::UpdateContainer(definition) => {
+            for property in definition.properties.iter_mut() {
+                if let ContainerUpdateProperty::Parent(parent) = property {
+                    if parent == clip_root {
+                        *parent = *root;
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Inserts `clip` so its first frame plays at `at_frame`, moving the frames of `host` from
+/// `at_frame` on after it. A frame range `at_frame` falls inside is split around the clip.
+///
+/// Ids the clip defines that collide with the host's are renamed with `remap_colliding_ids`.
+/// The clip's library items and other setup join the host's setup, and containers created under
+/// the clip's root are created under the host's root instead. The clip's root, background and
+/// seed are dropped, since the stage is the host's.
+pub fn splice(host: &[Action], clip: &[Action], at_frame: u32) -> Result<Vec<Action>, String> {
+    let host_length = frame_count(host);
+    if at_frame > host_length {
+        return Err(format!(
+            "Can't splice at frame {}, the stream ends at frame {}",
+            at_frame, host_length
+        ));
+    }
+    let mut clip = clip.to_vec();
+    remap_colliding_ids(&DefinedIds::from_actions(host), &mut clip);
+    if let Some(clip_root) = root_of(&clip) {
+        let root = root_of(host)
+            .ok_or_else(|| "Can't splice a clip into a stream without a root".to_string())?;
+        for action in clip.iter_mut() {
+            reparent_to_root(action, &clip_root, &root);
+        }
+    }
+    let (clip_setup, mut clip_frames) = match clip
+        .iter()
+        .position(|action| matches!(action, Action::EndInitialization))
+    {
+        Some(end) => {
+            let clip_frames = clip.split_off(end + 1);
+            (clip, clip_frames)
+        }
+        None => (vec![], clip),
+    };
+    let clip_setup = clip_setup
+        .into_iter()
+        .filter(|action| !is_stage_setup(action))
+        .collect::<Vec<Action>>();
+    let clip_start = first_frame(&clip_frames).unwrap_or(0);
+    let clip_length = frame_count(&clip_frames).saturating_sub(clip_start);
+    offset_frames(&mut clip_frames, at_frame as i64 - clip_start as i64)?;
+
+    let mut spliced = Vec::with_capacity(host.len() + clip_setup.len() + clip_frames.len() + 1);
+    // Where the clip's frames go: after the last frame before `at_frame`, so the updates leading
+    // up to the host's next frame stay with it
+    let mut frames_index = None;
+    for action in host.iter() {
+        match action {
+            Action::PresentFrame(start, count) if *start < at_frame && at_frame < start + count => {
+                spliced.push(Action::PresentFrame(*start, at_frame - start));
+                frames_index = Some(spliced.len());
+                spliced.push(Action::PresentFrame(
+                    at_frame + clip_length,
+                    start + count - at_frame,
+                ));
+            }
+            Action::PresentFrame(start, count) => {
+                if *start >= at_frame {
+                    spliced.push(Action::PresentFrame(start + clip_length, *count));
+                } else {
+                    spliced.push(Action::PresentFrame(*start, *count));
+                    if start + count <= at_frame {
+                        frames_index = Some(spliced.len());
+                    }
+                }
+            }
+            _ => spliced.push(action.clone()),
+        }
+    }
+    let setup_index = spliced
+        .iter()
+        .position(|action| matches!(action, Action::EndInitialization));
+    let frames_index = frames_index
+        .or_else(|| setup_index.map(|index| index + 1))
+        .unwrap_or(0);
+    let after_clip = spliced.split_off(frames_index);
+    spliced.extend(clip_frames);
+    spliced.extend(after_clip);
+    let setup_index = setup_index.unwrap_or(frames_index);
+    let after_setup = spliced.split_off(setup_index);
+    spliced.extend(clip_setup);
+    spliced.extend(after_setup);
+    Ok(spliced)
+}
+
+/// Splices `clip` in after the last frame of `host`.
+pub fn append(host: &[Action], clip: &[Action]) -> Result<Vec<Action>, String> {
+    splice(host, clip, frame_count(host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::ContainerCreationDefintition;
+    use crate::tween::Easing;
+    use crate::types::basic::ScaleRotationTranslation;
+
+    fn frames(actions: &[Action]) -> Vec<(u32, u32)> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::PresentFrame(start, count) => Some((*start, *count)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_splices_a_clip_into_a_frame_range() {
+        let root_id = ContainerId::new();
+        let host = vec![
+            Action::CreateRoot(root_id),
+            Action::EndInitialization,
+            Action::PresentFrame(0, 4),
+            Action::Label("end".to_string()),
+            Action::PresentFrame(4, 1),
+        ];
+        let (clip_root_id, clip_container_id) = (ContainerId::new(), ContainerId::new());
+        let clip = vec![
+            Action::CreateRoot(clip_root_id),
+            Action::EndInitialization,
+            Action::CreateContainer(ContainerCreationDefintition::new(
+                clip_root_id,
+                clip_container_id,
+                vec![],
+            )),
+            Action::PresentFrame(0, 2),
+        ];
+        let spliced = splice(&host, &clip, 1).unwrap();
+        assert_eq!(frames(&spliced), vec![(0, 1), (1, 2), (3, 3), (6, 1)]);
+        assert_eq!(spliced.len(), host.len() + 3);
+        assert_eq!(
+            spliced[3],
+            Action::CreateContainer(ContainerCreationDefintition::new(
+                root_id,
+                clip_container_id,
+                vec![],
+            ))
+        );
+        assert_eq!(frames(&append(&host, &clip).unwrap()).last(), Some(&(5, 2)));
+        assert!(splice(&host, &clip, 6).is_err());
+
+        let mut stripped = spliced;
+        strip_frames(&mut stripped, 1..3);
+        assert_eq!(frames(&stripped), vec![(0, 1), (1, 3), (4, 1)]);
+        // The clip's container is still created
+        assert_eq!(stripped.len(), host.len() + 2);
+    }
+
+    #[test]
+    fn it_retimes_tweens() {
+        let update = |duration_frames: u32| {
+            Action::UpdateContainer(
+                ContainerUpdateDefintition::new(
+                    ContainerId::new(),
+                    vec![ContainerUpdateProperty::Transform(
+                        ScaleRotationTranslation::default(),
+                        Easing::Linear,
+                        duration_frames,
+                    )],
+                )
+                .with_delay(3),
+            )
+        };
+        let mut actions = vec![update(10), update(1)];
+        retime(&mut actions, 0.5).unwrap();
+        let retimed = actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::UpdateContainer(definition) => Some(definition),
+                _ => None,
+            })
+            .map(|definition| match definition.properties()[0] {
+                ContainerUpdateProperty::Transform(_, _, duration_frames) => {
+                    (duration_frames, definition.delay())
+                }
+                _ => unreachable!(),
+            })
+            .collect::<Vec<(u32, u32)>>();
+        assert_eq!(retimed, vec![(5, 2), (1, 2)]);
+        assert!(retime(&mut actions, 0.0).is_err());
+    }
+}
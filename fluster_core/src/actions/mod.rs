@@ -0,0 +1,706 @@
+pub mod edit;
+pub mod optimize;
+
+use super::tween::Easing;
+use super::types::{
+    basic::{Bitmap, ScaleRotationTranslation, TextureDefinition, Vector2FDef},
+    coloring::{ColorTransform, Coloring},
+    shapes::{PathTrim, Shape},
+};
+use crate::{
+    baking::SpriteSheet,
+    ecs::{
+        components::{
+            Anchor, BounceBounds, Draggable, Focusable, HitShape, KeyBinding, ScrollView,
+        },
+        resources::{LibraryItemMetadata, LodVariant, QuadTreeLayer, QuadTreeLayerOptions},
+    },
+    modifiers::Modifier,
+    state_machine::StateMachine,
+    types::{
+        basic::{ContainerId, LibraryId},
+        coloring::ColorSpace,
+    },
+};
+use core::cmp::min;
+use palette::{LinSrgb, LinSrgba};
+use pathfinder_geometry::rect::RectF;
+use pathfinder_geometry::vector::Vector2F;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use streaming_iterator::StreamingIterator;
+
+pub enum FrameAdvanceResult {
+    NextFrame(u32),
+    PresentEnd(u32),
+    NotInPresent,
+}
+
+/// Maps frames and labels to action indices, so seeking doesn't have to scan the action list.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct ActionIndex {
+    /// (start, count, action index) of each `PresentFrame`, sorted by start frame.
+    frames: Vec<(u32, u32, usize)>,
+    labels: HashMap<String, usize>,
+}
+
+impl ActionIndex {
+    pub fn build(actions: &[Action]) -> Self {
+        let mut index = Self::default();
+        for (action_index, action) in actions.iter().enumerate() {
+            match action {
+                Action::PresentFrame(start, count) => {
+                    index.frames.push((*start, *count, action_index))
+                }
+                Action::Label(name) => {
+                    index.labels.entry(name.clone()).or_insert(action_index);
+                }
+                _ => (),
+            }
+        }
+        index.frames.sort_by_key(|(start, _, _)| *start);
+        index
+    }
+
+    /// The index of the `PresentFrame` action that presents `frame`.
+    pub fn frame(&self, frame: u32) -> Option<usize> {
+        let position = match self
+            .frames
+            .binary_search_by_key(&frame, |(start, _, _)| *start)
+        {
+            Ok(position) => position,
+            Err(0) => return None,
+            Err(position) => position - 1,
+        };
+        let (start, count, action_index) = self.frames[position];
+        if frame <= start + count {
+            Some(action_index)
+        } else {
+            None
+        }
+    }
+
+    pub fn label(&self, label: &str) -> Option<usize> {
+        self.labels.get(label).copied()
+    }
+}
+
+pub struct ActionList {
+    actions: Vec<Action>,
+    frame_index: u32,
+    action_index: usize,
+    labels: HashMap<String, usize>,
+    index: Option<ActionIndex>,
+    load_more: Box<dyn Fn() -> Option<Vec<Action>>>,
+}
+
+impl ActionList {
+    pub fn new(
+        load_more: Box<dyn Fn() -> Option<Vec<Action>>>,
+        initial_vec: Option<&Vec<Action>>,
+    ) -> Self {
+        let initial_vec = match initial_vec {
+            Some(vec) => vec.to_vec(),
+            None => vec![],
+        };
+        Self {
+            actions: initial_vec,
+            labels: HashMap::new(),
+            index: None,
+            frame_index: 0,
+            action_index: 0,
+            load_more,
+        }
+    }
+
+    /// Uses `index` to find frames and labels that haven't been loaded yet.
+    pub fn with_index(mut self, index: ActionIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub fn index(&self) -> Option<&ActionIndex> {
+        self.index.as_ref()
+    }
+
+    // Loads more actions until `index` is loaded or the stream runs out
+    fn load_until(&mut self, index: usize) -> bool {
+        while self.actions.len() <= index {
+            match (self.load_more)() {
+                Some(mut more) if !more.is_empty() => self.actions.append(&mut more),
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Moves to the `PresentFrame` action that presents `frame`. Returns the new action index.
+    /// Nothing between the current action and the new one is executed.
+    pub fn seek_to_frame(&mut self, frame: u32) -> Result<usize, String> {
+        let indexed = self.index.as_ref().and_then(|index| index.frame(frame));
+        let new_index = match indexed {
+            Some(index) if self.load_until(index) => Some(index),
+            _ => self.actions.iter().position(|action| match action {
+                Action::PresentFrame(start, count) => *start <= frame && frame <= start + count,
+                _ => false,
+            }),
+        };
+        match new_index {
+            Some(index) => {
+                self.action_index = index;
+                self.frame_index = frame;
+                Ok(index)
+            }
+            None => Err(format!(
+                "Could not find frame {} in any loaded actions",
+                frame
+            )),
+        }
+    }
+
+    pub fn action_index(&self) -> usize {
+        self.action_index
+    }
+
+    pub fn frame_index(&self) -> u32 {
+        self.frame_index
+    }
+
+    pub fn jump_to_label(&mut self, label: &str) -> Result<(usize, u32), String> {
+        let indexed = match self.index.as_ref().and_then(|index| index.label(label)) {
+            Some(index) if self.load_until(index) => Some(index),
+            _ => None,
+        };
+        let new_index = match self.labels.get(label).copied().or(indexed) {
+            Some(index) => index,
+            None => {
+                let mut index: Option<usize> = None;
+                for i in self.action_index..self.actions.len() {
+                    if let Action::Label(name) = &self.actions[i] {
+                        self.labels.insert(name.clone(), i);
+                        if name == label {
+                            index = Some(i);
+                            break;
+                        }
+                    }
+                }
+                match index {
+                    Some(i) => i,
+                    None => {
+                        return Err(format!(
+                            "Could not find label {} in any loaded actions",
+                            label
+                        ))
+                    }
+                }
+            }
+        };
+        self.labels.insert(label.to_string(), new_index);
+        self.action_index = new_index;
+        let mut search = new_index;
+        loop {
+            if search == 0 {
+                return Ok((new_index, 0));
+            }
+            search -= 1; //new_index will be Action::Label
+            match self.actions.get(search) {
+                Some(Action::PresentFrame(start, count)) => return Ok((new_index, start + count)),
+                Some(Action::EndInitialization) => return Ok((new_index, 0)),
+                _ => (),
+            }
+        }
+    }
+
+    pub fn back(&mut self) {
+        match self.actions.get(self.action_index - 1) {
+            Some(Action::EndInitialization) => (),
+            None => (),
+            _ => self.action_index -= 1,
+        }
+    }
+
+    pub fn advance_frame(&mut self, num_frames: u32) -> FrameAdvanceResult {
+        if let Some(Action::PresentFrame(start, count)) = self.actions.get(self.action_index) {
+            let max = *start + *count;
+            if self.frame_index > max {
+                FrameAdvanceResult::PresentEnd(max)
+            } else {
+                self.frame_index = (self.frame_index.max(*start) + num_frames).min(max);
+                if self.frame_index == max {
+                    FrameAdvanceResult::PresentEnd(self.frame_index)
+                } else {
+                    FrameAdvanceResult::NextFrame(self.frame_index)
+                }
+            }
+        } else {
+            FrameAdvanceResult::NotInPresent
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut Action> {
+        self.actions.get_mut(self.action_index)
+    }
+}
+
+impl StreamingIterator for ActionList {
+    type Item = Action;
+
+    fn advance(&mut self) {
+        if let Some(mut more) = (self.load_more)() {
+            self.actions.append(&mut more);
+        }
+        self.action_index = min(self.action_index + 1, self.actions.len().saturating_sub(1));
+        if let Some(Action::Label(name)) = self.actions.get(self.action_index) {
+            self.labels.insert(name.clone(), self.action_index);
+        }
+    }
+
+    fn get(&self) -> Option<&Self::Item> {
+        self.actions.get(self.action_index)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RectPoints {
+    #[serde(with = "Vector2FDef")]
+    pub origin: Vector2F,
+    #[serde(with = "Vector2FDef")]
+    pub lower_right: Vector2F,
+}
+
+impl RectPoints {
+    pub fn from_rect(rect: &RectF) -> RectPoints {
+        RectPoints {
+            origin: rect.origin(),
+            lower_right: rect.lower_right(),
+        }
+    }
+}
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ContainerCreationDefintition {
+    id: ContainerId,
+    parent: ContainerId,
+    properties: Vec<ContainerCreationProperty>,
+}
+
+impl ContainerCreationDefintition {
+    pub fn new(
+        parent: ContainerId,
+        id: ContainerId,
+        properties: Vec<ContainerCreationProperty>,
+    ) -> Self {
+        Self {
+            id,
+            parent,
+            properties,
+        }
+    }
+
+    pub fn id(&self) -> &ContainerId {
+        &self.id
+    }
+
+    pub fn parent(&self) -> &ContainerId {
+        &self.parent
+    }
+
+    pub fn properties(&self) -> &Vec<ContainerCreationProperty> {
+        &self.properties
+    }
+
+    // Id, parent and properties, for rewriting ids on import
+    pub(crate) fn parts_mut(
+        &mut self,
+    ) -> (
+        &mut ContainerId,
+        &mut ContainerId,
+        &mut Vec<ContainerCreationProperty>,
+    ) {
+        (&mut self.id, &mut self.parent, &mut self.properties)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ContainerCreationProperty {
+    Transform(ScaleRotationTranslation),
+    MorphIndex(f32),
+    Coloring(Coloring),
+    ViewRect(RectPoints),
+    Display(LibraryId),
+    Layer(QuadTreeLayer),
+    Order(i8),
+    Bounds(BoundsKindDefinition),
+    // Everything below was added after version 1 of the stream format. New variants go at the
+    // end, since the position of each variant is what bincode writes.
+    /// Per shape morph indices for a group display. See `MorphIndices`.
+    MorphIndices(Vec<f32>),
+    /// Recolors everything the container and its children draw. See `ColorTransform`.
+    ColorTransform(ColorTransform),
+    /// Snaps the container to whole device pixels when drawn.
+    PixelSnap,
+    HitShape(HitShape),
+    Draggable(Draggable),
+    /// Makes the container a scroll view over its children. See `ScrollView`.
+    ScrollView(ScrollView),
+    Focusable(Focusable),
+    /// See `KeyBindings`.
+    KeyBindings(Vec<KeyBinding>),
+    /// Units per second, in the parent's space. See `Velocity`.
+    Velocity(#[serde(with = "Vector2FDef")] Vector2F),
+    /// Units per second per second, in the parent's space.
+    Acceleration(#[serde(with = "Vector2FDef")] Vector2F),
+    /// Radians per second.
+    AngularVelocity(f32),
+    /// The fraction of velocity lost each second.
+    Damping(f32),
+    BounceBounds(BounceBounds),
+    /// See `Parallax`.
+    Parallax(f32),
+    Anchor(Anchor),
+    /// See `PathTrim`.
+    PathTrim(PathTrim),
+    Modifiers(Vec<Modifier>),
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ContainerUpdateDefintition {
+    id: ContainerId,
+    properties: Vec<ContainerUpdateProperty>,
+    #[serde(default)]
+    blend_in: Option<(Easing, u32)>,
+    #[serde(default)]
+    delay_frames: u32,
+}
+
+impl ContainerUpdateDefintition {
+    pub fn new(id: ContainerId, properties: Vec<ContainerUpdateProperty>) -> Self {
+        Self {
+            id,
+            properties,
+            blend_in: None,
+            delay_frames: 0,
+        }
+    }
+
+    /// Cross-fade into this update over `duration_frames` instead of cutting over. Tweens started
+    /// by this update blend out any in flight tweens of the same property, and swapping the
+    /// display of a vector container fades between the two shapes' colors.
+    pub fn with_blend_in(mut self, easing: Easing, duration_frames: u32) -> Self {
+        self.blend_in = Some((easing, duration_frames));
+        self
+    }
+
+    pub fn blend_in(&self) -> Option<(Easing, u32)> {
+        self.blend_in
+    }
+
+    /// Starts the tweens in this update `delay_frames` frames late. Properties that are set
+    /// immediately are not delayed.
+    pub fn with_delay(mut self, delay_frames: u32) -> Self {
+        self.delay_frames = delay_frames;
+        self
+    }
+
+    pub fn delay(&self) -> u32 {
+        self.delay_frames
+    }
+
+    pub fn id(&self) -> &ContainerId {
+        &self.id
+    }
+
+    pub fn properties(&self) -> &Vec<ContainerUpdateProperty> {
+        &self.properties
+    }
+
+    // Id and properties, for rewriting ids on import
+    pub(crate) fn parts_mut(&mut self) -> (&mut ContainerId, &mut Vec<ContainerUpdateProperty>) {
+        (&mut self.id, &mut self.properties)
+    }
+}
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ContainerUpdateProperty {
+    Transform(ScaleRotationTranslation, Easing, u32),
+    MorphIndex(f32, Easing, u32),
+    Coloring(Coloring, ColorSpace, Easing, u32),
+    ViewRect(RectPoints, Easing, u32),
+    Order(i8, Easing, u32),
+    Display(LibraryId),
+    RemoveDisplay,
+    Parent(ContainerId),
+    AddToLayer(QuadTreeLayer),
+    RemoveFromLayer(QuadTreeLayer),
+    Bounds(BoundsKindDefinition),
+    RemoveBounds,
+    // Everything below was added after version 1 of the stream format. New variants go at the
+    // end, since the position of each variant is what bincode writes.
+    /// Tweens the raw affine matrix, given row major as `[m11, m12, m13, m21, m22, m23]` with the
+    /// translation in m13 and m23. Each component is interpolated on its own, which keeps
+    /// imported transforms that don't decompose into scale, rotation and translation faithful.
+    /// The catch is that the matrices part way through are not rotations: large rotations shrink
+    /// and shear on the way, and a half turn passes through zero scale. Prefer `Transform` for
+    /// anything authored in the editor.
+    TransformMatrix([f32; 6], Easing, u32),
+    /// Tweens the morph index of each shape in a group display. Shapes without a per shape
+    /// index yet start from the container's morph index.
+    MorphIndices(Vec<f32>, Easing, u32),
+    ColorTransform(ColorTransform, Easing, u32),
+    /// Sets the transform immediately, cancelling any transform tweens in flight.
+    SetTransform(ScaleRotationTranslation),
+    /// Sets the morph index immediately, cancelling any morph tweens in flight.
+    SetMorphIndex(f32),
+    /// Sets the per shape morph indices immediately, cancelling any of their tweens in flight.
+    SetMorphIndices(Vec<f32>),
+    /// Sets the coloring immediately, cancelling any coloring tweens in flight.
+    SetColoring(Coloring),
+    /// Sets the color transform immediately, cancelling any color transform tweens in flight.
+    SetColorTransform(ColorTransform),
+    /// Sets the view rect immediately, cancelling any view rect tweens in flight.
+    SetViewRect(RectPoints),
+    /// Scales the speed of tweens on this container and its children. 0.0 pauses them.
+    TimeScale(f32),
+    /// Turns snapping to whole device pixels on or off.
+    PixelSnap(bool),
+    HitShape(HitShape),
+    RemoveHitShape,
+    Draggable(Draggable),
+    /// Stops the container being draggable. A drag in progress ends when the pointer is released.
+    RemoveDraggable,
+    ScrollView(ScrollView),
+    /// Stops clipping and scrolling the container's children.
+    RemoveScrollView,
+    /// Jumps a scroll view to the offset, clamped to its content, and stops any fling.
+    ScrollTo(#[serde(with = "Vector2FDef")] Vector2F),
+    Focusable(Focusable),
+    /// Stops the container taking focus. It keeps focus if it already has it.
+    RemoveFocusable,
+    /// Replaces the container's key bindings.
+    KeyBindings(Vec<KeyBinding>),
+    RemoveKeyBindings,
+    Velocity(#[serde(with = "Vector2FDef")] Vector2F),
+    Acceleration(#[serde(with = "Vector2FDef")] Vector2F),
+    AngularVelocity(f32),
+    Damping(f32),
+    BounceBounds(BounceBounds),
+    RemoveBounceBounds,
+    /// Removes the container's velocity, acceleration and angular velocity, leaving it where it
+    /// is.
+    StopMotion,
+    Parallax(f32),
+    RemoveParallax,
+    Anchor(Anchor),
+    /// Stops anchoring the container, leaving it where it is.
+    RemoveAnchor,
+    /// Tweens which part of the container's paths are drawn. See `PathTrim`.
+    PathTrim(PathTrim, Easing, u32),
+    /// Sets the path trim immediately, cancelling any path trim tweens in flight.
+    SetPathTrim(PathTrim),
+    /// Draws the container's paths whole again.
+    RemovePathTrim,
+    /// Replaces the container's modifiers, starting them over.
+    Modifiers(Vec<Modifier>),
+    /// Removes the container's modifiers, leaving its properties as the tweens left them.
+    RemoveModifiers,
+}
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum BoundsKindDefinition {
+    Display,
+    Defined(RectPoints),
+    // Everything below was added after version 1 of the stream format. New variants go at the
+    // end, since the position of each variant is what bincode writes.
+    /// Display bounds grown to include the outer half of any strokes, with their caps and joins.
+    DisplayWithStroke,
+    /// The union of the bounds of all descendants.
+    Children,
+}
+
+/// A layer drawn behind every container, over the background color.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum BackgroundDefinition {
+    Solid(LinSrgb),
+    VerticalGradient {
+        top: LinSrgb,
+        bottom: LinSrgb,
+    },
+    /// Fades from the center of the stage out to its corners.
+    RadialGradient {
+        inner: LinSrgb,
+        outer: LinSrgb,
+    },
+    /// A texture from the library, tiled from the top left or stretched to fill the stage.
+    Raster {
+        id: LibraryId,
+        tiled: bool,
+    },
+}
+
+//TODO: additional actions: Text, Scripts, Fonts
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Action {
+    CreateRoot(ContainerId),
+    AddQuadTreeLayer(QuadTreeLayer, RectPoints, QuadTreeLayerOptions),
+    SetBackground {
+        color: LinSrgb,
+    },
+    EndInitialization,
+    Label(String),
+    DefineShape {
+        id: LibraryId,
+        shape: Shape,
+    },
+    LoadBitmap {
+        id: LibraryId,
+        bitmap: Bitmap,
+    },
+    CreateContainer(ContainerCreationDefintition),
+    UpdateContainer(ContainerUpdateDefintition),
+    RemoveContainer(ContainerId, bool),
+    PresentFrame(u32, u32), //TODO: if frames have set indexes, then how would it be possible to load in additional frames? Clip ID?
+    // Everything below was added after version 1 of the stream format. New actions go at the
+    // end, since the position of each variant is what bincode writes.
+    DefineStateMachine {
+        name: String,
+        machine: StateMachine,
+    },
+    SetLibraryMetadata {
+        id: LibraryId,
+        metadata: LibraryItemMetadata,
+    },
+    /// Drops every library item no container displays or shape references, to free memory
+    /// between scenes.
+    UnloadUnusedLibraryItems,
+    LoadTexture {
+        id: LibraryId,
+        texture: TextureDefinition,
+    },
+    /// Replaces the background layers, which are drawn bottom to top.
+    SetBackgroundLayers(Vec<BackgroundDefinition>),
+    /// Adds a quad tree layer and registers a name for it.
+    DeclareQuadTreeLayer {
+        name: String,
+        layer: QuadTreeLayer,
+        bounds: RectPoints,
+        options: QuadTreeLayerOptions,
+    },
+    /// Adds or replaces named palette colors, used by `Shape::Swatch`.
+    DefinePalette(Vec<(String, LinSrgba)>),
+    /// Does nothing to playback. Reported to the host through `Runner::poll_markers` when the
+    /// playhead reaches it, to sync game logic, analytics or subtitles to authored frames.
+    Marker {
+        name: String,
+        data: HashMap<String, String>,
+    },
+    /// Records what the cells of a baked bitmap were baked from. See `baking::SpriteSheet`.
+    DefineSpriteSheet {
+        id: LibraryId,
+        sheet: SpriteSheet,
+    },
+    /// Reseeds the engine's `Random`. Everything random in playback is derived from the seed,
+    /// so the same action stream always yields identical frames, as long as it is stepped with
+    /// the same frame times. Streams that never set a seed use 0.
+    SetSeed(u64),
+    /// A library item stored outside the stream. `location` is a path or URL handed to the
+    /// runner's `AssetResolver` when the action is reached. See `linking`.
+    LibraryRef {
+        id: LibraryId,
+        location: String,
+    },
+    /// Simplified variants drawn in place of shape `id` when it's small on screen. See
+    /// `Library::set_lod_variants`.
+    DefineLodVariants {
+        id: LibraryId,
+        variants: Vec<LodVariant>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_advances_actions() {
+        let actions = vec![Action::PresentFrame(1, 1)];
+        let mut action_list = ActionList::new(Box::new(|| None), Some(&actions));
+        assert_eq!(action_list.action_index(), 0);
+        assert_eq!(action_list.actions.len(), 1);
+        action_list.get().expect("Did not return expected action");
+        assert_eq!(action_list.action_index(), 0);
+        action_list.advance();
+        assert_eq!(action_list.action_index(), 0); //Does not advance past the end of the list
+        action_list.get().expect("Did not return expected action");
+    }
+
+    #[test]
+    fn it_loads_more() {
+        let actions = vec![Action::PresentFrame(1, 1)];
+        let mut action_list = ActionList::new(
+            Box::new(|| {
+                Some(vec![
+                    Action::PresentFrame(2, 1),
+                    Action::PresentFrame(3, 1),
+                    Action::PresentFrame(4, 1),
+                ])
+            }),
+            Some(&actions),
+        );
+        assert_eq!(action_list.action_index(), 0);
+        assert_eq!(action_list.actions.len(), 1);
+        action_list.advance();
+        assert_eq!(action_list.actions.len(), 4);
+    }
+
+    #[test]
+    fn it_jumps() {
+        let actions = vec![
+            Action::PresentFrame(1, 1),
+            Action::Label(String::from("label_1")),
+            Action::PresentFrame(2, 1),
+            Action::PresentFrame(3, 1),
+            Action::Label(String::from("label_2")),
+            Action::PresentFrame(4, 1),
+        ];
+        let mut action_list = ActionList::new(Box::new(|| None), Some(&actions));
+        action_list.advance();
+        action_list.advance();
+        action_list.advance();
+        assert_eq!(action_list.action_index(), 3);
+        assert_eq!(action_list.labels.len(), 1);
+        let result = action_list.jump_to_label("label_1").unwrap();
+        assert_eq!(result, (1, 2));
+        assert_eq!(action_list.action_index(), 1);
+        let result = action_list.jump_to_label("label_2").unwrap();
+        assert_eq!(result, (4, 4));
+        assert_eq!(action_list.labels.len(), 2);
+        assert_eq!(action_list.action_index(), 4);
+    }
+
+    #[test]
+    fn it_seeks_with_an_index() {
+        let actions = vec![
+            Action::PresentFrame(0, 2),
+            Action::Label(String::from("label_1")),
+            Action::PresentFrame(2, 3),
+            Action::PresentFrame(5, 1),
+        ];
+        let index = ActionIndex::build(&actions);
+        assert_eq!(index.frame(3), Some(2));
+        assert_eq!(index.frame(7), None);
+        let remaining = std::cell::RefCell::new(actions[1..].to_vec());
+        let mut action_list = ActionList::new(
+            Box::new(move || {
+                let mut remaining = remaining.borrow_mut();
+                if remaining.is_empty() {
+                    None
+                } else {
+                    Some(vec![remaining.remove(0)])
+                }
+            }),
+            Some(&actions[..1].to_vec()),
+        )
+        .with_index(index);
+        assert_eq!(action_list.seek_to_frame(5), Ok(3));
+        assert_eq!(action_list.frame_index(), 5);
+        assert_eq!(action_list.jump_to_label("label_1"), Ok((1, 2)));
+        assert!(action_list.seek_to_frame(7).is_err());
+    }
+}
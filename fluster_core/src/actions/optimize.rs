@@ -0,0 +1,393 @@
+//! Shrinks exported action streams without changing what they play.
+//!
+//! Editors tend to export a `PresentFrame` per frame and key every property on every keyframe,
+//! whether it changed or not. `optimize` merges contiguous frame ranges and drops updates that set
+//! a value that is already in effect.
+//!
+//! Only the stream and its state machines are assumed to change containers. Hosts that also
+//! change them through the `Engine` should export without optimizing.
+
+use super::{
+    Action, ContainerCreationDefintition, ContainerCreationProperty, ContainerUpdateDefintition,
+    ContainerUpdateProperty,
+};
+use crate::types::basic::ContainerId;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// What `optimize` saved.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OptimizationReport {
+    pub actions_before: usize,
+    pub actions_after: usize,
+    /// `PresentFrame`s folded into the range right before them.
+    pub merged_frames: usize,
+    /// Update properties that set a value already in effect.
+    pub redundant_properties: usize,
+    /// `UpdateContainer`s dropped because every one of their properties was redundant.
+    pub redundant_updates: usize,
+}
+
+impl OptimizationReport {
+    pub fn actions_saved(&self) -> usize {
+        self.actions_before - self.actions_after
+    }
+}
+
+impl fmt::Display for OptimizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Removed {} of {} actions ({} frames merged, {} updates and {} properties redundant)",
+            self.actions_saved(),
+            self.actions_before,
+            self.merged_frames,
+            self.redundant_updates,
+            self.redundant_properties
+        )
+    }
+}
+
+/// Removes redundant updates, then merges the frame ranges that became contiguous.
+pub fn optimize(actions: &mut Vec<Action>) -> OptimizationReport {
+    let actions_before = actions.len();
+    let (redundant_properties, redundant_updates) = remove_redundant_updates(actions);
+    let merged_frames = merge_frames(actions);
+    OptimizationReport {
+        actions_before,
+        actions_after: actions.len(),
+        merged_frames,
+        redundant_properties,
+        redundant_updates,
+    }
+}
+
+/// Folds each `PresentFrame` into the one before it when nothing happens in between and the
+/// ranges touch. Returns the number merged.
+pub fn merge_frames(actions: &mut Vec<Action>) -> usize {
+    let mut merged = 0;
+    let mut optimized: Vec<Action> = Vec::with_capacity(actions.len());
+    for action in actions.drain(..) {
+        if let Action::PresentFrame(start, count) = action {
+            if let Some(Action::PresentFrame(last_start, last_count)) = optimized.last_mut() {
+                if *last_start + *last_count == start {
+                    *last_count += count;
+                    merged += 1;
+                    continue;
+                }
+            }
+        }
+        optimized.push(action);
+    }
+    *actions = optimized;
+    merged
+}
+
+// Container state that setting twice to the same value has no effect on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Slot {
+    Transform,
+    MorphIndex,
+    Coloring,
+    ColorTransform,
+    ViewRect,
+    PathTrim,
+    Display,
+    Parent,
+    PixelSnap,
+    TimeScale,
+    Parallax,
+}
+
+fn set_slot(property: &ContainerUpdateProperty) -> Option<Slot> {
+    match property {
+        ContainerUpdateProperty::SetTransform(..) => Some(Slot::Transform),
+        ContainerUpdateProperty::SetMorphIndex(..) => Some(Slot::MorphIndex),
+        ContainerUpdateProperty::SetColoring(..) => Some(Slot::Coloring),
+        ContainerUpdateProperty::SetColorTransform(..) => Some(Slot::ColorTransform),
+        ContainerUpdateProperty::SetViewRect(..) => Some(Slot::ViewRect),
+        ContainerUpdateProperty::SetPathTrim(..) => Some(Slot::PathTrim),
+        ContainerUpdateProperty::Display(..) => Some(Slot::Display),
+        ContainerUpdateProperty::Parent(..) => Some(Slot::Parent),
+        ContainerUpdateProperty::PixelSnap(..) => Some(Slot::PixelSnap),
+        ContainerUpdateProperty::TimeScale(..) => Some(Slot::TimeScale),
+        ContainerUpdateProperty::Parallax(..) => Some(Slot::Parallax),
+        _ => None,
+    }
+}
+
+// Tweens and removals, after which the value is no longer known
+fn changed_slot(property: &ContainerUpdateProperty) -> Option<Slot> {
+    match property {
+        ContainerUpdateProperty::Transform(..) | ContainerUpdateProperty::TransformMatrix(..) => {
+            Some(Slot::Transform)
+        }
+        ContainerUpdateProperty::MorphIndex(..)
+        | ContainerUpdateProperty::MorphIndices(..)
+        | ContainerUpdateProperty::SetMorphIndices(..) => Some(Slot::MorphIndex),
+        ContainerUpdateProperty::Coloring(..) => Some(Slot::Coloring),
+        ContainerUpdateProperty::ColorTransform(..) => Some(Slot::ColorTransform),
+        ContainerUpdateProperty::ViewRect(..) => Some(Slot::ViewRect),
+        ContainerUpdateProperty::PathTrim(..) | ContainerUpdateProperty::RemovePathTrim => {
+            Some(Slot::PathTrim)
+        }
+        ContainerUpdateProperty::RemoveDisplay => Some(Slot::Display),
+        ContainerUpdateProperty::RemoveParallax => Some(Slot::Parallax),
+        _ => None,
+    }
+}
+
+// Properties that let something besides the stream move or restyle the container
+fn is_dynamic(property: &ContainerUpdateProperty) -> bool {
+    matches!(
+        property,
+        ContainerUpdateProperty::Velocity(..)
+            | ContainerUpdateProperty::Acceleration(..)
+            | ContainerUpdateProperty::AngularVelocity(..)
+            | ContainerUpdateProperty::BounceBounds(..)
+            | ContainerUpdateProperty::Draggable(..)
+            | ContainerUpdateProperty::Anchor(..)
+            | ContainerUpdateProperty::Modifiers(..)
+    )
+}
+
+fn is_dynamic_creation(property: &ContainerCreationProperty) -> bool {
+    matches!(
+        property,
+        ContainerCreationProperty::Velocity(..)
+            | ContainerCreationProperty::Acceleration(..)
+            | ContainerCreationProperty::AngularVelocity(..)
+            | ContainerCreationProperty::BounceBounds(..)
+            | ContainerCreationProperty::Draggable(..)
+            | ContainerCreationProperty::Anchor(..)
+            | ContainerCreationProperty::Modifiers(..)
+    )
+}
+
+// The update that would set the same value as a creation property
+fn created_value(property: &ContainerCreationProperty) -> Option<ContainerUpdateProperty> {
+    match property {
+        ContainerCreationProperty::Transform(srt) => {
+            Some(ContainerUpdateProperty::SetTransform(*srt))
+        }
+        ContainerCreationProperty::MorphIndex(morph_index) => {
+            Some(ContainerUpdateProperty::SetMorphIndex(*morph_index))
+        }
+        ContainerCreationProperty::Coloring(coloring) => {
+            Some(ContainerUpdateProperty::SetColoring(coloring.clone()))
+        }
+        ContainerCreationProperty::ColorTransform(color_transform) => {
+            Some(ContainerUpdateProperty::SetColorTransform(*color_transform))
+        }
+        ContainerCreationProperty::ViewRect(view_rect) => {
+            Some(ContainerUpdateProperty::SetViewRect(*view_rect))
+        }
+        ContainerCreationProperty::PathTrim(path_trim) => {
+            Some(ContainerUpdateProperty::SetPathTrim(*path_trim))
+        }
+        ContainerCreationProperty::Display(id) => Some(ContainerUpdateProperty::Display(*id)),
+        ContainerCreationProperty::PixelSnap => Some(ContainerUpdateProperty::PixelSnap(true)),
+        ContainerCreationProperty::Parallax(parallax) => {
+            Some(ContainerUpdateProperty::Parallax(*parallax))
+        }
+        _ => None,
+    }
+}
+
+// What the stream has set so far, as of the action being optimized
+#[derive(Default)]
+struct KnownValues {
+    values: HashMap<(ContainerId, Slot), ContainerUpdateProperty>,
+    // Containers physics, dragging, anchoring or modifiers change
+    dynamic: HashSet<ContainerId>,
+    // Containers state machines update, at times the stream doesn't show
+    scripted: HashSet<ContainerId>,
+    // Scroll views move their children, which aren't tracked
+    scrolls: bool,
+}
+
+impl KnownValues {
+    fn new(actions: &[Action]) -> Self {
+        let mut known_values = Self::default();
+        for action in actions {
+            match action {
+                Action::DefineStateMachine { machine, .. } => known_values
+                    .scripted
+                    .extend(machine.updates().map(|definition| *definition.id())),
+                Action::CreateContainer(definition) => {
+                    known_values.scrolls |= definition.properties().iter().any(|property| {
+                        matches!(property, ContainerCreationProperty::ScrollView(..))
+                    })
+                }
+                Action::UpdateContainer(definition) => {
+                    known_values.scrolls |= definition.properties().iter().any(|property| {
+                        matches!(
+                            property,
+                            ContainerUpdateProperty::ScrollView(..)
+                                | ContainerUpdateProperty::ScrollTo(..)
+                        )
+                    })
+                }
+                _ => (),
+            }
+        }
+        known_values
+    }
+
+    fn is_tracked(&self, id: &ContainerId, slot: Slot) -> bool {
+        !(self.dynamic.contains(id)
+            || self.scripted.contains(id)
+            || (self.scrolls && slot == Slot::Transform))
+    }
+
+    fn forget(&mut self, id: &ContainerId) {
+        self.values
+            .retain(|(container_id, _), _| container_id != id);
+    }
+
+    fn create(&mut self, definition: &ContainerCreationDefintition) {
+        let id = *definition.id();
+        self.forget(&id);
+        self.dynamic.remove(&id);
+        if definition.properties().iter().any(is_dynamic_creation) {
+            self.dynamic.insert(id);
+        }
+        let values = definition
+            .properties()
+            .iter()
+            .filter_map(created_value)
+            .chain(Some(ContainerUpdateProperty::Parent(*definition.parent())));
+        for value in values {
+            if let Some(slot) = set_slot(&value) {
+                if self.is_tracked(&id, slot) {
+                    self.values.insert((id, slot), value);
+                }
+            }
+        }
+    }
+
+    // False if the property sets a value already in effect
+    fn keep(&mut self, id: &ContainerId, property: &ContainerUpdateProperty) -> bool {
+        if is_dynamic(property) {
+            self.dynamic.insert(*id);
+            self.forget(id);
+        } else if let Some(slot) = changed_slot(property) {
+            self.values.remove(&(*id, slot));
+        } else if let Some(slot) = set_slot(property) {
+            if !self.is_tracked(id, slot) {
+                return true;
+            }
+            if self.values.get(&(*id, slot)) == Some(property) {
+                return false;
+            }
+            self.values.insert((*id, slot), property.clone());
+        }
+        true
+    }
+
+    fn update(&mut self, definition: &mut ContainerUpdateDefintition) {
+        let id = definition.id;
+        definition
+            .properties
+            .retain(|property| self.keep(&id, property));
+    }
+}
+
+/// Drops update properties that set a value the stream already set, and updates left with
+/// nothing to do. A value stops being known once a tween or removal changes it, and at every
+/// label, since playback can jump there from anywhere. Returns the number of properties and
+/// updates dropped.
+pub fn remove_redundant_updates(actions: &mut Vec<Action>) -> (usize, usize) {
+    let mut known_values = KnownValues::new(actions);
+    let mut redundant_properties = 0;
+    let mut redundant_updates = 0;
+    let mut optimized = Vec::with_capacity(actions.len());
+    for mut action in actions.drain(..) {
+        match &mut action {
+            Action::CreateContainer(definition) => known_values.create(definition),
+            Action::UpdateContainer(definition) => {
+                let before = definition.properties.len();
+                known_values.update(definition);
+                let removed = before - definition.properties.len();
+                redundant_properties += removed;
+                if removed > 0 && definition.properties.is_empty() {
+                    redundant_updates += 1;
+                    continue;
+                }
+            }
+            Action::RemoveContainer(id, _) => known_values.forget(id),
+            Action::Label(..) => known_values.values.clear(),
+            _ => (),
+        }
+        optimized.push(action);
+    }
+    *actions = optimized;
+    (redundant_properties, redundant_updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tween::Easing;
+    use crate::types::basic::ScaleRotationTranslation;
+    use pathfinder_geometry::vector::Vector2F;
+
+    fn set_transform(id: ContainerId, x: f32) -> Action {
+        Action::UpdateContainer(ContainerUpdateDefintition::new(
+            id,
+            vec![ContainerUpdateProperty::SetTransform(
+                ScaleRotationTranslation::new(Vector2F::splat(1.0), 0.0, Vector2F::new(x, 0.0)),
+            )],
+        ))
+    }
+
+    #[test]
+    fn it_drops_redundant_updates_and_merges_frames() {
+        let (root_id, id) = (ContainerId::new(), ContainerId::new());
+        let mut actions = vec![
+            Action::CreateRoot(root_id),
+            Action::CreateContainer(ContainerCreationDefintition::new(
+                root_id,
+                id,
+                vec![ContainerCreationProperty::Transform(
+                    ScaleRotationTranslation::new(Vector2F::splat(1.0), 0.0, Vector2F::zero()),
+                )],
+            )),
+            Action::EndInitialization,
+            Action::PresentFrame(0, 1),
+            set_transform(id, 0.0),
+            Action::PresentFrame(1, 1),
+            set_transform(id, 5.0),
+            Action::PresentFrame(2, 1),
+            set_transform(id, 5.0),
+            Action::PresentFrame(3, 1),
+            // A tween makes the value unknown, so setting it afterwards is kept
+            Action::UpdateContainer(ContainerUpdateDefintition::new(
+                id,
+                vec![ContainerUpdateProperty::Transform(
+                    ScaleRotationTranslation::default(),
+                    Easing::Linear,
+                    2,
+                )],
+            )),
+            Action::PresentFrame(4, 2),
+            set_transform(id, 5.0),
+            Action::Label("loop".to_string()),
+            set_transform(id, 5.0),
+            Action::PresentFrame(6, 1),
+        ];
+        let report = optimize(&mut actions);
+        assert_eq!(report.redundant_updates, 2);
+        assert_eq!(report.redundant_properties, 2);
+        assert_eq!(report.merged_frames, 2);
+        assert_eq!(report.actions_saved(), 4);
+        let frames = actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::PresentFrame(start, count) => Some((*start, *count)),
+                _ => None,
+            })
+            .collect::<Vec<(u32, u32)>>();
+        assert_eq!(frames, vec![(0, 2), (2, 2), (4, 2), (6, 1)]);
+    }
+}
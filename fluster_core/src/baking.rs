@@ -0,0 +1,484 @@
+use crate::{
+    actions::{
+        Action, ContainerCreationDefintition, ContainerCreationProperty,
+        ContainerUpdateDefintition, ContainerUpdateProperty, RectPoints,
+    },
+    ecs::resources::{FrameTime, Library, LibraryItemMetadata},
+    engine::{DrawableItem, Engine, LibraryItem},
+    types::basic::{Bitmap, ContainerId, LibraryId, ScaleRotationTranslation, Vector2FDef},
+};
+use pathfinder_color::ColorU;
+use pathfinder_geometry::{
+    rect::{RectF, RectI},
+    transform2d::Transform2F,
+    vector::{Vector2F, Vector2I},
+};
+use reduce::Reduce;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+
+/// Sheets wider than this wrap onto another row of cells.
+const DEFAULT_MAX_SHEET_WIDTH: i32 = 2048;
+
+/// Tag added to the metadata of baked sprite sheets.
+pub const BAKED_TAG: &str = "baked";
+
+/// Draws vector content into pixels for baking. The editor implements this on top of its GPU
+/// renderer, since the engine itself can't rasterize.
+pub trait Rasterizer {
+    /// Draws `drawable_items` onto a transparent image of `size` pixels, with their transforms
+    /// already mapping into that image. Returns the pixels row by row from the top.
+    fn rasterize(
+        &mut self,
+        drawable_items: Vec<DrawableItem>,
+        size: Vector2I,
+    ) -> Result<Vec<ColorU>, String>;
+}
+
+/// What a cell of a sprite sheet was baked from.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum BakeSource {
+    Shape {
+        id: LibraryId,
+        morph: f32,
+    },
+    /// A container and its children, `frame` frames after baking started.
+    Container {
+        id: ContainerId,
+        frame: u32,
+    },
+}
+
+/// One cell of a sprite sheet.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SpriteFrame {
+    pub source: BakeSource,
+    /// The cell's pixels in the sheet.
+    pub rect: RectPoints,
+    /// Where the source's origin lands, in sheet pixels. May be outside `rect`.
+    #[serde(with = "Vector2FDef")]
+    pub origin: Vector2F,
+}
+
+impl SpriteFrame {
+    /// Maps the cell, drawn at its own origin as rasters are, back onto its source's space.
+    pub fn transform(&self, scale: f32) -> Transform2F {
+        Transform2F::from_scale(Vector2F::splat(1.0 / scale))
+            * Transform2F::from_translation(self.rect.origin - self.origin)
+    }
+}
+
+/// The bookkeeping for a baked sprite sheet. The engine only stores it, it is there so tools can
+/// map cells back to what they were baked from.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SpriteSheet {
+    /// Sheet pixels per stage unit.
+    pub scale: f32,
+    pub frames: Vec<SpriteFrame>,
+}
+
+impl SpriteSheet {
+    /// The frame a raster container displaying this sheet with `view_rect` is showing.
+    pub fn frame_at(&self, view_rect: RectF) -> Option<&SpriteFrame> {
+        self.frames.iter().find(|frame| {
+            RectF::from_points(frame.rect.origin, frame.rect.lower_right) == view_rect
+        })
+    }
+
+    /// The properties that make a container displaying sheet `id` show `frame`, with the frame's
+    /// source origin at the container's origin.
+    pub fn creation_properties(
+        &self,
+        id: LibraryId,
+        frame: usize,
+    ) -> Result<Vec<ContainerCreationProperty>, String> {
+        let sprite_frame = self.get_frame(frame)?;
+        Ok(vec![
+            ContainerCreationProperty::Display(id),
+            ContainerCreationProperty::ViewRect(sprite_frame.rect),
+            ContainerCreationProperty::Transform(ScaleRotationTranslation::from_transform(
+                sprite_frame.transform(self.scale),
+            )),
+        ])
+    }
+
+    /// Switches a container made with `creation_properties` to another frame.
+    pub fn update_properties(&self, frame: usize) -> Result<Vec<ContainerUpdateProperty>, String> {
+        let sprite_frame = self.get_frame(frame)?;
+        Ok(vec![
+            ContainerUpdateProperty::SetViewRect(sprite_frame.rect),
+            ContainerUpdateProperty::SetTransform(ScaleRotationTranslation::from_transform(
+                sprite_frame.transform(self.scale),
+            )),
+        ])
+    }
+
+    /// A clip that plays `frames` of sheet `id` as a flipbook on a new container, holding each
+    /// for `frames_per_cell` frames from `start_frame` on. Splice it into an action list where
+    /// `start_frame` would be presented.
+    pub fn flipbook(
+        &self,
+        id: LibraryId,
+        parent: ContainerId,
+        container_id: ContainerId,
+        frames: &[usize],
+        start_frame: u32,
+        frames_per_cell: u32,
+    ) -> Result<Vec<Action>, String> {
+        let first = *frames
+            .first()
+            .ok_or_else(|| "A flipbook needs at least one frame".to_string())?;
+        let mut actions = vec![Action::CreateContainer(ContainerCreationDefintition::new(
+            parent,
+            container_id,
+            self.creation_properties(id, first)?,
+        ))];
+        let mut frame = start_frame;
+        for (index, sheet_frame) in frames.iter().enumerate() {
+            if index > 0 {
+                actions.push(Action::UpdateContainer(ContainerUpdateDefintition::new(
+                    container_id,
+                    self.update_properties(*sheet_frame)?,
+                )));
+            }
+            actions.push(Action::PresentFrame(frame, frames_per_cell));
+            frame += frames_per_cell;
+        }
+        Ok(actions)
+    }
+
+    fn get_frame(&self, frame: usize) -> Result<&SpriteFrame, String> {
+        self.frames
+            .get(frame)
+            .ok_or_else(|| format!("Sprite sheet has no frame {}", frame))
+    }
+}
+
+/// A sprite sheet and its pixels, ready to be loaded into a library.
+#[derive(Clone, Debug)]
+pub struct BakedSpriteSheet {
+    pub id: LibraryId,
+    pub bitmap: Bitmap,
+    pub sheet: SpriteSheet,
+}
+
+impl BakedSpriteSheet {
+    /// Loads the sheet and its bookkeeping. The vector sources stay in the library, so tools can
+    /// keep showing them.
+    pub fn to_actions(&self, name: &str) -> Vec<Action> {
+        let mut metadata = LibraryItemMetadata::new(name.to_string());
+        metadata.tags.push(BAKED_TAG.to_string());
+        vec![
+            Action::LoadBitmap {
+                id: self.id,
+                bitmap: self.bitmap.clone(),
+            },
+            Action::SetLibraryMetadata {
+                id: self.id,
+                metadata,
+            },
+            Action::DefineSpriteSheet {
+                id: self.id,
+                sheet: self.sheet.clone(),
+            },
+        ]
+    }
+}
+
+struct BakeCell {
+    source: BakeSource,
+    drawable_items: Vec<DrawableItem>,
+    // In the source's space
+    bounds: RectF,
+}
+
+/// Collects shapes, or frames of a container, and bakes them into one sprite sheet.
+pub struct SpriteSheetBuilder {
+    cells: Vec<BakeCell>,
+    scale: f32,
+    padding: i32,
+    max_width: i32,
+}
+
+impl Default for SpriteSheetBuilder {
+    fn default() -> Self {
+        Self {
+            cells: vec![],
+            scale: 1.0,
+            padding: 1,
+            max_width: DEFAULT_MAX_SHEET_WIDTH,
+        }
+    }
+}
+
+impl SpriteSheetBuilder {
+    /// Sheet pixels per stage unit. Bake at the device pixel ratio content will be shown at.
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Transparent pixels around each cell, so neighbours don't bleed into each other when
+    /// sampled with smoothing.
+    pub fn with_padding(mut self, padding: i32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: i32) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    pub fn add_shape(
+        &mut self,
+        library: &Library,
+        id: &LibraryId,
+        morph: f32,
+    ) -> Result<(), String> {
+        let shape = library
+            .get_resolved_shape(id)
+            .ok_or_else(|| format!("Shape {:?} is not in the library", id))?;
+        let bounds = shape.compute_stroked_bounding(&Transform2F::default(), morph);
+        self.cells.push(BakeCell {
+            source: BakeSource::Shape { id: *id, morph },
+            drawable_items: vec![DrawableItem {
+                container_id: ContainerId::new(),
+                library_item: LibraryItem::Vector(shape),
+                transform: Transform2F::default(),
+                coloring: None,
+                view_rect: None,
+                morph,
+                morph_indices: vec![],
+                clips: vec![],
+                parallax: 1.0,
+            }],
+            bounds,
+        });
+        Ok(())
+    }
+
+    /// Captures the container and its children for `frames` frames, stepping the engine one
+    /// frame between each. Every frame gets the same cell size and origin, so they play back as
+    /// a flipbook without jittering.
+    pub fn add_container_frames(
+        &mut self,
+        engine: &mut Engine,
+        container_id: &ContainerId,
+        frames: u32,
+        frame_duration: Duration,
+    ) -> Result<(), String> {
+        let first = self.cells.len();
+        for frame in 0..frames {
+            if frame > 0 {
+                engine.update(FrameTime::new(frame_duration, 1));
+            }
+            let to_local = engine
+                .get_world_transform(container_id)
+                .ok_or_else(|| format!("Container {:?} not found", container_id))?
+                .inverse();
+            let drawable_items = engine
+                .get_drawable_items()
+                .into_iter()
+                .filter(|drawable_item| {
+                    engine
+                        .get_self_and_parents(&drawable_item.container_id)
+                        .contains(container_id)
+                })
+                .map(|mut drawable_item| {
+                    drawable_item.transform = to_local * drawable_item.transform;
+                    drawable_item.clips.clear();
+                    drawable_item
+                })
+                .collect::<Vec<DrawableItem>>();
+            let bounds = drawable_items
+                .iter()
+                .map(drawable_bounds)
+                .reduce(|a, b| a.union_rect(b))
+                .unwrap_or_default();
+            self.cells.push(BakeCell {
+                source: BakeSource::Container {
+                    id: *container_id,
+                    frame,
+                },
+                drawable_items,
+                bounds,
+            });
+        }
+        if let Some(shared) = self.cells[first..]
+            .iter()
+            .map(|cell| cell.bounds)
+            .reduce(|a, b| a.union_rect(b))
+        {
+            for cell in self.cells[first..].iter_mut() {
+                cell.bounds = shared;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn bake(
+        self,
+        rasterizer: &mut impl Rasterizer,
+        id: LibraryId,
+    ) -> Result<BakedSpriteSheet, String> {
+        if self.cells.is_empty() {
+            return Err("Nothing to bake".to_string());
+        }
+        let scale = self.scale;
+        let padding = self.padding;
+        let sizes = self
+            .cells
+            .iter()
+            .map(|cell| {
+                let size = (cell.bounds.size() * scale).ceil().to_i32();
+                size + Vector2I::splat(padding * 2)
+            })
+            .collect::<Vec<Vector2I>>();
+        let (sheet_size, positions) = pack_cells(&sizes, self.max_width)?;
+        let mut colors =
+            vec![ColorU::transparent_black(); (sheet_size.x() * sheet_size.y()) as usize];
+        let mut frames = vec![];
+        for ((cell, size), position) in self.cells.into_iter().zip(sizes).zip(positions) {
+            // Source space into the cell's own pixels
+            let to_cell = Transform2F::from_translation(Vector2F::splat(padding as f32))
+                * Transform2F::from_scale(Vector2F::splat(scale))
+                * Transform2F::from_translation(-cell.bounds.origin());
+            let drawable_items = cell
+                .drawable_items
+                .into_iter()
+                .map(|mut drawable_item| {
+                    drawable_item.transform = to_cell * drawable_item.transform;
+                    drawable_item
+                })
+                .collect();
+            let pixels = rasterizer.rasterize(drawable_items, size)?;
+            if pixels.len() != (size.x() * size.y()) as usize {
+                return Err(format!(
+                    "Rasterizer returned {} pixels for a {:?} cell",
+                    pixels.len(),
+                    size
+                ));
+            }
+            blit(
+                &mut colors,
+                sheet_size.x(),
+                &pixels,
+                RectI::new(position, size),
+            );
+            let rect = RectI::new(position, size).to_f32();
+            frames.push(SpriteFrame {
+                source: cell.source,
+                rect: RectPoints::from_rect(&rect),
+                origin: position.to_f32() + to_cell.translation(),
+            });
+        }
+        Ok(BakedSpriteSheet {
+            id,
+            bitmap: Bitmap {
+                size_x: sheet_size.x(),
+                size_y: sheet_size.y(),
+                colors: Arc::new(colors),
+            },
+            sheet: SpriteSheet { scale, frames },
+        })
+    }
+}
+
+fn drawable_bounds(drawable_item: &DrawableItem) -> RectF {
+    match &drawable_item.library_item {
+        LibraryItem::Vector(shape) => {
+            shape.compute_stroked_bounding(&drawable_item.transform, drawable_item.morph)
+        }
+        LibraryItem::Raster(pattern) => {
+            let size = drawable_item
+                .view_rect
+                .map(|view_rect| view_rect.size())
+                .unwrap_or_else(|| pattern.size().to_f32());
+            drawable_item.transform * RectF::new(Vector2F::zero(), size)
+        }
+    }
+}
+
+// Copies a cell's rows into the sheet
+fn blit(sheet: &mut [ColorU], sheet_width: i32, pixels: &[ColorU], rect: RectI) {
+    let width = rect.width() as usize;
+    for row in 0..rect.height() {
+        let source = (row * rect.width()) as usize;
+        let target = ((rect.origin_y() + row) * sheet_width + rect.origin_x()) as usize;
+        sheet[target..target + width].copy_from_slice(&pixels[source..source + width]);
+    }
+}
+
+/// Packs cells into rows, tallest first, starting a new row whenever one would grow past
+/// `max_width`. Returns the sheet size and each cell's position, in the order given.
+// TODO: shelves waste the space above shorter cells. A skyline packer would do better.
+pub fn pack_cells(sizes: &[Vector2I], max_width: i32) -> Result<(Vector2I, Vec<Vector2I>), String> {
+    if let Some(too_wide) = sizes.iter().find(|size| size.x() > max_width) {
+        return Err(format!(
+            "A {:?} cell doesn't fit in a sheet {} pixels wide",
+            too_wide, max_width
+        ));
+    }
+    let mut order = (0..sizes.len()).collect::<Vec<usize>>();
+    order.sort_by_key(|index| -sizes[*index].y());
+    let mut positions = vec![Vector2I::zero(); sizes.len()];
+    let mut cursor = Vector2I::zero();
+    let mut row_height = 0;
+    let mut width = 0;
+    for index in order {
+        let size = sizes[index];
+        if cursor.x() > 0 && cursor.x() + size.x() > max_width {
+            cursor = Vector2I::new(0, cursor.y() + row_height);
+            row_height = 0;
+        }
+        positions[index] = cursor;
+        cursor = cursor + Vector2I::new(size.x(), 0);
+        row_height = row_height.max(size.y());
+        width = width.max(cursor.x());
+    }
+    Ok((Vector2I::new(width, cursor.y() + row_height), positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_packs_cells_into_rows() {
+        let sizes = vec![
+            Vector2I::new(60, 10),
+            Vector2I::new(50, 30),
+            Vector2I::new(40, 20),
+        ];
+        let (sheet_size, positions) = pack_cells(&sizes, 100).unwrap();
+        assert_eq!(
+            positions,
+            vec![
+                Vector2I::new(0, 30),
+                Vector2I::new(0, 0),
+                Vector2I::new(50, 0)
+            ]
+        );
+        assert_eq!(sheet_size, Vector2I::new(90, 40));
+        assert!(pack_cells(&sizes, 55).is_err());
+    }
+
+    #[test]
+    fn it_maps_frames_back_to_their_source() {
+        let frame = SpriteFrame {
+            source: BakeSource::Shape {
+                id: LibraryId::new(),
+                morph: 0.0,
+            },
+            rect: RectPoints::from_rect(&RectF::new(
+                Vector2F::new(40.0, 20.0),
+                Vector2F::new(24.0, 24.0),
+            )),
+            origin: Vector2F::new(52.0, 32.0),
+        };
+        let transform = frame.transform(2.0);
+        assert_eq!(transform * Vector2F::zero(), Vector2F::new(-6.0, -6.0));
+        assert_eq!(transform * Vector2F::new(12.0, 12.0), Vector2F::zero());
+    }
+}
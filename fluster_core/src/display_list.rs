@@ -0,0 +1,165 @@
+//! Hands finished frames from the simulation thread to a render thread.
+//!
+//! The engine captures a `DisplayList` after each update and publishes it into a triple buffer.
+//! The render thread paints whichever list is newest whenever it presents, so a slow update
+//! repeats the previous frame instead of stalling presentation, and a slow present skips stale
+//! frames instead of queueing them.
+
+use crate::{
+    ecs::resources::{Camera, RenderQuality},
+    engine::{DrawableItem, Engine},
+    rendering::{apply_camera, paint_items, Renderer},
+};
+use pathfinder_geometry::transform2d::Transform2F;
+use std::{
+    mem,
+    sync::{Arc, Mutex},
+};
+
+/// Everything needed to paint one frame, detached from the engine. Drawables are in stage space
+/// and paint order; shapes and textures are shared with the library, so capturing is cheap.
+#[derive(Clone, Debug)]
+pub struct DisplayList {
+    pub drawable_items: Vec<DrawableItem>,
+    /// Drawn after `drawable_items`. See `Engine::get_debug_drawables`.
+    pub debug_items: Vec<DrawableItem>,
+    pub camera: Camera,
+    pub render_quality: RenderQuality,
+}
+
+impl DisplayList {
+    pub fn capture(engine: &Engine) -> Self {
+        Self {
+            drawable_items: engine.get_drawable_items(),
+            debug_items: engine.get_debug_drawables(),
+            camera: engine.get_camera(),
+            render_quality: engine.get_render_quality(),
+        }
+    }
+
+    /// Like `paint_with_camera`, but from the captured frame. Returns the number of items drawn.
+    pub fn paint(&self, renderer: &mut impl Renderer, device: Transform2F) -> usize {
+        renderer.set_render_quality(self.render_quality);
+        let drawable_items = self
+            .drawable_items
+            .iter()
+            .chain(self.debug_items.iter())
+            .map(|drawable_item| {
+                let mut drawable_item = drawable_item.clone();
+                apply_camera(&mut drawable_item, &self.camera, device);
+                drawable_item
+            })
+            .collect();
+        paint_items(renderer, drawable_items)
+    }
+}
+
+struct Shared<T> {
+    middle: Option<T>,
+    // True if `middle` was published since the reader last took it
+    fresh: bool,
+}
+
+/// A single producer, single consumer triple buffer. The writer fills one slot while the reader
+/// holds another, and they swap through the third, so neither waits on the other for longer than
+/// a swap.
+pub fn triple_buffer<T>() -> (TripleBufferWriter<T>, TripleBufferReader<T>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        middle: None,
+        fresh: false,
+    }));
+    (
+        TripleBufferWriter {
+            shared: shared.clone(),
+        },
+        TripleBufferReader {
+            front: None,
+            shared,
+        },
+    )
+}
+
+pub struct TripleBufferWriter<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> TripleBufferWriter<T> {
+    /// Makes `value` the newest. Returns the value it displaced, either one the reader never saw
+    /// or one the reader has moved on from, so it can be reused.
+    pub fn publish(&mut self, value: T) -> Option<T> {
+        let mut shared = self.shared.lock().unwrap();
+        shared.fresh = true;
+        mem::replace(&mut shared.middle, Some(value))
+    }
+
+    /// False once the reader has been dropped.
+    pub fn is_connected(&self) -> bool {
+        Arc::strong_count(&self.shared) > 1
+    }
+}
+
+pub struct TripleBufferReader<T> {
+    front: Option<T>,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> TripleBufferReader<T> {
+    /// The newest published value, or None if nothing has been published yet. Keeps returning
+    /// the same value until a newer one is published.
+    pub fn latest(&mut self) -> Option<&T> {
+        {
+            let mut shared = self.shared.lock().unwrap();
+            if shared.fresh {
+                shared.fresh = false;
+                mem::swap(&mut self.front, &mut shared.middle);
+            }
+        }
+        self.front.as_ref()
+    }
+
+    /// True if a value was published since the last call to `latest`.
+    pub fn has_update(&self) -> bool {
+        self.shared.lock().unwrap().fresh
+    }
+}
+
+pub type DisplayListWriter = TripleBufferWriter<Arc<DisplayList>>;
+pub type DisplayListReader = TripleBufferReader<Arc<DisplayList>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn it_reads_the_newest_value() {
+        let (mut writer, mut reader) = triple_buffer();
+        assert_eq!(reader.latest(), None);
+        assert_eq!(writer.publish(1), None);
+        assert_eq!(writer.publish(2), Some(1));
+        assert!(reader.has_update());
+        assert_eq!(reader.latest(), Some(&2));
+        assert_eq!(reader.latest(), Some(&2));
+        assert!(!reader.has_update());
+        writer.publish(3);
+        assert_eq!(reader.latest(), Some(&3));
+    }
+
+    #[test]
+    fn it_hands_values_across_threads() {
+        let (mut writer, mut reader) = triple_buffer();
+        let producer = thread::spawn(move || {
+            for frame in 0..1000u32 {
+                writer.publish(frame);
+            }
+        });
+        let mut last = None;
+        while last != Some(999) {
+            if let Some(&frame) = reader.latest() {
+                assert!(last.map_or(true, |last| frame >= last));
+                last = Some(frame);
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -1,22 +1,34 @@
 use super::{
-    components::{BoundsSource, Display, DisplayKind, Morph, ViewRect},
-    resources::Library,
+    components::{BoundsSource, Display, DisplayKind, Morph, MorphIndices, ViewRect},
+    resources::{Library, SceneGraph},
 };
 use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use specs::Entity;
 
+/// Returns `None` for `BoundsSource::Children`, which depends on the rest of the scene graph and
+/// is computed with `union_of_children_bounds` instead.
 pub fn recompute_bounds(
     source: &BoundsSource,
     transform: Transform2F,
     display: Option<&Display>,
     view_rect: Option<&ViewRect>,
     morph: Option<&Morph>,
+    morph_indices: Option<&MorphIndices>,
     library: &Library,
-) -> RectF {
+) -> Option<RectF> {
     match source {
-        BoundsSource::Display => match display {
+        BoundsSource::Display | BoundsSource::DisplayWithStroke => match display {
             Some(Display(id, DisplayKind::Vector)) => {
-                let shape = library.get_shape(id).unwrap();
-                shape.compute_bounding(&transform, morph.unwrap_or(&Morph(0.0)).0)
+                let shape = library.get_resolved_shape(id).unwrap();
+                Some(shape.compute_bounding_per_shape(
+                    &transform,
+                    morph.unwrap_or(&Morph(0.0)).0,
+                    morph_indices.map_or(&[], |MorphIndices(indices)| indices),
+                    match source {
+                        BoundsSource::DisplayWithStroke => true,
+                        _ => false,
+                    },
+                ))
             }
             Some(Display(id, DisplayKind::Raster)) => {
                 let pattern = library.get_texture(id).unwrap();
@@ -25,7 +37,7 @@ pub fn recompute_bounds(
                     .unwrap_or_else(|| (Vector2F::zero(), pattern.size().to_f32()));
                 let o = transform * o;
                 let lr = transform * lr;
-                RectF::from_points(o.min(lr), o.max(lr))
+                Some(RectF::from_points(o.min(lr), o.max(lr)))
             }
             None => {
                 panic!("Attmpting to compute the bounds of an entity without an attached display")
@@ -34,7 +46,31 @@ pub fn recompute_bounds(
         BoundsSource::Defined(rect) => {
             let o = transform * rect.origin();
             let lr = transform * rect.lower_right();
-            RectF::from_points(o.min(lr), o.max(lr))
+            Some(RectF::from_points(o.min(lr), o.max(lr)))
         }
+        BoundsSource::Children => None,
     }
 }
+
+/// Union of the bounds of `entity`'s descendants. Descendants without bounds are looked through,
+/// so grouping containers can be nested. Returns `None` if no descendant has bounds.
+pub fn union_of_children_bounds(
+    entity: &Entity,
+    scene_graph: &SceneGraph,
+    get_bounds: impl Fn(Entity) -> Option<RectF>,
+) -> Option<RectF> {
+    let mut union: Option<RectF> = None;
+    let mut stack = scene_graph
+        .get_children(entity)
+        .cloned()
+        .unwrap_or_default();
+    while let Some(child) = stack.pop() {
+        match get_bounds(child) {
+            Some(bounds) => {
+                union = Some(union.map_or(bounds, |union| union.union_rect(bounds)));
+            }
+            None => stack.extend(scene_graph.get_children(&child).into_iter().flatten()),
+        }
+    }
+    union
+}
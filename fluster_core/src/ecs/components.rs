@@ -1,13 +1,22 @@
 use super::resources::QuadTreeLayer;
-use crate::tween::PropertyTween;
-use crate::types::{basic::LibraryId, coloring::Coloring};
+use crate::actions::RectPoints;
+use crate::modifiers::{Modifier, ModifierOffsets};
+use crate::tween::{Easing, PropertyTween};
+use crate::types::{
+    basic::{ContainerId, LibraryId, Vector2FDef},
+    coloring::{ColorTransform, Coloring},
+    shapes::{Edge, PathTrim},
+};
 use pathfinder_geometry::rect::RectF;
 use pathfinder_geometry::transform2d::Transform2F;
+use pathfinder_geometry::vector::Vector2F;
+use serde::{Deserialize, Serialize};
 use specs::{
-    storage::{BTreeStorage, DenseVecStorage, VecStorage},
+    storage::{BTreeStorage, DenseVecStorage, NullStorage, VecStorage},
     Component, FlaggedStorage,
 };
 use std::collections::HashSet;
+use std::time::Duration;
 #[derive(Debug, Copy, Clone, Default)]
 pub struct LocalTransform(pub Transform2F);
 
@@ -35,7 +44,9 @@ impl Component for Bounds {
 #[derive(Debug)]
 pub enum BoundsSource {
     Display,
+    DisplayWithStroke,
     Defined(RectF),
+    Children,
 }
 
 #[derive(Debug, Default)]
@@ -47,10 +58,14 @@ impl Component for Layer {
     type Storage = FlaggedStorage<Self, BTreeStorage<Self>>;
 }
 
-#[derive(Component, Debug)]
-#[storage(DenseVecStorage)]
+#[derive(Debug)]
 pub struct Display(pub LibraryId, pub DisplayKind);
 
+// Flagged so the cached paint order notices displays coming and going
+impl Component for Display {
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum DisplayKind {
     Raster,
@@ -65,10 +80,21 @@ impl Component for Coloring {
     type Storage = BTreeStorage<Self>;
 }
 
-#[derive(Component, Clone, Copy, Default, Debug)]
-#[storage(VecStorage)]
+impl Component for ColorTransform {
+    type Storage = BTreeStorage<Self>;
+}
+
+impl Component for PathTrim {
+    type Storage = BTreeStorage<Self>;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
 pub struct Order(pub i8);
 
+impl Component for Order {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
 #[derive(Debug, Default, Copy, Clone)]
 pub struct Morph(pub f32);
 
@@ -76,6 +102,409 @@ impl Component for Morph {
     type Storage = FlaggedStorage<Self, BTreeStorage<Self>>;
 }
 
+/// Morph indices for the shapes of a displayed `Shape::Group`, by position, so each can animate
+/// on its own. Shapes past the end, and nested groups' shapes, use the container's `Morph`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MorphIndices(pub Vec<f32>);
+
+impl Component for MorphIndices {
+    type Storage = FlaggedStorage<Self, BTreeStorage<Self>>;
+}
+
+// Every animated container has one and most systems join over it each frame, so it's stored for
+// fast iteration rather than lookup
 #[derive(Component, Debug)]
-#[storage(BTreeStorage)]
+#[storage(DenseVecStorage)]
 pub struct Tweens(pub Vec<PropertyTween>);
+
+/// Scales how fast tweens advance on this container and all of its descendants. Scales multiply
+/// down the scene graph, and 0.0 pauses the subtree.
+#[derive(Component, Clone, Copy, Debug)]
+#[storage(BTreeStorage)]
+pub struct TimeScale(pub f32);
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// The area that counts as a hit for point queries, in the container's local space. Containers
+/// without one are hit anywhere inside their bounds.
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[storage(BTreeStorage)]
+pub enum HitShape {
+    /// The fill area of a library shape, at the container's current morph index. The shape does
+    /// not need to be the one displayed.
+    Shape(LibraryId),
+    Edges(Vec<Edge>),
+}
+
+/// Which way a `Draggable` container can move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DragAxis {
+    Both,
+    Horizontal,
+    Vertical,
+}
+
+impl Default for DragAxis {
+    fn default() -> Self {
+        Self::Both
+    }
+}
+
+/// Lets the pointer drag the container around. Drags are started with `Engine::pointer_down` and
+/// move the container's translation, in its parent's space, so rotated and scaled parents still
+/// track the pointer.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[storage(BTreeStorage)]
+pub struct Draggable {
+    pub axis: DragAxis,
+    /// Keeps the container's translation inside this rect, in its parent's space.
+    pub bounds: Option<RectPoints>,
+    /// Tweens the container back to where the drag started when it is dropped. A duration of 0
+    /// frames jumps straight back.
+    pub snap_back: Option<(Easing, u32)>,
+}
+
+impl Draggable {
+    /// Applies the axis and bounds constraints to a translation the pointer dragged to.
+    pub fn constrain(&self, start: Vector2F, translation: Vector2F) -> Vector2F {
+        let translation = match self.axis {
+            DragAxis::Both => translation,
+            DragAxis::Horizontal => Vector2F::new(translation.x(), start.y()),
+            DragAxis::Vertical => Vector2F::new(start.x(), translation.y()),
+        };
+        match &self.bounds {
+            Some(bounds) => translation.max(bounds.origin).min(bounds.lower_right),
+            None => translation,
+        }
+    }
+}
+
+/// Below this speed, in units per second, a flung scroll view stops.
+const MIN_SCROLL_SPEED: f32 = 1.0;
+
+/// Clips the container's children to a rect at its origin and scrolls them by `offset`. The
+/// children are the scrolled content. Scroll views are moved with `Engine::scroll`, or by
+/// dragging them with the `Engine::pointer_down` family, and keep moving for a while when flung.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScrollView {
+    /// The visible area, in the container's own space.
+    #[serde(with = "Vector2FDef")]
+    pub size: Vector2F,
+    /// How far the content extends from the container's origin. The offset is kept between zero
+    /// and `content_size - size`.
+    #[serde(with = "Vector2FDef")]
+    pub content_size: Vector2F,
+    #[serde(with = "Vector2FDef")]
+    pub offset: Vector2F,
+    /// The fraction of a fling's speed that is left after one second. 0 disables inertia.
+    pub friction: f32,
+    // Units per second. Runtime state, so it isn't saved.
+    #[serde(skip)]
+    pub(crate) velocity: Vector2F,
+}
+
+impl Component for ScrollView {
+    type Storage = FlaggedStorage<Self, BTreeStorage<Self>>;
+}
+
+impl ScrollView {
+    pub fn new(size: Vector2F, content_size: Vector2F) -> Self {
+        Self {
+            size,
+            content_size,
+            offset: Vector2F::zero(),
+            friction: 0.05,
+            velocity: Vector2F::zero(),
+        }
+    }
+
+    pub fn clip_rect(&self) -> RectF {
+        RectF::new(Vector2F::zero(), self.size)
+    }
+
+    pub fn max_offset(&self) -> Vector2F {
+        (self.content_size - self.size).max(Vector2F::zero())
+    }
+
+    pub fn clamp_offset(&self, offset: Vector2F) -> Vector2F {
+        offset.max(Vector2F::zero()).min(self.max_offset())
+    }
+
+    pub fn is_flung(&self) -> bool {
+        self.velocity != Vector2F::zero()
+    }
+
+    /// Moves the offset by `delta`, clamped to the content. Returns false if it didn't move.
+    pub fn scroll_by(&mut self, delta: Vector2F) -> bool {
+        let offset = self.clamp_offset(self.offset + delta);
+        let moved = offset != self.offset;
+        self.offset = offset;
+        moved
+    }
+
+    /// Starts the view coasting at `velocity`, in units per second.
+    pub fn fling(&mut self, velocity: Vector2F) {
+        self.velocity = if self.friction > 0.0 && velocity.length() >= MIN_SCROLL_SPEED {
+            velocity
+        } else {
+            Vector2F::zero()
+        };
+    }
+
+    /// Advances a fling by `seconds`. Returns false if the offset didn't move.
+    pub fn coast(&mut self, seconds: f32) -> bool {
+        if !self.is_flung() {
+            return false;
+        }
+        let moved = self.scroll_by(self.velocity * seconds);
+        let velocity = self.velocity * self.friction.max(0.0).min(1.0).powf(seconds);
+        // Hitting the end of the content stops the fling
+        self.velocity = if moved && velocity.length() >= MIN_SCROLL_SPEED {
+            velocity
+        } else {
+            Vector2F::zero()
+        };
+        moved
+    }
+}
+
+/// How much of the `Camera` applies to the container and its children. 0.0 locks them to the
+/// screen, for HUDs, 1.0 moves them with the world, and values in between make distant layers
+/// that pan and zoom less. Nested factors multiply.
+// TODO: hit testing still assumes the full camera applies to every container
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+#[storage(BTreeStorage)]
+pub struct Parallax(pub f32);
+
+impl Default for Parallax {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Pins the container's translation to a point on the stage, so HUD elements stay in their
+/// corner or on their edge whatever the stage's size or aspect ratio. The translation is in the
+/// parent's space, so anchored containers belong under the root or an unmoved layer, usually with
+/// `Parallax(0.0)` so the camera leaves them alone too.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[storage(BTreeStorage)]
+pub struct Anchor {
+    /// A fraction of the stage's safe area. (0, 0) is the top left, (1, 1) the bottom right.
+    #[serde(with = "Vector2FDef")]
+    pub point: Vector2F,
+    /// Added to `point`, in stage units.
+    #[serde(with = "Vector2FDef")]
+    pub offset: Vector2F,
+}
+
+impl Anchor {
+    pub fn new(point: Vector2F, offset: Vector2F) -> Self {
+        Self { point, offset }
+    }
+
+    /// Where the container's translation goes, when anchors are laid out in `area`.
+    pub fn position(&self, area: RectF) -> Vector2F {
+        area.origin() + area.size() * self.point + self.offset
+    }
+}
+
+/// Procedural modifiers layered over the container's properties every frame. See `Modifier`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Modifiers {
+    modifiers: Vec<Modifier>,
+    elapsed: Duration,
+    offsets: ModifierOffsets,
+}
+
+impl Component for Modifiers {
+    type Storage = FlaggedStorage<Self, BTreeStorage<Self>>;
+}
+
+impl Modifiers {
+    pub fn new(modifiers: Vec<Modifier>) -> Self {
+        Self {
+            offsets: ModifierOffsets::evaluate(&modifiers, 0.0),
+            modifiers,
+            elapsed: Duration::default(),
+        }
+    }
+
+    pub fn modifiers(&self) -> &[Modifier] {
+        &self.modifiers
+    }
+
+    /// What the modifiers add up to this frame.
+    pub fn offsets(&self) -> &ModifierOffsets {
+        &self.offsets
+    }
+
+    pub fn advance(&mut self, delta_time: Duration) {
+        self.elapsed += delta_time;
+        self.offsets = ModifierOffsets::evaluate(&self.modifiers, self.elapsed.as_secs_f32());
+    }
+}
+
+/// Below this speed, in units or radians per second, damped motion comes to rest.
+pub(crate) const MIN_MOTION_SPEED: f32 = 0.001;
+
+/// Moves the container's translation, in its parent's space, by this many units per second.
+/// Applied on top of any transform tweens.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+#[storage(BTreeStorage)]
+pub struct Velocity(pub Vector2F);
+
+/// Changes the container's `Velocity` by this many units per second, every second.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+#[storage(BTreeStorage)]
+pub struct Acceleration(pub Vector2F);
+
+/// Spins the container about its origin, in radians per second.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+#[storage(BTreeStorage)]
+pub struct AngularVelocity(pub f32);
+
+/// The fraction of the container's velocity and angular velocity lost each second.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+#[storage(BTreeStorage)]
+pub struct Damping(pub f32);
+
+/// Keeps a moving container's translation inside `bounds`, in its parent's space, by bouncing
+/// off the edges.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[storage(BTreeStorage)]
+pub struct BounceBounds {
+    pub bounds: RectPoints,
+    /// The fraction of speed kept by a bounce. 1.0 is perfectly elastic.
+    pub restitution: f32,
+}
+
+impl BounceBounds {
+    /// Reflects a translation that left the bounds back inside them, and the velocity with it.
+    pub fn bounce(&self, translation: Vector2F, velocity: Vector2F) -> (Vector2F, Vector2F) {
+        let (x, velocity_x) = bounce_axis(
+            translation.x(),
+            velocity.x(),
+            self.bounds.origin.x(),
+            self.bounds.lower_right.x(),
+            self.restitution,
+        );
+        let (y, velocity_y) = bounce_axis(
+            translation.y(),
+            velocity.y(),
+            self.bounds.origin.y(),
+            self.bounds.lower_right.y(),
+            self.restitution,
+        );
+        (Vector2F::new(x, y), Vector2F::new(velocity_x, velocity_y))
+    }
+}
+
+fn bounce_axis(position: f32, velocity: f32, min: f32, max: f32, restitution: f32) -> (f32, f32) {
+    let (position, velocity) = if position < min {
+        (
+            min + (min - position) * restitution,
+            -velocity * restitution,
+        )
+    } else if position > max {
+        (
+            max - (position - max) * restitution,
+            -velocity * restitution,
+        )
+    } else {
+        return (position, velocity);
+    };
+    // A bounce bigger than the bounds still has to end up inside them
+    (position.max(min).min(max), velocity)
+}
+
+/// Lets the container take keyboard focus, with `Engine::set_focus` or by tabbing through
+/// containers with `Engine::focus_next`.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[storage(BTreeStorage)]
+pub struct Focusable {
+    /// Tabbing visits lower indices first. Ties go in scene graph order.
+    pub tab_index: i32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct KeyModifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeyPhase {
+    Down,
+    Up,
+}
+
+/// What a `KeyBinding` does when it matches.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum KeyBindingAction {
+    /// Fires a state machine event, for authored logic to react to.
+    FireEvent(String),
+    SetFlag(String, bool),
+    Focus(ContainerId),
+    FocusNext,
+    FocusPrevious,
+}
+
+/// Keys are named by the host, which is expected to use the names of the W3C `KeyboardEvent.key`
+/// values, such as "a", "Enter" or "ArrowLeft".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyBinding {
+    pub key: String,
+    /// Must match exactly, so a binding without modifiers ignores Ctrl+key.
+    pub modifiers: KeyModifiers,
+    pub phase: KeyPhase,
+    pub action: KeyBindingAction,
+}
+
+impl KeyBinding {
+    pub fn new(key: &str, action: KeyBindingAction) -> Self {
+        Self {
+            key: key.to_string(),
+            modifiers: KeyModifiers::default(),
+            phase: KeyPhase::Down,
+            action,
+        }
+    }
+
+    pub fn with_modifiers(mut self, modifiers: KeyModifiers) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+
+    pub fn on_release(mut self) -> Self {
+        self.phase = KeyPhase::Up;
+        self
+    }
+
+    pub fn matches(&self, key: &str, modifiers: KeyModifiers, phase: KeyPhase) -> bool {
+        self.key == key && self.modifiers == modifiers && self.phase == phase
+    }
+}
+
+/// Key presses go to the focused container first, then bubble up through its parents until a
+/// binding matches. Bindings on the root container act as stage wide shortcuts.
+#[derive(Component, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[storage(BTreeStorage)]
+pub struct KeyBindings(pub Vec<KeyBinding>);
+
+/// Rounds the container's world translation to whole device pixels when drawn, so UI artwork
+/// stays crisp. Leave it off for anything that moves smoothly.
+#[derive(Component, Clone, Copy, Debug, Default)]
+#[storage(NullStorage)]
+pub struct PixelSnap;
+
+/// Marks a `Coloring` that is only there for a display swap's cross-fade. It is removed once the
+/// fade finishes, so the new shape shows its own color again.
+#[derive(Component, Clone, Copy, Debug, Default)]
+#[storage(NullStorage)]
+pub struct DisplayFade;
@@ -1,19 +1,27 @@
-use crate::quad_tree::QuadTree;
+use crate::quad_tree::{QuadTree, QuadTreeStats};
 use crate::{
-    actions::{ContainerCreationDefintition, ContainerUpdateDefintition},
+    actions::{Action, ContainerCreationDefintition, ContainerUpdateDefintition},
+    baking::SpriteSheet,
     types::{
-        basic::{ContainerId, LibraryId},
-        shapes::Shape,
+        basic::{ContainerId, LibraryId, ScaleRotationTranslation, TextureDefinition, Vector2FDef},
+        shapes::{Shape, DEFAULT_PROCEDURAL_STEPS},
     },
 };
+use log::warn;
+use palette::LinSrgba;
 use pathfinder_canvas::Vector2F;
 use pathfinder_content::pattern::Pattern;
 use pathfinder_geometry::rect::RectF;
+use pathfinder_geometry::transform2d::Transform2F;
 use serde::{Deserialize, Serialize};
 use specs::Entity;
-use std::collections::{hash_map::RandomState, HashMap, VecDeque};
-use std::sync::Arc;
+use std::collections::{hash_map::RandomState, HashMap, HashSet, VecDeque};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
 use std::time::Duration;
+use uuid::Uuid;
 
 #[derive(Default, Debug)]
 pub struct ContainerCreationQueue {
@@ -28,6 +36,14 @@ impl ContainerCreationQueue {
     pub fn dequeue(&mut self) -> Option<ContainerCreationDefintition> {
         self.container_data.pop_front()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ContainerCreationDefintition> {
+        self.container_data.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.container_data.is_empty()
+    }
 }
 
 #[derive(Default, Debug)]
@@ -43,37 +59,379 @@ impl ContainerUpdateQueue {
     pub fn dequeue(&mut self) -> Option<ContainerUpdateDefintition> {
         self.container_data.pop_front()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ContainerUpdateDefintition> {
+        self.container_data.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.container_data.is_empty()
+    }
+}
+
+/// A change to the structure of the scene, recorded for hosts to observe.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SceneEvent {
+    Created(ContainerId),
+    Removed(ContainerId),
+    Reparented {
+        container_id: ContainerId,
+        new_parent: ContainerId,
+    },
+    /// The container started or stopped displaying a library item, or switched to another one.
+    DisplayChanged(ContainerId),
+    /// The pointer picked up a `Draggable` container.
+    DragStarted(ContainerId),
+    /// A dragged container moved to `translation`, in its parent's space.
+    DragMoved {
+        container_id: ContainerId,
+        translation: Vector2F,
+    },
+    /// The pointer dropped the container at `translation`, before any snap back.
+    DragEnded {
+        container_id: ContainerId,
+        translation: Vector2F,
+    },
+    /// Keyboard focus moved to the container, or was cleared.
+    FocusChanged(Option<ContainerId>),
+    /// The library item was swapped for a new version, or a shape it references was. Sprites
+    /// baked from it are stale until they're baked again.
+    LibraryItemReplaced(LibraryId),
+}
+
+/// The drag in progress, if any. See `Engine::pointer_down`.
+#[derive(Debug, Default)]
+pub struct DragState {
+    pub(crate) active: Option<ActiveDrag>,
+    pub(crate) scrolling: Option<ActiveScroll>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ActiveDrag {
+    pub(crate) container_id: ContainerId,
+    pub(crate) start: ScaleRotationTranslation,
+    // From the container's translation to the pointer when the drag started, in parent space
+    pub(crate) grab_offset: Vector2F,
+    pub(crate) translation: Vector2F,
+}
+
+/// The container with keyboard focus, if any. See `Engine::set_focus`.
+#[derive(Debug, Default)]
+pub struct FocusState {
+    pub(crate) focused: Option<ContainerId>,
+}
+
+/// A `ScrollView` being dragged by the pointer.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ActiveScroll {
+    pub(crate) container_id: ContainerId,
+    // The pointer in the scroll view's space, which doesn't move with its content
+    pub(crate) last_point: Vector2F,
+    // Offset change since the last update, sampled into a velocity for flinging on release
+    pub(crate) moved: Vector2F,
+    pub(crate) velocity: Vector2F,
+}
+
+/// Hosts that never poll would otherwise grow the queue forever. Past this the oldest events are
+/// dropped.
+const MAX_SCENE_EVENTS: usize = 4096;
+
+#[derive(Default, Debug)]
+pub struct SceneEventQueue {
+    events: VecDeque<SceneEvent>,
+}
+
+impl SceneEventQueue {
+    pub fn push(&mut self, event: SceneEvent) {
+        if self.events.len() >= MAX_SCENE_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn drain(&mut self) -> Vec<SceneEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+/// Author facing information about a library item. Nothing at runtime depends on it.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct LibraryItemMetadata {
+    pub name: String,
+    pub tags: Vec<String>,
+    /// The file the item was imported from, if any.
+    pub origin: Option<String>,
+    /// Where the editor should place the item's origin when it is dropped into the scene.
+    #[serde(with = "Vector2FDef")]
+    pub pivot: Vector2F,
+}
+
+impl LibraryItemMetadata {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            ..Self::default()
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|item_tag| item_tag == tag)
+    }
+}
+
+/// A simplified stand in for a shape, drawn while the shape's bounds project to no more than
+/// `max_size` device pixels on their longest side.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LodVariant {
+    pub max_size: f32,
+    pub id: LibraryId,
 }
 
 #[derive(Default, Debug)]
 pub struct Library {
     shapes: HashMap<LibraryId, Arc<Shape>>,
+    // Shapes with references or swatches, with the references inlined and swatches applied
+    resolved_shapes: HashMap<LibraryId, Arc<Shape>>,
+    // Named colors for `Shape::Swatch`
+    palette: HashMap<String, LinSrgba>,
     textures: HashMap<LibraryId, Arc<Pattern>>,
+    metadata: HashMap<LibraryId, LibraryItemMetadata>,
+    // What the cells of baked textures were baked from
+    sprite_sheets: HashMap<LibraryId, SpriteSheet>,
+    // Maintained by the container systems as displays are set and removed
+    usages: HashMap<LibraryId, HashSet<ContainerId>>,
+    // Sorted smallest `max_size` first
+    lod_variants: HashMap<LibraryId, Vec<LodVariant>>,
 }
 
 impl Library {
     pub fn add_shape(&mut self, id: LibraryId, shape: Shape) {
         self.shapes.insert(id, Arc::new(shape));
+        self.resolve_dependents(&id);
     }
 
     pub fn add_texture(&mut self, id: LibraryId, pattern: Pattern) {
         self.textures.insert(id, Arc::new(pattern));
     }
 
+    /// Swaps an existing shape for a new version. Anything still holding the old `Arc` keeps
+    /// drawing the old version until it fetches the shape again. Returns the replaced shape and
+    /// every shape that references it, whose resolved shapes changed with it.
+    pub fn replace_shape(&mut self, id: LibraryId, shape: Shape) -> Result<Vec<LibraryId>, String> {
+        if !self.contains_shape(&id) {
+            return Err(format!("Shape {:?} is not in the library", id));
+        }
+        self.validate_shape(&id, &shape)?;
+        self.shapes.insert(id, Arc::new(shape));
+        Ok(self.resolve_dependents(&id))
+    }
+
+    /// Swaps an existing texture for a new version. A sprite sheet describing the old texture's
+    /// cells is dropped, since they won't line up with the new one.
+    pub fn replace_texture(&mut self, id: LibraryId, pattern: Pattern) -> Result<(), String> {
+        if !self.contains_texture(&id) {
+            return Err(format!("Texture {:?} is not in the library", id));
+        }
+        self.textures.insert(id, Arc::new(pattern));
+        self.sprite_sheets.remove(&id);
+        Ok(())
+    }
+
+    /// The shape as it was defined. Use `get_resolved_shape` to draw or measure it.
     pub fn get_shape(&self, id: &LibraryId) -> Option<Arc<Shape>> {
         self.shapes.get(id).cloned()
     }
 
+    /// The shape with any `Shape::Reference`s replaced by the shapes they reference, and any
+    /// `Shape::Swatch`es recolored from the palette.
+    pub fn get_resolved_shape(&self, id: &LibraryId) -> Option<Arc<Shape>> {
+        self.resolved_shapes
+            .get(id)
+            .or_else(|| self.shapes.get(id))
+            .cloned()
+    }
+
+    /// Checks that every shape `shape` references is in the library, and that defining it as
+    /// `id` would not make any shape reference itself.
+    pub fn validate_shape(&self, id: &LibraryId, shape: &Shape) -> Result<(), String> {
+        let mut visited = HashSet::new();
+        let mut stack = shape.references();
+        while let Some(reference) = stack.pop() {
+            if reference == *id {
+                return Err(format!("Shape {:?} references itself", id));
+            }
+            if !visited.insert(reference) {
+                continue;
+            }
+            match self.shapes.get(&reference) {
+                Some(referenced) => stack.extend(referenced.references()),
+                None => {
+                    return Err(format!(
+                        "Shape {:?} references {:?}, which is not in the library",
+                        id, reference
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn references_transitively(&self, shape: &Shape, target: &LibraryId) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = shape.references();
+        while let Some(reference) = stack.pop() {
+            if reference == *target {
+                return true;
+            }
+            if visited.insert(reference) {
+                if let Some(referenced) = self.shapes.get(&reference) {
+                    stack.extend(referenced.references());
+                }
+            }
+        }
+        false
+    }
+
+    // Re-inlines `changed` and every shape that references it, returning the shapes it resolved
+    fn resolve_dependents(&mut self, changed: &LibraryId) -> Vec<LibraryId> {
+        let dependents = self
+            .shapes
+            .iter()
+            .filter(|(id, shape)| *id == changed || self.references_transitively(shape, changed))
+            .map(|(id, _)| *id)
+            .collect::<Vec<LibraryId>>();
+        self.resolve_shapes(dependents.clone());
+        dependents
+    }
+
+    fn resolve_shapes(&mut self, ids: Vec<LibraryId>) {
+        for id in ids {
+            let resolved = match self.shapes.get(&id) {
+                Some(shape) if shape.needs_resolving() => {
+                    let palette = &self.palette;
+                    Some(Arc::new(
+                        self.inline_references(shape, &mut vec![id])
+                            .apply_swatches(&|name| palette.get(name).copied()),
+                    ))
+                }
+                _ => None,
+            };
+            match resolved {
+                Some(resolved) => self.resolved_shapes.insert(id, resolved),
+                None => self.resolved_shapes.remove(&id),
+            };
+        }
+    }
+
+    /// Adds or replaces a palette entry, recoloring every shape that uses it.
+    pub fn set_palette_entry(&mut self, name: String, color: LinSrgba) {
+        self.palette.insert(name, color);
+        self.resolve_swatches();
+    }
+
+    /// Shapes using the entry go back to their own colors.
+    pub fn remove_palette_entry(&mut self, name: &str) {
+        if self.palette.remove(name).is_some() {
+            self.resolve_swatches();
+        }
+    }
+
+    pub fn get_palette_entry(&self, name: &str) -> Option<LinSrgba> {
+        self.palette.get(name).copied()
+    }
+
+    pub fn palette(&self) -> impl Iterator<Item = (&String, &LinSrgba)> {
+        self.palette.iter()
+    }
+
+    // TODO: only shapes using the changed entry need resolving again
+    fn resolve_swatches(&mut self) {
+        let ids = self.resolved_shapes.keys().copied().collect();
+        self.resolve_shapes(ids);
+    }
+
+    // `path` holds the shapes being inlined, so cycles are left as unresolved references
+    fn inline_references(&self, shape: &Shape, path: &mut Vec<LibraryId>) -> Shape {
+        shape.inline_references(&mut |reference| {
+            if path.contains(reference) {
+                return None;
+            }
+            let referenced = self.shapes.get(reference)?;
+            path.push(*reference);
+            let inlined = self.inline_references(referenced, path);
+            path.pop();
+            Some(inlined)
+        })
+    }
+
     pub fn get_texture(&self, id: &LibraryId) -> Option<Arc<Pattern>> {
         self.textures.get(id).cloned()
     }
 
     pub fn remove_shape(&mut self, id: &LibraryId) {
         self.shapes.remove(id);
+        self.lod_variants.remove(id);
+        self.resolve_dependents(id);
+        self.remove_orphaned_metadata(id);
     }
 
     pub fn remove_texture(&mut self, id: &LibraryId) {
         self.textures.remove(id);
+        self.sprite_sheets.remove(id);
+        self.remove_orphaned_metadata(id);
+    }
+
+    pub fn set_sprite_sheet(&mut self, id: LibraryId, sheet: SpriteSheet) {
+        self.sprite_sheets.insert(id, sheet);
+    }
+
+    pub fn get_sprite_sheet(&self, id: &LibraryId) -> Option<&SpriteSheet> {
+        self.sprite_sheets.get(id)
+    }
+
+    /// Replaces the simplified variants drawn in place of shape `id` when it's small on screen.
+    /// An empty list removes them. Variants may be loaded after they're registered; until then
+    /// the shape itself is drawn.
+    pub fn set_lod_variants(
+        &mut self,
+        id: LibraryId,
+        mut variants: Vec<LodVariant>,
+    ) -> Result<(), String> {
+        if variants.iter().any(|variant| variant.id == id) {
+            return Err(format!("Shape {:?} can't be its own LOD variant", id));
+        }
+        if variants.is_empty() {
+            self.lod_variants.remove(&id);
+        } else {
+            variants.sort_by(|a, b| {
+                a.max_size
+                    .partial_cmp(&b.max_size)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            self.lod_variants.insert(id, variants);
+        }
+        Ok(())
+    }
+
+    pub fn get_lod_variants(&self, id: &LibraryId) -> &[LodVariant] {
+        self.lod_variants
+            .get(id)
+            .map_or(&[], |variants| variants.as_slice())
+    }
+
+    /// The shape to draw for `id` when its bounds project to `projected_size` device pixels on
+    /// their longest side: the variant with the smallest `max_size` that still fits, or `id`.
+    pub fn select_lod(&self, id: &LibraryId, projected_size: f32) -> LibraryId {
+        self.get_lod_variants(id)
+            .iter()
+            .find(|variant| projected_size <= variant.max_size && self.contains_shape(&variant.id))
+            .map_or(*id, |variant| variant.id)
+    }
+
+    fn remove_orphaned_metadata(&mut self, id: &LibraryId) {
+        if !self.contains_shape(id) && !self.contains_texture(id) {
+            self.metadata.remove(id);
+        }
     }
 
     pub fn contains_shape(&self, id: &LibraryId) -> bool {
@@ -83,6 +441,157 @@ impl Library {
     pub fn contains_texture(&self, id: &LibraryId) -> bool {
         self.textures.contains_key(id)
     }
+
+    pub fn add_usage(&mut self, id: LibraryId, container_id: ContainerId) {
+        self.usages.entry(id).or_default().insert(container_id);
+    }
+
+    pub fn remove_usage(&mut self, id: &LibraryId, container_id: &ContainerId) {
+        if let Some(usages) = self.usages.get_mut(id) {
+            usages.remove(container_id);
+            if usages.is_empty() {
+                self.usages.remove(id);
+            }
+        }
+    }
+
+    /// The containers currently displaying this item.
+    pub fn usages(&self, id: &LibraryId) -> Vec<ContainerId> {
+        self.usages
+            .get(id)
+            .map(|usages| usages.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Shapes and textures that no container is displaying, and no shape references.
+    pub fn unused_items(&self) -> Vec<LibraryId> {
+        // Variants are kept as long as the shape they stand in for
+        let referenced = self
+            .shapes
+            .values()
+            .flat_map(|shape| shape.references())
+            .chain(
+                self.lod_variants
+                    .iter()
+                    .filter(|(id, _)| self.shapes.contains_key(id))
+                    .flat_map(|(_, variants)| variants.iter().map(|variant| variant.id)),
+            )
+            .collect::<HashSet<LibraryId>>();
+        self.shapes
+            .keys()
+            .chain(self.textures.keys())
+            .filter(|id| !self.usages.contains_key(id) && !referenced.contains(id))
+            .copied()
+            .collect()
+    }
+
+    pub fn shapes(&self) -> impl Iterator<Item = (&LibraryId, &Arc<Shape>)> {
+        self.shapes.iter()
+    }
+
+    pub fn textures(&self) -> impl Iterator<Item = (&LibraryId, &Arc<Pattern>)> {
+        self.textures.iter()
+    }
+
+    /// Actions that recreate the library's contents, for saving projects and exporting scenes.
+    pub fn to_actions(&self) -> Result<Vec<Action>, String> {
+        self.to_actions_linked(&HashMap::new())
+    }
+
+    /// Like `to_actions`, but the shapes and textures in `links` are written as
+    /// `Action::LibraryRef`s to their locations instead of being embedded. Writing the linked
+    /// files is up to the caller, see `linking::write_linked_asset`.
+    pub fn to_actions_linked(
+        &self,
+        links: &HashMap<LibraryId, String>,
+    ) -> Result<Vec<Action>, String> {
+        let mut actions = vec![];
+        if !self.palette.is_empty() {
+            actions.push(Action::DefinePalette(
+                self.palette
+                    .iter()
+                    .map(|(name, color)| (name.clone(), *color))
+                    .collect(),
+            ));
+        }
+        actions.extend(self.shapes.iter().map(|(id, shape)| match links.get(id) {
+            Some(location) => Action::LibraryRef {
+                id: *id,
+                location: location.clone(),
+            },
+            None => Action::DefineShape {
+                id: *id,
+                shape: (**shape).clone(),
+            },
+        }));
+        for (id, pattern) in self.textures.iter() {
+            actions.push(match links.get(id) {
+                Some(location) => Action::LibraryRef {
+                    id: *id,
+                    location: location.clone(),
+                },
+                None => Action::LoadTexture {
+                    id: *id,
+                    texture: TextureDefinition::from_pattern(pattern)?,
+                },
+            });
+        }
+        actions.extend(
+            self.sprite_sheets
+                .iter()
+                .map(|(id, sheet)| Action::DefineSpriteSheet {
+                    id: *id,
+                    sheet: sheet.clone(),
+                }),
+        );
+        actions.extend(
+            self.lod_variants
+                .iter()
+                .map(|(id, variants)| Action::DefineLodVariants {
+                    id: *id,
+                    variants: variants.clone(),
+                }),
+        );
+        actions.extend(
+            self.metadata
+                .iter()
+                .map(|(id, metadata)| Action::SetLibraryMetadata {
+                    id: *id,
+                    metadata: metadata.clone(),
+                }),
+        );
+        Ok(actions)
+    }
+
+    /// Metadata may be set before the item it describes is loaded.
+    pub fn set_metadata(&mut self, id: LibraryId, metadata: LibraryItemMetadata) {
+        self.metadata.insert(id, metadata);
+    }
+
+    pub fn get_metadata(&self, id: &LibraryId) -> Option<&LibraryItemMetadata> {
+        self.metadata.get(id)
+    }
+
+    pub fn get_metadata_mut(&mut self, id: &LibraryId) -> Option<&mut LibraryItemMetadata> {
+        self.metadata.get_mut(id)
+    }
+
+    /// Names are not required to be unique, so every match is returned.
+    pub fn find_by_name(&self, name: &str) -> Vec<LibraryId> {
+        self.metadata
+            .iter()
+            .filter(|(_, metadata)| metadata.name == name)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    pub fn find_by_tag(&self, tag: &str) -> Vec<LibraryId> {
+        self.metadata
+            .iter()
+            .filter(|(_, metadata)| metadata.has_tag(tag))
+            .map(|(id, _)| *id)
+            .collect()
+    }
 }
 
 #[derive(Default, Debug)]
@@ -131,10 +640,92 @@ impl ContainerMapping {
 )]
 pub struct QuadTreeLayer(u32);
 
+// Layers from here up are reserved for the editor and engine internals
+const FIRST_RESERVED_LAYER: u32 = 0xFFFF_0000;
+
 impl QuadTreeLayer {
     pub const fn new(layer: u32) -> Self {
         QuadTreeLayer(layer)
     }
+
+    /// A layer in the range reserved for tools and internals, which authored content can't use.
+    pub const fn reserved(offset: u16) -> Self {
+        QuadTreeLayer(FIRST_RESERVED_LAYER + offset as u32)
+    }
+
+    pub fn is_reserved(&self) -> bool {
+        self.0 >= FIRST_RESERVED_LAYER
+    }
+}
+
+/// Names for quad tree layers, so content and tools can refer to layers without agreeing on ids.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuadTreeLayerRegistry {
+    layers: HashMap<String, QuadTreeLayer>,
+}
+
+impl QuadTreeLayerRegistry {
+    /// Names a layer for authored content. Fails if the layer is reserved, or if either the name
+    /// or the layer is already registered to something else. Declaring the same pair twice is
+    /// allowed.
+    pub fn declare(&mut self, name: &str, layer: QuadTreeLayer) -> Result<(), String> {
+        if layer.is_reserved() {
+            return Err(format!(
+                "Layer {:?} is reserved and can't be declared as {}",
+                layer, name
+            ));
+        }
+        self.insert(name, layer)
+    }
+
+    /// Names a layer in the reserved range, for tools and internals.
+    pub fn declare_reserved(&mut self, name: &str, layer: QuadTreeLayer) -> Result<(), String> {
+        if !layer.is_reserved() {
+            return Err(format!(
+                "Layer {:?} is not in the reserved range and can't be declared as {}",
+                layer, name
+            ));
+        }
+        self.insert(name, layer)
+    }
+
+    fn insert(&mut self, name: &str, layer: QuadTreeLayer) -> Result<(), String> {
+        match (self.layers.get(name), self.get_name(&layer)) {
+            (Some(existing), _) if *existing != layer => Err(format!(
+                "Layer name {} is already used by {:?}",
+                name, existing
+            )),
+            (_, Some(existing)) if existing != name => Err(format!(
+                "Layer {:?} is already declared as {}",
+                layer, existing
+            )),
+            _ => {
+                self.layers.insert(name.to_string(), layer);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<QuadTreeLayer> {
+        self.layers.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<QuadTreeLayer> {
+        self.layers.get(name).copied()
+    }
+
+    pub fn get_name(&self, layer: &QuadTreeLayer) -> Option<&str> {
+        self.layers
+            .iter()
+            .find(|(_, registered)| *registered == layer)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, QuadTreeLayer)> {
+        self.layers
+            .iter()
+            .map(|(name, layer)| (name.as_str(), *layer))
+    }
 }
 
 #[derive(Debug)]
@@ -143,6 +734,8 @@ pub enum QuadTreeQuery {
     Disk(QuadTreeLayer, Vector2F, f32),
     Rect(QuadTreeLayer, RectF),
     Ray(QuadTreeLayer, Vector2F, Vector2F),
+    /// The closest `usize` entities to the point, nearest first.
+    Nearest(QuadTreeLayer, Vector2F, usize),
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
@@ -195,12 +788,55 @@ impl QuadTrees {
             .and_then(|(tree, _)| tree.remove(&entity));
     }
 
+    /// The node boundaries of every layer's tree.
+    pub fn node_rects(&self) -> Vec<(QuadTreeLayer, Vec<RectF>)> {
+        self.0
+            .iter()
+            .map(|(layer, (tree, _))| (*layer, tree.node_aabbs().collect()))
+            .collect()
+    }
+
+    /// Returns `None` if the layer does not exist.
+    pub fn stats(&self, layer: &QuadTreeLayer) -> Option<QuadTreeStats> {
+        self.0.get(layer).map(|(tree, _)| tree.stats())
+    }
+
     pub fn remove_all_layers(&mut self, entity: Entity) {
         self.0.iter_mut().for_each(|(_, (tree, _))| {
             tree.remove(&entity);
         });
     }
 
+    /// Returns every overlapping pair of entities where the first is from `layer_a` and the
+    /// second is from `layer_b`. If both layers are the same, each pair is only reported once.
+    /// Returns `None` if either layer does not exist.
+    pub fn query_layer_pairs(
+        &self,
+        layer_a: &QuadTreeLayer,
+        layer_b: &QuadTreeLayer,
+    ) -> Option<Vec<(Entity, Entity)>> {
+        let (tree_a, _) = self.0.get(layer_a)?;
+        if layer_a == layer_b {
+            Some(tree_a.query_self_pairs())
+        } else {
+            let (tree_b, _) = self.0.get(layer_b)?;
+            Some(tree_a.query_pairs(tree_b))
+        }
+    }
+
+    /// The element and node counts of each layer.
+    pub fn layer_sizes(&self) -> Vec<(QuadTreeLayer, usize, usize)> {
+        self.0
+            .iter()
+            .map(|(layer, (quad_tree, _))| (*layer, quad_tree.len(), quad_tree.node_count()))
+            .collect()
+    }
+
+    /// The bounds `entity` was last inserted into `layer` with, including dilation.
+    pub fn get_bounds(&self, layer: &QuadTreeLayer, entity: &Entity) -> Option<RectF> {
+        self.0.get(layer).and_then(|(tree, _)| tree.get(entity))
+    }
+
     pub fn query(&self, query: &QuadTreeQuery) -> Option<Vec<(Entity, RectF)>> {
         match query {
             QuadTreeQuery::Point(layer, point) => self
@@ -219,10 +855,239 @@ impl QuadTrees {
                 .0
                 .get(layer)
                 .and_then(|tree| Some(tree.0.query_ray(origin, direction))),
+            QuadTreeQuery::Nearest(layer, point, count) => self
+                .0
+                .get(layer)
+                .and_then(|tree| Some(tree.0.query_nearest(point, *count))),
         }
     }
 }
 
+// TODO: experiment with thesholds once we have a faster pipeline
+const DEFAULT_TRANSLATION_THRESHOLD: f32 = 0.00001;
+const DEFAULT_ROTATION_THRESHOLD: f32 = 0.00001;
+const DEFAULT_SCALE_THRESHOLD: f32 = 0.00001;
+
+/// Scene wide tuning for the engine's systems.
+///
+/// Transform changes below the thresholds are dropped, so they don't dirty bounds and quad trees.
+/// Sub-pixel UI animation wants them tighter, large world space scenes can loosen them.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EngineConfig {
+    /// Compared against the squared length of the change in translation.
+    pub translation_threshold: f32,
+    /// Radians.
+    pub rotation_threshold: f32,
+    /// Compared against the squared length of the change in scale.
+    pub scale_threshold: f32,
+    /// Device pixels per stage unit. Used to snap `PixelSnap` containers.
+    pub device_pixel_ratio: f32,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            translation_threshold: DEFAULT_TRANSLATION_THRESHOLD,
+            rotation_threshold: DEFAULT_ROTATION_THRESHOLD,
+            scale_threshold: DEFAULT_SCALE_THRESHOLD,
+            device_pixel_ratio: 1.0,
+        }
+    }
+}
+
+/// How finely curves are tessellated. Low power devices can trade smoothness for speed.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RenderQuality {
+    /// Furthest a flattened curve may stray from the true curve, in shape units. `None` leaves
+    /// flattening to the renderer.
+    pub curve_tolerance: Option<f32>,
+    /// Points sampled around procedural shapes, like superellipses, when they're built.
+    pub procedural_steps: usize,
+}
+
+impl RenderQuality {
+    /// Coarse curves for low power devices.
+    pub fn low() -> Self {
+        Self {
+            curve_tolerance: Some(1.0),
+            procedural_steps: DEFAULT_PROCEDURAL_STEPS / 4,
+        }
+    }
+}
+
+impl Default for RenderQuality {
+    fn default() -> Self {
+        Self {
+            curve_tolerance: None,
+            procedural_steps: DEFAULT_PROCEDURAL_STEPS,
+        }
+    }
+}
+
+/// How the engine reacts to errors in content, like a container created twice or a display that
+/// isn't in the library. Either way the offending definition or property is skipped.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RuntimePolicy {
+    /// Errors are kept in `RuntimeErrors`, and the `Runner` fails the frame with them.
+    Strict,
+    /// Errors are logged and playback carries on.
+    Lenient,
+}
+
+impl Default for RuntimePolicy {
+    fn default() -> Self {
+        RuntimePolicy::Strict
+    }
+}
+
+/// Content errors from the last `Engine::update` under `RuntimePolicy::Strict`.
+#[derive(Default, Debug)]
+pub struct RuntimeErrors(pub Vec<String>);
+
+impl RuntimeErrors {
+    pub fn report(&mut self, policy: RuntimePolicy, error: String) {
+        match policy {
+            RuntimePolicy::Strict => self.0.push(error),
+            RuntimePolicy::Lenient => warn!("{}", error),
+        }
+    }
+}
+
+/// Set by anything that could change how the scene draws, cleared by
+/// `Engine::take_scene_dirty`. Conservative: it may be set when nothing visible changed.
+#[derive(Default, Debug)]
+pub struct SceneDirty(pub bool);
+
+/// Which debug overlays `Engine::get_debug_drawables` draws. Everything is off by default.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DebugFlags {
+    /// The bounds of every container that has them.
+    pub bounds: bool,
+    /// The nodes of every quad tree layer.
+    pub quad_trees: bool,
+    /// Bounds that changed in the last frame, both where they were and where they are now.
+    pub dirty_regions: bool,
+    /// A line from each container's origin to its parent's.
+    pub parent_links: bool,
+}
+
+impl DebugFlags {
+    pub fn any(&self) -> bool {
+        self.bounds || self.quad_trees || self.dirty_regions || self.parent_links
+    }
+}
+
+/// Bounds that changed during the last frame. Only recorded while `DebugFlags::dirty_regions` is
+/// set.
+#[derive(Default, Debug)]
+pub struct DirtyRegions(pub Vec<RectF>);
+
+const MIN_CAMERA_ZOOM: f32 = 0.05;
+const MAX_CAMERA_ZOOM: f32 = 64.0;
+
+/// How the stage is viewed. Applied on top of every container's world transform when painting,
+/// so zooming and panning never touch the scene itself. View space is stage space as it would be
+/// drawn with the default camera.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera {
+    zoom: f32,
+    #[serde(with = "Vector2FDef")]
+    pan: Vector2F,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            pan: Vector2F::zero(),
+        }
+    }
+}
+
+impl Camera {
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn pan(&self) -> Vector2F {
+        self.pan
+    }
+
+    pub fn transform(&self) -> Transform2F {
+        Transform2F::from_translation(self.pan)
+            * Transform2F::from_scale(Vector2F::splat(self.zoom))
+    }
+
+    /// The camera as seen by a `Parallax` layer. Pan scales linearly and zoom geometrically, so
+    /// a factor of 0.5 zooms half as many steps.
+    pub fn with_parallax(&self, parallax: f32) -> Camera {
+        Camera {
+            zoom: self.zoom.powf(parallax),
+            pan: self.pan * parallax,
+        }
+    }
+
+    pub fn view_to_stage(&self, view_position: Vector2F) -> Vector2F {
+        (view_position - self.pan) * (1.0 / self.zoom)
+    }
+
+    pub fn stage_to_view(&self, stage_position: Vector2F) -> Vector2F {
+        stage_position * self.zoom + self.pan
+    }
+
+    /// Multiplies the zoom by `factor`, keeping whatever is under `view_position` in place.
+    pub fn zoom_around(&mut self, view_position: Vector2F, factor: f32) {
+        let stage_position = self.view_to_stage(view_position);
+        self.zoom = (self.zoom * factor).clamp(MIN_CAMERA_ZOOM, MAX_CAMERA_ZOOM);
+        self.pan = view_position - stage_position * self.zoom;
+    }
+
+    pub fn pan_by(&mut self, delta: Vector2F) {
+        self.pan = self.pan + delta;
+    }
+
+    /// Zooms so the whole stage fits in the view, leaving `margin` on each side, and centers it.
+    pub fn fit(&mut self, stage_size: Vector2F, view_size: Vector2F, margin: f32) {
+        let available = view_size - Vector2F::splat(margin * 2.0);
+        if stage_size.x() <= 0.0 || stage_size.y() <= 0.0 {
+            return;
+        }
+        self.zoom = (available.x() / stage_size.x())
+            .min(available.y() / stage_size.y())
+            .clamp(MIN_CAMERA_ZOOM, MAX_CAMERA_ZOOM);
+        self.pan = (view_size - stage_size * self.zoom) * 0.5;
+    }
+
+    /// Back to 100% with no pan.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// The stage's size, and the part of it `Anchor`s are laid out in. Hosts update it through
+/// `Engine::set_stage_area` when the stage is resized.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct StageArea {
+    pub size: Vector2F,
+    /// The part of the stage clear of notches, rounded corners and system bars. None for the
+    /// whole stage.
+    pub safe_area: Option<RectF>,
+}
+
+impl StageArea {
+    pub fn new(size: Vector2F) -> Self {
+        Self {
+            size,
+            safe_area: None,
+        }
+    }
+
+    pub fn anchor_rect(&self) -> RectF {
+        self.safe_area
+            .unwrap_or_else(|| RectF::new(Vector2F::zero(), self.size))
+    }
+}
+
 #[derive(Default, Copy, Clone, Debug)]
 pub struct FrameTime {
     pub delta_time: Duration,
@@ -239,83 +1104,260 @@ impl FrameTime {
     }
 }
 
+/// The engine's source of randomness. Anything random in the engine draws from here or from
+/// `seed_for`, never from the OS, so an action stream plays back the same frames every time it
+/// is stepped through the same frames. Seeded by `Action::SetSeed`, or 0 if the stream never
+/// sets one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Random {
+    seed: u64,
+    state: u64,
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // SplitMix64
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+
+    /// Between 0.0 and 1.0, not including 1.0.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// A seed for one container, derived from the engine's seed and the container's id. It
+    /// doesn't depend on how many numbers have been drawn or the order containers were created
+    /// in, so adding a container doesn't change how the others' randomness plays out.
+    pub fn seed_for(&self, container_id: &ContainerId) -> u64 {
+        let uuid: Uuid = (*container_id).into();
+        uuid.as_bytes()
+            .iter()
+            .fold(self.seed, |hash, byte| mix(hash ^ u64::from(*byte)))
+    }
+}
+
+fn mix(value: u64) -> u64 {
+    let mut value = value;
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// Timing data for the most recent `Engine::update`.
+///
+/// Systems record into this through a shared borrow so that profiling does not serialize dispatch.
+#[derive(Default, Debug)]
+pub struct FrameProfile {
+    system_times: Mutex<Vec<(&'static str, Duration)>>,
+    dispatch_time: Duration,
+}
+
+impl FrameProfile {
+    pub fn record(&self, system: &'static str, elapsed: Duration) {
+        self.system_times.lock().unwrap().push((system, elapsed));
+    }
+
+    pub fn clear(&mut self) {
+        self.system_times.get_mut().unwrap().clear();
+        self.dispatch_time = Duration::default();
+    }
+
+    pub fn set_dispatch_time(&mut self, dispatch_time: Duration) {
+        self.dispatch_time = dispatch_time;
+    }
+
+    /// Wall time spent dispatching all systems, including time spent waiting on dependencies.
+    pub fn dispatch_time(&self) -> Duration {
+        self.dispatch_time
+    }
+
+    /// Time spent in each system, in the order the systems finished.
+    pub fn system_times(&self) -> Vec<(&'static str, Duration)> {
+        self.system_times.lock().unwrap().clone()
+    }
+
+    pub fn system_time(&self, system: &str) -> Option<Duration> {
+        self.system_times
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(name, _)| *name == system)
+            .map(|(_, elapsed)| *elapsed)
+    }
+}
+
+#[derive(Debug)]
+struct SceneNode {
+    // Held to reject stale lookups after specs reuses an id with a new generation
+    entity: Entity,
+    parent: Entity,
+    children: Vec<Entity>,
+}
+
+/// Parent/child relationships between container entities.
+///
+/// Nodes live in a `Vec` indexed by entity id rather than in hash maps. Specs keeps ids dense and
+/// reuses them, so the arena stays compact and the per-frame traversals don't hash.
 #[derive(Debug)]
 pub struct SceneGraph {
     root: Entity,
-    tree: HashMap<Entity, Vec<Entity>>,
-    parents: HashMap<Entity, Entity>,
+    nodes: Vec<Option<SceneNode>>,
+    version: u64,
+    // Parents that lost a child since the last `take_detached_parents`
+    detached_parents: Vec<Entity>,
+}
+
+// Shared by every scene graph, so a replaced graph never repeats a version
+static NEXT_SCENE_GRAPH_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn next_scene_graph_version() -> u64 {
+    NEXT_SCENE_GRAPH_VERSION.fetch_add(1, Ordering::Relaxed)
 }
 
 impl SceneGraph {
     pub fn new(root: Entity) -> Self {
-        let mut tree = HashMap::new();
-        tree.insert(root, vec![]);
-        let mut parents = HashMap::new();
-        parents.insert(root, root);
-        Self {
+        let mut scene_graph = Self {
             root,
-            tree,
-            parents,
+            nodes: vec![],
+            version: next_scene_graph_version(),
+            detached_parents: vec![],
+        };
+        scene_graph.insert_node(root, root);
+        scene_graph
+    }
+
+    fn node(&self, entity: &Entity) -> Option<&SceneNode> {
+        self.nodes
+            .get(entity.id() as usize)
+            .and_then(|node| node.as_ref())
+            .filter(|node| node.entity == *entity)
+    }
+
+    fn node_mut(&mut self, entity: &Entity) -> Option<&mut SceneNode> {
+        self.nodes
+            .get_mut(entity.id() as usize)
+            .and_then(|node| node.as_mut())
+            .filter(|node| node.entity == *entity)
+    }
+
+    /// Changes whenever an entity is added, removed or moved.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn insert_node(&mut self, entity: Entity, parent: Entity) {
+        self.version = next_scene_graph_version();
+        let index = entity.id() as usize;
+        if index >= self.nodes.len() {
+            self.nodes.resize_with(index + 1, || None);
         }
+        self.nodes[index] = Some(SceneNode {
+            entity,
+            parent,
+            children: vec![],
+        });
+    }
+
+    fn take_node(&mut self, entity: &Entity) -> Option<SceneNode> {
+        self.node(entity)?;
+        self.version = next_scene_graph_version();
+        self.nodes[entity.id() as usize].take()
     }
 
     pub fn add_entity(&mut self, parent: &Entity, entity: &Entity) {
-        self.tree
-            .entry(*parent)
-            .and_modify(|children| children.push(*entity));
-        self.parents.insert(*entity, *parent);
-        self.tree.insert(*entity, vec![]);
+        if let Some(parent_node) = self.node_mut(parent) {
+            parent_node.children.push(*entity);
+        }
+        self.insert_node(*entity, *parent);
     }
 
     pub fn remove_entity(&mut self, entity: &Entity) {
-        let parent = self.parents.remove(entity);
-        if let Some(children) = self.tree.remove(entity) {
-            let parent = parent.unwrap();
-            for child in children.iter() {
-                self.parents.insert(*child, parent);
+        if let Some(node) = self.take_node(entity) {
+            for child in node.children.iter() {
+                if let Some(child_node) = self.node_mut(child) {
+                    child_node.parent = node.parent;
+                }
             }
-            self.tree.entry(parent).and_modify(|existing_children| {
-                existing_children.retain(|child| child != entity);
-                existing_children.extend(children);
-            });
+            if let Some(parent_node) = self.node_mut(&node.parent) {
+                parent_node.children.retain(|child| child != entity);
+                parent_node.children.extend(node.children);
+            }
+            self.detached_parents.push(node.parent);
         }
     }
 
     pub fn reparent(&mut self, new_parent: &Entity, entity: Entity) {
-        if let Some(old_parent) = self.parents.get(&entity) {
-            self.tree
-                .entry(*old_parent)
-                .and_modify(|children| children.retain(|child| child != &entity));
+        self.version = next_scene_graph_version();
+        let old_parent = self.node(&entity).map(|node| node.parent);
+        if let Some(old_parent) = old_parent {
+            if let Some(parent_node) = self.node_mut(&old_parent) {
+                parent_node.children.retain(|child| child != &entity);
+            }
+            self.detached_parents.push(old_parent);
+        }
+        match self.node_mut(&entity) {
+            Some(node) => node.parent = *new_parent,
+            None => self.insert_node(entity, *new_parent),
+        }
+        if let Some(parent_node) = self.node_mut(new_parent) {
+            parent_node.children.push(entity);
         }
-        self.parents.insert(entity, *new_parent);
-        self.tree.entry(*new_parent).or_default().push(entity);
     }
 
     pub fn remove_entity_and_children(&mut self, entity: &Entity) -> Vec<Entity> {
+        if let Some(parent) = self.get_parent(entity).copied() {
+            if let Some(parent_node) = self.node_mut(&parent) {
+                parent_node.children.retain(|child| child != entity);
+            }
+            self.detached_parents.push(parent);
+        }
         let mut queue = VecDeque::new();
         let mut removed = vec![];
         queue.push_back(*entity);
         removed.push(*entity);
         while let Some(next) = queue.pop_front() {
-            self.tree
-                .remove(&next)
-                .unwrap_or_default()
-                .into_iter()
-                .for_each(|entity| {
-                    removed.push(entity);
-                    queue.push_back(entity);
-                });
-            self.parents.remove(&next);
+            if let Some(node) = self.take_node(&next) {
+                for child in node.children.into_iter() {
+                    removed.push(child);
+                    queue.push_back(child);
+                }
+            }
         }
         removed
     }
 
+    /// Parents that had a child removed or moved away since the last call, so whatever depends
+    /// on their children, like `BoundsSource::Children`, can be refreshed. Some may have been
+    /// removed since.
+    pub fn take_detached_parents(&mut self) -> Vec<Entity> {
+        std::mem::take(&mut self.detached_parents)
+    }
+
     pub fn get_parent(&self, entity: &Entity) -> Option<&Entity> {
-        self.parents.get(entity)
+        self.node(entity).map(|node| &node.parent)
     }
 
     pub fn get_children(&self, entity: &Entity) -> Option<&Vec<Entity>> {
-        self.tree.get(entity)
+        self.node(entity).map(|node| &node.children)
     }
 
     pub fn root(&self) -> &Entity {
@@ -330,6 +1372,49 @@ impl SceneGraph {
     }
 }
 
+/// Every container in paint order, back to front. Rebuilt only after the scene graph, or a
+/// container's `Order` or `Display`, changes, so static scenes skip sorting entirely.
+#[derive(Debug, Default)]
+pub struct PaintOrder {
+    entities: Vec<Entity>,
+    // The scene graph version the list was built from, None once an order or display changed
+    scene_graph_version: Option<u64>,
+}
+
+impl PaintOrder {
+    pub fn invalidate(&mut self) {
+        self.scene_graph_version = None;
+    }
+
+    pub fn is_valid_for(&self, scene_graph: &SceneGraph) -> bool {
+        self.scene_graph_version == Some(scene_graph.version())
+    }
+
+    /// Walks `scene_graph` breadth first, sorting each container's children by `order_of`.
+    /// Children with the same order keep the order they were added in.
+    pub fn rebuild(&mut self, scene_graph: &SceneGraph, order_of: impl Fn(&Entity) -> i8) {
+        self.entities.clear();
+        let mut next = 0;
+        let mut parent = *scene_graph.root();
+        loop {
+            let first_child = self.entities.len();
+            self.entities
+                .extend(scene_graph.get_children(&parent).into_iter().flatten());
+            self.entities[first_child..].sort_by_key(|entity| order_of(entity));
+            match self.entities.get(next) {
+                Some(entity) => parent = *entity,
+                None => break,
+            }
+            next += 1;
+        }
+        self.scene_graph_version = Some(scene_graph.version());
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+}
+
 pub struct ParentIterator<'a> {
     graph: &'a SceneGraph,
     current: &'a Entity,
@@ -338,7 +1423,7 @@ pub struct ParentIterator<'a> {
 impl<'a> Iterator for ParentIterator<'a> {
     type Item = &'a Entity;
     fn next(&mut self) -> Option<Self::Item> {
-        let next = self.graph.parents.get(self.current);
+        let next = self.graph.get_parent(self.current);
         if let Some(next) = next {
             if next != self.graph.root() {
                 self.current = next;
@@ -351,3 +1436,205 @@ impl<'a> Iterator for ParentIterator<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{coloring::Coloring, shapes::AugmentedShape};
+
+    #[test]
+    fn it_draws_the_same_numbers_from_the_same_seed() {
+        let mut first = Random::new(42);
+        let mut second = Random::new(42);
+        let draws = (0..16).map(|_| first.next_u64()).collect::<Vec<_>>();
+        assert_eq!(
+            draws,
+            (0..16).map(|_| second.next_u64()).collect::<Vec<_>>()
+        );
+        assert_ne!(draws[0], Random::new(43).next_u64());
+        assert!((0..100).all(|_| {
+            let value = first.next_f32();
+            value >= 0.0 && value < 1.0
+        }));
+
+        let container_id = ContainerId::new();
+        assert_eq!(
+            first.seed_for(&container_id),
+            Random::new(42).seed_for(&container_id)
+        );
+        assert_ne!(
+            first.seed_for(&container_id),
+            first.seed_for(&ContainerId::new())
+        );
+        assert_ne!(
+            first.seed_for(&container_id),
+            Random::new(43).seed_for(&container_id)
+        );
+    }
+
+    fn reference(id: LibraryId) -> Shape {
+        Shape::Group {
+            shapes: vec![AugmentedShape {
+                shape: Shape::Reference(id, Transform2F::default()),
+                transform: Transform2F::default(),
+            }],
+        }
+    }
+
+    #[test]
+    fn it_inlines_references_and_rejects_cycles() {
+        let (fill_id, symbol_id) = (LibraryId::new(), LibraryId::new());
+        let mut library = Library::default();
+        assert!(library
+            .validate_shape(&symbol_id, &reference(fill_id))
+            .is_err());
+        library.add_shape(
+            fill_id,
+            Shape::Fill {
+                contours: vec![],
+                color: LinSrgba::new(1.0, 0.0, 0.0, 1.0),
+            },
+        );
+        library.add_shape(symbol_id, reference(fill_id));
+        assert_eq!(
+            library.get_resolved_shape(&symbol_id).unwrap().color(),
+            Coloring::Colorings(vec![Coloring::Colorings(vec![Coloring::Color(
+                LinSrgba::new(1.0, 0.0, 0.0, 1.0)
+            )])])
+        );
+        assert!(library
+            .validate_shape(&fill_id, &reference(symbol_id))
+            .is_err());
+        assert!(library.unused_items().contains(&symbol_id));
+        assert!(!library.unused_items().contains(&fill_id));
+    }
+
+    #[test]
+    fn it_caches_paint_order_until_the_scene_graph_changes() {
+        use specs::{Builder, World, WorldExt};
+        let mut world = World::new();
+        let mut entity = || world.create_entity().build();
+        let (root, front, back, child) = (entity(), entity(), entity(), entity());
+        let mut scene_graph = SceneGraph::new(root);
+        scene_graph.add_entity(&root, &front);
+        scene_graph.add_entity(&root, &back);
+        scene_graph.add_entity(&front, &child);
+        let mut paint_order = PaintOrder::default();
+        assert!(!paint_order.is_valid_for(&scene_graph));
+        paint_order.rebuild(&scene_graph, |entity| if *entity == front { 1 } else { 0 });
+        assert_eq!(paint_order.entities(), &[back, front, child]);
+        assert!(paint_order.is_valid_for(&scene_graph));
+        scene_graph.reparent(&back, child);
+        assert!(!paint_order.is_valid_for(&scene_graph));
+        paint_order.rebuild(&scene_graph, |_| 0);
+        paint_order.invalidate();
+        assert!(!paint_order.is_valid_for(&scene_graph));
+    }
+
+    #[test]
+    fn it_selects_the_smallest_lod_that_fits() {
+        let (shape_id, medium_id, small_id) =
+            (LibraryId::new(), LibraryId::new(), LibraryId::new());
+        let mut library = Library::default();
+        for id in [shape_id, medium_id, small_id].iter() {
+            library.add_shape(
+                *id,
+                Shape::Fill {
+                    contours: vec![],
+                    color: LinSrgba::new(1.0, 0.0, 0.0, 1.0),
+                },
+            );
+        }
+        assert!(library
+            .set_lod_variants(
+                shape_id,
+                vec![LodVariant {
+                    max_size: 1.0,
+                    id: shape_id
+                }]
+            )
+            .is_err());
+        library
+            .set_lod_variants(
+                shape_id,
+                vec![
+                    LodVariant {
+                        max_size: 64.0,
+                        id: medium_id,
+                    },
+                    LodVariant {
+                        max_size: 16.0,
+                        id: small_id,
+                    },
+                ],
+            )
+            .unwrap();
+        assert_eq!(library.select_lod(&shape_id, 200.0), shape_id);
+        assert_eq!(library.select_lod(&shape_id, 32.0), medium_id);
+        assert_eq!(library.select_lod(&shape_id, 8.0), small_id);
+        // Variants stay loaded while the shape they stand in for is
+        assert!(!library.unused_items().contains(&small_id));
+        library.remove_shape(&small_id);
+        assert_eq!(library.select_lod(&shape_id, 8.0), medium_id);
+    }
+
+    #[test]
+    fn it_replaces_shapes_and_reports_their_dependents() {
+        let (fill_id, symbol_id) = (LibraryId::new(), LibraryId::new());
+        let fill = |color| Shape::Fill {
+            contours: vec![],
+            color,
+        };
+        let mut library = Library::default();
+        assert!(library
+            .replace_shape(fill_id, fill(LinSrgba::new(1.0, 0.0, 0.0, 1.0)))
+            .is_err());
+        library.add_shape(fill_id, fill(LinSrgba::new(1.0, 0.0, 0.0, 1.0)));
+        library.add_shape(symbol_id, reference(fill_id));
+        let old = library.get_resolved_shape(&symbol_id).unwrap();
+        let mut changed = library
+            .replace_shape(fill_id, fill(LinSrgba::new(0.0, 1.0, 0.0, 1.0)))
+            .unwrap();
+        changed.sort();
+        let mut expected = vec![fill_id, symbol_id];
+        expected.sort();
+        assert_eq!(changed, expected);
+        assert_eq!(
+            library.get_resolved_shape(&symbol_id).unwrap().color(),
+            Coloring::Colorings(vec![Coloring::Colorings(vec![Coloring::Color(
+                LinSrgba::new(0.0, 1.0, 0.0, 1.0)
+            )])])
+        );
+        // Holders of the old version keep it
+        assert_ne!(old, library.get_resolved_shape(&symbol_id).unwrap());
+        assert!(library
+            .replace_shape(fill_id, reference(symbol_id))
+            .is_err());
+    }
+
+    #[test]
+    fn it_recolors_swatches_when_the_palette_changes() {
+        let id = LibraryId::new();
+        let mut library = Library::default();
+        library.add_shape(
+            id,
+            Shape::Swatch(
+                "accent".to_string(),
+                Box::new(Shape::Fill {
+                    contours: vec![],
+                    color: LinSrgba::new(1.0, 0.0, 0.0, 1.0),
+                }),
+            ),
+        );
+        let color = |library: &Library| library.get_resolved_shape(&id).unwrap().color();
+        assert_eq!(
+            color(&library),
+            Coloring::Color(LinSrgba::new(1.0, 0.0, 0.0, 1.0))
+        );
+        library.set_palette_entry("accent".to_string(), LinSrgba::new(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(
+            color(&library),
+            Coloring::Color(LinSrgba::new(0.0, 0.0, 1.0, 1.0))
+        );
+    }
+}
@@ -1,51 +1,116 @@
 use super::{
-    common::recompute_bounds,
+    common::{recompute_bounds, union_of_children_bounds},
     components::{
-        Bounds, BoundsSource, Display, DisplayKind, Layer, LocalTransform, Morph, Order, Tweens,
-        ViewRect, WorldTransform,
+        Acceleration, Anchor, AngularVelocity, BounceBounds, Bounds, BoundsSource, Damping,
+        Display, DisplayFade, DisplayKind, Draggable, Focusable, HitShape, KeyBindings, Layer,
+        LocalTransform, Modifiers, Morph, MorphIndices, Order, Parallax, PixelSnap, ScrollView,
+        TimeScale, Tweens, Velocity, ViewRect, WorldTransform, MIN_MOTION_SPEED,
     },
     resources::{
-        ContainerCreationQueue, ContainerMapping, ContainerUpdateQueue, FrameTime, Library,
-        QuadTreeLayer, QuadTrees, SceneGraph,
+        ContainerCreationQueue, ContainerMapping, ContainerUpdateQueue, DebugFlags, DirtyRegions,
+        DragState, EngineConfig, FrameProfile, FrameTime, Library, PaintOrder, QuadTreeLayer,
+        QuadTrees, RuntimeErrors, RuntimePolicy, SceneDirty, SceneEvent, SceneEventQueue,
+        SceneGraph, StageArea,
     },
 };
 use crate::{
     actions::{
-        BoundsKindDefinition, ContainerCreationProperty, ContainerUpdateProperty, RectPoints,
+        BoundsKindDefinition, ContainerCreationProperty, ContainerUpdateDefintition,
+        ContainerUpdateProperty, RectPoints,
     },
+    state_machine::StateMachines,
     tween::{PropertyTween, PropertyTweenData, PropertyTweenUpdate, Tween, TweenDuration},
-    types::{basic::ScaleRotationTranslation, coloring::Coloring},
+    types::{
+        basic::ScaleRotationTranslation,
+        coloring::{ColorSpace, ColorTransform, Coloring},
+        shapes::PathTrim,
+    },
 };
 use palette::LinSrgba;
 use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
 use reduce::Reduce;
 use specs::{
-    prelude::ComponentEvent, shred::ResourceId, BitSet, Entities, Entity, Join, Read, ReadExpect,
-    ReadStorage, ReaderId, System, SystemData, World, Write, WriteExpect, WriteStorage,
+    prelude::ComponentEvent,
+    shred::{ResourceId, RunningTime},
+    BitSet, Entities, Entity, Join, Read, ReadExpect, ReadStorage, ReaderId, System, SystemData,
+    World, Write, WriteExpect, WriteStorage,
 };
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::iter;
+use std::time::Instant;
+
+/// Wraps a system and records how long each run takes into the `FrameProfile` resource.
+pub struct Profiled<S> {
+    name: &'static str,
+    system: S,
+}
+
+impl<S> Profiled<S> {
+    pub fn new(name: &'static str, system: S) -> Self {
+        Self { name, system }
+    }
+}
 
-// TODO: experiment with thesholds once we have a faster pipeline
-const TRANSLATION_THRESHOLD: f32 = 0.00001;
-const ROTATION_THRESHOLD: f32 = 0.00001;
-const SCALE_THRESHOLD: f32 = 0.00001;
+impl<'a, S> System<'a> for Profiled<S>
+where
+    S: System<'a>,
+    S::SystemData: SystemData<'a>,
+{
+    type SystemData = (S::SystemData, Read<'a, FrameProfile>);
+
+    fn run(&mut self, (data, frame_profile): Self::SystemData) {
+        let start = Instant::now();
+        self.system.run(data);
+        frame_profile.record(self.name, start.elapsed());
+    }
+
+    fn running_time(&self) -> RunningTime {
+        self.system.running_time()
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Read::<FrameProfile>::setup(world);
+        self.system.setup(world);
+    }
+}
 
 #[derive(SystemData)]
 pub struct ContainerCreationSystemData<'a> {
+    runtime_policy: Read<'a, RuntimePolicy>,
+    runtime_errors: Write<'a, RuntimeErrors>,
     container_mapping: Write<'a, ContainerMapping>,
     container_creation_queue: Write<'a, ContainerCreationQueue>,
+    scene_events: Write<'a, SceneEventQueue>,
+    scene_dirty: Write<'a, SceneDirty>,
     scene_graph: WriteExpect<'a, SceneGraph>,
-    library: Read<'a, Library>,
+    library: Write<'a, Library>,
     entities: Entities<'a>,
     local_transform_storage: WriteStorage<'a, LocalTransform>,
     world_transform_storage: WriteStorage<'a, WorldTransform>,
     order_storage: WriteStorage<'a, Order>,
     morph_storage: WriteStorage<'a, Morph>,
+    morph_indices_storage: WriteStorage<'a, MorphIndices>,
     bounds_storage: WriteStorage<'a, Bounds>,
     layer_storage: WriteStorage<'a, Layer>,
     view_rect_storage: WriteStorage<'a, ViewRect>,
     coloring_storage: WriteStorage<'a, Coloring>,
+    color_transform_storage: WriteStorage<'a, ColorTransform>,
     display_storage: WriteStorage<'a, Display>,
+    pixel_snap_storage: WriteStorage<'a, PixelSnap>,
+    hit_shape_storage: WriteStorage<'a, HitShape>,
+    draggable_storage: WriteStorage<'a, Draggable>,
+    scroll_view_storage: WriteStorage<'a, ScrollView>,
+    focusable_storage: WriteStorage<'a, Focusable>,
+    key_bindings_storage: WriteStorage<'a, KeyBindings>,
+    velocity_storage: WriteStorage<'a, Velocity>,
+    acceleration_storage: WriteStorage<'a, Acceleration>,
+    angular_velocity_storage: WriteStorage<'a, AngularVelocity>,
+    damping_storage: WriteStorage<'a, Damping>,
+    bounce_bounds_storage: WriteStorage<'a, BounceBounds>,
+    parallax_storage: WriteStorage<'a, Parallax>,
+    anchor_storage: WriteStorage<'a, Anchor>,
+    path_trim_storage: WriteStorage<'a, PathTrim>,
+    modifiers_storage: WriteStorage<'a, Modifiers>,
 }
 
 pub struct ContainerCreation;
@@ -55,15 +120,25 @@ impl<'a> System<'a> for ContainerCreation {
 
     fn run(&mut self, mut data: Self::SystemData) {
         while let Some(definition) = data.container_creation_queue.dequeue() {
+            data.scene_dirty.0 = true;
+            let policy = *data.runtime_policy;
             if data.container_mapping.contains_container(definition.id()) {
-                // TODO: errors
-                todo!();
+                data.runtime_errors.report(
+                    policy,
+                    format!("Container {:?} already exists", definition.id()),
+                );
             } else if !data
                 .container_mapping
                 .contains_container(definition.parent())
             {
-                // TODO: errors
-                todo!();
+                data.runtime_errors.report(
+                    policy,
+                    format!(
+                        "Parent {:?} of container {:?} does not exist",
+                        definition.parent(),
+                        definition.id()
+                    ),
+                );
             } else {
                 let mut entity_builder = data.entities.build_entity();
 
@@ -90,10 +165,20 @@ impl<'a> System<'a> for ContainerCreation {
                             entity_builder =
                                 entity_builder.with(Morph(*morph), &mut data.morph_storage);
                         }
+                        ContainerCreationProperty::MorphIndices(morph_indices) => {
+                            entity_builder = entity_builder.with(
+                                MorphIndices(morph_indices.clone()),
+                                &mut data.morph_indices_storage,
+                            );
+                        }
                         ContainerCreationProperty::Coloring(coloring) => {
                             entity_builder =
                                 entity_builder.with(coloring.clone(), &mut data.coloring_storage);
                         }
+                        ContainerCreationProperty::ColorTransform(color_transform) => {
+                            entity_builder = entity_builder
+                                .with(*color_transform, &mut data.color_transform_storage);
+                        }
                         ContainerCreationProperty::ViewRect(rect_points) => {
                             entity_builder = entity_builder.with(
                                 ViewRect(RectF::from_points(
@@ -109,11 +194,19 @@ impl<'a> System<'a> for ContainerCreation {
                             } else if data.library.contains_texture(display) {
                                 Display(*display, DisplayKind::Raster)
                             } else {
-                                // TODO: errors
-                                panic!()
+                                data.runtime_errors.report(
+                                    policy,
+                                    format!(
+                                        "Display {:?} of container {:?} is not in the library",
+                                        display,
+                                        definition.id()
+                                    ),
+                                );
+                                continue;
                             };
                             entity_builder =
                                 entity_builder.with(display_item, &mut data.display_storage);
+                            data.library.add_usage(*display, *definition.id());
                         }
                         ContainerCreationProperty::Order(order) => {
                             entity_builder =
@@ -125,6 +218,14 @@ impl<'a> System<'a> for ContainerCreation {
                                     bounds: RectF::default(),
                                     source: BoundsSource::Display,
                                 },
+                                BoundsKindDefinition::DisplayWithStroke => Bounds {
+                                    bounds: RectF::default(),
+                                    source: BoundsSource::DisplayWithStroke,
+                                },
+                                BoundsKindDefinition::Children => Bounds {
+                                    bounds: RectF::default(),
+                                    source: BoundsSource::Children,
+                                },
                                 BoundsKindDefinition::Defined(rect_points) => Bounds {
                                     // NOTE: not definining bounds since that will get computed in first frame after entity is added
                                     bounds: RectF::default(),
@@ -136,6 +237,74 @@ impl<'a> System<'a> for ContainerCreation {
                             };
                             entity_builder = entity_builder.with(bounds, &mut data.bounds_storage);
                         }
+                        ContainerCreationProperty::HitShape(hit_shape) => {
+                            // TODO: count hit shapes as library usages
+                            entity_builder =
+                                entity_builder.with(hit_shape.clone(), &mut data.hit_shape_storage);
+                        }
+                        ContainerCreationProperty::Draggable(draggable) => {
+                            entity_builder =
+                                entity_builder.with(*draggable, &mut data.draggable_storage);
+                        }
+                        ContainerCreationProperty::ScrollView(scroll_view) => {
+                            let mut scroll_view = *scroll_view;
+                            scroll_view.offset = scroll_view.clamp_offset(scroll_view.offset);
+                            entity_builder =
+                                entity_builder.with(scroll_view, &mut data.scroll_view_storage);
+                        }
+                        ContainerCreationProperty::Focusable(focusable) => {
+                            entity_builder =
+                                entity_builder.with(*focusable, &mut data.focusable_storage);
+                        }
+                        ContainerCreationProperty::KeyBindings(bindings) => {
+                            entity_builder = entity_builder.with(
+                                KeyBindings(bindings.clone()),
+                                &mut data.key_bindings_storage,
+                            );
+                        }
+                        ContainerCreationProperty::Velocity(velocity) => {
+                            entity_builder = entity_builder
+                                .with(Velocity(*velocity), &mut data.velocity_storage);
+                        }
+                        ContainerCreationProperty::Acceleration(acceleration) => {
+                            entity_builder = entity_builder
+                                .with(Acceleration(*acceleration), &mut data.acceleration_storage);
+                        }
+                        ContainerCreationProperty::AngularVelocity(angular_velocity) => {
+                            entity_builder = entity_builder.with(
+                                AngularVelocity(*angular_velocity),
+                                &mut data.angular_velocity_storage,
+                            );
+                        }
+                        ContainerCreationProperty::Damping(damping) => {
+                            entity_builder =
+                                entity_builder.with(Damping(*damping), &mut data.damping_storage);
+                        }
+                        ContainerCreationProperty::BounceBounds(bounce_bounds) => {
+                            entity_builder = entity_builder
+                                .with(*bounce_bounds, &mut data.bounce_bounds_storage);
+                        }
+                        ContainerCreationProperty::Parallax(parallax) => {
+                            entity_builder = entity_builder
+                                .with(Parallax(*parallax), &mut data.parallax_storage);
+                        }
+                        ContainerCreationProperty::Anchor(anchor) => {
+                            entity_builder = entity_builder.with(*anchor, &mut data.anchor_storage);
+                        }
+                        ContainerCreationProperty::PathTrim(path_trim) => {
+                            entity_builder =
+                                entity_builder.with(*path_trim, &mut data.path_trim_storage);
+                        }
+                        ContainerCreationProperty::Modifiers(modifiers) => {
+                            entity_builder = entity_builder.with(
+                                Modifiers::new(modifiers.clone()),
+                                &mut data.modifiers_storage,
+                            );
+                        }
+                        ContainerCreationProperty::PixelSnap => {
+                            entity_builder =
+                                entity_builder.with(PixelSnap, &mut data.pixel_snap_storage);
+                        }
                         ContainerCreationProperty::Layer(..) => {}
                     }
                 }
@@ -159,6 +328,8 @@ impl<'a> System<'a> for ContainerCreation {
                 data.container_mapping
                     .add_container(*definition.id(), entity);
                 data.scene_graph.add_entity(&parent_entity, &entity);
+                data.scene_events
+                    .push(SceneEvent::Created(*definition.id()));
                 // NOTE: not inserting into quad tree since that will get handled during the first frame this entity exists in
             }
         }
@@ -166,33 +337,82 @@ impl<'a> System<'a> for ContainerCreation {
 }
 #[derive(SystemData)]
 pub struct ContainerUpdateSystemData<'a> {
+    runtime_policy: Read<'a, RuntimePolicy>,
+    runtime_errors: Write<'a, RuntimeErrors>,
     container_mapping: Write<'a, ContainerMapping>,
     container_update_queue: Write<'a, ContainerUpdateQueue>,
+    scene_events: Write<'a, SceneEventQueue>,
+    scene_dirty: Write<'a, SceneDirty>,
     scene_graph: WriteExpect<'a, SceneGraph>,
     quad_trees: Write<'a, QuadTrees>,
-    library: Read<'a, Library>,
-    local_transform_storage: ReadStorage<'a, LocalTransform>,
+    library: Write<'a, Library>,
+    local_transform_storage: WriteStorage<'a, LocalTransform>,
     world_transform_storage: WriteStorage<'a, WorldTransform>,
     order_storage: WriteStorage<'a, Order>,
     morph_storage: WriteStorage<'a, Morph>,
+    morph_indices_storage: WriteStorage<'a, MorphIndices>,
     bounds_storage: WriteStorage<'a, Bounds>,
     layer_storage: WriteStorage<'a, Layer>,
     view_rect_storage: WriteStorage<'a, ViewRect>,
     coloring_storage: WriteStorage<'a, Coloring>,
+    color_transform_storage: WriteStorage<'a, ColorTransform>,
     display_storage: WriteStorage<'a, Display>,
+    display_fade_storage: WriteStorage<'a, DisplayFade>,
     tween_storage: WriteStorage<'a, Tweens>,
+    time_scale_storage: WriteStorage<'a, TimeScale>,
+    pixel_snap_storage: WriteStorage<'a, PixelSnap>,
+    hit_shape_storage: WriteStorage<'a, HitShape>,
+    draggable_storage: WriteStorage<'a, Draggable>,
+    scroll_view_storage: WriteStorage<'a, ScrollView>,
+    focusable_storage: WriteStorage<'a, Focusable>,
+    key_bindings_storage: WriteStorage<'a, KeyBindings>,
+    velocity_storage: WriteStorage<'a, Velocity>,
+    acceleration_storage: WriteStorage<'a, Acceleration>,
+    angular_velocity_storage: WriteStorage<'a, AngularVelocity>,
+    damping_storage: WriteStorage<'a, Damping>,
+    bounce_bounds_storage: WriteStorage<'a, BounceBounds>,
+    parallax_storage: WriteStorage<'a, Parallax>,
+    anchor_storage: WriteStorage<'a, Anchor>,
+    path_trim_storage: WriteStorage<'a, PathTrim>,
+    modifiers_storage: WriteStorage<'a, Modifiers>,
 }
 
 pub struct ContainerUpdate;
 
 impl ContainerUpdate {
-    fn add_tween(tween_storage: &mut WriteStorage<Tweens>, entity: Entity, tween: PropertyTween) {
-        tween_storage
+    fn add_tween(
+        tween_storage: &mut WriteStorage<Tweens>,
+        entity: Entity,
+        tween: PropertyTween,
+        definition: &ContainerUpdateDefintition,
+    ) {
+        let tween = tween.with_delay(definition.delay());
+        let tweens = &mut tween_storage
             .entry(entity)
             .unwrap()
             .or_insert(Tweens(vec![]))
-            .0
-            .push(tween);
+            .0;
+        let tween = match definition.blend_in() {
+            Some((easing, duration_frames)) if duration_frames > 0 => {
+                let (outgoing, remaining): (Vec<_>, Vec<_>) = tweens
+                    .drain(..)
+                    .partition(|other| other.tween_data().is_same_property(tween.tween_data()));
+                *tweens = remaining;
+                tween.with_blend_in(outgoing, TweenDuration::new_frame(duration_frames), easing)
+            }
+            _ => tween,
+        };
+        tweens.push(tween);
+    }
+
+    fn cancel_tweens(
+        tween_storage: &mut WriteStorage<Tweens>,
+        entity: Entity,
+        matches: impl Fn(&PropertyTweenData) -> bool,
+    ) {
+        if let Some(tweens) = tween_storage.get_mut(entity) {
+            tweens.0.retain(|tween| !matches(tween.tween_data()));
+        }
     }
 }
 
@@ -200,6 +420,8 @@ impl<'a> System<'a> for ContainerUpdate {
     type SystemData = ContainerUpdateSystemData<'a>;
     fn run(&mut self, mut data: Self::SystemData) {
         while let Some(definition) = data.container_update_queue.dequeue() {
+            data.scene_dirty.0 = true;
+            let policy = *data.runtime_policy;
             if let Some(entity) = data.container_mapping.get_entity(definition.id()) {
                 let entity = *entity;
                 for property in definition.properties() {
@@ -212,7 +434,35 @@ impl<'a> System<'a> for ContainerUpdate {
                                     TweenDuration::new_frame(*duration_frames),
                                     *easing,
                                 );
-                                Self::add_tween(&mut data.tween_storage, entity, tween);
+                                Self::add_tween(
+                                    &mut data.tween_storage,
+                                    entity,
+                                    tween,
+                                    &definition,
+                                );
+                            }
+                        }
+                        ContainerUpdateProperty::TransformMatrix(
+                            matrix,
+                            easing,
+                            duration_frames,
+                        ) => {
+                            if let Some(start) = data.local_transform_storage.get(entity) {
+                                let tween = PropertyTween::new_transform_matrix(
+                                    start.0,
+                                    Transform2F::row_major(
+                                        matrix[0], matrix[1], matrix[2], matrix[3], matrix[4],
+                                        matrix[5],
+                                    ),
+                                    TweenDuration::new_frame(*duration_frames),
+                                    *easing,
+                                );
+                                Self::add_tween(
+                                    &mut data.tween_storage,
+                                    entity,
+                                    tween,
+                                    &definition,
+                                );
                             }
                         }
                         ContainerUpdateProperty::MorphIndex(morph, easing, duration_frames) => {
@@ -228,7 +478,36 @@ impl<'a> System<'a> for ContainerUpdate {
                                 TweenDuration::new_frame(*duration_frames),
                                 *easing,
                             );
-                            Self::add_tween(&mut data.tween_storage, entity, tween);
+                            Self::add_tween(&mut data.tween_storage, entity, tween, &definition);
+                        }
+                        ContainerUpdateProperty::MorphIndices(
+                            morph_indices,
+                            easing,
+                            duration_frames,
+                        ) => {
+                            let morph = data
+                                .morph_storage
+                                .get(entity)
+                                .copied()
+                                .unwrap_or_default()
+                                .0;
+                            let mut start = data
+                                .morph_indices_storage
+                                .entry(entity)
+                                .unwrap()
+                                .or_insert(MorphIndices::default())
+                                .0
+                                .clone();
+                            if start.len() < morph_indices.len() {
+                                start.resize(morph_indices.len(), morph);
+                            }
+                            let tween = PropertyTween::new_morph_indices(
+                                start,
+                                morph_indices.clone(),
+                                TweenDuration::new_frame(*duration_frames),
+                                *easing,
+                            );
+                            Self::add_tween(&mut data.tween_storage, entity, tween, &definition);
                         }
                         ContainerUpdateProperty::Coloring(
                             coloring,
@@ -240,7 +519,7 @@ impl<'a> System<'a> for ContainerUpdate {
                                 |display| match display.1 {
                                     DisplayKind::Vector => data
                                         .library
-                                        .get_shape(&display.0)
+                                        .get_resolved_shape(&display.0)
                                         .and_then(|shape| Some(Some(shape.color())))
                                         .unwrap_or(None),
                                     DisplayKind::Raster => {
@@ -248,6 +527,7 @@ impl<'a> System<'a> for ContainerUpdate {
                                     }
                                 },
                             );
+                            data.display_fade_storage.remove(entity);
                             let coloring_component = data.coloring_storage.get(entity);
                             let start = match (library_item, coloring_component) {
                                 (_, Some(component)) => component.clone(),
@@ -258,8 +538,14 @@ impl<'a> System<'a> for ContainerUpdate {
                                     coloring
                                 }
                                 (_, _) => {
-                                    //TODO: errors
-                                    todo!()
+                                    data.runtime_errors.report(
+                                        policy,
+                                        format!(
+                                            "Container {:?} has no color to tween from",
+                                            definition.id()
+                                        ),
+                                    );
+                                    continue;
                                 }
                             };
                             let tween = PropertyTween::new_coloring(
@@ -269,7 +555,25 @@ impl<'a> System<'a> for ContainerUpdate {
                                 TweenDuration::new_frame(*duration_frames),
                                 *easing,
                             );
-                            Self::add_tween(&mut data.tween_storage, entity, tween);
+                            Self::add_tween(&mut data.tween_storage, entity, tween, &definition);
+                        }
+                        ContainerUpdateProperty::ColorTransform(
+                            color_transform,
+                            easing,
+                            duration_frames,
+                        ) => {
+                            let start = *data
+                                .color_transform_storage
+                                .entry(entity)
+                                .unwrap()
+                                .or_insert(ColorTransform::default());
+                            let tween = PropertyTween::new_color_transform(
+                                start,
+                                *color_transform,
+                                TweenDuration::new_frame(*duration_frames),
+                                *easing,
+                            );
+                            Self::add_tween(&mut data.tween_storage, entity, tween, &definition);
                         }
                         ContainerUpdateProperty::ViewRect(rect_points, easing, duration_frames) => {
                             let library_item = data
@@ -290,8 +594,14 @@ impl<'a> System<'a> for ContainerUpdate {
                                     rect
                                 }
                                 (_, _) => {
-                                    //TODO: errors
-                                    todo!()
+                                    data.runtime_errors.report(
+                                        policy,
+                                        format!(
+                                            "Container {:?} has no view rect to tween from",
+                                            definition.id()
+                                        ),
+                                    );
+                                    continue;
                                 }
                             };
                             let tween = PropertyTween::new_view_rect(
@@ -300,7 +610,16 @@ impl<'a> System<'a> for ContainerUpdate {
                                 TweenDuration::new_frame(*duration_frames),
                                 *easing,
                             );
-                            Self::add_tween(&mut data.tween_storage, entity, tween);
+                            if definition.blend_in().is_none() {
+                                Self::cancel_tweens(
+                                    &mut data.tween_storage,
+                                    entity,
+                                    |tween_data| {
+                                        matches!(tween_data, PropertyTweenData::ViewRect { .. })
+                                    },
+                                );
+                            }
+                            Self::add_tween(&mut data.tween_storage, entity, tween, &definition);
                         }
                         ContainerUpdateProperty::Order(order, easing, duration_frames) => {
                             let start = data
@@ -315,7 +634,214 @@ impl<'a> System<'a> for ContainerUpdate {
                                 TweenDuration::new_frame(*duration_frames),
                                 *easing,
                             );
-                            Self::add_tween(&mut data.tween_storage, entity, tween);
+                            Self::add_tween(&mut data.tween_storage, entity, tween, &definition);
+                        }
+                        ContainerUpdateProperty::SetTransform(srt) => {
+                            Self::cancel_tweens(&mut data.tween_storage, entity, |tween_data| {
+                                tween_data.is_transform()
+                            });
+                            data.local_transform_storage
+                                .insert(
+                                    entity,
+                                    LocalTransform(Transform2F::from_scale_rotation_translation(
+                                        srt.scale,
+                                        srt.theta,
+                                        srt.translation,
+                                    )),
+                                )
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::SetMorphIndex(morph) => {
+                            Self::cancel_tweens(&mut data.tween_storage, entity, |tween_data| {
+                                matches!(tween_data, PropertyTweenData::MorphIndex { .. })
+                            });
+                            data.morph_storage.insert(entity, Morph(*morph)).unwrap();
+                        }
+                        ContainerUpdateProperty::SetMorphIndices(morph_indices) => {
+                            Self::cancel_tweens(&mut data.tween_storage, entity, |tween_data| {
+                                matches!(tween_data, PropertyTweenData::MorphIndices { .. })
+                            });
+                            data.morph_indices_storage
+                                .insert(entity, MorphIndices(morph_indices.clone()))
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::SetColoring(coloring) => {
+                            Self::cancel_tweens(&mut data.tween_storage, entity, |tween_data| {
+                                matches!(tween_data, PropertyTweenData::Coloring { .. })
+                            });
+                            // The coloring is the container's own now, not a fade's
+                            data.display_fade_storage.remove(entity);
+                            data.coloring_storage
+                                .insert(entity, coloring.clone())
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::SetColorTransform(color_transform) => {
+                            Self::cancel_tweens(&mut data.tween_storage, entity, |tween_data| {
+                                matches!(tween_data, PropertyTweenData::ColorTransform { .. })
+                            });
+                            data.color_transform_storage
+                                .insert(entity, *color_transform)
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::SetViewRect(rect_points) => {
+                            Self::cancel_tweens(&mut data.tween_storage, entity, |tween_data| {
+                                matches!(tween_data, PropertyTweenData::ViewRect { .. })
+                            });
+                            data.view_rect_storage
+                                .insert(
+                                    entity,
+                                    ViewRect(RectF::from_points(
+                                        rect_points.origin,
+                                        rect_points.lower_right,
+                                    )),
+                                )
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::TimeScale(scale) => {
+                            // Tweens can't run backwards
+                            data.time_scale_storage
+                                .insert(entity, TimeScale(scale.max(0.0)))
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::HitShape(hit_shape) => {
+                            data.hit_shape_storage
+                                .insert(entity, hit_shape.clone())
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::RemoveHitShape => {
+                            data.hit_shape_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::Draggable(draggable) => {
+                            data.draggable_storage.insert(entity, *draggable).unwrap();
+                        }
+                        ContainerUpdateProperty::RemoveDraggable => {
+                            data.draggable_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::ScrollView(scroll_view) => {
+                            let mut scroll_view = *scroll_view;
+                            scroll_view.offset = scroll_view.clamp_offset(scroll_view.offset);
+                            data.scroll_view_storage
+                                .insert(entity, scroll_view)
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::RemoveScrollView => {
+                            data.scroll_view_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::Focusable(focusable) => {
+                            data.focusable_storage.insert(entity, *focusable).unwrap();
+                        }
+                        ContainerUpdateProperty::RemoveFocusable => {
+                            data.focusable_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::KeyBindings(bindings) => {
+                            data.key_bindings_storage
+                                .insert(entity, KeyBindings(bindings.clone()))
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::RemoveKeyBindings => {
+                            data.key_bindings_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::Velocity(velocity) => {
+                            data.velocity_storage
+                                .insert(entity, Velocity(*velocity))
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::Acceleration(acceleration) => {
+                            data.acceleration_storage
+                                .insert(entity, Acceleration(*acceleration))
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::AngularVelocity(angular_velocity) => {
+                            data.angular_velocity_storage
+                                .insert(entity, AngularVelocity(*angular_velocity))
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::Damping(damping) => {
+                            data.damping_storage
+                                .insert(entity, Damping(*damping))
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::BounceBounds(bounce_bounds) => {
+                            data.bounce_bounds_storage
+                                .insert(entity, *bounce_bounds)
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::RemoveBounceBounds => {
+                            data.bounce_bounds_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::Parallax(parallax) => {
+                            data.parallax_storage
+                                .insert(entity, Parallax(*parallax))
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::RemoveParallax => {
+                            data.parallax_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::Anchor(anchor) => {
+                            data.anchor_storage.insert(entity, *anchor).unwrap();
+                        }
+                        ContainerUpdateProperty::RemoveAnchor => {
+                            data.anchor_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::PathTrim(path_trim, easing, duration_frames) => {
+                            let start = *data
+                                .path_trim_storage
+                                .entry(entity)
+                                .unwrap()
+                                .or_insert(PathTrim::default());
+                            let tween = PropertyTween::new_path_trim(
+                                start,
+                                *path_trim,
+                                TweenDuration::new_frame(*duration_frames),
+                                *easing,
+                            );
+                            if definition.blend_in().is_none() {
+                                Self::cancel_tweens(
+                                    &mut data.tween_storage,
+                                    entity,
+                                    |tween_data| {
+                                        matches!(tween_data, PropertyTweenData::PathTrim { .. })
+                                    },
+                                );
+                            }
+                            Self::add_tween(&mut data.tween_storage, entity, tween, &definition);
+                        }
+                        ContainerUpdateProperty::SetPathTrim(path_trim) => {
+                            Self::cancel_tweens(&mut data.tween_storage, entity, |tween_data| {
+                                matches!(tween_data, PropertyTweenData::PathTrim { .. })
+                            });
+                            data.path_trim_storage.insert(entity, *path_trim).unwrap();
+                        }
+                        ContainerUpdateProperty::RemovePathTrim => {
+                            Self::cancel_tweens(&mut data.tween_storage, entity, |tween_data| {
+                                matches!(tween_data, PropertyTweenData::PathTrim { .. })
+                            });
+                            data.path_trim_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::Modifiers(modifiers) => {
+                            data.modifiers_storage
+                                .insert(entity, Modifiers::new(modifiers.clone()))
+                                .unwrap();
+                        }
+                        ContainerUpdateProperty::RemoveModifiers => {
+                            data.modifiers_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::StopMotion => {
+                            data.velocity_storage.remove(entity);
+                            data.acceleration_storage.remove(entity);
+                            data.angular_velocity_storage.remove(entity);
+                        }
+                        ContainerUpdateProperty::ScrollTo(offset) => {
+                            if let Some(scroll_view) = data.scroll_view_storage.get_mut(entity) {
+                                scroll_view.offset = scroll_view.clamp_offset(*offset);
+                                scroll_view.fling(Vector2F::zero());
+                            }
+                        }
+                        ContainerUpdateProperty::PixelSnap(true) => {
+                            data.pixel_snap_storage.insert(entity, PixelSnap).unwrap();
+                        }
+                        ContainerUpdateProperty::PixelSnap(false) => {
+                            data.pixel_snap_storage.remove(entity);
                         }
                         ContainerUpdateProperty::Display(display) => {
                             let display_item = if data.library.contains_shape(display) {
@@ -323,13 +849,93 @@ impl<'a> System<'a> for ContainerUpdate {
                             } else if data.library.contains_texture(display) {
                                 Display(*display, DisplayKind::Raster)
                             } else {
-                                // TODO: errors
-                                panic!()
+                                data.runtime_errors.report(
+                                    policy,
+                                    format!(
+                                        "Display {:?} of container {:?} is not in the library",
+                                        display,
+                                        definition.id()
+                                    ),
+                                );
+                                continue;
                             };
+                            // A fade from an earlier swap is replaced, starting from the color it
+                            // has reached
+                            let fading_coloring =
+                                if data.display_fade_storage.remove(entity).is_some() {
+                                    Self::cancel_tweens(
+                                        &mut data.tween_storage,
+                                        entity,
+                                        |tween_data| {
+                                            matches!(tween_data, PropertyTweenData::Coloring { .. })
+                                        },
+                                    );
+                                    data.coloring_storage.remove(entity)
+                                } else {
+                                    None
+                                };
+                            if let Some((easing, duration_frames)) = definition.blend_in() {
+                                // A coloring component overrides the shape's color, so only fade
+                                // when the container is showing the color from the library
+                                let from_coloring =
+                                    match (data.display_storage.get(entity), fading_coloring) {
+                                        (Some(Display(_, DisplayKind::Vector)), Some(coloring)) => {
+                                            Some(coloring)
+                                        }
+                                        (Some(Display(previous, DisplayKind::Vector)), None)
+                                            if data.coloring_storage.get(entity).is_none() =>
+                                        {
+                                            data.library
+                                                .get_resolved_shape(previous)
+                                                .map(|shape| shape.color())
+                                        }
+                                        _ => None,
+                                    };
+                                let to_coloring = data
+                                    .library
+                                    .get_resolved_shape(display)
+                                    .map(|shape| shape.color());
+                                if let (Some(from_coloring), Some(to_coloring)) =
+                                    (from_coloring, to_coloring)
+                                {
+                                    data.coloring_storage
+                                        .insert(entity, from_coloring.clone())
+                                        .unwrap();
+                                    data.display_fade_storage
+                                        .insert(entity, DisplayFade)
+                                        .unwrap();
+                                    let tween = PropertyTween::new_coloring(
+                                        from_coloring,
+                                        to_coloring,
+                                        ColorSpace::Linear,
+                                        TweenDuration::new_frame(duration_frames),
+                                        easing,
+                                    );
+                                    // The swap happens now, so its fade isn't delayed or blended
+                                    // like the update's other tweens
+                                    data.tween_storage
+                                        .entry(entity)
+                                        .unwrap()
+                                        .or_insert(Tweens(vec![]))
+                                        .0
+                                        .push(tween);
+                                }
+                            }
+                            if let Some(Display(previous, _)) = data.display_storage.get(entity) {
+                                data.library.remove_usage(previous, definition.id());
+                            }
                             data.display_storage.insert(entity, display_item).unwrap();
+                            data.library.add_usage(*display, *definition.id());
+                            data.scene_events
+                                .push(SceneEvent::DisplayChanged(*definition.id()));
                         }
                         ContainerUpdateProperty::RemoveDisplay => {
-                            data.display_storage.remove(entity);
+                            if let Some(Display(previous, _)) = data.display_storage.remove(entity)
+                            {
+                                data.library.remove_usage(&previous, definition.id());
+                                data.scene_events
+                                    .push(SceneEvent::DisplayChanged(*definition.id()));
+                            }
                         }
                         ContainerUpdateProperty::Bounds(bounds_definition) => {
                             let bounds = match bounds_definition {
@@ -337,6 +943,14 @@ impl<'a> System<'a> for ContainerUpdate {
                                     bounds: RectF::default(),
                                     source: BoundsSource::Display,
                                 },
+                                BoundsKindDefinition::DisplayWithStroke => Bounds {
+                                    bounds: RectF::default(),
+                                    source: BoundsSource::DisplayWithStroke,
+                                },
+                                BoundsKindDefinition::Children => Bounds {
+                                    bounds: RectF::default(),
+                                    source: BoundsSource::Children,
+                                },
                                 BoundsKindDefinition::Defined(rect_points) => Bounds {
                                     // NOTE: not definining bounds since that will get computed in first frame after entity is updated
                                     bounds: RectF::default(),
@@ -370,24 +984,37 @@ impl<'a> System<'a> for ContainerUpdate {
                                 }
                             };
                         }
-                        ContainerUpdateProperty::Parent(new_parent) => {
-                            if let Some(new_parent) = data.container_mapping.get_entity(new_parent)
+                        ContainerUpdateProperty::Parent(new_parent_id) => {
+                            if let Some(new_parent) =
+                                data.container_mapping.get_entity(new_parent_id)
                             {
                                 data.scene_graph.reparent(new_parent, entity);
+                                data.scene_events.push(SceneEvent::Reparented {
+                                    container_id: *definition.id(),
+                                    new_parent: *new_parent_id,
+                                });
                                 if let Some(transfom) = data.world_transform_storage.get_mut(entity)
                                 {
                                     transfom.0 = Transform2F::default();
                                 }
                             } else {
-                                // Todo: errors
-                                todo!();
+                                data.runtime_errors.report(
+                                    policy,
+                                    format!(
+                                        "New parent {:?} of container {:?} does not exist",
+                                        new_parent_id,
+                                        definition.id()
+                                    ),
+                                );
                             }
                         }
                     }
                 }
             } else {
-                //TODO: errors
-                todo!();
+                data.runtime_errors.report(
+                    policy,
+                    format!("Container {:?} does not exist", definition.id()),
+                );
             }
         }
     }
@@ -396,9 +1023,13 @@ impl<'a> System<'a> for ContainerUpdate {
 pub struct ApplyTransformTweens;
 
 impl<'a> System<'a> for ApplyTransformTweens {
-    type SystemData = (WriteStorage<'a, LocalTransform>, ReadStorage<'a, Tweens>);
+    type SystemData = (
+        WriteStorage<'a, LocalTransform>,
+        ReadStorage<'a, Tweens>,
+        Read<'a, EngineConfig>,
+    );
 
-    fn run(&mut self, (mut local_transform_storage, tweens_storage): Self::SystemData) {
+    fn run(&mut self, (mut local_transform_storage, tweens_storage, config): Self::SystemData) {
         for (mut local_transform, tweens) in
             (&mut local_transform_storage.restrict_mut(), &tweens_storage).join()
         {
@@ -406,7 +1037,7 @@ impl<'a> System<'a> for ApplyTransformTweens {
                 .0
                 .iter()
                 .filter_map(|tween| {
-                    if let PropertyTweenData::Transform { .. } = tween.tween_data() {
+                    if tween.tween_data().is_transform() {
                         if let PropertyTweenUpdate::Transform(end_transfom) = tween.compute() {
                             Some(end_transfom)
                         } else {
@@ -419,7 +1050,7 @@ impl<'a> System<'a> for ApplyTransformTweens {
                 .reduce(|acc_transform, transform| transform * acc_transform)
                 .map(|updated| {
                     let before_update = local_transform.get_unchecked().0;
-                    if transform_changed(before_update, updated) {
+                    if transform_changed(&config, before_update, updated) {
                         local_transform.get_mut_unchecked().0 = updated;
                     }
                 });
@@ -427,10 +1058,11 @@ impl<'a> System<'a> for ApplyTransformTweens {
     }
 }
 
-fn transform_changed(before: Transform2F, after: Transform2F) -> bool {
-    (after.translation() - before.translation()).square_length() >= TRANSLATION_THRESHOLD
-        || (after.rotation() - before.rotation()).abs() >= ROTATION_THRESHOLD
-        || (after.extract_scale() - before.extract_scale()).square_length() >= SCALE_THRESHOLD
+fn transform_changed(config: &EngineConfig, before: Transform2F, after: Transform2F) -> bool {
+    (after.translation() - before.translation()).square_length() >= config.translation_threshold
+        || (after.rotation() - before.rotation()).abs() >= config.rotation_threshold
+        || (after.extract_scale() - before.extract_scale()).square_length()
+            >= config.scale_threshold
 }
 
 pub struct ApplyMorphTweens;
@@ -439,8 +1071,9 @@ impl<'a> System<'a> for ApplyMorphTweens {
     type SystemData = (WriteStorage<'a, Morph>, ReadStorage<'a, Tweens>);
 
     fn run(&mut self, (mut morph_storage, tweens_storage): Self::SystemData) {
-        // We don't need a restrict_mut here, because all returned morphs will be updated
-        for (morph, tweens) in (&mut morph_storage, &tweens_storage).join() {
+        // Morph is flagged, so only borrow it mutably when a tween actually sets it. Otherwise
+        // every container with a tween of any kind gets its bounds recomputed.
+        for (mut morph, tweens) in (&mut morph_storage.restrict_mut(), &tweens_storage).join() {
             tweens
                 .0
                 .iter()
@@ -457,12 +1090,39 @@ impl<'a> System<'a> for ApplyMorphTweens {
                 })
                 .reduce(|morph_acc, morph| morph_acc * morph)
                 .map(|updated| {
-                    morph.0 = updated;
+                    morph.get_mut_unchecked().0 = updated;
                 });
         }
     }
 }
 
+/// Concurrent per shape morph tweens don't combine, the most recently started one wins.
+pub struct ApplyMorphIndicesTweens;
+
+impl<'a> System<'a> for ApplyMorphIndicesTweens {
+    type SystemData = (WriteStorage<'a, MorphIndices>, ReadStorage<'a, Tweens>);
+
+    fn run(&mut self, (mut morph_indices_storage, tweens_storage): Self::SystemData) {
+        for (mut morph_indices, tweens) in
+            (&mut morph_indices_storage.restrict_mut(), &tweens_storage).join()
+        {
+            let updated = tweens.0.iter().rev().find_map(|tween| {
+                if let PropertyTweenData::MorphIndices { .. } = tween.tween_data() {
+                    if let PropertyTweenUpdate::MorphIndices(indices) = tween.compute() {
+                        return Some(indices);
+                    }
+                }
+                None
+            });
+            if let Some(updated) = updated {
+                morph_indices.get_mut_unchecked().0 = updated;
+            }
+        }
+    }
+}
+
+/// Concurrent view rect tweens don't combine, the most recently started one wins. Starting a
+/// view rect tween cancels any others unless the update blends in.
 pub struct ApplyViewRectTweens;
 
 impl<'a> System<'a> for ApplyViewRectTweens {
@@ -470,86 +1130,121 @@ impl<'a> System<'a> for ApplyViewRectTweens {
 
     fn run(&mut self, (mut view_storage, tweens_storage): Self::SystemData) {
         for (view_rect, tweens) in (&mut view_storage, &tweens_storage).join() {
-            tweens
-                .0
-                .iter()
-                .filter(|tween| {
-                    if let PropertyTweenData::ViewRect { .. } = tween.tween_data() {
-                        true
-                    } else {
-                        false
-                    }
-                })
-                .map(|tween| {
-                    if let PropertyTweenUpdate::ViewRect(end_rect) = tween.compute() {
-                        end_rect
-                    } else {
-                        panic!();
-                    }
-                })
-                .enumerate()
-                .reduce(|(_, sum_rect), (index, rect)| {
-                    (
-                        index,
-                        RectF::from_points(
-                            sum_rect.origin() + rect.origin(),
-                            sum_rect.lower_right() + rect.lower_right(),
-                        ),
-                    )
-                })
-                .map(|(count, sum_rect)| {
-                    view_rect.0 = RectF::from_points(
-                        sum_rect.origin() / (count + 1) as f32,
-                        sum_rect.lower_right() / count as f32,
-                    );
-                });
+            if let Some(updated) = latest_view_rect(&tweens.0) {
+                view_rect.0 = updated;
+            }
         }
     }
 }
 
+fn latest_view_rect(tweens: &[PropertyTween]) -> Option<RectF> {
+    tweens
+        .iter()
+        .rev()
+        .filter_map(|tween| {
+            if let PropertyTweenData::ViewRect { .. } = tween.tween_data() {
+                if let PropertyTweenUpdate::ViewRect(rect) = tween.compute() {
+                    return Some(rect);
+                }
+            }
+            None
+        })
+        .next()
+}
+
 pub struct ApplyColoringTweens;
 
 impl<'a> System<'a> for ApplyColoringTweens {
-    type SystemData = (WriteStorage<'a, Coloring>, ReadStorage<'a, Tweens>);
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Coloring>,
+        WriteStorage<'a, DisplayFade>,
+        ReadStorage<'a, Tweens>,
+    );
 
-    fn run(&mut self, (mut coloring_storage, tweens_storage): Self::SystemData) {
+    fn run(
+        &mut self,
+        (entities, mut coloring_storage, mut display_fade_storage, tweens_storage): Self::SystemData,
+    ) {
         for (coloring, tweens) in (&mut coloring_storage, &tweens_storage).join() {
             tweens
                 .0
                 .iter()
-                .filter(|tween| {
-                    if let PropertyTweenData::Coloring { .. } = tween.tween_data() {
-                        true
-                    } else {
-                        false
-                    }
-                })
+                .filter(|tween| matches!(tween.tween_data(), PropertyTweenData::Coloring { .. }))
                 .map(|tween| {
                     if let PropertyTweenUpdate::Coloring(end_coloring) = tween.compute() {
-                        end_coloring.into_denormalized()
+                        end_coloring
                     } else {
                         panic!();
                     }
                 })
                 .enumerate()
-                .reduce(|(_, sum_denormalized), (index, denormalized)| {
-                    (index, sum_denormalized.add(&denormalized))
+                // Keeps a running mean of every coloring tween
+                .fold(None, |acc: Option<Coloring>, (index, next)| {
+                    Some(match acc {
+                        Some(acc) => acc.blend(&next, 1.0 / (index + 1) as f32),
+                        None => next,
+                    })
                 })
-                .map(|(count, sum_denormalized)| {
-                    *coloring = (sum_denormalized.div((count + 1) as f32)).into_coloring();
+                .map(|updated| {
+                    *coloring = updated;
                 });
         }
+        // A finished fade has reached the new shape's color, which shows without a coloring
+        let faded = (&entities, &display_fade_storage)
+            .join()
+            .filter(|(entity, _)| {
+                tweens_storage.get(*entity).map_or(true, |tweens| {
+                    !tweens.0.iter().any(|tween| {
+                        matches!(tween.tween_data(), PropertyTweenData::Coloring { .. })
+                            && !tween.is_complete()
+                    })
+                })
+            })
+            .map(|(entity, _)| entity)
+            .collect::<Vec<Entity>>();
+        for entity in faded {
+            coloring_storage.remove(entity);
+            display_fade_storage.remove(entity);
+        }
+    }
+}
+
+/// Concurrent color transform tweens don't combine, the most recently started one wins.
+pub struct ApplyColorTransformTweens;
+
+impl<'a> System<'a> for ApplyColorTransformTweens {
+    type SystemData = (WriteStorage<'a, ColorTransform>, ReadStorage<'a, Tweens>);
+
+    fn run(&mut self, (mut color_transform_storage, tweens_storage): Self::SystemData) {
+        for (color_transform, tweens) in (&mut color_transform_storage, &tweens_storage).join() {
+            let updated = tweens.0.iter().rev().find_map(|tween| {
+                if let PropertyTweenData::ColorTransform { .. } = tween.tween_data() {
+                    if let PropertyTweenUpdate::ColorTransform(updated) = tween.compute() {
+                        return Some(updated);
+                    }
+                }
+                None
+            });
+            if let Some(updated) = updated {
+                *color_transform = updated;
+            }
+        }
     }
 }
 
 pub struct ApplyOrderTweens;
 
 impl<'a> System<'a> for ApplyOrderTweens {
-    type SystemData = (WriteStorage<'a, Order>, ReadStorage<'a, Tweens>);
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Order>,
+        ReadStorage<'a, Tweens>,
+    );
 
-    fn run(&mut self, (mut order_storage, tweens_storage): Self::SystemData) {
-        for (order, tweens) in (&mut order_storage, &tweens_storage).join() {
-            tweens
+    fn run(&mut self, (entities, mut order_storage, tweens_storage): Self::SystemData) {
+        for (entity, tweens) in (&entities, &tweens_storage).join() {
+            let updated = tweens
                 .0
                 .iter()
                 .filter_map(|tween| {
@@ -563,10 +1258,115 @@ impl<'a> System<'a> for ApplyOrderTweens {
                         None
                     }
                 })
-                .reduce(|max, order| max.max(order))
-                .map(|updated| {
-                    order.0 = updated;
-                });
+                .reduce(|max, order| max.max(order));
+            // Only touch orders that change, writing flags them and throws out the paint order
+            if let Some(updated) = updated {
+                if order_storage
+                    .get(entity)
+                    .map_or(false, |order| order.0 != updated)
+                {
+                    if let Some(order) = order_storage.get_mut(entity) {
+                        order.0 = updated;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Throws out the cached `PaintOrder` when any container's `Order` or `Display` changes. Scene
+/// graph changes are caught by its version instead.
+#[derive(Default)]
+pub struct InvalidatePaintOrder {
+    order_reader_id: Option<ReaderId<ComponentEvent>>,
+    display_reader_id: Option<ReaderId<ComponentEvent>>,
+}
+
+impl<'a> System<'a> for InvalidatePaintOrder {
+    type SystemData = (
+        ReadStorage<'a, Order>,
+        ReadStorage<'a, Display>,
+        Write<'a, PaintOrder>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.order_reader_id = Some(WriteStorage::<Order>::fetch(&world).register_reader());
+        self.display_reader_id = Some(WriteStorage::<Display>::fetch(&world).register_reader());
+    }
+
+    fn run(&mut self, (order_storage, display_storage, mut paint_order): Self::SystemData) {
+        // Both channels have to be drained every run, so count them separately
+        let orders_changed = order_storage
+            .channel()
+            .read(self.order_reader_id.as_mut().unwrap())
+            .count();
+        let displays_changed = display_storage
+            .channel()
+            .read(self.display_reader_id.as_mut().unwrap())
+            .count();
+        if orders_changed + displays_changed > 0 {
+            paint_order.invalidate();
+        }
+    }
+}
+
+/// Concurrent path trim tweens don't combine, the most recently started one wins.
+pub struct ApplyPathTrimTweens;
+
+impl<'a> System<'a> for ApplyPathTrimTweens {
+    type SystemData = (WriteStorage<'a, PathTrim>, ReadStorage<'a, Tweens>);
+
+    fn run(&mut self, (mut path_trim_storage, tweens_storage): Self::SystemData) {
+        for (path_trim, tweens) in (&mut path_trim_storage, &tweens_storage).join() {
+            let updated = tweens.0.iter().rev().find_map(|tween| {
+                if let PropertyTweenData::PathTrim { .. } = tween.tween_data() {
+                    if let PropertyTweenUpdate::PathTrim(updated) = tween.compute() {
+                        return Some(updated);
+                    }
+                }
+                None
+            });
+            if let Some(updated) = updated {
+                *path_trim = updated;
+            }
+        }
+    }
+}
+
+/// Advances modifiers by the frame's time, scaled like tweens by the container's `TimeScale`.
+pub struct UpdateModifiers;
+
+impl<'a> System<'a> for UpdateModifiers {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Modifiers>,
+        ReadStorage<'a, TimeScale>,
+        ReadExpect<'a, SceneGraph>,
+        Read<'a, FrameTime>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut modifiers_storage, time_scale_storage, scene_graph, frame_time): Self::SystemData,
+    ) {
+        let any_time_scale = (&time_scale_storage).join().next().is_some();
+        for (entity, modifiers) in (&entities, &mut modifiers_storage).join() {
+            // The root is its own parent, so stop there rather than looping forever
+            let time_scale = if any_time_scale {
+                iter::successors(Some(entity), |entity| {
+                    if entity == scene_graph.root() {
+                        None
+                    } else {
+                        scene_graph.get_parent(entity).copied()
+                    }
+                })
+                .filter_map(|entity| time_scale_storage.get(entity))
+                .fold(1.0, |scale, time_scale| scale * time_scale.0)
+            } else {
+                1.0
+            };
+            modifiers.advance(frame_time.delta_time.mul_f32(time_scale));
         }
     }
 }
@@ -574,6 +1374,8 @@ impl<'a> System<'a> for ApplyOrderTweens {
 #[derive(Default)]
 pub struct UpdateWorldTransform {
     reader_id: Option<ReaderId<ComponentEvent>>,
+    scroll_view_reader_id: Option<ReaderId<ComponentEvent>>,
+    modifiers_reader_id: Option<ReaderId<ComponentEvent>>,
 }
 
 impl<'a> System<'a> for UpdateWorldTransform {
@@ -581,17 +1383,29 @@ impl<'a> System<'a> for UpdateWorldTransform {
         Entities<'a>,
         WriteStorage<'a, WorldTransform>,
         ReadStorage<'a, LocalTransform>,
+        ReadStorage<'a, ScrollView>,
+        ReadStorage<'a, Modifiers>,
         ReadExpect<'a, SceneGraph>,
     );
 
     fn setup(&mut self, world: &mut World) {
         Self::SystemData::setup(world);
         self.reader_id = Some(WriteStorage::<LocalTransform>::fetch(&world).register_reader());
+        self.scroll_view_reader_id =
+            Some(WriteStorage::<ScrollView>::fetch(&world).register_reader());
+        self.modifiers_reader_id = Some(WriteStorage::<Modifiers>::fetch(&world).register_reader());
     }
 
     fn run(
         &mut self,
-        (entities, mut world_transform_storage, local_transform_storage, scene_graph): Self::SystemData,
+        (
+            entities,
+            mut world_transform_storage,
+            local_transform_storage,
+            scroll_view_storage,
+            modifiers_storage,
+            scene_graph,
+        ): Self::SystemData,
     ) {
         let mut dirty = BitSet::default();
         local_transform_storage
@@ -604,6 +1418,38 @@ impl<'a> System<'a> for UpdateWorldTransform {
                 }
                 _ => (),
             });
+        // Scrolling moves every child, and removing a scroll view puts them back
+        scroll_view_storage
+            .channel()
+            .read(self.scroll_view_reader_id.as_mut().unwrap())
+            .into_iter()
+            .for_each(|event| match event {
+                ComponentEvent::Modified(id)
+                | ComponentEvent::Inserted(id)
+                | ComponentEvent::Removed(id) => {
+                    dirty.add(*id);
+                }
+            });
+        // Modifiers change every frame, and removing them puts the container back
+        modifiers_storage
+            .channel()
+            .read(self.modifiers_reader_id.as_mut().unwrap())
+            .into_iter()
+            .for_each(|event| match event {
+                ComponentEvent::Modified(id)
+                | ComponentEvent::Inserted(id)
+                | ComponentEvent::Removed(id) => {
+                    dirty.add(*id);
+                }
+            });
+        // The transform a container passes down to its children
+        let content_transform =
+            |entity: Entity, world_transform: Transform2F| match scroll_view_storage.get(entity) {
+                Some(scroll_view) => {
+                    world_transform * Transform2F::from_translation(-scroll_view.offset)
+                }
+                None => world_transform,
+            };
         // First pass algorithm. O(m log n), where m is # dirty nodes and n is # total nodes.
         // TODO: This would be more efficient with memoization instead of walking up the whole tree for each child.
         let dirty_roots = (&entities, &dirty)
@@ -623,7 +1469,7 @@ impl<'a> System<'a> for UpdateWorldTransform {
             let mut current_world_transform = Transform2F::default();
             for parent in scene_graph.get_parent_iter(&dirty_root) {
                 if let Some(transform) = world_transform_storage.get(*parent) {
-                    current_world_transform = transform.0;
+                    current_world_transform = content_transform(*parent, transform.0);
                     break;
                 }
             }
@@ -632,14 +1478,21 @@ impl<'a> System<'a> for UpdateWorldTransform {
                 // We do not diff world transforms, since that could propogate error down the scene graph
                 let current_world_transform =
                     if let Some(transform) = world_transform_storage.get_mut(next) {
-                        transform.0 =
-                            current_world_transform * local_transform_storage.get(next).unwrap().0;
+                        let local_transform = local_transform_storage.get(next).unwrap().0;
+                        let local_transform = match modifiers_storage.get(next) {
+                            Some(modifiers) if modifiers.offsets().changes_transform() => {
+                                local_transform * modifiers.offsets().transform()
+                            }
+                            _ => local_transform,
+                        };
+                        transform.0 = current_world_transform * local_transform;
                         transform.0
                     } else {
                         current_world_transform
                     };
+                let content_transform = content_transform(next, current_world_transform);
                 for child in scene_graph.get_children(&next).unwrap() {
-                    queue.push_back((*child, current_world_transform));
+                    queue.push_back((*child, content_transform));
                 }
             }
         }
@@ -650,16 +1503,22 @@ pub struct UpdateBounds {
     transform_reader_id: Option<ReaderId<ComponentEvent>>,
     bounds_reader_id: Option<ReaderId<ComponentEvent>>,
     morph_reader_id: Option<ReaderId<ComponentEvent>>,
+    morph_indices_reader_id: Option<ReaderId<ComponentEvent>>,
 }
 
 impl<'a> System<'a> for UpdateBounds {
     type SystemData = (
+        Entities<'a>,
+        WriteExpect<'a, SceneGraph>,
         WriteStorage<'a, Bounds>,
         ReadStorage<'a, WorldTransform>,
         ReadStorage<'a, Morph>,
+        ReadStorage<'a, MorphIndices>,
         ReadStorage<'a, Display>,
         ReadStorage<'a, ViewRect>,
         Read<'a, Library>,
+        Read<'a, DebugFlags>,
+        Write<'a, DirtyRegions>,
     );
 
     fn setup(&mut self, world: &mut World) {
@@ -668,19 +1527,27 @@ impl<'a> System<'a> for UpdateBounds {
             Some(WriteStorage::<WorldTransform>::fetch(&world).register_reader());
         self.bounds_reader_id = Some(WriteStorage::<Bounds>::fetch(&world).register_reader());
         self.morph_reader_id = Some(WriteStorage::<Morph>::fetch(&world).register_reader());
+        self.morph_indices_reader_id =
+            Some(WriteStorage::<MorphIndices>::fetch(&world).register_reader());
     }
 
     fn run(
         &mut self,
         (
+            entities,
+            mut scene_graph,
             mut bounds_storage,
             transform_storage,
             morph_storage,
+            morph_indices_storage,
             display_storage,
             view_rect_storage,
             library,
+            debug_flags,
+            mut dirty_regions,
         ): Self::SystemData,
     ) {
+        dirty_regions.0.clear();
         let mut dirty = BitSet::default();
         transform_storage
             .channel()
@@ -712,26 +1579,83 @@ impl<'a> System<'a> for UpdateBounds {
                 }
                 _ => (),
             });
+        morph_indices_storage
+            .channel()
+            .read(self.morph_indices_reader_id.as_mut().unwrap())
+            .into_iter()
+            .for_each(|event| match event {
+                ComponentEvent::Modified(id) | ComponentEvent::Inserted(id) => {
+                    dirty.add(*id);
+                }
+                _ => (),
+            });
 
         // We don't need a restrict_mut here, because all returned bounds will be updated
-        for (bounds, transform, morph, display, view_rect, _) in (
+        for (bounds, transform, morph, morph_indices, display, view_rect, _) in (
             &mut bounds_storage,
             &transform_storage,
             (&morph_storage).maybe(),
+            (&morph_indices_storage).maybe(),
             (&display_storage).maybe(),
             (&view_rect_storage).maybe(),
             &dirty,
         )
             .join()
         {
-            bounds.bounds = recompute_bounds(
+            if let Some(updated) = recompute_bounds(
                 &bounds.source,
                 transform.0,
                 display,
                 view_rect,
                 morph,
+                morph_indices,
                 &*library,
-            );
+            ) {
+                if debug_flags.dirty_regions && bounds.bounds != updated {
+                    dirty_regions.0.push(bounds.bounds);
+                    dirty_regions.0.push(updated);
+                }
+                bounds.bounds = updated;
+            }
+        }
+
+        // Anything that changed invalidates every ancestor that takes its bounds from children, and
+        // so does a child being removed or moved away
+        let detached_parents = scene_graph.take_detached_parents();
+        let mut dirty_groups = (&entities, &dirty)
+            .join()
+            .map(|(entity, _)| entity)
+            .chain(detached_parents.into_iter())
+            .flat_map(|entity| {
+                std::iter::once(entity)
+                    .chain(scene_graph.get_parent_iter(&entity).copied())
+                    .collect::<Vec<Entity>>()
+            })
+            .filter(|entity| {
+                matches!(
+                    bounds_storage.get(*entity),
+                    Some(Bounds {
+                        source: BoundsSource::Children,
+                        ..
+                    })
+                )
+            })
+            .collect::<HashSet<Entity>>()
+            .into_iter()
+            .map(|entity| (scene_graph.get_parent_iter(&entity).count(), entity))
+            .collect::<Vec<(usize, Entity)>>();
+        // Deepest first so nested groups are up to date before their parents read them
+        dirty_groups.sort_by(|(depth_a, _), (depth_b, _)| depth_b.cmp(depth_a));
+        for (_, entity) in dirty_groups {
+            let union = union_of_children_bounds(&entity, &scene_graph, |child| {
+                bounds_storage.get(child).map(|bounds| bounds.bounds)
+            });
+            match (bounds_storage.get_mut(entity), union) {
+                (Some(bounds), Some(union)) => bounds.bounds = union,
+                // Nothing left to bound, which also takes it out of the quad trees
+                (Some(bounds), None) => bounds.bounds = RectF::default(),
+                _ => (),
+            }
         }
     }
 }
@@ -783,30 +1707,876 @@ impl<'a> System<'a> for UpdateQuadTree {
             (&*entities, &bounds_storage, &layer_storage, &dirty).join()
         {
             let aabb = bounds.bounds;
-            layers
-                .quad_trees
-                .iter()
-                .for_each(|tree| quad_trees.update(entity, *tree, aabb));
+            // Empty bounds, like a group with no children, can't be hit or overlap anything
+            if aabb == RectF::default() {
+                layers
+                    .quad_trees
+                    .iter()
+                    .for_each(|tree| quad_trees.remove_from_layer(tree, entity));
+            } else {
+                layers
+                    .quad_trees
+                    .iter()
+                    .for_each(|tree| quad_trees.update(entity, *tree, aabb));
+            }
+        }
+    }
+}
+
+/// Moves containers with a `Velocity`, `Acceleration` or `AngularVelocity`.
+// TODO: respect TimeScale like tweens do
+pub struct IntegrateMotion;
+
+impl<'a> System<'a> for IntegrateMotion {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, LocalTransform>,
+        WriteStorage<'a, Velocity>,
+        ReadStorage<'a, Acceleration>,
+        WriteStorage<'a, AngularVelocity>,
+        ReadStorage<'a, Damping>,
+        ReadStorage<'a, BounceBounds>,
+        Read<'a, FrameTime>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut local_transform_storage,
+            mut velocity_storage,
+            acceleration_storage,
+            mut angular_velocity_storage,
+            damping_storage,
+            bounce_bounds_storage,
+            frame_time,
+        ): Self::SystemData,
+    ) {
+        let seconds = frame_time.delta_time.as_secs_f32();
+        if seconds <= 0.0 {
+            return;
+        }
+        // Accelerating from rest needs somewhere to keep the velocity
+        let at_rest = (&entities, &acceleration_storage, !&velocity_storage)
+            .join()
+            .map(|(entity, _, _)| entity)
+            .collect::<Vec<Entity>>();
+        for entity in at_rest {
+            velocity_storage
+                .insert(entity, Velocity::default())
+                .unwrap();
+        }
+        let mut moving = velocity_storage.mask().clone();
+        moving |= angular_velocity_storage.mask();
+        for (entity, mut local_transform, _) in (
+            &entities,
+            &mut local_transform_storage.restrict_mut(),
+            &moving,
+        )
+            .join()
+        {
+            let damping = damping_storage.get(entity).map_or(1.0, |damping| {
+                (1.0 - damping.0).max(0.0).min(1.0).powf(seconds)
+            });
+            let mut transform = local_transform.get_unchecked().0;
+            let mut changed = false;
+            if let Some(velocity) = velocity_storage.get_mut(entity) {
+                if let Some(acceleration) = acceleration_storage.get(entity) {
+                    velocity.0 = velocity.0 + acceleration.0 * seconds;
+                }
+                velocity.0 = velocity.0 * damping;
+                if velocity.0.length() < MIN_MOTION_SPEED {
+                    velocity.0 = Vector2F::zero();
+                }
+                if velocity.0 != Vector2F::zero() {
+                    let mut translation = transform.translation() + velocity.0 * seconds;
+                    if let Some(bounce_bounds) = bounce_bounds_storage.get(entity) {
+                        let (bounced, bounced_velocity) =
+                            bounce_bounds.bounce(translation, velocity.0);
+                        translation = bounced;
+                        velocity.0 = bounced_velocity;
+                    }
+                    transform =
+                        Transform2F::from_translation(translation - transform.translation())
+                            * transform;
+                    changed = true;
+                }
+            }
+            if let Some(angular_velocity) = angular_velocity_storage.get_mut(entity) {
+                angular_velocity.0 *= damping;
+                if angular_velocity.0.abs() < MIN_MOTION_SPEED {
+                    angular_velocity.0 = 0.0;
+                }
+                if angular_velocity.0 != 0.0 {
+                    // Spin about the container's own origin, so its translation stays put
+                    let translation = Transform2F::from_translation(transform.translation());
+                    transform = translation
+                        * Transform2F::from_rotation(angular_velocity.0 * seconds)
+                        * translation.inverse()
+                        * transform;
+                    changed = true;
+                }
+            }
+            if changed {
+                local_transform.get_mut_unchecked().0 = transform;
+            }
+        }
+    }
+}
+
+/// Moves anchored containers to their point on the stage. Only transforms that are out of place
+/// are written, which happens when the stage is resized, an anchor changes, or a tween or motion
+/// moved the container this frame.
+pub struct UpdateAnchors;
+
+impl<'a> System<'a> for UpdateAnchors {
+    type SystemData = (
+        WriteStorage<'a, LocalTransform>,
+        ReadStorage<'a, Anchor>,
+        Read<'a, StageArea>,
+    );
+
+    fn run(&mut self, (mut local_transform_storage, anchor_storage, stage_area): Self::SystemData) {
+        let area = stage_area.anchor_rect();
+        for (mut local_transform, anchor) in
+            (&mut local_transform_storage.restrict_mut(), &anchor_storage).join()
+        {
+            let position = anchor.position(area);
+            if local_transform.get_unchecked().0.translation() != position {
+                local_transform.get_mut_unchecked().0.vector = position;
+            }
+        }
+    }
+}
+
+/// Samples how fast the pointer is scrolling a dragged `ScrollView`, and coasts flung ones.
+pub struct UpdateScrollViews;
+
+impl<'a> System<'a> for UpdateScrollViews {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, ScrollView>,
+        Write<'a, DragState>,
+        Read<'a, ContainerMapping>,
+        Read<'a, FrameTime>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut scroll_view_storage, mut drag_state, container_mapping, frame_time): Self::SystemData,
+    ) {
+        let seconds = frame_time.delta_time.as_secs_f32();
+        let dragged = drag_state.scrolling.as_mut().and_then(|active| {
+            if seconds > 0.0 {
+                active.velocity = active.moved * (1.0 / seconds);
+            }
+            active.moved = Vector2F::zero();
+            container_mapping.get_entity(&active.container_id).copied()
+        });
+        // Only touch flung views, so resting ones aren't flagged as modified
+        let flung = (&entities, &scroll_view_storage)
+            .join()
+            .filter(|(entity, scroll_view)| scroll_view.is_flung() && Some(*entity) != dragged)
+            .map(|(entity, _)| entity)
+            .collect::<Vec<Entity>>();
+        for entity in flung {
+            if let Some(scroll_view) = scroll_view_storage.get_mut(entity) {
+                scroll_view.coast(seconds);
+            }
         }
     }
 }
 
-pub struct UpdateTweens;
+pub struct UpdateStateMachines;
+
+impl<'a> System<'a> for UpdateStateMachines {
+    type SystemData = (
+        Write<'a, StateMachines>,
+        Write<'a, ContainerUpdateQueue>,
+        Read<'a, FrameTime>,
+    );
+
+    fn run(
+        &mut self,
+        (mut state_machines, mut container_update_queue, frame_time): Self::SystemData,
+    ) {
+        for definition in state_machines.advance(frame_time.delta_frame) {
+            container_update_queue.enqueue(definition);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct UpdateTweens {
+    // Frame based tweens only advance in whole frames, so scaled frames are carried over here
+    frame_remainders: HashMap<Entity, f32>,
+}
 
 impl<'a> System<'a> for UpdateTweens {
-    type SystemData = (WriteStorage<'a, Tweens>, Read<'a, FrameTime>);
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Tweens>,
+        ReadStorage<'a, TimeScale>,
+        ReadExpect<'a, SceneGraph>,
+        Read<'a, FrameTime>,
+    );
 
-    fn run(&mut self, (mut tweens_storage, frame_time): Self::SystemData) {
-        // update tweens
-        (&mut tweens_storage).join().for_each(|tweens| {
+    fn run(
+        &mut self,
+        (entities, mut tweens_storage, time_scale_storage, scene_graph, frame_time): Self::SystemData,
+    ) {
+        // Walking up the scene graph is most of the cost in big scenes, and usually nothing is
+        // time scaled
+        let any_time_scale = (&time_scale_storage).join().next().is_some();
+        for (entity, tweens) in (&entities, &mut tweens_storage).join() {
+            // The root is its own parent, so stop there rather than looping forever
+            let time_scale = if any_time_scale {
+                iter::successors(Some(entity), |entity| {
+                    if entity == scene_graph.root() {
+                        None
+                    } else {
+                        scene_graph.get_parent(entity).copied()
+                    }
+                })
+                .filter_map(|entity| time_scale_storage.get(entity))
+                .fold(1.0, |scale, time_scale| scale * time_scale.0)
+            } else {
+                1.0
+            };
+            let (delta_frame, delta_time) = if (time_scale - 1.0).abs() < std::f32::EPSILON {
+                (frame_time.delta_frame, frame_time.delta_time)
+            } else {
+                let remainder = self.frame_remainders.entry(entity).or_insert(0.0);
+                let scaled_frames = frame_time.delta_frame as f32 * time_scale + *remainder;
+                *remainder = scaled_frames.fract();
+                (
+                    scaled_frames.trunc() as u32,
+                    frame_time.delta_time.mul_f32(time_scale),
+                )
+            };
+            // Tweens that completed last frame have had their final value applied by now
+            tweens.0.retain(|tween| !tween.is_complete());
             tweens
                 .0
                 .iter_mut()
-                .for_each(|tween| tween.update(frame_time.delta_frame, frame_time.delta_time))
+                .for_each(|tween| tween.update(delta_frame, delta_time));
+        }
+        self.frame_remainders.retain(|entity, _| {
+            tweens_storage
+                .get(*entity)
+                .map_or(false, |tweens| !tweens.0.is_empty())
         });
-        // filter out complete tweens
-        for tweens in (&mut tweens_storage).join() {
-            tweens.0.retain(|tween| !tween.is_complete());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::{ContainerCreationDefintition, ContainerUpdateDefintition},
+        ecs::resources::QuadTreeLayerOptions,
+        engine::Engine,
+        tween::Easing,
+        types::{
+            basic::{ContainerId, LibraryId},
+            shapes::{AugmentedShape, Contour, Edge, MorphContour, MorphEdge, Shape},
+        },
+    };
+
+    fn view_rect_tween(start: RectF, end: RectF, frames: u32) -> PropertyTween {
+        PropertyTween::new_view_rect(
+            RectPoints::from_rect(&start),
+            RectPoints::from_rect(&end),
+            TweenDuration::new_frame(frames),
+            Easing::Linear,
+        )
+    }
+
+    fn rect(x: f32, y: f32, size: f32) -> RectF {
+        RectF::new(Vector2F::new(x, y), Vector2F::splat(size))
+    }
+
+    #[test]
+    fn it_applies_a_single_view_rect_tween() {
+        let mut tween = view_rect_tween(rect(0.0, 0.0, 10.0), rect(10.0, 20.0, 30.0), 10);
+        tween.update(5, Default::default());
+        assert_eq!(latest_view_rect(&[tween]), Some(rect(5.0, 10.0, 20.0)));
+    }
+
+    #[test]
+    fn it_uses_the_latest_view_rect_tween() {
+        let mut first = view_rect_tween(rect(0.0, 0.0, 10.0), rect(10.0, 10.0, 10.0), 10);
+        let mut second = view_rect_tween(rect(0.0, 0.0, 10.0), rect(0.0, 0.0, 20.0), 10);
+        first.update(5, Default::default());
+        second.update(5, Default::default());
+        assert_eq!(
+            latest_view_rect(&[first, second]),
+            Some(rect(0.0, 0.0, 15.0))
+        );
+    }
+
+    #[test]
+    fn it_does_not_snap_back_after_an_interrupted_view_rect_tween() {
+        let root_id = ContainerId::new();
+        let container_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, Library::default(), QuadTrees::default());
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![ContainerCreationProperty::ViewRect(RectPoints::from_rect(
+                &rect(0.0, 0.0, 10.0),
+            ))],
+        ));
+        let tween_to = |target: RectF, frames: u32| {
+            ContainerUpdateDefintition::new(
+                container_id,
+                vec![ContainerUpdateProperty::ViewRect(
+                    RectPoints::from_rect(&target),
+                    Easing::Linear,
+                    frames,
+                )],
+            )
+        };
+        let frame = FrameTime::new(Default::default(), 1);
+        engine.update_container(&tween_to(rect(100.0, 100.0, 10.0), 10));
+        for _ in 0..3 {
+            engine.update(frame);
+        }
+        engine.update_container(&tween_to(rect(0.0, 0.0, 20.0), 2));
+        for _ in 0..4 {
+            engine.update(frame);
+        }
+        assert_eq!(
+            engine.get_view_rect(&container_id),
+            Some(rect(0.0, 0.0, 20.0))
+        );
+    }
+
+    fn tweening_container() -> (Engine, ContainerId) {
+        let root_id = ContainerId::new();
+        let container_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, Library::default(), QuadTrees::default());
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![
+                ContainerCreationProperty::Transform(ScaleRotationTranslation::default()),
+                ContainerCreationProperty::ViewRect(RectPoints::from_rect(&rect(0.0, 0.0, 10.0))),
+            ],
+        ));
+        engine.update_container(&ContainerUpdateDefintition::new(
+            container_id,
+            vec![
+                ContainerUpdateProperty::Transform(
+                    ScaleRotationTranslation::new(
+                        Vector2F::splat(1.0),
+                        0.0,
+                        Vector2F::new(100.0, 0.0),
+                    ),
+                    Easing::Linear,
+                    10,
+                ),
+                ContainerUpdateProperty::ViewRect(
+                    RectPoints::from_rect(&rect(0.0, 0.0, 20.0)),
+                    Easing::Linear,
+                    10,
+                ),
+            ],
+        ));
+        (engine, container_id)
+    }
+
+    #[test]
+    fn it_cancels_only_the_tweens_an_instant_update_sets() {
+        let (mut engine, container_id) = tweening_container();
+        let frame = FrameTime::new(Default::default(), 1);
+        for _ in 0..2 {
+            engine.update(frame);
+        }
+        engine.set_transform(
+            &container_id,
+            ScaleRotationTranslation::new(Vector2F::splat(1.0), 0.0, Vector2F::new(5.0, 0.0)),
+        );
+        for _ in 0..4 {
+            engine.update(frame);
+        }
+        let local = engine.get_local_transform(&container_id).unwrap();
+        assert_eq!(local.translation(), Vector2F::new(5.0, 0.0));
+        // The view rect tween is still partway through
+        let view_rect = engine.get_view_rect(&container_id).unwrap();
+        assert!(view_rect.width() > 10.0 && view_rect.width() < 20.0);
+    }
+
+    #[test]
+    fn it_holds_an_instantly_set_view_rect_over_a_running_tween() {
+        let (mut engine, container_id) = tweening_container();
+        let frame = FrameTime::new(Default::default(), 1);
+        for _ in 0..2 {
+            engine.update(frame);
+        }
+        engine.set_view_rect(&container_id, rect(5.0, 5.0, 5.0));
+        for _ in 0..10 {
+            engine.update(frame);
+        }
+        assert_eq!(
+            engine.get_view_rect(&container_id),
+            Some(rect(5.0, 5.0, 5.0))
+        );
+    }
+
+    #[test]
+    fn it_bounds_group_shapes_by_their_own_morph_index() {
+        // Grows from 10x10 to 20x20 as it morphs
+        let morph_square = || AugmentedShape {
+            shape: Shape::MorphFill {
+                contours: MorphContour::from_edges(&[
+                    MorphEdge::Move(Vector2F::zero(), Vector2F::zero()),
+                    MorphEdge::Line(Vector2F::new(10.0, 0.0), Vector2F::new(20.0, 0.0)),
+                    MorphEdge::Line(Vector2F::splat(10.0), Vector2F::splat(20.0)),
+                ]),
+                color: LinSrgba::new(1.0, 1.0, 1.0, 1.0),
+            },
+            transform: Transform2F::default(),
+        };
+        let shape_id = LibraryId::new();
+        let mut library = Library::default();
+        library.add_shape(
+            shape_id,
+            Shape::Group {
+                shapes: vec![morph_square(), morph_square()],
+            },
+        );
+        let root_id = ContainerId::new();
+        let container_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, library, QuadTrees::default());
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![
+                ContainerCreationProperty::Transform(ScaleRotationTranslation::default()),
+                ContainerCreationProperty::Display(shape_id),
+                ContainerCreationProperty::Bounds(BoundsKindDefinition::Display),
+            ],
+        ));
+        let frame = FrameTime::new(Default::default(), 1);
+        engine.update(frame);
+        assert_eq!(engine.get_bounds(&container_id), Some(rect(0.0, 0.0, 10.0)));
+        engine.update_container(&ContainerUpdateDefintition::new(
+            container_id,
+            vec![ContainerUpdateProperty::MorphIndices(
+                vec![0.0, 1.0],
+                Easing::Linear,
+                2,
+            )],
+        ));
+        for _ in 0..3 {
+            engine.update(frame);
+        }
+        assert_eq!(engine.get_bounds(&container_id), Some(rect(0.0, 0.0, 20.0)));
+    }
+
+    const GROUP_LAYER: QuadTreeLayer = QuadTreeLayer::new(1);
+
+    // A group with a 10x10 square at (0, 0) and another at (20, 20), in `GROUP_LAYER`. Returns
+    // the engine, the group, and the square at (20, 20)
+    fn group_of_two_squares() -> (Engine, ContainerId, ContainerId) {
+        let shape_id = LibraryId::new();
+        let mut library = Library::default();
+        library.add_shape(
+            shape_id,
+            Shape::Fill {
+                contours: Contour::from_edges(&Edge::new_rect(
+                    Vector2F::splat(10.0),
+                    Transform2F::default(),
+                )),
+                color: LinSrgba::new(1.0, 1.0, 1.0, 1.0),
+            },
+        );
+        let root_id = ContainerId::new();
+        let group_id = ContainerId::new();
+        let far_id = ContainerId::new();
+        let mut quad_trees = QuadTrees::default();
+        quad_trees.create_quad_tree(
+            GROUP_LAYER,
+            rect(-100.0, -100.0, 200.0),
+            QuadTreeLayerOptions::new(0.0),
+        );
+        let mut engine = Engine::new(root_id, library, quad_trees);
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            group_id,
+            vec![
+                ContainerCreationProperty::Transform(ScaleRotationTranslation::default()),
+                ContainerCreationProperty::Bounds(BoundsKindDefinition::Children),
+                ContainerCreationProperty::Layer(GROUP_LAYER),
+            ],
+        ));
+        for (container_id, position) in [(ContainerId::new(), 0.0), (far_id, 20.0)].iter() {
+            engine.create_container(&ContainerCreationDefintition::new(
+                group_id,
+                *container_id,
+                vec![
+                    ContainerCreationProperty::Transform(ScaleRotationTranslation::new(
+                        Vector2F::splat(1.0),
+                        0.0,
+                        Vector2F::splat(*position),
+                    )),
+                    ContainerCreationProperty::Display(shape_id),
+                    ContainerCreationProperty::Bounds(BoundsKindDefinition::Display),
+                ],
+            ));
+        }
+        engine.update(FrameTime::new(Default::default(), 1));
+        assert_eq!(engine.get_bounds(&group_id), Some(rect(0.0, 0.0, 30.0)));
+        (engine, group_id, far_id)
+    }
+
+    #[test]
+    fn it_fades_each_blended_display_swap_and_then_shows_the_shape_color() {
+        let mut library = Library::default();
+        let shape_ids = [
+            LinSrgba::new(1.0, 0.0, 0.0, 1.0),
+            LinSrgba::new(0.0, 1.0, 0.0, 1.0),
+            LinSrgba::new(0.0, 0.0, 1.0, 1.0),
+        ]
+        .iter()
+        .map(|color| {
+            let shape_id = LibraryId::new();
+            library.add_shape(
+                shape_id,
+                Shape::Fill {
+                    contours: Contour::from_edges(&Edge::new_rect(
+                        Vector2F::splat(10.0),
+                        Transform2F::default(),
+                    )),
+                    color: *color,
+                },
+            );
+            shape_id
+        })
+        .collect::<Vec<LibraryId>>();
+        let root_id = ContainerId::new();
+        let container_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, library, QuadTrees::default());
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![
+                ContainerCreationProperty::Transform(ScaleRotationTranslation::default()),
+                ContainerCreationProperty::Display(shape_ids[0]),
+            ],
+        ));
+        let frame = FrameTime::new(Default::default(), 1);
+        engine.update(frame);
+        for shape_id in shape_ids[1..].iter() {
+            engine.update_container(
+                &ContainerUpdateDefintition::new(
+                    container_id,
+                    vec![ContainerUpdateProperty::Display(*shape_id)],
+                )
+                .with_blend_in(Easing::Linear, 4),
+            );
+            engine.update(frame);
+            assert!(engine.get_coloring(&container_id).is_some());
+            for _ in 0..5 {
+                engine.update(frame);
+            }
+            // The fade's coloring is gone, so the next swap fades too
+            assert_eq!(engine.get_coloring(&container_id), None);
+        }
+    }
+
+    #[test]
+    fn it_shrinks_group_bounds_when_a_child_is_removed() {
+        let (mut engine, group_id, far_id) = group_of_two_squares();
+        engine.remove_container(&far_id).unwrap();
+        engine.update(FrameTime::new(Default::default(), 1));
+        assert_eq!(engine.get_bounds(&group_id), Some(rect(0.0, 0.0, 10.0)));
+    }
+
+    #[test]
+    fn it_empties_group_bounds_when_the_last_child_is_removed() {
+        let (mut engine, group_id, _) = group_of_two_squares();
+        let group_layer_size = |engine: &Engine| {
+            engine
+                .get_quad_trees()
+                .layer_sizes()
+                .into_iter()
+                .find(|(layer, _, _)| *layer == GROUP_LAYER)
+                .map(|(_, elements, _)| elements)
+        };
+        assert_eq!(group_layer_size(&engine), Some(1));
+        for child_id in engine.get_children(&group_id).unwrap() {
+            engine.remove_container(&child_id).unwrap();
+        }
+        engine.update(FrameTime::new(Default::default(), 1));
+        assert_eq!(engine.get_bounds(&group_id), Some(RectF::default()));
+        assert_eq!(group_layer_size(&engine), Some(0));
+    }
+
+    #[test]
+    fn it_shrinks_group_bounds_when_a_child_is_reparented_away() {
+        let (mut engine, group_id, far_id) = group_of_two_squares();
+        let root_id = engine.get_root_container_id();
+        engine.update_container(&ContainerUpdateDefintition::new(
+            far_id,
+            vec![ContainerUpdateProperty::Parent(root_id)],
+        ));
+        engine.update(FrameTime::new(Default::default(), 1));
+        assert_eq!(engine.get_bounds(&group_id), Some(rect(0.0, 0.0, 10.0)));
+    }
+
+    #[test]
+    fn it_reports_scene_events() {
+        let root_id = ContainerId::new();
+        let parent_id = ContainerId::new();
+        let child_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, Library::default(), QuadTrees::default());
+        let frame = FrameTime::new(Default::default(), 1);
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            parent_id,
+            vec![],
+        ));
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            child_id,
+            vec![],
+        ));
+        engine.update(frame);
+        engine.update_container(&ContainerUpdateDefintition::new(
+            child_id,
+            vec![ContainerUpdateProperty::Parent(parent_id)],
+        ));
+        engine.update(frame);
+        engine.remove_container_and_children(&parent_id).unwrap();
+        assert_eq!(
+            engine.poll_scene_events(),
+            vec![
+                SceneEvent::Created(parent_id),
+                SceneEvent::Created(child_id),
+                SceneEvent::Reparented {
+                    container_id: child_id,
+                    new_parent: parent_id,
+                },
+                SceneEvent::Removed(parent_id),
+                SceneEvent::Removed(child_id),
+            ]
+        );
+        assert!(engine.poll_scene_events().is_empty());
+    }
+
+    #[test]
+    fn it_skips_bad_definitions_by_runtime_policy() {
+        let root_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, Library::default(), QuadTrees::default());
+        let frame = FrameTime::new(Default::default(), 1);
+        let orphan =
+            ContainerCreationDefintition::new(ContainerId::new(), ContainerId::new(), vec![]);
+        let missing_display = ContainerCreationDefintition::new(
+            root_id,
+            ContainerId::new(),
+            vec![ContainerCreationProperty::Display(LibraryId::new())],
+        );
+        engine.create_container(&orphan);
+        engine.create_container(&missing_display);
+        engine.update(frame);
+        assert_eq!(engine.take_runtime_errors().len(), 2);
+        // The container is still created, just without the display
+        assert!(engine.get_display(missing_display.id()).is_none());
+        assert!(engine.get_parent(missing_display.id()).is_some());
+
+        engine.set_runtime_policy(RuntimePolicy::Lenient);
+        engine.create_container(&orphan);
+        engine.update(frame);
+        assert!(engine.take_runtime_errors().is_empty());
+    }
+
+    #[test]
+    fn it_converts_between_screen_stage_and_container_space() {
+        let root_id = ContainerId::new();
+        let container_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, Library::default(), QuadTrees::default());
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![ContainerCreationProperty::Transform(
+                ScaleRotationTranslation::new(Vector2F::splat(2.0), 0.0, Vector2F::splat(10.0)),
+            )],
+        ));
+        engine.update(FrameTime::new(Default::default(), 1));
+        let mut config = engine.get_config();
+        config.device_pixel_ratio = 2.0;
+        engine.set_config(config);
+        let mut camera = crate::ecs::resources::Camera::default();
+        camera.pan_by(Vector2F::new(5.0, 0.0));
+
+        let screen = Vector2F::new(50.0, 40.0);
+        let stage = engine.screen_to_stage(screen, &camera);
+        assert_eq!(stage, Vector2F::new(20.0, 20.0));
+        assert_eq!(engine.stage_to_screen(stage, &camera), screen);
+        let local = engine.stage_to_container_local(stage, &container_id);
+        assert_eq!(local, Some(Vector2F::splat(5.0)));
+        assert_eq!(
+            engine.container_local_to_stage(Vector2F::splat(5.0), &container_id),
+            Some(stage)
+        );
+        assert_eq!(
+            engine.stage_to_container_local(stage, &ContainerId::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn it_bounces_moving_containers_off_their_bounds() {
+        let root_id = ContainerId::new();
+        let container_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, Library::default(), QuadTrees::default());
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![
+                ContainerCreationProperty::Transform(ScaleRotationTranslation::default()),
+                ContainerCreationProperty::Velocity(Vector2F::new(10.0, 0.0)),
+                ContainerCreationProperty::BounceBounds(BounceBounds {
+                    bounds: RectPoints::from_rect(&rect(0.0, 0.0, 15.0)),
+                    restitution: 1.0,
+                }),
+            ],
+        ));
+        let second = FrameTime::new(std::time::Duration::from_secs(1), 60);
+        let translation = |engine: &Engine| {
+            engine
+                .get_local_transform(&container_id)
+                .unwrap()
+                .translation()
+        };
+        engine.update(second);
+        assert_eq!(translation(&engine), Vector2F::new(10.0, 0.0));
+        engine.update(second);
+        assert_eq!(translation(&engine), Vector2F::new(10.0, 0.0));
+        engine.update(second);
+        assert_eq!(translation(&engine), Vector2F::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn it_keeps_anchored_containers_pinned_when_the_stage_resizes() {
+        let root_id = ContainerId::new();
+        let container_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, Library::default(), QuadTrees::default());
+        engine.set_stage_area(StageArea::new(Vector2F::new(800.0, 600.0)));
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![
+                ContainerCreationProperty::Transform(ScaleRotationTranslation::default()),
+                ContainerCreationProperty::Anchor(Anchor::new(
+                    Vector2F::new(1.0, 0.0),
+                    Vector2F::new(-20.0, 10.0),
+                )),
+            ],
+        ));
+        let frame = FrameTime::new(std::time::Duration::from_millis(16), 1);
+        let translation = |engine: &Engine| {
+            engine
+                .get_world_transform(&container_id)
+                .unwrap()
+                .translation()
+        };
+        engine.update(frame);
+        assert_eq!(translation(&engine), Vector2F::new(780.0, 10.0));
+        engine.set_stage_area(StageArea::new(Vector2F::new(1024.0, 768.0)));
+        engine.update(frame);
+        assert_eq!(translation(&engine), Vector2F::new(1004.0, 10.0));
+        engine.set_stage_area(StageArea {
+            size: Vector2F::new(1024.0, 768.0),
+            safe_area: Some(RectF::new(
+                Vector2F::new(0.0, 40.0),
+                Vector2F::new(980.0, 728.0),
+            )),
+        });
+        engine.update(frame);
+        assert_eq!(translation(&engine), Vector2F::new(960.0, 50.0));
+    }
+
+    // The root is its own parent, so walking up from it for the time scale used to never end
+    #[test]
+    fn it_time_scales_tweens_on_the_root_and_under_it() {
+        let root_id = ContainerId::new();
+        let container_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, Library::default(), QuadTrees::default());
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![ContainerCreationProperty::Transform(
+                ScaleRotationTranslation::default(),
+            )],
+        ));
+        engine.set_time_scale(&root_id, 0.5);
+        for id in [root_id, container_id].iter() {
+            engine.update_container(&ContainerUpdateDefintition::new(
+                *id,
+                vec![ContainerUpdateProperty::Transform(
+                    ScaleRotationTranslation::new(
+                        Vector2F::splat(1.0),
+                        0.0,
+                        Vector2F::new(100.0, 0.0),
+                    ),
+                    Easing::Linear,
+                    10,
+                )],
+            ));
+        }
+        let frame = FrameTime::new(std::time::Duration::from_millis(100), 1);
+        for _ in 0..4 {
+            engine.update(frame);
+        }
+        // Four frames at half speed is two frames of ten
+        for id in [root_id, container_id].iter() {
+            let local = engine.get_local_transform(id).unwrap();
+            assert!((local.translation().x() - 20.0).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn it_layers_noise_over_transform_tweens() {
+        use crate::modifiers::{Modifier, ModifierTarget, Noise};
+        let root_id = ContainerId::new();
+        let container_id = ContainerId::new();
+        let noise = Noise::new(5.0, 4.0, 42);
+        let mut engine = Engine::new(root_id, Library::default(), QuadTrees::default());
+        engine.create_container(&ContainerCreationDefintition::new(
+            root_id,
+            container_id,
+            vec![
+                ContainerCreationProperty::Transform(ScaleRotationTranslation::default()),
+                ContainerCreationProperty::Modifiers(vec![Modifier::Noise(
+                    ModifierTarget::Translation,
+                    noise,
+                )]),
+            ],
+        ));
+        engine.update_container(&ContainerUpdateDefintition::new(
+            container_id,
+            vec![ContainerUpdateProperty::Transform(
+                ScaleRotationTranslation::new(Vector2F::splat(1.0), 0.0, Vector2F::new(100.0, 0.0)),
+                Easing::Linear,
+                10,
+            )],
+        ));
+        let frame = FrameTime::new(std::time::Duration::from_millis(100), 1);
+        let mut moved = false;
+        for frame_index in 1..=5 {
+            engine.update(frame);
+            let local = engine.get_local_transform(&container_id).unwrap();
+            let world = engine.get_world_transform(&container_id).unwrap();
+            // The tween only moves along x, and the noise never builds up in the local transform
+            assert_eq!(local.translation().y(), 0.0);
+            let offset = world.translation() - local.translation();
+            assert!((offset.x() - noise.sample(frame_index as f32 * 0.1)).abs() < 0.001);
+            assert!(offset.y().abs() <= 5.0);
+            moved |= offset.y() != 0.0;
         }
+        assert!(moved);
     }
 }
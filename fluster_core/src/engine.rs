@@ -1,48 +1,114 @@
 use crate::{
-    actions::{ContainerCreationDefintition, ContainerUpdateDefintition},
+    actions::{
+        ContainerCreationDefintition, ContainerCreationProperty, ContainerUpdateDefintition,
+        ContainerUpdateProperty, RectPoints,
+    },
     ecs::{
-        common::recompute_bounds,
+        common::{recompute_bounds, union_of_children_bounds},
         components::{
-            Bounds, Display, DisplayKind, Layer, LocalTransform, Morph, Order, Tweens, ViewRect,
-            WorldTransform,
+            Acceleration, Anchor, AngularVelocity, BounceBounds, Bounds, Damping, Display,
+            DisplayFade, DisplayKind, Draggable, Focusable, HitShape, KeyBindingAction,
+            KeyBindings, KeyModifiers, KeyPhase, Layer, LocalTransform, Modifiers, Morph,
+            MorphIndices, Order, Parallax, PixelSnap, ScrollView, TimeScale, Tweens, Velocity,
+            ViewRect, WorldTransform,
         },
         resources::{
-            ContainerCreationQueue, ContainerMapping, ContainerUpdateQueue, FrameTime, Library,
-            QuadTreeQuery, QuadTrees, SceneGraph,
+            ActiveDrag, ActiveScroll, Camera, ContainerCreationQueue, ContainerMapping,
+            ContainerUpdateQueue, DebugFlags, DirtyRegions, DragState, EngineConfig, FocusState,
+            FrameProfile, FrameTime, Library, PaintOrder, QuadTreeLayer, QuadTreeLayerOptions,
+            QuadTreeLayerRegistry, QuadTreeQuery, QuadTrees, Random, RenderQuality, RuntimeErrors,
+            RuntimePolicy, SceneDirty, SceneEvent, SceneEventQueue, SceneGraph, StageArea,
         },
         systems::{
-            ApplyColoringTweens, ApplyMorphTweens, ApplyOrderTweens, ApplyTransformTweens,
-            ApplyViewRectTweens, ContainerCreation, ContainerUpdate, UpdateBounds, UpdateQuadTree,
-            UpdateTweens, UpdateWorldTransform,
+            ApplyColorTransformTweens, ApplyColoringTweens, ApplyMorphIndicesTweens,
+            ApplyMorphTweens, ApplyOrderTweens, ApplyPathTrimTweens, ApplyTransformTweens,
+            ApplyViewRectTweens, ContainerCreation, ContainerUpdate, IntegrateMotion,
+            InvalidatePaintOrder, Profiled, UpdateAnchors, UpdateBounds, UpdateModifiers,
+            UpdateQuadTree, UpdateScrollViews, UpdateStateMachines, UpdateTweens,
+            UpdateWorldTransform,
         },
     },
+    rendering::ClipRect,
+    state_machine::{StateMachine, StateMachines},
+    tween::Easing,
     types::{
-        basic::{ContainerId, LibraryId},
-        coloring::Coloring,
-        shapes::Shape,
+        basic::{ContainerId, LibraryId, ScaleRotationTranslation},
+        coloring::{ColorTransform, Coloring},
+        shapes::{Contour, Edge, PathTrim, Shape},
     },
+    util::{self, ray_aabb_distance},
+};
+use palette::LinSrgba;
+use pathfinder_content::{
+    pattern::Pattern,
+    stroke::{LineCap, LineJoin, StrokeStyle},
 };
-use pathfinder_content::pattern::Pattern;
 use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use rayon::ThreadPool;
+use serde::{Deserialize, Serialize};
 use specs::{
     error::Error as SpecsError,
     shred::{Fetch, FetchMut},
-    Builder, Dispatcher, DispatcherBuilder, Entity, Join, World, WorldExt,
+    Builder, Component, Dispatcher, DispatcherBuilder, Entity, Join, World, WorldExt,
 };
 use std::{
     cmp::Ordering,
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet},
+    iter,
     sync::Arc,
+    time::Instant,
 };
 
-pub struct Engine<'a, 'b> {
+pub struct Engine {
     root_container_id: ContainerId,
     world: World,
-    dispatcher: Dispatcher<'a, 'b>,
+    dispatcher: Dispatcher<'static, 'static>,
 }
 
-impl<'a, 'b> Engine<'a, 'b> {
+impl Engine {
     pub fn new(root_container_id: ContainerId, library: Library, quad_trees: QuadTrees) -> Self {
+        Self::build(
+            root_container_id,
+            library,
+            quad_trees,
+            EngineConfig::default(),
+            None,
+        )
+    }
+
+    pub fn with_config(
+        root_container_id: ContainerId,
+        library: Library,
+        quad_trees: QuadTrees,
+        config: EngineConfig,
+    ) -> Self {
+        Self::build(root_container_id, library, quad_trees, config, None)
+    }
+
+    /// Constructs an Engine whose systems are dispatched on the provided thread pool instead of
+    /// a pool created by specs.
+    pub fn with_thread_pool(
+        root_container_id: ContainerId,
+        library: Library,
+        quad_trees: QuadTrees,
+        thread_pool: Arc<ThreadPool>,
+    ) -> Self {
+        Self::build(
+            root_container_id,
+            library,
+            quad_trees,
+            EngineConfig::default(),
+            Some(thread_pool),
+        )
+    }
+
+    fn build(
+        root_container_id: ContainerId,
+        library: Library,
+        quad_trees: QuadTrees,
+        config: EngineConfig,
+        thread_pool: Option<Arc<ThreadPool>>,
+    ) -> Self {
         let mut world = World::new();
         //Register components
         world.register::<LocalTransform>();
@@ -52,8 +118,25 @@ impl<'a, 'b> Engine<'a, 'b> {
         world.register::<Order>();
         world.register::<Display>();
         world.register::<Tweens>();
+        world.register::<TimeScale>();
+        world.register::<PixelSnap>();
+        world.register::<HitShape>();
+        world.register::<Draggable>();
+        world.register::<ScrollView>();
+        world.register::<Focusable>();
+        world.register::<KeyBindings>();
+        world.register::<Velocity>();
+        world.register::<Acceleration>();
+        world.register::<AngularVelocity>();
+        world.register::<Damping>();
+        world.register::<BounceBounds>();
+        world.register::<Parallax>();
+        world.register::<Anchor>();
+        world.register::<PathTrim>();
+        world.register::<Modifiers>();
         world.register::<Layer>();
         world.register::<Coloring>();
+        world.register::<DisplayFade>();
         world.register::<ViewRect>();
 
         // Setup resources
@@ -64,61 +147,160 @@ impl<'a, 'b> Engine<'a, 'b> {
             .build();
         world.insert(SceneGraph::new(root));
         world.insert(quad_trees);
+        world.insert(QuadTreeLayerRegistry::default());
         let mut container_mapping = ContainerMapping::default();
         container_mapping.add_container(root_container_id, root);
         world.insert(container_mapping);
         world.insert(library);
         world.insert(ContainerCreationQueue::default());
         world.insert(ContainerUpdateQueue::default());
+        world.insert(SceneEventQueue::default());
+        world.insert(SceneDirty(true));
+        world.insert(FrameProfile::default());
+        world.insert(config);
+        world.insert(Camera::default());
+        world.insert(StageArea::default());
+        world.insert(DebugFlags::default());
+        world.insert(RenderQuality::default());
+        world.insert(RuntimePolicy::default());
+        world.insert(RuntimeErrors::default());
+        world.insert(PaintOrder::default());
+        world.insert(DirtyRegions::default());
+        world.insert(StateMachines::default());
+        world.insert(DragState::default());
+        world.insert(FocusState::default());
+        world.insert(Random::default());
 
         // Setup systems
-        let mut dispatcher = DispatcherBuilder::new()
-            .with(ContainerCreation, "container_creation", &[])
-            .with(ContainerUpdate, "container_update", &["container_creation"])
+        // The tween application systems each write a different storage, so once creation and
+        // updates have been processed they are free to run in parallel.
+        let mut dispatcher_builder = DispatcherBuilder::new();
+        if let Some(thread_pool) = thread_pool {
+            dispatcher_builder = dispatcher_builder.with_pool(thread_pool);
+        }
+        let mut dispatcher = dispatcher_builder
+            .with(
+                Profiled::new("container_creation", ContainerCreation),
+                "container_creation",
+                &[],
+            )
             .with(
-                ApplyTransformTweens,
+                Profiled::new("update_state_machines", UpdateStateMachines),
+                "update_state_machines",
+                &[],
+            )
+            .with(
+                Profiled::new("container_update", ContainerUpdate),
+                "container_update",
+                &["container_creation", "update_state_machines"],
+            )
+            .with(
+                Profiled::new("apply_transform_tweens", ApplyTransformTweens),
                 "apply_transform_tweens",
                 &["container_creation", "container_update"],
             )
             .with(
-                ApplyMorphTweens,
+                Profiled::new("apply_morph_tweens", ApplyMorphTweens),
                 "apply_morph_tweens",
                 &["container_creation", "container_update"],
             )
             .with(
-                ApplyViewRectTweens,
+                Profiled::new("apply_morph_indices_tweens", ApplyMorphIndicesTweens),
+                "apply_morph_indices_tweens",
+                &["container_creation", "container_update"],
+            )
+            .with(
+                Profiled::new("apply_view_rect_tweens", ApplyViewRectTweens),
                 "apply_view_rect_tweens",
                 &["container_creation", "container_update"],
             )
             .with(
-                ApplyColoringTweens,
+                Profiled::new("apply_coloring_tweens", ApplyColoringTweens),
                 "apply_coloring_tweens",
                 &["container_creation", "container_update"],
             )
             .with(
-                ApplyOrderTweens,
+                Profiled::new("apply_order_tweens", ApplyOrderTweens),
                 "apply_order_tweens",
                 &["container_creation", "container_update"],
             )
             .with(
-                UpdateWorldTransform::default(),
-                "update_world_transform",
+                Profiled::new("apply_color_transform_tweens", ApplyColorTransformTweens),
+                "apply_color_transform_tweens",
+                &["container_creation", "container_update"],
+            )
+            .with(
+                Profiled::new("apply_path_trim_tweens", ApplyPathTrimTweens),
+                "apply_path_trim_tweens",
+                &["container_creation", "container_update"],
+            )
+            .with(
+                Profiled::new("update_scroll_views", UpdateScrollViews),
+                "update_scroll_views",
+                &["container_creation", "container_update"],
+            )
+            .with(
+                Profiled::new("integrate_motion", IntegrateMotion),
+                "integrate_motion",
                 &["apply_transform_tweens"],
             )
             .with(
-                UpdateBounds::default(),
+                Profiled::new("update_anchors", UpdateAnchors),
+                "update_anchors",
+                &["apply_transform_tweens", "integrate_motion"],
+            )
+            .with(
+                Profiled::new("update_modifiers", UpdateModifiers),
+                "update_modifiers",
+                &["container_creation", "container_update"],
+            )
+            .with(
+                Profiled::new("update_world_transform", UpdateWorldTransform::default()),
+                "update_world_transform",
+                &[
+                    "apply_transform_tweens",
+                    "update_scroll_views",
+                    "integrate_motion",
+                    "update_anchors",
+                    "update_modifiers",
+                ],
+            )
+            .with(
+                Profiled::new("update_bounds", UpdateBounds::default()),
                 "update_bounds",
-                &["update_world_transform", "apply_morph_tweens"],
+                &[
+                    "update_world_transform",
+                    "apply_morph_tweens",
+                    "apply_morph_indices_tweens",
+                ],
             )
             .with(
-                UpdateQuadTree::default(),
+                Profiled::new("update_quad_tree", UpdateQuadTree::default()),
                 "update_quad_tree",
                 &["update_bounds"],
             )
             .with(
-                UpdateTweens,
+                Profiled::new("invalidate_paint_order", InvalidatePaintOrder::default()),
+                "invalidate_paint_order",
+                &[
+                    "container_creation",
+                    "container_update",
+                    "apply_order_tweens",
+                ],
+            )
+            .with(
+                Profiled::new("update_tweens", UpdateTweens::default()),
                 "update_tweens",
-                &["apply_transform_tweens", "apply_morph_tweens"],
+                &[
+                    "apply_transform_tweens",
+                    "apply_morph_tweens",
+                    "apply_morph_indices_tweens",
+                    "apply_view_rect_tweens",
+                    "apply_coloring_tweens",
+                    "apply_order_tweens",
+                    "apply_color_transform_tweens",
+                    "apply_path_trim_tweens",
+                ],
             )
             .build();
         dispatcher.setup(&mut world);
@@ -129,10 +311,97 @@ impl<'a, 'b> Engine<'a, 'b> {
         }
     }
 
-    pub fn update(&mut self, frame_time: FrameTime) {
+    /// Steps the scene by `frame_time`. Returns false if the engine was quiescent and the
+    /// systems were skipped, in which case nothing in the scene changed.
+    pub fn update(&mut self, frame_time: FrameTime) -> bool {
+        let quiescent = self.is_quiescent_for(frame_time.delta_frame);
         self.world.insert(frame_time);
+        self.world.write_resource::<FrameProfile>().clear();
+        self.world.write_resource::<RuntimeErrors>().0.clear();
+        if quiescent {
+            // Keep AfterFrames transitions counting, and don't leave last frame's regions around
+            self.get_state_machines_mut()
+                .skip_frames(frame_time.delta_frame);
+            self.world.write_resource::<DirtyRegions>().0.clear();
+            return false;
+        }
+        // Tweens finishing this frame are gone once it's dispatched, so check before
+        if self.is_tweening() || self.is_scrolling() || self.is_moving() || self.is_modified() {
+            self.mark_scene_dirty();
+        }
+        let dispatch_start = Instant::now();
         self.dispatcher.dispatch(&mut self.world);
+        self.world
+            .write_resource::<FrameProfile>()
+            .set_dispatch_time(dispatch_start.elapsed());
         self.world.maintain();
+        true
+    }
+
+    /// True if an `update` of one frame would change nothing: no tweens are running, nothing is
+    /// queued, no state machine is about to transition, and the scene hasn't been touched since
+    /// the last `take_scene_dirty`. Hosts that never take the dirty flag never go quiescent.
+    pub fn is_quiescent(&self) -> bool {
+        self.is_quiescent_for(1)
+    }
+
+    fn is_quiescent_for(&self, delta_frames: u32) -> bool {
+        !self.world.read_resource::<SceneDirty>().0
+            && self
+                .world
+                .read_resource::<ContainerCreationQueue>()
+                .is_empty()
+            && self
+                .world
+                .read_resource::<ContainerUpdateQueue>()
+                .is_empty()
+            && self
+                .world
+                .read_resource::<StateMachines>()
+                .is_settled(delta_frames)
+            && !self.is_tweening()
+            && !self.is_scrolling()
+            && !self.is_moving()
+            && !self.is_modified()
+    }
+
+    // Modifiers change their container every frame for as long as they are attached
+    fn is_modified(&self) -> bool {
+        (&self.world.read_storage::<Modifiers>())
+            .join()
+            .any(|modifiers| !modifiers.modifiers().is_empty())
+    }
+
+    // A flung scroll view is coasting, or the pointer scrolled one since the last update
+    fn is_scrolling(&self) -> bool {
+        let drag_state = self.world.read_resource::<DragState>();
+        if let Some(active) = &drag_state.scrolling {
+            if active.moved != Vector2F::zero() || active.velocity != Vector2F::zero() {
+                return true;
+            }
+        }
+        (&self.world.read_storage::<ScrollView>())
+            .join()
+            .any(|scroll_view| scroll_view.is_flung())
+    }
+
+    // Anything with motion that hasn't come to rest
+    fn is_moving(&self) -> bool {
+        (&self.world.read_storage::<Velocity>())
+            .join()
+            .any(|velocity| velocity.0 != Vector2F::zero())
+            || (&self.world.read_storage::<Acceleration>())
+                .join()
+                .any(|acceleration| acceleration.0 != Vector2F::zero())
+            || (&self.world.read_storage::<AngularVelocity>())
+                .join()
+                .any(|angular_velocity| angular_velocity.0 != 0.0)
+    }
+
+    fn is_tweening(&self) -> bool {
+        (&self.world.read_storage::<Tweens>())
+            .join()
+            .any(|tweens| !tweens.0.is_empty())
     }
 
     pub fn root_container_id(&self) -> &ContainerId {
@@ -144,6 +413,7 @@ impl<'a, 'b> Engine<'a, 'b> {
     }
 
     pub fn get_scene_graph_mut(&mut self) -> FetchMut<SceneGraph> {
+        self.mark_scene_dirty();
         self.world.write_resource::<SceneGraph>()
     }
 
@@ -160,6 +430,7 @@ impl<'a, 'b> Engine<'a, 'b> {
     }
 
     pub fn get_library_mut(&mut self) -> FetchMut<Library> {
+        self.mark_scene_dirty();
         self.world.write_resource::<Library>()
     }
 
@@ -171,12 +442,428 @@ impl<'a, 'b> Engine<'a, 'b> {
         self.world.write_resource::<QuadTrees>()
     }
 
+    pub fn get_layer_registry(&self) -> Fetch<QuadTreeLayerRegistry> {
+        self.world.read_resource::<QuadTreeLayerRegistry>()
+    }
+
+    pub fn get_layer_registry_mut(&mut self) -> FetchMut<QuadTreeLayerRegistry> {
+        self.world.write_resource::<QuadTreeLayerRegistry>()
+    }
+
+    /// Registers `name` for `layer` and creates its quad tree. Nothing is created if the name or
+    /// layer collides with an existing declaration.
+    pub fn declare_quad_tree_layer(
+        &mut self,
+        name: &str,
+        layer: QuadTreeLayer,
+        bounds: RectF,
+        options: QuadTreeLayerOptions,
+    ) -> Result<(), String> {
+        self.get_layer_registry_mut().declare(name, layer)?;
+        self.get_quad_trees_mut()
+            .create_quad_tree(layer, bounds, options);
+        Ok(())
+    }
+
+    pub fn get_frame_profile(&self) -> Fetch<FrameProfile> {
+        self.world.read_resource::<FrameProfile>()
+    }
+
+    pub fn get_config(&self) -> EngineConfig {
+        *self.world.read_resource::<EngineConfig>()
+    }
+
+    /// Takes effect from the next `update`.
+    pub fn set_config(&mut self, config: EngineConfig) {
+        self.mark_scene_dirty();
+        *self.world.write_resource::<EngineConfig>() = config;
+    }
+
+    pub fn get_render_quality(&self) -> RenderQuality {
+        *self.world.read_resource::<RenderQuality>()
+    }
+
+    /// Takes effect from the next paint. Shapes already built keep the step counts they were
+    /// built with.
+    pub fn set_render_quality(&mut self, render_quality: RenderQuality) {
+        self.mark_scene_dirty();
+        *self.world.write_resource::<RenderQuality>() = render_quality;
+    }
+
+    pub fn get_runtime_policy(&self) -> RuntimePolicy {
+        *self.world.read_resource::<RuntimePolicy>()
+    }
+
+    pub fn set_runtime_policy(&mut self, runtime_policy: RuntimePolicy) {
+        *self.world.write_resource::<RuntimePolicy>() = runtime_policy;
+    }
+
+    /// Errors in content met during the last `update`, under `RuntimePolicy::Strict`. Cleared at
+    /// the start of every update.
+    pub fn take_runtime_errors(&mut self) -> Vec<String> {
+        std::mem::replace(&mut self.world.write_resource::<RuntimeErrors>().0, vec![])
+    }
+
+    pub fn get_camera(&self) -> Camera {
+        *self.world.read_resource::<Camera>()
+    }
+
+    /// Takes effect from the next paint.
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.mark_scene_dirty();
+        *self.world.write_resource::<Camera>() = camera;
+    }
+
+    /// Converts a position in device pixels on the output, such as a cursor position, into stage
+    /// space by undoing the device pixel ratio and `camera`. Pass `camera.with_parallax` to pick
+    /// in a `Parallax` layer. Hit tests and the `pointer_down` family expect stage space.
+    pub fn screen_to_stage(&self, screen_position: Vector2F, camera: &Camera) -> Vector2F {
+        let device_pixel_ratio = self.get_config().device_pixel_ratio;
+        camera.view_to_stage(screen_position * (1.0 / device_pixel_ratio))
+    }
+
+    /// The inverse of `screen_to_stage`.
+    pub fn stage_to_screen(&self, stage_position: Vector2F, camera: &Camera) -> Vector2F {
+        camera.stage_to_view(stage_position) * self.get_config().device_pixel_ratio
+    }
+
+    /// Converts a stage position into the local space of the container, the space its shapes and
+    /// children are defined in, using its world transform as of the last `update`.
+    pub fn stage_to_container_local(
+        &self,
+        stage_position: Vector2F,
+        container_id: &ContainerId,
+    ) -> Option<Vector2F> {
+        self.get_world_transform(container_id)
+            .map(|world_transform| world_transform.inverse() * stage_position)
+    }
+
+    /// The inverse of `stage_to_container_local`.
+    pub fn container_local_to_stage(
+        &self,
+        local_position: Vector2F,
+        container_id: &ContainerId,
+    ) -> Option<Vector2F> {
+        self.get_world_transform(container_id)
+            .map(|world_transform| world_transform * local_position)
+    }
+
+    pub fn get_stage_area(&self) -> StageArea {
+        *self.world.read_resource::<StageArea>()
+    }
+
+    /// Anchored containers move to match on the next update.
+    pub fn set_stage_area(&mut self, stage_area: StageArea) {
+        self.mark_scene_dirty();
+        *self.world.write_resource::<StageArea>() = stage_area;
+    }
+
+    /// Restarts the engine's `Random` from `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        *self.world.write_resource::<Random>() = Random::new(seed);
+    }
+
+    pub fn get_random_mut(&mut self) -> FetchMut<Random> {
+        self.world.write_resource::<Random>()
+    }
+
+    /// The seed for anything random about `container_id`. See `Random::seed_for`.
+    pub fn get_seed(&self, container_id: &ContainerId) -> u64 {
+        self.world.read_resource::<Random>().seed_for(container_id)
+    }
+
+    pub fn mark_scene_dirty(&mut self) {
+        self.world.write_resource::<SceneDirty>().0 = true;
+    }
+
+    /// True if anything that could change how the scene draws has happened since the last call.
+    /// Used to skip drawing frames that would look the same as the last one.
+    pub fn take_scene_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.world.write_resource::<SceneDirty>().0, false)
+    }
+
+    pub fn get_debug_flags(&self) -> DebugFlags {
+        *self.world.read_resource::<DebugFlags>()
+    }
+
+    pub fn set_debug_flags(&mut self, debug_flags: DebugFlags) {
+        self.mark_scene_dirty();
+        *self.world.write_resource::<DebugFlags>() = debug_flags;
+    }
+
+    /// Every container created, removed, reparented or given a new display, and library item
+    /// replaced, since the last poll, in the order they happened. Changes made through actions show up once the frame that
+    /// processes them has run.
+    pub fn poll_scene_events(&mut self) -> Vec<SceneEvent> {
+        self.world.write_resource::<SceneEventQueue>().drain()
+    }
+
+    pub fn get_state_machines(&self) -> Fetch<StateMachines> {
+        self.world.read_resource::<StateMachines>()
+    }
+
+    pub fn get_state_machines_mut(&mut self) -> FetchMut<StateMachines> {
+        self.world.write_resource::<StateMachines>()
+    }
+
+    /// Fails if the machine names a state it doesn't define, see `StateMachines::add`.
+    pub fn add_state_machine(&mut self, name: String, machine: StateMachine) -> Result<(), String> {
+        self.get_state_machines_mut().add(name, machine)
+    }
+
+    pub fn set_state_flag(&mut self, flag: &str, value: bool) {
+        self.get_state_machines_mut().set_flag(flag, value);
+    }
+
+    /// Fires an event that state machine transitions can react to during the next `update`.
+    pub fn fire_state_event(&mut self, event: &str) {
+        self.get_state_machines_mut().fire_event(event);
+    }
+
+    /// Moves a state machine to `state` on the next `update`, regardless of its transitions.
+    pub fn force_transition(
+        &mut self,
+        machine: &str,
+        state: &str,
+        blend: Option<(Easing, u32)>,
+    ) -> Result<(), String> {
+        match self.get_state_machines_mut().get_mut(machine) {
+            Some(state_machine) => state_machine.force_transition(state, blend),
+            None => Err(format!("No state machine named {}", machine)),
+        }
+    }
+
+    pub fn get_current_state(&self, machine: &str) -> Option<String> {
+        self.get_state_machines()
+            .get(machine)
+            .map(|state_machine| state_machine.current().to_string())
+    }
+
+    /// Gives keyboard focus to a `Focusable` container, or clears it with `None`.
+    pub fn set_focus(&mut self, container_id: Option<ContainerId>) -> Result<(), String> {
+        if let Some(container_id) = &container_id {
+            let entity = self
+                .get_entity(container_id)
+                .ok_or_else(|| format!("Container {:?} does not exist", container_id))?;
+            if !self.world.read_storage::<Focusable>().contains(entity) {
+                return Err(format!("Container {:?} is not focusable", container_id));
+            }
+        }
+        let previous = self.get_focus();
+        self.world.write_resource::<FocusState>().focused = container_id;
+        if previous != container_id {
+            self.world
+                .write_resource::<SceneEventQueue>()
+                .push(SceneEvent::FocusChanged(container_id));
+        }
+        Ok(())
+    }
+
+    /// The focused container. Removing it clears the focus.
+    pub fn get_focus(&self) -> Option<ContainerId> {
+        self.world
+            .read_resource::<FocusState>()
+            .focused
+            .filter(|container_id| self.get_entity(container_id).is_some())
+    }
+
+    /// Moves focus to the next `Focusable` container in tab order, or the previous one if
+    /// `reverse` is set, wrapping around at the ends. Returns the newly focused container.
+    pub fn focus_next(&mut self, reverse: bool) -> Option<ContainerId> {
+        let tab_order = self.get_tab_order();
+        let last = tab_order.len().checked_sub(1)?;
+        let current = self.get_focus().and_then(|focused| {
+            tab_order
+                .iter()
+                .position(|container_id| *container_id == focused)
+        });
+        let next = match (current, reverse) {
+            (Some(index), false) if index == last => 0,
+            (Some(index), false) => index + 1,
+            (Some(0), true) => last,
+            (Some(index), true) => index - 1,
+            (None, false) => 0,
+            (None, true) => last,
+        };
+        let container_id = tab_order[next];
+        self.set_focus(Some(container_id)).ok()?;
+        Some(container_id)
+    }
+
+    /// Every `Focusable` container, in the order `focus_next` visits them.
+    pub fn get_tab_order(&self) -> Vec<ContainerId> {
+        let scene_graph = self.get_scene_graph();
+        let container_mapping = self.get_container_mapping();
+        let focusable_storage = self.world.read_storage::<Focusable>();
+        let mut focusables = vec![];
+        // Depth first, so ties in tab index go in the order containers are listed in the outliner
+        let mut stack = vec![*scene_graph.root()];
+        while let Some(entity) = stack.pop() {
+            if let (Some(focusable), Some(container_id)) = (
+                focusable_storage.get(entity),
+                container_mapping.get_container(&entity),
+            ) {
+                focusables.push((focusable.tab_index, *container_id));
+            }
+            if let Some(children) = scene_graph.get_children(&entity) {
+                stack.extend(children.iter().rev().copied());
+            }
+        }
+        // Stable, so ties keep scene graph order
+        focusables.sort_by_key(|(tab_index, _)| *tab_index);
+        focusables
+            .into_iter()
+            .map(|(_, container_id)| container_id)
+            .collect()
+    }
+
+    /// Routes a key press to the focused container's `KeyBindings`, then its parents', and
+    /// finally the root's. Returns false if no binding matched, so the host can handle the key
+    /// itself. Hosts should pass key repeats through as more presses.
+    pub fn key_down(&mut self, key: &str, modifiers: KeyModifiers) -> bool {
+        self.route_key(key, modifiers, KeyPhase::Down)
+    }
+
+    /// Like `key_down`, for bindings made with `KeyBinding::on_release`.
+    pub fn key_up(&mut self, key: &str, modifiers: KeyModifiers) -> bool {
+        self.route_key(key, modifiers, KeyPhase::Up)
+    }
+
+    fn route_key(&mut self, key: &str, modifiers: KeyModifiers, phase: KeyPhase) -> bool {
+        let target = self.get_focus().unwrap_or(self.root_container_id);
+        let action = {
+            let key_bindings_storage = self.world.read_storage::<KeyBindings>();
+            self.get_self_and_parents(&target)
+                .into_iter()
+                .find_map(|container_id| {
+                    let entity = self.get_entity(&container_id)?;
+                    key_bindings_storage
+                        .get(entity)?
+                        .0
+                        .iter()
+                        .find(|binding| binding.matches(key, modifiers, phase))
+                        .map(|binding| binding.action.clone())
+                })
+        };
+        match action {
+            Some(KeyBindingAction::FireEvent(event)) => self.fire_state_event(&event),
+            Some(KeyBindingAction::SetFlag(flag, value)) => self.set_state_flag(&flag, value),
+            Some(KeyBindingAction::Focus(container_id)) => {
+                // TODO: report bindings to missing or unfocusable containers to the host
+                let _ = self.set_focus(Some(container_id));
+            }
+            Some(KeyBindingAction::FocusNext) => {
+                self.focus_next(false);
+            }
+            Some(KeyBindingAction::FocusPrevious) => {
+                self.focus_next(true);
+            }
+            None => return false,
+        }
+        true
+    }
+
     pub fn get_root_container_id(&self) -> ContainerId {
         let scene_graph = self.get_scene_graph();
         let container_mapping = self.get_container_mapping();
         *container_mapping.get_container(scene_graph.root()).unwrap()
     }
 
+    fn get_entity(&self, container_id: &ContainerId) -> Option<Entity> {
+        self.get_container_mapping()
+            .get_entity(container_id)
+            .copied()
+    }
+
+    /// The container's transform after all of its parents have been applied, as of the last
+    /// `update`.
+    pub fn get_world_transform(&self, container_id: &ContainerId) -> Option<Transform2F> {
+        let entity = self.get_entity(container_id)?;
+        self.world
+            .read_storage::<WorldTransform>()
+            .get(entity)
+            .map(|transform| transform.0)
+    }
+
+    pub fn get_view_rect(&self, container_id: &ContainerId) -> Option<RectF> {
+        let entity = self.get_entity(container_id)?;
+        self.world
+            .read_storage::<ViewRect>()
+            .get(entity)
+            .map(|view_rect| view_rect.0)
+    }
+
+    /// The coloring set on the container, overriding its shape's own color. None if it shows
+    /// the shape's color.
+    pub fn get_coloring(&self, container_id: &ContainerId) -> Option<Coloring> {
+        let entity = self.get_entity(container_id)?;
+        self.world.read_storage::<Coloring>().get(entity).cloned()
+    }
+
+    /// The container's transform relative to its parent.
+    pub fn get_local_transform(&self, container_id: &ContainerId) -> Option<Transform2F> {
+        let entity = self.get_entity(container_id)?;
+        self.world
+            .read_storage::<LocalTransform>()
+            .get(entity)
+            .map(|transform| transform.0)
+    }
+
+    /// The container's bounds in world space, as of the last `update`. `None` if the container
+    /// does not exist or has no bounds.
+    pub fn get_bounds(&self, container_id: &ContainerId) -> Option<RectF> {
+        let entity = self.get_entity(container_id)?;
+        self.world
+            .read_storage::<Bounds>()
+            .get(entity)
+            .map(|bounds| bounds.bounds)
+    }
+
+    /// `None` for the root container, or if the container does not exist.
+    pub fn get_parent(&self, container_id: &ContainerId) -> Option<ContainerId> {
+        let entity = self.get_entity(container_id)?;
+        let scene_graph = self.get_scene_graph();
+        let parent = scene_graph.get_parent(&entity)?;
+        self.get_container_mapping().get_container(parent).copied()
+    }
+
+    /// The container followed by each of its parents, ending with the root. Note that
+    /// `get_parent` of the root is the root itself.
+    pub fn get_self_and_parents(&self, container_id: &ContainerId) -> Vec<ContainerId> {
+        let root = self.root_container_id;
+        iter::successors(Some(*container_id), |container_id| {
+            if *container_id == root {
+                None
+            } else {
+                self.get_parent(container_id)
+            }
+        })
+        .collect()
+    }
+
+    /// The library item the container displays, and whether it is a shape or a texture.
+    pub fn get_display(&self, container_id: &ContainerId) -> Option<(LibraryId, DisplayKind)> {
+        let entity = self.get_entity(container_id)?;
+        self.world
+            .read_storage::<Display>()
+            .get(entity)
+            .map(|display| (display.0, display.1))
+    }
+
+    /// The direct children of the container, in no particular order.
+    pub fn get_children(&self, container_id: &ContainerId) -> Option<Vec<ContainerId>> {
+        let entity = self.get_entity(container_id)?;
+        let container_mapping = self.get_container_mapping();
+        self.get_scene_graph()
+            .get_children(&entity)
+            .map(|children| {
+                children
+                    .iter()
+                    .filter_map(|child| container_mapping.get_container(child).copied())
+                    .collect()
+            })
+    }
+
     pub fn create_container(&mut self, definition: &ContainerCreationDefintition) {
         let mut container_creation_queue = self.world.write_resource::<ContainerCreationQueue>();
         container_creation_queue.enqueue(definition.clone());
@@ -187,17 +874,122 @@ impl<'a, 'b> Engine<'a, 'b> {
         container_update_queue.enqueue(definition.clone());
     }
 
+    /// Sets the container's transform at the start of the next `update`, skipping any tweening.
+    pub fn set_transform(&mut self, container_id: &ContainerId, srt: ScaleRotationTranslation) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::SetTransform(srt)],
+        ));
+    }
+
+    /// Sets the container moving at `velocity` units per second, in its parent's space. Useful
+    /// for throwing a container at the end of a drag.
+    pub fn set_velocity(&mut self, container_id: &ContainerId, velocity: Vector2F) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::Velocity(velocity)],
+        ));
+    }
+
+    pub fn set_morph_index(&mut self, container_id: &ContainerId, morph_index: f32) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::SetMorphIndex(morph_index)],
+        ));
+    }
+
+    /// Sets the morph index of each shape in the container's group display. See `MorphIndices`.
+    pub fn set_morph_indices(&mut self, container_id: &ContainerId, morph_indices: Vec<f32>) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::SetMorphIndices(morph_indices)],
+        ));
+    }
+
+    /// Draws part of the container's paths. See `PathTrim`.
+    pub fn set_path_trim(&mut self, container_id: &ContainerId, path_trim: PathTrim) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::SetPathTrim(path_trim)],
+        ));
+    }
+
+    pub fn set_coloring(&mut self, container_id: &ContainerId, coloring: Coloring) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::SetColoring(coloring)],
+        ));
+    }
+
+    /// Recolors the container and its children. See `ColorTransform`.
+    pub fn set_color_transform(
+        &mut self,
+        container_id: &ContainerId,
+        color_transform: ColorTransform,
+    ) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::SetColorTransform(color_transform)],
+        ));
+    }
+
+    pub fn set_view_rect(&mut self, container_id: &ContainerId, view_rect: RectF) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::SetViewRect(RectPoints::from_rect(
+                &view_rect,
+            ))],
+        ));
+    }
+
+    /// Scales how fast tweens on the container and its children advance. 0.0 pauses them.
+    pub fn set_time_scale(&mut self, container_id: &ContainerId, time_scale: f32) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::TimeScale(time_scale)],
+        ));
+    }
+
+    /// The time scale set on this container alone, ignoring its parents.
+    pub fn get_time_scale(&self, container_id: &ContainerId) -> Option<f32> {
+        let entity = self.get_entity(container_id)?;
+        Some(
+            self.world
+                .read_storage::<TimeScale>()
+                .get(entity)
+                .copied()
+                .unwrap_or_default()
+                .0,
+        )
+    }
+
+    pub fn set_pixel_snap(&mut self, container_id: &ContainerId, pixel_snap: bool) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::PixelSnap(pixel_snap)],
+        ));
+    }
+
     pub fn remove_container(&mut self, container_id: &ContainerId) -> Result<(), SpecsError> {
+        self.mark_scene_dirty();
         let mut scene_graph = self.world.write_resource::<SceneGraph>();
         let mut container_mapping = self.world.write_resource::<ContainerMapping>();
         let mut quad_trees = self.world.write_resource::<QuadTrees>();
-        let entities = self.world.entities_mut();
+        let mut library = self.world.write_resource::<Library>();
+        let mut scene_events = self.world.write_resource::<SceneEventQueue>();
+        // Reading storage shares the entities resource, so entities can't be fetched mutably here
+        let display_storage = self.world.read_storage::<Display>();
+        let entities = self.world.entities();
         let entity = container_mapping.get_entity(container_id).copied();
         if let Some(entity) = entity {
+            if let Some(Display(library_id, _)) = display_storage.get(entity) {
+                library.remove_usage(library_id, container_id);
+            }
             container_mapping.remove_container(container_id);
             scene_graph.remove_entity(&entity);
             quad_trees.remove_all_layers(entity);
             entities.delete(entity)?;
+            scene_events.push(SceneEvent::Removed(*container_id));
         }
         Ok(())
     }
@@ -206,12 +998,22 @@ impl<'a, 'b> Engine<'a, 'b> {
         &mut self,
         container_id: &ContainerId,
     ) -> Result<(), SpecsError> {
+        self.mark_scene_dirty();
         let mut scene_graph = self.world.write_resource::<SceneGraph>();
         let mut container_mapping = self.world.write_resource::<ContainerMapping>();
-        let entities = self.world.entities_mut();
         let mut quad_trees = self.world.write_resource::<QuadTrees>();
+        let mut library = self.world.write_resource::<Library>();
+        let mut scene_events = self.world.write_resource::<SceneEventQueue>();
+        let display_storage = self.world.read_storage::<Display>();
+        let entities = self.world.entities();
         if let Some(entity) = container_mapping.get_entity(container_id) {
             for entity in scene_graph.remove_entity_and_children(entity).into_iter() {
+                if let Some(removed_id) = container_mapping.get_container(&entity) {
+                    if let Some(Display(library_id, _)) = display_storage.get(entity) {
+                        library.remove_usage(library_id, removed_id);
+                    }
+                    scene_events.push(SceneEvent::Removed(*removed_id));
+                }
                 container_mapping.remove_entity(&entity);
                 quad_trees.remove_all_layers(entity);
                 entities.delete(entity)?;
@@ -220,6 +1022,160 @@ impl<'a, 'b> Engine<'a, 'b> {
         Ok(())
     }
 
+    /// Library items that no container displays, including containers still waiting to be
+    /// created or updated.
+    pub fn unused_library_items(&self) -> Vec<LibraryId> {
+        let pending = self.pending_library_items();
+        self.get_library()
+            .unused_items()
+            .into_iter()
+            .filter(|id| !pending.contains(id))
+            .collect()
+    }
+
+    /// Swaps a library shape for a new version, for assets edited outside of the scene. Every
+    /// container showing it, or a shape that references it, has its bounds recomputed, and a
+    /// `SceneEvent::LibraryItemReplaced` is queued for each changed shape.
+    pub fn replace_shape(&mut self, id: LibraryId, shape: Shape) -> Result<(), String> {
+        let changed = self.get_library_mut().replace_shape(id, shape)?;
+        self.library_items_replaced(changed);
+        Ok(())
+    }
+
+    /// Swaps a library texture for a new version. See `replace_shape`.
+    pub fn replace_texture(&mut self, id: LibraryId, pattern: Pattern) -> Result<(), String> {
+        self.get_library_mut().replace_texture(id, pattern)?;
+        self.library_items_replaced(vec![id]);
+        Ok(())
+    }
+
+    fn library_items_replaced(&mut self, changed: Vec<LibraryId>) {
+        self.mark_scene_dirty();
+        let usages = {
+            let library = self.get_library();
+            changed
+                .iter()
+                .flat_map(|id| library.usages(id))
+                .collect::<Vec<ContainerId>>()
+        };
+        for container_id in usages {
+            self.refresh_bounds(&container_id);
+        }
+        let mut scene_events = self.world.write_resource::<SceneEventQueue>();
+        for id in changed {
+            scene_events.push(SceneEvent::LibraryItemReplaced(id));
+        }
+    }
+
+    /// Recolors every shape drawn with this palette entry, from the next paint.
+    pub fn set_palette_entry(&mut self, name: &str, color: LinSrgba) {
+        self.get_library_mut()
+            .set_palette_entry(name.to_string(), color);
+    }
+
+    /// Drops every unused library item, returning the ids that were unloaded.
+    pub fn unload_unused_library_items(&mut self) -> Vec<LibraryId> {
+        let unused = self.unused_library_items();
+        let mut library = self.get_library_mut();
+        for id in unused.iter() {
+            library.remove_shape(id);
+            library.remove_texture(id);
+        }
+        unused
+    }
+
+    fn pending_library_items(&self) -> HashSet<LibraryId> {
+        let creation_queue = self.world.read_resource::<ContainerCreationQueue>();
+        let update_queue = self.world.read_resource::<ContainerUpdateQueue>();
+        let created = creation_queue
+            .iter()
+            .flat_map(|definition| definition.properties().iter())
+            .filter_map(|property| match property {
+                ContainerCreationProperty::Display(id) => Some(*id),
+                _ => None,
+            });
+        let updated = update_queue
+            .iter()
+            .flat_map(|definition| definition.properties().iter())
+            .filter_map(|property| match property {
+                ContainerUpdateProperty::Display(id) => Some(*id),
+                _ => None,
+            });
+        created.chain(updated).collect()
+    }
+
+    /// Summarizes what the library and scene are holding onto. Sizes are estimates.
+    pub fn memory_report(&self) -> MemoryReport {
+        let library = self.get_library();
+        let metadata_name = |id: &LibraryId| {
+            library
+                .get_metadata(id)
+                .map(|metadata| metadata.name.clone())
+        };
+        let shapes = library.shapes().map(|(id, shape)| LibraryItemReport {
+            id: *id,
+            name: metadata_name(id),
+            bytes: shape.approximate_size(),
+            edges: shape.len(),
+            size: (0, 0),
+            usages: library.usages(id).len(),
+            references: Arc::strong_count(shape),
+        });
+        let textures = library.textures().map(|(id, pattern)| {
+            let size = pattern.size();
+            LibraryItemReport {
+                id: *id,
+                name: metadata_name(id),
+                bytes: texture_bytes(pattern),
+                edges: 0,
+                size: (size.x(), size.y()),
+                usages: library.usages(id).len(),
+                references: Arc::strong_count(pattern),
+            }
+        });
+        let mut library_items = shapes.chain(textures).collect::<Vec<LibraryItemReport>>();
+        library_items.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+        let total_edges = library_items.iter().map(|item| item.edges).sum();
+        let total_shape_bytes = library
+            .shapes()
+            .map(|(_, shape)| shape.approximate_size())
+            .sum();
+        let total_texture_bytes = library
+            .textures()
+            .map(|(_, pattern)| texture_bytes(pattern))
+            .sum();
+
+        fn count<T: Component>(world: &World) -> usize {
+            (&world.read_storage::<T>()).join().count()
+        }
+        let component_counts = vec![
+            ("LocalTransform", count::<LocalTransform>(&self.world)),
+            ("WorldTransform", count::<WorldTransform>(&self.world)),
+            ("Bounds", count::<Bounds>(&self.world)),
+            ("Morph", count::<Morph>(&self.world)),
+            ("Order", count::<Order>(&self.world)),
+            ("Display", count::<Display>(&self.world)),
+            ("Tweens", count::<Tweens>(&self.world)),
+            ("TimeScale", count::<TimeScale>(&self.world)),
+            ("Layer", count::<Layer>(&self.world)),
+            ("Coloring", count::<Coloring>(&self.world)),
+            ("ViewRect", count::<ViewRect>(&self.world)),
+        ]
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect();
+
+        MemoryReport {
+            library_items,
+            total_edges,
+            total_shape_bytes,
+            total_texture_bytes,
+            entity_count: (&self.world.entities()).join().count(),
+            component_counts,
+            quad_trees: self.get_quad_trees().layer_sizes(),
+        }
+    }
+
     pub fn refresh_bounds(&mut self, container_id: &ContainerId) {
         {
             let read_storage = self.world.read_resource::<ContainerMapping>();
@@ -232,18 +1188,26 @@ impl<'a, 'b> Engine<'a, 'b> {
                 let display_storage = self.world.read_storage::<Display>();
                 let view_rect_storage = self.world.read_storage::<ViewRect>();
                 let morph_storage = self.world.read_storage::<Morph>();
+                let morph_indices_storage = self.world.read_storage::<MorphIndices>();
                 let library = self.world.read_resource::<Library>();
+                let scene_graph = self.world.read_resource::<SceneGraph>();
                 if let (Some(bounds), Some(transform)) =
                     (bounds_storage.get(entity), transform_storage.get(entity))
                 {
-                    Some(recompute_bounds(
+                    recompute_bounds(
                         &bounds.source,
                         transform.0,
                         display_storage.get(entity),
                         view_rect_storage.get(entity),
                         morph_storage.get(entity),
+                        morph_indices_storage.get(entity),
                         &*library,
-                    ))
+                    )
+                    .or_else(|| {
+                        union_of_children_bounds(&entity, &scene_graph, |child| {
+                            bounds_storage.get(child).map(|bounds| bounds.bounds)
+                        })
+                    })
                 } else {
                     None
                 }
@@ -257,18 +1221,32 @@ impl<'a, 'b> Engine<'a, 'b> {
         });
     }
 
+    /// For `QuadTreeQuery::Ray` the handles are the points where the ray crosses the shape's
+    /// edges rather than vertices, with their distance along the ray as the separation. Results
+    /// are ordered by distance along the ray, and vector shapes the ray misses are dropped.
     pub fn spatial_query(&self, query: &QuadTreeQuery) -> Vec<SelectionHandle> {
         let transform_storage = self.world.read_storage::<WorldTransform>();
         let morph_storage = self.world.read_storage::<Morph>();
         let display_storage = self.world.read_storage::<Display>();
         let container_mapping = self.world.read_resource::<ContainerMapping>();
         let library = self.world.read_resource::<Library>();
-        self.world
+        let selections = self
+            .world
             .read_resource::<QuadTrees>()
             .query(query)
             .map_or_else(
                 || vec![],
-                |entities| self.depth_sort_bounding_boxes(entities),
+                |entities| match query {
+                    // Already sorted by distance, which is the point of asking
+                    QuadTreeQuery::Nearest(..) => entities,
+                    QuadTreeQuery::Point(_, point) => self.depth_sort_bounding_boxes(
+                        entities
+                            .into_iter()
+                            .filter(|(entity, _)| self.hit_shape_contains(*entity, *point))
+                            .collect(),
+                    ),
+                    _ => self.depth_sort_bounding_boxes(entities),
+                },
             )
             .into_iter()
             .map(|(entity, bounds)| {
@@ -277,41 +1255,422 @@ impl<'a, 'b> Engine<'a, 'b> {
                 let morph = morph_storage.get(entity).copied().unwrap_or_default().0;
                 let (shape_id, edge_list) = match display_storage.get(entity) {
                     Some(Display(shape_id, DisplayKind::Vector)) => library
-                        .get_shape(shape_id)
+                        .get_resolved_shape(shape_id)
                         .and_then(|shape| Some((Some(*shape_id), shape.edge_list(morph))))
                         .unwrap_or((Some(*shape_id), vec![])),
                     _ => (None, vec![]),
                 };
-                let handles = edge_list
-                    .into_iter()
-                    .enumerate()
-                    .flat_map(|(index, edge)| match query {
-                        QuadTreeQuery::Point(_, point) => edge
-                            .query_disk(point, 10.0, &transform)
-                            .map(|(vertex_index, distance, vertex)| {
-                                VertexHandle::new(vertex, index, vertex_index, distance)
+                let handles = match query {
+                    QuadTreeQuery::Ray(_, origin, direction) => {
+                        let inverse = transform.inverse();
+                        Edge::query_ray(&edge_list, origin, direction, &transform)
+                            .into_iter()
+                            .map(|(index, distance, hit)| {
+                                VertexHandle::new(inverse * hit, index, 0, distance)
                             })
-                            .collect::<Vec<VertexHandle>>(),
-                        QuadTreeQuery::Disk(_, point, radius) => edge
-                            .query_disk(point, *radius, &transform)
-                            .map(|(vertex_index, distance, vertex)| {
-                                VertexHandle::new(vertex, index, vertex_index, distance)
-                            })
-                            .collect::<Vec<VertexHandle>>(),
-                        QuadTreeQuery::Rect(_, rect) => edge
-                            .query_rect(rect, &transform)
-                            .map(|(vertex_index, vertex)| {
-                                VertexHandle::new(vertex, index, vertex_index, 0.0)
-                            })
-                            .collect::<Vec<VertexHandle>>(),
-                        QuadTreeQuery::Ray(layer, origin, direction) => {
-                            //Todo Not used yet and is a pain, so this is future Lily's problem
-                            todo!()
-                        }
-                    })
-                    .collect();
+                            .collect()
+                    }
+                    _ => edge_list
+                        .into_iter()
+                        .enumerate()
+                        .flat_map(|(index, edge)| match query {
+                            QuadTreeQuery::Point(_, point) => edge
+                                .query_disk(point, 10.0, &transform)
+                                .map(|(vertex_index, distance, vertex)| {
+                                    VertexHandle::new(vertex, index, vertex_index, distance)
+                                })
+                                .collect::<Vec<VertexHandle>>(),
+                            QuadTreeQuery::Disk(_, point, radius) => edge
+                                .query_disk(point, *radius, &transform)
+                                .map(|(vertex_index, distance, vertex)| {
+                                    VertexHandle::new(vertex, index, vertex_index, distance)
+                                })
+                                .collect::<Vec<VertexHandle>>(),
+                            QuadTreeQuery::Rect(_, rect) => edge
+                                .query_rect(rect, &transform)
+                                .map(|(vertex_index, vertex)| {
+                                    VertexHandle::new(vertex, index, vertex_index, 0.0)
+                                })
+                                .collect::<Vec<VertexHandle>>(),
+                            QuadTreeQuery::Nearest(_, point, _) => edge
+                                .query_disk(point, std::f32::MAX, &transform)
+                                .map(|(vertex_index, distance, vertex)| {
+                                    VertexHandle::new(vertex, index, vertex_index, distance)
+                                })
+                                .collect::<Vec<VertexHandle>>(),
+                            QuadTreeQuery::Ray(..) => unreachable!(),
+                        })
+                        .collect(),
+                };
                 SelectionHandle::new(container_id, shape_id, transform, bounds, morph, handles)
+            });
+        match query {
+            QuadTreeQuery::Ray(_, origin, direction) => {
+                let mut hits = selections
+                    .filter_map(|selection| {
+                        let distance = match selection.shape_id() {
+                            Some(_) => selection.min_vertex()?.separation(),
+                            // Rasters have no edges, so their bounds are as precise as it gets
+                            None => ray_aabb_distance(*origin, *direction, *selection.bounds())?,
+                        };
+                        Some((distance, selection))
+                    })
+                    .collect::<Vec<(f32, SelectionHandle)>>();
+                hits.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                hits.into_iter().map(|(_, selection)| selection).collect()
+            }
+            _ => selections.collect(),
+        }
+    }
+
+    /// The containers in `layer` under `point`, front to back. Containers with a `HitShape` are
+    /// only hit inside it, others anywhere inside their bounds.
+    pub fn hit_test(&self, layer: &QuadTreeLayer, point: Vector2F) -> Vec<ContainerId> {
+        let entities = self
+            .world
+            .read_resource::<QuadTrees>()
+            .query(&QuadTreeQuery::Point(*layer, point))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(entity, _)| {
+                self.hit_shape_contains(*entity, point) && self.clips_contain(*entity, point)
+            })
+            .collect();
+        let container_mapping = self.world.read_resource::<ContainerMapping>();
+        self.depth_sort_bounding_boxes(entities)
+            .into_iter()
+            .filter_map(|(entity, _)| container_mapping.get_container(&entity).copied())
+            .collect()
+    }
+
+    /// Starts dragging the front most container in `layer` under `point` that is `Draggable`, or
+    /// has a `Draggable` parent. Returns the container picked up. Changes are applied on the next
+    /// `update`, like any other container update. If a `ScrollView` is closer to the container
+    /// than any `Draggable`, the pointer scrolls it instead.
+    pub fn pointer_down(&mut self, layer: &QuadTreeLayer, point: Vector2F) -> Option<ContainerId> {
+        let (container_id, scrolls) = self.hit_test(layer, point).into_iter().find_map(|hit| {
+            let draggable_storage = self.world.read_storage::<Draggable>();
+            let scroll_view_storage = self.world.read_storage::<ScrollView>();
+            self.get_self_and_parents(&hit)
+                .into_iter()
+                .find_map(|container_id| {
+                    let entity = self.get_entity(&container_id)?;
+                    if draggable_storage.contains(entity) {
+                        Some((container_id, false))
+                    } else if scroll_view_storage.contains(entity) {
+                        Some((container_id, true))
+                    } else {
+                        None
+                    }
+                })
+        })?;
+        if scrolls {
+            // Catching a flung view stops it
+            self.fling(&container_id, Vector2F::zero());
+            let last_point = self.to_local_space(&container_id, point);
+            self.world.write_resource::<DragState>().scrolling = Some(ActiveScroll {
+                container_id,
+                last_point,
+                moved: Vector2F::zero(),
+                velocity: Vector2F::zero(),
+            });
+            return Some(container_id);
+        }
+        let local_transform = self.get_local_transform(&container_id).unwrap_or_default();
+        let start = ScaleRotationTranslation::from_transform(local_transform);
+        self.world.write_resource::<DragState>().active = Some(ActiveDrag {
+            container_id,
+            start,
+            grab_offset: self.to_parent_space(&container_id, point) - start.translation,
+            translation: start.translation,
+        });
+        self.world
+            .write_resource::<SceneEventQueue>()
+            .push(SceneEvent::DragStarted(container_id));
+        Some(container_id)
+    }
+
+    /// Moves the dragged container, if there is one, so it stays under `point`.
+    pub fn pointer_move(&mut self, point: Vector2F) {
+        let scrolling = self.world.read_resource::<DragState>().scrolling;
+        if let Some(active) = scrolling {
+            let local_point = self.to_local_space(&active.container_id, point);
+            // Dragging the content up scrolls down
+            let moved = self.scroll_by(&active.container_id, active.last_point - local_point);
+            if let Some(active) = &mut self.world.write_resource::<DragState>().scrolling {
+                active.last_point = local_point;
+                active.moved = active.moved + moved;
+            }
+            return;
+        }
+        let drag = match self.world.read_resource::<DragState>().active {
+            Some(drag) => drag,
+            None => return,
+        };
+        let draggable = match self.get_draggable(&drag.container_id) {
+            Some(draggable) => draggable,
+            // The container was removed mid drag, or stopped being draggable
+            None => return,
+        };
+        let translation = draggable.constrain(
+            drag.start.translation,
+            self.to_parent_space(&drag.container_id, point) - drag.grab_offset,
+        );
+        if translation == drag.translation {
+            return;
+        }
+        if let Some(active) = &mut self.world.write_resource::<DragState>().active {
+            active.translation = translation;
+        }
+        let mut srt = ScaleRotationTranslation::from_transform(
+            self.get_local_transform(&drag.container_id)
+                .unwrap_or_default(),
+        );
+        srt.translation = translation;
+        self.set_transform(&drag.container_id, srt);
+        self.world
+            .write_resource::<SceneEventQueue>()
+            .push(SceneEvent::DragMoved {
+                container_id: drag.container_id,
+                translation,
+            });
+    }
+
+    /// Drops the dragged container, if there is one, at `point`. Snaps it back if its
+    /// `Draggable` asks for that.
+    pub fn pointer_up(&mut self, point: Vector2F) {
+        self.pointer_move(point);
+        let scrolling = self.world.write_resource::<DragState>().scrolling.take();
+        if let Some(active) = scrolling {
+            self.fling(&active.container_id, active.velocity);
+            return;
+        }
+        let drag = match self.world.write_resource::<DragState>().active.take() {
+            Some(drag) => drag,
+            None => return,
+        };
+        self.world
+            .write_resource::<SceneEventQueue>()
+            .push(SceneEvent::DragEnded {
+                container_id: drag.container_id,
+                translation: drag.translation,
+            });
+        match self
+            .get_draggable(&drag.container_id)
+            .and_then(|draggable| draggable.snap_back)
+        {
+            Some((_, 0)) => self.set_transform(&drag.container_id, drag.start),
+            Some((easing, duration_frames)) => {
+                self.update_container(&ContainerUpdateDefintition::new(
+                    drag.container_id,
+                    vec![ContainerUpdateProperty::Transform(
+                        drag.start,
+                        easing,
+                        duration_frames,
+                    )],
+                ))
+            }
+            None => (),
+        }
+    }
+
+    /// The container being dragged, if any.
+    pub fn get_dragged(&self) -> Option<ContainerId> {
+        self.world
+            .read_resource::<DragState>()
+            .active
+            .map(|drag| drag.container_id)
+    }
+
+    pub fn get_draggable(&self, container_id: &ContainerId) -> Option<Draggable> {
+        let entity = self.get_entity(container_id)?;
+        self.world.read_storage::<Draggable>().get(entity).copied()
+    }
+
+    /// Scrolls the inner most `ScrollView` under `point` in `layer` that can still move by
+    /// `delta`, given in the scroll view's own units. For mouse wheels and trackpads. Returns the
+    /// container scrolled.
+    pub fn scroll(
+        &mut self,
+        layer: &QuadTreeLayer,
+        point: Vector2F,
+        delta: Vector2F,
+    ) -> Option<ContainerId> {
+        let hit = self.hit_test(layer, point).into_iter().next()?;
+        let scroll_views = self
+            .get_self_and_parents(&hit)
+            .into_iter()
+            .filter(|container_id| self.get_scroll_view(container_id).is_some())
+            .collect::<Vec<ContainerId>>();
+        scroll_views.into_iter().find(|container_id| {
+            self.fling(container_id, Vector2F::zero());
+            self.scroll_by(container_id, delta) != Vector2F::zero()
+        })
+    }
+
+    /// Jumps the scroll view to `offset` on the next `update`, stopping any fling.
+    pub fn scroll_to(&mut self, container_id: &ContainerId, offset: Vector2F) {
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::ScrollTo(offset)],
+        ));
+    }
+
+    pub fn get_scroll_view(&self, container_id: &ContainerId) -> Option<ScrollView> {
+        let entity = self.get_entity(container_id)?;
+        self.world.read_storage::<ScrollView>().get(entity).copied()
+    }
+
+    pub fn get_anchor(&self, container_id: &ContainerId) -> Option<Anchor> {
+        let entity = self.get_entity(container_id)?;
+        self.world.read_storage::<Anchor>().get(entity).copied()
+    }
+
+    pub fn get_path_trim(&self, container_id: &ContainerId) -> Option<PathTrim> {
+        let entity = self.get_entity(container_id)?;
+        self.world.read_storage::<PathTrim>().get(entity).copied()
+    }
+
+    // Returns how far the offset actually moved
+    fn scroll_by(&mut self, container_id: &ContainerId, delta: Vector2F) -> Vector2F {
+        let entity = match self.get_entity(container_id) {
+            Some(entity) => entity,
+            None => return Vector2F::zero(),
+        };
+        let moved = match self.world.write_storage::<ScrollView>().get_mut(entity) {
+            Some(scroll_view) => {
+                let start = scroll_view.offset;
+                scroll_view.scroll_by(delta);
+                scroll_view.offset - start
+            }
+            None => Vector2F::zero(),
+        };
+        if moved != Vector2F::zero() {
+            self.mark_scene_dirty();
+        }
+        moved
+    }
+
+    fn fling(&mut self, container_id: &ContainerId, velocity: Vector2F) {
+        let entity = match self.get_entity(container_id) {
+            Some(entity) => entity,
+            None => return,
+        };
+        let mut scroll_view_storage = self.world.write_storage::<ScrollView>();
+        // Stopping a view that is already still shouldn't flag it as modified
+        let flung = match scroll_view_storage.get(entity) {
+            Some(scroll_view) => scroll_view.is_flung(),
+            None => return,
+        };
+        if flung || velocity != Vector2F::zero() {
+            if let Some(scroll_view) = scroll_view_storage.get_mut(entity) {
+                scroll_view.fling(velocity);
+            }
+        }
+    }
+
+    // False if `point` is outside the clip rect of any scroll view the entity is inside of
+    fn clips_contain(&self, entity: Entity, point: Vector2F) -> bool {
+        let scroll_view_storage = self.world.read_storage::<ScrollView>();
+        let transform_storage = self.world.read_storage::<WorldTransform>();
+        self.get_scene_graph()
+            .get_parent_iter(&entity)
+            .all(|parent| match scroll_view_storage.get(*parent) {
+                Some(scroll_view) => {
+                    let transform = transform_storage
+                        .get(*parent)
+                        .copied()
+                        .unwrap_or_default()
+                        .0;
+                    scroll_view
+                        .clip_rect()
+                        .contains_point(transform.inverse() * point)
+                }
+                None => true,
             })
+    }
+
+    // Stage point in the container's own space, before any scroll offset it applies
+    fn to_local_space(&self, container_id: &ContainerId, point: Vector2F) -> Vector2F {
+        self.stage_to_container_local(point, container_id)
+            .unwrap_or(point)
+    }
+
+    // Stage point in the space of the container's parent, which is where its translation lives
+    fn to_parent_space(&self, container_id: &ContainerId, point: Vector2F) -> Vector2F {
+        let parent_transform = self
+            .get_parent(container_id)
+            .and_then(|parent| self.get_world_transform(&parent))
+            .unwrap_or_default();
+        parent_transform.inverse() * point
+    }
+
+    // True for containers without a hit shape, since their bounds already matched
+    fn hit_shape_contains(&self, entity: Entity, point: Vector2F) -> bool {
+        let hit_shape_storage = self.world.read_storage::<HitShape>();
+        let hit_shape = match hit_shape_storage.get(entity) {
+            Some(hit_shape) => hit_shape,
+            None => return true,
+        };
+        let transform = self
+            .world
+            .read_storage::<WorldTransform>()
+            .get(entity)
+            .copied()
+            .unwrap_or_default()
+            .0;
+        let local_point = transform.inverse() * point;
+        match hit_shape {
+            HitShape::Edges(edges) => Edge::contains_point(edges, local_point),
+            HitShape::Shape(shape_id) => {
+                // TODO: per shape morph indices for groups
+                let morph = self
+                    .world
+                    .read_storage::<Morph>()
+                    .get(entity)
+                    .copied()
+                    .unwrap_or_default()
+                    .0;
+                match self
+                    .world
+                    .read_resource::<Library>()
+                    .get_resolved_shape(shape_id)
+                {
+                    Some(shape) => Edge::contains_point(&shape.edge_list(morph), local_point),
+                    None => false,
+                }
+            }
+        }
+    }
+
+    /// Returns the containers in `layer` whose bounds overlap the bounds of `container_id`. The
+    /// container does not need to be in `layer` itself.
+    pub fn overlapping(
+        &self,
+        container_id: &ContainerId,
+        layer: &QuadTreeLayer,
+    ) -> Vec<ContainerId> {
+        let container_mapping = self.world.read_resource::<ContainerMapping>();
+        let quad_trees = self.world.read_resource::<QuadTrees>();
+        let bounds_storage = self.world.read_storage::<Bounds>();
+        let entity = match container_mapping.get_entity(container_id) {
+            Some(entity) => *entity,
+            None => return vec![],
+        };
+        // Prefer the bounds as they are in the layer so dilation is taken into account
+        let bounds = match quad_trees
+            .get_bounds(layer, &entity)
+            .or_else(|| bounds_storage.get(entity).map(|bounds| bounds.bounds))
+        {
+            Some(bounds) => bounds,
+            None => return vec![],
+        };
+        quad_trees
+            .query(&QuadTreeQuery::Rect(*layer, bounds))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(other, _)| *other != entity)
+            .filter_map(|(other, _)| container_mapping.get_container(&other).copied())
             .collect()
     }
 
@@ -354,97 +1713,385 @@ impl<'a, 'b> Engine<'a, 'b> {
         entities
     }
 
+    /// Every displayable container, in paint order. See `visit_drawables`.
     pub fn get_drawable_items(&self) -> Vec<DrawableItem> {
+        let mut drawable_items = vec![];
+        self.visit_drawables(|drawable_item| drawable_items.push(drawable_item));
+        drawable_items
+    }
+
+    /// Hands every displayable container to `visitor` in paint order, back to front, without
+    /// collecting them first. Items are built for each visit, so the visitor owns them.
+    pub fn visit_drawables(&self, mut visitor: impl FnMut(DrawableItem)) {
         let library = self.get_library();
         let scene_graph = self.get_scene_graph();
+        let container_mapping = self.get_container_mapping();
         let display_storage = self.world.read_storage::<Display>();
         let transform_storage = self.world.read_storage::<WorldTransform>();
         let coloring_storage = self.world.read_storage::<Coloring>();
         let view_rect_storage = self.world.read_storage::<ViewRect>();
         let order_storage = self.world.read_storage::<Order>();
         let morph_storage = self.world.read_storage::<Morph>();
-        let mut unordered = (
-            &self.world.entities(),
-            &display_storage,
-            &transform_storage,
-            (&coloring_storage).maybe(),
-            (&view_rect_storage).maybe(),
-            (&order_storage).maybe(),
-            (&morph_storage).maybe(),
-        )
-            .join()
-            .filter_map(
-                |(entity, display, transform, coloring, view_rect, order, morph)| {
-                    if let Some(library_item) = match display.1 {
-                        DisplayKind::Vector => library
-                            .get_shape(&display.0)
-                            .and_then(|shape| Some(LibraryItem::Vector(shape))),
-                        DisplayKind::Raster => library
-                            .get_texture(&display.0)
-                            .and_then(|pattern| Some(LibraryItem::Raster(pattern))),
-                    } {
-                        Some((
-                            entity,
-                            (
-                                order.copied().unwrap_or_default().0,
-                                DrawableItem {
-                                    library_item,
-                                    transform: transform.0,
-                                    coloring: coloring.cloned(),
-                                    view_rect: view_rect.and_then(|view_rect| Some(view_rect.0)),
-                                    morph: morph
-                                        .and_then(|morph| Some(morph.0))
-                                        .unwrap_or_default(),
-                                },
-                            ),
-                        ))
-                    } else {
-                        None
+        let morph_indices_storage = self.world.read_storage::<MorphIndices>();
+        let pixel_snap_storage = self.world.read_storage::<PixelSnap>();
+        let color_transform_storage = self.world.read_storage::<ColorTransform>();
+        let path_trim_storage = self.world.read_storage::<PathTrim>();
+        let modifiers_storage = self.world.read_storage::<Modifiers>();
+        // Most scenes never use one, so skip walking every container's parents
+        let has_color_transforms = (&color_transform_storage).join().next().is_some()
+            || (&modifiers_storage)
+                .join()
+                .any(|modifiers| modifiers.offsets().opacity != 0.0);
+        let color_transform_of = |entity: Entity| {
+            // The root is its own parent, so stop there rather than looping forever
+            iter::successors(Some(entity), |entity| {
+                if entity == scene_graph.root() {
+                    None
+                } else {
+                    scene_graph.get_parent(entity).copied()
+                }
+            })
+            .fold(ColorTransform::default(), |combined, entity| {
+                let combined = match color_transform_storage.get(entity) {
+                    Some(outer) => combined.then(outer),
+                    None => combined,
+                };
+                match modifiers_storage.get(entity) {
+                    Some(modifiers) if modifiers.offsets().opacity != 0.0 => {
+                        combined.then(&ColorTransform::opacity(1.0 + modifiers.offsets().opacity))
                     }
+                    _ => combined,
+                }
+            })
+        };
+        let parallax_storage = self.world.read_storage::<Parallax>();
+        let has_parallax = (&parallax_storage).join().next().is_some();
+        let parallax_of = |entity: Entity| {
+            if !has_parallax {
+                return 1.0;
+            }
+            iter::successors(Some(entity), |entity| {
+                if entity == scene_graph.root() {
+                    None
+                } else {
+                    scene_graph.get_parent(entity).copied()
+                }
+            })
+            .filter_map(|entity| parallax_storage.get(entity))
+            .fold(1.0, |combined, parallax| combined * parallax.0)
+        };
+        let scroll_view_storage = self.world.read_storage::<ScrollView>();
+        let has_scroll_views = (&scroll_view_storage).join().next().is_some();
+        let clips_of = |entity: Entity| {
+            let mut clips = scene_graph
+                .get_parent_iter(&entity)
+                .filter_map(|parent| {
+                    let scroll_view = scroll_view_storage.get(*parent)?;
+                    Some(ClipRect {
+                        rect: scroll_view.clip_rect(),
+                        transform: transform_storage
+                            .get(*parent)
+                            .copied()
+                            .unwrap_or_default()
+                            .0,
+                        parallax: parallax_of(*parent),
+                    })
+                })
+                .collect::<Vec<ClipRect>>();
+            clips.reverse();
+            clips
+        };
+        let device_pixel_ratio = self
+            .world
+            .read_resource::<EngineConfig>()
+            .device_pixel_ratio;
+        let camera = self.get_camera();
+        // Longest side of the shape's bounds once the camera and device pixel ratio apply
+        let projected_size =
+            |entity: Entity, shape: &Shape, transform: &Transform2F, morph: f32| {
+                let bounds = shape.compute_bounding(transform, morph);
+                let zoom = camera.with_parallax(parallax_of(entity)).zoom();
+                bounds.width().max(bounds.height()) * zoom * device_pixel_ratio
+            };
+        let drawable_of = |entity: Entity| {
+            let display = display_storage.get(entity)?;
+            let transform = transform_storage.get(entity)?;
+            let morph = morph_storage
+                .get(entity)
+                .map(|morph| morph.0)
+                .unwrap_or_default();
+            let morph = match modifiers_storage.get(entity) {
+                Some(modifiers) if modifiers.offsets().morph_index != 0.0 => {
+                    util::clamp_0_1(morph + modifiers.offsets().morph_index)
+                }
+                _ => morph,
+            };
+            let library_item = match display.1 {
+                DisplayKind::Vector => {
+                    let id = if library.get_lod_variants(&display.0).is_empty() {
+                        display.0
+                    } else {
+                        library
+                            .get_resolved_shape(&display.0)
+                            .map_or(display.0, |shape| {
+                                library.select_lod(
+                                    &display.0,
+                                    projected_size(entity, &shape, &transform.0, morph),
+                                )
+                            })
+                    };
+                    library.get_resolved_shape(&id).map(|shape| {
+                        match path_trim_storage.get(entity) {
+                            Some(path_trim) if !path_trim.is_whole() => {
+                                LibraryItem::Vector(Arc::new(shape.trim(path_trim, morph)))
+                            }
+                            _ => LibraryItem::Vector(shape),
+                        }
+                    })
+                }
+                DisplayKind::Raster => library.get_texture(&display.0).map(LibraryItem::Raster),
+            }?;
+            let container_id = *container_mapping.get_container(&entity)?;
+            let coloring = coloring_storage.get(entity);
+            let color_transform = if has_color_transforms {
+                color_transform_of(entity)
+            } else {
+                ColorTransform::default()
+            };
+            let coloring = if color_transform.is_identity() {
+                coloring.cloned()
+            } else {
+                // TODO: only the alpha of raster tints is drawn so far
+                let base = coloring.cloned().unwrap_or_else(|| match &library_item {
+                    LibraryItem::Vector(shape) => shape.color(),
+                    LibraryItem::Raster(_) => Coloring::Color(LinSrgba::new(1.0, 1.0, 1.0, 1.0)),
+                });
+                Some(color_transform.apply_to_coloring(&base))
+            };
+            Some(DrawableItem {
+                container_id,
+                library_item,
+                transform: match pixel_snap_storage.get(entity) {
+                    Some(_) => snap_to_pixels(transform.0, device_pixel_ratio),
+                    None => transform.0,
                 },
-            )
-            .collect::<HashMap<Entity, (i8, DrawableItem)>>();
-        let mut sorted = vec![];
-        let mut queue = VecDeque::new();
-        queue.push_back(*scene_graph.root());
-        while let Some(next) = queue.pop_front() {
-            let mut children = scene_graph.get_children(&next).cloned().unwrap();
-            // Sort back to front
-            children.sort_by(|a, b| {
-                let order_a = unordered
-                    .get(&a)
-                    .and_then(|(order, _)| Some(*order))
-                    .unwrap_or_default();
-                let order_b = unordered
-                    .get(&b)
-                    .and_then(|(order, _)| Some(*order))
-                    .unwrap_or_default();
-                order_a.cmp(&order_b)
-            });
-            for child in children {
-                queue.push_back(child);
-                if let Some((_, display_item)) = unordered.remove(&child) {
-                    sorted.push(display_item)
-                };
+                coloring,
+                view_rect: view_rect_storage.get(entity).map(|view_rect| view_rect.0),
+                morph,
+                morph_indices: morph_indices_storage
+                    .get(entity)
+                    .map(|morph_indices| morph_indices.0.clone())
+                    .unwrap_or_default(),
+                clips: if has_scroll_views {
+                    clips_of(entity)
+                } else {
+                    vec![]
+                },
+                parallax: parallax_of(entity),
+            })
+        };
+        if !self
+            .world
+            .read_resource::<PaintOrder>()
+            .is_valid_for(&scene_graph)
+        {
+            // Only displayed containers are sorted by their order
+            self.world
+                .write_resource::<PaintOrder>()
+                .rebuild(&scene_graph, |entity| match display_storage.get(*entity) {
+                    Some(_) => order_storage.get(*entity).copied().unwrap_or_default().0,
+                    None => 0,
+                });
+        }
+        for entity in self.world.read_resource::<PaintOrder>().entities() {
+            if let Some(drawable_item) = drawable_of(*entity) {
+                visitor(drawable_item);
             }
         }
-        sorted
+    }
+
+    /// Outlines of the engine's internals for whichever `DebugFlags` are set, in world space.
+    /// Draw them over the scene. They belong to the root container and never enter the library.
+    pub fn get_debug_drawables(&self) -> Vec<DrawableItem> {
+        let debug_flags = self.get_debug_flags();
+        if !debug_flags.any() {
+            return vec![];
+        }
+        let scene_graph = self.get_scene_graph();
+        let bounds_storage = self.world.read_storage::<Bounds>();
+        let transform_storage = self.world.read_storage::<WorldTransform>();
+        let entities = self.world.entities();
+        let mut drawables = vec![];
+        if debug_flags.quad_trees {
+            let edges = self
+                .get_quad_trees()
+                .node_rects()
+                .into_iter()
+                .flat_map(|(_, rects)| rects)
+                .flat_map(debug_rect_edges)
+                .collect();
+            drawables.push(debug_drawable(
+                self.root_container_id,
+                edges,
+                DEBUG_QUAD_TREE_COLOR,
+            ));
+        }
+        if debug_flags.bounds {
+            let edges = (&bounds_storage)
+                .join()
+                .flat_map(|bounds| debug_rect_edges(bounds.bounds))
+                .collect();
+            drawables.push(debug_drawable(
+                self.root_container_id,
+                edges,
+                DEBUG_BOUNDS_COLOR,
+            ));
+        }
+        if debug_flags.dirty_regions {
+            let edges = self
+                .world
+                .read_resource::<DirtyRegions>()
+                .0
+                .iter()
+                .flat_map(|rect| debug_rect_edges(*rect))
+                .collect();
+            drawables.push(debug_drawable(
+                self.root_container_id,
+                edges,
+                DEBUG_DIRTY_REGION_COLOR,
+            ));
+        }
+        if debug_flags.parent_links {
+            let edges = (&entities, &transform_storage)
+                .join()
+                .filter_map(|(entity, transform)| {
+                    // The root is its own parent
+                    let parent = scene_graph
+                        .get_parent(&entity)
+                        .filter(|parent| **parent != entity)?;
+                    let parent_transform = transform_storage.get(*parent)?;
+                    Some(vec![
+                        Edge::Move(parent_transform.0.translation()),
+                        Edge::Line(transform.0.translation()),
+                    ])
+                })
+                .flatten()
+                .collect();
+            drawables.push(debug_drawable(
+                self.root_container_id,
+                edges,
+                DEBUG_PARENT_LINK_COLOR,
+            ));
+        }
+        drawables.into_iter().flatten().collect()
     }
 }
 
-#[derive(Debug)]
+const DEBUG_BOUNDS_COLOR: (f32, f32, f32) = (0.0, 0.8, 0.2);
+const DEBUG_QUAD_TREE_COLOR: (f32, f32, f32) = (0.2, 0.4, 1.0);
+const DEBUG_DIRTY_REGION_COLOR: (f32, f32, f32) = (1.0, 0.2, 0.2);
+const DEBUG_PARENT_LINK_COLOR: (f32, f32, f32) = (1.0, 0.6, 0.0);
+
+fn debug_rect_edges(rect: RectF) -> Vec<Edge> {
+    Edge::new_rect(rect.size(), Transform2F::from_translation(rect.origin()))
+}
+
+fn debug_drawable(
+    container_id: ContainerId,
+    edges: Vec<Edge>,
+    (red, green, blue): (f32, f32, f32),
+) -> Option<DrawableItem> {
+    if edges.is_empty() {
+        return None;
+    }
+    Some(DrawableItem {
+        container_id,
+        library_item: LibraryItem::Vector(Arc::new(Shape::Path {
+            contours: Contour::from_edges(&edges),
+            color: LinSrgba::new(red, green, blue, 1.0),
+            stroke_style: StrokeStyle {
+                line_width: 1.0,
+                line_cap: LineCap::default(),
+                line_join: LineJoin::default(),
+            },
+        })),
+        transform: Transform2F::default(),
+        coloring: None,
+        view_rect: None,
+        morph: 0.0,
+        morph_indices: vec![],
+        clips: vec![],
+        parallax: 1.0,
+    })
+}
+
+fn texture_bytes(pattern: &Pattern) -> usize {
+    // Textures are uploaded as RGBA8
+    let size = pattern.size();
+    (size.x() * size.y()) as usize * 4
+}
+
+// Rounds only the translation, so rotated or scaled artwork keeps its shape
+fn snap_to_pixels(transform: Transform2F, device_pixel_ratio: f32) -> Transform2F {
+    let device_translation = transform.translation() * device_pixel_ratio;
+    let snapped = Vector2F::new(
+        device_translation.x().round(),
+        device_translation.y().round(),
+    );
+    Transform2F::from_translation((snapped - device_translation) * (1.0 / device_pixel_ratio))
+        * transform
+}
+
+/// Approximate memory used by a single library item.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LibraryItemReport {
+    pub id: LibraryId,
+    pub name: Option<String>,
+    pub bytes: usize,
+    /// Edge count for shapes, 0 for textures.
+    pub edges: usize,
+    /// Pixel dimensions for textures, 0 by 0 for shapes.
+    pub size: (i32, i32),
+    /// Containers displaying the item.
+    pub usages: usize,
+    /// Live references to the item's data, including the library's own. Anything above
+    /// `usages + 1` is held outside the scene, such as by a renderer's cache.
+    pub references: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryReport {
+    /// Sorted largest first.
+    pub library_items: Vec<LibraryItemReport>,
+    pub total_edges: usize,
+    pub total_shape_bytes: usize,
+    pub total_texture_bytes: usize,
+    pub entity_count: usize,
+    pub component_counts: Vec<(String, usize)>,
+    /// Layer, element count and node count of each quad tree.
+    pub quad_trees: Vec<(QuadTreeLayer, usize, usize)>,
+}
+
+#[derive(Clone, Debug)]
 pub enum LibraryItem {
     Vector(Arc<Shape>),
     Raster(Arc<Pattern>),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct DrawableItem {
+    pub container_id: ContainerId,
     pub library_item: LibraryItem,
     pub transform: Transform2F,
     pub coloring: Option<Coloring>,
     pub view_rect: Option<RectF>,
     pub morph: f32,
+    /// Per shape morph indices for group shapes, empty if the container has none.
+    pub morph_indices: Vec<f32>,
+    /// The clip rects of every `ScrollView` the container is inside of, outer most first.
+    pub clips: Vec<ClipRect>,
+    /// How much of the camera applies to the container. See `Parallax`.
+    pub parallax: f32,
 }
 
 #[derive(Clone, Debug)]
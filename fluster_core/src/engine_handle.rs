@@ -0,0 +1,267 @@
+use crate::{
+    actions::{ContainerCreationDefintition, ContainerUpdateDefintition},
+    display_list::{triple_buffer, DisplayList, DisplayListReader, DisplayListWriter},
+    ecs::resources::{FrameTime, Library, QuadTreeQuery, QuadTrees},
+    engine::{DrawableItem, Engine, SelectionHandle},
+    rendering::{paint, Renderer},
+    types::{
+        basic::{Bitmap, ContainerId, LibraryId},
+        shapes::Shape,
+    },
+};
+use log::error;
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+type EngineTask = Box<dyn FnOnce(&mut Engine) + Send>;
+
+enum EngineCommand {
+    CreateContainer(ContainerCreationDefintition),
+    UpdateContainer(ContainerUpdateDefintition),
+    RemoveContainer(ContainerId, bool),
+    DefineShape(LibraryId, Shape),
+    LoadBitmap(LibraryId, Bitmap),
+    Update(FrameTime),
+    Execute(EngineTask),
+    PublishDisplayLists(DisplayListWriter),
+    Shutdown,
+}
+
+/// Owns an `Engine` on a dedicated thread.
+///
+/// All interaction happens over a channel, so the handle is `Send` and can be held by hosts
+/// (GUI toolkits, scripting runtimes, game engines) that want to keep simulation off of their own
+/// threads. Commands are processed in the order they are sent.
+pub struct EngineHandle {
+    sender: Sender<EngineCommand>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EngineHandle {
+    pub fn spawn(root_container_id: ContainerId, library: Library, quad_trees: QuadTrees) -> Self {
+        let (sender, receiver) = channel();
+        let thread = thread::spawn(move || {
+            let engine = Engine::new(root_container_id, library, quad_trees);
+            run_engine(engine, receiver);
+        });
+        Self {
+            sender,
+            thread: Some(thread),
+        }
+    }
+
+    fn send(&self, command: EngineCommand) -> Result<(), String> {
+        self.sender
+            .send(command)
+            .map_err(|_| "Engine thread has shut down".to_string())
+    }
+
+    pub fn create_container(&self, definition: ContainerCreationDefintition) -> Result<(), String> {
+        self.send(EngineCommand::CreateContainer(definition))
+    }
+
+    pub fn update_container(&self, definition: ContainerUpdateDefintition) -> Result<(), String> {
+        self.send(EngineCommand::UpdateContainer(definition))
+    }
+
+    pub fn remove_container(&self, container_id: ContainerId) -> Result<(), String> {
+        self.send(EngineCommand::RemoveContainer(container_id, false))
+    }
+
+    pub fn remove_container_and_children(&self, container_id: ContainerId) -> Result<(), String> {
+        self.send(EngineCommand::RemoveContainer(container_id, true))
+    }
+
+    pub fn define_shape(&self, id: LibraryId, shape: Shape) -> Result<(), String> {
+        self.send(EngineCommand::DefineShape(id, shape))
+    }
+
+    pub fn load_bitmap(&self, id: LibraryId, bitmap: Bitmap) -> Result<(), String> {
+        self.send(EngineCommand::LoadBitmap(id, bitmap))
+    }
+
+    /// Runs one frame of the engine's systems. Does not block.
+    pub fn update(&self, frame_time: FrameTime) -> Result<(), String> {
+        self.send(EngineCommand::Update(frame_time))
+    }
+
+    /// Runs `query` on the engine thread and blocks until it returns.
+    pub fn query<T, F>(&self, query: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Engine) -> T + Send + 'static,
+    {
+        let (reply_sender, reply_receiver) = channel();
+        self.send(EngineCommand::Execute(Box::new(move |engine| {
+            // The caller may have stopped waiting, in which case there is nobody to tell
+            let _ = reply_sender.send(query(engine));
+        })))?;
+        reply_receiver
+            .recv()
+            .map_err(|_| "Engine thread dropped the query".to_string())
+    }
+
+    pub fn drawable_items(&self) -> Result<Vec<DrawableItem>, String> {
+        self.query(|engine| engine.get_drawable_items())
+    }
+
+    pub fn spatial_query(&self, query: QuadTreeQuery) -> Result<Vec<SelectionHandle>, String> {
+        self.query(move |engine| engine.spatial_query(&query))
+    }
+
+    /// Paints the current frame into `renderer` on the engine thread, then hands the renderer back.
+    pub fn render_frame<R>(&self, mut renderer: R) -> Result<R, String>
+    where
+        R: Renderer + Send + 'static,
+    {
+        self.query(move |engine| {
+            paint(&mut renderer, engine);
+            renderer
+        })
+    }
+
+    /// Captures a `DisplayList` after every `update` from now on, for a render thread to paint
+    /// with `DisplayList::paint`. Updates and presentation then run at their own pace; the reader
+    /// always sees the newest finished frame. Replaces any reader handed out before.
+    pub fn display_lists(&self) -> Result<DisplayListReader, String> {
+        let (writer, reader) = triple_buffer();
+        self.send(EngineCommand::PublishDisplayLists(writer))?;
+        Ok(reader)
+    }
+
+    /// Stops the engine thread and waits for it to finish any queued commands.
+    pub fn shutdown(mut self) -> Result<(), String> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<(), String> {
+        if let Some(thread) = self.thread.take() {
+            // If the thread already exited there is nothing listening, which is fine
+            let _ = self.sender.send(EngineCommand::Shutdown);
+            thread
+                .join()
+                .map_err(|_| "Engine thread panicked".to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for EngineHandle {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+fn run_engine(mut engine: Engine, receiver: Receiver<EngineCommand>) {
+    let mut display_lists: Option<DisplayListWriter> = None;
+    while let Ok(command) = receiver.recv() {
+        match command {
+            EngineCommand::CreateContainer(definition) => engine.create_container(&definition),
+            EngineCommand::UpdateContainer(definition) => engine.update_container(&definition),
+            EngineCommand::RemoveContainer(container_id, recursive) => {
+                let result = if recursive {
+                    engine.remove_container_and_children(&container_id)
+                } else {
+                    engine.remove_container(&container_id)
+                };
+                if let Err(error) = result {
+                    error!("Failed to remove container {:?}: {:?}", container_id, error);
+                }
+            }
+            EngineCommand::DefineShape(id, shape) => engine.get_library_mut().add_shape(id, shape),
+            EngineCommand::LoadBitmap(id, mut bitmap) => {
+                engine.get_library_mut().add_texture(id, bitmap.pattern())
+            }
+            EngineCommand::Update(frame_time) => {
+                engine.update(frame_time);
+                publish_display_list(&mut display_lists, &engine);
+            }
+            EngineCommand::Execute(task) => task(&mut engine),
+            EngineCommand::PublishDisplayLists(writer) => {
+                display_lists = Some(writer);
+                // So the render thread has something to draw before the next update
+                publish_display_list(&mut display_lists, &engine);
+            }
+            EngineCommand::Shutdown => break,
+        }
+    }
+}
+
+fn publish_display_list(display_lists: &mut Option<DisplayListWriter>, engine: &Engine) {
+    if let Some(writer) = display_lists {
+        if writer.is_connected() {
+            writer.publish(Arc::new(DisplayList::capture(engine)));
+        } else {
+            // The render thread hung up, so stop capturing for it
+            *display_lists = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::ContainerCreationProperty,
+        types::{
+            basic::ScaleRotationTranslation,
+            shapes::{Contour, Edge},
+        },
+    };
+    use palette::LinSrgba;
+    use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+
+    #[test]
+    fn it_runs_commands_sent_through_the_handle() {
+        let root_id = ContainerId::new();
+        let handle = EngineHandle::spawn(root_id, Library::default(), QuadTrees::default());
+        let shape_id = LibraryId::new();
+        handle
+            .define_shape(
+                shape_id,
+                Shape::Fill {
+                    contours: Contour::from_edges(&Edge::new_rect(
+                        Vector2F::splat(10.0),
+                        Transform2F::default(),
+                    )),
+                    color: LinSrgba::new(1.0, 1.0, 1.0, 1.0),
+                },
+            )
+            .unwrap();
+        let container_id = ContainerId::new();
+        let translation = Vector2F::new(5.0, 7.0);
+        handle
+            .create_container(ContainerCreationDefintition::new(
+                root_id,
+                container_id,
+                vec![
+                    ContainerCreationProperty::Transform(ScaleRotationTranslation::new(
+                        Vector2F::splat(1.0),
+                        0.0,
+                        translation,
+                    )),
+                    ContainerCreationProperty::Display(shape_id),
+                ],
+            ))
+            .unwrap();
+        handle
+            .update(FrameTime::new(Default::default(), 1))
+            .unwrap();
+        // Queries are queued behind the commands sent before them
+        let drawable_items = handle.drawable_items().unwrap();
+        assert_eq!(drawable_items.len(), 1);
+        assert_eq!(drawable_items[0].container_id, container_id);
+        assert_eq!(drawable_items[0].transform.translation(), translation);
+        handle.remove_container(container_id).unwrap();
+        handle
+            .update(FrameTime::new(Default::default(), 1))
+            .unwrap();
+        assert!(handle.drawable_items().unwrap().is_empty());
+        handle.shutdown().unwrap();
+    }
+}
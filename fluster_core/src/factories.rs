@@ -1,10 +1,14 @@
 use crate::{
-    actions::{BoundsKindDefinition, ContainerCreationDefintition, ContainerCreationProperty},
+    actions::{
+        BoundsKindDefinition, ContainerCreationDefintition, ContainerCreationProperty,
+        ContainerUpdateDefintition, ContainerUpdateProperty,
+    },
     ecs::resources::QuadTreeLayer,
     engine::Engine,
     types::basic::{ContainerId, LibraryId, ScaleRotationTranslation},
 };
-use pathfinder_geometry::transform2d::Transform2F;
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+use std::f32::consts::PI;
 
 pub fn new_container(engine: &mut Engine, parent: ContainerId) -> ContainerId {
     let container_id = ContainerId::new();
@@ -75,3 +79,193 @@ pub fn new_display_container_with_collision(
     ));
     container_id
 }
+
+/// Where `repeat_definitions` puts each copy, relative to the definition's own transform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RepeatLayout {
+    /// Rows of `columns` copies, `spacing` apart, filled left to right then top to bottom.
+    Grid { columns: usize, spacing: Vector2F },
+    /// Evenly around a circle of `radius`, starting on the right. Each copy is turned to face
+    /// away from the center.
+    Ring { radius: f32 },
+}
+
+impl RepeatLayout {
+    pub fn transform(&self, index: usize, count: usize) -> Transform2F {
+        match self {
+            RepeatLayout::Grid { columns, spacing } => {
+                let columns = (*columns).max(1);
+                Transform2F::from_translation(
+                    Vector2F::new((index % columns) as f32, (index / columns) as f32) * *spacing,
+                )
+            }
+            RepeatLayout::Ring { radius } => {
+                let theta = PI * 2.0 * index as f32 / count.max(1) as f32;
+                Transform2F::from_rotation(theta)
+                    * Transform2F::from_translation(Vector2F::new(*radius, 0.0))
+            }
+        }
+    }
+}
+
+/// Copies `definition` `count` times, for crowds and patterns. Each copy gets a new id and
+/// `definition`'s properties, with its place in `layout` applied over the definition's
+/// transform. `definition`'s own id is not used. If `updates` isn't empty, every copy gets an
+/// update with them, starting `stagger_frames` after the copy before it so the tweens ripple
+/// through the copies. Send the results as actions, or see `instantiate`.
+pub fn repeat_definitions(
+    definition: &ContainerCreationDefintition,
+    updates: &[ContainerUpdateProperty],
+    layout: RepeatLayout,
+    count: usize,
+    stagger_frames: u32,
+) -> Vec<(
+    ContainerCreationDefintition,
+    Option<ContainerUpdateDefintition>,
+)> {
+    let base = definition
+        .properties()
+        .iter()
+        .find_map(|property| match property {
+            ContainerCreationProperty::Transform(srt) => Some(
+                Transform2F::from_scale_rotation_translation(srt.scale, srt.theta, srt.translation),
+            ),
+            _ => None,
+        })
+        .unwrap_or_default();
+    (0..count)
+        .map(|index| {
+            let container_id = ContainerId::new();
+            let transform =
+                ScaleRotationTranslation::from_transform(layout.transform(index, count) * base);
+            let mut properties = definition
+                .properties()
+                .iter()
+                .filter(|property| !matches!(property, ContainerCreationProperty::Transform(..)))
+                .cloned()
+                .collect::<Vec<_>>();
+            properties.insert(0, ContainerCreationProperty::Transform(transform));
+            let update = if updates.is_empty() {
+                None
+            } else {
+                Some(
+                    ContainerUpdateDefintition::new(container_id, updates.to_vec())
+                        .with_delay(stagger_frames.saturating_mul(index as u32)),
+                )
+            };
+            (
+                ContainerCreationDefintition::new(*definition.parent(), container_id, properties),
+                update,
+            )
+        })
+        .collect()
+}
+
+/// Creates the copies from `repeat_definitions` and returns their ids, in layout order.
+pub fn instantiate(
+    engine: &mut Engine,
+    definition: &ContainerCreationDefintition,
+    updates: &[ContainerUpdateProperty],
+    layout: RepeatLayout,
+    count: usize,
+    stagger_frames: u32,
+) -> Vec<ContainerId> {
+    repeat_definitions(definition, updates, layout, count, stagger_frames)
+        .into_iter()
+        .map(|(creation, update)| {
+            engine.create_container(&creation);
+            if let Some(update) = update {
+                engine.update_container(&update);
+            }
+            *creation.id()
+        })
+        .collect()
+}
+
+pub fn instantiate_grid(
+    engine: &mut Engine,
+    definition: &ContainerCreationDefintition,
+    updates: &[ContainerUpdateProperty],
+    count: usize,
+    columns: usize,
+    spacing: Vector2F,
+    stagger_frames: u32,
+) -> Vec<ContainerId> {
+    instantiate(
+        engine,
+        definition,
+        updates,
+        RepeatLayout::Grid { columns, spacing },
+        count,
+        stagger_frames,
+    )
+}
+
+pub fn instantiate_ring(
+    engine: &mut Engine,
+    definition: &ContainerCreationDefintition,
+    updates: &[ContainerUpdateProperty],
+    count: usize,
+    radius: f32,
+    stagger_frames: u32,
+) -> Vec<ContainerId> {
+    instantiate(
+        engine,
+        definition,
+        updates,
+        RepeatLayout::Ring { radius },
+        count,
+        stagger_frames,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tween::Easing;
+
+    #[test]
+    fn it_lays_out_and_staggers_copies() {
+        let definition = ContainerCreationDefintition::new(
+            ContainerId::new(),
+            ContainerId::new(),
+            vec![ContainerCreationProperty::Transform(
+                ScaleRotationTranslation::new(Vector2F::splat(1.0), 0.0, Vector2F::new(10.0, 0.0)),
+            )],
+        );
+        let updates = vec![ContainerUpdateProperty::MorphIndex(1.0, Easing::Linear, 10)];
+        let copies = repeat_definitions(
+            &definition,
+            &updates,
+            RepeatLayout::Grid {
+                columns: 2,
+                spacing: Vector2F::new(5.0, 7.0),
+            },
+            3,
+            4,
+        );
+        let translations = copies
+            .iter()
+            .map(|(creation, _)| match creation.properties()[0] {
+                ContainerCreationProperty::Transform(srt) => srt.translation,
+                _ => panic!("Expected a transform"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            translations,
+            vec![
+                Vector2F::new(10.0, 0.0),
+                Vector2F::new(15.0, 0.0),
+                Vector2F::new(10.0, 7.0),
+            ]
+        );
+        let delays = copies
+            .iter()
+            .map(|(_, update)| update.as_ref().unwrap().delay())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![0, 4, 8]);
+        assert!(copies
+            .iter()
+            .all(|(creation, _)| creation.parent() == definition.parent()));
+    }
+}
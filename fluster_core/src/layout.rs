@@ -0,0 +1,287 @@
+use crate::{
+    actions::{ContainerUpdateDefintition, ContainerUpdateProperty},
+    engine::Engine,
+    tween::Easing,
+    types::basic::{ContainerId, ScaleRotationTranslation},
+};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use reduce::Reduce;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// Centers horizontally, leaving vertical position alone.
+    CenterHorizontal,
+    /// Centers vertically, leaving horizontal position alone.
+    CenterVertical,
+}
+
+/// Layout operations work on world space bounds as of the last `Engine::update`, and enqueue
+/// transform updates that are applied over `duration_frames`. Laying out the same container twice
+/// before an update will use stale bounds for the second call.
+impl Engine {
+    /// Scales and moves the container so its bounds fill `rect`. With `preserve_aspect` the
+    /// container is scaled uniformly and centered in `rect` instead.
+    pub fn fit_into(
+        &mut self,
+        container_id: &ContainerId,
+        rect: RectF,
+        preserve_aspect: bool,
+        easing: Easing,
+        duration_frames: u32,
+    ) -> Result<(), String> {
+        let bounds = self.layout_bounds(container_id)?;
+        if bounds.width() <= 0.0 || bounds.height() <= 0.0 {
+            return Err(format!(
+                "Container {:?} has empty bounds and cannot be scaled to fit",
+                container_id
+            ));
+        }
+        let scale = rect.size() / bounds.size();
+        let scale = if preserve_aspect {
+            Vector2F::splat(scale.x().min(scale.y()))
+        } else {
+            scale
+        };
+        let adjustment = Transform2F::from_translation(rect.center())
+            * Transform2F::from_scale(scale)
+            * Transform2F::from_translation(-bounds.center());
+        self.adjust_world_transform(container_id, adjustment, easing, duration_frames)
+    }
+
+    /// Moves the container so the center of its bounds is at `point`.
+    pub fn center_on(
+        &mut self,
+        container_id: &ContainerId,
+        point: Vector2F,
+        easing: Easing,
+        duration_frames: u32,
+    ) -> Result<(), String> {
+        let bounds = self.layout_bounds(container_id)?;
+        let adjustment = Transform2F::from_translation(point - bounds.center());
+        self.adjust_world_transform(container_id, adjustment, easing, duration_frames)
+    }
+
+    /// Lines up the containers against the edge or center of their combined bounds.
+    pub fn align(
+        &mut self,
+        container_ids: &[ContainerId],
+        alignment: Alignment,
+        easing: Easing,
+        duration_frames: u32,
+    ) -> Result<(), String> {
+        let all_bounds = container_ids
+            .iter()
+            .map(|container_id| Ok((*container_id, self.layout_bounds(container_id)?)))
+            .collect::<Result<Vec<(ContainerId, RectF)>, String>>()?;
+        let union = match all_bounds
+            .iter()
+            .map(|(_, bounds)| *bounds)
+            .reduce(|a, b| a.union_rect(b))
+        {
+            Some(union) => union,
+            None => return Ok(()),
+        };
+        for (container_id, bounds) in all_bounds {
+            let offset = match alignment {
+                Alignment::Left => Vector2F::new(union.min_x() - bounds.min_x(), 0.0),
+                Alignment::Right => Vector2F::new(union.max_x() - bounds.max_x(), 0.0),
+                Alignment::Top => Vector2F::new(0.0, union.min_y() - bounds.min_y()),
+                Alignment::Bottom => Vector2F::new(0.0, union.max_y() - bounds.max_y()),
+                Alignment::CenterHorizontal => {
+                    Vector2F::new(union.center().x() - bounds.center().x(), 0.0)
+                }
+                Alignment::CenterVertical => {
+                    Vector2F::new(0.0, union.center().y() - bounds.center().y())
+                }
+            };
+            self.adjust_world_transform(
+                &container_id,
+                Transform2F::from_translation(offset),
+                easing,
+                duration_frames,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn layout_bounds(&self, container_id: &ContainerId) -> Result<RectF, String> {
+        self.get_bounds(container_id)
+            .ok_or_else(|| format!("Container {:?} has no bounds to lay out", container_id))
+    }
+
+    // Applies `adjustment` in world space by converting it into the container's parent space.
+    fn adjust_world_transform(
+        &mut self,
+        container_id: &ContainerId,
+        adjustment: Transform2F,
+        easing: Easing,
+        duration_frames: u32,
+    ) -> Result<(), String> {
+        let (world, local) = match (
+            self.get_world_transform(container_id),
+            self.get_local_transform(container_id),
+        ) {
+            (Some(world), Some(local)) => (world, local),
+            _ => return Err(format!("Container {:?} has no transform", container_id)),
+        };
+        let parent = world * local.inverse();
+        let updated = parent.inverse() * adjustment * world;
+        self.update_container(&ContainerUpdateDefintition::new(
+            *container_id,
+            vec![ContainerUpdateProperty::Transform(
+                ScaleRotationTranslation::from_transform(updated),
+                easing,
+                duration_frames,
+            )],
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::{BoundsKindDefinition, ContainerCreationDefintition, ContainerCreationProperty},
+        ecs::resources::{FrameTime, Library, QuadTrees},
+        types::{
+            basic::LibraryId,
+            shapes::{Contour, Edge, Shape},
+        },
+    };
+    use palette::LinSrgba;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> RectF {
+        RectF::new(Vector2F::new(x, y), Vector2F::new(width, height))
+    }
+
+    // An engine with a 10x10 square at each of `positions`
+    fn squares_at(positions: &[Vector2F]) -> (Engine, Vec<ContainerId>) {
+        let shape_id = LibraryId::new();
+        let mut library = Library::default();
+        library.add_shape(
+            shape_id,
+            Shape::Fill {
+                contours: Contour::from_edges(&Edge::new_rect(
+                    Vector2F::splat(10.0),
+                    Transform2F::default(),
+                )),
+                color: LinSrgba::new(1.0, 1.0, 1.0, 1.0),
+            },
+        );
+        let root_id = ContainerId::new();
+        let mut engine = Engine::new(root_id, library, QuadTrees::default());
+        let container_ids = positions
+            .iter()
+            .map(|position| {
+                let container_id = ContainerId::new();
+                engine.create_container(&ContainerCreationDefintition::new(
+                    root_id,
+                    container_id,
+                    vec![
+                        ContainerCreationProperty::Transform(ScaleRotationTranslation::new(
+                            Vector2F::splat(1.0),
+                            0.0,
+                            *position,
+                        )),
+                        ContainerCreationProperty::Display(shape_id),
+                        ContainerCreationProperty::Bounds(BoundsKindDefinition::Display),
+                    ],
+                ));
+                container_id
+            })
+            .collect();
+        update(&mut engine);
+        (engine, container_ids)
+    }
+
+    // Runs past the end of the single frame tweens layout enqueues
+    fn update(engine: &mut Engine) {
+        let frame = FrameTime::new(Default::default(), 1);
+        for _ in 0..3 {
+            engine.update(frame);
+        }
+    }
+
+    #[test]
+    fn it_fits_into_a_rect() {
+        let (mut engine, container_ids) = squares_at(&[Vector2F::splat(5.0)]);
+        let target = rect(100.0, 50.0, 40.0, 20.0);
+        engine
+            .fit_into(&container_ids[0], target, false, Easing::Linear, 1)
+            .unwrap();
+        update(&mut engine);
+        assert_eq!(engine.get_bounds(&container_ids[0]), Some(target));
+    }
+
+    #[test]
+    fn it_fits_into_a_rect_preserving_aspect() {
+        let (mut engine, container_ids) = squares_at(&[Vector2F::splat(5.0)]);
+        engine
+            .fit_into(
+                &container_ids[0],
+                rect(100.0, 50.0, 40.0, 20.0),
+                true,
+                Easing::Linear,
+                1,
+            )
+            .unwrap();
+        update(&mut engine);
+        // Scaled by the smaller of 4 and 2, and centered across the spare width
+        assert_eq!(
+            engine.get_bounds(&container_ids[0]),
+            Some(rect(110.0, 50.0, 20.0, 20.0))
+        );
+    }
+
+    #[test]
+    fn it_centers_on_a_point() {
+        let (mut engine, container_ids) = squares_at(&[Vector2F::splat(5.0)]);
+        engine
+            .center_on(
+                &container_ids[0],
+                Vector2F::new(50.0, 30.0),
+                Easing::Linear,
+                1,
+            )
+            .unwrap();
+        update(&mut engine);
+        assert_eq!(
+            engine.get_bounds(&container_ids[0]),
+            Some(rect(45.0, 25.0, 10.0, 10.0))
+        );
+    }
+
+    #[test]
+    fn it_aligns_to_each_edge_and_center() {
+        // Their combined bounds run from (0, 0) to (30, 40)
+        let positions = [Vector2F::zero(), Vector2F::new(20.0, 30.0)];
+        let expected = [
+            (Alignment::Left, [(0.0, 0.0), (0.0, 30.0)]),
+            (Alignment::Right, [(20.0, 0.0), (20.0, 30.0)]),
+            (Alignment::Top, [(0.0, 0.0), (20.0, 0.0)]),
+            (Alignment::Bottom, [(0.0, 30.0), (20.0, 30.0)]),
+            (Alignment::CenterHorizontal, [(10.0, 0.0), (10.0, 30.0)]),
+            (Alignment::CenterVertical, [(0.0, 15.0), (20.0, 15.0)]),
+        ];
+        for (alignment, origins) in expected.iter() {
+            let (mut engine, container_ids) = squares_at(&positions);
+            engine
+                .align(&container_ids, *alignment, Easing::Linear, 1)
+                .unwrap();
+            update(&mut engine);
+            for (container_id, (x, y)) in container_ids.iter().zip(origins.iter()) {
+                assert_eq!(
+                    engine.get_bounds(container_id),
+                    Some(rect(*x, *y, 10.0, 10.0)),
+                    "{:?}",
+                    alignment
+                );
+            }
+        }
+    }
+}
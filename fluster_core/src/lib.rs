@@ -1,18 +1,26 @@
 #![deny(clippy::all)]
-#![feature(div_duration)]
-#![feature(clamp)]
 
 #[macro_use]
 extern crate nom;
 
 pub mod actions;
+pub mod baking;
+pub mod display_list;
 pub mod ecs;
 pub mod engine;
+pub mod engine_handle;
 pub mod factories;
+pub mod layout;
+pub mod linking;
+pub mod modifiers;
+pub mod pacing;
+pub mod prelude;
 mod quad_tree;
 pub mod rendering;
 pub mod runner;
 pub mod serialization;
+pub mod state_machine;
 pub mod tween;
 pub mod types;
 mod util;
+pub mod watermark;
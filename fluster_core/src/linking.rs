@@ -0,0 +1,146 @@
+//! Library items stored outside the action stream. An `Action::LibraryRef` names a location
+//! instead of embedding the item, and the runner hands that location to an `AssetResolver` when
+//! it reaches the action. Linked files hold a single serialized `DefineShape`, `LoadBitmap` or
+//! `LoadTexture` action, so anything that can write a stream can write one.
+
+use crate::{
+    actions::Action,
+    ecs::resources::Library,
+    serialization::{deserialize_action, serialize_action, ACTION_VERSION},
+    types::{
+        basic::{Bitmap, LibraryId, TextureDefinition},
+        shapes::Shape,
+    },
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Linked files hold a single action, written at the version this build writes
+const LINKED_ASSET_VERSION: u8 = ACTION_VERSION;
+
+/// A library item loaded from a `LibraryRef` location.
+#[derive(Clone, PartialEq, Debug)]
+pub enum LinkedAsset {
+    Shape(Shape),
+    Bitmap(Bitmap),
+    Texture(TextureDefinition),
+}
+
+impl LinkedAsset {
+    /// Reads an asset written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        match deserialize_action(bytes, LINKED_ASSET_VERSION).map_err(|e| format!("{:?}", e))? {
+            Action::DefineShape { shape, .. } => Ok(LinkedAsset::Shape(shape)),
+            Action::LoadBitmap { bitmap, .. } => Ok(LinkedAsset::Bitmap(bitmap)),
+            Action::LoadTexture { texture, .. } => Ok(LinkedAsset::Texture(texture)),
+            action => Err(format!("{:?} is not a library item", action)),
+        }
+    }
+
+    /// The contents of a linked file. `id` is only recorded for tools reading the file; the
+    /// `LibraryRef` pointing at it decides the id it's loaded under.
+    pub fn to_bytes(&self, id: LibraryId) -> Result<Vec<u8>, String> {
+        let action = match self {
+            LinkedAsset::Shape(shape) => Action::DefineShape {
+                id,
+                shape: shape.clone(),
+            },
+            LinkedAsset::Bitmap(bitmap) => Action::LoadBitmap {
+                id,
+                bitmap: bitmap.clone(),
+            },
+            LinkedAsset::Texture(texture) => Action::LoadTexture {
+                id,
+                texture: texture.clone(),
+            },
+        };
+        serialize_action(&action, LINKED_ASSET_VERSION).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Snapshots a library item so it can be written out and linked.
+    pub fn from_library(library: &Library, id: &LibraryId) -> Result<Self, String> {
+        if let Some(shape) = library.get_shape(id) {
+            Ok(LinkedAsset::Shape((*shape).clone()))
+        } else if let Some(pattern) = library.get_texture(id) {
+            Ok(LinkedAsset::Texture(TextureDefinition::from_pattern(
+                &pattern,
+            )?))
+        } else {
+            Err(format!("Could not find library item {:?}", id))
+        }
+    }
+}
+
+/// Loads the assets `Action::LibraryRef`s point at. Hosts that fetch over the network, or
+/// decode other formats, provide their own.
+pub trait AssetResolver {
+    fn resolve(&self, location: &str) -> Result<LinkedAsset, String>;
+}
+
+/// Resolves locations as paths, relative ones against `root`. URLs are left to host provided
+/// resolvers.
+#[derive(Clone, Debug, Default)]
+pub struct FileAssetResolver {
+    root: PathBuf,
+}
+
+impl FileAssetResolver {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl AssetResolver for FileAssetResolver {
+    fn resolve(&self, location: &str) -> Result<LinkedAsset, String> {
+        if location.contains("://") {
+            return Err(format!(
+                "Can't resolve {}, URLs need a host provided AssetResolver",
+                location
+            ));
+        }
+        let path = self.root.join(location);
+        let bytes =
+            fs::read(&path).map_err(|e| format!("Could not read {}: {:}", path.display(), e))?;
+        LinkedAsset::from_bytes(&bytes)
+    }
+}
+
+/// Writes library item `id` to `path`, ready to be linked with a `LibraryRef`.
+pub fn write_linked_asset(library: &Library, id: &LibraryId, path: &Path) -> Result<(), String> {
+    let bytes = LinkedAsset::from_library(library, id)?.to_bytes(*id)?;
+    fs::write(path, bytes).map_err(|e| format!("Could not write {}: {:}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use palette::LinSrgba;
+
+    #[test]
+    fn it_round_trips_linked_shapes() {
+        let id = LibraryId::new();
+        let shape = Shape::Fill {
+            contours: vec![],
+            color: LinSrgba::new(1.0, 0.0, 0.0, 1.0),
+        };
+        let mut library = Library::default();
+        library.add_shape(id, shape.clone());
+        let bytes = LinkedAsset::from_library(&library, &id)
+            .unwrap()
+            .to_bytes(id)
+            .unwrap();
+        assert_eq!(
+            LinkedAsset::from_bytes(&bytes).unwrap(),
+            LinkedAsset::Shape(shape)
+        );
+    }
+
+    #[test]
+    fn it_leaves_urls_to_host_resolvers() {
+        assert!(FileAssetResolver::default()
+            .resolve("https://example.com/shape.fsa")
+            .is_err());
+    }
+}
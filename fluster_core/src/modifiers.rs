@@ -0,0 +1,161 @@
+use crate::util;
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+use serde::{Deserialize, Serialize};
+
+/// The property of a container a modifier changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ModifierTarget {
+    /// In the container's own units. X and Y wander independently.
+    Translation,
+    /// In radians.
+    Rotation,
+    /// Added to a scale of 1.0.
+    Scale,
+    /// Added to the alpha multiplier of everything the container draws, and its children.
+    Opacity,
+    MorphIndex,
+}
+
+/// Smooth, seeded value noise. The same seed always wanders the same way, so replays and
+/// previews match.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Noise {
+    /// The furthest the noise strays from 0.0, either way.
+    pub amplitude: f32,
+    /// New random values per second. Higher is jumpier.
+    pub frequency: f32,
+    pub seed: u32,
+}
+
+impl Noise {
+    pub fn new(amplitude: f32, frequency: f32, seed: u32) -> Self {
+        Self {
+            amplitude,
+            frequency,
+            seed,
+        }
+    }
+
+    /// The noise `seconds` after it started, between -amplitude and amplitude.
+    pub fn sample(&self, seconds: f32) -> f32 {
+        Self::sample_seeded(self.seed, self.amplitude, self.frequency * seconds)
+    }
+
+    fn sample_seeded(seed: u32, amplitude: f32, position: f32) -> f32 {
+        let index = position.floor();
+        let fraction = position - index;
+        let index = index as i32;
+        // Smoothstep between random values, so the noise has no corners
+        let eased = fraction * fraction * (3.0 - 2.0 * fraction);
+        amplitude * util::lerp(hash(seed, index), hash(seed, index.wrapping_add(1)), eased)
+    }
+
+    // A second, independent stream from the same seed, for the Y of translations
+    fn sample_second(&self, seconds: f32) -> f32 {
+        Self::sample_seeded(
+            self.seed ^ 0x5bd1_e995,
+            self.amplitude,
+            self.frequency * seconds,
+        )
+    }
+}
+
+// Integer hash to [-1.0, 1.0]
+fn hash(seed: u32, index: i32) -> f32 {
+    let mut x = (index as u32).wrapping_mul(0x9e37_79b1) ^ seed.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    (x as f32 / std::u32::MAX as f32) * 2.0 - 1.0
+}
+
+/// Changes a property every frame without keyframes. Modifiers are layered over the property's
+/// value after tweens are applied, so a container can tween across the stage and shake on the way.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Modifier {
+    Noise(ModifierTarget, Noise),
+}
+
+/// What a container's modifiers add up to on one frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModifierOffsets {
+    pub translation: Vector2F,
+    pub rotation: f32,
+    pub scale: f32,
+    pub opacity: f32,
+    pub morph_index: f32,
+}
+
+impl Default for ModifierOffsets {
+    fn default() -> Self {
+        Self {
+            translation: Vector2F::zero(),
+            rotation: 0.0,
+            scale: 1.0,
+            opacity: 0.0,
+            morph_index: 0.0,
+        }
+    }
+}
+
+impl ModifierOffsets {
+    pub fn evaluate(modifiers: &[Modifier], seconds: f32) -> Self {
+        let mut offsets = Self::default();
+        for modifier in modifiers {
+            match modifier {
+                Modifier::Noise(ModifierTarget::Translation, noise) => {
+                    offsets.translation +=
+                        Vector2F::new(noise.sample(seconds), noise.sample_second(seconds));
+                }
+                Modifier::Noise(ModifierTarget::Rotation, noise) => {
+                    offsets.rotation += noise.sample(seconds)
+                }
+                Modifier::Noise(ModifierTarget::Scale, noise) => {
+                    offsets.scale += noise.sample(seconds)
+                }
+                Modifier::Noise(ModifierTarget::Opacity, noise) => {
+                    offsets.opacity += noise.sample(seconds)
+                }
+                Modifier::Noise(ModifierTarget::MorphIndex, noise) => {
+                    offsets.morph_index += noise.sample(seconds)
+                }
+            }
+        }
+        offsets
+    }
+
+    /// Applied after the container's local transform, so rotation and scale wobble around the
+    /// container's origin.
+    pub fn transform(&self) -> Transform2F {
+        Transform2F::from_scale_rotation_translation(
+            Vector2F::splat(self.scale),
+            self.rotation,
+            self.translation,
+        )
+    }
+
+    pub fn changes_transform(&self) -> bool {
+        self.translation != Vector2F::zero() || self.rotation != 0.0 || self.scale != 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_samples_the_same_noise_for_the_same_seed() {
+        let noise = Noise::new(4.0, 3.0, 7);
+        let samples = (0..100)
+            .map(|frame| noise.sample(frame as f32 / 60.0))
+            .collect::<Vec<f32>>();
+        assert!(samples.iter().all(|sample| sample.abs() <= 4.0));
+        assert!(samples.iter().any(|sample| *sample != samples[0]));
+        for (frame, sample) in samples.iter().enumerate() {
+            assert_eq!(Noise::new(4.0, 3.0, 7).sample(frame as f32 / 60.0), *sample);
+        }
+        assert_ne!(Noise::new(4.0, 3.0, 8).sample(0.5), noise.sample(0.5));
+    }
+}
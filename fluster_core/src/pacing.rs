@@ -0,0 +1,146 @@
+use std::time::{Duration, Instant};
+
+/// More whole frames than this between two calls are dropped rather than caught up on, so a long
+/// stall (a breakpoint, a minimized window) doesn't fast forward through the animation.
+const MAX_CATCH_UP_FRAMES: u32 = 8;
+
+/// Where `Runner::next_frame` gets its timing from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PacingMode {
+    /// Every call steps the animation by a fixed number of frames and returns
+    /// `FrameResult::Wait` for the rest of that time. Playback is deterministic, which suits
+    /// headless rendering and hosts without vsync.
+    Timed,
+    /// The host paces the calls, usually by blocking on a vsynced present, and never waits.
+    /// Animation steps by however many whole frames of wall time have passed since the last call,
+    /// so playback speed doesn't depend on the display's refresh rate.
+    Vsync,
+}
+
+/// How often the runner steps animation and draws.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FramePacing {
+    pub mode: PacingMode,
+    /// Caps how often frames are drawn. Animation keeps the action list's frame rate by stepping
+    /// several frames per draw. Useful to save battery while idle.
+    pub max_fps: Option<f32>,
+    /// Skips drawing frames where nothing in the scene changed. The previous frame has to still
+    /// be on screen for that to be correct, so hosts that lose their surface contents should call
+    /// `Runner::request_redraw`.
+    pub render_on_demand: bool,
+}
+
+impl Default for FramePacing {
+    fn default() -> Self {
+        Self {
+            mode: PacingMode::Timed,
+            max_fps: None,
+            render_on_demand: false,
+        }
+    }
+}
+
+impl FramePacing {
+    /// The shortest time allowed between draws.
+    pub fn min_draw_interval(&self) -> Duration {
+        self.max_fps
+            .filter(|max_fps| *max_fps > 0.0)
+            .map(|max_fps| Duration::from_secs_f32(1.0 / max_fps))
+            .unwrap_or_default()
+    }
+
+    /// Frames to step per call in `PacingMode::Timed`. Without a cap this is always 1.
+    pub fn timed_steps(&self, frame_duration: Duration) -> u32 {
+        if frame_duration == Duration::default() {
+            return 1;
+        }
+        let steps = self.min_draw_interval().as_secs_f64() / frame_duration.as_secs_f64();
+        (steps.ceil() as u32).max(1)
+    }
+}
+
+/// Converts wall time between calls into whole animation frames for `PacingMode::Vsync`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FrameClock {
+    last_tick: Option<Instant>,
+    // Time not yet spent on a whole frame
+    remainder: Duration,
+    last_draw: Option<Instant>,
+}
+
+impl FrameClock {
+    /// The first tick always steps a single frame.
+    pub(crate) fn tick(&mut self, now: Instant, frame_duration: Duration) -> u32 {
+        let last_tick = match self.last_tick.replace(now) {
+            Some(last_tick) => last_tick,
+            None => return 1,
+        };
+        if frame_duration == Duration::default() {
+            return 1;
+        }
+        self.remainder += now.saturating_duration_since(last_tick);
+        let steps = (self.remainder.as_secs_f64() / frame_duration.as_secs_f64()).floor() as u32;
+        if steps > MAX_CATCH_UP_FRAMES {
+            self.remainder = Duration::default();
+            MAX_CATCH_UP_FRAMES
+        } else {
+            self.remainder -= frame_duration * steps;
+            steps
+        }
+    }
+
+    /// When the next draw is allowed, if that is still in the future.
+    pub(crate) fn draw_blocked_until(
+        &self,
+        now: Instant,
+        min_interval: Duration,
+    ) -> Option<Instant> {
+        self.last_draw
+            .map(|last_draw| last_draw + min_interval)
+            .filter(|allowed| *allowed > now)
+    }
+
+    pub(crate) fn record_draw(&mut self, now: Instant) {
+        self.last_draw = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_steps_several_frames_per_capped_draw() {
+        let frame_duration = Duration::from_millis(10);
+        let mut pacing = FramePacing::default();
+        assert_eq!(pacing.timed_steps(frame_duration), 1);
+        pacing.max_fps = Some(40.0);
+        assert_eq!(pacing.timed_steps(frame_duration), 3);
+        pacing.max_fps = Some(1000.0);
+        assert_eq!(pacing.timed_steps(frame_duration), 1);
+    }
+
+    #[test]
+    fn it_carries_partial_frames_between_ticks() {
+        let frame_duration = Duration::from_millis(10);
+        let start = Instant::now();
+        let mut clock = FrameClock::default();
+        assert_eq!(clock.tick(start, frame_duration), 1);
+        assert_eq!(
+            clock.tick(start + Duration::from_millis(6), frame_duration),
+            0
+        );
+        assert_eq!(
+            clock.tick(start + Duration::from_millis(12), frame_duration),
+            1
+        );
+        assert_eq!(
+            clock.tick(start + Duration::from_millis(35), frame_duration),
+            2
+        );
+        assert_eq!(
+            clock.tick(start + Duration::from_secs(10), frame_duration),
+            MAX_CATCH_UP_FRAMES
+        );
+    }
+}
@@ -0,0 +1,41 @@
+//! The types most hosts need to build and play an action list.
+//!
+//! ```
+//! use fluster_core::prelude::*;
+//! use fluster_core::rendering::RecordingRenderer;
+//! use pathfinder_geometry::vector::Vector2F;
+//! use std::time::Duration;
+//!
+//! let actions = vec![
+//!     Action::CreateRoot(ContainerId::new()),
+//!     Action::EndInitialization,
+//!     Action::PresentFrame(0, 1),
+//! ];
+//! let mut action_list = ActionList::new(Box::new(|| None), Some(&actions));
+//! let mut runner = Runner::initialize(
+//!     &mut action_list,
+//!     Duration::from_secs_f64(1.0 / 60.0),
+//!     Vector2F::new(800.0, 600.0),
+//! )?;
+//! let mut renderer = RecordingRenderer::default();
+//! runner.next_frame(&mut renderer, &mut action_list)?;
+//! assert_eq!(renderer.frame_count(), 1);
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! See `examples/headless.rs` for a scene that actually draws something.
+
+pub use crate::actions::{
+    Action, ActionList, ContainerCreationDefintition, ContainerCreationProperty,
+    ContainerUpdateDefintition, ContainerUpdateProperty,
+};
+pub use crate::ecs::components::KeyModifiers;
+pub use crate::engine::Engine;
+pub use crate::rendering::Renderer;
+pub use crate::runner::{FrameCapture, FrameResult, MarkerEvent, Runner, Viewport};
+pub use crate::tween::Easing;
+pub use crate::types::{
+    basic::{ContainerId, LibraryId, ScaleRotationTranslation},
+    coloring::{ColorSpace, Coloring},
+    shapes::{Edge, Shape},
+};
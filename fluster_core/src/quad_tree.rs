@@ -2,7 +2,8 @@ use crate::util::{distance_from_abb, ray_aabb_intersect};
 use fmt::Debug;
 use pathfinder_geometry::{rect::RectF, vector::Vector2F};
 use std::{
-    collections::HashMap,
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
     fmt,
     hash::{BuildHasher, Hash},
     mem,
@@ -100,12 +101,14 @@ impl<T: Eq + PartialEq + Hash + Clone + Copy + Debug, S: BuildHasher> QuadTree<T
             ref mut rect_cache,
         } = self;
 
-        if root.insert(item, aabb, config) {
-            rect_cache.insert(item, aabb);
-            return Some(());
-        } else {
+        // Checked up front, since the duplicate may have been pushed into a different node than
+        // the one the new item lands in
+        if !config.allow_duplicates && root.contains_duplicate(&aabb, config) {
             return None;
         }
+        root.insert(item, aabb, config);
+        rect_cache.insert(item, aabb);
+        Some(())
     }
 
     /// Returns a vector of (element, bounding-box, id) for each element
@@ -142,6 +145,91 @@ impl<T: Eq + PartialEq + Hash + Clone + Copy + Debug, S: BuildHasher> QuadTree<T
         nodes
     }
 
+    /// Returns up to `count` (element, bounding-box) pairs ordered by the distance from
+    /// `query_point` to their bounding box, closest first. Elements containing the point have a
+    /// distance of 0.
+    pub fn query_nearest(&self, query_point: &Vector2F, count: usize) -> Vec<(T, RectF)> {
+        let mut nodes = Vec::with_capacity(count);
+        if count == 0 {
+            return nodes;
+        }
+        // Best first search. A node's distance is a lower bound for everything inside it, so
+        // once an element is the closest thing in the queue nothing unvisited can beat it.
+        let mut queue = BinaryHeap::new();
+        queue.push(NearestCandidate {
+            distance: distance_from_abb(*query_point, self.root.aabb),
+            item: NearestItem::Node(&self.root),
+        });
+        while let Some(NearestCandidate { item, .. }) = queue.pop() {
+            match item {
+                NearestItem::Element(element, aabb) => {
+                    nodes.push((element, aabb));
+                    if nodes.len() == count {
+                        break;
+                    }
+                }
+                NearestItem::Node(node) => {
+                    queue.extend(
+                        node.elements
+                            .iter()
+                            .map(|&(element, aabb)| NearestCandidate {
+                                distance: distance_from_abb(*query_point, aabb),
+                                item: NearestItem::Element(element, aabb),
+                            }),
+                    );
+                    if let Some(ref children) = node.children {
+                        queue.extend(children.iter().map(|child| NearestCandidate {
+                            distance: distance_from_abb(*query_point, child.aabb),
+                            item: NearestItem::Node(child),
+                        }));
+                    }
+                }
+            }
+        }
+        nodes
+    }
+
+    /// Returns every pair of elements, one from this tree and one from `other`, whose bounding
+    /// boxes intersect.
+    pub fn query_pairs<S2: BuildHasher>(&self, other: &QuadTree<T, S2>) -> Vec<(T, T)> {
+        sweep_pairs(self.sorted_by_min_x(), Some(other.sorted_by_min_x()))
+    }
+
+    /// Returns every pair of distinct elements in this tree whose bounding boxes intersect. Each
+    /// pair is only reported once.
+    pub fn query_self_pairs(&self) -> Vec<(T, T)> {
+        sweep_pairs(self.sorted_by_min_x(), None)
+    }
+
+    fn sorted_by_min_x(&self) -> Vec<(T, RectF)> {
+        let mut items = self
+            .rect_cache
+            .iter()
+            .map(|(item, aabb)| (*item, *aabb))
+            .collect::<Vec<(T, RectF)>>();
+        items
+            .sort_by(|(_, a), (_, b)| a.min_x().partial_cmp(&b.min_x()).unwrap_or(Ordering::Equal));
+        items
+    }
+
+    /// Returns the bounding-box the item was inserted with.
+    pub fn get(&self, item: &T) -> Option<RectF> {
+        self.rect_cache.get(item).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rect_cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rect_cache.is_empty()
+    }
+
+    /// The number of nodes in the tree, including the root.
+    pub fn node_count(&self) -> usize {
+        self.root.node_count()
+    }
+
     /// Attempts to remove the item from the tree.  If that
     /// item was present, it returns the bounding-box of the removed item
     pub fn remove(&mut self, item: &T) -> Option<RectF> {
@@ -157,9 +245,89 @@ impl<T: Eq + PartialEq + Hash + Clone + Copy + Debug, S: BuildHasher> QuadTree<T
     pub fn bounding_box(&self) -> RectF {
         self.root.bounding_box()
     }
+
+    /// The bounding-box of every node, parents before their children.
+    pub fn node_aabbs(&self) -> impl Iterator<Item = RectF> + '_ {
+        self.nodes().map(|node| node.aabb)
+    }
+
+    /// A snapshot of how items are spread through the tree.
+    pub fn stats(&self) -> QuadTreeStats {
+        let mut stats = QuadTreeStats {
+            element_count: self.len(),
+            ..QuadTreeStats::default()
+        };
+        for node in self.nodes() {
+            if stats.depth_histogram.len() <= node.depth {
+                stats.depth_histogram.resize(node.depth + 1, 0);
+            }
+            stats.depth_histogram[node.depth] += 1;
+            stats.node_count += 1;
+            if node.children.is_none() {
+                stats.leaf_count += 1;
+            } else {
+                stats.branch_element_count += node.elements.len();
+            }
+            stats.elements_per_node.push(node.elements.len());
+        }
+        stats
+    }
+
+    fn nodes(&self) -> QuadNodeIter<T> {
+        QuadNodeIter {
+            stack: vec![&self.root],
+        }
+    }
+}
+
+/// Returned by `QuadTree::stats`. Nodes are counted parents before their children, in the same
+/// order as `QuadTree::node_aabbs`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuadTreeStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub element_count: usize,
+    /// Items held by nodes that have children. These are items that straddle a split, and every
+    /// query that reaches the node has to test them. A large share of the total usually means the
+    /// layer's dilation is too generous.
+    pub branch_element_count: usize,
+    /// Number of nodes at each depth, with the root at depth 0.
+    pub depth_histogram: Vec<usize>,
+    /// Items held directly by each node, not counting its children.
+    pub elements_per_node: Vec<usize>,
+}
+
+impl QuadTreeStats {
+    pub fn max_depth(&self) -> usize {
+        self.depth_histogram.len().saturating_sub(1)
+    }
+}
+
+struct QuadNodeIter<'a, T: Eq + PartialEq + Clone + Copy + Debug> {
+    stack: Vec<&'a QuadNode<T>>,
+}
+
+impl<'a, T: Eq + PartialEq + Clone + Copy + Debug> Iterator for QuadNodeIter<'a, T> {
+    type Item = &'a QuadNode<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        if let Some(children) = &node.children {
+            // Reversed so the first child comes out next
+            self.stack
+                .extend(children.iter().rev().map(|child| child.as_ref()));
+        }
+        Some(node)
+    }
 }
 
 impl<T: Eq + PartialEq + Clone + Copy + Debug> QuadNode<T> {
+    fn node_count(&self) -> usize {
+        1 + self.children.as_ref().map_or(0, |children| {
+            children.iter().map(|child| child.node_count()).sum()
+        })
+    }
+
     fn bounding_box(&self) -> RectF {
         self.aabb
     }
@@ -174,15 +342,15 @@ impl<T: Eq + PartialEq + Clone + Copy + Debug> QuadNode<T> {
         }
     }
 
-    fn insert(&mut self, item: T, item_aabb: RectF, config: &QuadTreeConfig) -> bool {
+    fn insert(&mut self, item: T, item_aabb: RectF, config: &QuadTreeConfig) {
         // Assert that this insert is valid.
         assert!(item_aabb.width() <= self.aabb.width() && item_aabb.height() <= self.aabb.height());
 
-        let inserted = if item_aabb.contains_point(self.aabb.center())
+        if item_aabb.contains_point(self.aabb.center())
             || self.depth == config.max_depth
             || self.elements.len() < config.max_children - 1
         {
-            self.attempt_insert_self(item, item_aabb, config)
+            self.elements.push((item, item_aabb));
         } else {
             if self.children.is_none() {
                 let split = split_quad(self.aabb);
@@ -200,27 +368,29 @@ impl<T: Eq + PartialEq + Clone + Copy + Debug> QuadNode<T> {
                 self.elements = elements;
             }
             // Try to fit this item into a child. If it doesn't fit, put it in
-            self.attempt_insert_children(item, item_aabb, config)
-                || self.attempt_insert_self(item, item_aabb, config)
-        };
-        if inserted {
-            self.sub_tree_count += 1;
+            if !self.attempt_insert_children(item, item_aabb, config) {
+                self.elements.push((item, item_aabb));
+            }
         }
-        inserted
+        self.sub_tree_count += 1;
     }
 
-    fn attempt_insert_self(&mut self, item: T, item_aabb: RectF, config: &QuadTreeConfig) -> bool {
-        if config.allow_duplicates
-            || self
-                .elements
-                .iter()
-                .any(|&(_, e_bb)| close_to_rect(e_bb, item_aabb, config.epsilon))
+    // A duplicate is an element with the same bounds as one already in the tree. Elements with
+    // the same bounds take the same path down, so only that path is searched.
+    fn contains_duplicate(&self, item_aabb: &RectF, config: &QuadTreeConfig) -> bool {
+        if self
+            .elements
+            .iter()
+            .any(|&(_, e_bb)| close_to_rect(e_bb, *item_aabb, config.epsilon))
         {
-            self.elements.push((item, item_aabb));
-            true
-        } else {
-            false
+            return true;
         }
+        self.children.as_ref().map_or(false, |children| {
+            children
+                .iter()
+                .filter(|child| child.aabb.contains_rect(*item_aabb))
+                .any(|child| child.contains_duplicate(item_aabb, config))
+        })
     }
 
     fn attempt_insert_children(
@@ -337,9 +507,9 @@ impl<T: Eq + PartialEq + Clone + Copy + Debug> QuadNode<T> {
     ) {
         if let Some(ref children) = self.children {
             for child in children.iter() {
+                // Unlike a point, a ray can pass through several children
                 if ray_aabb_intersect(*origin, *direction, child.aabb) {
                     child.query_ray(origin, direction, config, out);
-                    break; // A point can only be contained by 1 child.
                 }
             }
         }
@@ -351,6 +521,91 @@ impl<T: Eq + PartialEq + Clone + Copy + Debug> QuadNode<T> {
     }
 }
 
+enum NearestItem<'a, T: Eq + PartialEq + Clone + Copy + Debug> {
+    Node(&'a QuadNode<T>),
+    Element(T, RectF),
+}
+
+struct NearestCandidate<'a, T: Eq + PartialEq + Clone + Copy + Debug> {
+    distance: f32,
+    item: NearestItem<'a, T>,
+}
+
+impl<'a, T: Eq + PartialEq + Clone + Copy + Debug> Ord for NearestCandidate<'a, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so that BinaryHeap pops the closest candidate. On ties elements come out
+        // before nodes, since nothing in the node can be any closer.
+        other
+            .distance
+            .partial_cmp(&self.distance)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| match (&self.item, &other.item) {
+                (NearestItem::Element(..), NearestItem::Node(_)) => Ordering::Greater,
+                (NearestItem::Node(_), NearestItem::Element(..)) => Ordering::Less,
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+impl<'a, T: Eq + PartialEq + Clone + Copy + Debug> PartialOrd for NearestCandidate<'a, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, T: Eq + PartialEq + Clone + Copy + Debug> PartialEq for NearestCandidate<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a, T: Eq + PartialEq + Clone + Copy + Debug> Eq for NearestCandidate<'a, T> {}
+
+// Sweep and prune along x. Both inputs must be sorted by min_x. Without `b`, pairs are found
+// within `a`.
+fn sweep_pairs<T: Copy>(a: Vec<(T, RectF)>, b: Option<Vec<(T, RectF)>>) -> Vec<(T, T)> {
+    let self_pairs = b.is_none();
+    let b = b.unwrap_or_default();
+    let mut pairs = vec![];
+    let mut active_a: Vec<(T, RectF)> = vec![];
+    let mut active_b: Vec<(T, RectF)> = vec![];
+    let (mut index_a, mut index_b) = (0, 0);
+    while index_a < a.len() || index_b < b.len() {
+        let from_a = index_b >= b.len()
+            || (index_a < a.len() && a[index_a].1.min_x() <= b[index_b].1.min_x());
+        let (item, aabb) = if from_a {
+            index_a += 1;
+            a[index_a - 1]
+        } else {
+            index_b += 1;
+            b[index_b - 1]
+        };
+        active_a.retain(|(_, active)| active.max_x() >= aabb.min_x());
+        active_b.retain(|(_, active)| active.max_x() >= aabb.min_x());
+        if from_a {
+            let opposite = if self_pairs { &active_a } else { &active_b };
+            for (other, other_aabb) in opposite.iter() {
+                if aabb.intersects(*other_aabb) {
+                    pairs.push(if self_pairs {
+                        (*other, item)
+                    } else {
+                        (item, *other)
+                    });
+                }
+            }
+            active_a.push((item, aabb));
+        } else {
+            for (other, other_aabb) in active_a.iter() {
+                if aabb.intersects(*other_aabb) {
+                    pairs.push((*other, item));
+                }
+            }
+            active_b.push((item, aabb));
+        }
+    }
+    pairs
+}
+
 fn split_quad(rect: RectF) -> [RectF; 4] {
     let origin = rect.origin();
     let half = rect.size() / 2.0;
@@ -371,3 +626,103 @@ fn close_to_rect(a: RectF, b: RectF, epsilon: f32) -> bool {
     close_to_point(a.origin(), b.origin(), epsilon)
         && close_to_point(a.lower_right(), b.lower_right(), epsilon)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::RandomState;
+
+    fn new_tree(allow_duplicates: bool) -> QuadTree<u32, RandomState> {
+        QuadTree::new(
+            RectF::new(Vector2F::zero(), Vector2F::splat(100.0)),
+            allow_duplicates,
+            1,
+            2,
+            8,
+            RandomState::new(),
+        )
+    }
+
+    fn rect(x: f32, y: f32) -> RectF {
+        RectF::new(Vector2F::new(x, y), Vector2F::splat(1.0))
+    }
+
+    #[test]
+    fn it_rejects_duplicate_bounds_when_not_allowed() {
+        let mut tree = new_tree(false);
+        assert_eq!(tree.insert(0, rect(10.0, 10.0)), Some(()));
+        assert_eq!(tree.insert(1, rect(10.0, 10.0)), None);
+        assert_eq!(tree.insert(2, rect(20.0, 20.0)), Some(()));
+        // The tree has split, so the first element now lives in a child
+        assert!(tree.node_count() > 1);
+        assert_eq!(tree.insert(3, rect(10.0, 10.0)), None);
+        assert_eq!(tree.query_point(&Vector2F::splat(10.5)).len(), 1);
+
+        let mut tree = new_tree(true);
+        assert_eq!(tree.insert(0, rect(10.0, 10.0)), Some(()));
+        assert_eq!(tree.insert(1, rect(10.0, 10.0)), Some(()));
+        assert_eq!(tree.query_point(&Vector2F::splat(10.5)).len(), 2);
+    }
+
+    #[test]
+    fn it_returns_nearest_in_distance_order() {
+        let mut tree = new_tree(true);
+        // Enough elements to force the tree to split
+        for i in 0..10 {
+            tree.insert(i, rect(i as f32 * 9.0, i as f32 * 9.0));
+        }
+        let nearest = tree.query_nearest(&Vector2F::new(40.0, 40.0), 3);
+        let ids = nearest.iter().map(|(id, _)| *id).collect::<Vec<u32>>();
+        assert_eq!(ids[0], 4);
+        assert_eq!(ids.len(), 3);
+        assert!(ids.contains(&5));
+        assert!(ids.contains(&3));
+        assert_eq!(tree.query_nearest(&Vector2F::zero(), 20).len(), 10);
+        assert!(tree.query_nearest(&Vector2F::zero(), 0).is_empty());
+    }
+
+    #[test]
+    fn it_reports_node_stats() {
+        let mut tree = new_tree(true);
+        for i in 0..10 {
+            tree.insert(i, rect(i as f32 * 9.0, i as f32 * 9.0));
+        }
+        let rects = tree.node_aabbs().collect::<Vec<RectF>>();
+        assert!(rects.len() > 1);
+        assert_eq!(rects.len(), tree.node_count());
+        assert_eq!(rects[0], tree.bounding_box());
+        let stats = tree.stats();
+        assert_eq!(stats.node_count, rects.len());
+        assert_eq!(stats.element_count, 10);
+        assert_eq!(stats.elements_per_node.iter().sum::<usize>(), 10);
+        assert_eq!(stats.depth_histogram[0], 1);
+        assert_eq!(
+            stats.depth_histogram.iter().sum::<usize>(),
+            stats.node_count
+        );
+        assert!(stats.max_depth() >= 1);
+    }
+
+    #[test]
+    fn it_finds_overlapping_pairs() {
+        let mut tree_a = new_tree(true);
+        let mut tree_b = new_tree(true);
+        tree_a.insert(0, RectF::new(Vector2F::zero(), Vector2F::splat(10.0)));
+        tree_a.insert(1, RectF::new(Vector2F::splat(5.0), Vector2F::splat(10.0)));
+        tree_a.insert(2, RectF::new(Vector2F::splat(50.0), Vector2F::splat(10.0)));
+        tree_b.insert(
+            10,
+            RectF::new(Vector2F::new(12.0, 12.0), Vector2F::splat(5.0)),
+        );
+        tree_b.insert(
+            11,
+            RectF::new(Vector2F::new(55.0, 0.0), Vector2F::splat(5.0)),
+        );
+
+        assert_eq!(tree_a.query_pairs(&tree_b), vec![(1, 10)]);
+        assert_eq!(tree_b.query_pairs(&tree_a), vec![(10, 1)]);
+        let self_pairs = tree_a.query_self_pairs();
+        assert_eq!(self_pairs.len(), 1);
+        assert!(self_pairs.contains(&(0, 1)) || self_pairs.contains(&(1, 0)));
+    }
+}
@@ -1,35 +1,170 @@
+//! Colors are linear light everywhere in the core: shapes, colorings, color transforms and
+//! backgrounds all store and blend `LinSrgb(a)`. Nothing is gamma encoded until it reaches a
+//! `Renderer`, which encodes for its output with `OutputColorSpace::encode`. Authored colors
+//! picked in sRGB have to be linearized on the way in, with `Srgb::into_linear`.
+
+use super::actions::BackgroundDefinition;
 use super::types::{coloring::Coloring, shapes::Shape};
-use crate::engine::{Engine, LibraryItem};
-use palette::{IntoComponent, LinSrgb, LinSrgba};
+use crate::ecs::resources::{Camera, Library, RenderQuality};
+use crate::engine::{DrawableItem, Engine, LibraryItem};
+use palette::{IntoComponent, LinSrgb, LinSrgba, Srgb};
 use pathfinder_color::ColorU;
 use pathfinder_content::pattern::Pattern;
 use pathfinder_geometry::rect::RectF;
 use pathfinder_geometry::transform2d::Transform2F;
-use pathfinder_geometry::vector::Vector2F;
-use std::sync::Arc;
-
-pub fn lin_srgb_to_coloru(rgb: LinSrgb) -> ColorU {
-    let components = rgb.into_components();
-    ColorU::new(
-        components.0.into_component(),
-        components.1.into_component(),
-        components.2.into_component(),
-        255,
-    )
-}
-
-pub fn lin_srgba_to_coloru(rgba: LinSrgba) -> ColorU {
-    let components = rgba.into_components();
-    ColorU::new(
-        components.0.into_component(),
-        components.1.into_component(),
-        components.2.into_component(),
-        components.3.into_component(),
-    )
+use pathfinder_geometry::vector::{Vector2F, Vector2I};
+use std::{mem, sync::Arc};
+
+/// How a renderer encodes linear colors for the surface it draws to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputColorSpace {
+    /// Encoded with the sRGB transfer function, for ordinary 8 bit surfaces.
+    Srgb,
+    /// Components passed through as Display P3 with no gamut mapping, so saturated colors use the
+    /// whole wider gamut. P3 shares sRGB's transfer function, so this encodes the same bytes as
+    /// `Srgb`; it's for hosts that tag their surface as P3.
+    // TODO: tag Metal layers as P3 when this is picked
+    DisplayP3,
+    /// No transfer function, for framebuffers that encode on write, or linear captures.
+    Linear,
+}
+
+impl Default for OutputColorSpace {
+    fn default() -> Self {
+        OutputColorSpace::Srgb
+    }
+}
+
+impl OutputColorSpace {
+    /// Alpha is coverage, not light, so it's never encoded.
+    pub fn encode(&self, color: LinSrgba) -> ColorU {
+        let (red, green, blue, alpha) = color.into_components();
+        let (red, green, blue) = match self {
+            OutputColorSpace::Srgb | OutputColorSpace::DisplayP3 => {
+                Srgb::from_linear(LinSrgb::new(red, green, blue)).into_components()
+            }
+            OutputColorSpace::Linear => (red, green, blue),
+        };
+        ColorU::new(
+            red.into_component(),
+            green.into_component(),
+            blue.into_component(),
+            alpha.into_component(),
+        )
+    }
 }
+
+/// An opaque version of `color`, for backgrounds.
+pub fn opaque(color: LinSrgb) -> LinSrgba {
+    let (red, green, blue) = color.into_components();
+    LinSrgba::new(red, green, blue, 1.0)
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeInstance {
+    pub transform: Transform2F,
+    pub color_override: Option<Coloring>,
+    pub morph_index: f32,
+    /// Overrides `morph_index` for each shape of a group, by position. See `MorphIndices`.
+    pub morph_indices: Vec<f32>,
+}
+
+/// Clips drawing to `rect`, given in the space `transform` maps to the stage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipRect {
+    pub rect: RectF,
+    pub transform: Transform2F,
+    /// How much of the camera applies to the clip. See `Parallax`.
+    pub parallax: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RasterInstance {
+    pub view_rect: Option<RectF>,
+    pub transform: Transform2F,
+    pub tint: Option<Coloring>,
+}
+
+/// A `BackgroundDefinition` with its texture looked up, ready to draw.
+#[derive(Clone, Debug)]
+pub enum BackgroundLayer {
+    Solid(LinSrgba),
+    VerticalGradient { top: LinSrgba, bottom: LinSrgba },
+    RadialGradient { inner: LinSrgba, outer: LinSrgba },
+    Raster { pattern: Arc<Pattern>, tiled: bool },
+}
+
+impl PartialEq for BackgroundLayer {
+    // Textures are compared by identity, like the library items in draw calls
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Solid(color), Self::Solid(other_color)) => color == other_color,
+            (
+                Self::VerticalGradient { top, bottom },
+                Self::VerticalGradient {
+                    top: other_top,
+                    bottom: other_bottom,
+                },
+            ) => top == other_top && bottom == other_bottom,
+            (
+                Self::RadialGradient { inner, outer },
+                Self::RadialGradient {
+                    inner: other_inner,
+                    outer: other_outer,
+                },
+            ) => inner == other_inner && outer == other_outer,
+            (
+                Self::Raster { pattern, tiled },
+                Self::Raster {
+                    pattern: other_pattern,
+                    tiled: other_tiled,
+                },
+            ) => Arc::ptr_eq(pattern, other_pattern) && tiled == other_tiled,
+            _ => false,
+        }
+    }
+}
+
+/// Returns `None` if a raster background's texture is not in the library.
+pub fn resolve_background(
+    definition: &BackgroundDefinition,
+    library: &Library,
+) -> Option<BackgroundLayer> {
+    let layer = match definition {
+        BackgroundDefinition::Solid(color) => BackgroundLayer::Solid(opaque(*color)),
+        BackgroundDefinition::VerticalGradient { top, bottom } => {
+            BackgroundLayer::VerticalGradient {
+                top: opaque(*top),
+                bottom: opaque(*bottom),
+            }
+        }
+        BackgroundDefinition::RadialGradient { inner, outer } => BackgroundLayer::RadialGradient {
+            inner: opaque(*inner),
+            outer: opaque(*outer),
+        },
+        BackgroundDefinition::Raster { id, tiled } => BackgroundLayer::Raster {
+            pattern: library.get_texture(id)?,
+            tiled: *tiled,
+        },
+    };
+    Some(layer)
+}
+
+/// A run of consecutive drawables that share the same library item.
+#[derive(Debug)]
+pub enum DrawBatch {
+    Vector(Arc<Shape>, Vec<ShapeInstance>),
+    Raster(Arc<Pattern>, Vec<RasterInstance>),
+}
+
 pub trait Renderer {
     fn start_frame(&mut self, stage_size: Vector2F);
-    fn set_background(&mut self, color: ColorU);
+    /// Called every frame, after `start_frame`. Renderers that leave flattening to their backend
+    /// can ignore it.
+    fn set_render_quality(&mut self, _render_quality: RenderQuality) {}
+    /// The color the frame is cleared to, in linear light like every other color.
+    fn set_background(&mut self, color: LinSrgba);
+    /// Fills the whole stage. Called after `set_background` and before any containers are drawn.
+    fn draw_background(&mut self, layer: BackgroundLayer);
     fn draw_shape(
         &mut self,
         shape: Arc<Shape>,
@@ -37,6 +172,19 @@ pub trait Renderer {
         color_override: Option<Coloring>,
         morph_index: f32,
     );
+    /// Draws the same shape once per instance, in order. Implementations can override this to
+    /// build the shape's geometry once and reuse it for every instance. The default drops each
+    /// instance's `morph_indices`, so renderers that support them have to override it.
+    fn draw_shape_instanced(&mut self, shape: Arc<Shape>, instances: Vec<ShapeInstance>) {
+        for instance in instances {
+            self.draw_shape(
+                shape.clone(),
+                instance.transform,
+                instance.color_override,
+                instance.morph_index,
+            );
+        }
+    }
     fn draw_raster(
         &mut self,
         pattern: Arc<Pattern>,
@@ -44,28 +192,472 @@ pub trait Renderer {
         transform: Transform2F,
         tint: Option<Coloring>,
     );
+    /// Clips everything drawn until the matching `pop_clip`, on top of any clips already pushed.
+    /// Renderers that can't clip can ignore these, and draw scrolled content unclipped.
+    fn push_clip(&mut self, _clip: ClipRect) {}
+    fn pop_clip(&mut self) {}
     fn end_frame(&mut self);
+    /// Asks for the next frame to be read back as it ends, before it's presented. Renderers
+    /// without a read back path can ignore it.
+    fn request_read_back(&mut self) {}
+    /// The frame read back since the last call, if one was requested.
+    fn take_read_back(&mut self) -> Option<Result<RgbaBuffer, String>> {
+        None
+    }
 }
 
-pub fn paint(renderer: &mut impl Renderer, engine: &Engine) {
-    for drawable_item in engine.get_drawable_items() {
-        match drawable_item.library_item {
-            LibraryItem::Vector(shape) => {
-                renderer.draw_shape(
-                    shape,
-                    drawable_item.transform,
-                    drawable_item.coloring,
-                    drawable_item.morph,
-                );
+/// Pixels read back from a renderer, as RGBA8 rows.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RgbaBuffer {
+    pub size: Vector2I,
+    pub pixels: Vec<u8>,
+}
+
+/// Groups consecutive drawables that reference the same `Shape` or `Pattern`. Paint order is
+/// preserved, so only neighbours are merged.
+pub fn batch_drawables(drawable_items: Vec<DrawableItem>) -> Vec<DrawBatch> {
+    let mut batches: Vec<DrawBatch> = vec![];
+    for drawable_item in drawable_items {
+        match (drawable_item.library_item, batches.last_mut()) {
+            (LibraryItem::Vector(shape), Some(DrawBatch::Vector(batch_shape, instances)))
+                if Arc::ptr_eq(&shape, batch_shape) =>
+            {
+                instances.push(ShapeInstance {
+                    transform: drawable_item.transform,
+                    color_override: drawable_item.coloring,
+                    morph_index: drawable_item.morph,
+                    morph_indices: drawable_item.morph_indices,
+                });
+            }
+            (LibraryItem::Vector(shape), _) => batches.push(DrawBatch::Vector(
+                shape,
+                vec![ShapeInstance {
+                    transform: drawable_item.transform,
+                    color_override: drawable_item.coloring,
+                    morph_index: drawable_item.morph,
+                    morph_indices: drawable_item.morph_indices,
+                }],
+            )),
+            (LibraryItem::Raster(pattern), Some(DrawBatch::Raster(batch_pattern, instances)))
+                if Arc::ptr_eq(&pattern, batch_pattern) =>
+            {
+                instances.push(RasterInstance {
+                    view_rect: drawable_item.view_rect,
+                    transform: drawable_item.transform,
+                    tint: drawable_item.coloring,
+                });
             }
-            LibraryItem::Raster(pattern) => {
-                renderer.draw_raster(
-                    pattern,
-                    drawable_item.view_rect,
-                    drawable_item.transform,
-                    drawable_item.coloring,
+            (LibraryItem::Raster(pattern), _) => batches.push(DrawBatch::Raster(
+                pattern,
+                vec![RasterInstance {
+                    view_rect: drawable_item.view_rect,
+                    transform: drawable_item.transform,
+                    tint: drawable_item.coloring,
+                }],
+            )),
+        }
+    }
+    batches
+}
+
+/// Draws every displayable container in paint order. Returns the number of items drawn.
+pub fn paint(renderer: &mut impl Renderer, engine: &Engine) -> usize {
+    paint_with_camera(renderer, engine, Transform2F::default())
+}
+
+/// Like `paint`, with `camera` applied on top of every container's world transform and the
+/// engine's `Camera`. Used to map stage units onto device pixels on HiDPI displays. Any debug
+/// overlays enabled in the engine's `DebugFlags` are drawn last, and counted.
+pub fn paint_with_camera(
+    renderer: &mut impl Renderer,
+    engine: &Engine,
+    camera: Transform2F,
+) -> usize {
+    let engine_camera = engine.get_camera();
+    let mut painter = ItemPainter::default();
+    engine.visit_drawables(|mut drawable_item| {
+        apply_camera(&mut drawable_item, &engine_camera, camera);
+        painter.push(renderer, drawable_item);
+    });
+    for mut drawable_item in engine.get_debug_drawables() {
+        apply_camera(&mut drawable_item, &engine_camera, camera);
+        painter.push(renderer, drawable_item);
+    }
+    painter.finish(renderer)
+}
+
+/// Moves a drawable and its clips from stage space into view space with `camera`, scaled by
+/// their parallax, then applies `device` on top.
+pub fn apply_camera(drawable_item: &mut DrawableItem, camera: &Camera, device: Transform2F) {
+    let view = |parallax: f32| device * camera.with_parallax(parallax).transform();
+    drawable_item.transform = view(drawable_item.parallax) * drawable_item.transform;
+    for clip in drawable_item.clips.iter_mut() {
+        clip.transform = view(clip.parallax) * clip.transform;
+    }
+}
+
+/// Draws items already fetched from `Engine::get_drawable_items`. Lets hosts such as the editor
+/// filter or adjust what gets drawn.
+pub fn paint_items(renderer: &mut impl Renderer, drawable_items: Vec<DrawableItem>) -> usize {
+    let mut painter = ItemPainter::default();
+    for drawable_item in drawable_items {
+        painter.push(renderer, drawable_item);
+    }
+    painter.finish(renderer)
+}
+
+// Draws items as they arrive in paint order. Items are only batched with neighbours under the
+// same clips, so each run is painted as soon as the clips change.
+#[derive(Default)]
+struct ItemPainter {
+    clips: Vec<ClipRect>,
+    run: Vec<DrawableItem>,
+    drawable_count: usize,
+}
+
+impl ItemPainter {
+    fn push(&mut self, renderer: &mut impl Renderer, drawable_item: DrawableItem) {
+        if drawable_item.clips != self.clips {
+            paint_batches(
+                renderer,
+                batch_drawables(mem::replace(&mut self.run, vec![])),
+            );
+            set_clips(renderer, &mut self.clips, &drawable_item.clips);
+        }
+        self.run.push(drawable_item);
+        self.drawable_count += 1;
+    }
+
+    // Returns the number of items drawn
+    fn finish(mut self, renderer: &mut impl Renderer) -> usize {
+        paint_batches(
+            renderer,
+            batch_drawables(mem::replace(&mut self.run, vec![])),
+        );
+        set_clips(renderer, &mut self.clips, &[]);
+        self.drawable_count
+    }
+}
+
+fn paint_batches(renderer: &mut impl Renderer, batches: Vec<DrawBatch>) {
+    for batch in batches {
+        match batch {
+            DrawBatch::Vector(shape, instances) => {
+                renderer.draw_shape_instanced(shape, instances);
+            }
+            DrawBatch::Raster(pattern, instances) => {
+                for instance in instances {
+                    renderer.draw_raster(
+                        pattern.clone(),
+                        instance.view_rect,
+                        instance.transform,
+                        instance.tint,
+                    );
+                }
+            }
+        }
+    }
+}
+
+// Pops back to the clips shared with `target`, then pushes the rest of `target`
+fn set_clips(renderer: &mut impl Renderer, current: &mut Vec<ClipRect>, target: &[ClipRect]) {
+    let shared = current
+        .iter()
+        .zip(target.iter())
+        .take_while(|(clip, target_clip)| clip == target_clip)
+        .count();
+    for _ in shared..current.len() {
+        renderer.pop_clip();
+    }
+    for clip in &target[shared..] {
+        renderer.push_clip(*clip);
+    }
+    *current = target.to_vec();
+}
+
+/// A call made on a `RecordingRenderer`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordedCall {
+    StartFrame(Vector2F),
+    SetBackground(LinSrgba),
+    DrawBackground,
+    DrawShape(Arc<Shape>, ShapeInstance),
+    DrawRaster(Arc<Pattern>, RasterInstance),
+    PushClip(ClipRect),
+    PopClip,
+    EndFrame,
+}
+
+/// Keeps every call instead of drawing, so hosts and tests can check what a frame would draw
+/// without a GPU.
+///
+/// ```
+/// use fluster_core::rendering::{RecordedCall, RecordingRenderer, Renderer};
+/// use pathfinder_geometry::vector::Vector2F;
+///
+/// let mut renderer = RecordingRenderer::default();
+/// renderer.start_frame(Vector2F::new(800.0, 600.0));
+/// renderer.end_frame();
+/// assert_eq!(renderer.frame_count(), 1);
+/// assert_eq!(renderer.calls().last(), Some(&RecordedCall::EndFrame));
+/// ```
+#[derive(Debug, Default)]
+pub struct RecordingRenderer {
+    calls: Vec<RecordedCall>,
+}
+
+impl RecordingRenderer {
+    pub fn calls(&self) -> &[RecordedCall] {
+        &self.calls
+    }
+
+    pub fn take_calls(&mut self) -> Vec<RecordedCall> {
+        mem::replace(&mut self.calls, vec![])
+    }
+
+    /// Frames ended since the calls were last taken.
+    pub fn frame_count(&self) -> usize {
+        self.calls
+            .iter()
+            .filter(|call| **call == RecordedCall::EndFrame)
+            .count()
+    }
+
+    /// The calls of the last ended frame, from its `StartFrame` on.
+    pub fn last_frame(&self) -> &[RecordedCall] {
+        let end = match self
+            .calls
+            .iter()
+            .rposition(|call| *call == RecordedCall::EndFrame)
+        {
+            Some(end) => end + 1,
+            None => return &[],
+        };
+        let start = self.calls[..end]
+            .iter()
+            .rposition(|call| matches!(call, RecordedCall::StartFrame(_)))
+            .unwrap_or(0);
+        &self.calls[start..end]
+    }
+}
+
+impl Renderer for RecordingRenderer {
+    fn start_frame(&mut self, stage_size: Vector2F) {
+        self.calls.push(RecordedCall::StartFrame(stage_size));
+    }
+
+    fn set_background(&mut self, color: LinSrgba) {
+        self.calls.push(RecordedCall::SetBackground(color));
+    }
+
+    fn draw_background(&mut self, _layer: BackgroundLayer) {
+        self.calls.push(RecordedCall::DrawBackground);
+    }
+
+    fn draw_shape(
+        &mut self,
+        shape: Arc<Shape>,
+        transform: Transform2F,
+        color_override: Option<Coloring>,
+        morph_index: f32,
+    ) {
+        self.draw_shape_instanced(
+            shape,
+            vec![ShapeInstance {
+                transform,
+                color_override,
+                morph_index,
+                morph_indices: vec![],
+            }],
+        );
+    }
+
+    // Recorded per instance, so batching doesn't change what's recorded
+    fn draw_shape_instanced(&mut self, shape: Arc<Shape>, instances: Vec<ShapeInstance>) {
+        for instance in instances {
+            self.calls
+                .push(RecordedCall::DrawShape(shape.clone(), instance));
+        }
+    }
+
+    fn draw_raster(
+        &mut self,
+        pattern: Arc<Pattern>,
+        view_rect: Option<RectF>,
+        transform: Transform2F,
+        tint: Option<Coloring>,
+    ) {
+        self.calls.push(RecordedCall::DrawRaster(
+            pattern,
+            RasterInstance {
+                view_rect,
+                transform,
+                tint,
+            },
+        ));
+    }
+
+    fn push_clip(&mut self, clip: ClipRect) {
+        self.calls.push(RecordedCall::PushClip(clip));
+    }
+
+    fn pop_clip(&mut self) {
+        self.calls.push(RecordedCall::PopClip);
+    }
+
+    fn end_frame(&mut self) {
+        self.calls.push(RecordedCall::EndFrame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        basic::ContainerId,
+        shapes::{Contour, Edge},
+    };
+    use palette::LinSrgba;
+
+    fn drawable(shape: &Arc<Shape>, x: f32) -> DrawableItem {
+        DrawableItem {
+            container_id: ContainerId::new(),
+            library_item: LibraryItem::Vector(shape.clone()),
+            transform: Transform2F::from_translation(Vector2F::new(x, 0.0)),
+            coloring: None,
+            view_rect: None,
+            morph: 0.0,
+            morph_indices: vec![],
+            clips: vec![],
+            parallax: 1.0,
+        }
+    }
+
+    #[test]
+    fn it_batches_consecutive_shapes() {
+        let square = Arc::new(Shape::Fill {
+            contours: Contour::from_edges(&Edge::new_rect(
+                Vector2F::splat(10.0),
+                Transform2F::default(),
+            )),
+            color: LinSrgba::new(1.0, 0.0, 0.0, 1.0),
+        });
+        let identical_square = Arc::new((*square).clone());
+        let batches = batch_drawables(vec![
+            drawable(&square, 0.0),
+            drawable(&square, 1.0),
+            drawable(&identical_square, 2.0),
+            drawable(&square, 3.0),
+        ]);
+        // Batching is by identity, not equality, and never reorders
+        assert_eq!(batches.len(), 3);
+        match &batches[0] {
+            DrawBatch::Vector(shape, instances) => {
+                assert!(Arc::ptr_eq(shape, &square));
+                assert_eq!(instances.len(), 2);
+                assert_eq!(
+                    instances[1].transform.translation(),
+                    Vector2F::new(1.0, 0.0)
                 );
             }
+            _ => panic!("Expected a vector batch"),
+        }
+        match &batches[2] {
+            DrawBatch::Vector(shape, instances) => {
+                assert!(Arc::ptr_eq(shape, &square));
+                assert_eq!(instances.len(), 1);
+            }
+            _ => panic!("Expected a vector batch"),
+        }
+    }
+
+    #[derive(Default)]
+    struct ClipRecorder(Vec<String>);
+
+    impl Renderer for ClipRecorder {
+        fn start_frame(&mut self, _stage_size: Vector2F) {}
+        fn set_background(&mut self, _color: LinSrgba) {}
+        fn draw_background(&mut self, _layer: BackgroundLayer) {}
+        fn draw_shape(
+            &mut self,
+            _shape: Arc<Shape>,
+            _transform: Transform2F,
+            _color_override: Option<Coloring>,
+            _morph_index: f32,
+        ) {
         }
+        fn draw_shape_instanced(&mut self, _shape: Arc<Shape>, instances: Vec<ShapeInstance>) {
+            self.0.push(format!("draw {}", instances.len()));
+        }
+        fn draw_raster(
+            &mut self,
+            _pattern: Arc<Pattern>,
+            _view_rect: Option<RectF>,
+            _transform: Transform2F,
+            _tint: Option<Coloring>,
+        ) {
+        }
+        fn push_clip(&mut self, clip: ClipRect) {
+            self.0.push(format!("push {}", clip.rect.width()));
+        }
+        fn pop_clip(&mut self) {
+            self.0.push("pop".to_string());
+        }
+        fn end_frame(&mut self) {}
+    }
+
+    #[test]
+    fn it_only_pushes_clips_that_change() {
+        let square = Arc::new(Shape::Fill {
+            contours: Contour::from_edges(&Edge::new_rect(
+                Vector2F::splat(10.0),
+                Transform2F::default(),
+            )),
+            color: LinSrgba::new(1.0, 0.0, 0.0, 1.0),
+        });
+        let outer = ClipRect {
+            rect: RectF::new(Vector2F::zero(), Vector2F::splat(100.0)),
+            transform: Transform2F::default(),
+            parallax: 1.0,
+        };
+        let inner = ClipRect {
+            rect: RectF::new(Vector2F::zero(), Vector2F::splat(50.0)),
+            transform: Transform2F::default(),
+            parallax: 1.0,
+        };
+        let clipped = |clips: Vec<ClipRect>| DrawableItem {
+            clips,
+            ..drawable(&square, 0.0)
+        };
+        let mut renderer = ClipRecorder::default();
+        paint_items(
+            &mut renderer,
+            vec![
+                clipped(vec![]),
+                clipped(vec![outer]),
+                clipped(vec![outer]),
+                clipped(vec![outer, inner]),
+                clipped(vec![outer]),
+            ],
+        );
+        assert_eq!(
+            renderer.0,
+            vec!["draw 1", "push 100", "draw 2", "push 50", "draw 1", "pop", "draw 1", "pop"]
+        );
+    }
+
+    #[test]
+    fn it_encodes_colors_at_the_renderer_boundary() {
+        let grey = LinSrgba::new(0.5, 0.5, 0.5, 0.5);
+        let linear = OutputColorSpace::Linear.encode(grey);
+        let srgb = OutputColorSpace::Srgb.encode(grey);
+        // The sRGB curve lifts mid tones, but leaves alpha alone
+        assert!(srgb.r > linear.r);
+        assert_eq!(srgb.a, linear.a);
+        assert_eq!(OutputColorSpace::DisplayP3.encode(grey), srgb);
+        assert_eq!(
+            OutputColorSpace::Srgb.encode(LinSrgba::new(1.0, 1.0, 1.0, 1.0)),
+            ColorU::white()
+        );
     }
 }
@@ -1,26 +1,64 @@
-use super::actions::{Action, ActionList};
-use super::rendering::{lin_srgb_to_coloru, paint, Renderer};
+use super::actions::{Action, ActionList, BackgroundDefinition};
+use super::rendering::{opaque, paint_with_camera, resolve_background, Renderer, RgbaBuffer};
+use super::util;
 use crate::{
-    ecs::resources::{FrameTime, Library, QuadTrees},
+    ecs::{
+        components::KeyModifiers,
+        resources::{
+            DebugFlags, FrameTime, Library, QuadTreeLayer, QuadTreeLayerRegistry, QuadTrees,
+            RenderQuality, RuntimePolicy, StageArea,
+        },
+    },
     engine::Engine,
+    linking::{AssetResolver, FileAssetResolver, LinkedAsset},
+    pacing::{FrameClock, FramePacing, PacingMode},
     types::{
-        basic::{Bitmap, ContainerId, LibraryId},
+        basic::{Bitmap, ContainerId, LibraryId, TextureDefinition},
         shapes::Shape,
     },
+    watermark::Watermark,
 };
+use log::warn;
 use palette::LinSrgb;
-use pathfinder_geometry::{rect::RectF, vector::Vector2F};
-use std::time::{Duration, Instant};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+    time::{Duration, Instant},
+};
 use streaming_iterator::StreamingIterator;
 
+/// Hosts that never poll would otherwise grow the queue forever. Past this the oldest markers are
+/// dropped.
+const MAX_PENDING_MARKERS: usize = 1024;
+
+/// An `Action::Marker` the playhead reached.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MarkerEvent {
+    /// The frame presented right after the marker.
+    pub frame: u32,
+    pub name: String,
+    pub data: HashMap<String, String>,
+}
+
 pub struct State {
     frame_duration: Duration,
     frame_time_elapsed: Duration,
     frame: u32,
     background_color: LinSrgb,
+    background_layers: Vec<BackgroundDefinition>,
     stage_size: Vector2F,
+    device_pixel_ratio: f32,
     last_frame_end_time: Instant,
-    //TODO: pause
+    // Set when something the engine doesn't track, like the background, changed
+    needs_redraw: bool,
+    markers: VecDeque<MarkerEvent>,
+    // From the initialization actions, handed to the engine once it exists
+    seed: u64,
+    paused: bool,
+    // Set by seeks, so a paused player still shows the frame it was sent to
+    step_while_paused: bool,
+    capture_requested: bool,
 }
 
 impl State {
@@ -30,10 +68,54 @@ impl State {
             frame_time_elapsed: Duration::from_millis(0),
             frame_duration,
             background_color,
+            background_layers: vec![],
             stage_size,
+            device_pixel_ratio: 1.0,
             last_frame_end_time: Instant::now(),
+            needs_redraw: true,
+            markers: VecDeque::new(),
+            seed: 0,
+            paused: false,
+            step_while_paused: false,
+            capture_requested: false,
         }
     }
+
+    fn push_marker(&mut self, name: &str, data: &HashMap<String, String>) {
+        if self.markers.len() >= MAX_PENDING_MARKERS {
+            self.markers.pop_front();
+        }
+        self.markers.push_back(MarkerEvent {
+            frame: self.frame,
+            name: name.to_string(),
+            data: data.clone(),
+        });
+    }
+}
+
+/// A frame read back after `Runner::capture_next_frame`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FrameCapture {
+    /// The frame that was presented.
+    pub frame: u32,
+    pub buffer: RgbaBuffer,
+}
+
+/// A rect of the stage drawn to its own output, such as one window of a video wall. Outputs can
+/// overlap, and several can show the whole stage to mirror it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// The part of the stage shown, in stage pixels.
+    pub stage_rect: RectF,
+    /// The size of the output in device pixels. The rect is stretched to fill it.
+    pub output_size: Vector2F,
+}
+
+impl Viewport {
+    fn stage_to_output(&self) -> Transform2F {
+        Transform2F::from_scale(self.output_size / self.stage_rect.size())
+            * Transform2F::from_translation(-self.stage_rect.origin())
+    }
 }
 
 pub enum FrameResult {
@@ -42,70 +124,452 @@ pub enum FrameResult {
     Quit,
 }
 
-pub struct Runner<'a, 'b> {
+/// Timing and scene statistics for the most recently presented frame.
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    pub frame: u32,
+    /// Time spent executing actions, updating the engine, and building the frame.
+    pub cpu_time: Duration,
+    pub target_time: Duration,
+    /// Per-system time spent in the engine update, as reported by `FrameProfile`.
+    pub system_times: Vec<(&'static str, Duration)>,
+    pub drawable_count: usize,
+    /// False if the frame was stepped but not drawn, because nothing changed or the FPS cap
+    /// held it back. `drawable_count` is then the last drawn frame's.
+    pub drawn: bool,
+    /// True if the engine was quiescent for every step, so nothing in the scene changed since the
+    /// previous frame. See `Engine::is_quiescent`.
+    pub idle: bool,
+}
+
+impl FrameStats {
+    /// Fraction of the frame budget used. Values over 1.0 mean the frame ran long.
+    pub fn load(&self) -> f32 {
+        util::div_duration_f32(self.cpu_time, self.target_time)
+    }
+}
+
+pub struct Runner {
     state: State,
-    engine: Engine<'a, 'b>,
+    engine: Engine,
+    stats: FrameStats,
+    pacing: FramePacing,
+    clock: FrameClock,
+    resolver: Box<dyn AssetResolver>,
+    capture: Option<Result<FrameCapture, String>>,
+    // The overlay container and the texture it shows
+    watermark: Option<(ContainerId, LibraryId)>,
 }
 
-impl<'a, 'b> Runner<'a, 'b> {
+impl Runner {
+    /// Linked assets are resolved as paths relative to the working directory.
     pub fn initialize(
         actions: &mut ActionList,
         frame_duration: Duration,
         stage_size: Vector2F,
     ) -> Result<Self, String> {
-        let (root_container_id, state, library, quad_trees) =
-            initialize(actions, frame_duration, stage_size)?;
-        let engine = Engine::new(root_container_id, library, quad_trees);
-        Ok(Self { state, engine })
+        Self::initialize_with_resolver(
+            actions,
+            frame_duration,
+            stage_size,
+            Box::new(FileAssetResolver::default()),
+        )
+    }
+
+    /// `resolver` loads every `Action::LibraryRef`, during initialization and playback.
+    pub fn initialize_with_resolver(
+        actions: &mut ActionList,
+        frame_duration: Duration,
+        stage_size: Vector2F,
+        resolver: Box<dyn AssetResolver>,
+    ) -> Result<Self, String> {
+        let (root_container_id, state, library, quad_trees, layer_registry) =
+            initialize(actions, frame_duration, stage_size, resolver.as_ref())?;
+        let mut engine = Engine::new(root_container_id, library, quad_trees);
+        *engine.get_layer_registry_mut() = layer_registry;
+        engine.set_seed(state.seed);
+        engine.set_stage_area(StageArea::new(stage_size));
+        Ok(Self {
+            state,
+            engine,
+            stats: FrameStats::default(),
+            pacing: FramePacing::default(),
+            clock: FrameClock::default(),
+            resolver,
+            capture: None,
+            watermark: None,
+        })
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    /// For hosts that query or update the scene directly, such as debugging tools. Updates are
+    /// applied on the next frame the runner steps.
+    pub fn get_engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn get_engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// Device pixels per stage unit. Frames are drawn at the stage size times this ratio, and
+    /// `PixelSnap` containers snap to the device pixel grid.
+    pub fn set_device_pixel_ratio(&mut self, device_pixel_ratio: f32) {
+        self.state.device_pixel_ratio = device_pixel_ratio;
+        let mut config = self.engine.get_config();
+        config.device_pixel_ratio = device_pixel_ratio;
+        self.engine.set_config(config);
+    }
+
+    /// Trades curve smoothness for speed. Shapes already in the library keep the step counts
+    /// they were built with.
+    pub fn set_render_quality(&mut self, render_quality: RenderQuality) {
+        self.engine.set_render_quality(render_quality);
+    }
+
+    /// Whether errors in content fail the frame, or are logged and skipped. See `RuntimePolicy`.
+    pub fn set_runtime_policy(&mut self, runtime_policy: RuntimePolicy) {
+        self.engine.set_runtime_policy(runtime_policy);
+    }
+
+    pub fn get_device_pixel_ratio(&self) -> f32 {
+        self.state.device_pixel_ratio
+    }
+
+    pub fn get_stage_size(&self) -> Vector2F {
+        self.state.stage_size
+    }
+
+    /// Resizes the stage, for hosts whose window can change shape. `Anchor`ed containers move to
+    /// match on the next frame. The safe area is kept, so hosts should update it too if it moved.
+    pub fn set_stage_size(&mut self, stage_size: Vector2F) {
+        self.state.stage_size = stage_size;
+        let mut stage_area = self.engine.get_stage_area();
+        stage_area.size = stage_size;
+        self.engine.set_stage_area(stage_area);
+        self.request_redraw();
+    }
+
+    /// The part of the stage clear of notches and system bars, in stage units. None for the
+    /// whole stage.
+    pub fn set_safe_area(&mut self, safe_area: Option<RectF>) {
+        let mut stage_area = self.engine.get_stage_area();
+        stage_area.safe_area = safe_area;
+        self.engine.set_stage_area(stage_area);
+    }
+
+    pub fn get_debug_flags(&self) -> DebugFlags {
+        self.engine.get_debug_flags()
+    }
+
+    /// Debug overlays are drawn over the scene from the next frame on.
+    pub fn set_debug_flags(&mut self, debug_flags: DebugFlags) {
+        self.engine.set_debug_flags(debug_flags);
+    }
+
+    /// Continues playback from `frame`. Uses the action list's index when it has one, so long
+    /// movies don't need to be scanned. Actions between the current frame and `frame` are skipped,
+    /// not replayed.
+    pub fn seek_to_frame(&mut self, actions: &mut ActionList, frame: u32) -> Result<(), String> {
+        actions.seek_to_frame(frame)?;
+        self.state.frame = frame;
+        self.state.step_while_paused = true;
+        Ok(())
+    }
+
+    /// Continues playback from the frame `label` marks.
+    pub fn goto_label(&mut self, actions: &mut ActionList, label: &str) -> Result<(), String> {
+        let (_, frame) = actions.jump_to_label(label)?;
+        self.state.frame = frame;
+        self.state.step_while_paused = true;
+        Ok(())
+    }
+
+    /// While paused `next_frame` only steps the frame a seek lands on, but still redraws when
+    /// asked to.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.state.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.paused
+    }
+
+    pub fn set_state_flag(&mut self, flag: &str, value: bool) {
+        self.engine.set_state_flag(flag, value);
+    }
+
+    pub fn fire_state_event(&mut self, event: &str) {
+        self.engine.fire_state_event(event);
+    }
+
+    pub fn get_pacing(&self) -> FramePacing {
+        self.pacing
+    }
+
+    pub fn set_pacing(&mut self, pacing: FramePacing) {
+        self.pacing = pacing;
+        self.clock = FrameClock::default();
+    }
+
+    /// Every marker the playhead reached since the last poll, in order. Markers skipped over by
+    /// seeking or by jumping to a label are not reported.
+    pub fn poll_markers(&mut self) -> Vec<MarkerEvent> {
+        self.state.markers.drain(..).collect()
+    }
+
+    /// Routes a key press to the scene. See `Engine::key_down`.
+    pub fn key_down(&mut self, key: &str, modifiers: KeyModifiers) -> bool {
+        self.engine.key_down(key, modifiers)
+    }
+
+    pub fn key_up(&mut self, key: &str, modifiers: KeyModifiers) -> bool {
+        self.engine.key_up(key, modifiers)
+    }
+
+    /// Makes the next frame draw even if nothing changed. Call when the host's surface lost its
+    /// contents, such as after a resize.
+    pub fn request_redraw(&mut self) {
+        self.state.needs_redraw = true;
     }
 
     pub fn next_frame(
         &mut self,
         renderer: &mut impl Renderer,
         actions: &mut ActionList,
+    ) -> Result<FrameResult, String> {
+        let mut read_back = None;
+        let mut drawn = false;
+        let result = self.step_and_draw(actions, |state, engine| {
+            drawn = true;
+            if state.capture_requested {
+                renderer.request_read_back();
+            }
+            let drawable_count = draw_frame(renderer, state, engine)?;
+            if state.capture_requested {
+                read_back = renderer.take_read_back();
+            }
+            Ok(drawable_count)
+        });
+        if drawn {
+            self.store_capture(read_back);
+        }
+        result
+    }
+
+    /// Like `next_frame`, but draws to every output, each showing its own viewport into the
+    /// stage. Outputs are taken from the iterator as they're drawn, so hosts can switch graphics
+    /// contexts as they go. The stats count the drawables of the last output, and captures are
+    /// read back from the first.
+    pub fn next_frame_viewports<'a, R: Renderer + 'a>(
+        &mut self,
+        outputs: impl IntoIterator<Item = (&'a mut R, Viewport)>,
+        actions: &mut ActionList,
+    ) -> Result<FrameResult, String> {
+        let mut read_back = None;
+        let mut drawn = false;
+        let result = self.step_and_draw(actions, |state, engine| {
+            drawn = true;
+            let mut drawable_count = 0;
+            for (index, (renderer, viewport)) in outputs.into_iter().enumerate() {
+                let capture = index == 0 && state.capture_requested;
+                if capture {
+                    renderer.request_read_back();
+                }
+                drawable_count = draw_viewport(renderer, state, engine, &viewport);
+                if capture {
+                    read_back = renderer.take_read_back();
+                }
+            }
+            Ok(drawable_count)
+        });
+        if drawn {
+            self.store_capture(read_back);
+        }
+        result
+    }
+
+    /// Replaces the watermark drawn over the scene, or removes it. Shows from the next frame on.
+    pub fn set_watermark(&mut self, watermark: Option<&Watermark>) -> Result<(), String> {
+        if let Some((container_id, library_id)) = self.watermark.take() {
+            self.engine
+                .remove_container(&container_id)
+                .map_err(|error| format!("{:?}", error))?;
+            self.engine.get_library_mut().remove_texture(&library_id);
+        }
+        if let Some(watermark) = watermark {
+            let (container_id, library_id) = (ContainerId::new(), LibraryId::new());
+            self.engine
+                .get_library_mut()
+                .add_texture(library_id, watermark.image.clone().pattern());
+            let root_container_id = self.engine.get_root_container_id();
+            self.engine
+                .create_container(&watermark.container_definition(
+                    root_container_id,
+                    container_id,
+                    library_id,
+                ));
+            self.watermark = Some((container_id, library_id));
+        }
+        self.request_redraw();
+        Ok(())
+    }
+
+    /// Reads back the next frame that gets drawn, to be collected with `take_capture`. The frame
+    /// is drawn even if nothing changed.
+    pub fn capture_next_frame(&mut self) {
+        self.state.capture_requested = true;
+        self.state.needs_redraw = true;
+    }
+
+    /// The frame read back since `capture_next_frame` was called, once it has been drawn.
+    pub fn take_capture(&mut self) -> Option<Result<FrameCapture, String>> {
+        self.capture.take()
+    }
+
+    // Only called once a frame has been drawn
+    fn store_capture(&mut self, read_back: Option<Result<RgbaBuffer, String>>) {
+        if !self.state.capture_requested {
+            return;
+        }
+        self.state.capture_requested = false;
+        let frame = self.stats.frame;
+        self.capture = Some(
+            read_back
+                .unwrap_or_else(|| Err("The renderer can't read back frames".to_string()))
+                .map(|buffer| FrameCapture { frame, buffer }),
+        );
+    }
+
+    fn step_and_draw(
+        &mut self,
+        actions: &mut ActionList,
+        draw: impl FnOnce(&State, &Engine) -> Result<usize, String>,
     ) -> Result<FrameResult, String> {
         let frame_start_time = Instant::now();
-        let mut frame_result = FrameResult::Continue;
-        execute_actions(&mut self.state, actions, &mut self.engine)?;
-        if let Some(Action::PresentFrame(start, count)) = actions.get() {
-            if self.state.frame < start + count {
-                let frame_time = FrameTime {
-                    delta_frame: 1,
-                    delta_time: self.state.frame_time_elapsed
-                        + (frame_start_time - self.state.last_frame_end_time),
-                };
-                self.engine.update(frame_time);
-                draw_frame(renderer, &self.state, &self.engine)?;
-                self.state.last_frame_end_time = Instant::now();
-                let frame_time_elapsed = self.state.last_frame_end_time - frame_start_time;
-                println!(
-                    "frame {:?} time {:?}, {:?}% of target ",
-                    self.state.frame,
-                    frame_time_elapsed,
-                    frame_time_elapsed.div_duration_f32(self.state.frame_duration) * 100.0
-                );
-                self.state.frame_time_elapsed = frame_time_elapsed;
-                self.state.frame += 1;
-                frame_result = if frame_time_elapsed < self.state.frame_duration {
-                    FrameResult::Wait(
-                        self.state.last_frame_end_time
-                            + (self.state.frame_duration - frame_time_elapsed),
-                    )
-                } else {
-                    FrameResult::Continue
+        let frame_duration = self.state.frame_duration;
+        let steps = match self.pacing.mode {
+            PacingMode::Timed => self.pacing.timed_steps(frame_duration),
+            PacingMode::Vsync => self.clock.tick(frame_start_time, frame_duration),
+        };
+        // The clock still ticks while paused, so resuming doesn't catch up on the paused time
+        let step_while_paused = mem::replace(&mut self.state.step_while_paused, false);
+        let steps = match (self.state.paused, step_while_paused) {
+            (false, _) => steps,
+            (true, true) => 1,
+            (true, false) => 0,
+        };
+        // Time since the last frame ended, plus how long that frame took, split between the steps
+        let delta_time = (self.state.frame_time_elapsed
+            + (frame_start_time - self.state.last_frame_end_time))
+            / steps.max(1);
+        let mut stepped = 0;
+        let mut presented_frame = self.stats.frame;
+        let mut idle = true;
+        while stepped < steps {
+            execute_actions(
+                &mut self.state,
+                actions,
+                &mut self.engine,
+                self.resolver.as_ref(),
+            )?;
+            match actions.get() {
+                Some(Action::PresentFrame(start, count)) if self.state.frame < start + count => {
+                    let updated = self.engine.update(FrameTime {
+                        delta_frame: 1,
+                        delta_time,
+                    });
+                    let errors = self.engine.take_runtime_errors();
+                    if !errors.is_empty() {
+                        return Err(errors.join("\n"));
+                    }
+                    idle &= !updated;
+                    presented_frame = self.state.frame;
+                    self.state.frame += 1;
+                    stepped += 1;
+                    let pending_label = self.engine.get_state_machines_mut().take_pending_label();
+                    if let Some(label) = pending_label {
+                        let (_, frame) = actions.jump_to_label(&label)?;
+                        self.state.frame = frame;
+                    }
+                }
+                Some(Action::PresentFrame(..)) => {
+                    actions.advance();
+                    if stepped == 0 {
+                        return Ok(FrameResult::Continue);
+                    }
+                }
+                _ => {
+                    if stepped == 0 {
+                        return Ok(FrameResult::Continue);
+                    }
+                    break;
                 }
-            } else {
-                actions.advance();
             }
         }
-        Ok(frame_result)
+        // Always taken, so changes don't pile up while render on demand is off
+        let scene_dirty = self.engine.take_scene_dirty();
+        let needs_redraw = mem::replace(&mut self.state.needs_redraw, false);
+        let draw_blocked_until = match self.pacing.mode {
+            PacingMode::Timed => None,
+            PacingMode::Vsync => self
+                .clock
+                .draw_blocked_until(frame_start_time, self.pacing.min_draw_interval()),
+        };
+        let draw = draw_blocked_until.is_none()
+            && (!self.pacing.render_on_demand || scene_dirty || needs_redraw);
+        let drawable_count = if draw {
+            self.clock.record_draw(frame_start_time);
+            draw(&self.state, &self.engine)?
+        } else {
+            // Try again once the cap allows it
+            self.state.needs_redraw = needs_redraw || (draw_blocked_until.is_some() && scene_dirty);
+            self.stats.drawable_count
+        };
+        self.state.last_frame_end_time = Instant::now();
+        let frame_time_elapsed = self.state.last_frame_end_time - frame_start_time;
+        let target_time = frame_duration * steps.max(1);
+        self.stats = FrameStats {
+            frame: presented_frame,
+            cpu_time: frame_time_elapsed,
+            target_time,
+            system_times: self.engine.get_frame_profile().system_times(),
+            drawable_count,
+            drawn: draw,
+            idle: idle && !scene_dirty,
+        };
+        if frame_time_elapsed > target_time {
+            warn!(
+                "frame {} took {:?}, {:.1}% of target",
+                self.stats.frame,
+                frame_time_elapsed,
+                self.stats.load() * 100.0
+            );
+        }
+        self.state.frame_time_elapsed = frame_time_elapsed;
+        Ok(match self.pacing.mode {
+            PacingMode::Timed if frame_time_elapsed < target_time => FrameResult::Wait(
+                self.state.last_frame_end_time + (target_time - frame_time_elapsed),
+            ),
+            PacingMode::Timed => FrameResult::Continue,
+            // Nothing was presented, so there is no vsync to block on
+            PacingMode::Vsync if !draw => FrameResult::Wait(
+                draw_blocked_until.unwrap_or(self.state.last_frame_end_time + frame_duration),
+            ),
+            PacingMode::Vsync => FrameResult::Continue,
+        })
     }
 }
 
-fn define_shape(id: &LibraryId, shape: &Shape, library: &mut Library) {
+fn define_shape(id: &LibraryId, shape: &Shape, library: &mut Library) -> Result<(), String> {
     if !library.contains_shape(id) {
+        library.validate_shape(id, shape)?;
         library.add_shape(*id, shape.clone());
     }
+    Ok(())
 }
 
 fn load_bitmap(id: &LibraryId, bitmap: &mut Bitmap, library: &mut Library) {
@@ -114,109 +578,304 @@ fn load_bitmap(id: &LibraryId, bitmap: &mut Bitmap, library: &mut Library) {
     }
 }
 
+fn load_texture(id: &LibraryId, texture: &TextureDefinition, library: &mut Library) {
+    if !library.contains_texture(id) {
+        library.add_texture(*id, texture.to_pattern());
+    }
+}
+
+fn load_linked(
+    id: &LibraryId,
+    location: &str,
+    resolver: &dyn AssetResolver,
+    library: &mut Library,
+) -> Result<(), String> {
+    if library.contains_shape(id) || library.contains_texture(id) {
+        return Ok(());
+    }
+    match resolver.resolve(location)? {
+        LinkedAsset::Shape(shape) => define_shape(id, &shape, library),
+        LinkedAsset::Bitmap(mut bitmap) => {
+            load_bitmap(id, &mut bitmap, library);
+            Ok(())
+        }
+        LinkedAsset::Texture(texture) => {
+            load_texture(id, &texture, library);
+            Ok(())
+        }
+    }
+}
+
 fn initialize(
     actions: &mut ActionList,
     frame_duration: Duration,
     stage_size: Vector2F,
-) -> Result<(ContainerId, State, Library, QuadTrees), String> {
+    resolver: &dyn AssetResolver,
+) -> Result<
+    (
+        ContainerId,
+        State,
+        Library,
+        QuadTrees,
+        QuadTreeLayerRegistry,
+    ),
+    String,
+> {
     let mut library = Library::default();
     let mut quad_trees = QuadTrees::default();
+    let mut layer_registry = QuadTreeLayerRegistry::default();
     let mut root_entity_id: Option<ContainerId> = None;
     let mut background_color = LinSrgb::new(1.0, 1.0, 1.0);
+    let mut background_layers = vec![];
+    let mut markers = vec![];
+    let mut seed = 0;
     while let Some(action) = actions.get_mut() {
         match action {
             Action::CreateRoot(id) => {
                 root_entity_id = Some(*id);
             }
             Action::DefineShape { id, shape } => {
-                define_shape(id, shape, &mut library);
+                define_shape(id, shape, &mut library)?;
             }
             Action::LoadBitmap { id, ref mut bitmap } => {
                 load_bitmap(id, bitmap, &mut library);
             }
+            Action::LoadTexture { id, texture } => {
+                load_texture(id, texture, &mut library);
+            }
+            Action::LibraryRef { id, location } => {
+                load_linked(id, location, resolver, &mut library)?;
+            }
+            Action::SetLibraryMetadata { id, metadata } => {
+                library.set_metadata(*id, metadata.clone());
+            }
+            Action::DefineSpriteSheet { id, sheet } => {
+                library.set_sprite_sheet(*id, sheet.clone());
+            }
+            Action::DefineLodVariants { id, variants } => {
+                library.set_lod_variants(*id, variants.clone())?;
+            }
+            Action::DefinePalette(entries) => {
+                for (name, color) in entries.iter() {
+                    library.set_palette_entry(name.clone(), *color);
+                }
+            }
             Action::SetBackground { color } => background_color = *color,
+            Action::SetBackgroundLayers(layers) => background_layers = layers.clone(),
             Action::EndInitialization => break,
             Action::AddQuadTreeLayer(layer, bounds, options) => {
+                check_layer_not_reserved(layer)?;
                 quad_trees.create_quad_tree(
                     *layer,
                     RectF::from_points(bounds.origin, bounds.lower_right),
                     options.clone(),
                 );
             }
+            Action::DeclareQuadTreeLayer {
+                name,
+                layer,
+                bounds,
+                options,
+            } => {
+                layer_registry.declare(name, *layer)?;
+                quad_trees.create_quad_tree(
+                    *layer,
+                    RectF::from_points(bounds.origin, bounds.lower_right),
+                    options.clone(),
+                );
+            }
+            Action::Marker { name, data } => markers.push((name.clone(), data.clone())),
+            Action::SetSeed(value) => seed = *value,
             _ => return Err("Unexpected action in initialization".to_string()),
         }
         actions.advance();
     }
 
     if let Some(root_entity_id) = root_entity_id {
-        Ok((
-            root_entity_id,
-            State::new(background_color, frame_duration, stage_size),
-            library,
-            quad_trees,
-        ))
+        let mut state = State::new(background_color, frame_duration, stage_size);
+        state.background_layers = background_layers;
+        state.seed = seed;
+        for (name, data) in markers {
+            state.push_marker(&name, &data);
+        }
+        Ok((root_entity_id, state, library, quad_trees, layer_registry))
     } else {
         Err("Action list did not define a root element".to_string())
     }
 }
 
+fn check_layer_not_reserved(layer: &QuadTreeLayer) -> Result<(), String> {
+    if layer.is_reserved() {
+        Err(format!(
+            "Layer {:?} is reserved for the editor and engine",
+            layer
+        ))
+    } else {
+        Ok(())
+    }
+}
+
 fn execute_actions(
     state: &mut State,
     actions: &mut ActionList,
     engine: &mut Engine,
+    resolver: &dyn AssetResolver,
 ) -> Result<(), String> {
-    let mut state = state;
     while let Some(action) = actions.get_mut() {
-        match action {
-            Action::DefineShape { id, shape } => {
-                let library = &mut *engine.get_library_mut();
-                define_shape(id, shape, library);
-            }
-            Action::LoadBitmap { id, ref mut bitmap } => {
-                let library = &mut *engine.get_library_mut();
-                load_bitmap(id, bitmap, library);
-            }
-            Action::CreateContainer(container_create_defintiion) => {
-                engine.create_container(container_create_defintiion);
-            }
-            Action::UpdateContainer(container_update_definition) => {
-                engine.update_container(container_update_definition);
-            }
-            Action::RemoveContainer(id, recursive) => {
-                if *recursive {
-                    engine.remove_container(id);
-                } else {
-                    engine.remove_container_and_children(id);
-                }
-            }
-            Action::AddQuadTreeLayer(layer, bounds, options) => {
-                engine.get_quad_trees_mut().create_quad_tree(
-                    *layer,
-                    RectF::from_points(bounds.origin, bounds.lower_right),
-                    options.clone(),
-                );
-            }
-            Action::SetBackground { color } => state.background_color = *color,
-            Action::PresentFrame(_, _) => break,
-            Action::CreateRoot { .. } => {
-                return Err("Attempting to define an additional Root".to_string())
+        if let Action::PresentFrame(_, _) = action {
+            break;
+        }
+        if let Err(error) = execute_action(state, action, engine, resolver) {
+            match engine.get_runtime_policy() {
+                RuntimePolicy::Strict => return Err(error),
+                RuntimePolicy::Lenient => warn!("Skipping action: {}", error),
             }
-            Action::Label(_) => (),
-            Action::EndInitialization => (),
         }
         actions.advance();
     }
     Ok(())
 }
 
-fn draw_frame(renderer: &mut impl Renderer, state: &State, engine: &Engine) -> Result<(), String> {
-    renderer.start_frame(state.stage_size);
-    renderer.set_background(lin_srgb_to_coloru(state.background_color));
-    paint(renderer, engine);
-    renderer.end_frame();
+fn execute_action(
+    state: &mut State,
+    action: &mut Action,
+    engine: &mut Engine,
+    resolver: &dyn AssetResolver,
+) -> Result<(), String> {
+    match action {
+        Action::DefineShape { id, shape } => {
+            let library = &mut *engine.get_library_mut();
+            define_shape(id, shape, library)?;
+        }
+        Action::LoadBitmap { id, ref mut bitmap } => {
+            let library = &mut *engine.get_library_mut();
+            load_bitmap(id, bitmap, library);
+        }
+        Action::LoadTexture { id, texture } => {
+            let library = &mut *engine.get_library_mut();
+            load_texture(id, texture, library);
+        }
+        Action::LibraryRef { id, location } => {
+            let library = &mut *engine.get_library_mut();
+            load_linked(id, location, resolver, library)?;
+        }
+        Action::SetLibraryMetadata { id, metadata } => {
+            engine.get_library_mut().set_metadata(*id, metadata.clone());
+        }
+        Action::DefineSpriteSheet { id, sheet } => {
+            engine
+                .get_library_mut()
+                .set_sprite_sheet(*id, sheet.clone());
+        }
+        Action::DefineLodVariants { id, variants } => {
+            engine
+                .get_library_mut()
+                .set_lod_variants(*id, variants.clone())?;
+            engine.mark_scene_dirty();
+        }
+        Action::DefinePalette(entries) => {
+            for (name, color) in entries.iter() {
+                engine.set_palette_entry(name, *color);
+            }
+        }
+        Action::UnloadUnusedLibraryItems => {
+            engine.unload_unused_library_items();
+        }
+        Action::CreateContainer(container_create_defintiion) => {
+            engine.create_container(container_create_defintiion);
+        }
+        Action::UpdateContainer(container_update_definition) => {
+            engine.update_container(container_update_definition);
+        }
+        Action::RemoveContainer(id, recursive) => {
+            let result = if *recursive {
+                engine.remove_container(id)
+            } else {
+                engine.remove_container_and_children(id)
+            };
+            result.map_err(|error| format!("Failed to remove {:?}: {:?}", id, error))?;
+        }
+        Action::AddQuadTreeLayer(layer, bounds, options) => {
+            check_layer_not_reserved(layer)?;
+            engine.get_quad_trees_mut().create_quad_tree(
+                *layer,
+                RectF::from_points(bounds.origin, bounds.lower_right),
+                options.clone(),
+            );
+        }
+        Action::DeclareQuadTreeLayer {
+            name,
+            layer,
+            bounds,
+            options,
+        } => {
+            engine.declare_quad_tree_layer(
+                name,
+                *layer,
+                RectF::from_points(bounds.origin, bounds.lower_right),
+                options.clone(),
+            )?;
+        }
+        Action::DefineStateMachine { name, machine } => {
+            engine.add_state_machine(name.clone(), machine.clone())?;
+        }
+        Action::SetBackground { color } => {
+            state.background_color = *color;
+            state.needs_redraw = true;
+        }
+        Action::SetBackgroundLayers(layers) => {
+            state.background_layers = layers.clone();
+            state.needs_redraw = true;
+        }
+        // Handled by execute_actions
+        Action::PresentFrame(_, _) => (),
+        Action::CreateRoot { .. } => {
+            return Err("Attempting to define an additional Root".to_string())
+        }
+        Action::Marker { name, data } => state.push_marker(name, data),
+        Action::SetSeed(seed) => engine.set_seed(*seed),
+        Action::Label(_) => (),
+        Action::EndInitialization => (),
+    }
     Ok(())
 }
 
+fn draw_frame(
+    renderer: &mut impl Renderer,
+    state: &State,
+    engine: &Engine,
+) -> Result<usize, String> {
+    let viewport = Viewport {
+        stage_rect: RectF::new(Vector2F::zero(), state.stage_size),
+        output_size: state.stage_size * state.device_pixel_ratio,
+    };
+    Ok(draw_viewport(renderer, state, engine, &viewport))
+}
+
+fn draw_viewport(
+    renderer: &mut impl Renderer,
+    state: &State,
+    engine: &Engine,
+    viewport: &Viewport,
+) -> usize {
+    renderer.start_frame(viewport.output_size);
+    renderer.set_render_quality(engine.get_render_quality());
+    renderer.set_background(opaque(state.background_color));
+    {
+        // TODO: backgrounds fill each output, rather than spanning them like the stage does
+        let library = engine.get_library();
+        for definition in state.background_layers.iter() {
+            match resolve_background(definition, &library) {
+                Some(layer) => renderer.draw_background(layer),
+                None => warn!("Background texture {:?} is not in the library", definition),
+            }
+        }
+    }
+    let drawable_count = paint_with_camera(renderer, engine, viewport.stage_to_output());
+    renderer.end_frame();
+    drawable_count
+}
+
 /*#[cfg(test)]
 mod tests {
     use super::*;
@@ -226,7 +885,7 @@ mod tests {
     use crate::tween::Easing;
     use crate::types::basic::ScaleRotationTranslation;
     use crate::types::coloring::Coloring;
-    use crate::types::shapes::Edge;
+    use crate::types::shapes::{Contour, Edge};
     use mockall::predicate::*;
     use mockall::*;
     use pathfinder_content::{pattern::Pattern, stroke::StrokeStyle};
@@ -287,12 +946,12 @@ mod tests {
             Action::DefineShape {
                 id: shape_id,
                 shape: Shape::Fill {
-                    edges: vec![
+                    contours: Contour::from_edges(&[
                         Edge::Line(Vector2F::new(-15.0, -15.0)),
                         Edge::Line(Vector2F::new(15.0, -15.0)),
                         Edge::Line(Vector2F::new(15.0, 15.0)),
                         Edge::Line(Vector2F::new(-15.0, 15.0)),
-                    ],
+                    ]),
                     color: ColorU::new(0, 255, 0, 255),
                 },
             },
@@ -389,7 +1048,7 @@ mod tests {
         library.insert(
             shape_id,
             DisplayLibraryItem::Vector(Shape::Path {
-                edges: vec![],
+                contours: vec![],
                 color: ColorU::white(),
                 stroke_style: StrokeStyle::default(),
             }),
@@ -474,12 +1133,12 @@ mod tests {
         library.insert(
             shape_id,
             DisplayLibraryItem::Vector(Shape::Fill {
-                edges: vec![
+                contours: Contour::from_edges(&[
                     Edge::Line(Vector2F::new(-15.0, -15.0)),
                     Edge::Line(Vector2F::new(15.0, -15.0)),
                     Edge::Line(Vector2F::new(15.0, 15.0)),
                     Edge::Line(Vector2F::new(-15.0, 15.0)),
-                ],
+                ]),
                 color: ColorU::new(0, 255, 0, 255),
             }),
         );
@@ -532,12 +1191,12 @@ mod tests {
             .times(1)
             .withf(|drawn_shape, transform, color_override, morph_index| {
                 let model_shape = Shape::Fill {
-                    edges: vec![
+                    contours: Contour::from_edges(&[
                         Edge::Line(Vector2F::new(-15.0, -15.0)),
                         Edge::Line(Vector2F::new(15.0, -15.0)),
                         Edge::Line(Vector2F::new(15.0, 15.0)),
                         Edge::Line(Vector2F::new(-15.0, 15.0)),
-                    ],
+                    ]),
                     color: ColorU::new(0, 255, 0, 255),
                 };
                 drawn_shape == &model_shape
@@ -1,18 +1,43 @@
-use super::actions::Action;
+use super::actions::optimize::{optimize, OptimizationReport};
+use super::actions::{
+    Action, ActionIndex, ActionList, BackgroundDefinition, ContainerCreationDefintition,
+    ContainerCreationProperty, ContainerUpdateDefintition, ContainerUpdateProperty, RectPoints,
+};
+use super::baking::BakeSource;
+use super::ecs::components::{HitShape, KeyBinding, KeyBindingAction};
+use super::ecs::resources::{QuadTreeLayer, QuadTreeLayerOptions};
+use super::tween::Easing;
+use super::types::basic::{Bitmap, ContainerId, LibraryId, ScaleRotationTranslation};
+use super::types::shapes::Shape;
 use bincode::Error as BinError;
 use bincode::ErrorKind as BinErrorKind;
 use circular::Buffer;
-use nom::error::ErrorKind as NomErrorKind;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use log::error;
 use nom::number::streaming::{le_i32, le_u32, le_u8};
-use nom::{Err, IResult, Needed};
+use nom::Err;
+use palette::LinSrgb;
+use pathfinder_geometry::vector::Vector2F;
 use pathfinder_geometry::vector::Vector2I;
-use std::io::{Read, Write};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{Chain, Cursor, Read, Write};
 
 const FILE_VERSION: u8 = 1;
-const ACTION_VERSION: u8 = 1;
+const COMPRESSED_FILE_VERSION: u8 = 2;
+// Version 2 added blend ins and delays to container updates
+pub(crate) const ACTION_VERSION: u8 = 2;
+// Written in place of the action version for the index chunk at the start of a stream
+const INDEX_CHUNK: u8 = 0;
 const STARTING_BUFFER_SIZE: usize = 1000;
 const MAX_BUFFER_SIZE: usize = 4_096_000; // 1000 *(2 ^ 12) bytes ~ 4MB
 
+// Quantization steps per unit for transforms in compressed streams
+const TRANSLATION_STEPS: f32 = 20.0; // 1/20th of a pixel, same as Flash twips
+const SCALE_STEPS: f32 = 4096.0;
+const THETA_STEPS: f32 = 4096.0;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Header {
     version: u8,
@@ -36,46 +61,156 @@ named!(
     )
 );
 
-macro_rules! extract_action(
-    ($i:expr, $version:expr, $size:expr) => (
-        extract_action($version, $size)($i)
-    );
+named!(
+    record<(u8, &[u8])>,
+    do_parse!(version: le_u8 >> data_size: le_u32 >> data: take!(data_size) >> ((version, data)))
 );
 
-named!(
-    action<Action>,
-    do_parse!(
-        version: le_u8
-            >> data_size: le_u32
-            >> action: extract_action!(version, data_size as usize)
-            >> (action)
+type QuantizedTransform = [i32; 5];
+
+fn quantize(transform: &ScaleRotationTranslation) -> QuantizedTransform {
+    [
+        (transform.scale.x() * SCALE_STEPS).round() as i32,
+        (transform.scale.y() * SCALE_STEPS).round() as i32,
+        (transform.theta * THETA_STEPS).round() as i32,
+        (transform.translation.x() * TRANSLATION_STEPS).round() as i32,
+        (transform.translation.y() * TRANSLATION_STEPS).round() as i32,
+    ]
+}
+
+fn dequantize(quantized: &QuantizedTransform) -> ScaleRotationTranslation {
+    ScaleRotationTranslation::new(
+        Vector2F::new(
+            quantized[0] as f32 / SCALE_STEPS,
+            quantized[1] as f32 / SCALE_STEPS,
+        ),
+        quantized[2] as f32 / THETA_STEPS,
+        Vector2F::new(
+            quantized[3] as f32 / TRANSLATION_STEPS,
+            quantized[4] as f32 / TRANSLATION_STEPS,
+        ),
     )
-);
+}
+
+/// Actions as they are written to compressed streams. Transform keyframes are stored as the
+/// quantized difference from the container's previous keyframe, which is mostly zeros and small
+/// numbers for dense animations, and so compresses well.
+#[derive(Serialize, Deserialize)]
+enum EncodedAction {
+    Action(Action),
+    TransformDelta {
+        id: ContainerId,
+        delta: QuantizedTransform,
+        easing: Easing,
+        duration_frames: u32,
+    },
+}
 
-fn extract_action(version: u8, size: usize) -> impl Fn(&[u8]) -> IResult<&[u8], Action> {
-    move |input: &[u8]| map!(input, |i| parse_action(i, version, size), |v| v)
+/// Tracks the last transform keyframe written or read for each container.
+#[derive(Default)]
+struct TransformDeltas {
+    previous: HashMap<ContainerId, QuantizedTransform>,
 }
 
-fn parse_action(input: &[u8], version: u8, size: usize) -> IResult<&[u8], Action> {
-    if input.len() < size {
-        return Err(Err::Incomplete(Needed::Size(size)));
+impl TransformDeltas {
+    fn encode(&mut self, action: &Action) -> EncodedAction {
+        if let Action::UpdateContainer(definition) = action {
+            // Blended and delayed updates, like staggered ones, are written whole
+            if let (
+                [ContainerUpdateProperty::Transform(transform, easing, duration_frames)],
+                None,
+                0,
+            ) = (
+                &definition.properties()[..],
+                definition.blend_in(),
+                definition.delay(),
+            ) {
+                let quantized = quantize(transform);
+                let previous = self
+                    .previous
+                    .insert(*definition.id(), quantized)
+                    .unwrap_or_default();
+                let mut delta = QuantizedTransform::default();
+                for ((delta, current), previous) in delta.iter_mut().zip(&quantized).zip(&previous)
+                {
+                    *delta = current.wrapping_sub(*previous);
+                }
+                return EncodedAction::TransformDelta {
+                    id: *definition.id(),
+                    delta,
+                    easing: *easing,
+                    duration_frames: *duration_frames,
+                };
+            }
+        }
+        EncodedAction::Action(action.clone())
     }
-    if size < 1 {
-        return Err(Err::Incomplete(Needed::Size(1)));
+
+    fn decode(&mut self, encoded: EncodedAction) -> Action {
+        match encoded {
+            EncodedAction::Action(action) => action,
+            EncodedAction::TransformDelta {
+                id,
+                delta,
+                easing,
+                duration_frames,
+            } => {
+                let quantized = self.previous.entry(id).or_default();
+                for (quantized, delta) in quantized.iter_mut().zip(&delta) {
+                    *quantized = quantized.wrapping_add(*delta);
+                }
+                Action::UpdateContainer(ContainerUpdateDefintition::new(
+                    id,
+                    vec![ContainerUpdateProperty::Transform(
+                        dequantize(quantized),
+                        easing,
+                        duration_frames,
+                    )],
+                ))
+            }
+        }
     }
+}
+
+enum StreamReader<T: Read> {
+    Plain(T),
+    // The header is read before we know the stream is compressed, so whatever was buffered past
+    // it is chained back in front of the stream.
+    Deflate(DeflateDecoder<Chain<Cursor<Vec<u8>>, T>>),
+}
 
-    match deserialize_action(&input[..size], version) {
-        Ok(action) => Ok((&input[size..], action)),
-        Err(e) => {
-            println!("{:?}", e); //Write the bincode error so we get it when debugging
-            Err(Err::Failure((input, NomErrorKind::Verify)))
+impl<T: Read> Read for StreamReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            StreamReader::Plain(stream) => stream.read(buf),
+            StreamReader::Deflate(stream) => stream.read(buf),
         }
     }
 }
 
 pub struct DeserializationIterator<T: Read> {
-    stream: T,
+    stream: StreamReader<T>,
     buffer: Buffer,
+    transform_deltas: Option<TransformDeltas>,
+    index: Option<ActionIndex>,
+}
+
+impl<T: Read> DeserializationIterator<T> {
+    /// The stream's index chunk, once it has been read. Streams written by older versions have
+    /// no index.
+    pub fn take_index(&mut self) -> Option<ActionIndex> {
+        self.index.take()
+    }
+
+    fn decode(&mut self, bytes: &[u8], version: u8) -> Result<Action, BinError> {
+        match self.transform_deltas.as_mut() {
+            Some(transform_deltas) => {
+                let encoded = bincode::deserialize(bytes)?;
+                Ok(transform_deltas.decode(encoded))
+            }
+            None => deserialize_action(bytes, version),
+        }
+    }
 }
 
 //TODO: handle error cases correctly!
@@ -83,11 +218,31 @@ impl<T: Read> Iterator for DeserializationIterator<T> {
     type Item = Action;
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            match action(self.buffer.data()) {
-                Ok((remaining, action)) => {
-                    let offset = self.buffer.available_data() - remaining.len();
+            let parsed = match record(self.buffer.data()) {
+                Ok((remaining, (version, bytes))) => Ok((
+                    self.buffer.available_data() - remaining.len(),
+                    version,
+                    bytes.to_vec(),
+                )),
+                Err(error) => Err(error),
+            };
+            match parsed {
+                Ok((offset, version, bytes)) => {
                     self.buffer.consume(offset);
-                    return Some(action);
+                    if version == INDEX_CHUNK {
+                        match bincode::deserialize(&bytes) {
+                            Ok(index) => self.index = Some(index),
+                            Err(e) => error!("Failed to deserialize index: {:?}", e),
+                        }
+                        continue;
+                    }
+                    return match self.decode(&bytes, version) {
+                        Ok(action) => Some(action),
+                        Err(e) => {
+                            error!("Failed to deserialize action: {:?}", e);
+                            None
+                        }
+                    };
                 }
                 Err(error) => match error {
                     Err::Incomplete(needed) => {
@@ -101,7 +256,14 @@ impl<T: Read> Iterator for DeserializationIterator<T> {
                         } else {
                             return None;
                         }
-                        let bytes = self.stream.read(self.buffer.space()).unwrap();
+                        let bytes = match self.stream.read(self.buffer.space()) {
+                            Ok(bytes) => bytes,
+                            // Corrupt deflate data ends up here
+                            Err(e) => {
+                                error!("Failed to read action stream: {:?}", e);
+                                return None;
+                            }
+                        };
                         if bytes == 0 {
                             return None;
                         }
@@ -133,44 +295,599 @@ pub fn deserialize_stream<T: Read>(
     buffer.consume(offset);
 
     match header.version {
-        1 => {
-            let iter = DeserializationIterator { stream, buffer };
+        FILE_VERSION => {
+            let iter = DeserializationIterator {
+                stream: StreamReader::Plain(stream),
+                buffer,
+                transform_deltas: None,
+                index: None,
+            };
+            Ok((header.stage_size, header.fps, iter))
+        }
+        COMPRESSED_FILE_VERSION => {
+            let buffered = buffer.data().to_vec();
+            let iter = DeserializationIterator {
+                stream: StreamReader::Deflate(DeflateDecoder::new(
+                    Cursor::new(buffered).chain(stream),
+                )),
+                buffer: Buffer::with_capacity(STARTING_BUFFER_SIZE),
+                transform_deltas: Some(TransformDeltas::default()),
+                index: None,
+            };
             Ok((header.stage_size, header.fps, iter))
         }
         _ => Err(BinError::from(BinErrorKind::Custom(format!(
             "Unsupported verion: {}, maximum supported version: {}",
-            header.version, FILE_VERSION
+            header.version, COMPRESSED_FILE_VERSION
         )))),
     }
 }
 
-pub fn serialize_stream(
-    actions: &[Action],
+/// Builds an `ActionList` that decodes actions from the stream as it plays, rather than loading
+/// the whole file up front. Works with both plain and compressed streams.
+pub fn deserialize_action_list<T: Read + 'static>(
+    stream: T,
+) -> Result<(Vector2I, u8, ActionList), BinError> {
+    let (stage_size, frames_per_second, mut iter) = deserialize_stream(stream)?;
+    let initial = iter.next().into_iter().collect::<Vec<Action>>();
+    if initial.is_empty() {
+        return Err(BinError::from(BinErrorKind::Custom(
+            "Stream contains no actions".to_string(),
+        )));
+    }
+    let index = iter.take_index();
+    let iter = RefCell::new(iter);
+    let action_list = ActionList::new(
+        Box::new(move || iter.borrow_mut().next().map(|action| vec![action])),
+        Some(&initial),
+    );
+    let action_list = match index {
+        Some(index) => action_list.with_index(index),
+        None => action_list,
+    };
+    Ok((stage_size, frames_per_second, action_list))
+}
+
+fn write_header(
+    out: &mut impl Write,
+    version: u8,
     stage_size: Vector2I,
     frames_per_second: u8,
-    out: &mut impl Write,
 ) -> Result<(), BinError> {
     out.write_all(&"FSR".bytes().collect::<Vec<u8>>()[..])?;
-    out.write_all(&[FILE_VERSION, frames_per_second])?;
+    out.write_all(&[version, frames_per_second])?;
     out.write_all(&stage_size.x().to_le_bytes())?;
     out.write_all(&stage_size.y().to_le_bytes())?;
+    Ok(())
+}
+
+fn write_index(out: &mut impl Write, actions: &[Action]) -> Result<(), BinError> {
+    write_record(
+        out,
+        INDEX_CHUNK,
+        &bincode::serialize(&ActionIndex::build(actions))?,
+    )
+}
+
+fn write_record(out: &mut impl Write, version: u8, serialized: &[u8]) -> Result<(), BinError> {
+    out.write_all(&[version])?;
+    out.write_all(&(serialized.len() as u32).to_le_bytes())?;
+    out.write_all(serialized)?;
+    Ok(())
+}
+
+pub fn serialize_stream(
+    actions: &[Action],
+    stage_size: Vector2I,
+    frames_per_second: u8,
+    out: &mut impl Write,
+) -> Result<(), BinError> {
+    write_header(out, FILE_VERSION, stage_size, frames_per_second)?;
+    write_index(out, actions)?;
+    for action in actions {
+        write_record(
+            out,
+            ACTION_VERSION,
+            &serialize_action(action, ACTION_VERSION)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Like `serialize_stream`, but quantizes transform keyframes, stores them as deltas from the
+/// container's previous keyframe, and deflates everything after the header. Transforms are
+/// rounded to 1/20th of a pixel and 1/4096th of a unit of scale or radian.
+pub fn serialize_stream_compressed(
+    actions: &[Action],
+    stage_size: Vector2I,
+    frames_per_second: u8,
+    out: &mut impl Write,
+) -> Result<(), BinError> {
+    write_header(out, COMPRESSED_FILE_VERSION, stage_size, frames_per_second)?;
+    let mut encoder = DeflateEncoder::new(out, Compression::best());
+    write_index(&mut encoder, actions)?;
+    let mut transform_deltas = TransformDeltas::default();
     for action in actions {
-        out.write_all(&[ACTION_VERSION])?;
-        let serialized = serialize_action(action, ACTION_VERSION)?;
-        out.write_all(&(serialized.len() as u32).to_le_bytes())?;
-        out.write_all(&serialized[..])?;
+        let encoded = transform_deltas.encode(action);
+        write_record(&mut encoder, ACTION_VERSION, &bincode::serialize(&encoded)?)?;
     }
+    encoder.finish()?;
     Ok(())
 }
 
-//Version is unused for now, but I hate unversioned APIs
+/// How `export_stream` writes a stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExportOptions {
+    /// Runs `optimize::optimize` over the actions before writing them. On by default.
+    pub optimize: bool,
+    /// Writes with `serialize_stream_compressed` rather than `serialize_stream`.
+    pub compress: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            optimize: true,
+            compress: false,
+        }
+    }
+}
+
+/// Writes `actions` for playback, the way tools should export them. Returns what optimizing
+/// saved, which is empty when it's turned off.
+pub fn export_stream(
+    actions: &[Action],
+    stage_size: Vector2I,
+    frames_per_second: u8,
+    options: ExportOptions,
+    out: &mut impl Write,
+) -> Result<OptimizationReport, BinError> {
+    let mut optimized;
+    let (actions, report) = if options.optimize {
+        optimized = actions.to_vec();
+        let report = optimize(&mut optimized);
+        (&optimized[..], report)
+    } else {
+        (actions, OptimizationReport::default())
+    };
+    if options.compress {
+        serialize_stream_compressed(actions, stage_size, frames_per_second, out)?;
+    } else {
+        serialize_stream(actions, stage_size, frames_per_second, out)?;
+    }
+    Ok(report)
+}
+
+// Actions are always written at the latest version
 pub fn serialize_action(action: &Action, _version: u8) -> Result<Vec<u8>, BinError> {
     bincode::serialize(action)
 }
 
-//Version is unused for now, but I hate unversioned APIs
-pub fn deserialize_action(bytes: &[u8], _version: u8) -> Result<Action, BinError> {
-    bincode::deserialize(bytes)
+pub fn deserialize_action(bytes: &[u8], version: u8) -> Result<Action, BinError> {
+    match version {
+        1 => bincode::deserialize::<ActionV1>(bytes).map(Action::from),
+        _ => bincode::deserialize(bytes),
+    }
+}
+
+/// `Action` as version 1 wrote it. Only container updates have changed shape since, the other
+/// actions and properties it knew are read as they are now.
+#[derive(Deserialize)]
+enum ActionV1 {
+    CreateRoot(ContainerId),
+    AddQuadTreeLayer(QuadTreeLayer, RectPoints, QuadTreeLayerOptions),
+    SetBackground {
+        color: LinSrgb,
+    },
+    EndInitialization,
+    Label(String),
+    DefineShape {
+        id: LibraryId,
+        shape: Shape,
+    },
+    LoadBitmap {
+        id: LibraryId,
+        bitmap: Bitmap,
+    },
+    CreateContainer(ContainerCreationDefintition),
+    UpdateContainer {
+        id: ContainerId,
+        properties: Vec<ContainerUpdateProperty>,
+    },
+    RemoveContainer(ContainerId, bool),
+    PresentFrame(u32, u32),
+}
+
+impl From<ActionV1> for Action {
+    fn from(action: ActionV1) -> Self {
+        match action {
+            ActionV1::CreateRoot(id) => Action::CreateRoot(id),
+            ActionV1::AddQuadTreeLayer(layer, bounds, options) => {
+                Action::AddQuadTreeLayer(layer, bounds, options)
+            }
+            ActionV1::SetBackground { color } => Action::SetBackground { color },
+            ActionV1::EndInitialization => Action::EndInitialization,
+            ActionV1::Label(label) => Action::Label(label),
+            ActionV1::DefineShape { id, shape } => Action::DefineShape { id, shape },
+            ActionV1::LoadBitmap { id, bitmap } => Action::LoadBitmap { id, bitmap },
+            ActionV1::CreateContainer(definition) => Action::CreateContainer(definition),
+            ActionV1::UpdateContainer { id, properties } => {
+                Action::UpdateContainer(ContainerUpdateDefintition::new(id, properties))
+            }
+            ActionV1::RemoveContainer(id, recursive) => Action::RemoveContainer(id, recursive),
+            ActionV1::PresentFrame(start, count) => Action::PresentFrame(start, count),
+        }
+    }
+}
+
+/// The containers and library items an action stream defines, with `CreateRoot`,
+/// `CreateContainer`, or one of the actions that loads a library item.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DefinedIds {
+    pub containers: HashSet<ContainerId>,
+    pub library_items: HashSet<LibraryId>,
+}
+
+impl DefinedIds {
+    /// Definitions are collected from anywhere in `actions`, so the result doesn't depend on
+    /// their order or on which frame they are played in.
+    pub fn from_actions(actions: &[Action]) -> Self {
+        let mut defined_ids = Self::default();
+        for action in actions {
+            match action {
+                Action::CreateRoot(id) => {
+                    defined_ids.containers.insert(*id);
+                }
+                Action::CreateContainer(definition) => {
+                    defined_ids.containers.insert(*definition.id());
+                }
+                Action::DefineShape { id, .. }
+                | Action::LoadBitmap { id, .. }
+                | Action::LoadTexture { id, .. }
+                | Action::LibraryRef { id, .. } => {
+                    defined_ids.library_items.insert(*id);
+                }
+                _ => (),
+            }
+        }
+        defined_ids
+    }
+}
+
+/// Ids renamed by `remap_colliding_ids`, from the id in the imported stream to its replacement.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IdRemapping {
+    pub containers: HashMap<ContainerId, ContainerId>,
+    pub library_items: HashMap<LibraryId, LibraryId>,
+}
+
+impl IdRemapping {
+    pub fn is_empty(&self) -> bool {
+        self.containers.is_empty() && self.library_items.is_empty()
+    }
+
+    /// The id `id` was renamed to, or `id` if it was left alone.
+    pub fn container(&self, id: &ContainerId) -> ContainerId {
+        self.containers.get(id).copied().unwrap_or(*id)
+    }
+
+    /// The id `id` was renamed to, or `id` if it was left alone.
+    pub fn library_item(&self, id: &LibraryId) -> LibraryId {
+        self.library_items.get(id).copied().unwrap_or(*id)
+    }
+
+    /// Rewrites every id `action` defines or refers to, including references nested in shapes,
+    /// hit shapes, key bindings, backgrounds, sprite sheets, LOD variants and state machines.
+    pub fn apply(&self, action: &mut Action) {
+        match action {
+            Action::CreateRoot(id) | Action::RemoveContainer(id, _) => *id = self.container(id),
+            Action::DefineShape { id, shape } => {
+                *id = self.library_item(id);
+                for reference in shape.references_mut() {
+                    *reference = self.library_item(reference);
+                }
+            }
+            Action::LoadBitmap { id, .. }
+            | Action::LoadTexture { id, .. }
+            | Action::SetLibraryMetadata { id, .. }
+            | Action::LibraryRef { id, .. } => *id = self.library_item(id),
+            Action::SetBackgroundLayers(layers) => {
+                for layer in layers.iter_mut() {
+                    if let BackgroundDefinition::Raster { id, .. } = layer {
+                        *id = self.library_item(id);
+                    }
+                }
+            }
+            Action::CreateContainer(definition) => {
+                let (id, parent, properties) = definition.parts_mut();
+                *id = self.container(id);
+                *parent = self.container(parent);
+                for property in properties.iter_mut() {
+                    match property {
+                        ContainerCreationProperty::Display(id) => *id = self.library_item(id),
+                        ContainerCreationProperty::HitShape(hit_shape) => {
+                            self.apply_hit_shape(hit_shape)
+                        }
+                        ContainerCreationProperty::KeyBindings(key_bindings) => {
+                            self.apply_key_bindings(key_bindings)
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            Action::UpdateContainer(definition) => self.apply_update(definition),
+            Action::DefineStateMachine { machine, .. } => {
+                for definition in machine.updates_mut() {
+                    self.apply_update(definition);
+                }
+            }
+            Action::DefineSpriteSheet { id, sheet } => {
+                *id = self.library_item(id);
+                for frame in sheet.frames.iter_mut() {
+                    match &mut frame.source {
+                        BakeSource::Shape { id, .. } => *id = self.library_item(id),
+                        BakeSource::Container { id, .. } => *id = self.container(id),
+                    }
+                }
+            }
+            Action::DefineLodVariants { id, variants } => {
+                *id = self.library_item(id);
+                for variant in variants.iter_mut() {
+                    variant.id = self.library_item(&variant.id);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn apply_update(&self, definition: &mut ContainerUpdateDefintition) {
+        let (id, properties) = definition.parts_mut();
+        *id = self.container(id);
+        for property in properties.iter_mut() {
+            match property {
+                ContainerUpdateProperty::Display(id) => *id = self.library_item(id),
+                ContainerUpdateProperty::Parent(parent) => *parent = self.container(parent),
+                ContainerUpdateProperty::HitShape(hit_shape) => self.apply_hit_shape(hit_shape),
+                ContainerUpdateProperty::KeyBindings(key_bindings) => {
+                    self.apply_key_bindings(key_bindings)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn apply_hit_shape(&self, hit_shape: &mut HitShape) {
+        if let HitShape::Shape(id) = hit_shape {
+            *id = self.library_item(id);
+        }
+    }
+
+    fn apply_key_bindings(&self, key_bindings: &mut Vec<KeyBinding>) {
+        for key_binding in key_bindings.iter_mut() {
+            if let KeyBindingAction::Focus(id) = &mut key_binding.action {
+                *id = self.container(id);
+            }
+        }
+    }
+}
+
+/// Gives everything `imported` defines that is already in `existing` a new id, and rewrites every
+/// reference to it in `imported`, so both streams can be played into one engine. Ids `imported`
+/// only refers to, such as a parent in the document it's imported into, are left alone. Returns
+/// the ids that were renamed.
+pub fn remap_colliding_ids(existing: &DefinedIds, imported: &mut [Action]) -> IdRemapping {
+    let defined = DefinedIds::from_actions(imported);
+    let remapping = IdRemapping {
+        containers: defined
+            .containers
+            .intersection(&existing.containers)
+            .map(|id| (*id, ContainerId::new()))
+            .collect(),
+        library_items: defined
+            .library_items
+            .intersection(&existing.library_items)
+            .map(|id| (*id, LibraryId::new()))
+            .collect(),
+    };
+    if !remapping.is_empty() {
+        for action in imported.iter_mut() {
+            remapping.apply(action);
+        }
+    }
+    remapping
+}
+
+#[cfg(test)]
+mod import_tests {
+    use super::*;
+    use crate::actions::ContainerCreationDefintition;
+    use crate::types::shapes::{AugmentedShape, Shape};
+    use pathfinder_geometry::transform2d::Transform2F;
+
+    #[test]
+    fn it_remaps_colliding_ids_and_their_references() {
+        let (root_id, container_id, shape_id) =
+            (ContainerId::new(), ContainerId::new(), LibraryId::new());
+        let existing = DefinedIds::from_actions(&[
+            Action::CreateRoot(root_id),
+            Action::DefineShape {
+                id: shape_id,
+                shape: Shape::Clip { contours: vec![] },
+            },
+            Action::CreateContainer(ContainerCreationDefintition::new(
+                root_id,
+                container_id,
+                vec![],
+            )),
+        ]);
+        let fresh_shape_id = LibraryId::new();
+        let mut imported = vec![
+            // Updates read before the creation they follow are still remapped
+            Action::UpdateContainer(ContainerUpdateDefintition::new(
+                container_id,
+                vec![ContainerUpdateProperty::Display(shape_id)],
+            )),
+            Action::CreateContainer(ContainerCreationDefintition::new(
+                root_id,
+                container_id,
+                vec![ContainerCreationProperty::Display(fresh_shape_id)],
+            )),
+            Action::DefineShape {
+                id: fresh_shape_id,
+                shape: Shape::Group {
+                    shapes: vec![AugmentedShape {
+                        shape: Shape::Reference(shape_id, Transform2F::default()),
+                        transform: Transform2F::default(),
+                    }],
+                },
+            },
+            Action::DefineShape {
+                id: shape_id,
+                shape: Shape::Clip { contours: vec![] },
+            },
+        ];
+        let remapping = remap_colliding_ids(&existing, &mut imported);
+        // The root is only referred to, so it's left alone
+        assert_eq!(remapping.containers.len(), 1);
+        assert_eq!(remapping.library_items.len(), 1);
+        let new_container_id = remapping.container(&container_id);
+        let new_shape_id = remapping.library_item(&shape_id);
+        assert_ne!(new_container_id, container_id);
+        assert_eq!(
+            imported[0],
+            Action::UpdateContainer(ContainerUpdateDefintition::new(
+                new_container_id,
+                vec![ContainerUpdateProperty::Display(new_shape_id)],
+            ))
+        );
+        assert_eq!(
+            imported[1],
+            Action::CreateContainer(ContainerCreationDefintition::new(
+                root_id,
+                new_container_id,
+                vec![ContainerCreationProperty::Display(fresh_shape_id)],
+            ))
+        );
+        match &imported[2] {
+            Action::DefineShape { id, shape } => {
+                assert_eq!(*id, fresh_shape_id);
+                assert_eq!(shape.references(), vec![new_shape_id]);
+            }
+            _ => panic!("Expected a shape"),
+        }
+        assert!(DefinedIds::from_actions(&imported)
+            .library_items
+            .is_disjoint(&existing.library_items));
+    }
+}
+
+#[cfg(test)]
+mod compression_tests {
+    use super::*;
+
+    fn keyframes(id: ContainerId, count: usize) -> Vec<Action> {
+        let mut actions = vec![];
+        for frame in 0..count {
+            let transform = ScaleRotationTranslation::new(
+                Vector2F::splat(1.0 + frame as f32 * 0.01),
+                frame as f32 * 0.05,
+                Vector2F::new(frame as f32 * 1.5, 100.0),
+            );
+            let definition = ContainerUpdateDefintition::new(
+                id,
+                vec![ContainerUpdateProperty::Transform(
+                    transform,
+                    Easing::Linear,
+                    1,
+                )],
+            );
+            // Some are delayed, like a stagger makes them
+            let definition = if frame % 10 == 0 {
+                definition.with_delay(3)
+            } else {
+                definition
+            };
+            actions.push(Action::UpdateContainer(definition));
+            actions.push(Action::PresentFrame(frame as u32, 1));
+        }
+        actions
+    }
+
+    #[test]
+    fn it_round_trips_compressed_streams() {
+        let actions = keyframes(ContainerId::new(), 500);
+        let mut plain = vec![];
+        serialize_stream(&actions, Vector2I::new(960, 480), 60, &mut plain).unwrap();
+        let mut compressed = vec![];
+        serialize_stream_compressed(&actions, Vector2I::new(960, 480), 60, &mut compressed)
+            .unwrap();
+        assert!(compressed.len() * 4 < plain.len());
+
+        let (size, frames_per_second, mut iter) =
+            deserialize_stream(Cursor::new(compressed)).unwrap();
+        assert_eq!(size, Vector2I::new(960, 480));
+        assert_eq!(frames_per_second, 60);
+        let read = iter.by_ref().collect::<Vec<Action>>();
+        assert_eq!(read.len(), actions.len());
+        assert_eq!(iter.take_index(), Some(ActionIndex::build(&actions)));
+        for (read, written) in read.iter().zip(actions.iter()) {
+            match (read, written) {
+                (Action::UpdateContainer(read), Action::UpdateContainer(written)) => {
+                    assert_eq!(read.id(), written.id());
+                    assert_eq!(read.delay(), written.delay());
+                    match (&read.properties()[0], &written.properties()[0]) {
+                        (
+                            ContainerUpdateProperty::Transform(read, _, _),
+                            ContainerUpdateProperty::Transform(written, _, _),
+                        ) => {
+                            assert!((read.translation - written.translation).length() <= 0.05);
+                            assert!((read.scale - written.scale).length() <= 0.001);
+                            assert!((read.theta - written.theta).abs() <= 0.001);
+                        }
+                        _ => panic!("Expected transforms"),
+                    }
+                }
+                _ => assert_eq!(read, written),
+            }
+        }
+    }
+
+    #[test]
+    fn it_reads_version_1_actions() {
+        let id = ContainerId::new();
+        let uuid: uuid::Uuid = id.into();
+        // Written by the first release of the stream format
+        let mut update = vec![8, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0];
+        update.extend_from_slice(uuid.as_bytes());
+        update.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 11, 0, 0, 0]);
+        let fixture: [&[u8]; 4] = [
+            &[3, 0, 0, 0],
+            &[4, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, b'e', b'n', b'd'],
+            &update,
+            &[10, 0, 0, 0, 7, 0, 0, 0, 2, 0, 0, 0],
+        ];
+        let read = fixture
+            .iter()
+            .map(|bytes| deserialize_action(bytes, 1).unwrap())
+            .collect::<Vec<Action>>();
+        assert_eq!(
+            read,
+            vec![
+                Action::EndInitialization,
+                Action::Label("end".to_string()),
+                Action::UpdateContainer(ContainerUpdateDefintition::new(
+                    id,
+                    vec![ContainerUpdateProperty::RemoveBounds],
+                )),
+                Action::PresentFrame(7, 2),
+            ]
+        );
+        // Written again at the latest version, they read back the same
+        for action in read.iter() {
+            let bytes = serialize_action(action, ACTION_VERSION).unwrap();
+            assert_eq!(&deserialize_action(&bytes, ACTION_VERSION).unwrap(), action);
+        }
+    }
 }
 
 /*#[cfg(test)]
@@ -179,7 +896,7 @@ mod tests {
     use crate::actions::{EntityDefinition, PartDefinition};
     use crate::types::{
         basic::ScaleRotationTranslation,
-        shapes::{Edge, Shape},
+        shapes::{Contour, Edge, Shape},
     };
     use pathfinder_color::ColorU;
     use pathfinder_geometry::transform2d::Transform2F;
@@ -216,12 +933,12 @@ mod tests {
         let action = Action::DefineShape {
             id: shape_id,
             shape: Shape::Fill {
-                edges: vec![
+                contours: Contour::from_edges(&[
                     Edge::Line(Vector2F::new(1.0, 5.0)),
                     Edge::Line(Vector2F::new(5.0, 1.0)),
                     Edge::Line(Vector2F::new(1.0, 1.0)),
                     Edge::Line(Vector2F::new(5.0, 5.0)),
-                ],
+                ]),
                 color: ColorU::white(),
             },
         };
@@ -243,12 +960,12 @@ mod tests {
             Action::DefineShape {
                 id: shape_id,
                 shape: Shape::Fill {
-                    edges: vec![
+                    contours: Contour::from_edges(&[
                         Edge::Line(Vector2F::new(1.0, 5.0)),
                         Edge::Line(Vector2F::new(5.0, 1.0)),
                         Edge::Line(Vector2F::new(1.0, 1.0)),
                         Edge::Line(Vector2F::new(5.0, 5.0)),
-                    ],
+                    ]),
                     color: ColorU::white(),
                 },
             },
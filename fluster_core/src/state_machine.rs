@@ -0,0 +1,379 @@
+use crate::{actions::ContainerUpdateDefintition, tween::Easing};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// What happens when a state is entered.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum StateAction {
+    /// Jumps the `ActionList` to a label. The jump is made by the `Runner` after the frame.
+    Label(String),
+    /// Applies a set of container updates, cross-faded by the transition's blend.
+    Updates(Vec<ContainerUpdateDefintition>),
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum TransitionCondition {
+    Flag(String),
+    NotFlag(String),
+    /// An event fired since the last update.
+    Event(String),
+    /// The machine has spent at least this many frames in its current state.
+    AfterFrames(u32),
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Transition {
+    /// `None` allows the transition from any state.
+    from: Option<String>,
+    to: String,
+    /// All conditions must hold for the transition to be taken.
+    conditions: Vec<TransitionCondition>,
+    blend: Option<(Easing, u32)>,
+}
+
+impl Transition {
+    pub fn new(from: Option<String>, to: String, conditions: Vec<TransitionCondition>) -> Self {
+        Self {
+            from,
+            to,
+            conditions,
+            blend: None,
+        }
+    }
+
+    pub fn with_blend(mut self, easing: Easing, duration_frames: u32) -> Self {
+        self.blend = Some((easing, duration_frames));
+        self
+    }
+
+    fn is_satisfied(
+        &self,
+        current: &str,
+        frames_in_state: u32,
+        flags: &HashSet<String>,
+        events: &HashSet<String>,
+    ) -> bool {
+        self.from.as_ref().map_or(true, |from| from == current)
+            && self.to != current
+            && self.conditions.iter().all(|condition| match condition {
+                TransitionCondition::Flag(flag) => flags.contains(flag),
+                TransitionCondition::NotFlag(flag) => !flags.contains(flag),
+                TransitionCondition::Event(event) => events.contains(event),
+                TransitionCondition::AfterFrames(frames) => frames_in_state >= *frames,
+            })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StateMachine {
+    states: HashMap<String, StateAction>,
+    transitions: Vec<Transition>,
+    current: String,
+    #[serde(skip)]
+    frames_in_state: u32,
+    #[serde(skip)]
+    pending: Option<(String, Option<(Easing, u32)>)>,
+}
+
+impl StateMachine {
+    pub fn new(
+        states: HashMap<String, StateAction>,
+        transitions: Vec<Transition>,
+        initial: String,
+    ) -> Result<Self, String> {
+        let new_self = Self {
+            states,
+            transitions,
+            pending: Some((initial.clone(), None)),
+            current: initial,
+            frames_in_state: 0,
+        };
+        new_self.validate()?;
+        Ok(new_self)
+    }
+
+    // Machines read from a stream skip `new`, so are checked again when added
+    fn validate(&self) -> Result<(), String> {
+        if !self.states.contains_key(&self.current) {
+            return Err(format!("Initial state {} is not defined", self.current));
+        }
+        if let Some(transition) = self
+            .transitions
+            .iter()
+            .find(|transition| !self.states.contains_key(&transition.to))
+        {
+            return Err(format!("Transition to undefined state {}", transition.to));
+        }
+        Ok(())
+    }
+
+    pub fn current(&self) -> &str {
+        &self.current
+    }
+
+    // Every container update a state applies
+    pub(crate) fn updates(&self) -> impl Iterator<Item = &ContainerUpdateDefintition> {
+        self.states
+            .values()
+            .filter_map(|state_action| match state_action {
+                StateAction::Updates(updates) => Some(updates.iter()),
+                StateAction::Label(..) => None,
+            })
+            .flatten()
+    }
+
+    // Like `updates`, for rewriting ids on import
+    pub(crate) fn updates_mut(&mut self) -> impl Iterator<Item = &mut ContainerUpdateDefintition> {
+        self.states
+            .values_mut()
+            .filter_map(|state_action| match state_action {
+                StateAction::Updates(updates) => Some(updates.iter_mut()),
+                StateAction::Label(..) => None,
+            })
+            .flatten()
+    }
+
+    /// Queues a transition for the next update, ignoring conditions.
+    pub fn force_transition(
+        &mut self,
+        state: &str,
+        blend: Option<(Easing, u32)>,
+    ) -> Result<(), String> {
+        if !self.states.contains_key(state) {
+            return Err(format!("State {} is not defined", state));
+        }
+        self.pending = Some((state.to_string(), blend));
+        Ok(())
+    }
+
+    /// Moves to the first state whose transition is satisfied. Returns the entered state's action
+    /// and the blend to enter it with. Only one transition is taken per frame.
+    pub fn advance(
+        &mut self,
+        delta_frames: u32,
+        flags: &HashSet<String>,
+        events: &HashSet<String>,
+    ) -> Option<(StateAction, Option<(Easing, u32)>)> {
+        self.frames_in_state += delta_frames;
+        let next = self.pending.take().or_else(|| {
+            let (current, frames_in_state) = (&self.current, self.frames_in_state);
+            self.transitions
+                .iter()
+                .find(|transition| transition.is_satisfied(current, frames_in_state, flags, events))
+                .map(|transition| (transition.to.clone(), transition.blend))
+        });
+        let (state, blend) = next?;
+        // Every state is checked to exist when the machine is added
+        let state_action = self.states.get(&state)?.clone();
+        self.current = state;
+        self.frames_in_state = 0;
+        Some((state_action, blend))
+    }
+
+    fn would_advance(
+        &self,
+        delta_frames: u32,
+        flags: &HashSet<String>,
+        events: &HashSet<String>,
+    ) -> bool {
+        let frames_in_state = self.frames_in_state + delta_frames;
+        self.pending.is_some()
+            || self.transitions.iter().any(|transition| {
+                transition.is_satisfied(&self.current, frames_in_state, flags, events)
+            })
+    }
+}
+
+/// Every state machine in the scene, along with the flags and events their transitions read.
+/// Machines advance in order of their names.
+#[derive(Default, Debug)]
+pub struct StateMachines {
+    machines: BTreeMap<String, StateMachine>,
+    flags: HashSet<String>,
+    events: HashSet<String>,
+    pending_label: Option<String>,
+}
+
+impl StateMachines {
+    /// Adds a machine, which enters its current state on the next update. Fails if its initial
+    /// state or a transition's target isn't defined.
+    pub fn add(&mut self, name: String, mut machine: StateMachine) -> Result<(), String> {
+        machine
+            .validate()
+            .map_err(|error| format!("State machine {}: {}", name, error))?;
+        if machine.pending.is_none() {
+            machine.pending = Some((machine.current.clone(), None));
+        }
+        self.machines.insert(name, machine);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<StateMachine> {
+        self.machines.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StateMachine> {
+        self.machines.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut StateMachine> {
+        self.machines.get_mut(name)
+    }
+
+    pub fn set_flag(&mut self, flag: &str, value: bool) {
+        if value {
+            self.flags.insert(flag.to_string());
+        } else {
+            self.flags.remove(flag);
+        }
+    }
+
+    pub fn fire_event(&mut self, event: &str) {
+        self.events.insert(event.to_string());
+    }
+
+    /// Advances every machine, returning the updates of any states entered. Events are consumed.
+    /// If several machines jump to a label in the same frame, the one whose name sorts first wins.
+    pub fn advance(&mut self, delta_frames: u32) -> Vec<ContainerUpdateDefintition> {
+        let mut updates = vec![];
+        let mut label_jump = None;
+        let (flags, events) = (&self.flags, &self.events);
+        for machine in self.machines.values_mut() {
+            match machine.advance(delta_frames, flags, events) {
+                Some((StateAction::Updates(definitions), blend)) => {
+                    updates.extend(definitions.into_iter().map(|definition| match blend {
+                        Some((easing, duration_frames)) => {
+                            definition.with_blend_in(easing, duration_frames)
+                        }
+                        None => definition,
+                    }))
+                }
+                Some((StateAction::Label(label), _)) => {
+                    label_jump.get_or_insert(label);
+                }
+                None => (),
+            }
+        }
+        if label_jump.is_some() {
+            self.pending_label = label_jump;
+        }
+        self.events.clear();
+        updates
+    }
+
+    /// True if advancing by `delta_frames` would not change any machine's state.
+    pub fn is_settled(&self, delta_frames: u32) -> bool {
+        self.pending_label.is_none()
+            && self.events.is_empty()
+            && self
+                .machines
+                .values()
+                .all(|machine| !machine.would_advance(delta_frames, &self.flags, &self.events))
+    }
+
+    /// Counts frames towards `AfterFrames` transitions without advancing. Only valid while
+    /// `is_settled`, since no transitions are checked.
+    pub fn skip_frames(&mut self, delta_frames: u32) {
+        for machine in self.machines.values_mut() {
+            machine.frames_in_state += delta_frames;
+        }
+    }
+
+    /// The label the action list should jump to, if a state asked for one.
+    pub fn take_pending_label(&mut self) -> Option<String> {
+        self.pending_label.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> StateMachine {
+        let mut states = HashMap::new();
+        states.insert("idle".to_string(), StateAction::Updates(vec![]));
+        states.insert("walk".to_string(), StateAction::Label("walk".to_string()));
+        let transitions = vec![
+            Transition::new(
+                Some("idle".to_string()),
+                "walk".to_string(),
+                vec![TransitionCondition::Event("move".to_string())],
+            ),
+            Transition::new(
+                None,
+                "idle".to_string(),
+                vec![TransitionCondition::AfterFrames(2)],
+            ),
+        ];
+        StateMachine::new(states, transitions, "idle".to_string()).unwrap()
+    }
+
+    #[test]
+    fn it_follows_transitions() {
+        let mut machine = machine();
+        let flags = HashSet::new();
+        let mut events = HashSet::new();
+        // Entering the initial state
+        assert!(machine.advance(1, &flags, &events).is_some());
+        assert!(machine.advance(1, &flags, &events).is_none());
+        events.insert("move".to_string());
+        assert_eq!(
+            machine.advance(1, &flags, &events),
+            Some((StateAction::Label("walk".to_string()), None))
+        );
+        events.clear();
+        assert!(machine.advance(1, &flags, &events).is_none());
+        assert!(machine.advance(1, &flags, &events).is_some());
+        assert_eq!(machine.current(), "idle");
+    }
+
+    #[test]
+    fn it_settles_between_transitions() {
+        let mut machines = StateMachines::default();
+        machines.add("player".to_string(), machine()).unwrap();
+        assert!(!machines.is_settled(1));
+        machines.advance(1);
+        assert!(machines.is_settled(1));
+        machines.fire_event("move");
+        assert!(!machines.is_settled(1));
+        machines.advance(1);
+        assert_eq!(machines.take_pending_label(), Some("walk".to_string()));
+        assert!(machines.is_settled(1));
+        machines.skip_frames(1);
+        assert!(!machines.is_settled(1));
+    }
+
+    #[test]
+    fn it_rejects_undefined_states() {
+        let mut machine = machine();
+        assert!(machine.force_transition("run", None).is_err());
+        assert!(StateMachine::new(HashMap::new(), vec![], "idle".to_string()).is_err());
+    }
+
+    #[test]
+    fn it_rejects_undefined_states_read_from_a_stream() {
+        let mut machines = StateMachines::default();
+        let mut undefined_initial = machine();
+        undefined_initial.current = "run".to_string();
+        assert!(machines.add("a".to_string(), undefined_initial).is_err());
+        let mut undefined_target = machine();
+        undefined_target
+            .transitions
+            .push(Transition::new(None, "run".to_string(), vec![]));
+        assert!(machines.add("b".to_string(), undefined_target).is_err());
+        assert!(machines.get("a").is_none() && machines.get("b").is_none());
+    }
+
+    #[test]
+    fn it_jumps_to_the_label_of_the_first_machine_by_name() {
+        let mut machines = StateMachines::default();
+        for name in ["c", "a", "b"].iter() {
+            let mut states = HashMap::new();
+            states.insert(name.to_string(), StateAction::Label(name.to_string()));
+            let machine = StateMachine::new(states, vec![], name.to_string()).unwrap();
+            machines.add(name.to_string(), machine).unwrap();
+        }
+        machines.advance(1);
+        assert_eq!(machines.take_pending_label(), Some("a".to_string()));
+    }
+}
@@ -1,7 +1,8 @@
 use super::actions::RectPoints;
 use super::types::{
     basic::ScaleRotationTranslation,
-    coloring::{ColorSpace, Coloring},
+    coloring::{ColorSpace, ColorTransform, Coloring},
+    shapes::PathTrim,
 };
 use super::util;
 use pathfinder_geometry::rect::RectF;
@@ -11,6 +12,7 @@ use std::f32::{
     consts::{FRAC_PI_2, PI},
     EPSILON,
 };
+use std::mem;
 use std::time::Duration;
 
 pub trait Tween {
@@ -250,6 +252,19 @@ pub struct PropertyTween {
     data: PropertyTweenData,
     elapsed: TweenElapsed,
     easing: Easing,
+    blend_in: Option<Box<BlendIn>>,
+    // Frames left before the tween starts. It holds its start value until then.
+    delay_frames: u32,
+}
+
+/// Cross-fades from the tweens a `PropertyTween` replaced into the new tween, so that switching
+/// animations part way through doesn't snap. The outgoing tweens keep running until the blend
+/// completes.
+#[derive(Clone, Debug)]
+pub struct BlendIn {
+    outgoing: Vec<PropertyTween>,
+    elapsed: TweenElapsed,
+    easing: Easing,
 }
 
 #[derive(Clone, Debug)]
@@ -282,9 +297,25 @@ impl TweenElapsed {
         }
     }
 
+    pub fn advance(&self, delta_frames: u32, delta_time: Duration) -> Self {
+        match self {
+            Self::Time(elapsed_time, max_time) => {
+                let elapsed_time = if let Some(time) = elapsed_time.checked_add(delta_time) {
+                    time
+                } else {
+                    *max_time
+                };
+                Self::Time(elapsed_time, *max_time)
+            }
+            Self::Frame(elapsed_frame, max_frame) => {
+                Self::Frame(elapsed_frame + delta_frames, *max_frame)
+            }
+        }
+    }
+
     pub fn as_percent(&self) -> f32 {
         match self {
-            Self::Time(elapsed, max) => elapsed.div_duration_f32(*max),
+            Self::Time(elapsed, max) => util::div_duration_f32(*elapsed, *max),
             Self::Frame(elapsed, max) => *elapsed as f32 / *max as f32,
         }
     }
@@ -301,6 +332,10 @@ pub enum PropertyTweenData {
         start: ScaleRotationTranslation,
         end: ScaleRotationTranslation,
     },
+    TransformMatrix {
+        start: Transform2F,
+        end: Transform2F,
+    },
     ViewRect {
         start: RectPoints,
         end: RectPoints,
@@ -309,10 +344,37 @@ pub enum PropertyTweenData {
         start: f32,
         end: f32,
     },
+    MorphIndices {
+        start: Vec<f32>,
+        end: Vec<f32>,
+    },
+    ColorTransform {
+        start: ColorTransform,
+        end: ColorTransform,
+    },
     Order {
         start: i8,
         end: i8,
     },
+    PathTrim {
+        start: PathTrim,
+        end: PathTrim,
+    },
+}
+
+impl PropertyTweenData {
+    /// True for both decomposed and matrix transform tweens, which drive the same property.
+    pub fn is_transform(&self) -> bool {
+        match self {
+            Self::Transform { .. } | Self::TransformMatrix { .. } => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_same_property(&self, other: &PropertyTweenData) -> bool {
+        mem::discriminant(self) == mem::discriminant(other)
+            || (self.is_transform() && other.is_transform())
+    }
 }
 
 impl PropertyTween {
@@ -331,6 +393,8 @@ impl PropertyTween {
             },
             elapsed: Self::construct_elapsed(duration),
             easing,
+            blend_in: None,
+            delay_frames: 0,
         }
     }
 
@@ -353,6 +417,23 @@ impl PropertyTween {
             data: PropertyTweenData::Transform { start, end },
             elapsed: Self::construct_elapsed(duration),
             easing,
+            blend_in: None,
+            delay_frames: 0,
+        }
+    }
+
+    pub fn new_transform_matrix(
+        start: Transform2F,
+        end: Transform2F,
+        duration: TweenDuration,
+        easing: Easing,
+    ) -> Self {
+        Self {
+            data: PropertyTweenData::TransformMatrix { start, end },
+            elapsed: Self::construct_elapsed(duration),
+            easing,
+            blend_in: None,
+            delay_frames: 0,
         }
     }
 
@@ -366,6 +447,8 @@ impl PropertyTween {
             data: PropertyTweenData::ViewRect { start, end },
             elapsed: Self::construct_elapsed(duration),
             easing,
+            blend_in: None,
+            delay_frames: 0,
         }
     }
 
@@ -377,6 +460,41 @@ impl PropertyTween {
             },
             elapsed: Self::construct_elapsed(duration),
             easing,
+            blend_in: None,
+            delay_frames: 0,
+        }
+    }
+
+    pub fn new_morph_indices(
+        start: Vec<f32>,
+        end: Vec<f32>,
+        duration: TweenDuration,
+        easing: Easing,
+    ) -> Self {
+        Self {
+            data: PropertyTweenData::MorphIndices {
+                start: start.into_iter().map(util::clamp_0_1).collect(),
+                end: end.into_iter().map(util::clamp_0_1).collect(),
+            },
+            elapsed: Self::construct_elapsed(duration),
+            easing,
+            blend_in: None,
+            delay_frames: 0,
+        }
+    }
+
+    pub fn new_color_transform(
+        start: ColorTransform,
+        end: ColorTransform,
+        duration: TweenDuration,
+        easing: Easing,
+    ) -> Self {
+        Self {
+            data: PropertyTweenData::ColorTransform { start, end },
+            elapsed: Self::construct_elapsed(duration),
+            easing,
+            blend_in: None,
+            delay_frames: 0,
         }
     }
 
@@ -385,6 +503,23 @@ impl PropertyTween {
             data: PropertyTweenData::Order { start, end },
             elapsed: Self::construct_elapsed(duration),
             easing,
+            blend_in: None,
+            delay_frames: 0,
+        }
+    }
+
+    pub fn new_path_trim(
+        start: PathTrim,
+        end: PathTrim,
+        duration: TweenDuration,
+        easing: Easing,
+    ) -> Self {
+        Self {
+            data: PropertyTweenData::PathTrim { start, end },
+            elapsed: Self::construct_elapsed(duration),
+            easing,
+            blend_in: None,
+            delay_frames: 0,
         }
     }
 
@@ -395,41 +530,45 @@ impl PropertyTween {
         }
     }
 
-    pub fn tween_data(&self) -> &PropertyTweenData {
-        &self.data
+    /// Blends from `outgoing` into this tween over `duration`. `outgoing` should only contain
+    /// tweens of the same property.
+    pub fn with_blend_in(
+        mut self,
+        outgoing: Vec<PropertyTween>,
+        duration: TweenDuration,
+        easing: Easing,
+    ) -> Self {
+        self.blend_in = if outgoing.is_empty() {
+            None
+        } else {
+            Some(Box::new(BlendIn {
+                outgoing,
+                elapsed: Self::construct_elapsed(duration),
+                easing,
+            }))
+        };
+        self
     }
-}
 
-#[derive(Clone, Debug)]
-pub enum PropertyTweenUpdate {
-    Coloring(Coloring),
-    Transform(Transform2F),
-    ViewRect(RectF),
-    Morph(f32),
-    Order(i8),
-}
+    /// Holds the tween at its start for `delay_frames` frames before it starts playing.
+    pub fn with_delay(mut self, delay_frames: u32) -> Self {
+        self.delay_frames = delay_frames;
+        self
+    }
 
-impl Tween for PropertyTween {
-    type Item = PropertyTweenUpdate;
+    pub fn tween_data(&self) -> &PropertyTweenData {
+        &self.data
+    }
 
-    fn update(&mut self, delta_frames: u32, delta_time: Duration) {
-        let elapsed = match &self.elapsed {
-            TweenElapsed::Time(elapsed_time, max_time) => {
-                let elapsed_time = if let Some(time) = elapsed_time.checked_add(delta_time) {
-                    time
-                } else {
-                    *max_time
-                };
-                TweenElapsed::Time(elapsed_time, *max_time)
-            }
-            TweenElapsed::Frame(elapsed_frame, max_frame) => {
-                TweenElapsed::Frame(elapsed_frame + delta_frames, *max_frame)
-            }
-        };
-        self.elapsed = elapsed;
+    fn color_space(&self) -> ColorSpace {
+        if let PropertyTweenData::Coloring { color_space, .. } = &self.data {
+            *color_space
+        } else {
+            ColorSpace::Linear
+        }
     }
 
-    fn compute(&self) -> Self::Item {
+    fn compute_unblended(&self) -> PropertyTweenUpdate {
         let value = self.easing.ease(self.elapsed.as_percent());
         match &self.data {
             PropertyTweenData::Coloring {
@@ -438,11 +577,10 @@ impl Tween for PropertyTween {
                 color_space,
             } => PropertyTweenUpdate::Coloring(start.lerp(end, value, *color_space)),
             PropertyTweenData::Transform { start, end } => {
-                PropertyTweenUpdate::Transform(Transform2F::from_scale_rotation_translation(
-                    start.scale.lerp(end.scale, value),
-                    (end.theta - start.theta) * value + start.theta,
-                    start.translation.lerp(end.translation, value),
-                ))
+                PropertyTweenUpdate::Transform(lerp_transform(start, end, value))
+            }
+            PropertyTweenData::TransformMatrix { start, end } => {
+                PropertyTweenUpdate::Transform(lerp_matrix(start, end, value))
             }
             PropertyTweenData::ViewRect { start, end } => {
                 PropertyTweenUpdate::ViewRect(RectF::from_points(
@@ -453,13 +591,155 @@ impl Tween for PropertyTween {
             PropertyTweenData::MorphIndex { start, end } => {
                 PropertyTweenUpdate::Morph(util::lerp(*start, *end, value))
             }
+            PropertyTweenData::MorphIndices { start, end } => {
+                PropertyTweenUpdate::MorphIndices(lerp_indices(start, end, value))
+            }
+            PropertyTweenData::ColorTransform { start, end } => {
+                PropertyTweenUpdate::ColorTransform(start.lerp(end, value))
+            }
             PropertyTweenData::Order { start, end } => {
                 PropertyTweenUpdate::Order(util::lerp(*start as f32, *end as f32, value) as i8)
             }
+            PropertyTweenData::PathTrim { start, end } => {
+                PropertyTweenUpdate::PathTrim(start.lerp(end, value))
+            }
+        }
+    }
+}
+
+fn lerp_transform(
+    start: &ScaleRotationTranslation,
+    end: &ScaleRotationTranslation,
+    value: f32,
+) -> Transform2F {
+    Transform2F::from_scale_rotation_translation(
+        start.scale.lerp(end.scale, value),
+        (end.theta - start.theta) * value + start.theta,
+        start.translation.lerp(end.translation, value),
+    )
+}
+
+// Indices missing from `start` hold at their `end` value
+fn lerp_indices(start: &[f32], end: &[f32], value: f32) -> Vec<f32> {
+    end.iter()
+        .enumerate()
+        .map(|(index, end)| util::lerp(start.get(index).copied().unwrap_or(*end), *end, value))
+        .collect()
+}
+
+fn lerp_matrix(start: &Transform2F, end: &Transform2F, value: f32) -> Transform2F {
+    let translation = start.translation().lerp(end.translation(), value);
+    Transform2F::row_major(
+        util::lerp(start.m11(), end.m11(), value),
+        util::lerp(start.m12(), end.m12(), value),
+        translation.x(),
+        util::lerp(start.m21(), end.m21(), value),
+        util::lerp(start.m22(), end.m22(), value),
+        translation.y(),
+    )
+}
+
+#[derive(Clone, Debug)]
+pub enum PropertyTweenUpdate {
+    Coloring(Coloring),
+    Transform(Transform2F),
+    ViewRect(RectF),
+    Morph(f32),
+    MorphIndices(Vec<f32>),
+    ColorTransform(ColorTransform),
+    Order(i8),
+    PathTrim(PathTrim),
+}
+
+impl PropertyTweenUpdate {
+    /// Interpolates between two updates of the same property. Mismatched properties snap to `end`.
+    pub fn lerp(&self, end: &PropertyTweenUpdate, value: f32, color_space: ColorSpace) -> Self {
+        match (self, end) {
+            (Self::Coloring(start), Self::Coloring(end)) => {
+                Self::Coloring(start.lerp(end, value, color_space))
+            }
+            (Self::Transform(start), Self::Transform(end)) => {
+                let start = ScaleRotationTranslation::from_transform(*start);
+                let mut end = ScaleRotationTranslation::from_transform(*end);
+                // Go the short way around
+                let delta = end.theta - start.theta;
+                if delta > PI {
+                    end.theta -= PI * 2.0;
+                } else if delta < -PI {
+                    end.theta += PI * 2.0;
+                }
+                Self::Transform(lerp_transform(&start, &end, value))
+            }
+            (Self::ViewRect(start), Self::ViewRect(end)) => Self::ViewRect(RectF::from_points(
+                start.origin().lerp(end.origin(), value),
+                start.lower_right().lerp(end.lower_right(), value),
+            )),
+            (Self::Morph(start), Self::Morph(end)) => Self::Morph(util::lerp(*start, *end, value)),
+            (Self::MorphIndices(start), Self::MorphIndices(end)) => {
+                Self::MorphIndices(lerp_indices(start, end, value))
+            }
+            (Self::ColorTransform(start), Self::ColorTransform(end)) => {
+                Self::ColorTransform(start.lerp(end, value))
+            }
+            (Self::Order(start), Self::Order(end)) => {
+                Self::Order(util::lerp(*start as f32, *end as f32, value) as i8)
+            }
+            (Self::PathTrim(start), Self::PathTrim(end)) => Self::PathTrim(start.lerp(end, value)),
+            (_, end) => end.clone(),
+        }
+    }
+}
+
+impl Tween for PropertyTween {
+    type Item = PropertyTweenUpdate;
+
+    fn update(&mut self, delta_frames: u32, delta_time: Duration) {
+        if self.delay_frames > 0 {
+            // TODO: carry the frames left over from the delay into the tween
+            self.delay_frames = self.delay_frames.saturating_sub(delta_frames);
+            return;
+        }
+        self.elapsed = self.elapsed.advance(delta_frames, delta_time);
+        if let Some(blend_in) = &mut self.blend_in {
+            blend_in.elapsed = blend_in.elapsed.advance(delta_frames, delta_time);
+            for tween in blend_in.outgoing.iter_mut() {
+                tween.update(delta_frames, delta_time);
+            }
+        }
+        if self
+            .blend_in
+            .as_ref()
+            .map_or(false, |blend_in| blend_in.elapsed.is_complete())
+        {
+            self.blend_in = None;
+        }
+    }
+
+    fn compute(&self) -> Self::Item {
+        let update = self.compute_unblended();
+        if let Some(blend_in) = &self.blend_in {
+            let color_space = self.color_space();
+            // Outgoing tweens are weighted evenly
+            let outgoing = blend_in
+                .outgoing
+                .iter()
+                .map(|tween| tween.compute())
+                .enumerate()
+                .fold(None, |acc: Option<PropertyTweenUpdate>, (index, next)| {
+                    Some(match acc {
+                        Some(acc) => acc.lerp(&next, 1.0 / (index + 1) as f32, color_space),
+                        None => next,
+                    })
+                });
+            if let Some(outgoing) = outgoing {
+                let value = blend_in.easing.ease(blend_in.elapsed.as_percent());
+                return outgoing.lerp(&update, value, color_space);
+            }
         }
+        update
     }
     fn is_complete(&self) -> bool {
-        self.elapsed.is_complete()
+        self.delay_frames == 0 && self.elapsed.is_complete() && self.blend_in.is_none()
     }
     fn easing(&self) -> Easing {
         self.easing
@@ -1,5 +1,5 @@
 use pathfinder_color::ColorU;
-use pathfinder_content::pattern::{Image, Pattern};
+use pathfinder_content::pattern::{Image, Pattern, PatternSource};
 use pathfinder_geometry::transform2d::Transform2F;
 use pathfinder_geometry::vector::{Vector2F, Vector2I};
 use pathfinder_simd::default::F32x2;
@@ -92,6 +92,49 @@ impl Bitmap {
     }
 }
 
+/// A serializable snapshot of an image backed `Pattern`, pixels stored as RGBA8.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TextureDefinition {
+    pub size_x: i32,
+    pub size_y: i32,
+    #[serde(
+        serialize_with = "bitmap_contents_ser",
+        deserialize_with = "bitmap_contents_des"
+    )]
+    pub colors: Arc<Vec<ColorU>>,
+    pub smoothing_enabled: bool,
+    pub repeat_x: bool,
+    pub repeat_y: bool,
+}
+
+impl TextureDefinition {
+    /// Fails for patterns drawn from render targets, since their pixels live on the GPU.
+    pub fn from_pattern(pattern: &Pattern) -> Result<Self, String> {
+        match pattern.source() {
+            PatternSource::Image(image) => Ok(Self {
+                size_x: image.size().x(),
+                size_y: image.size().y(),
+                colors: image.pixels().clone(),
+                smoothing_enabled: pattern.smoothing_enabled(),
+                repeat_x: pattern.repeat_x(),
+                repeat_y: pattern.repeat_y(),
+            }),
+            PatternSource::RenderTarget { .. } => {
+                Err("Render target patterns can't be serialized".to_string())
+            }
+        }
+    }
+
+    pub fn to_pattern(&self) -> Pattern {
+        let image = Image::new(Vector2I::new(self.size_x, self.size_y), self.colors.clone());
+        let mut pattern = Pattern::from_image(image);
+        pattern.set_smoothing_enabled(self.smoothing_enabled);
+        pattern.set_repeat_x(self.repeat_x);
+        pattern.set_repeat_y(self.repeat_y);
+        pattern
+    }
+}
+
 pub fn bitmap_contents_des<'de, D>(deserializer: D) -> Result<Arc<Vec<ColorU>>, D::Error>
 where
     D: Deserializer<'de>,
@@ -1,3 +1,4 @@
+use crate::util;
 use palette::{FromColor, Hsva, Laba, Lcha, LinSrgba, Mix};
 use pathfinder_geometry::vector::Vector4F;
 use serde::{Deserialize, Serialize};
@@ -40,35 +41,44 @@ impl DenormalizedColoring {
         coloring.into_denormalized()
     }
 
-    pub fn add(&self, other: &Self) -> Self {
-        match self {
-            Self::Color(self_deno) => {
-                if let Self::Color(other_deno) = other {
-                    Self::Color(*self_deno + *other_deno)
-                } else {
-                    Self::None
-                }
-            }
-            Self::Colorings(self_denos) => {
-                if let Self::Colorings(other_denos) = other {
-                    if self_denos.len() != other_denos.len() {
-                        Self::None
-                    } else {
-                        let mut new_colorings: Vec<DenormalizedColoring> =
-                            vec![DenormalizedColoring::None; self_denos.len()];
-                        for i in 0..self_denos.len() {
-                            new_colorings[i] = self_denos[i].add(&other_denos[i]);
-                        }
-                        Self::Colorings(new_colorings)
-                    }
-                } else {
-                    Self::None
-                }
+    /// Combines two colorings component by component. Mismatched shapes are resolved by:
+    /// - `None` contributing nothing, the other side is returned unchanged
+    /// - a single `Color` being broadcast across every entry of `Colorings`
+    /// - `Colorings` of different lengths being truncated to the shorter of the two
+    pub fn combine(&self, other: &Self, op: &impl Fn(Vector4F, Vector4F) -> Vector4F) -> Self {
+        match (self, other) {
+            (Self::None, other) => other.clone(),
+            (this, Self::None) => this.clone(),
+            (Self::Color(self_deno), Self::Color(other_deno)) => {
+                Self::Color(op(*self_deno, *other_deno))
             }
-            Self::None => Self::None,
+            (Self::Color(_), Self::Colorings(other_denos)) => Self::Colorings(
+                other_denos
+                    .iter()
+                    .map(|other_deno| self.combine(other_deno, op))
+                    .collect(),
+            ),
+            (Self::Colorings(self_denos), Self::Color(_)) => Self::Colorings(
+                self_denos
+                    .iter()
+                    .map(|self_deno| self_deno.combine(other, op))
+                    .collect(),
+            ),
+            (Self::Colorings(self_denos), Self::Colorings(other_denos)) => Self::Colorings(
+                self_denos
+                    .iter()
+                    .zip(other_denos.iter())
+                    .map(|(self_deno, other_deno)| self_deno.combine(other_deno, op))
+                    .collect(),
+            ),
         }
     }
 
+    /// See `combine` for how mismatched colorings are handled.
+    pub fn add(&self, other: &Self) -> Self {
+        self.combine(other, &|a, b| a + b)
+    }
+
     pub fn div(&self, rhs: f32) -> Self {
         match self {
             Self::Color(self_deno) => Self::Color(*self_deno * Vector4F::splat(1.0 / rhs)),
@@ -117,6 +127,17 @@ impl Coloring {
         denormalized.into_coloring()
     }
 
+    /// Mixes `other` into this coloring in linear space, `weight` 0.0 being all `self` and 1.0
+    /// all `other`. Unlike `lerp`, mismatched colorings are reconciled as described in
+    /// `DenormalizedColoring::combine` rather than dropped.
+    pub fn blend(&self, other: &Coloring, weight: f32) -> Coloring {
+        self.into_denormalized()
+            .combine(&other.into_denormalized(), &|a, b| {
+                a + (b - a) * Vector4F::splat(weight)
+            })
+            .into_coloring()
+    }
+
     // If the Colorings don't match return None. In effect this means we'll return to the default Coloring of the shape.
     pub fn lerp(&self, end: &Coloring, percent: f32, color_space: ColorSpace) -> Self {
         match self {
@@ -166,3 +187,200 @@ impl Coloring {
         }
     }
 }
+
+/// Multiplies then offsets each channel of a color, in linear RGBA, like Flash's
+/// ColorTransform. Containers apply their own, then their ancestors', to everything they draw.
+// TODO: hue rotation needs a full color matrix
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ColorTransform {
+    pub multiply: [f32; 4],
+    pub offset: [f32; 4],
+}
+
+impl Default for ColorTransform {
+    fn default() -> Self {
+        Self {
+            multiply: [1.0; 4],
+            offset: [0.0; 4],
+        }
+    }
+}
+
+impl ColorTransform {
+    /// Scales red, green and blue. 0.0 fades to black.
+    pub fn brightness(amount: f32) -> Self {
+        Self {
+            multiply: [amount, amount, amount, 1.0],
+            ..Self::default()
+        }
+    }
+
+    /// Scales alpha, clamped to at least 0.0. 0.0 is fully transparent.
+    pub fn opacity(amount: f32) -> Self {
+        Self {
+            multiply: [1.0, 1.0, 1.0, amount.max(0.0)],
+            ..Self::default()
+        }
+    }
+
+    /// Scales red, green and blue away from mid grey. 0.0 is flat grey.
+    pub fn contrast(amount: f32) -> Self {
+        let offset = 0.5 * (1.0 - amount);
+        Self {
+            multiply: [amount, amount, amount, 1.0],
+            offset: [offset, offset, offset, 0.0],
+        }
+    }
+
+    /// Mixes `amount` of a solid color over everything, keeping alpha. 1.0 is a flat fill, for
+    /// effects like flashing white on a hit.
+    pub fn fill(color: LinSrgba, amount: f32) -> Self {
+        let keep = 1.0 - amount;
+        Self {
+            multiply: [keep, keep, keep, 1.0],
+            offset: [
+                color.color.red * amount,
+                color.color.green * amount,
+                color.color.blue * amount,
+                0.0,
+            ],
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// This transform followed by `outer`.
+    pub fn then(&self, outer: &ColorTransform) -> Self {
+        let mut result = Self::default();
+        for channel in 0..4 {
+            result.multiply[channel] = self.multiply[channel] * outer.multiply[channel];
+            result.offset[channel] =
+                self.offset[channel] * outer.multiply[channel] + outer.offset[channel];
+        }
+        result
+    }
+
+    pub fn lerp(&self, end: &ColorTransform, percent: f32) -> Self {
+        let mut result = *self;
+        for channel in 0..4 {
+            result.multiply[channel] =
+                util::lerp(self.multiply[channel], end.multiply[channel], percent);
+            result.offset[channel] = util::lerp(self.offset[channel], end.offset[channel], percent);
+        }
+        result
+    }
+
+    pub fn apply(&self, color: LinSrgba) -> LinSrgba {
+        let (red, green, blue, alpha) = color.into_components();
+        let channel = |index: usize, value: f32| {
+            util::clamp_0_1(value * self.multiply[index] + self.offset[index])
+        };
+        LinSrgba::new(
+            channel(0, red),
+            channel(1, green),
+            channel(2, blue),
+            channel(3, alpha),
+        )
+    }
+
+    pub fn apply_to_coloring(&self, coloring: &Coloring) -> Coloring {
+        match coloring {
+            Coloring::Color(color) => Coloring::Color(self.apply(*color)),
+            Coloring::Colorings(colorings) => Coloring::Colorings(
+                colorings
+                    .iter()
+                    .map(|coloring| self.apply_to_coloring(coloring))
+                    .collect(),
+            ),
+            Coloring::None => Coloring::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn color() -> impl Strategy<Value = Coloring> {
+        (0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0)
+            .prop_map(|(r, g, b, a)| Coloring::Color(LinSrgba::new(r, g, b, a)))
+    }
+
+    fn colorings() -> impl Strategy<Value = Coloring> {
+        prop::collection::vec(color(), 0..8).prop_map(Coloring::Colorings)
+    }
+
+    fn approx_eq(a: &Coloring, b: &Coloring) -> bool {
+        match (a, b) {
+            (Coloring::Color(a), Coloring::Color(b)) => {
+                let (a, b) = (a.into_components(), b.into_components());
+                (a.0 - b.0).abs() < 0.0001
+                    && (a.1 - b.1).abs() < 0.0001
+                    && (a.2 - b.2).abs() < 0.0001
+                    && (a.3 - b.3).abs() < 0.0001
+            }
+            (Coloring::Colorings(a), Coloring::Colorings(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| approx_eq(a, b))
+            }
+            (Coloring::None, Coloring::None) => true,
+            _ => false,
+        }
+    }
+
+    fn len(coloring: &Coloring) -> usize {
+        match coloring {
+            Coloring::Colorings(colorings) => colorings.len(),
+            _ => 1,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn it_blends_to_endpoints(a in color(), b in color()) {
+            prop_assert!(approx_eq(&a.blend(&b, 0.0), &a));
+            prop_assert!(approx_eq(&a.blend(&b, 1.0), &b));
+        }
+
+        #[test]
+        fn it_blends_with_itself(a in prop_oneof![color(), colorings()], weight in 0.0f32..1.0) {
+            prop_assert!(approx_eq(&a.blend(&a, weight), &a));
+        }
+
+        #[test]
+        fn it_broadcasts_colors(a in color(), b in colorings(), weight in 0.0f32..1.0) {
+            prop_assert_eq!(len(&a.blend(&b, weight)), len(&b));
+            prop_assert_eq!(len(&b.blend(&a, weight)), len(&b));
+        }
+
+        #[test]
+        fn it_truncates_mismatched_colorings(
+            a in colorings(),
+            b in colorings(),
+            weight in 0.0f32..1.0
+        ) {
+            prop_assert_eq!(len(&a.blend(&b, weight)), len(&a).min(len(&b)));
+        }
+
+        #[test]
+        fn it_ignores_none(a in prop_oneof![color(), colorings()], weight in 0.0f32..1.0) {
+            prop_assert!(approx_eq(&a.blend(&Coloring::None, weight), &a));
+            prop_assert!(approx_eq(&Coloring::None.blend(&a, weight), &a));
+        }
+
+        #[test]
+        fn it_composes_color_transforms(
+            a in prop_oneof![color(), colorings()],
+            brightness in 0.0f32..1.0,
+            contrast in 0.0f32..1.0
+        ) {
+            let inner = ColorTransform::brightness(brightness);
+            let outer = ColorTransform::contrast(contrast);
+            let stepwise = outer.apply_to_coloring(&inner.apply_to_coloring(&a));
+            prop_assert!(approx_eq(&inner.then(&outer).apply_to_coloring(&a), &stepwise));
+            prop_assert!(approx_eq(&ColorTransform::default().apply_to_coloring(&a), &a));
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Recycles the `Vec<Edge>`s built for transient edge lists. Editing tools rebuild a shape's
+//! edges on every pointer move, so once a pool has warmed up they stop allocating.
+
+use super::shapes::{Edge, Shape};
+
+// Buffers held at once. More than this are dropped when returned
+const MAX_POOLED_BUFFERS: usize = 8;
+// Buffers that grew past this many edges are dropped rather than kept around
+const MAX_POOLED_CAPACITY: usize = 1 << 16;
+
+/// What a pool has done since it was created, to check that it's actually being reused.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EdgePoolStats {
+    /// Buffers handed out that had to be allocated.
+    pub allocated: usize,
+    /// Buffers handed out that were recycled.
+    pub reused: usize,
+    /// Buffers given back and kept.
+    pub returned: usize,
+    /// Buffers given back and dropped, because the pool was full or they were too large.
+    pub discarded: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct EdgePool {
+    free: Vec<Vec<Edge>>,
+    stats: EdgePoolStats,
+}
+
+impl EdgePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty buffer, recycled if one is available.
+    pub fn take(&mut self) -> Vec<Edge> {
+        match self.free.pop() {
+            Some(edges) => {
+                self.stats.reused += 1;
+                edges
+            }
+            None => {
+                self.stats.allocated += 1;
+                vec![]
+            }
+        }
+    }
+
+    /// A buffer holding a copy of `edges`.
+    pub fn take_copy(&mut self, edges: &[Edge]) -> Vec<Edge> {
+        let mut copy = self.take();
+        copy.extend_from_slice(edges);
+        copy
+    }
+
+    /// A buffer holding `shape`'s flat edge list. See `Shape::edge_list`.
+    pub fn take_edge_list(&mut self, shape: &Shape, morph_percent: f32) -> Vec<Edge> {
+        let mut edges = self.take();
+        shape.edge_list_into(morph_percent, &mut edges);
+        edges
+    }
+
+    /// Clears `edges` and keeps it for the next `take`.
+    pub fn give_back(&mut self, mut edges: Vec<Edge>) {
+        if self.free.len() < MAX_POOLED_BUFFERS && edges.capacity() <= MAX_POOLED_CAPACITY {
+            edges.clear();
+            self.free.push(edges);
+            self.stats.returned += 1;
+        } else {
+            self.stats.discarded += 1;
+        }
+    }
+
+    pub fn stats(&self) -> EdgePoolStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_geometry::vector::Vector2F;
+
+    #[test]
+    fn it_recycles_buffers() {
+        let mut pool = EdgePool::new();
+        let edges = [
+            Edge::Move(Vector2F::zero()),
+            Edge::Line(Vector2F::splat(1.0)),
+        ];
+        let first = pool.take_copy(&edges);
+        let capacity = first.capacity();
+        pool.give_back(first);
+        let second = pool.take();
+        assert!(second.is_empty());
+        assert_eq!(second.capacity(), capacity);
+        assert_eq!(
+            pool.stats(),
+            EdgePoolStats {
+                allocated: 1,
+                reused: 1,
+                returned: 1,
+                discarded: 0,
+            }
+        );
+    }
+}
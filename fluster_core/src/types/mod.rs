@@ -1,3 +1,4 @@
 pub mod basic;
 pub mod coloring;
+pub mod edge_pool;
 pub mod shapes;
@@ -1,19 +1,67 @@
 use super::{
-    basic::{transform_des, transform_ser, Vector2FDef},
+    basic::{transform_des, transform_ser, LibraryId, Vector2FDef},
     coloring::Coloring,
 };
 use crate::util;
 use palette::LinSrgba;
 use pathfinder_canvas::Path2D;
 use pathfinder_content::{
-    outline::ArcDirection,
-    stroke::{LineCap, LineJoin, StrokeStyle},
+    outline::{ArcDirection, ContourIterFlags},
+    stroke::{LineCap, LineJoin, OutlineStrokeToFill, StrokeStyle},
 };
 use pathfinder_geometry::transform2d::Transform2F;
 use pathfinder_geometry::{rect::RectF, vector::Vector2F};
 use reduce::Reduce;
 use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
+use std::mem;
+
+/// Points sampled around procedural shapes at full quality. See `RenderQuality`.
+pub const DEFAULT_PROCEDURAL_STEPS: usize = 120;
+// Fewer points than this can't make a closed curve out of quadratics
+const MIN_PROCEDURAL_STEPS: usize = 6;
+
+// Samples taken along each edge when looking for the closest point to split at
+const SPLIT_SEARCH_STEPS: usize = 32;
+// Rounds of ternary search to refine the closest sample
+const SPLIT_REFINE_STEPS: usize = 16;
+
+/// The kinds of edge a drawn edge can be converted between.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EdgeKind {
+    Line,
+    Quadratic,
+    Bezier,
+    ArcTo,
+}
+
+/// How a bezier control handle relates to the handle on the other side of its anchor point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HandleType {
+    /// The handles move independently.
+    Corner,
+    /// The handles stay in a straight line through the anchor, so the curve has no kink, but keep
+    /// their own lengths.
+    Smooth,
+    /// The handles mirror each other exactly.
+    Symmetric,
+}
+
+// Tolerance, in radians, for handles to count as lined up
+const SMOOTH_HANDLE_TOLERANCE: f32 = 0.01;
+// Relative tolerance for handles to count as the same length
+const SYMMETRIC_HANDLE_TOLERANCE: f32 = 0.01;
+
+fn lerp(from: Vector2F, to: Vector2F, t: f32) -> Vector2F {
+    from + (to - from) * t
+}
+
+fn arc_point(center: Vector2F, axes: Vector2F, angle: f32) -> Vector2F {
+    center + Vector2F::new(axes.x() * angle.cos(), axes.y() * angle.sin())
+}
+
+// Number of line segments used to approximate each curve when ray casting
+const RAY_CURVE_STEPS: usize = 16;
 
 #[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Edge {
@@ -162,13 +210,25 @@ impl Edge {
     }
 
     pub fn new_superellipse(size: Vector2F, exponent: f32, transform: Transform2F) -> Vec<Self> {
+        Self::new_superellipse_with_steps(size, exponent, DEFAULT_PROCEDURAL_STEPS, transform)
+    }
+
+    /// A superellipse sampled at `steps` points, rounded up to a multiple of three. Pass
+    /// `RenderQuality::procedural_steps` to match the engine's quality setting.
+    pub fn new_superellipse_with_steps(
+        size: Vector2F,
+        exponent: f32,
+        steps: usize,
+        transform: Transform2F,
+    ) -> Vec<Self> {
         let size = size / 2.0;
         let transform = Transform2F::from_translation(size) * transform;
-        const MAX_STEP: usize = 120;
-        const STEP_SIZE: f32 = 2.0 * PI / MAX_STEP as f32;
-        let key_points = (0..MAX_STEP)
+        // Each quadratic spans three samples
+        let max_step = ((steps.max(MIN_PROCEDURAL_STEPS) + 2) / 3) * 3;
+        let step_size = 2.0 * PI / max_step as f32;
+        let key_points = (0..max_step)
             .map(move |step| {
-                let arc_step = (step as f32) * STEP_SIZE;
+                let arc_step = (step as f32) * step_size;
                 let x =
                     size.x() * arc_step.cos().signum() * arc_step.cos().abs().powf(2.0 / exponent);
                 let y =
@@ -180,7 +240,7 @@ impl Edge {
         fn compute_control_point(p_0: Vector2F, p_mid: Vector2F, p_1: Vector2F) -> Vector2F {
             p_mid * 2.0 - p_0 * 0.5 - p_1 * 0.5
         }
-        for index in (2..MAX_STEP).step_by(3) {
+        for index in (2..max_step).step_by(3) {
             edges.push(Self::Quadratic {
                 control: transform
                     * compute_control_point(
@@ -194,8 +254,8 @@ impl Edge {
         edges.push(Self::Quadratic {
             control: transform
                 * compute_control_point(
-                    key_points[MAX_STEP - 2],
-                    key_points[MAX_STEP - 1],
+                    key_points[max_step - 2],
+                    key_points[max_step - 1],
                     key_points[0],
                 ),
             to: transform * key_points[0],
@@ -209,8 +269,14 @@ impl Edge {
             Self::Move(v) => *v,
             Self::Line(v) => *v,
             Self::Quadratic { to, .. } | Self::Bezier { to, .. } | Self::ArcTo { to, .. } => *to,
-            Self::Arc { .. } => Vector2F::zero(), // TODO: compute
-            Self::Close { .. } => Vector2F::zero(), //TODO: Uh.... What should this endpoint be
+            Self::Arc {
+                center,
+                end_angle,
+                axes,
+                ..
+            } => arc_point(*center, *axes, *end_angle),
+            // Close has no point of its own, the contour ends back at its start. See `Contour`.
+            Self::Close { .. } => Vector2F::zero(),
         }
     }
 
@@ -415,6 +481,629 @@ impl Edge {
             Self::Close => Self::match_points_rect(vec![].into_iter(), *rect, *transform),
         }
     }
+
+    /// Intersects a ray with every edge in `edges` after applying `transform`. Returns
+    /// (edge index, distance along the ray, hit point) for every hit, closest first. Curves are
+    /// flattened, so hits on them are approximate.
+    pub fn query_ray(
+        edges: &[Edge],
+        origin: &Vector2F,
+        direction: &Vector2F,
+        transform: &Transform2F,
+    ) -> Vec<(usize, f32, Vector2F)> {
+        let mut hits = vec![];
+        let mut current = Vector2F::zero();
+        let mut contour_start = Vector2F::zero();
+        for (index, edge) in edges.iter().enumerate() {
+            let segment_edge = match edge {
+                Self::Move(to) => {
+                    current = *to;
+                    contour_start = *to;
+                    continue;
+                }
+                Self::Close => Self::Line(contour_start),
+                edge => *edge,
+            };
+            // Let pathfinder do the work of turning arcs into curves
+            let mut outline =
+                Self::edges_to_path(vec![Self::Move(current), segment_edge].into_iter())
+                    .into_outline();
+            outline.transform(transform);
+            for contour in outline.contours() {
+                for segment in contour.iter(ContourIterFlags::empty()) {
+                    let points = if segment.is_line() {
+                        vec![segment.baseline.from(), segment.baseline.to()]
+                    } else {
+                        let segment = segment.to_cubic();
+                        let cubic = segment.as_cubic_segment();
+                        (0..=RAY_CURVE_STEPS)
+                            .map(|step| cubic.sample(step as f32 / RAY_CURVE_STEPS as f32))
+                            .collect()
+                    };
+                    hits.extend(points.windows(2).filter_map(|line| {
+                        util::ray_segment_intersect(*origin, *direction, line[0], line[1])
+                            .map(|(distance, point)| (index, distance, point))
+                    }));
+                }
+            }
+            current = match edge {
+                Self::Close => contour_start,
+                Self::Arc { .. } => outline
+                    .contours()
+                    .last()
+                    .and_then(|contour| contour.last_position())
+                    .map(|end| transform.inverse() * end)
+                    .unwrap_or(current),
+                edge => edge.end_point(),
+            };
+        }
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        hits
+    }
+
+    /// Whether `point` is inside the area `edges` would fill. See `Contour::contains_point`.
+    pub fn contains_point(edges: &[Edge], point: Vector2F) -> bool {
+        Contour::contains_point(&Contour::from_edges(edges), point)
+    }
+
+    // Winding number of the closed outline `edges` draws around `point`, with curves flattened
+    fn winding_number(edges: impl Iterator<Item = Edge>, point: Vector2F) -> i32 {
+        let outline = Self::edges_to_path(edges).into_outline();
+        let mut winding = 0;
+        for contour in outline.contours() {
+            let mut points = vec![];
+            for segment in contour.iter(ContourIterFlags::empty()) {
+                if segment.is_line() {
+                    points.push(segment.baseline.from());
+                    points.push(segment.baseline.to());
+                } else {
+                    let segment = segment.to_cubic();
+                    let cubic = segment.as_cubic_segment();
+                    points.extend(
+                        (0..=RAY_CURVE_STEPS)
+                            .map(|step| cubic.sample(step as f32 / RAY_CURVE_STEPS as f32)),
+                    );
+                }
+            }
+            if let Some(first) = points.first().copied() {
+                points.push(first);
+            }
+            winding += points
+                .windows(2)
+                .map(|line| winding_crossing(line[0], line[1], point))
+                .sum::<i32>();
+        }
+        winding
+    }
+
+    /// The point `update_point` would move for the same index.
+    pub fn point(&self, index: usize) -> Option<Vector2F> {
+        match (self, index) {
+            (Self::Move(to), _) | (Self::Line(to), _) => Some(*to),
+            (Self::Quadratic { control, .. }, 0) | (Self::ArcTo { control, .. }, 0) => {
+                Some(*control)
+            }
+            (Self::Quadratic { to, .. }, _) | (Self::ArcTo { to, .. }, _) => Some(*to),
+            (Self::Bezier { control_1, .. }, 0) => Some(*control_1),
+            (Self::Bezier { control_2, .. }, 1) => Some(*control_2),
+            (Self::Bezier { to, .. }, _) => Some(*to),
+            (Self::Arc { center, .. }, _) => Some(*center),
+            (Self::Close, _) => None,
+        }
+    }
+
+    /// Whether the point at `index` is a control point rather than one the path passes through.
+    pub fn is_control_point(&self, index: usize) -> bool {
+        match self {
+            Self::Quadratic { .. } | Self::ArcTo { .. } => index == 0,
+            Self::Bezier { .. } => index < 2,
+            _ => false,
+        }
+    }
+
+    // The first and last drawn edges of the contour containing `index`
+    fn contour_range(edges: &[Edge], index: usize) -> (usize, usize) {
+        let first = edges[..=index]
+            .iter()
+            .rposition(|edge| match edge {
+                Self::Move(..) => true,
+                _ => false,
+            })
+            .map_or(0, |index| index + 1);
+        let last = edges[index..]
+            .iter()
+            .position(|edge| match edge {
+                Self::Move(..) | Self::Close => true,
+                _ => false,
+            })
+            .map_or(edges.len(), |offset| index + offset)
+            .max(first + 1)
+            - 1;
+        (first, last)
+    }
+
+    /// For a bezier control point, the anchor it hangs off and the bezier control on the other
+    /// side of that anchor, if there is one. Closed contours wrap around.
+    pub fn handle_pair(
+        edges: &[Edge],
+        edge_id: usize,
+        vertex_id: usize,
+    ) -> Option<(Vector2F, Option<(usize, usize)>)> {
+        let starts = Self::segment_starts(edges);
+        let is_bezier = |index: usize| match edges.get(index) {
+            Some(Self::Bezier { .. }) => true,
+            _ => false,
+        };
+        if !is_bezier(edge_id) || vertex_id > 1 {
+            return None;
+        }
+        let (first, last) = Self::contour_range(edges, edge_id);
+        let (contour_start, closed) = {
+            let contour_start = starts[first].1;
+            let ends_at_start =
+                (edges[last].end_point() - contour_start).square_length() <= std::f32::EPSILON;
+            (contour_start, ends_at_start)
+        };
+        if vertex_id == 0 {
+            // Outgoing handle, paired with the incoming handle of the previous edge
+            let anchor = starts[edge_id].0;
+            let previous = if edge_id > first {
+                Some(edge_id - 1)
+            } else if closed && (anchor - contour_start).square_length() <= std::f32::EPSILON {
+                Some(last)
+            } else {
+                None
+            };
+            Some((
+                anchor,
+                previous
+                    .filter(|index| is_bezier(*index))
+                    .map(|index| (index, 1)),
+            ))
+        } else {
+            // Incoming handle, paired with the outgoing handle of the next edge
+            let anchor = edges[edge_id].end_point();
+            let next = if edge_id < last {
+                Some(edge_id + 1)
+            } else if closed {
+                Some(first)
+            } else {
+                None
+            };
+            Some((
+                anchor,
+                next.filter(|index| is_bezier(*index))
+                    .map(|index| (index, 0)),
+            ))
+        }
+    }
+
+    /// Works out how a bezier control point relates to its partner from their current
+    /// positions. `None` if the point isn't a bezier control or has no partner.
+    pub fn handle_type(edges: &[Edge], edge_id: usize, vertex_id: usize) -> Option<HandleType> {
+        let (anchor, (opposite_edge, opposite_vertex)) =
+            match Self::handle_pair(edges, edge_id, vertex_id) {
+                Some((anchor, Some(opposite))) => (anchor, opposite),
+                _ => return None,
+            };
+        let handle = edges[edge_id].point(vertex_id)? - anchor;
+        let opposite = edges[opposite_edge].point(opposite_vertex)? - anchor;
+        let (handle_length, opposite_length) = (handle.length(), opposite.length());
+        if handle_length <= std::f32::EPSILON || opposite_length <= std::f32::EPSILON {
+            return Some(HandleType::Corner);
+        }
+        let cos = handle.dot(opposite) / (handle_length * opposite_length);
+        if cos > -(SMOOTH_HANDLE_TOLERANCE.cos()) {
+            Some(HandleType::Corner)
+        } else if (handle_length - opposite_length).abs()
+            <= SYMMETRIC_HANDLE_TOLERANCE * handle_length.max(opposite_length)
+        {
+            Some(HandleType::Symmetric)
+        } else {
+            Some(HandleType::Smooth)
+        }
+    }
+
+    /// Moves a point like `update_point`, keeping the curve's tangents intact. Moving a bezier
+    /// control drags its partner across the anchor according to `handle_type`. Moving a point
+    /// the path passes through carries the bezier controls hanging off it along with it.
+    pub fn move_point(
+        edges: &mut [Edge],
+        edge_id: usize,
+        vertex_id: usize,
+        position: Vector2F,
+        handle_type: HandleType,
+    ) {
+        let current = match edges.get(edge_id).and_then(|edge| edge.point(vertex_id)) {
+            Some(current) => current,
+            None => return,
+        };
+        if edges[edge_id].is_control_point(vertex_id) {
+            let pair = Self::handle_pair(edges, edge_id, vertex_id);
+            edges[edge_id].update_point(vertex_id, position);
+            if let Some((anchor, Some((opposite_edge, opposite_vertex)))) = pair {
+                let handle = position - anchor;
+                let length = match handle_type {
+                    HandleType::Corner => return,
+                    HandleType::Smooth => edges[opposite_edge]
+                        .point(opposite_vertex)
+                        .map_or(0.0, |opposite| (opposite - anchor).length()),
+                    HandleType::Symmetric => handle.length(),
+                };
+                if handle.length() > std::f32::EPSILON {
+                    let mirrored = anchor - handle * (length / handle.length());
+                    edges[opposite_edge].update_point(opposite_vertex, mirrored);
+                }
+            }
+        } else {
+            let delta = position - current;
+            edges[edge_id].update_point(vertex_id, position);
+            // The incoming control of this edge, and the outgoing control of the next
+            let attached = [
+                Self::handle_pair(edges, edge_id, 1).map(|_| (edge_id, 1)),
+                Self::handle_pair(edges, edge_id, 1)
+                    .and_then(|(_, opposite)| opposite)
+                    .or_else(|| match edges.get(edge_id + 1) {
+                        Some(Self::Bezier { .. }) => Some((edge_id + 1, 0)),
+                        _ => None,
+                    }),
+            ];
+            for (attached_edge, attached_vertex) in attached.iter().flatten() {
+                if let Some(control) = edges[*attached_edge].point(*attached_vertex) {
+                    edges[*attached_edge].update_point(*attached_vertex, control + delta);
+                }
+            }
+        }
+    }
+
+    // (start point, start of the contour) for every edge
+    fn segment_starts(edges: &[Edge]) -> Vec<(Vector2F, Vector2F)> {
+        let mut starts = Vec::with_capacity(edges.len());
+        let mut current = Vector2F::zero();
+        let mut contour_start = Vector2F::zero();
+        for edge in edges {
+            starts.push((current, contour_start));
+            match edge {
+                Self::Move(to) => {
+                    current = *to;
+                    contour_start = *to;
+                }
+                Self::Close => current = contour_start,
+                edge => current = edge.end_point(),
+            }
+        }
+        starts
+    }
+
+    fn point_at(&self, start: Vector2F, t: f32) -> Option<Vector2F> {
+        match self {
+            Self::Line(to) => Some(lerp(start, *to, t)),
+            Self::Quadratic { control, to } => {
+                Some(lerp(lerp(start, *control, t), lerp(*control, *to, t), t))
+            }
+            Self::Bezier {
+                control_1,
+                control_2,
+                to,
+            } => {
+                let a = lerp(start, *control_1, t);
+                let b = lerp(*control_1, *control_2, t);
+                let c = lerp(*control_2, *to, t);
+                Some(lerp(lerp(a, b, t), lerp(b, c, t), t))
+            }
+            Self::Arc {
+                center,
+                start_angle,
+                end_angle,
+                axes,
+            } => Some(arc_point(
+                *center,
+                *axes,
+                util::lerp(*start_angle, *end_angle, t),
+            )),
+            _ => None,
+        }
+    }
+
+    // Length of a line, curve or arc, measured along RAY_CURVE_STEPS chords. Edges without a
+    // parametric form are measured as a straight line to their end.
+    fn length(&self, start: Vector2F) -> f32 {
+        let mut previous = match self.point_at(start, 0.0) {
+            Some(point) => point,
+            None => return (self.end_point() - start).length(),
+        };
+        let mut length = 0.0;
+        for step in 1..=RAY_CURVE_STEPS {
+            if let Some(point) = self.point_at(start, step as f32 / RAY_CURVE_STEPS as f32) {
+                length += (point - previous).length();
+                previous = point;
+            }
+        }
+        length
+    }
+
+    // The t that is `distance` along the edge, measured like `length`
+    fn parameter_at(&self, start: Vector2F, distance: f32) -> f32 {
+        let mut previous = match self.point_at(start, 0.0) {
+            Some(point) => point,
+            None => {
+                let length = (self.end_point() - start).length();
+                return if length > 0.0 { distance / length } else { 0.0 };
+            }
+        };
+        let mut travelled = 0.0;
+        for step in 1..=RAY_CURVE_STEPS {
+            let t = step as f32 / RAY_CURVE_STEPS as f32;
+            let point = self.point_at(start, t).unwrap_or(previous);
+            let chord = (point - previous).length();
+            if travelled + chord >= distance && chord > 0.0 {
+                return t - (1.0 - (distance - travelled) / chord) / RAY_CURVE_STEPS as f32;
+            }
+            travelled += chord;
+            previous = point;
+        }
+        1.0
+    }
+
+    // The part of the edge between t0 and t1, and the point it starts from. Edges that can't be
+    // split come back as lines, which is exact for everything but `ArcTo`.
+    // TODO: split ArcTo along its arc
+    fn sub_edge(&self, start: Vector2F, t0: f32, t1: f32) -> (Vector2F, Edge) {
+        if t0 <= 0.0 && t1 >= 1.0 {
+            return (start, *self);
+        }
+        let first = if t1 < 1.0 {
+            self.split_at(start, t1).map(|(first, _)| first)
+        } else {
+            Ok(*self)
+        };
+        match first {
+            Ok(first) if t0 > 0.0 => {
+                let from = self.point_at(start, t0).unwrap_or(start);
+                match first.split_at(start, t0 / t1.min(1.0)) {
+                    Ok((_, second)) => (from, second),
+                    Err(_) => (from, Edge::Line(first.end_point())),
+                }
+            }
+            Ok(first) => (start, first),
+            Err(_) => {
+                let end = self.end_point();
+                (
+                    lerp(start, end, t0.max(0.0)),
+                    Edge::Line(lerp(start, end, t1.min(1.0))),
+                )
+            }
+        }
+    }
+
+    // Splits a line or curve at t with de Casteljau's algorithm, so both halves trace the
+    // original exactly
+    fn split_at(&self, start: Vector2F, t: f32) -> Result<(Edge, Edge), String> {
+        match self {
+            Self::Line(to) => Ok((Self::Line(lerp(start, *to, t)), Self::Line(*to))),
+            Self::Quadratic { control, to } => {
+                let first_control = lerp(start, *control, t);
+                let second_control = lerp(*control, *to, t);
+                let split = lerp(first_control, second_control, t);
+                Ok((
+                    Self::Quadratic {
+                        control: first_control,
+                        to: split,
+                    },
+                    Self::Quadratic {
+                        control: second_control,
+                        to: *to,
+                    },
+                ))
+            }
+            Self::Bezier {
+                control_1,
+                control_2,
+                to,
+            } => {
+                let a = lerp(start, *control_1, t);
+                let b = lerp(*control_1, *control_2, t);
+                let c = lerp(*control_2, *to, t);
+                let ab = lerp(a, b, t);
+                let bc = lerp(b, c, t);
+                let split = lerp(ab, bc, t);
+                Ok((
+                    Self::Bezier {
+                        control_1: a,
+                        control_2: ab,
+                        to: split,
+                    },
+                    Self::Bezier {
+                        control_1: bc,
+                        control_2: c,
+                        to: *to,
+                    },
+                ))
+            }
+            Self::Arc {
+                center,
+                start_angle,
+                end_angle,
+                axes,
+            } => {
+                let split = util::lerp(*start_angle, *end_angle, t);
+                Ok((
+                    Self::Arc {
+                        center: *center,
+                        start_angle: *start_angle,
+                        end_angle: split,
+                        axes: *axes,
+                    },
+                    Self::Arc {
+                        center: *center,
+                        start_angle: split,
+                        end_angle: *end_angle,
+                        axes: *axes,
+                    },
+                ))
+            }
+            edge => Err(format!("Cannot split {:?}", edge)),
+        }
+    }
+
+    /// The edge that passes closest to `point`, and how far along it the closest point is.
+    /// Only lines, curves, arcs and closes are considered.
+    pub fn closest_edge(edges: &[Edge], point: Vector2F) -> Option<(usize, f32)> {
+        let distance = |edge: &Edge, start: Vector2F, t: f32| {
+            edge.point_at(start, t)
+                .map(|on_edge| (on_edge - point).square_length())
+                .unwrap_or(std::f32::MAX)
+        };
+        let mut closest: Option<(usize, f32, f32)> = None;
+        for (index, (edge, (start, contour_start))) in edges
+            .iter()
+            .zip(Self::segment_starts(edges).into_iter())
+            .enumerate()
+        {
+            let edge = match edge {
+                Self::Close => Self::Line(contour_start),
+                Self::Line(..) | Self::Quadratic { .. } | Self::Bezier { .. } => *edge,
+                _ => continue,
+            };
+            let step = 1.0 / SPLIT_SEARCH_STEPS as f32;
+            let (mut t, mut best) = (0..=SPLIT_SEARCH_STEPS)
+                .map(|sample| {
+                    let t = sample as f32 * step;
+                    (t, distance(&edge, start, t))
+                })
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+                .unwrap();
+            let (mut low, mut high) = ((t - step).max(0.0), (t + step).min(1.0));
+            for _ in 0..SPLIT_REFINE_STEPS {
+                let third = (high - low) / 3.0;
+                if distance(&edge, start, low + third) < distance(&edge, start, high - third) {
+                    high -= third;
+                } else {
+                    low += third;
+                }
+            }
+            let refined = (low + high) * 0.5;
+            if distance(&edge, start, refined) < best {
+                t = refined;
+                best = distance(&edge, start, refined);
+            }
+            if closest.map_or(true, |(_, _, closest)| best < closest) {
+                closest = Some((index, t, best));
+            }
+        }
+        closest.map(|(index, t, _)| (index, t))
+    }
+
+    /// Adds a point to the edge closest to `point`, splitting it so the path keeps its shape.
+    /// Returns the index of the edge that now ends at the new point.
+    pub fn insert_point(edges: &mut Vec<Edge>, point: Vector2F) -> Result<usize, String> {
+        let (index, t) = Self::closest_edge(edges, point)
+            .ok_or_else(|| "There are no edges to add a point to".to_owned())?;
+        let (start, contour_start) = Self::segment_starts(edges)[index];
+        if let Self::Close = edges[index] {
+            // Closing stays last, so the new point goes on a line just before it
+            let (first, _) = Self::Line(contour_start).split_at(start, t)?;
+            edges.insert(index, first);
+        } else {
+            let (first, second) = edges[index].split_at(start, t)?;
+            edges[index] = second;
+            edges.insert(index, first);
+        }
+        Ok(index)
+    }
+
+    /// Deletes the point `edges[index]` ends at. The following edge is rejoined to the previous
+    /// point, keeping its own control points.
+    pub fn remove_point(edges: &mut Vec<Edge>, index: usize) -> Result<(), String> {
+        match edges.get(index) {
+            None => Err(format!("There is no edge {}", index)),
+            Some(Self::Close) => Err("Close does not have a point to delete".to_owned()),
+            Some(Self::Move(..)) => {
+                // The next point becomes the start of the contour
+                match edges.get(index + 1) {
+                    Some(Self::Move(..)) | Some(Self::Close) | None => {
+                        edges.remove(index);
+                    }
+                    Some(next) => {
+                        edges[index] = Self::Move(next.end_point());
+                        edges.remove(index + 1);
+                    }
+                }
+                Ok(())
+            }
+            Some(..) => {
+                edges.remove(index);
+                Ok(())
+            }
+        }
+    }
+
+    /// Converts `edges[index]` to another kind of edge with the same end points, keeping as much
+    /// of its curve as the new kind can represent.
+    pub fn convert_edge(edges: &mut Vec<Edge>, index: usize, kind: EdgeKind) -> Result<(), String> {
+        let (start, _) = *Self::segment_starts(edges)
+            .get(index)
+            .ok_or_else(|| format!("There is no edge {}", index))?;
+        let edge = edges[index];
+        // The single control point that best describes the edge's curve
+        let (to, control) = match edge {
+            Self::Line(to) => (to, lerp(start, to, 0.5)),
+            Self::Quadratic { control, to } | Self::ArcTo { control, to, .. } => (to, control),
+            Self::Bezier {
+                control_1,
+                control_2,
+                to,
+            } => (to, (control_1 * 3.0 + control_2 * 3.0 - start - to) * 0.25),
+            edge => return Err(format!("Cannot convert {:?}", edge)),
+        };
+        let converted = match (kind, edge) {
+            (EdgeKind::Line, _) => Self::Line(to),
+            (EdgeKind::Quadratic, Self::Quadratic { .. })
+            | (EdgeKind::Bezier, Self::Bezier { .. })
+            | (EdgeKind::ArcTo, Self::ArcTo { .. }) => edge,
+            (EdgeKind::Quadratic, _) => Self::Quadratic { control, to },
+            // Degree elevation, which is exact for lines and quadratics
+            (EdgeKind::Bezier, _) => Self::Bezier {
+                control_1: lerp(start, control, 2.0 / 3.0),
+                control_2: lerp(to, control, 2.0 / 3.0),
+                to,
+            },
+            (EdgeKind::ArcTo, _) => {
+                let chord = to - start;
+                let offset = control - start;
+                // An arc needs a corner to round, so bow straight edges out to one side
+                let control = if (chord.x() * offset.y() - chord.y() * offset.x()).abs()
+                    <= std::f32::EPSILON * chord.square_length().max(1.0)
+                {
+                    lerp(start, to, 0.5) + Vector2F::new(-chord.y(), chord.x()) * 0.5
+                } else {
+                    control
+                };
+                Self::ArcTo {
+                    control,
+                    to,
+                    radius: (control - start).length().min((to - control).length()) * 0.5,
+                }
+            }
+        };
+        edges[index] = converted;
+        Ok(())
+    }
+}
+
+// +1 if the segment crosses the horizontal line through `point` upwards to the right of it, -1
+// if it crosses downwards, otherwise 0
+fn winding_crossing(from: Vector2F, to: Vector2F, point: Vector2F) -> i32 {
+    let side =
+        (to.x() - from.x()) * (point.y() - from.y()) - (point.x() - from.x()) * (to.y() - from.y());
+    if from.y() <= point.y() {
+        if to.y() > point.y() && side > 0.0 {
+            return 1;
+        }
+    } else if to.y() <= point.y() && side < 0.0 {
+        return -1;
+    }
+    0
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -507,37 +1196,478 @@ impl MorphEdge {
             Self::Close => Edge::Close,
         }
     }
+
+    /// Pairs an edge of a morph's start pose with the matching edge of its end pose. Both have to
+    /// be the same kind of edge. Arcs don't morph, convert them to curves first.
+    pub fn from_edges(start: &Edge, end: &Edge) -> Result<Self, String> {
+        match (start, end) {
+            (Edge::Move(start), Edge::Move(end)) => Ok(Self::Move(*start, *end)),
+            (Edge::Line(start), Edge::Line(end)) => Ok(Self::Line(*start, *end)),
+            (
+                Edge::Quadratic {
+                    control: control_start,
+                    to: to_start,
+                },
+                Edge::Quadratic {
+                    control: control_end,
+                    to: to_end,
+                },
+            ) => Ok(Self::Quadratic {
+                control_start: *control_start,
+                to_start: *to_start,
+                control_end: *control_end,
+                to_end: *to_end,
+            }),
+            (
+                Edge::Bezier {
+                    control_1: control_1_start,
+                    control_2: control_2_start,
+                    to: to_start,
+                },
+                Edge::Bezier {
+                    control_1: control_1_end,
+                    control_2: control_2_end,
+                    to: to_end,
+                },
+            ) => Ok(Self::Bezier {
+                control_1_start: *control_1_start,
+                control_2_start: *control_2_start,
+                to_start: *to_start,
+                control_1_end: *control_1_end,
+                control_2_end: *control_2_end,
+                to_end: *to_end,
+            }),
+            (
+                Edge::ArcTo {
+                    control: control_start,
+                    to: to_start,
+                    radius: radius_start,
+                },
+                Edge::ArcTo {
+                    control: control_end,
+                    to: to_end,
+                    radius: radius_end,
+                },
+            ) => Ok(Self::ArcTo {
+                control_start: *control_start,
+                to_start: *to_start,
+                radius_start: *radius_start,
+                control_end: *control_end,
+                to_end: *to_end,
+                radius_end: *radius_end,
+            }),
+            (Edge::Close, Edge::Close) => Ok(Self::Close),
+            (Edge::Arc { .. }, _) | (_, Edge::Arc { .. }) => {
+                Err("Arcs can't morph, convert them to curves first".to_owned())
+            }
+            (start, end) => Err(format!(
+                "Can't morph between {:?} and {:?}, the edges don't match",
+                start, end
+            )),
+        }
+    }
+}
+
+/// One subpath of a shape: a start point and the edges drawn from it, none of which are a `Move`
+/// or a `Close`. Shapes made of several contours can have holes, by winding the holes the
+/// opposite way to the outline around them.
+///
+/// Editing still works on flat edge lists, where each `Move` starts a contour and a `Close` ends
+/// one. Edges after a `Close` without a `Move` start from the closed contour's start point, and a
+/// `Line` before any `Move` starts the first contour where it ends.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Contour {
+    pub start: Vector2F,
+    pub edges: Vec<Edge>,
+    /// Closed contours draw a line back to `start`. Fills are always closed when drawn, this only
+    /// changes how strokes are joined.
+    pub closed: bool,
+}
+
+impl Contour {
+    pub fn new(start: Vector2F, edges: Vec<Edge>, closed: bool) -> Self {
+        Self {
+            start,
+            edges,
+            closed,
+        }
+    }
+
+    pub fn from_edges(edges: &[Edge]) -> Vec<Contour> {
+        let mut contours = vec![];
+        let mut current: Option<Contour> = None;
+        // None until the first contour starts
+        let mut contour_start = None;
+        for edge in edges {
+            match edge {
+                Edge::Move(to) => {
+                    contours.extend(current.replace(Contour::new(*to, vec![], false)));
+                    contour_start = Some(*to);
+                }
+                // Like a `Path2D`, a line with nothing before it starts the path where it ends
+                Edge::Line(to) if contour_start.is_none() => {
+                    current = Some(Contour::new(*to, vec![], false));
+                    contour_start = Some(*to);
+                }
+                Edge::Close => {
+                    let start = contour_start.unwrap_or_else(Vector2F::zero);
+                    let mut closed = current
+                        .take()
+                        .unwrap_or_else(|| Contour::new(start, vec![], false));
+                    closed.closed = true;
+                    contours.push(closed);
+                }
+                edge => {
+                    let start = contour_start.unwrap_or_else(Vector2F::zero);
+                    current
+                        .get_or_insert_with(|| Contour::new(start, vec![], false))
+                        .edges
+                        .push(*edge)
+                }
+            }
+        }
+        contours.extend(current);
+        contours
+    }
+
+    pub fn to_edges(contours: &[Contour]) -> Vec<Edge> {
+        let mut edges = vec![];
+        Self::append_edges(contours, &mut edges);
+        edges
+    }
+
+    /// Like `to_edges`, but appends to `edges` so its capacity can be reused.
+    pub fn append_edges(contours: &[Contour], edges: &mut Vec<Edge>) {
+        edges.reserve(Self::edge_count(contours));
+        for contour in contours {
+            edges.push(Edge::Move(contour.start));
+            edges.extend_from_slice(&contour.edges);
+            if contour.closed {
+                edges.push(Edge::Close);
+            }
+        }
+    }
+
+    /// The length of the flat edge list `to_edges` would return.
+    pub fn edge_count(contours: &[Contour]) -> usize {
+        contours
+            .iter()
+            .map(|contour| 1 + contour.edges.len() + contour.closed as usize)
+            .sum()
+    }
+
+    pub fn to_path(contours: &[Contour]) -> Path2D {
+        Edge::edges_to_path(Self::to_edges(contours).into_iter())
+    }
+
+    fn compute_bounding(contours: &[Contour], transform: &Transform2F) -> RectF {
+        Edge::compute_bounding(Self::to_edges(contours).into_iter(), transform)
+    }
+
+    // Strokes are outlined before transforming, so they scale and skew with the shape like they
+    // do when drawn
+    fn compute_stroked_bounding(
+        contours: &[Contour],
+        stroke_style: &StrokeStyle,
+        transform: &Transform2F,
+    ) -> RectF {
+        let outline = Self::to_path(contours).into_outline();
+        let mut stroke_to_fill = OutlineStrokeToFill::new(&outline, *stroke_style);
+        stroke_to_fill.offset();
+        let mut stroked = stroke_to_fill.into_outline();
+        stroked.transform(transform);
+        stroked.bounds()
+    }
+
+    /// How many times this contour winds around `point`, treating it as closed. Counter
+    /// clockwise and clockwise contours have opposite signs. Curves are flattened.
+    pub fn winding_number(&self, point: Vector2F) -> i32 {
+        Edge::winding_number(
+            Self::to_edges(std::slice::from_ref(self)).into_iter(),
+            point,
+        )
+    }
+
+    /// Whether `point` is inside the area `contours` would fill, using the same non-zero winding
+    /// rule as fills, so it is outside holes.
+    pub fn contains_point(contours: &[Contour], point: Vector2F) -> bool {
+        contours
+            .iter()
+            .map(|contour| contour.winding_number(point))
+            .sum::<i32>()
+            != 0
+    }
+
+    /// The part of the contour between `start` and `end`, as fractions of its length. The line
+    /// back to the start of a closed contour counts towards its length, and a closed contour
+    /// comes out open unless it's left whole. None if nothing is left.
+    pub fn trim(&self, start: f32, end: f32) -> Option<Contour> {
+        if start <= 0.0 && end >= 1.0 {
+            return Some(self.clone());
+        }
+        let mut edges = self.edges.clone();
+        if self.closed {
+            edges.push(Edge::Line(self.start));
+        }
+        let mut from = self.start;
+        let segments = edges
+            .into_iter()
+            .map(|edge| {
+                let segment = (from, edge, edge.length(from));
+                from = edge.end_point();
+                segment
+            })
+            .collect::<Vec<(Vector2F, Edge, f32)>>();
+        let total = segments.iter().map(|(_, _, length)| length).sum::<f32>();
+        let (start, end) = (start.max(0.0) * total, end.min(1.0) * total);
+        if end <= start {
+            return None;
+        }
+        let mut trimmed: Option<Contour> = None;
+        let mut travelled = 0.0;
+        for (from, edge, length) in segments {
+            let (edge_start, edge_end) = (travelled, travelled + length);
+            travelled = edge_end;
+            if edge_end <= start {
+                continue;
+            }
+            if edge_start >= end {
+                break;
+            }
+            let t0 = if start > edge_start {
+                edge.parameter_at(from, start - edge_start)
+            } else {
+                0.0
+            };
+            let t1 = if end < edge_end {
+                edge.parameter_at(from, end - edge_start)
+            } else {
+                1.0
+            };
+            let (piece_start, piece) = edge.sub_edge(from, t0, t1);
+            trimmed
+                .get_or_insert_with(|| Contour::new(piece_start, vec![], false))
+                .edges
+                .push(piece);
+        }
+        trimmed
+    }
+}
+
+/// Draws part of each contour of a path, from `start` to `end` as fractions of the contour's
+/// length. Tweening `end` from 0 to 1 draws the path on. Fills and clips are drawn whole.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PathTrim {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for PathTrim {
+    fn default() -> Self {
+        Self {
+            start: 0.0,
+            end: 1.0,
+        }
+    }
+}
+
+impl PathTrim {
+    pub fn new(start: f32, end: f32) -> Self {
+        Self { start, end }
+    }
+
+    /// Whether the trim leaves paths whole.
+    pub fn is_whole(&self) -> bool {
+        self.start <= 0.0 && self.end >= 1.0
+    }
+
+    pub fn lerp(&self, end: &PathTrim, value: f32) -> Self {
+        Self {
+            start: util::lerp(self.start, end.start, value),
+            end: util::lerp(self.end, end.end, value),
+        }
+    }
+
+    pub fn apply(&self, contours: &[Contour]) -> Vec<Contour> {
+        contours
+            .iter()
+            .filter_map(|contour| contour.trim(self.start, self.end))
+            .collect()
+    }
+}
+
+/// A `Contour` whose points morph. The start and end of the morph share the same contours, so
+/// holes stay holes part way through.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MorphContour {
+    /// The start point at the start and the end of the morph.
+    pub start: (Vector2F, Vector2F),
+    pub edges: Vec<MorphEdge>,
+    pub closed: bool,
+}
+
+impl MorphContour {
+    pub fn new(start: (Vector2F, Vector2F), edges: Vec<MorphEdge>, closed: bool) -> Self {
+        Self {
+            start,
+            edges,
+            closed,
+        }
+    }
+
+    pub fn to_contour(&self, percent: f32) -> Contour {
+        Contour::new(
+            self.start.0.lerp(self.start.1, percent),
+            self.edges
+                .iter()
+                .map(|edge| edge.to_edge(percent))
+                .collect(),
+            self.closed,
+        )
+    }
+
+    pub fn from_edges(edges: &[MorphEdge]) -> Vec<MorphContour> {
+        let mut contours = vec![];
+        let mut current: Option<MorphContour> = None;
+        let mut contour_start = None;
+        for edge in edges {
+            match edge {
+                MorphEdge::Move(start, end) => {
+                    contours.extend(current.replace(MorphContour::new(
+                        (*start, *end),
+                        vec![],
+                        false,
+                    )));
+                    contour_start = Some((*start, *end));
+                }
+                MorphEdge::Line(start, end) if contour_start.is_none() => {
+                    current = Some(MorphContour::new((*start, *end), vec![], false));
+                    contour_start = Some((*start, *end));
+                }
+                MorphEdge::Close => {
+                    let start = contour_start.unwrap_or((Vector2F::zero(), Vector2F::zero()));
+                    let mut closed = current
+                        .take()
+                        .unwrap_or_else(|| MorphContour::new(start, vec![], false));
+                    closed.closed = true;
+                    contours.push(closed);
+                }
+                edge => {
+                    let start = contour_start.unwrap_or((Vector2F::zero(), Vector2F::zero()));
+                    current
+                        .get_or_insert_with(|| MorphContour::new(start, vec![], false))
+                        .edges
+                        .push(edge.clone())
+                }
+            }
+        }
+        contours.extend(current);
+        contours
+    }
+
+    pub fn to_edges(contours: &[MorphContour]) -> Vec<MorphEdge> {
+        let mut edges = Vec::with_capacity(Self::edge_count(contours));
+        for contour in contours {
+            edges.push(MorphEdge::Move(contour.start.0, contour.start.1));
+            edges.extend_from_slice(&contour.edges);
+            if contour.closed {
+                edges.push(MorphEdge::Close);
+            }
+        }
+        edges
+    }
+
+    pub fn edge_count(contours: &[MorphContour]) -> usize {
+        contours
+            .iter()
+            .map(|contour| 1 + contour.edges.len() + contour.closed as usize)
+            .sum()
+    }
+}
+
+// Contours are saved as the flat edge lists shapes had before they were split into contours, so
+// older files load unchanged
+mod contours_as_edges {
+    use super::{Contour, Edge};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        contours: &[Contour],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Contour::to_edges(contours).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Contour>, D::Error> {
+        Vec::<Edge>::deserialize(deserializer).map(|edges| Contour::from_edges(&edges))
+    }
+}
+
+mod morph_contours_as_edges {
+    use super::{MorphContour, MorphEdge};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        contours: &[MorphContour],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        MorphContour::to_edges(contours).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<MorphContour>, D::Error> {
+        Vec::<MorphEdge>::deserialize(deserializer).map(|edges| MorphContour::from_edges(&edges))
+    }
 }
 
-//TODO: should close path be an instruction in edge???
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Shape {
     Path {
-        edges: Vec<Edge>,
+        #[serde(rename = "edges", with = "contours_as_edges")]
+        contours: Vec<Contour>,
         color: LinSrgba,
         #[serde(with = "StrokeStyleDef")]
         stroke_style: StrokeStyle,
     },
     Fill {
-        edges: Vec<Edge>,
+        #[serde(rename = "edges", with = "contours_as_edges")]
+        contours: Vec<Contour>,
         color: LinSrgba,
     },
     MorphPath {
-        edges: Vec<MorphEdge>,
+        #[serde(rename = "edges", with = "morph_contours_as_edges")]
+        contours: Vec<MorphContour>,
         color: LinSrgba,
         #[serde(with = "StrokeStyleDef")]
         stroke_style: StrokeStyle,
     },
     MorphFill {
-        edges: Vec<MorphEdge>,
+        #[serde(rename = "edges", with = "morph_contours_as_edges")]
+        contours: Vec<MorphContour>,
         color: LinSrgba,
     },
     Clip {
-        edges: Vec<Edge>,
+        #[serde(rename = "edges", with = "contours_as_edges")]
+        contours: Vec<Contour>,
     },
     Group {
         shapes: Vec<AugmentedShape>,
     },
+    /// Another library shape, drawn with the given transform. Lets symbols be composed from
+    /// existing shapes without copying their edges. The `Library` inlines references when the
+    /// shape is added, so everything else sees a plain shape. Unresolved, a reference has no
+    /// edges or color.
+    Reference(
+        LibraryId,
+        #[serde(serialize_with = "transform_ser", deserialize_with = "transform_des")] Transform2F,
+    ),
+    /// Draws the shape in the named palette color, replacing the colors of everything in it.
+    /// Resolved by the `Library` like references. Shapes whose entry is missing keep their own
+    /// colors.
+    Swatch(String, Box<Shape>),
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -553,6 +1683,11 @@ impl AugmentedShape {
             .compute_bounding(&(*transform * self.transform), morph_percent)
     }
 
+    pub fn compute_stroked_bounding(&self, transform: &Transform2F, morph_percent: f32) -> RectF {
+        self.shape
+            .compute_stroked_bounding(&(*transform * self.transform), morph_percent)
+    }
+
     pub fn edge_list(&self, morph_percent: f32) -> Vec<Edge> {
         self.shape.edge_list(morph_percent)
     }
@@ -565,46 +1700,220 @@ impl AugmentedShape {
 impl Shape {
     pub fn compute_bounding(&self, transform: &Transform2F, morph_percent: f32) -> RectF {
         match self {
-            Shape::Path { edges, .. } | Shape::Fill { edges, .. } | Shape::Clip { edges, .. } => {
-                Edge::compute_bounding(edges.iter().map(|e| *e), &transform)
-            }
-            Shape::MorphPath {
-                edges: morph_edges, ..
-            }
-            | Shape::MorphFill {
-                edges: morph_edges, ..
-            } => {
-                let edges = morph_edges
-                    .iter()
-                    .map(|morph_edge| morph_edge.to_edge(morph_percent));
-                Edge::compute_bounding(edges, &transform)
+            Shape::Path { contours, .. }
+            | Shape::Fill { contours, .. }
+            | Shape::Clip { contours, .. } => Contour::compute_bounding(contours, &transform),
+            Shape::MorphPath { .. } | Shape::MorphFill { .. } => {
+                Contour::compute_bounding(&self.contours(morph_percent), &transform)
             }
             Shape::Group { shapes } => shapes
                 .iter()
                 .map(|s| s.compute_bounding(transform, morph_percent))
                 .reduce(|a, b| a.union_rect(b))
                 .unwrap(),
+            Shape::Reference(_, reference_transform) => RectF::new(
+                (*transform * *reference_transform).translation(),
+                Vector2F::zero(),
+            ),
+            Shape::Swatch(_, shape) => shape.compute_bounding(transform, morph_percent),
         }
     }
 
-    pub fn edge_list(&self, morph_percent: f32) -> Vec<Edge> {
+    /// Like `compute_bounding`, but paths include the area their strokes cover, caps and joins
+    /// included.
+    pub fn compute_stroked_bounding(&self, transform: &Transform2F, morph_percent: f32) -> RectF {
+        match self {
+            Shape::Path {
+                contours,
+                stroke_style,
+                ..
+            } => Contour::compute_stroked_bounding(contours, stroke_style, transform),
+            Shape::MorphPath { stroke_style, .. } => Contour::compute_stroked_bounding(
+                &self.contours(morph_percent),
+                stroke_style,
+                transform,
+            ),
+            Shape::Group { shapes } => shapes
+                .iter()
+                .map(|s| s.compute_stroked_bounding(transform, morph_percent))
+                .reduce(|a, b| a.union_rect(b))
+                .unwrap(),
+            Shape::Swatch(_, shape) => shape.compute_stroked_bounding(transform, morph_percent),
+            _ => self.compute_bounding(transform, morph_percent),
+        }
+    }
+
+    /// Like `compute_bounding`, or `compute_stroked_bounding` with `include_strokes`, but the
+    /// shapes of a group take their morph from `morph_indices` by position, falling back to
+    /// `morph_percent`.
+    pub fn compute_bounding_per_shape(
+        &self,
+        transform: &Transform2F,
+        morph_percent: f32,
+        morph_indices: &[f32],
+        include_strokes: bool,
+    ) -> RectF {
+        let bounding = |s: &AugmentedShape, morph: f32| {
+            if include_strokes {
+                s.compute_stroked_bounding(transform, morph)
+            } else {
+                s.compute_bounding(transform, morph)
+            }
+        };
+        match self {
+            Shape::Group { shapes } if !morph_indices.is_empty() => shapes
+                .iter()
+                .enumerate()
+                .map(|(index, s)| {
+                    bounding(
+                        s,
+                        morph_indices.get(index).copied().unwrap_or(morph_percent),
+                    )
+                })
+                .reduce(|a, b| a.union_rect(b))
+                .unwrap(),
+            _ if include_strokes => self.compute_stroked_bounding(transform, morph_percent),
+            _ => self.compute_bounding(transform, morph_percent),
+        }
+    }
+
+    /// The widest stroke used by this shape, or 0 if it is only filled.
+    pub fn max_stroke_width(&self) -> f32 {
         match self {
-            Shape::Path { edges, .. } | Shape::Fill { edges, .. } | Shape::Clip { edges, .. } => {
-                edges.to_vec()
+            Shape::Path { stroke_style, .. } | Shape::MorphPath { stroke_style, .. } => {
+                stroke_style.line_width
             }
-            Shape::MorphPath {
-                edges: morph_edges, ..
+            Shape::Fill { .. } | Shape::MorphFill { .. } | Shape::Clip { .. } => 0.0,
+            // Not exact, since it ignores any scale in the child transforms
+            Shape::Group { shapes } => shapes
+                .iter()
+                .map(|s| s.shape.max_stroke_width())
+                .fold(0.0, f32::max),
+            Shape::Reference(..) => 0.0,
+            Shape::Swatch(_, shape) => shape.max_stroke_width(),
+        }
+    }
+
+    /// The shape's contours, as a flat edge list. See `Contour`.
+    pub fn edge_list(&self, morph_percent: f32) -> Vec<Edge> {
+        let mut edges = vec![];
+        self.edge_list_into(morph_percent, &mut edges);
+        edges
+    }
+
+    /// Appends the flat edge list to `edges`, so a buffer from an `EdgePool` can be reused.
+    /// Static contours are copied straight in, without building intermediate contours.
+    pub fn edge_list_into(&self, morph_percent: f32, edges: &mut Vec<Edge>) {
+        match self {
+            Shape::Path { contours, .. }
+            | Shape::Fill { contours, .. }
+            | Shape::Clip { contours, .. } => Contour::append_edges(contours, edges),
+            Shape::Group { shapes } => {
+                for shape in shapes {
+                    shape.shape.edge_list_into(morph_percent, edges);
+                }
             }
-            | Shape::MorphFill {
-                edges: morph_edges, ..
-            } => morph_edges
+            Shape::Reference(..) => {}
+            Shape::Swatch(_, shape) => shape.edge_list_into(morph_percent, edges),
+            _ => Contour::append_edges(&self.contours(morph_percent), edges),
+        }
+    }
+
+    /// Every contour in the shape, including the shapes of a group, ignoring their transforms.
+    pub fn contours(&self, morph_percent: f32) -> Vec<Contour> {
+        match self {
+            Shape::Path { contours, .. }
+            | Shape::Fill { contours, .. }
+            | Shape::Clip { contours, .. } => contours.to_vec(),
+            Shape::MorphPath { contours, .. } | Shape::MorphFill { contours, .. } => contours
                 .iter()
-                .map(|morph_edge| morph_edge.to_edge(morph_percent))
-                .collect::<Vec<Edge>>(),
+                .map(|contour| contour.to_contour(morph_percent))
+                .collect(),
             Shape::Group { shapes } => shapes
                 .iter()
-                .flat_map(|s| s.edge_list(morph_percent))
-                .collect::<Vec<Edge>>(),
+                .flat_map(|s| s.shape.contours(morph_percent))
+                .collect(),
+            Shape::Reference(..) => vec![],
+            Shape::Swatch(_, shape) => shape.contours(morph_percent),
+        }
+    }
+
+    /// A morph from the start of this path or fill to `end`, keeping its color and stroke. `end`
+    /// has to have the same edges as the start pose, in the same order, so each point knows where
+    /// it is going.
+    pub fn morph_to(&self, end: &[Edge]) -> Result<Shape, String> {
+        let start = self.edge_list(0.0);
+        if start.len() != end.len() {
+            return Err(format!(
+                "The end pose has {} edges, the start pose has {}",
+                end.len(),
+                start.len()
+            ));
+        }
+        let edges = start
+            .iter()
+            .zip(end.iter())
+            .map(|(start, end)| MorphEdge::from_edges(start, end))
+            .collect::<Result<Vec<MorphEdge>, String>>()?;
+        let contours = MorphContour::from_edges(&edges);
+        match self {
+            Shape::Path {
+                color,
+                stroke_style,
+                ..
+            }
+            | Shape::MorphPath {
+                color,
+                stroke_style,
+                ..
+            } => Ok(Shape::MorphPath {
+                contours,
+                color: *color,
+                stroke_style: *stroke_style,
+            }),
+            Shape::Fill { color, .. } | Shape::MorphFill { color, .. } => Ok(Shape::MorphFill {
+                contours,
+                color: *color,
+            }),
+            _ => Err("Only paths and fills can morph".to_owned()),
+        }
+    }
+
+    /// The shape with the contours of its paths trimmed. Morphing paths are trimmed as they are
+    /// at `morph_percent`, and come back as plain paths.
+    pub fn trim(&self, trim: &PathTrim, morph_percent: f32) -> Shape {
+        match self {
+            Shape::Path {
+                contours,
+                color,
+                stroke_style,
+            } => Shape::Path {
+                contours: trim.apply(contours),
+                color: *color,
+                stroke_style: *stroke_style,
+            },
+            Shape::MorphPath {
+                color,
+                stroke_style,
+                ..
+            } => Shape::Path {
+                contours: trim.apply(&self.contours(morph_percent)),
+                color: *color,
+                stroke_style: *stroke_style,
+            },
+            Shape::Group { shapes } => Shape::Group {
+                shapes: shapes
+                    .iter()
+                    .map(|augmented_shape| AugmentedShape {
+                        shape: augmented_shape.shape.trim(trim, morph_percent),
+                        transform: augmented_shape.transform,
+                    })
+                    .collect(),
+            },
+            Shape::Swatch(name, shape) => {
+                Shape::Swatch(name.clone(), Box::new(shape.trim(trim, morph_percent)))
+            }
+            shape => shape.clone(),
         }
     }
 
@@ -614,29 +1923,162 @@ impl Shape {
             | Shape::Fill { color, .. }
             | Shape::MorphPath { color, .. }
             | Shape::MorphFill { color, .. } => Coloring::Color(*color),
-            Shape::Clip { .. } => Coloring::None,
+            Shape::Clip { .. } | Shape::Reference(..) => Coloring::None,
             Shape::Group { shapes } => {
                 Coloring::Colorings(shapes.iter().map(|s| s.shape.color()).collect())
             }
+            Shape::Swatch(_, shape) => shape.color(),
         }
     }
 
+    /// The length of the shape's flat edge list.
     pub fn len(&self) -> usize {
         match self {
-            Shape::Path { edges, .. } | Shape::Fill { edges, .. } | Shape::Clip { edges, .. } => {
-                edges.len()
-            }
-            Shape::MorphPath {
-                edges: morph_edges, ..
+            Shape::Path { contours, .. }
+            | Shape::Fill { contours, .. }
+            | Shape::Clip { contours, .. } => Contour::edge_count(contours),
+            Shape::MorphPath { contours, .. } | Shape::MorphFill { contours, .. } => {
+                MorphContour::edge_count(contours)
             }
-            | Shape::MorphFill {
-                edges: morph_edges, ..
-            } => morph_edges.len(),
             Shape::Group { shapes } => shapes
                 .iter()
                 .map(|s| s.len())
                 .reduce(|l, acc| l + acc)
                 .unwrap_or_else(|| 0),
+            Shape::Reference(..) => 0,
+            Shape::Swatch(_, shape) => shape.len(),
+        }
+    }
+
+    /// Rough number of bytes used by this shape, including its edges.
+    pub fn approximate_size(&self) -> usize {
+        mem::size_of::<Shape>()
+            + match self {
+                Shape::Path { contours, .. }
+                | Shape::Fill { contours, .. }
+                | Shape::Clip { contours, .. } => contours
+                    .iter()
+                    .map(|contour| {
+                        mem::size_of::<Contour>()
+                            + contour.edges.capacity() * mem::size_of::<Edge>()
+                    })
+                    .sum(),
+                Shape::MorphPath { contours, .. } | Shape::MorphFill { contours, .. } => contours
+                    .iter()
+                    .map(|contour| {
+                        mem::size_of::<MorphContour>()
+                            + contour.edges.capacity() * mem::size_of::<MorphEdge>()
+                    })
+                    .sum(),
+                Shape::Group { shapes } => shapes
+                    .iter()
+                    .map(|s| s.shape.approximate_size() + mem::size_of::<Transform2F>())
+                    .sum(),
+                Shape::Reference(..) => 0,
+                Shape::Swatch(name, shape) => name.capacity() + shape.approximate_size(),
+            }
+    }
+
+    /// Every library shape this shape references directly, including inside groups.
+    pub fn references(&self) -> Vec<LibraryId> {
+        match self {
+            Shape::Reference(id, _) => vec![*id],
+            Shape::Group { shapes } => shapes.iter().flat_map(|s| s.shape.references()).collect(),
+            Shape::Swatch(_, shape) => shape.references(),
+            _ => vec![],
+        }
+    }
+
+    /// Like `references`, for rewriting them in place.
+    pub fn references_mut(&mut self) -> Vec<&mut LibraryId> {
+        match self {
+            Shape::Reference(id, _) => vec![id],
+            Shape::Group { shapes } => shapes
+                .iter_mut()
+                .flat_map(|s| s.shape.references_mut())
+                .collect(),
+            Shape::Swatch(_, shape) => shape.references_mut(),
+            _ => vec![],
+        }
+    }
+
+    /// True if the library has to resolve references or swatches before this shape is drawn.
+    pub fn needs_resolving(&self) -> bool {
+        match self {
+            Shape::Reference(..) | Shape::Swatch(..) => true,
+            Shape::Group { shapes } => shapes.iter().any(|s| s.shape.needs_resolving()),
+            _ => false,
+        }
+    }
+
+    /// Replaces every swatch with its shape, recolored with the entry `lookup` returns for it.
+    pub fn apply_swatches(&self, lookup: &impl Fn(&str) -> Option<LinSrgba>) -> Shape {
+        match self {
+            Shape::Swatch(name, shape) => {
+                let mut shape = shape.apply_swatches(lookup);
+                if let Some(color) = lookup(name) {
+                    shape.recolor(color);
+                }
+                shape
+            }
+            Shape::Group { shapes } => Shape::Group {
+                shapes: shapes
+                    .iter()
+                    .map(|s| AugmentedShape {
+                        shape: s.shape.apply_swatches(lookup),
+                        transform: s.transform,
+                    })
+                    .collect(),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    fn recolor(&mut self, new_color: LinSrgba) {
+        match self {
+            Shape::Path { color, .. }
+            | Shape::Fill { color, .. }
+            | Shape::MorphPath { color, .. }
+            | Shape::MorphFill { color, .. } => *color = new_color,
+            Shape::Group { shapes } => {
+                for s in shapes.iter_mut() {
+                    s.shape.recolor(new_color);
+                }
+            }
+            Shape::Swatch(_, shape) => shape.recolor(new_color),
+            Shape::Clip { .. } | Shape::Reference(..) => (),
+        }
+    }
+
+    /// Replaces every reference with a group holding the shape `resolve` returns for it.
+    /// References it returns `None` for are left in place.
+    pub fn inline_references(
+        &self,
+        resolve: &mut impl FnMut(&LibraryId) -> Option<Shape>,
+    ) -> Shape {
+        match self {
+            Shape::Reference(id, transform) => match resolve(id) {
+                Some(shape) => Shape::Group {
+                    shapes: vec![AugmentedShape {
+                        shape,
+                        transform: *transform,
+                    }],
+                },
+                None => self.clone(),
+            },
+            Shape::Group { shapes } => Shape::Group {
+                shapes: shapes
+                    .iter()
+                    .map(|s| AugmentedShape {
+                        shape: s.shape.inline_references(resolve),
+                        transform: s.transform,
+                    })
+                    .collect(),
+            },
+            Shape::Swatch(name, shape) => {
+                Shape::Swatch(name.clone(), Box::new(shape.inline_references(resolve)))
+            }
+            _ => self.clone(),
         }
     }
 }
@@ -666,3 +2108,291 @@ pub enum LineJoinDef {
     Bevel,
     Round,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vector2F, b: Vector2F) {
+        assert!((a - b).length() < 0.01, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn it_splits_a_bezier_without_changing_its_shape() {
+        let mut edges = vec![
+            Edge::Move(Vector2F::zero()),
+            Edge::Bezier {
+                control_1: Vector2F::new(0.0, 10.0),
+                control_2: Vector2F::new(10.0, 10.0),
+                to: Vector2F::new(10.0, 0.0),
+            },
+        ];
+        let index = Edge::insert_point(&mut edges, Vector2F::new(5.0, 8.0)).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(edges.len(), 3);
+        assert_near(edges[1].end_point(), Vector2F::new(5.0, 7.5));
+        assert_near(edges[2].end_point(), Vector2F::new(10.0, 0.0));
+        let starts = Edge::segment_starts(&edges);
+        assert_near(
+            edges[2].point_at(starts[2].0, 0.5).unwrap(),
+            Vector2F::new(8.4375, 5.625),
+        );
+    }
+
+    #[test]
+    fn it_removes_a_point_and_rejoins_its_neighbors() {
+        let mut edges = Edge::new_rect(Vector2F::splat(10.0), Transform2F::default());
+        Edge::remove_point(&mut edges, 2).unwrap();
+        assert_eq!(edges.len(), 5);
+        assert_eq!(edges[2], Edge::Line(Vector2F::new(0.0, 10.0)));
+        Edge::remove_point(&mut edges, 0).unwrap();
+        assert_eq!(edges[0], Edge::Move(Vector2F::new(10.0, 0.0)));
+        let close = edges.len() - 1;
+        assert!(Edge::remove_point(&mut edges, close).is_err());
+    }
+
+    #[test]
+    fn it_mirrors_symmetric_handles() {
+        let mut edges = vec![
+            Edge::Move(Vector2F::zero()),
+            Edge::Bezier {
+                control_1: Vector2F::new(0.0, 5.0),
+                control_2: Vector2F::new(5.0, 10.0),
+                to: Vector2F::new(10.0, 10.0),
+            },
+            Edge::Bezier {
+                control_1: Vector2F::new(15.0, 10.0),
+                control_2: Vector2F::new(20.0, 5.0),
+                to: Vector2F::new(20.0, 0.0),
+            },
+        ];
+        assert_eq!(Edge::handle_type(&edges, 1, 1), Some(HandleType::Symmetric));
+        assert_eq!(Edge::handle_type(&edges, 1, 0), None);
+        Edge::move_point(
+            &mut edges,
+            1,
+            1,
+            Vector2F::new(7.0, 12.0),
+            HandleType::Symmetric,
+        );
+        assert_near(edges[2].point(0).unwrap(), Vector2F::new(13.0, 8.0));
+        // Moving the anchor carries both handles with it
+        Edge::move_point(
+            &mut edges,
+            1,
+            2,
+            Vector2F::new(10.0, 20.0),
+            HandleType::Corner,
+        );
+        assert_near(edges[1].point(1).unwrap(), Vector2F::new(7.0, 22.0));
+        assert_near(edges[2].point(0).unwrap(), Vector2F::new(13.0, 18.0));
+    }
+
+    #[test]
+    fn it_converts_a_line_to_a_bezier() {
+        let mut edges = vec![
+            Edge::Move(Vector2F::zero()),
+            Edge::Line(Vector2F::new(30.0, 0.0)),
+        ];
+        Edge::convert_edge(&mut edges, 1, EdgeKind::Bezier).unwrap();
+        if let Edge::Bezier {
+            control_1,
+            control_2,
+            to,
+        } = edges[1]
+        {
+            assert_near(control_1, Vector2F::new(10.0, 0.0));
+            assert_near(control_2, Vector2F::new(20.0, 0.0));
+            assert_near(to, Vector2F::new(30.0, 0.0));
+        } else {
+            panic!("Expected a bezier, got {:?}", edges[1]);
+        }
+        Edge::convert_edge(&mut edges, 1, EdgeKind::Line).unwrap();
+        assert_eq!(edges[1], Edge::Line(Vector2F::new(30.0, 0.0)));
+    }
+
+    // A 30x30 square with a 10x10 hole wound the other way in its middle
+    fn square_with_hole() -> Vec<Edge> {
+        let mut edges = Edge::new_rect(Vector2F::splat(30.0), Transform2F::default());
+        edges.extend(Edge::new_rect(
+            Vector2F::splat(10.0),
+            Transform2F::from_translation(Vector2F::new(20.0, 10.0))
+                * Transform2F::from_scale(Vector2F::new(-1.0, 1.0)),
+        ));
+        edges
+    }
+
+    #[test]
+    fn it_splits_edges_into_contours() {
+        let edges = square_with_hole();
+        let contours = Contour::from_edges(&edges);
+        assert_eq!(contours.len(), 2);
+        assert!(contours.iter().all(|contour| contour.closed));
+        assert_eq!(contours[1].start, Vector2F::new(20.0, 10.0));
+        assert_eq!(Contour::to_edges(&contours), edges);
+        assert_eq!(Contour::edge_count(&contours), edges.len());
+        // Edges after a close carry on from the closed contour's start
+        let contours = Contour::from_edges(&[
+            Edge::Move(Vector2F::splat(5.0)),
+            Edge::Line(Vector2F::new(10.0, 5.0)),
+            Edge::Close,
+            Edge::Line(Vector2F::new(5.0, 10.0)),
+        ]);
+        assert_eq!(contours.len(), 2);
+        assert_eq!(contours[1].start, Vector2F::splat(5.0));
+        assert!(!contours[1].closed);
+    }
+
+    #[test]
+    fn it_starts_contours_at_a_leading_line() {
+        let contours = Contour::from_edges(&[
+            Edge::Line(Vector2F::splat(5.0)),
+            Edge::Line(Vector2F::new(10.0, 5.0)),
+            Edge::Close,
+        ]);
+        assert_eq!(contours.len(), 1);
+        assert_eq!(contours[0].start, Vector2F::splat(5.0));
+        assert_eq!(
+            contours[0].edges,
+            vec![Edge::Line(Vector2F::new(10.0, 5.0))]
+        );
+        let contours = MorphContour::from_edges(&[
+            MorphEdge::Line(Vector2F::splat(5.0), Vector2F::splat(6.0)),
+            MorphEdge::Line(Vector2F::new(10.0, 5.0), Vector2F::new(12.0, 6.0)),
+            MorphEdge::Close,
+        ]);
+        assert_eq!(contours.len(), 1);
+        assert_eq!(
+            contours[0].start,
+            (Vector2F::splat(5.0), Vector2F::splat(6.0))
+        );
+        assert_eq!(contours[0].edges.len(), 1);
+    }
+
+    #[test]
+    fn it_hit_tests_around_holes() {
+        let contours = Contour::from_edges(&square_with_hole());
+        assert_eq!(
+            contours[0].winding_number(Vector2F::splat(15.0)),
+            -contours[1].winding_number(Vector2F::splat(15.0))
+        );
+        assert!(Contour::contains_point(&contours, Vector2F::splat(5.0)));
+        assert!(!Contour::contains_point(&contours, Vector2F::splat(15.0)));
+        assert!(!Contour::contains_point(&contours, Vector2F::splat(35.0)));
+    }
+
+    // How shapes were saved before contours. Path is only here to give Fill the same index.
+    #[derive(Serialize)]
+    enum EdgeListShape {
+        #[allow(dead_code)]
+        Path {
+            edges: Vec<Edge>,
+            color: LinSrgba,
+            #[serde(with = "StrokeStyleDef")]
+            stroke_style: StrokeStyle,
+        },
+        Fill {
+            edges: Vec<Edge>,
+            color: LinSrgba,
+        },
+    }
+
+    #[test]
+    fn it_loads_shapes_saved_as_edge_lists() {
+        let color = LinSrgba::new(1.0, 0.0, 0.0, 1.0);
+        let saved = bincode::serialize(&EdgeListShape::Fill {
+            edges: square_with_hole(),
+            color,
+        })
+        .unwrap();
+        let shape = bincode::deserialize::<Shape>(&saved).unwrap();
+        assert_eq!(
+            shape,
+            Shape::Fill {
+                contours: Contour::from_edges(&square_with_hole()),
+                color,
+            }
+        );
+        assert_eq!(bincode::serialize(&shape).unwrap(), saved);
+    }
+
+    #[test]
+    fn it_includes_strokes_in_stroked_bounds() {
+        let line = Shape::Path {
+            contours: Contour::from_edges(&[
+                Edge::Move(Vector2F::zero()),
+                Edge::Line(Vector2F::new(10.0, 0.0)),
+            ]),
+            color: LinSrgba::new(0.0, 0.0, 0.0, 1.0),
+            stroke_style: StrokeStyle {
+                line_width: 4.0,
+                line_cap: LineCap::Square,
+                line_join: LineJoin::Bevel,
+            },
+        };
+        let transform = Transform2F::from_scale(Vector2F::splat(2.0));
+        let bounds = line.compute_bounding(&transform, 0.0);
+        assert_near(bounds.size(), Vector2F::new(20.0, 0.0));
+        let stroked = line.compute_stroked_bounding(&transform, 0.0);
+        assert_near(stroked.origin(), Vector2F::new(-4.0, -4.0));
+        assert_near(stroked.lower_right(), Vector2F::new(24.0, 4.0));
+    }
+
+    #[test]
+    fn it_rounds_superellipse_steps_to_whole_curves() {
+        let edges = Edge::new_superellipse_with_steps(
+            Vector2F::splat(10.0),
+            4.0,
+            10,
+            Transform2F::default(),
+        );
+        // A move, four quadratics over twelve samples, the one back to the start, and a close
+        assert_eq!(edges.len(), 7);
+        assert_eq!(edges[0].end_point(), edges[5].end_point());
+    }
+
+    #[test]
+    fn it_trims_contours_by_length() {
+        let square = Contour::from_edges(&[
+            Edge::Move(Vector2F::zero()),
+            Edge::Line(Vector2F::new(10.0, 0.0)),
+            Edge::Line(Vector2F::new(10.0, 10.0)),
+            Edge::Line(Vector2F::new(0.0, 10.0)),
+            Edge::Close,
+        ]);
+        // The close is the last quarter
+        let trimmed = square[0].trim(0.125, 0.625).unwrap();
+        assert!(!trimmed.closed);
+        assert_near(trimmed.start, Vector2F::new(5.0, 0.0));
+        let ends = trimmed
+            .edges
+            .iter()
+            .map(|edge| edge.end_point())
+            .collect::<Vec<Vector2F>>();
+        assert_eq!(ends.len(), 3);
+        assert_near(ends[0], Vector2F::new(10.0, 0.0));
+        assert_near(ends[1], Vector2F::new(10.0, 10.0));
+        assert_near(ends[2], Vector2F::new(5.0, 10.0));
+        assert!(square[0].trim(0.5, 0.5).is_none());
+        assert_eq!(square[0].trim(0.0, 1.0).unwrap(), square[0]);
+    }
+
+    #[test]
+    fn it_morphs_a_shape_to_a_matching_end_pose() {
+        let square = Shape::Fill {
+            contours: Contour::from_edges(&square_with_hole()),
+            color: LinSrgba::new(0.0, 0.0, 0.0, 1.0),
+        };
+        let mut end = square.edge_list(0.0);
+        end[1] = Edge::Line(Vector2F::new(20.0, 0.0));
+        let morph = square.morph_to(&end).unwrap();
+        assert_eq!(morph.edge_list(0.0), square.edge_list(0.0));
+        assert_eq!(morph.edge_list(1.0), end);
+        assert_eq!(
+            morph.edge_list(0.5)[1],
+            Edge::Line(Vector2F::new(25.0, 0.0))
+        );
+        end.pop();
+        assert!(square.morph_to(&end).is_err());
+    }
+}
@@ -1,10 +1,16 @@
 use pathfinder_geometry::{rect::RectF, vector::Vector2F};
 use std::mem;
+use std::time::Duration;
 
 pub fn clamp_0_1(number: f32) -> f32 {
     number.clamp(0.0, 1.0)
 }
 
+// Duration::div_duration_f32 is still unstable
+pub fn div_duration_f32(numerator: Duration, denominator: Duration) -> f32 {
+    numerator.as_secs_f32() / denominator.as_secs_f32()
+}
+
 pub fn lerp(start: f32, end: f32, percent: f32) -> f32 {
     (end - start) * percent + start
 }
@@ -15,58 +21,134 @@ pub fn distance_from_abb(point: Vector2F, aabb: RectF) -> f32 {
 
 pub fn closest_point_on_aabb(point: Vector2F, aabb: RectF) -> Vector2F {
     Vector2F::new(
-        point.x().min(aabb.min_x()).max(aabb.max_x()),
-        point.y().min(aabb.min_y()).max(aabb.max_y()),
+        point.x().max(aabb.min_x()).min(aabb.max_x()),
+        point.y().max(aabb.min_y()).min(aabb.max_y()),
     )
 }
 
 pub fn ray_aabb_intersect(ray_origin: Vector2F, ray_direction: Vector2F, aabb: RectF) -> bool {
+    ray_aabb_distance(ray_origin, ray_direction, aabb).is_some()
+}
+
+/// Returns the ray parameter where the ray enters `aabb`, or 0 if it starts inside.
+pub fn ray_aabb_distance(
+    ray_origin: Vector2F,
+    ray_direction: Vector2F,
+    aabb: RectF,
+) -> Option<f32> {
     // ref: Page 180-181 of Real Time Colision Detection (Ch. 5.3.3)
-    let mut t_min = std::f32::MIN;
+    let mut t_min = 0.0f32;
     let mut t_max = std::f32::MAX;
-    // test x-slab
-    if ray_direction.x().abs() < std::f32::EPSILON {
-        return ray_origin.x() >= aabb.min_x() && ray_origin.x() <= aabb.max_x();
-    } else {
-        // compute intersection with near and far planes of slab
-        let ood = 1.0 / ray_direction.x();
-        let mut t1 = (aabb.min_x() - ray_origin.x()) * ood;
-        let mut t2 = (aabb.max_x() - ray_origin.x()) * ood;
+    for &(origin, direction, min, max) in [
+        (
+            ray_origin.x(),
+            ray_direction.x(),
+            aabb.min_x(),
+            aabb.max_x(),
+        ),
+        (
+            ray_origin.y(),
+            ray_direction.y(),
+            aabb.min_y(),
+            aabb.max_y(),
+        ),
+    ]
+    .iter()
+    {
+        if direction.abs() < std::f32::EPSILON {
+            // Parallel to the slab, so the origin has to already be inside it
+            if origin < min || origin > max {
+                return None;
+            }
+        } else {
+            // compute intersection with near and far planes of slab
+            let ood = 1.0 / direction;
+            let mut t1 = (min - origin) * ood;
+            let mut t2 = (max - origin) * ood;
 
-        // ensure t1 is intersection w/ near plane and t2 w/ far plane
-        if t1 > t2 {
-            mem::swap(&mut t1, &mut t2);
-        }
+            // ensure t1 is intersection w/ near plane and t2 w/ far plane
+            if t1 > t2 {
+                mem::swap(&mut t1, &mut t2);
+            }
 
-        // compute intersetion of slab with interval
-        t_min = t_min.max(t1);
-        t_max = t_max.min(t2);
-        // return if we are not in overlap
-        if t_min > t_max {
-            return false;
+            // compute intersetion of slab with interval
+            t_min = t_min.max(t1);
+            t_max = t_max.min(t2);
+            // return if we are not in overlap
+            if t_min > t_max {
+                return None;
+            }
         }
     }
-    // test y-slab
-    if ray_direction.y().abs() < std::f32::EPSILON {
-        return ray_origin.y() >= aabb.min_y() && ray_origin.y() <= aabb.max_y();
+    Some(t_min)
+}
+
+/// Intersects a ray with the segment from `start` to `end`. Returns the ray parameter and the
+/// point of intersection. Parallel segments never intersect.
+pub fn ray_segment_intersect(
+    ray_origin: Vector2F,
+    ray_direction: Vector2F,
+    start: Vector2F,
+    end: Vector2F,
+) -> Option<(f32, Vector2F)> {
+    let segment = end - start;
+    let denominator = ray_direction.det(segment);
+    if denominator.abs() < std::f32::EPSILON {
+        return None;
+    }
+    let offset = start - ray_origin;
+    let t = offset.det(segment) / denominator;
+    let u = offset.det(ray_direction) / denominator;
+    if t >= 0.0 && u >= 0.0 && u <= 1.0 {
+        Some((t, ray_origin + ray_direction * t))
     } else {
-        // compute intersection with near and far planes of slab
-        let ood = 1.0 / ray_direction.y();
-        let mut t1 = (aabb.min_y() - ray_origin.y()) * ood;
-        let mut t2 = (aabb.max_y() - ray_origin.y()) * ood;
+        None
+    }
+}
 
-        // ensure t1 is intersection w/ near plane and t2 w/ far plane
-        if t1 > t2 {
-            mem::swap(&mut t1, &mut t2);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // compute intersetion of slab with interval
-        t_min = t_min.max(t1);
-        t_max = t_max.min(t2);
-        // return if we are not in overlap
-        if t_min > t_max {
-            return false;
-        }
+    #[test]
+    fn it_clamps_to_aabb() {
+        let aabb = RectF::new(Vector2F::zero(), Vector2F::splat(10.0));
+        assert_eq!(
+            closest_point_on_aabb(Vector2F::new(15.0, 5.0), aabb),
+            Vector2F::new(10.0, 5.0)
+        );
+        assert_eq!(
+            closest_point_on_aabb(Vector2F::new(5.0, 5.0), aabb),
+            Vector2F::new(5.0, 5.0)
+        );
+        assert_eq!(distance_from_abb(Vector2F::new(-3.0, -4.0), aabb), 5.0);
+    }
+
+    #[test]
+    fn it_intersects_rays() {
+        let aabb = RectF::new(Vector2F::splat(10.0), Vector2F::splat(10.0));
+        assert_eq!(
+            ray_aabb_distance(Vector2F::zero(), Vector2F::new(1.0, 1.0), aabb),
+            Some(10.0)
+        );
+        // Pointing away
+        assert_eq!(
+            ray_aabb_distance(Vector2F::zero(), Vector2F::new(-1.0, -1.0), aabb),
+            None
+        );
+        // Axis aligned and outside the y slab
+        assert_eq!(
+            ray_aabb_distance(Vector2F::zero(), Vector2F::new(1.0, 0.0), aabb),
+            None
+        );
+        let (t, point) = ray_segment_intersect(
+            Vector2F::zero(),
+            Vector2F::new(1.0, 0.0),
+            Vector2F::new(5.0, -1.0),
+            Vector2F::new(5.0, 1.0),
+        )
+        .unwrap();
+        assert_eq!(t, 5.0);
+        assert_eq!(point, Vector2F::new(5.0, 0.0));
     }
-    true
 }
@@ -0,0 +1,112 @@
+//! A watermark hosts draw over the scene, for review builds and streaming previews. It lives in
+//! a container the runner adds under the root, so it never has to be authored into the
+//! `ActionList`.
+
+use crate::{
+    actions::{ContainerCreationDefintition, ContainerCreationProperty},
+    ecs::components::Anchor,
+    types::{
+        basic::{Bitmap, ContainerId, LibraryId},
+        coloring::ColorTransform,
+    },
+};
+use pathfinder_geometry::vector::Vector2F;
+
+/// The corner of the stage's safe area a watermark sits in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// An image pinned to a corner of the stage, drawn after everything else.
+// TODO: text watermarks, once there is text rendering
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watermark {
+    pub image: Bitmap,
+    pub corner: Corner,
+    /// Space between the image and the edges of the stage, in stage units.
+    pub margin: f32,
+    pub opacity: f32,
+}
+
+impl Watermark {
+    pub fn new(image: Bitmap) -> Self {
+        Self {
+            image,
+            corner: Corner::BottomRight,
+            margin: 16.0,
+            opacity: 0.5,
+        }
+    }
+
+    fn anchor(&self) -> Anchor {
+        let size = Vector2F::new(self.image.size_x as f32, self.image.size_y as f32);
+        let margin = Vector2F::splat(self.margin);
+        // Containers are positioned by their top left corner
+        let (point, offset) = match self.corner {
+            Corner::TopLeft => (Vector2F::zero(), margin),
+            Corner::TopRight => (
+                Vector2F::new(1.0, 0.0),
+                Vector2F::new(-(size.x() + margin.x()), margin.y()),
+            ),
+            Corner::BottomLeft => (
+                Vector2F::new(0.0, 1.0),
+                Vector2F::new(margin.x(), -(size.y() + margin.y())),
+            ),
+            Corner::BottomRight => (Vector2F::splat(1.0), -(size + margin)),
+        };
+        Anchor::new(point, offset)
+    }
+
+    /// The overlay container showing texture `library_id`. It ignores the camera, and sorts
+    /// after its siblings so it draws over the scene.
+    pub(crate) fn container_definition(
+        &self,
+        parent: ContainerId,
+        container_id: ContainerId,
+        library_id: LibraryId,
+    ) -> ContainerCreationDefintition {
+        ContainerCreationDefintition::new(
+            parent,
+            container_id,
+            vec![
+                ContainerCreationProperty::Display(library_id),
+                ContainerCreationProperty::Order(i8::MAX),
+                ContainerCreationProperty::Parallax(0.0),
+                ContainerCreationProperty::Anchor(self.anchor()),
+                ContainerCreationProperty::ColorTransform(ColorTransform::opacity(self.opacity)),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_color::ColorU;
+    use pathfinder_geometry::rect::RectF;
+    use std::sync::Arc;
+
+    #[test]
+    fn it_keeps_watermarks_inside_their_corner() {
+        let mut watermark = Watermark::new(Bitmap {
+            size_x: 100,
+            size_y: 50,
+            colors: Arc::new(vec![ColorU::white(); 5000]),
+        });
+        watermark.margin = 10.0;
+        let stage = RectF::new(Vector2F::zero(), Vector2F::new(800.0, 600.0));
+        assert_eq!(
+            watermark.anchor().position(stage),
+            Vector2F::new(690.0, 540.0)
+        );
+        watermark.corner = Corner::TopRight;
+        assert_eq!(
+            watermark.anchor().position(stage),
+            Vector2F::new(690.0, 10.0)
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::messages::{AnchorMessage, AppMessage};
+use fluster_core::{ecs::components::Anchor, types::basic::ContainerId};
+use iced::{button::State as ButtonState, Button, Column, Length, Row, Text};
+use pathfinder_geometry::vector::Vector2F;
+
+/// The points of the stage the anchor pane can pin a container to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnchorPreset {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl AnchorPreset {
+    // In reading order, so they lay out as a 3x3 grid
+    const ALL: [AnchorPreset; 9] = [
+        AnchorPreset::TopLeft,
+        AnchorPreset::Top,
+        AnchorPreset::TopRight,
+        AnchorPreset::Left,
+        AnchorPreset::Center,
+        AnchorPreset::Right,
+        AnchorPreset::BottomLeft,
+        AnchorPreset::Bottom,
+        AnchorPreset::BottomRight,
+    ];
+
+    /// The preset's point as a fraction of the stage's safe area. See `Anchor::point`.
+    pub fn point(self) -> Vector2F {
+        match self {
+            AnchorPreset::TopLeft => Vector2F::new(0.0, 0.0),
+            AnchorPreset::Top => Vector2F::new(0.5, 0.0),
+            AnchorPreset::TopRight => Vector2F::new(1.0, 0.0),
+            AnchorPreset::Left => Vector2F::new(0.0, 0.5),
+            AnchorPreset::Center => Vector2F::new(0.5, 0.5),
+            AnchorPreset::Right => Vector2F::new(1.0, 0.5),
+            AnchorPreset::BottomLeft => Vector2F::new(0.0, 1.0),
+            AnchorPreset::Bottom => Vector2F::new(0.5, 1.0),
+            AnchorPreset::BottomRight => Vector2F::new(1.0, 1.0),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            AnchorPreset::TopLeft => "TL",
+            AnchorPreset::Top => "T",
+            AnchorPreset::TopRight => "TR",
+            AnchorPreset::Left => "L",
+            AnchorPreset::Center => "C",
+            AnchorPreset::Right => "R",
+            AnchorPreset::BottomLeft => "BL",
+            AnchorPreset::Bottom => "B",
+            AnchorPreset::BottomRight => "BR",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AnchorPaneState {
+    preset_states: [ButtonState; 9],
+    clear_state: ButtonState,
+}
+
+impl AnchorPaneState {
+    /// Presets for pinning the selected container to the stage. Anchors set by hand to points
+    /// between presets show up as "Custom".
+    pub fn anchor_pane(
+        &mut self,
+        selected: Option<ContainerId>,
+        anchor: Option<Anchor>,
+    ) -> Column<AppMessage> {
+        let current = anchor.map(|anchor| {
+            AnchorPreset::ALL
+                .iter()
+                .copied()
+                .find(|preset| preset.point() == anchor.point)
+        });
+        let status = match current {
+            Some(Some(preset)) => format!("Anchor: {}", preset.label()),
+            Some(None) => "Anchor: Custom".to_owned(),
+            None => "Anchor: None".to_owned(),
+        };
+        let message = move |preset: Option<AnchorPreset>| {
+            selected.map(|container_id| {
+                AppMessage::AnchorMessage(AnchorMessage::Set(container_id, preset))
+            })
+        };
+        let mut column = Column::new()
+            .padding(20)
+            .spacing(3)
+            .push(Text::new(status).size(16));
+        let mut row = Row::new().spacing(3);
+        for (index, (preset, state)) in AnchorPreset::ALL
+            .iter()
+            .copied()
+            .zip(self.preset_states.iter_mut())
+            .enumerate()
+        {
+            let mut button =
+                Button::new(state, Text::new(preset.label()).size(14)).width(Length::Fill);
+            if let Some(on_press) = message(Some(preset)) {
+                button = button.on_press(on_press);
+            }
+            row = row.push(button);
+            if index % 3 == 2 {
+                column = column.push(row);
+                row = Row::new().spacing(3);
+            }
+        }
+        let mut clear = Button::new(&mut self.clear_state, Text::new("Unanchor").size(14));
+        if let (Some(on_press), Some(_)) = (message(None), anchor) {
+            clear = clear.on_press(on_press);
+        }
+        column.push(clear)
+    }
+}
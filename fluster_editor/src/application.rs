@@ -1,30 +1,73 @@
-use crate::messages::AppMessage;
+use crate::anchoring::AnchorPaneState;
+use crate::assets::{AssetWatcher, POLL_INTERVAL};
+use crate::commands::{CommandPaletteState, EditorCommand, KeyBindings, KeyChord};
+use crate::documents::{Document, DocumentManager, DocumentTabsState};
+use crate::messages::{
+    AnchorMessage, AppMessage, ChromeMessage, CommandMessage, DocumentMessage, EditMessage,
+    MorphMessage, OutlinerMessage, SymbolMessage, TimelineMessage, ViewportMessage,
+};
 use crate::rendering::StageRenderer;
-use crate::simulation::{StageState, TimelineState};
-use crate::tools::{EditDisplayState, EditState, Tool};
+use crate::simulation::StageState;
+use crate::stage_chrome::{ChromeDisplayState, ChromeState, GuideOrientation, Ruler, RULER_SIZE};
+use crate::timeline::TimelinePaneState;
+use crate::tools::{
+    cursor_to_view, view_to_stage, EditDisplayState, EditState, SelectionShape, ToolRegistry,
+    ViewportState,
+};
 
+use fluster_core::types::basic::ContainerId;
 use iced::{
-    button::State as ButtonState, executor, image::Handle as ImageHandle, mouse, Align,
-    Application, Button, Column, Command, Container, Element, Image, Length, Row, Size,
+    button::State as ButtonState, executor, image::Handle as ImageHandle, keyboard, mouse, time,
+    Align, Application, Button, Column, Command, Container, Element, Image, Length, Row, Size,
+    Space, Subscription, Text,
 };
 use iced_graphics::{Backend, Defaults, Primitive, Renderer};
-use iced_native::{layout, Clipboard, Event, Hasher, Layout, Point, Widget};
+use iced_native::{layout, window, Clipboard, Event, Hasher, Layout, Point, Widget};
 use palette::LinSrgb;
 use pathfinder_geometry::vector::{Vector2F, Vector2I};
-use std::{convert::TryInto, hash::Hash};
-pub struct Stage<'a, 'b, 'c> {
+use std::{
+    convert::TryInto,
+    hash::Hash,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+// TODO: let the timeline set how long morphs take, and where they start
+const MORPH_DURATION_FRAMES: u32 = 24;
+// Zoom per press of the zoom commands
+const COMMAND_ZOOM_STEP: f32 = 2.0;
+// Two clicks on the same container closer together than this are a double click
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
+// In the user's config directory, or the working directory on platforms without one
+fn key_bindings_path() -> PathBuf {
+    dirs::config_dir()
+        .map(|config_dir| config_dir.join("fluster"))
+        .unwrap_or_default()
+        .join("keybindings.cfg")
+}
+
+pub struct Stage<'a> {
     width: u16,
     height: u16,
     frame: ImageHandle,
     edit_state: &'a EditState,
-    stage_state: &'a StageState<'b, 'c>,
+    stage_state: &'a StageState,
+    chrome_state: &'a ChromeState,
+    viewport_state: &'a ViewportState,
+    key_bindings: &'a KeyBindings,
+    palette_open: bool,
 }
 
-impl<'a, 'b, 'c> Stage<'a, 'b, 'c> {
+impl<'a> Stage<'a> {
     pub fn new(
         frame: ImageHandle,
-        stage_state: &'a StageState<'b, 'c>,
+        stage_state: &'a StageState,
         edit_state: &'a EditState,
+        chrome_state: &'a ChromeState,
+        viewport_state: &'a ViewportState,
+        key_bindings: &'a KeyBindings,
+        palette_open: bool,
     ) -> Self {
         Self {
             width: stage_state.width().try_into().unwrap(),
@@ -32,11 +75,15 @@ impl<'a, 'b, 'c> Stage<'a, 'b, 'c> {
             frame,
             stage_state,
             edit_state,
+            chrome_state,
+            viewport_state,
+            key_bindings,
+            palette_open,
         }
     }
 }
 
-impl<'a, 'b, 'c, B> Widget<AppMessage, Renderer<B>> for Stage<'a, 'b, 'c>
+impl<'a, B> Widget<AppMessage, Renderer<B>> for Stage<'a>
 where
     B: Backend,
 {
@@ -66,7 +113,11 @@ where
         cursor_position: Point,
     ) -> (Primitive, mouse::Interaction) {
         let cursor = if layout.bounds().contains(cursor_position) {
-            self.edit_state.mouse_cursor()
+            if self.viewport_state.is_panning() {
+                self.viewport_state.mouse_cursor()
+            } else {
+                self.edit_state.mouse_cursor()
+            }
         } else {
             mouse::Interaction::Idle
         };
@@ -89,52 +140,132 @@ where
         _clipboard: Option<&dyn Clipboard>,
     ) {
         let in_bounds = layout.bounds().contains(cursor_position);
-        let stage_position = Vector2F::new(
-            (cursor_position.x - layout.bounds().x) * self.stage_state.scale(),
-            //TODO: Potential BUG: Is the inversion of the Y-axis here at all correct or is it making up for another issue elsewhere
-            (self.height as f32 - (cursor_position.y - layout.bounds().y))
-                * self.stage_state.scale(),
+        let view_position = cursor_to_view(
+            Vector2F::new(
+                cursor_position.x - layout.bounds().x,
+                cursor_position.y - layout.bounds().y,
+            ),
+            self.height as f32,
+            self.stage_state.scale(),
         );
+        let stage_position = view_to_stage(view_position, self.stage_state.engine());
         match event {
             Event::Mouse(mouse_event) => {
+                let viewport_message =
+                    self.viewport_state
+                        .on_mouse_event(mouse_event, view_position, in_bounds);
+                if let Some(viewport_message) = viewport_message {
+                    messages.push(AppMessage::ViewportMessage(viewport_message));
+                    return;
+                }
+                // Holding space reserves the mouse for panning
+                if self.viewport_state.is_panning() {
+                    return;
+                }
+                // A guide being dragged out of a ruler or across the stage takes over the mouse
+                if self.chrome_state.is_dragging_guide() {
+                    match mouse_event {
+                        mouse::Event::CursorMoved { .. } => messages.push(
+                            AppMessage::ChromeMessage(ChromeMessage::MoveGuide(if in_bounds {
+                                Some(stage_position)
+                            } else {
+                                None
+                            })),
+                        ),
+                        mouse::Event::ButtonReleased(mouse::Button::Left) => {
+                            messages.push(AppMessage::ChromeMessage(ChromeMessage::DropGuide))
+                        }
+                        _ => (),
+                    }
+                    return;
+                }
                 let selection_shape = self.edit_state.selection_shape(stage_position);
                 let selection = self.stage_state.query_selection(&selection_shape);
                 messages.push(AppMessage::EditHandleMessage(selection.clone()));
+                if let (
+                    mouse::Event::ButtonPressed(mouse::Button::Left),
+                    SelectionShape::Point(..),
+                    true,
+                ) = (mouse_event, selection_shape, in_bounds)
+                {
+                    if selection.is_empty() {
+                        if let Some(guide_index) = self.chrome_state.guide_at(stage_position) {
+                            messages.push(AppMessage::ChromeMessage(ChromeMessage::PickUpGuide(
+                                guide_index,
+                            )));
+                            return;
+                        }
+                    }
+                    let clicked = selection
+                        .first()
+                        .map(|selection_handle| *selection_handle.container_id());
+                    messages.push(AppMessage::OutlinerMessage(OutlinerMessage::Select(
+                        clicked,
+                    )));
+                    messages.push(AppMessage::SymbolMessage(SymbolMessage::Click(clicked)));
+                }
                 if let Some(edit_message) = self.edit_state.on_mouse_event(
                     mouse_event,
                     selection,
-                    stage_position,
+                    self.chrome_state.snap(stage_position),
                     in_bounds,
                 ) {
                     messages.push(AppMessage::EditMessage(edit_message))
                 }
             }
             Event::Keyboard(keyboard_event) => {
-                //match keyboard_event {}
-                // TODO: oh fuck hot keys
+                // The palette's search box has the keyboard while it's open
+                if self.palette_open {
+                    if let keyboard::Event::KeyPressed {
+                        key_code: keyboard::KeyCode::Escape,
+                        ..
+                    } = keyboard_event
+                    {
+                        messages.push(AppMessage::CommandMessage(CommandMessage::ClosePalette));
+                    }
+                    return;
+                }
+                if let Some(viewport_message) =
+                    self.viewport_state.on_keyboard_event(keyboard_event)
+                {
+                    messages.push(AppMessage::ViewportMessage(viewport_message));
+                }
+                if let keyboard::Event::KeyPressed {
+                    key_code,
+                    modifiers,
+                } = keyboard_event
+                {
+                    if let Some(command) = self
+                        .key_bindings
+                        .command_for(&KeyChord::from_event(key_code, modifiers))
+                    {
+                        messages.push(AppMessage::CommandMessage(CommandMessage::Run(command)));
+                    }
+                }
                 // TODO: modifiers for clicks!!!!!! :(
                 // delete, copy, paste, cut, uh...
             }
+            Event::Window(window::Event::FileDropped(path)) => {
+                messages.push(AppMessage::DocumentMessage(DocumentMessage::Import(path)))
+            }
             _ => (),
         }
     }
 }
 
-impl<'a, 'b, 'c> Into<Element<'a, AppMessage>> for Stage<'a, 'b, 'c> {
+impl<'a> Into<Element<'a, AppMessage>> for Stage<'a> {
     fn into(self) -> Element<'a, AppMessage> {
         Element::new(self)
     }
 }
 
+// Tool buttons per row of the tool pane
+const TOOL_PANE_COLUMNS: usize = 2;
+
 #[derive(Default)]
 pub struct ToolPaneState {
-    pointer_state: ButtonState,
-    path_state: ButtonState,
-    rect_state: ButtonState,
-    polygon_state: ButtonState,
-    ellipse_state: ButtonState,
-    fill_state: ButtonState,
-    eyedropper_state: ButtonState,
+    // One per registered tool, in registry order
+    button_states: Vec<ButtonState>,
 }
 
 pub struct AppFlags {
@@ -157,99 +288,201 @@ impl Default for AppFlags {
     }
 }
 
-pub struct App<'a, 'b> {
-    stage_state: StageState<'a, 'b>,
+pub struct App {
+    documents: DocumentManager,
+    document_tabs_state: DocumentTabsState,
     stage_renderer: StageRenderer,
     edit_state: EditState,
     edit_display_state: EditDisplayState,
-    timeline_state: TimelineState,
     frame_handle: ImageHandle,
     tool_pane_state: ToolPaneState,
+    chrome_display_state: ChromeDisplayState,
+    anchor_pane_state: AnchorPaneState,
+    bake_state: ButtonState,
+    morph_state: ButtonState,
+    symbol_state: ButtonState,
+    timeline_pane_state: TimelinePaneState,
+    // When the stage was last clicked, and what was clicked, to spot double clicks
+    last_stage_click: Option<(Instant, Option<ContainerId>)>,
+    viewport_state: ViewportState,
+    key_bindings: KeyBindings,
+    command_palette_state: CommandPaletteState,
+    asset_watcher: AssetWatcher,
 }
 
-impl<'a, 'b> App<'a, 'b> {
+impl App {
+    fn command_message(&self, command: EditorCommand) -> AppMessage {
+        let Document {
+            stage_state,
+            timeline_state,
+            chrome_state,
+            ..
+        } = self.documents.active();
+        let view_center = stage_state.stage_size() * 0.5;
+        match command {
+            EditorCommand::SwitchTool(tool_id) => {
+                AppMessage::EditMessage(EditMessage::ToolChange(tool_id))
+            }
+            EditorCommand::Cancel => AppMessage::EditMessage(EditMessage::Cancel),
+            EditorCommand::ZoomIn => AppMessage::ViewportMessage(ViewportMessage::Zoom {
+                view_position: view_center,
+                factor: COMMAND_ZOOM_STEP,
+            }),
+            EditorCommand::ZoomOut => AppMessage::ViewportMessage(ViewportMessage::Zoom {
+                view_position: view_center,
+                factor: 1.0 / COMMAND_ZOOM_STEP,
+            }),
+            EditorCommand::ActualSize => AppMessage::ViewportMessage(ViewportMessage::ActualSize),
+            EditorCommand::FitToStage => AppMessage::ViewportMessage(ViewportMessage::FitToStage),
+            EditorCommand::ToggleGrid => {
+                AppMessage::ChromeMessage(ChromeMessage::ShowGrid(!chrome_state.show_grid()))
+            }
+            EditorCommand::ToggleSnapToGrid => {
+                AppMessage::ChromeMessage(ChromeMessage::SnapToGrid(!chrome_state.snap_to_grid()))
+            }
+            EditorCommand::ToggleSnapToGuides => AppMessage::ChromeMessage(
+                ChromeMessage::SnapToGuides(!chrome_state.snap_to_guides()),
+            ),
+            // Handled in update, before it gets here
+            EditorCommand::OpenCommandPalette => AppMessage::StageUpdateMessage,
+            EditorCommand::NewDocument => AppMessage::DocumentMessage(DocumentMessage::New),
+            EditorCommand::CloseDocument => {
+                AppMessage::DocumentMessage(DocumentMessage::Close(self.documents.active_index()))
+            }
+            // TODO: pick where to export to. Until then it goes in the working directory
+            EditorCommand::ExportDocument => AppMessage::DocumentMessage(DocumentMessage::Export(
+                PathBuf::from(format!("{}.fluster", self.documents.active().name)),
+            )),
+            EditorCommand::Undo => AppMessage::DocumentMessage(DocumentMessage::Undo),
+            EditorCommand::Redo => AppMessage::DocumentMessage(DocumentMessage::Redo),
+            EditorCommand::Copy => AppMessage::DocumentMessage(DocumentMessage::Copy),
+            EditorCommand::Paste => AppMessage::DocumentMessage(DocumentMessage::Paste),
+            EditorCommand::PreviousFrame => {
+                AppMessage::TimelineMessage(TimelineMessage::PreviousFrame)
+            }
+            EditorCommand::NextFrame => AppMessage::TimelineMessage(TimelineMessage::NextFrame),
+            EditorCommand::InsertBlankKeyframe => {
+                AppMessage::TimelineMessage(TimelineMessage::InsertBlankKeyframe)
+            }
+            EditorCommand::ToggleAutoAdvance => AppMessage::TimelineMessage(
+                TimelineMessage::AutoAdvance(!timeline_state.auto_advance()),
+            ),
+        }
+    }
+
     fn refresh_stage(&mut self) {
+        let Document {
+            stage_state,
+            timeline_state,
+            outliner_state,
+            chrome_state,
+            ..
+        } = self.documents.active_mut();
+        outliner_state.sync(stage_state.engine(), &stage_state.editor_containers());
+        stage_state.draw_selection(outliner_state.selected());
+        let mut hidden = outliner_state.hidden_containers();
+        hidden.extend(timeline_state.hidden_containers());
+        // The open symbol's parts draw in place of the instance it was opened from
+        hidden.extend(stage_state.open_symbol_instance().copied());
+        stage_state.set_hidden(hidden.clone());
         let frame_handle = self.stage_renderer.draw_frame(
-            self.stage_state.background_color(),
-            self.stage_state.engine(),
+            stage_state.background_color(),
+            stage_state.engine(),
+            &hidden,
+            stage_state.symbol_focus().as_ref(),
+            chrome_state.overlay(stage_state.stage_size()),
         );
         self.frame_handle = frame_handle.unwrap();
     }
 
-    fn tool_pane(tool_pane_state: &mut ToolPaneState) -> Column<AppMessage> {
-        fn button_factory(button_state: &mut ButtonState, tool: Tool) -> Button<AppMessage> {
-            Button::new(button_state, Image::new(tool.image_handle()))
-                .on_press(AppMessage::EditMessage(tool.change_message()))
-                .width(Length::Fill)
+    fn tool_pane<'a>(
+        tool_pane_state: &'a mut ToolPaneState,
+        tools: &ToolRegistry,
+    ) -> Column<'a, AppMessage> {
+        tool_pane_state
+            .button_states
+            .resize_with(tools.len(), ButtonState::default);
+        let buttons = tools
+            .iter()
+            .zip(tool_pane_state.button_states.iter_mut())
+            .map(|((tool_id, tool), button_state)| {
+                Button::new(button_state, Image::new(tool.image_handle()))
+                    .on_press(AppMessage::EditMessage(EditMessage::ToolChange(tool_id)))
+                    .width(Length::Fill)
+            })
+            .collect::<Vec<_>>();
+        let mut column = Column::new().padding(20).spacing(3);
+        let mut row = Row::new().spacing(3).align_items(Align::Center);
+        for (index, button) in buttons.into_iter().enumerate() {
+            row = row.push(button);
+            if index % TOOL_PANE_COLUMNS == TOOL_PANE_COLUMNS - 1 {
+                column = column.push(row);
+                row = Row::new().spacing(3).align_items(Align::Center);
+            }
         }
-
-        Column::new()
-            .padding(20)
-            .spacing(3)
-            .push(
-                Row::new()
-                    .spacing(3)
-                    .align_items(Align::Center)
-                    .push(button_factory(
-                        &mut tool_pane_state.pointer_state,
-                        Tool::Pointer,
-                    ))
-                    .push(button_factory(&mut tool_pane_state.path_state, Tool::Path)),
-            )
-            .push(
-                Row::new()
-                    .spacing(3)
-                    .align_items(Align::Center)
-                    .push(button_factory(&mut tool_pane_state.rect_state, Tool::Rect))
-                    .push(button_factory(
-                        &mut tool_pane_state.polygon_state,
-                        Tool::Polygon,
-                    )),
-            )
-            .push(
-                Row::new()
-                    .spacing(3)
-                    .align_items(Align::Center)
-                    .push(button_factory(
-                        &mut tool_pane_state.ellipse_state,
-                        Tool::Ellipse,
-                    ))
-                    .push(button_factory(&mut tool_pane_state.fill_state, Tool::Fill)),
-            )
-            .push(
-                Row::new()
-                    .spacing(3)
-                    .align_items(Align::Center)
-                    .push(button_factory(
-                        &mut tool_pane_state.eyedropper_state,
-                        Tool::Eyedropper,
-                    )),
-            )
+        if tools.len() % TOOL_PANE_COLUMNS != 0 {
+            column = column.push(row);
+        }
+        column
     }
 }
 
-impl<'a, 'b> Application for App<'a, 'b> {
+impl Application for App {
     type Executor = executor::Default;
     type Message = AppMessage;
     type Flags = AppFlags;
 
     // TODO: saving/loading/new
     fn new(flags: Self::Flags) -> (Self, Command<Self::Message>) {
-        let stage_state = StageState::new(flags.stage_size, flags.background_color);
+        let documents = DocumentManager::new(flags.stage_size, flags.background_color);
         let mut stage_renderer = StageRenderer::new(flags.stage_size).unwrap();
-        let timeline_state = TimelineState::new(stage_state.root());
+        let edit_state = EditState::default();
+        let key_bindings_path = key_bindings_path();
+        let key_bindings = if key_bindings_path.exists() {
+            KeyBindings::load(&key_bindings_path, edit_state.tools()).unwrap_or_else(|error| {
+                println!("{:}", error);
+                KeyBindings::defaults(edit_state.tools())
+            })
+        } else {
+            // Write out the defaults, so there's a file to edit
+            let key_bindings = KeyBindings::defaults(edit_state.tools());
+            if let Err(error) = key_bindings.save(&key_bindings_path, edit_state.tools()) {
+                println!("{:}", error);
+            }
+            key_bindings
+        };
+        let document = documents.active();
         let frame_handle = stage_renderer
-            .draw_frame(stage_state.background_color(), stage_state.engine())
+            .draw_frame(
+                document.stage_state.background_color(),
+                document.stage_state.engine(),
+                &document.outliner_state.hidden_containers(),
+                None,
+                document
+                    .chrome_state
+                    .overlay(document.stage_state.stage_size()),
+            )
             .unwrap();
         (
             Self {
-                stage_state,
+                documents,
+                document_tabs_state: DocumentTabsState::default(),
                 stage_renderer,
-                edit_state: EditState::default(),
+                edit_state,
                 edit_display_state: EditDisplayState::default(),
-                timeline_state,
                 frame_handle,
                 tool_pane_state: ToolPaneState::default(),
+                chrome_display_state: ChromeDisplayState::default(),
+                anchor_pane_state: AnchorPaneState::default(),
+                bake_state: ButtonState::default(),
+                morph_state: ButtonState::default(),
+                symbol_state: ButtonState::default(),
+                timeline_pane_state: TimelinePaneState::default(),
+                last_stage_click: None,
+                viewport_state: ViewportState::default(),
+                key_bindings,
+                command_palette_state: CommandPaletteState::default(),
+                asset_watcher: AssetWatcher::default(),
             },
             Command::none(),
         )
@@ -260,35 +493,331 @@ impl<'a, 'b> Application for App<'a, 'b> {
     }
 
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+        let edits_document = message.edits_document();
+        let Document {
+            stage_state,
+            timeline_state,
+            outliner_state,
+            chrome_state,
+            ..
+        } = self.documents.active_mut();
         let refresh_stage = match message {
             Self::Message::EditMessage(edit_message) => {
                 self.edit_state.update(&edit_message);
-                self.stage_state.apply_edit(&edit_message)
+                let refresh = stage_state.apply_edit(&edit_message);
+                for container_id in stage_state.take_completed_shapes() {
+                    timeline_state.add_drawn_shape(&container_id);
+                }
+                refresh
+            }
+            Self::Message::TimelineMessage(timeline_message) => match timeline_message {
+                TimelineMessage::PreviousFrame => {
+                    timeline_state.previous_frame();
+                    true
+                }
+                TimelineMessage::NextFrame => {
+                    timeline_state.next_frame();
+                    true
+                }
+                TimelineMessage::InsertBlankKeyframe => {
+                    match timeline_state.insert_blank_keyframe() {
+                        Ok(()) => true,
+                        Err(error) => {
+                            println!("{:}", error);
+                            false
+                        }
+                    }
+                }
+                TimelineMessage::AutoAdvance(auto_advance) => {
+                    timeline_state.set_auto_advance(auto_advance);
+                    false
+                }
+            },
+            Self::Message::EditHandleMessage(handles) => stage_state.draw_handles(handles),
+            Self::Message::OutlinerMessage(outliner_message) => {
+                let refresh = outliner_state.update(&outliner_message);
+                if let OutlinerMessage::Reparent {
+                    container_id,
+                    new_parent,
+                } = outliner_message
+                {
+                    match stage_state.reparent(&container_id, &new_parent) {
+                        Ok(()) => true,
+                        Err(error) => {
+                            println!("{:}", error);
+                            refresh
+                        }
+                    }
+                } else {
+                    refresh
+                }
+            }
+            Self::Message::ChromeMessage(ChromeMessage::DebugFlags(debug_flags)) => {
+                stage_state.set_debug_flags(debug_flags);
+                true
+            }
+            Self::Message::ChromeMessage(chrome_message) => chrome_state.update(&chrome_message),
+            Self::Message::ViewportMessage(viewport_message) => {
+                let mut camera = stage_state.camera();
+                let refresh = self.viewport_state.update(
+                    &viewport_message,
+                    &mut camera,
+                    stage_state.stage_size(),
+                );
+                stage_state.set_camera(camera);
+                refresh
+            }
+            Self::Message::AnchorMessage(AnchorMessage::Set(container_id, preset)) => {
+                match stage_state.set_anchor(&container_id, preset) {
+                    Ok(()) => true,
+                    Err(error) => {
+                        println!("{:}", error);
+                        false
+                    }
+                }
+            }
+            Self::Message::BakeMessage(container_id) => {
+                match stage_state.bake_to_sprite(&container_id, &mut self.stage_renderer) {
+                    Ok(()) => true,
+                    Err(error) => {
+                        println!("{:}", error);
+                        false
+                    }
+                }
+            }
+            Self::Message::MorphMessage(MorphMessage::Start(container_id)) => {
+                match stage_state.start_morph(&container_id) {
+                    Ok(()) => true,
+                    Err(error) => {
+                        println!("{:}", error);
+                        false
+                    }
+                }
+            }
+            Self::Message::MorphMessage(MorphMessage::Finish) => match stage_state.finish_morph() {
+                Ok(container_id) => {
+                    timeline_state.key_morph(&container_id, 0, MORPH_DURATION_FRAMES);
+                    true
+                }
+                Err(error) => {
+                    println!("{:}", error);
+                    false
+                }
+            },
+            Self::Message::SymbolMessage(SymbolMessage::Click(clicked)) => {
+                let now = Instant::now();
+                let is_double_click = match self.last_stage_click.replace((now, clicked)) {
+                    Some((last_time, last_clicked)) => {
+                        last_clicked == clicked
+                            && now.duration_since(last_time) < DOUBLE_CLICK_INTERVAL
+                    }
+                    None => false,
+                };
+                if !is_double_click {
+                    false
+                } else {
+                    // A third click starts over rather than making another double click
+                    self.last_stage_click = None;
+                    // TODO: open symbols nested in the open symbol
+                    let symbol_is_open = stage_state.open_symbol_instance().is_some();
+                    let result = match (clicked, symbol_is_open) {
+                        (Some(container_id), false) => {
+                            stage_state.open_symbol(&container_id).map(|_| None)
+                        }
+                        (None, true) => stage_state.close_symbol().map(Some),
+                        _ => Ok(None),
+                    };
+                    match result {
+                        Ok(selected) => {
+                            outliner_state.update(&OutlinerMessage::Select(selected));
+                            true
+                        }
+                        Err(error) => {
+                            println!("{:}", error);
+                            false
+                        }
+                    }
+                }
+            }
+            Self::Message::SymbolMessage(SymbolMessage::Close) => {
+                match stage_state.close_symbol() {
+                    Ok(instance) => {
+                        outliner_state.update(&OutlinerMessage::Select(Some(instance)));
+                        true
+                    }
+                    Err(error) => {
+                        println!("{:}", error);
+                        false
+                    }
+                }
+            }
+            Self::Message::CommandMessage(CommandMessage::Run(
+                EditorCommand::OpenCommandPalette,
+            )) => {
+                self.command_palette_state.open();
+                false
+            }
+            Self::Message::CommandMessage(CommandMessage::Run(command)) => {
+                self.command_palette_state.close();
+                let message = self.command_message(command);
+                return self.update(message);
+            }
+            Self::Message::CommandMessage(CommandMessage::RunFirstMatch) => {
+                self.command_palette_state.close();
+                match self
+                    .command_palette_state
+                    .matches(self.edit_state.tools())
+                    .first()
+                {
+                    Some(command) => {
+                        let message = self.command_message(*command);
+                        return self.update(message);
+                    }
+                    None => false,
+                }
+            }
+            Self::Message::CommandMessage(CommandMessage::Query(query)) => {
+                self.command_palette_state.set_query(query);
+                false
+            }
+            Self::Message::CommandMessage(CommandMessage::ClosePalette) => {
+                self.command_palette_state.close();
+                false
+            }
+            Self::Message::DocumentMessage(DocumentMessage::Import(path))
+                if self.asset_watcher.can_import(&path) =>
+            {
+                match self
+                    .asset_watcher
+                    .import(&path)
+                    .and_then(|asset| stage_state.import_asset(&path, asset))
+                {
+                    Ok(container_id) => {
+                        outliner_state.sync(stage_state.engine(), &stage_state.editor_containers());
+                        outliner_state.update(&OutlinerMessage::Select(Some(container_id)));
+                        true
+                    }
+                    Err(error) => {
+                        println!("{:}", error);
+                        false
+                    }
+                }
+            }
+            Self::Message::DocumentMessage(document_message) => {
+                match self.documents.update(&document_message) {
+                    Ok(refresh) => refresh,
+                    Err(error) => {
+                        println!("{:}", error);
+                        false
+                    }
+                }
             }
-            Self::Message::EditHandleMessage(handles) => self.stage_state.draw_handles(handles),
             Self::Message::StageUpdateMessage => true,
+            Self::Message::PollAssets => self.asset_watcher.poll(self.documents.stage_states_mut()),
         };
+        // Messages that fail don't refresh, and shouldn't leave an undo step either
+        if edits_document && refresh_stage {
+            self.documents.active_mut().record_history();
+        }
         if refresh_stage {
             self.refresh_stage();
         }
         Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Self::Message> {
+        time::every(POLL_INTERVAL).map(|_| AppMessage::PollAssets)
+    }
+
     fn view(&mut self) -> Element<Self::Message> {
+        let tab_bar = self.document_tabs_state.tab_bar(&self.documents);
+        let Document {
+            stage_state,
+            timeline_state,
+            outliner_state,
+            chrome_state,
+            ..
+        } = self.documents.active_mut();
         let stage = Stage::new(
             self.frame_handle.clone(),
-            &self.stage_state,
+            stage_state,
             &self.edit_state,
+            chrome_state,
+            &self.viewport_state,
+            &self.key_bindings,
+            self.command_palette_state.is_open(),
         );
-        let tools = Self::tool_pane(&mut self.tool_pane_state);
+        let stage_area = Column::new()
+            .push(
+                Row::new()
+                    .push(Space::with_width(Length::Units(RULER_SIZE)))
+                    .push(Ruler::new(GuideOrientation::Horizontal, stage_state)),
+            )
+            .push(
+                Row::new()
+                    .push(Ruler::new(GuideOrientation::Vertical, stage_state))
+                    .push(stage),
+            );
+        let mut side_pane = Column::new();
+        if self.command_palette_state.is_open() {
+            side_pane = side_pane.push(
+                self.command_palette_state
+                    .palette(self.edit_state.tools(), &self.key_bindings),
+            );
+        }
+        let tools = Self::tool_pane(&mut self.tool_pane_state, self.edit_state.tools());
         let options_pane = self.edit_display_state.options_pane(&self.edit_state);
+        let chrome_pane = self
+            .chrome_display_state
+            .chrome_pane(chrome_state, stage_state.debug_flags());
+        let selected = outliner_state.selected().copied();
+        let anchor_pane = self.anchor_pane_state.anchor_pane(
+            selected,
+            selected.and_then(|container_id| stage_state.engine().get_anchor(&container_id)),
+        );
+        let mut bake = Button::new(&mut self.bake_state, Text::new("Bake to Sprite").size(14));
+        if let Some(container_id) = selected {
+            bake = bake.on_press(AppMessage::BakeMessage(container_id));
+        }
+        let morph = if stage_state.is_making_morph() {
+            Button::new(&mut self.morph_state, Text::new("Finish Morph").size(14))
+                .on_press(AppMessage::MorphMessage(MorphMessage::Finish))
+        } else {
+            let morph = Button::new(&mut self.morph_state, Text::new("Make Morph").size(14));
+            match selected {
+                Some(container_id) => {
+                    morph.on_press(AppMessage::MorphMessage(MorphMessage::Start(container_id)))
+                }
+                None => morph,
+            }
+        };
+        if stage_state.open_symbol_instance().is_some() {
+            side_pane = side_pane.push(
+                Container::new(
+                    Button::new(&mut self.symbol_state, Text::new("Close Symbol").size(14))
+                        .on_press(AppMessage::SymbolMessage(SymbolMessage::Close)),
+                )
+                .padding(20),
+            );
+        }
+        let outliner = outliner_state.view();
         let content = Row::new()
             .padding(20)
             .spacing(20)
             .align_items(Align::Center)
-            .push(stage)
-            .push(Column::new().push(tools).push(options_pane));
-        Container::new(content)
+            .push(outliner)
+            .push(stage_area)
+            .push(
+                side_pane
+                    .push(tools)
+                    .push(options_pane)
+                    .push(chrome_pane)
+                    .push(anchor_pane)
+                    .push(self.timeline_pane_state.timeline_pane(timeline_state))
+                    .push(Container::new(bake).padding(20))
+                    .push(Container::new(morph).padding(20)),
+            );
+        Container::new(Column::new().spacing(10).push(tab_bar).push(content))
             .width(Length::Fill)
             .height(Length::Fill)
             .center_x()
@@ -0,0 +1,300 @@
+use crate::simulation::StageState;
+use fluster_core::types::{
+    basic::Bitmap,
+    shapes::{AugmentedShape, Contour, Edge, Shape},
+};
+use palette::LinSrgba;
+use pathfinder_color::ColorU;
+use pathfinder_content::{
+    pattern::Pattern,
+    stroke::{LineCap, LineJoin, StrokeStyle},
+};
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use usvg::NodeExt;
+
+/// How often source files are checked for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What an importer read out of a source file.
+#[derive(Clone, Debug)]
+pub enum ImportedAsset {
+    Shape(Shape),
+    Texture(Pattern),
+}
+
+/// Reads one kind of source file into a library item.
+pub trait AssetImporter {
+    /// Lowercase file extensions, without the dot.
+    fn extensions(&self) -> &[&str];
+
+    fn import(&self, path: &Path) -> Result<ImportedAsset, String>;
+}
+
+/// Reads 8 bit RGB and RGBA PNGs into textures.
+pub struct PngImporter;
+
+impl AssetImporter for PngImporter {
+    fn extensions(&self) -> &[&str] {
+        &["png"]
+    }
+
+    fn import(&self, path: &Path) -> Result<ImportedAsset, String> {
+        let file =
+            File::open(path).map_err(|error| format!("Could not open {:?}: {}", path, error))?;
+        let (info, mut reader) = png::Decoder::new(file)
+            .read_info()
+            .map_err(|error| format!("Could not read {:?}: {}", path, error))?;
+        let mut pixels = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut pixels)
+            .map_err(|error| format!("Could not read {:?}: {}", path, error))?;
+        let colors = match (info.color_type, info.bit_depth) {
+            (png::ColorType::RGBA, png::BitDepth::Eight) => pixels
+                .chunks_exact(4)
+                .map(|pixel| ColorU::new(pixel[0], pixel[1], pixel[2], pixel[3]))
+                .collect(),
+            (png::ColorType::RGB, png::BitDepth::Eight) => pixels
+                .chunks_exact(3)
+                .map(|pixel| ColorU::new(pixel[0], pixel[1], pixel[2], 255))
+                .collect(),
+            (color_type, bit_depth) => {
+                return Err(format!(
+                    "Unsupported PNG format {:?} {:?} in {:?}",
+                    color_type, bit_depth, path
+                ))
+            }
+        };
+        let mut bitmap = Bitmap {
+            size_x: info.width as i32,
+            size_y: info.height as i32,
+            colors: Arc::new(colors),
+        };
+        Ok(ImportedAsset::Texture(bitmap.pattern()))
+    }
+}
+
+/// Reads the filled and stroked paths of an SVG into a group shape, one child per fill or stroke.
+// TODO: gradients, patterns and dashes. Paths painted with them are skipped
+pub struct SvgImporter;
+
+impl AssetImporter for SvgImporter {
+    fn extensions(&self) -> &[&str] {
+        &["svg"]
+    }
+
+    fn import(&self, path: &Path) -> Result<ImportedAsset, String> {
+        let tree = usvg::Tree::from_file(path, &usvg::Options::default())
+            .map_err(|error| format!("Could not read {:?}: {}", path, error))?;
+        let mut shapes = vec![];
+        for node in tree.root().descendants() {
+            if let usvg::NodeKind::Path(ref svg_path) = *node.borrow() {
+                let transform = svg_transform(&node.abs_transform());
+                let contours = Contour::from_edges(&svg_edges(&svg_path.data));
+                if let Some(usvg::Fill {
+                    paint: usvg::Paint::Color(color),
+                    opacity,
+                    ..
+                }) = svg_path.fill
+                {
+                    shapes.push(AugmentedShape {
+                        shape: Shape::Fill {
+                            contours: contours.clone(),
+                            color: svg_color(color, opacity),
+                        },
+                        transform,
+                    });
+                }
+                if let Some(usvg::Stroke {
+                    paint: usvg::Paint::Color(color),
+                    opacity,
+                    width,
+                    linecap,
+                    linejoin,
+                    miterlimit,
+                    ..
+                }) = svg_path.stroke
+                {
+                    let line_join = match linejoin {
+                        usvg::LineJoin::Miter => LineJoin::Miter(miterlimit.value() as f32),
+                        usvg::LineJoin::Round => LineJoin::Round,
+                        usvg::LineJoin::Bevel => LineJoin::Bevel,
+                    };
+                    let line_cap = match linecap {
+                        usvg::LineCap::Butt => LineCap::Butt,
+                        usvg::LineCap::Round => LineCap::Round,
+                        usvg::LineCap::Square => LineCap::Square,
+                    };
+                    shapes.push(AugmentedShape {
+                        shape: Shape::Path {
+                            contours,
+                            color: svg_color(color, opacity),
+                            stroke_style: StrokeStyle {
+                                line_width: width.value() as f32,
+                                line_cap,
+                                line_join,
+                            },
+                        },
+                        transform,
+                    });
+                }
+            }
+        }
+        if shapes.is_empty() {
+            return Err(format!("No filled or stroked paths in {:?}", path));
+        }
+        Ok(ImportedAsset::Shape(Shape::Group { shapes }))
+    }
+}
+
+fn svg_edges(data: &usvg::PathData) -> Vec<Edge> {
+    let point = |x: f64, y: f64| Vector2F::new(x as f32, y as f32);
+    data.iter()
+        .map(|segment| match *segment {
+            usvg::PathSegment::MoveTo { x, y } => Edge::Move(point(x, y)),
+            usvg::PathSegment::LineTo { x, y } => Edge::Line(point(x, y)),
+            usvg::PathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => Edge::Bezier {
+                control_1: point(x1, y1),
+                control_2: point(x2, y2),
+                to: point(x, y),
+            },
+            usvg::PathSegment::ClosePath => Edge::Close,
+        })
+        .collect()
+}
+
+fn svg_transform(transform: &usvg::Transform) -> Transform2F {
+    Transform2F::row_major(
+        transform.a as f32,
+        transform.c as f32,
+        transform.e as f32,
+        transform.b as f32,
+        transform.d as f32,
+        transform.f as f32,
+    )
+}
+
+fn svg_color(color: usvg::Color, opacity: usvg::Opacity) -> LinSrgba {
+    LinSrgba::new(
+        color.red as f32 / 255.0,
+        color.green as f32 / 255.0,
+        color.blue as f32 / 255.0,
+        opacity.value() as f32,
+    )
+}
+
+/// Watches the files library items were imported from, `LibraryItemMetadata::origin`, and
+/// re-imports the items when their files change. Every open document's items are reloaded.
+/// `poll` is driven by the application's timer subscription.
+// TODO: OS file notifications rather than polling
+pub struct AssetWatcher {
+    importers: Vec<Box<dyn AssetImporter>>,
+    modified: HashMap<PathBuf, SystemTime>,
+}
+
+impl Default for AssetWatcher {
+    fn default() -> Self {
+        let mut asset_watcher = Self {
+            importers: vec![],
+            modified: HashMap::new(),
+        };
+        asset_watcher.register(Box::new(SvgImporter));
+        asset_watcher.register(Box::new(PngImporter));
+        asset_watcher
+    }
+}
+
+impl AssetWatcher {
+    pub fn register(&mut self, importer: Box<dyn AssetImporter>) {
+        self.importers.push(importer);
+    }
+
+    /// True if a registered importer reads files with the path's extension.
+    pub fn can_import(&self, path: &Path) -> bool {
+        self.importer_for(path).is_some()
+    }
+
+    pub fn import(&self, path: &Path) -> Result<ImportedAsset, String> {
+        self.importer_for(path)
+            .ok_or_else(|| format!("No importer for {:?}", path))?
+            .import(path)
+    }
+
+    fn importer_for(&self, path: &Path) -> Option<&dyn AssetImporter> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        self.importers
+            .iter()
+            .find(|importer| importer.extensions().contains(&extension.as_str()))
+            .map(|importer| importer.as_ref())
+    }
+
+    /// Re-imports items whose source files changed since the last poll. Returns true if anything
+    /// was replaced.
+    pub fn poll<'a>(&mut self, stage_states: impl Iterator<Item = &'a mut StageState>) -> bool {
+        let mut stage_states = stage_states.collect::<Vec<_>>();
+        let origins = stage_states
+            .iter()
+            .map(|stage_state| stage_state.library_origins())
+            .collect::<Vec<_>>();
+        let changed = origins
+            .iter()
+            .flatten()
+            .map(|(_, path)| path)
+            .filter(|path| self.importer_for(path).is_some())
+            .cloned()
+            .collect::<HashSet<PathBuf>>()
+            .into_iter()
+            .filter(|path| {
+                let modified = match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+                    Ok(modified) => modified,
+                    // Missing files keep the last version that was imported
+                    Err(..) => return false,
+                };
+                // Files seen for the first time were just imported, so there's nothing to reload
+                match self.modified.insert(path.clone(), modified) {
+                    Some(last_modified) => last_modified != modified,
+                    None => false,
+                }
+            })
+            .collect::<HashSet<PathBuf>>();
+        if changed.is_empty() {
+            return false;
+        }
+        let mut imported = HashMap::new();
+        for path in changed.iter() {
+            match self
+                .importer_for(path)
+                .map(|importer| importer.import(path))
+            {
+                Some(Ok(asset)) => {
+                    imported.insert(path.clone(), asset);
+                }
+                Some(Err(error)) => println!("{:}", error),
+                None => (),
+            }
+        }
+        let mut replaced = false;
+        for (stage_state, origins) in stage_states.iter_mut().zip(origins.into_iter()) {
+            for (id, path) in origins {
+                if let Some(asset) = imported.get(&path) {
+                    match stage_state.replace_library_item(id, asset.clone()) {
+                        Ok(()) => replaced = true,
+                        Err(error) => println!("{:}", error),
+                    }
+                }
+            }
+        }
+        replaced
+    }
+}
@@ -0,0 +1,479 @@
+use crate::messages::{AppMessage, CommandMessage};
+use crate::tools::{ToolId, ToolRegistry};
+use iced::{
+    button::State as ButtonState, keyboard, text_input::State as TextInputState, Button, Column,
+    Length, Row, Text, TextInput,
+};
+use std::fs;
+use std::path::Path;
+
+// Most commands the palette lists at once
+const MAX_PALETTE_RESULTS: usize = 10;
+
+/// Everything the editor can do from a key binding or the command palette.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EditorCommand {
+    SwitchTool(ToolId),
+    Cancel,
+    ZoomIn,
+    ZoomOut,
+    ActualSize,
+    FitToStage,
+    ToggleGrid,
+    ToggleSnapToGrid,
+    ToggleSnapToGuides,
+    OpenCommandPalette,
+    NewDocument,
+    CloseDocument,
+    ExportDocument,
+    Undo,
+    Redo,
+    Copy,
+    Paste,
+    PreviousFrame,
+    NextFrame,
+    InsertBlankKeyframe,
+    ToggleAutoAdvance,
+}
+
+impl EditorCommand {
+    const FIXED: [EditorCommand; 20] = [
+        EditorCommand::Cancel,
+        EditorCommand::ZoomIn,
+        EditorCommand::ZoomOut,
+        EditorCommand::ActualSize,
+        EditorCommand::FitToStage,
+        EditorCommand::ToggleGrid,
+        EditorCommand::ToggleSnapToGrid,
+        EditorCommand::ToggleSnapToGuides,
+        EditorCommand::OpenCommandPalette,
+        EditorCommand::NewDocument,
+        EditorCommand::CloseDocument,
+        EditorCommand::ExportDocument,
+        EditorCommand::Undo,
+        EditorCommand::Redo,
+        EditorCommand::Copy,
+        EditorCommand::Paste,
+        EditorCommand::PreviousFrame,
+        EditorCommand::NextFrame,
+        EditorCommand::InsertBlankKeyframe,
+        EditorCommand::ToggleAutoAdvance,
+    ];
+
+    /// Every command, with a tool switch for each registered tool.
+    pub fn all(tools: &ToolRegistry) -> Vec<EditorCommand> {
+        tools
+            .iter()
+            .map(|(tool_id, _)| EditorCommand::SwitchTool(tool_id))
+            .chain(Self::FIXED.iter().copied())
+            .collect()
+    }
+
+    /// The name the command is saved under in the key bindings file.
+    pub fn id(&self, tools: &ToolRegistry) -> String {
+        match self {
+            EditorCommand::SwitchTool(tool_id) => format!(
+                "tool.{}",
+                tools
+                    .get(*tool_id)
+                    .map_or("unknown".to_owned(), |tool| tool.name().to_lowercase())
+            ),
+            EditorCommand::Cancel => "edit.cancel".to_owned(),
+            EditorCommand::ZoomIn => "view.zoom_in".to_owned(),
+            EditorCommand::ZoomOut => "view.zoom_out".to_owned(),
+            EditorCommand::ActualSize => "view.actual_size".to_owned(),
+            EditorCommand::FitToStage => "view.fit_to_stage".to_owned(),
+            EditorCommand::ToggleGrid => "view.toggle_grid".to_owned(),
+            EditorCommand::ToggleSnapToGrid => "view.toggle_snap_to_grid".to_owned(),
+            EditorCommand::ToggleSnapToGuides => "view.toggle_snap_to_guides".to_owned(),
+            EditorCommand::OpenCommandPalette => "palette.open".to_owned(),
+            EditorCommand::NewDocument => "document.new".to_owned(),
+            EditorCommand::CloseDocument => "document.close".to_owned(),
+            EditorCommand::ExportDocument => "document.export".to_owned(),
+            EditorCommand::Undo => "edit.undo".to_owned(),
+            EditorCommand::Redo => "edit.redo".to_owned(),
+            EditorCommand::Copy => "edit.copy".to_owned(),
+            EditorCommand::Paste => "edit.paste".to_owned(),
+            EditorCommand::PreviousFrame => "timeline.previous_frame".to_owned(),
+            EditorCommand::NextFrame => "timeline.next_frame".to_owned(),
+            EditorCommand::InsertBlankKeyframe => "timeline.insert_blank_keyframe".to_owned(),
+            EditorCommand::ToggleAutoAdvance => "timeline.toggle_auto_advance".to_owned(),
+        }
+    }
+
+    /// What the command palette shows.
+    pub fn label(&self, tools: &ToolRegistry) -> String {
+        match self {
+            EditorCommand::SwitchTool(tool_id) => format!(
+                "Tool: {}",
+                tools.get(*tool_id).map_or("Unknown", |tool| tool.name())
+            ),
+            EditorCommand::Cancel => "Cancel Edit".to_owned(),
+            EditorCommand::ZoomIn => "Zoom In".to_owned(),
+            EditorCommand::ZoomOut => "Zoom Out".to_owned(),
+            EditorCommand::ActualSize => "Actual Size".to_owned(),
+            EditorCommand::FitToStage => "Fit to Stage".to_owned(),
+            EditorCommand::ToggleGrid => "Toggle Grid".to_owned(),
+            EditorCommand::ToggleSnapToGrid => "Toggle Snap to Grid".to_owned(),
+            EditorCommand::ToggleSnapToGuides => "Toggle Snap to Guides".to_owned(),
+            EditorCommand::OpenCommandPalette => "Command Palette".to_owned(),
+            EditorCommand::NewDocument => "New Document".to_owned(),
+            EditorCommand::CloseDocument => "Close Document".to_owned(),
+            EditorCommand::ExportDocument => "Export Document".to_owned(),
+            EditorCommand::Undo => "Undo".to_owned(),
+            EditorCommand::Redo => "Redo".to_owned(),
+            EditorCommand::Copy => "Copy".to_owned(),
+            EditorCommand::Paste => "Paste".to_owned(),
+            EditorCommand::PreviousFrame => "Previous Frame".to_owned(),
+            EditorCommand::NextFrame => "Next Frame".to_owned(),
+            EditorCommand::InsertBlankKeyframe => "Insert Blank Keyframe".to_owned(),
+            EditorCommand::ToggleAutoAdvance => "Toggle Auto Advance".to_owned(),
+        }
+    }
+}
+
+// The keys bindings can use. iced's key codes can't be parsed, so the file format only knows
+// about these.
+const KEYS: [keyboard::KeyCode; 58] = {
+    use keyboard::KeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1,
+        Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10,
+        F11, F12, Escape, Delete, Tab, Equals, Minus, Apostrophe, LBracket, RBracket, Comma,
+        Period,
+    ]
+};
+
+/// A key and the modifiers held with it, written like "Ctrl+Shift+Z".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    key_code: keyboard::KeyCode,
+    control: bool,
+    shift: bool,
+    alt: bool,
+    logo: bool,
+}
+
+impl KeyChord {
+    pub fn new(key_code: keyboard::KeyCode) -> Self {
+        Self {
+            key_code,
+            control: false,
+            shift: false,
+            alt: false,
+            logo: false,
+        }
+    }
+
+    pub fn with_control(mut self) -> Self {
+        self.control = true;
+        self
+    }
+
+    pub fn with_shift(mut self) -> Self {
+        self.shift = true;
+        self
+    }
+
+    pub fn from_event(key_code: keyboard::KeyCode, modifiers: keyboard::ModifiersState) -> Self {
+        Self {
+            key_code,
+            control: modifiers.control,
+            shift: modifiers.shift,
+            alt: modifiers.alt,
+            logo: modifiers.logo,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts = text.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = parts
+            .pop()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| format!("Key binding \"{}\" has no key", text))?;
+        let key_code = KEYS
+            .iter()
+            .copied()
+            .find(|key_code| format!("{:?}", key_code).eq_ignore_ascii_case(key))
+            .ok_or_else(|| format!("Unknown key \"{}\"", key))?;
+        let mut chord = Self::new(key_code);
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.control = true,
+                "shift" => chord.shift = true,
+                "alt" => chord.alt = true,
+                "logo" | "cmd" | "super" => chord.logo = true,
+                _ => return Err(format!("Unknown modifier \"{}\"", modifier)),
+            }
+        }
+        Ok(chord)
+    }
+
+    pub fn format(&self) -> String {
+        let mut text = String::new();
+        for (held, name) in [
+            (self.control, "Ctrl+"),
+            (self.shift, "Shift+"),
+            (self.alt, "Alt+"),
+            (self.logo, "Logo+"),
+        ]
+        .iter()
+        {
+            if *held {
+                text.push_str(name);
+            }
+        }
+        text.push_str(&format!("{:?}", self.key_code));
+        text
+    }
+}
+
+/// Maps key chords to commands. A command can have any number of chords, but a chord runs one
+/// command.
+#[derive(Clone, Debug, Default)]
+pub struct KeyBindings {
+    bindings: Vec<(KeyChord, EditorCommand)>,
+}
+
+impl KeyBindings {
+    pub fn defaults(tools: &ToolRegistry) -> Self {
+        use keyboard::KeyCode;
+        let mut bindings = Self::default();
+        for (tool_id, tool) in tools.iter() {
+            let key_code = match tool.name() {
+                "Pointer" => KeyCode::V,
+                "Path" => KeyCode::P,
+                "Rect" => KeyCode::R,
+                "Polygon" => KeyCode::G,
+                "Ellipse" => KeyCode::E,
+                "Fill" => KeyCode::K,
+                "Eyedropper" => KeyCode::I,
+                "Star" => KeyCode::S,
+                _ => continue,
+            };
+            bindings.bind(KeyChord::new(key_code), EditorCommand::SwitchTool(tool_id));
+        }
+        bindings.bind(KeyChord::new(KeyCode::Escape), EditorCommand::Cancel);
+        bindings.bind(
+            KeyChord::new(KeyCode::Equals).with_control(),
+            EditorCommand::ZoomIn,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::Minus).with_control(),
+            EditorCommand::ZoomOut,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::Key0).with_control(),
+            EditorCommand::ActualSize,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::Key1).with_control(),
+            EditorCommand::FitToStage,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::Apostrophe).with_control(),
+            EditorCommand::ToggleGrid,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::Apostrophe)
+                .with_control()
+                .with_shift(),
+            EditorCommand::ToggleSnapToGrid,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::P).with_control().with_shift(),
+            EditorCommand::OpenCommandPalette,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::N).with_control(),
+            EditorCommand::NewDocument,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::W).with_control(),
+            EditorCommand::CloseDocument,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::E).with_control(),
+            EditorCommand::ExportDocument,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::Z).with_control(),
+            EditorCommand::Undo,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::Z).with_control().with_shift(),
+            EditorCommand::Redo,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::C).with_control(),
+            EditorCommand::Copy,
+        );
+        bindings.bind(
+            KeyChord::new(KeyCode::V).with_control(),
+            EditorCommand::Paste,
+        );
+        bindings.bind(KeyChord::new(KeyCode::Comma), EditorCommand::PreviousFrame);
+        bindings.bind(KeyChord::new(KeyCode::Period), EditorCommand::NextFrame);
+        bindings.bind(
+            KeyChord::new(KeyCode::F7),
+            EditorCommand::InsertBlankKeyframe,
+        );
+        bindings
+    }
+
+    /// Binds `chord` to `command`, replacing whatever `chord` ran before.
+    pub fn bind(&mut self, chord: KeyChord, command: EditorCommand) {
+        self.bindings.retain(|(bound, _)| *bound != chord);
+        self.bindings.push((chord, command));
+    }
+
+    pub fn unbind_command(&mut self, command: EditorCommand) {
+        self.bindings.retain(|(_, bound)| *bound != command);
+    }
+
+    pub fn command_for(&self, chord: &KeyChord) -> Option<EditorCommand> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| bound == chord)
+            .map(|(_, command)| *command)
+    }
+
+    pub fn chords_for(&self, command: EditorCommand) -> Vec<KeyChord> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == command)
+            .map(|(chord, _)| *chord)
+            .collect()
+    }
+
+    /// The defaults, overridden by the bindings file at `path`. Each line of the file is a
+    /// command id, an equals sign, and a comma separated list of chords, which may be empty to
+    /// unbind the command. Commands the file doesn't mention keep their defaults. Lines starting
+    /// with # are ignored.
+    pub fn load(path: &Path, tools: &ToolRegistry) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("{:}", error))?;
+        let mut bindings = Self::defaults(tools);
+        let commands = EditorCommand::all(tools);
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut split = line.splitn(2, '=');
+            let (id, chords) = match (split.next(), split.next()) {
+                (Some(id), Some(chords)) => (id.trim(), chords.trim()),
+                _ => return Err(format!("Line {} is missing an \"=\"", line_number + 1)),
+            };
+            let command = commands
+                .iter()
+                .copied()
+                .find(|command| command.id(tools) == id)
+                .ok_or_else(|| format!("Unknown command \"{}\" on line {}", id, line_number + 1))?;
+            bindings.unbind_command(command);
+            for chord in chords.split(',').filter(|chord| !chord.trim().is_empty()) {
+                bindings.bind(KeyChord::parse(chord)?, command);
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Writes every command's bindings to `path`, in the format `load` reads.
+    pub fn save(&self, path: &Path, tools: &ToolRegistry) -> Result<(), String> {
+        let mut text = "# command = chords, comma separated. Leave empty to unbind.\n".to_owned();
+        for command in EditorCommand::all(tools) {
+            let chords = self
+                .chords_for(command)
+                .iter()
+                .map(KeyChord::format)
+                .collect::<Vec<_>>()
+                .join(", ");
+            text.push_str(&format!("{} = {}\n", command.id(tools), chords));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| format!("{:}", error))?;
+        }
+        fs::write(path, text).map_err(|error| format!("{:}", error))
+    }
+}
+
+/// A searchable list of every command, opened with `EditorCommand::OpenCommandPalette`.
+#[derive(Debug, Default)]
+pub struct CommandPaletteState {
+    open: bool,
+    query: String,
+    query_state: TextInputState,
+    result_states: Vec<ButtonState>,
+}
+
+impl CommandPaletteState {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+        self.query.clear();
+        self.query_state = TextInputState::focused();
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+        self.query_state = TextInputState::default();
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+    }
+
+    /// Commands whose label contains every word of the query, ignoring case.
+    pub fn matches(&self, tools: &ToolRegistry) -> Vec<EditorCommand> {
+        let query = self.query.to_lowercase();
+        EditorCommand::all(tools)
+            .into_iter()
+            .filter(|command| {
+                let label = command.label(tools).to_lowercase();
+                query.split_whitespace().all(|word| label.contains(word))
+            })
+            .take(MAX_PALETTE_RESULTS)
+            .collect()
+    }
+
+    pub fn palette(
+        &mut self,
+        tools: &ToolRegistry,
+        key_bindings: &KeyBindings,
+    ) -> Column<AppMessage> {
+        let matches = self.matches(tools);
+        self.result_states
+            .resize_with(matches.len(), ButtonState::default);
+        let query = TextInput::new(
+            &mut self.query_state,
+            "Type a command",
+            &self.query,
+            |query| AppMessage::CommandMessage(CommandMessage::Query(query)),
+        )
+        .on_submit(AppMessage::CommandMessage(CommandMessage::RunFirstMatch))
+        .width(Length::Fill);
+        matches.iter().zip(self.result_states.iter_mut()).fold(
+            Column::new().padding(20).spacing(3).push(query),
+            |column, (command, state)| {
+                let chords = key_bindings
+                    .chords_for(*command)
+                    .iter()
+                    .map(KeyChord::format)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                column.push(
+                    Button::new(
+                        state,
+                        Row::new()
+                            .push(Text::new(command.label(tools)).size(14).width(Length::Fill))
+                            .push(Text::new(chords).size(14)),
+                    )
+                    .on_press(AppMessage::CommandMessage(CommandMessage::Run(*command)))
+                    .width(Length::Fill),
+                )
+            },
+        )
+    }
+}
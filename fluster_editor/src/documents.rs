@@ -0,0 +1,333 @@
+use crate::messages::{AppMessage, DocumentMessage, OutlinerMessage};
+use crate::outliner::OutlinerState;
+use crate::simulation::{ContainerClipboard, StageState, TimelineState};
+use crate::stage_chrome::ChromeState;
+use fluster_core::linking::FileAssetResolver;
+use fluster_core::serialization::{deserialize_stream, export_stream, ExportOptions};
+use iced::{button::State as ButtonState, Align, Button, Row, Text};
+use palette::LinSrgb;
+use pathfinder_geometry::vector::Vector2I;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+// TODO: a frame rate per document, set from the timeline
+const EXPORT_FRAMES_PER_SECOND: u8 = 60;
+// Undo steps kept per document, oldest dropped first
+const HISTORY_LENGTH: usize = 100;
+
+#[derive(Clone)]
+struct Checkpoint {
+    scene: Vec<ContainerClipboard>,
+    timeline_state: TimelineState,
+}
+
+/// A document's undo and redo steps. Each step is the whole scene, as `StageState::snapshot`
+/// copies it, and the timeline, after an edit. The last undo step is the document as it is now.
+// TODO: keep the components the clipboard doesn't copy yet
+#[derive(Default)]
+struct History {
+    undo: VecDeque<Checkpoint>,
+    redo: Vec<Checkpoint>,
+}
+
+/// One open document. Each document has its own engine, timeline, outliner and guides. Tools and
+/// their options are shared between documents.
+pub struct Document {
+    pub name: String,
+    pub stage_state: StageState,
+    pub timeline_state: TimelineState,
+    pub outliner_state: OutlinerState,
+    pub chrome_state: ChromeState,
+    history: History,
+}
+
+impl Document {
+    pub fn new(name: String, stage_size: Vector2I, background_color: LinSrgb) -> Self {
+        let stage_state = StageState::new(stage_size, background_color);
+        let timeline_state = TimelineState::new(stage_state.root());
+        let mut outliner_state = OutlinerState::default();
+        outliner_state.sync(stage_state.engine(), &stage_state.editor_containers());
+        let mut new_self = Self {
+            name,
+            stage_state,
+            timeline_state,
+            outliner_state,
+            chrome_state: ChromeState::default(),
+            history: History::default(),
+        };
+        new_self.record_history();
+        new_self
+    }
+
+    /// Adds the document as it is now as an undo step, and forgets anything that could be redone.
+    pub fn record_history(&mut self) {
+        // Snapshots can't be taken with a symbol open. Closing it records the edits made in it.
+        let scene = match self.stage_state.snapshot() {
+            Ok(scene) => scene,
+            Err(_) => return,
+        };
+        self.history.undo.push_back(Checkpoint {
+            scene,
+            timeline_state: self.timeline_state.clone(),
+        });
+        if self.history.undo.len() > HISTORY_LENGTH {
+            self.history.undo.pop_front();
+        }
+        self.history.redo.clear();
+    }
+
+    fn undo(&mut self) -> Result<(), String> {
+        // The last step is the document as it is, so there has to be one before it
+        if self.history.undo.len() < 2 {
+            return Err("Nothing to undo".to_owned());
+        }
+        let previous = self.history.undo[self.history.undo.len() - 2].clone();
+        self.restore(&previous)?;
+        if let Some(current) = self.history.undo.pop_back() {
+            self.history.redo.push(current);
+        }
+        Ok(())
+    }
+
+    fn redo(&mut self) -> Result<(), String> {
+        let next = self
+            .history
+            .redo
+            .last()
+            .cloned()
+            .ok_or_else(|| "Nothing to redo".to_owned())?;
+        self.restore(&next)?;
+        if let Some(next) = self.history.redo.pop() {
+            self.history.undo.push_back(next);
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self, checkpoint: &Checkpoint) -> Result<(), String> {
+        self.stage_state.restore(&checkpoint.scene)?;
+        self.timeline_state = checkpoint.timeline_state.clone();
+        self.outliner_state.sync(
+            self.stage_state.engine(),
+            &self.stage_state.editor_containers(),
+        );
+        // The selection may be gone
+        self.outliner_state.update(&OutlinerMessage::Select(None));
+        Ok(())
+    }
+}
+
+/// The open documents, and the clipboard they share. There is always at least one document open.
+pub struct DocumentManager {
+    documents: Vec<Document>,
+    active: usize,
+    clipboard: Option<ContainerClipboard>,
+    // New documents are made with the same stage as the first
+    stage_size: Vector2I,
+    background_color: LinSrgb,
+    // Only used to name new documents, so closing one doesn't reuse its name
+    created: usize,
+}
+
+impl DocumentManager {
+    pub fn new(stage_size: Vector2I, background_color: LinSrgb) -> Self {
+        let mut new_self = Self {
+            documents: vec![],
+            active: 0,
+            clipboard: None,
+            stage_size,
+            background_color,
+            created: 0,
+        };
+        new_self.new_document();
+        new_self
+    }
+
+    pub fn active(&self) -> &Document {
+        &self.documents[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Document {
+        &mut self.documents[self.active]
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn stage_states_mut(&mut self) -> impl Iterator<Item = &mut StageState> {
+        self.documents
+            .iter_mut()
+            .map(|document| &mut document.stage_state)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.documents.iter().map(|document| document.name.as_str())
+    }
+
+    fn new_document(&mut self) {
+        self.created += 1;
+        self.documents.push(Document::new(
+            format!("Untitled {}", self.created),
+            self.stage_size,
+            self.background_color,
+        ));
+        self.active = self.documents.len() - 1;
+    }
+
+    /// Returns true if the stage needs to be redrawn.
+    pub fn update(&mut self, message: &DocumentMessage) -> Result<bool, String> {
+        match message {
+            DocumentMessage::New => {
+                self.new_document();
+                Ok(true)
+            }
+            DocumentMessage::Select(index) => {
+                if *index < self.documents.len() {
+                    self.active = *index;
+                    Ok(true)
+                } else {
+                    Err(format!("No document {}", index))
+                }
+            }
+            DocumentMessage::Close(index) => {
+                if *index >= self.documents.len() {
+                    return Err(format!("No document {}", index));
+                }
+                // TODO: ask to save
+                self.documents.remove(*index);
+                if self.documents.is_empty() {
+                    self.new_document();
+                } else if self.active >= *index && self.active > 0 {
+                    self.active -= 1;
+                }
+                Ok(true)
+            }
+            DocumentMessage::Copy => {
+                let document = self.active();
+                let selected = document
+                    .outliner_state
+                    .selected()
+                    .ok_or_else(|| "Nothing selected to copy".to_owned())?;
+                let clipboard = document.stage_state.copy(selected)?;
+                self.clipboard = Some(clipboard);
+                Ok(false)
+            }
+            DocumentMessage::Paste => {
+                let clipboard = self
+                    .clipboard
+                    .as_ref()
+                    .ok_or_else(|| "Nothing to paste".to_owned())?;
+                let document = &mut self.documents[self.active];
+                let pasted = document.stage_state.paste(clipboard)?;
+                document.outliner_state.sync(
+                    document.stage_state.engine(),
+                    &document.stage_state.editor_containers(),
+                );
+                document
+                    .outliner_state
+                    .update(&OutlinerMessage::Select(Some(pasted)));
+                Ok(true)
+            }
+            DocumentMessage::Import(path) => {
+                let file = File::open(path)
+                    .map_err(|error| format!("Could not open {:?}: {}", path, error))?;
+                let (_, _, actions) = deserialize_stream(BufReader::new(file))
+                    .map_err(|error| format!("Could not read {:?}: {}", path, error))?;
+                let document = &mut self.documents[self.active];
+                // Relative links are found next to the stream
+                let resolver = FileAssetResolver::new(
+                    path.parent().map(Path::to_path_buf).unwrap_or_default(),
+                );
+                let imported = document
+                    .stage_state
+                    .import_actions(actions.collect(), &resolver)?;
+                document.outliner_state.sync(
+                    document.stage_state.engine(),
+                    &document.stage_state.editor_containers(),
+                );
+                if let Some(first) = imported.first() {
+                    document
+                        .outliner_state
+                        .update(&OutlinerMessage::Select(Some(*first)));
+                }
+                Ok(true)
+            }
+            DocumentMessage::Undo => {
+                self.active_mut().undo()?;
+                Ok(true)
+            }
+            DocumentMessage::Redo => {
+                self.active_mut().redo()?;
+                Ok(true)
+            }
+            DocumentMessage::Export(path) => {
+                let document = self.active();
+                let actions = document
+                    .stage_state
+                    .export_actions(&document.timeline_state)?;
+                let file = File::create(path)
+                    .map_err(|error| format!("Could not create {:?}: {}", path, error))?;
+                export_stream(
+                    &actions,
+                    self.stage_size,
+                    EXPORT_FRAMES_PER_SECOND,
+                    ExportOptions::default(),
+                    &mut BufWriter::new(file),
+                )
+                .map_err(|error| format!("Could not write {:?}: {}", path, error))?;
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct TabState {
+    select_state: ButtonState,
+    close_state: ButtonState,
+}
+
+#[derive(Debug, Default)]
+pub struct DocumentTabsState {
+    tab_states: Vec<TabState>,
+    new_state: ButtonState,
+}
+
+impl DocumentTabsState {
+    pub fn tab_bar(&mut self, documents: &DocumentManager) -> Row<AppMessage> {
+        let active = documents.active_index();
+        let names = documents
+            .names()
+            .map(str::to_owned)
+            .collect::<Vec<String>>();
+        self.tab_states.resize_with(names.len(), TabState::default);
+        let row = names
+            .into_iter()
+            .zip(self.tab_states.iter_mut())
+            .enumerate()
+            .fold(
+                Row::new().spacing(3).align_items(Align::Center),
+                |row, (index, (name, tab_state))| {
+                    let label = if index == active {
+                        format!("[{}]", name)
+                    } else {
+                        name
+                    };
+                    row.push(
+                        Button::new(&mut tab_state.select_state, Text::new(label).size(14))
+                            .on_press(AppMessage::DocumentMessage(DocumentMessage::Select(index))),
+                    )
+                    .push(
+                        Button::new(&mut tab_state.close_state, Text::new("x").size(14))
+                            .on_press(AppMessage::DocumentMessage(DocumentMessage::Close(index))),
+                    )
+                },
+            );
+        row.push(
+            Button::new(&mut self.new_state, Text::new("+").size(14))
+                .on_press(AppMessage::DocumentMessage(DocumentMessage::New)),
+        )
+    }
+}
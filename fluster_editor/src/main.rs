@@ -1,9 +1,17 @@
 #![deny(clippy::all)]
+mod anchoring;
 mod application;
+mod assets;
+mod commands;
+mod documents;
 mod messages;
+mod outliner;
 mod rendering;
 mod scratch_pad;
 mod simulation;
+mod stage_chrome;
+mod symbols;
+mod timeline;
 mod tools;
 use application::{App, AppFlags};
 use iced::{Application, Settings};
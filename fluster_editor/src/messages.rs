@@ -1,12 +1,37 @@
-use crate::tools::{Tool, ToolOption};
-use fluster_core::engine::SelectionHandle;
+use crate::anchoring::AnchorPreset;
+use crate::commands::EditorCommand;
+use crate::stage_chrome::GuideOrientation;
+use crate::tools::{ShapeTemplate, ToolId, ToolOption};
+use fluster_core::{
+    ecs::resources::DebugFlags,
+    engine::SelectionHandle,
+    types::{
+        basic::ContainerId,
+        shapes::{EdgeKind, HandleType},
+    },
+};
 use pathfinder_geometry::vector::Vector2F;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub enum AppMessage {
     EditMessage(EditMessage),
     EditHandleMessage(Vec<SelectionHandle>),
+    OutlinerMessage(OutlinerMessage),
+    ChromeMessage(ChromeMessage),
+    ViewportMessage(ViewportMessage),
+    AnchorMessage(AnchorMessage),
+    /// Bakes the container's shape into a sprite.
+    BakeMessage(ContainerId),
+    MorphMessage(MorphMessage),
+    CommandMessage(CommandMessage),
+    DocumentMessage(DocumentMessage),
+    SymbolMessage(SymbolMessage),
+    TimelineMessage(TimelineMessage),
     StageUpdateMessage,
+    /// Checks imported files for changes, sent every `assets::POLL_INTERVAL`.
+    PollAssets,
 }
 
 impl AppMessage {
@@ -17,16 +42,158 @@ impl AppMessage {
     pub fn from_tool_option(option: ToolOption) -> Self {
         Self::from_edit_message(EditMessage::ChangeOption(option))
     }
+
+    /// Whether the message changes the document, so undo has a step to go back to afterwards.
+    /// Drawing and dragging only count once they end.
+    // TODO: reloading an imported asset changes documents too, but isn't undone
+    pub fn edits_document(&self) -> bool {
+        match self {
+            Self::EditMessage(EditMessage::ToolUpdate(tool_message)) => matches!(
+                tool_message,
+                ToolMessage::PathEnd
+                    | ToolMessage::MovePointEnd
+                    | ToolMessage::InsertPoint { .. }
+                    | ToolMessage::DeletePoint { .. }
+                    | ToolMessage::ConvertEdge { .. }
+                    | ToolMessage::TemplateEnd
+            ),
+            Self::OutlinerMessage(outliner_message) => {
+                matches!(outliner_message, OutlinerMessage::Reparent { .. })
+            }
+            Self::TimelineMessage(timeline_message) => {
+                matches!(timeline_message, TimelineMessage::InsertBlankKeyframe)
+            }
+            Self::DocumentMessage(document_message) => matches!(
+                document_message,
+                DocumentMessage::Paste | DocumentMessage::Import(..)
+            ),
+            Self::AnchorMessage(..) | Self::BakeMessage(..) => true,
+            Self::MorphMessage(morph_message) => matches!(morph_message, MorphMessage::Finish),
+            // Parts edited in a symbol are only written back when it closes, and clicking nothing
+            // closes it
+            Self::SymbolMessage(symbol_message) => matches!(
+                symbol_message,
+                SymbolMessage::Close | SymbolMessage::Click(None)
+            ),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum EditMessage {
     ToolUpdate(ToolMessage),
     ChangeOption(ToolOption),
-    ToolChange(Tool),
+    ToolChange(ToolId),
     Cancel,
 }
 
+#[derive(Debug, Clone)]
+pub enum OutlinerMessage {
+    Select(Option<ContainerId>),
+    SetVisible(ContainerId, bool),
+    StartMove(ContainerId),
+    CancelMove,
+    /// Moves the container under a new parent without changing where it sits on the stage.
+    Reparent {
+        container_id: ContainerId,
+        new_parent: ContainerId,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum ChromeMessage {
+    ShowGrid(bool),
+    GridSpacing(f32),
+    SnapToGrid(bool),
+    SnapToGuides(bool),
+    StartGuide(GuideOrientation),
+    PickUpGuide(usize),
+    /// None while the cursor is off the stage.
+    MoveGuide(Option<Vector2F>),
+    DropGuide,
+    ClearGuides,
+    DebugFlags(DebugFlags),
+}
+
+#[derive(Debug, Clone)]
+pub enum AnchorMessage {
+    /// Pins the container to a point on the stage without moving it. None unpins it.
+    Set(ContainerId, Option<AnchorPreset>),
+}
+
+#[derive(Debug, Clone)]
+pub enum MorphMessage {
+    /// Starts making a morph of the container's shape. Until it finishes, dragging points moves
+    /// them in the end pose.
+    Start(ContainerId),
+    /// Keeps the morph and keys it on the timeline.
+    Finish,
+}
+
+#[derive(Debug, Clone)]
+pub enum DocumentMessage {
+    New,
+    /// Switches to the document at this tab index.
+    Select(usize),
+    Close(usize),
+    /// Copies the selected container, and its children, to the clipboard documents share.
+    Copy,
+    Paste,
+    /// Imports a file dropped on the stage into the active document. SVGs and PNGs become library
+    /// items, anything else is read as an action stream and its setup imported.
+    Import(PathBuf),
+    /// Writes the active document to a file for the player, see `StageState::export_actions`.
+    Export(PathBuf),
+    /// Steps the active document back to before its last edit, see `History`.
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Clone)]
+pub enum SymbolMessage {
+    /// A left click on the stage, and the container under it. Double clicking a container opens
+    /// the symbol it shows, and double clicking off the open symbol closes it.
+    Click(Option<ContainerId>),
+    Close,
+}
+
+#[derive(Debug, Clone)]
+pub enum TimelineMessage {
+    PreviousFrame,
+    NextFrame,
+    /// Starts an empty keyframe on the active layer at the current frame, to draw the next
+    /// drawing of a frame by frame animation on.
+    InsertBlankKeyframe,
+    /// Moves to the next frame after each finished shape.
+    AutoAdvance(bool),
+}
+
+#[derive(Debug, Clone)]
+pub enum CommandMessage {
+    Run(EditorCommand),
+    ClosePalette,
+    /// The command palette's search text changed.
+    Query(String),
+    /// Runs the command at the top of the command palette.
+    RunFirstMatch,
+}
+
+#[derive(Debug, Clone)]
+pub enum ViewportMessage {
+    /// Multiplies the zoom by `factor`, keeping the point under `view_position` still.
+    Zoom {
+        view_position: Vector2F,
+        factor: f32,
+    },
+    PanMode(bool),
+    PanStart(Vector2F),
+    PanTo(Vector2F),
+    PanEnd,
+    FitToStage,
+    ActualSize,
+}
+
 // TODO: could we use just one End message?
 #[derive(Clone, Debug)]
 pub enum ToolMessage {
@@ -43,11 +210,24 @@ pub enum ToolMessage {
     PathEnd,
     MovePointStart {
         selection_handle: SelectionHandle,
+        /// None to work it out from the handles' current positions.
+        handle_type: Option<HandleType>,
     },
     MovePointHover {
         hover_position: Vector2F,
     },
     MovePointEnd,
+    InsertPoint {
+        selection_handle: SelectionHandle,
+        position: Vector2F,
+    },
+    DeletePoint {
+        selection_handle: SelectionHandle,
+    },
+    ConvertEdge {
+        selection_handle: SelectionHandle,
+        kind: EdgeKind,
+    },
     TemplateStart {
         start_position: Vector2F,
         options: Vec<ToolOption>,
@@ -59,9 +239,10 @@ pub enum ToolMessage {
     TemplateEnd,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum Template {
     Ellipse,
     Polygon,
     Rectangle,
+    Custom(Arc<dyn ShapeTemplate>),
 }
@@ -0,0 +1,220 @@
+use crate::messages::{AppMessage, OutlinerMessage};
+use fluster_core::{ecs::components::DisplayKind, engine::Engine, types::basic::ContainerId};
+use iced::{
+    button::State as ButtonState, scrollable::State as ScrollableState, Align, Button, Checkbox,
+    Column, Image, Length, Row, Scrollable, Space, Text,
+};
+use iced_native::image::Handle as ImageHandle;
+use std::collections::{HashMap, HashSet};
+
+const INDENT: u16 = 12;
+
+fn icon_handle(display_kind: Option<DisplayKind>) -> ImageHandle {
+    ImageHandle::from_path(format!(
+        "{}/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        match display_kind {
+            Some(DisplayKind::Vector) => "assets/icons/034-vector.png",
+            Some(DisplayKind::Raster) => "assets/icons/037-png file.png",
+            None => "assets/icons/026-layer.png",
+        }
+    ))
+}
+
+#[derive(Clone, Debug)]
+struct OutlinerRow {
+    container_id: ContainerId,
+    depth: u16,
+    label: String,
+    display_kind: Option<DisplayKind>,
+    // False if this container or any of its parents has been hidden
+    shown: bool,
+}
+
+#[derive(Debug, Default)]
+struct RowState {
+    select_state: ButtonState,
+    move_state: ButtonState,
+}
+
+/// The scene outliner. Mirrors the engine's scene graph as a tree and tracks which containers are
+/// selected or hidden in the editor. Hiding a container only affects the editor's stage, it is
+/// not saved into the scene.
+#[derive(Debug, Default)]
+pub struct OutlinerState {
+    rows: Vec<OutlinerRow>,
+    row_states: HashMap<ContainerId, RowState>,
+    hidden: HashSet<ContainerId>,
+    selected: Option<ContainerId>,
+    moving: Option<ContainerId>,
+    scroll_state: ScrollableState,
+}
+
+impl OutlinerState {
+    /// Rebuilds the tree from the engine's scene graph. Containers in `excluded`, and their
+    /// children, are editor internals and are left out.
+    pub fn sync(&mut self, engine: &Engine, excluded: &[ContainerId]) {
+        let library = engine.get_library();
+        let mut rows = vec![];
+        // Depth first so children are listed directly under their parent
+        let mut stack = vec![(engine.get_root_container_id(), 0, true)];
+        while let Some((container_id, depth, parent_shown)) = stack.pop() {
+            if excluded.contains(&container_id) {
+                continue;
+            }
+            let display = engine.get_display(&container_id);
+            let label = display
+                .and_then(|(library_id, _)| library.get_metadata(&library_id))
+                .map(|metadata| metadata.name.clone())
+                .unwrap_or_else(|| {
+                    // The last 8 digits of the uuid are plenty to tell containers apart
+                    let id = format!("{:?}", container_id);
+                    format!(
+                        "Container {}",
+                        &id[id.len().saturating_sub(9)..id.len() - 1]
+                    )
+                });
+            let shown = parent_shown && !self.hidden.contains(&container_id);
+            if let Some(children) = engine.get_children(&container_id) {
+                stack.extend(
+                    children
+                        .into_iter()
+                        .rev()
+                        .map(|child| (child, depth + 1, shown)),
+                );
+            }
+            rows.push(OutlinerRow {
+                container_id,
+                depth,
+                label,
+                display_kind: display.map(|(_, display_kind)| display_kind),
+                shown,
+            });
+        }
+        let live = rows
+            .iter()
+            .map(|row| row.container_id)
+            .collect::<HashSet<ContainerId>>();
+        self.row_states.retain(|id, _| live.contains(id));
+        self.hidden.retain(|id| live.contains(id));
+        self.selected = self.selected.filter(|id| live.contains(id));
+        self.moving = self.moving.filter(|id| live.contains(id));
+        self.rows = rows;
+    }
+
+    /// Returns true if the stage needs to be redrawn.
+    pub fn update(&mut self, message: &OutlinerMessage) -> bool {
+        match message {
+            OutlinerMessage::Select(selected) => {
+                let changed = self.selected != *selected;
+                self.selected = *selected;
+                changed
+            }
+            OutlinerMessage::SetVisible(container_id, visible) => {
+                if *visible {
+                    self.hidden.remove(container_id)
+                } else {
+                    self.hidden.insert(*container_id)
+                }
+            }
+            OutlinerMessage::StartMove(container_id) => {
+                self.moving = Some(*container_id);
+                false
+            }
+            OutlinerMessage::CancelMove => {
+                self.moving = None;
+                false
+            }
+            // The move itself is applied by the StageState
+            OutlinerMessage::Reparent { .. } => {
+                self.moving = None;
+                false
+            }
+        }
+    }
+
+    pub fn selected(&self) -> Option<&ContainerId> {
+        self.selected.as_ref()
+    }
+
+    /// Every container that should not be drawn, including children of hidden containers.
+    pub fn hidden_containers(&self) -> HashSet<ContainerId> {
+        self.rows
+            .iter()
+            .filter(|row| !row.shown)
+            .map(|row| row.container_id)
+            .collect()
+    }
+
+    // TODO: real drag and drop once Iced supports it. Until then "Move" picks a container up and
+    // clicking another row drops it there.
+    pub fn view(&mut self) -> Scrollable<AppMessage> {
+        let selected = self.selected;
+        let moving = self.moving;
+        let hidden = &self.hidden;
+        let row_states = &mut self.row_states;
+        let mut column = Column::new().spacing(2);
+        for (index, row) in self.rows.iter().enumerate() {
+            let container_id = row.container_id;
+            let is_root = index == 0;
+            let row_state = row_states.entry(container_id).or_default();
+            let label = if selected == Some(container_id) {
+                format!("> {}", row.label)
+            } else {
+                row.label.clone()
+            };
+            let text = Text::new(label).size(16).color(if row.shown {
+                [0.0, 0.0, 0.0]
+            } else {
+                [0.6, 0.6, 0.6]
+            });
+            let on_select = match moving {
+                Some(moving_id) if moving_id != container_id => OutlinerMessage::Reparent {
+                    container_id: moving_id,
+                    new_parent: container_id,
+                },
+                _ => OutlinerMessage::Select(Some(container_id)),
+            };
+            let mut line = Row::new()
+                .spacing(3)
+                .align_items(Align::Center)
+                .push(Space::with_width(Length::Units(row.depth * INDENT)));
+            if !is_root {
+                line = line.push(Checkbox::new(
+                    !hidden.contains(&container_id),
+                    "",
+                    move |visible| {
+                        AppMessage::OutlinerMessage(OutlinerMessage::SetVisible(
+                            container_id,
+                            visible,
+                        ))
+                    },
+                ));
+            }
+            line = line
+                .push(Image::new(icon_handle(row.display_kind)).width(Length::Units(16)))
+                .push(
+                    Button::new(&mut row_state.select_state, text)
+                        .on_press(AppMessage::OutlinerMessage(on_select))
+                        .width(Length::Fill),
+                );
+            if !is_root {
+                let (move_label, move_message) = if moving == Some(container_id) {
+                    ("Cancel", OutlinerMessage::CancelMove)
+                } else {
+                    ("Move", OutlinerMessage::StartMove(container_id))
+                };
+                line = line.push(
+                    Button::new(&mut row_state.move_state, Text::new(move_label).size(14))
+                        .on_press(AppMessage::OutlinerMessage(move_message)),
+                );
+            }
+            column = column.push(line);
+        }
+        Scrollable::new(&mut self.scroll_state)
+            .padding(20)
+            .width(Length::Units(280))
+            .height(Length::Fill)
+            .push(column)
+    }
+}
@@ -1,22 +1,35 @@
 use fluster_core::{
-    engine::Engine,
-    rendering::{lin_srgb_to_coloru, paint, Renderer as FlusterRenderer},
+    baking::{BakeSource, Rasterizer},
+    ecs::components::DisplayKind,
+    engine::{DrawableItem, Engine, LibraryItem},
+    rendering::{apply_camera, opaque, paint_items, Renderer as FlusterRenderer},
+    types::{
+        basic::ContainerId,
+        coloring::{ColorTransform, Coloring},
+        shapes::Shape,
+    },
 };
-use fluster_graphics::FlusterRendererImpl;
-use gl::{ReadPixels, BGRA, UNSIGNED_BYTE};
+use fluster_graphics::{FlusterRendererImpl, FlusterRendererOptions};
+use gl::{ReadPixels, BGRA, RGBA, UNSIGNED_BYTE};
 use iced::image::Handle as ImageHandle;
-use palette::LinSrgb;
+use palette::{LinSrgb, LinSrgba};
 use pathfinder_canvas::CanvasFontContext;
-use pathfinder_color::ColorF;
+use pathfinder_color::{ColorF, ColorU};
+use pathfinder_geometry::transform2d::Transform2F;
 use pathfinder_geometry::vector::Vector2I;
 use pathfinder_gl::{GLDevice, GLVersion};
 use pathfinder_renderer::gpu::options::{DestFramebuffer, RendererMode, RendererOptions};
 use pathfinder_renderer::gpu::renderer::Renderer;
 use pathfinder_resources::embedded::EmbeddedResourceLoader;
 use sdl2::video::{GLContext, GLProfile, Window};
+use std::collections::HashSet;
 use std::convert::TryInto;
 use std::error::Error;
 use std::ffi::c_void;
+use std::sync::Arc;
+
+// How much of the stage shows through while a symbol is open
+const DIMMED_OPACITY: f32 = 0.3;
 
 /*
  *   Note: This is kinda a hack until there is a cleaner way to use pathfinder and iced together.
@@ -67,7 +80,12 @@ impl StageRenderer {
 
         let font_context = CanvasFontContext::from_system_source();
 
-        let fluster_renderer = FlusterRendererImpl::new(font_context, renderer, Box::new(|| ()));
+        let fluster_renderer = FlusterRendererImpl::new(
+            font_context,
+            renderer,
+            FlusterRendererOptions::default(),
+            Box::new(|_| ()),
+        );
         Ok(StageRenderer {
             renderer: fluster_renderer,
             window,
@@ -80,11 +98,34 @@ impl StageRenderer {
         &mut self,
         background_color: LinSrgb,
         engine: &Engine,
+        hidden: &HashSet<ContainerId>,
+        focus: Option<&HashSet<ContainerId>>,
+        overlay: Vec<Arc<Shape>>,
     ) -> Result<ImageHandle, Box<dyn Error>> {
         self.renderer.start_frame(self.stage_size.to_f32());
-        self.renderer
-            .set_background(lin_srgb_to_coloru(background_color));
-        paint(&mut self.renderer, engine);
+        self.renderer.set_background(opaque(background_color));
+        let engine_camera = engine.get_camera();
+        let camera = engine_camera.transform();
+        let drawable_items = engine
+            .get_drawable_items()
+            .into_iter()
+            .filter(|drawable_item| !hidden.contains(&drawable_item.container_id))
+            .map(|drawable_item| show_vector_source(engine, drawable_item))
+            .map(|drawable_item| match focus {
+                Some(focus) if !focus.contains(&drawable_item.container_id) => dim(drawable_item),
+                _ => drawable_item,
+            })
+            .chain(engine.get_debug_drawables())
+            .map(|mut drawable_item| {
+                apply_camera(&mut drawable_item, &engine_camera, Transform2F::default());
+                drawable_item
+            })
+            .collect();
+        paint_items(&mut self.renderer, drawable_items);
+        // Editor chrome goes on top of the scene, and is never part of it
+        for shape in overlay {
+            self.renderer.draw_shape(shape, camera, None, 0.0);
+        }
         self.renderer.end_frame();
         let pixels = unsafe {
             let buffer_size = self.stage_size.x() * self.stage_size.y() * 4;
@@ -110,3 +151,95 @@ impl StageRenderer {
         ))
     }
 }
+
+impl Rasterizer for StageRenderer {
+    fn rasterize(
+        &mut self,
+        drawable_items: Vec<DrawableItem>,
+        size: Vector2I,
+    ) -> Result<Vec<ColorU>, String> {
+        if size.x() > self.stage_size.x() || size.y() > self.stage_size.y() {
+            return Err(format!(
+                "A {:?} cell is bigger than the {:?} stage",
+                size, self.stage_size
+            ));
+        }
+        self.renderer.start_frame(self.stage_size.to_f32());
+        self.renderer
+            .set_background(LinSrgba::new(0.0, 0.0, 0.0, 0.0));
+        paint_items(&mut self.renderer, drawable_items);
+        self.renderer.end_frame();
+        let mut pixels = vec![ColorU::transparent_black(); (size.x() * size.y()) as usize];
+        unsafe {
+            // GL's origin is the bottom left, so the top of the frame is the last rows
+            ReadPixels(
+                0,
+                self.stage_size.y() - size.y(),
+                size.x(),
+                size.y(),
+                RGBA,
+                UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut c_void,
+            );
+        }
+        Ok(pixels
+            .chunks(size.x() as usize)
+            .rev()
+            .flatten()
+            .copied()
+            .collect())
+    }
+}
+
+// Baked sprites whose vector source is still in the library are edited as that source
+fn show_vector_source(engine: &Engine, drawable_item: DrawableItem) -> DrawableItem {
+    let view_rect = match drawable_item.view_rect {
+        Some(view_rect) => view_rect,
+        None => return drawable_item,
+    };
+    let library = engine.get_library();
+    let source = engine
+        .get_display(&drawable_item.container_id)
+        .filter(|(_, display_kind)| matches!(display_kind, DisplayKind::Raster))
+        .and_then(|(library_id, _)| library.get_sprite_sheet(&library_id))
+        .and_then(|sheet| {
+            let frame = sheet.frame_at(view_rect)?;
+            match frame.source {
+                BakeSource::Shape { id, morph } => Some((
+                    library.get_resolved_shape(&id)?,
+                    morph,
+                    frame.transform(sheet.scale),
+                )),
+                BakeSource::Container { .. } => None,
+            }
+        });
+    match source {
+        Some((shape, morph, frame_transform)) => DrawableItem {
+            library_item: LibraryItem::Vector(shape),
+            transform: drawable_item.transform * frame_transform.inverse(),
+            // Tints are meant for the raster, the source keeps its own colors
+            coloring: None,
+            view_rect: None,
+            morph,
+            ..drawable_item
+        },
+        None => drawable_item,
+    }
+}
+
+// Everything outside the open symbol fades back, so it's clear what can be edited
+fn dim(drawable_item: DrawableItem) -> DrawableItem {
+    let base =
+        drawable_item
+            .coloring
+            .clone()
+            .unwrap_or_else(|| match &drawable_item.library_item {
+                LibraryItem::Vector(shape) => shape.color(),
+                // TODO: raster tints aren't drawn by the renderer yet
+                LibraryItem::Raster(_) => Coloring::None,
+            });
+    DrawableItem {
+        coloring: Some(ColorTransform::opacity(DIMMED_OPACITY).apply_to_coloring(&base)),
+        ..drawable_item
+    }
+}
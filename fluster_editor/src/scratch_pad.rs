@@ -1,12 +1,16 @@
 use crate::messages::{EditMessage, Template, ToolMessage};
 use crate::tools::{ToolOption, ToolOptionHandle};
 use fluster_core::{
-    ecs::resources::{Library, QuadTreeLayer},
+    ecs::{
+        components::DisplayKind,
+        resources::{Library, QuadTreeLayer},
+    },
     engine::{Engine, SelectionHandle},
     factories::new_display_container_with_collision,
     types::{
         basic::{ContainerId, LibraryId},
-        shapes::{Edge, Shape},
+        edge_pool::EdgePool,
+        shapes::{Contour, Edge, EdgeKind, HandleType, Shape},
     },
 };
 use palette::LinSrgba;
@@ -16,7 +20,8 @@ use pathfinder_geometry::vector::Vector2F;
 use std::collections::HashMap;
 use std::mem;
 
-pub const EDIT_LAYER: QuadTreeLayer = QuadTreeLayer::new(std::u32::MAX - 1);
+pub const EDIT_LAYER: QuadTreeLayer = QuadTreeLayer::reserved(0);
+pub const EDIT_LAYER_NAME: &str = "fluster.editor";
 
 fn create_shape_prototype(options: &Vec<ToolOption>) -> Shape {
     //TODO: Fill and StrokedFill, rename Path to Stroke
@@ -36,7 +41,7 @@ fn create_shape_prototype(options: &Vec<ToolOption>) -> Shape {
         }
     }
     Shape::Path {
-        edges: vec![],
+        contours: vec![],
         color: line_color.unwrap_or(LinSrgba::new(0.0, 0.0, 0.0, 1.0)),
         stroke_style: StrokeStyle {
             line_width,
@@ -46,17 +51,24 @@ fn create_shape_prototype(options: &Vec<ToolOption>) -> Shape {
     }
 }
 
-fn update_library(library: &mut Library, id: LibraryId, shape_prototype: &Shape, edges: Vec<Edge>) {
+fn update_library(library: &mut Library, id: LibraryId, shape_prototype: &Shape, edges: &[Edge]) {
     let shape = match shape_prototype {
         Shape::Path {
             color,
             stroke_style,
             ..
         } => Shape::Path {
-            edges,
+            contours: Contour::from_edges(edges),
             color: *color,
             stroke_style: *stroke_style,
         },
+        Shape::Fill { color, .. } => Shape::Fill {
+            contours: Contour::from_edges(edges),
+            color: *color,
+        },
+        Shape::Clip { .. } => Shape::Clip {
+            contours: Contour::from_edges(edges),
+        },
         _ => todo!(),
     };
 
@@ -65,12 +77,15 @@ fn update_library(library: &mut Library, id: LibraryId, shape_prototype: &Shape,
 
 pub struct ScratchPad {
     state: ScratchPadState,
+    // Shapes finished since the last `take_completed_shapes`
+    completed_shapes: Vec<ContainerId>,
 }
 
 impl Default for ScratchPad {
     fn default() -> Self {
         Self {
             state: ScratchPadState::default(),
+            completed_shapes: vec![],
         }
     }
 }
@@ -82,7 +97,43 @@ impl ScratchPad {
         engine: &mut Engine,
     ) -> Result<bool, String> {
         // TODO: CLEANUP: Move bulk of ScratchPadState.apply_edit here
-        self.state.apply_edit(edit_message, engine)
+        self.state
+            .apply_edit(edit_message, engine, &mut self.completed_shapes)
+    }
+
+    /// The containers of shapes that were drawn since this was last called.
+    pub fn take_completed_shapes(&mut self) -> Vec<ContainerId> {
+        mem::take(&mut self.completed_shapes)
+    }
+
+    /// Starts making a morph of the container's shape. The shape is duplicated as the end pose,
+    /// and vertex drags edit the end pose until `finish_morph`.
+    pub fn start_morph(
+        &mut self,
+        engine: &mut Engine,
+        container_id: &ContainerId,
+    ) -> Result<(), String> {
+        if let ScratchPadState::None = self.state {
+            self.state = ScratchPadState::EditMorph(MorphScratchPad::start(engine, container_id)?);
+            Ok(())
+        } else {
+            Err("Attempting to make a morph while an edit was in progress".to_owned())
+        }
+    }
+
+    /// Leaves the morph in the library and shows its start pose. Returns the morphing container.
+    pub fn finish_morph(&mut self, engine: &mut Engine) -> Result<ContainerId, String> {
+        match mem::take(&mut self.state) {
+            ScratchPadState::EditMorph(morph_scratch_pad) => Ok(morph_scratch_pad.complete(engine)),
+            state => {
+                self.state = state;
+                Err("No morph in progress".to_owned())
+            }
+        }
+    }
+
+    pub fn is_making_morph(&self) -> bool {
+        matches!(self.state, ScratchPadState::EditMorph(_))
     }
 }
 
@@ -90,6 +141,7 @@ enum ScratchPadState {
     NewPath(ShapeScratchPad),
     NewTemplateShape(TemplateShapeScratchpad),
     EditVertexes(VertexScratchPad),
+    EditMorph(MorphScratchPad),
     None,
 }
 
@@ -104,7 +156,16 @@ impl ScratchPadState {
         &mut self,
         edit_message: &EditMessage,
         engine: &mut Engine,
+        completed_shapes: &mut Vec<ContainerId>,
     ) -> Result<bool, String> {
+        if let Self::EditMorph(morph_scratch_pad) = self {
+            return match edit_message {
+                EditMessage::ToolUpdate(tool_message) => {
+                    morph_scratch_pad.apply_tool(tool_message, engine)
+                }
+                _ => Ok(false),
+            };
+        }
         match edit_message {
             // TODO: this is gonna become very large. Maybe break it up into delegated functions
             EditMessage::ToolUpdate(tool_message) => match tool_message {
@@ -143,17 +204,20 @@ impl ScratchPadState {
                 }
                 ToolMessage::PathEnd => {
                     if let Self::NewPath(shape_scratch_pad) = self {
-                        shape_scratch_pad.complete_path(engine);
+                        completed_shapes.extend(shape_scratch_pad.complete_path(engine));
                         *self = Self::None;
                         Ok(true) //TODO: update scene message
                     } else {
                         Err("Unexpected Message \"PathEnd\"".to_owned())
                     }
                 }
-                ToolMessage::MovePointStart { selection_handle } => {
+                ToolMessage::MovePointStart {
+                    selection_handle,
+                    handle_type,
+                } => {
                     if let Self::None = self {
                         let vertex_scratch_pad =
-                            VertexScratchPad::start_drag(engine, selection_handle)?;
+                            VertexScratchPad::start_drag(engine, selection_handle, *handle_type)?;
                         *self = Self::EditVertexes(vertex_scratch_pad);
                         Ok(false)
                     } else {
@@ -178,6 +242,36 @@ impl ScratchPadState {
                         Err("Unexpected Message \"PathEnd\"".to_owned())
                     }
                 }
+                ToolMessage::InsertPoint {
+                    selection_handle,
+                    position,
+                } => {
+                    if let Self::None = self {
+                        VertexScratchPad::insert_point(engine, selection_handle, *position)?;
+                        Ok(true)
+                    } else {
+                        Err("Attempting to add a vertex while edit in progress".to_owned())
+                    }
+                }
+                ToolMessage::DeletePoint { selection_handle } => {
+                    if let Self::None = self {
+                        VertexScratchPad::delete_point(engine, selection_handle)?;
+                        Ok(true)
+                    } else {
+                        Err("Attempting to delete a vertex while edit in progress".to_owned())
+                    }
+                }
+                ToolMessage::ConvertEdge {
+                    selection_handle,
+                    kind,
+                } => {
+                    if let Self::None = self {
+                        VertexScratchPad::convert_edge(engine, selection_handle, *kind)?;
+                        Ok(true)
+                    } else {
+                        Err("Attempting to convert an edge while edit in progress".to_owned())
+                    }
+                }
                 ToolMessage::TemplateStart {
                     start_position,
                     options,
@@ -188,7 +282,7 @@ impl ScratchPadState {
                             engine,
                             *start_position,
                             options,
-                            *template,
+                            template.clone(),
                         );
                         *self = Self::NewTemplateShape(template_scratch_pad);
                         Ok(false)
@@ -210,7 +304,7 @@ impl ScratchPadState {
                 }
                 ToolMessage::TemplateEnd => {
                     if let Self::NewTemplateShape(template_scratch_pad) = self {
-                        template_scratch_pad.complete(engine)?;
+                        completed_shapes.extend(template_scratch_pad.complete(engine)?);
                         *self = Self::None;
                         Ok(true) //TODO: update scene message
                     } else {
@@ -231,14 +325,28 @@ struct ShapeScratchPad {
     shape_prototype: Shape,
     close_path: bool,
     selected_point: (usize, usize),
+    // Previews of closed paths need a copy of the edges with a close on the end
+    edge_pool: EdgePool,
 }
 
 struct VertexScratchPad {
     container_id: ContainerId,
     item_id: LibraryId,
+    // Stage positions to the shape's own space, for shapes inside transformed containers
+    to_local: Transform2F,
     edges: Vec<Edge>,
     shape_prototype: Shape,
     selected_point: (usize, usize),
+    handle_type: HandleType,
+}
+
+struct MorphScratchPad {
+    container_id: ContainerId,
+    item_id: LibraryId,
+    // The shape the morph starts from. Its edges are the start pose
+    start_shape: Shape,
+    end_edges: Vec<Edge>,
+    dragging: Option<((usize, usize), HandleType)>,
 }
 
 struct TemplateShapeScratchpad {
@@ -271,7 +379,7 @@ impl ShapeScratchPad {
             item_id,
             vec![EDIT_LAYER],
         );
-        let new_self = Self {
+        let mut new_self = Self {
             container_id,
             item_id,
             edges: vec![Edge::Move(start_position)],
@@ -279,18 +387,22 @@ impl ShapeScratchPad {
             shape_prototype: create_shape_prototype(options),
             close_path,
             selected_point: (0, 0), // TODO: merge commited_edges and selected_point concept
+            edge_pool: EdgePool::new(),
         };
         new_self.update_library(&mut *engine.get_library_mut());
         new_self
     }
 
-    fn update_library(&self, library: &mut Library) {
-        let mut edges = self.edges.clone();
-        // add close after clone to keep end of list what is currently being edited
+    fn update_library(&mut self, library: &mut Library) {
         if self.close_path {
+            // add close to a copy to keep end of list what is currently being edited
+            let mut edges = self.edge_pool.take_copy(&self.edges);
             edges.push(Edge::Close);
+            update_library(library, self.item_id, &self.shape_prototype, &edges);
+            self.edge_pool.give_back(edges);
+        } else {
+            update_library(library, self.item_id, &self.shape_prototype, &self.edges);
         }
-        update_library(library, self.item_id, &self.shape_prototype, edges);
     }
 
     fn next_edge(&mut self, library: &mut Library, next_position: Vector2F) {
@@ -300,10 +412,6 @@ impl ShapeScratchPad {
         //TODO: other path types
         self.edges.push(Edge::Line(next_position));
         self.committed_edges = self.edges.len();
-        let mut edges = self.edges.clone();
-        if self.close_path {
-            edges.push(Edge::Close);
-        }
         self.update_library(library);
     }
 
@@ -315,13 +423,15 @@ impl ShapeScratchPad {
         self.update_library(library);
     }
 
-    fn complete_path(&mut self, engine: &mut Engine) {
+    // Returns the finished shape's container, or None if it was too short to keep
+    fn complete_path(&mut self, engine: &mut Engine) -> Option<ContainerId> {
         if self.committed_edges < self.edges.len() {
             self.edges.pop();
         }
         if self.committed_edges <= 1 {
             engine.get_library_mut().remove_shape(&self.item_id);
             engine.remove_container(&self.container_id).unwrap();
+            None
         } else {
             if self.close_path {
                 self.edges.push(Edge::Close);
@@ -330,25 +440,36 @@ impl ShapeScratchPad {
                 &mut *engine.get_library_mut(),
                 self.item_id,
                 &self.shape_prototype,
-                mem::take(&mut self.edges),
+                &mem::take(&mut self.edges),
             );
             engine.refresh_bounds(&self.container_id);
+            Some(self.container_id)
         }
     }
 }
 
 impl VertexScratchPad {
-    fn start_drag(engine: &mut Engine, selection_handle: &SelectionHandle) -> Result<Self, String> {
+    fn start_drag(
+        engine: &mut Engine,
+        selection_handle: &SelectionHandle,
+        handle_type: Option<HandleType>,
+    ) -> Result<Self, String> {
         if let (Some(vertex), Some(item_id)) =
             (selection_handle.min_vertex(), selection_handle.shape_id())
         {
             if let Some(shape) = engine.get_library_mut().get_shape(item_id) {
+                let edges = shape.edge_list(selection_handle.morph());
+                let handle_type = handle_type
+                    .or_else(|| Edge::handle_type(&edges, vertex.edge_id(), vertex.vertex_id()))
+                    .unwrap_or(HandleType::Corner);
                 Ok(Self {
                     container_id: *selection_handle.container_id(),
                     item_id: *item_id,
-                    edges: shape.edge_list(selection_handle.morph()),
+                    to_local: selection_handle.world_transform().inverse(),
+                    edges,
                     shape_prototype: (*shape).clone(),
                     selected_point: (vertex.edge_id(), vertex.vertex_id()),
+                    handle_type,
                 })
             } else {
                 Err(format!("Could not find library item {:?}", item_id))
@@ -358,14 +479,72 @@ impl VertexScratchPad {
         }
     }
 
+    // Applies a one off edit to the edges of the selected shape
+    fn edit_edges(
+        engine: &mut Engine,
+        selection_handle: &SelectionHandle,
+        edit: impl FnOnce(&mut Vec<Edge>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let item_id = selection_handle
+            .shape_id()
+            .ok_or_else(|| "Selection is not a shape".to_owned())?;
+        let shape = engine
+            .get_library()
+            .get_shape(&item_id)
+            .ok_or_else(|| format!("Could not find library item {:?}", item_id))?;
+        let mut edges = shape.edge_list(selection_handle.morph());
+        edit(&mut edges)?;
+        update_library(&mut *engine.get_library_mut(), item_id, &shape, &edges);
+        engine.refresh_bounds(selection_handle.container_id());
+        Ok(())
+    }
+
+    fn insert_point(
+        engine: &mut Engine,
+        selection_handle: &SelectionHandle,
+        position: Vector2F,
+    ) -> Result<(), String> {
+        let local_position = engine
+            .stage_to_container_local(position, selection_handle.container_id())
+            .unwrap_or_else(|| selection_handle.world_transform().inverse() * position);
+        Self::edit_edges(engine, selection_handle, |edges| {
+            Edge::insert_point(edges, local_position).map(|_| ())
+        })
+    }
+
+    fn delete_point(engine: &mut Engine, selection_handle: &SelectionHandle) -> Result<(), String> {
+        let vertex = selection_handle
+            .min_vertex()
+            .ok_or_else(|| "Selection contained 0 vertexes".to_owned())?;
+        let edge_id = vertex.edge_id();
+        Self::edit_edges(engine, selection_handle, |edges| {
+            Edge::remove_point(edges, edge_id)
+        })
+    }
+
+    fn convert_edge(
+        engine: &mut Engine,
+        selection_handle: &SelectionHandle,
+        kind: EdgeKind,
+    ) -> Result<(), String> {
+        let vertex = selection_handle
+            .min_vertex()
+            .ok_or_else(|| "Selection contained 0 vertexes".to_owned())?;
+        let edge_id = vertex.edge_id();
+        Self::edit_edges(engine, selection_handle, |edges| {
+            Edge::convert_edge(edges, edge_id, kind)
+        })
+    }
+
     fn update_preview_drag(&mut self, library: &mut Library, temp_position: Vector2F) {
-        self.edges[self.selected_point.0].update_point(self.selected_point.1, temp_position);
-        update_library(
-            library,
-            self.item_id,
-            &self.shape_prototype,
-            self.edges.clone(),
+        Edge::move_point(
+            &mut self.edges,
+            self.selected_point.0,
+            self.selected_point.1,
+            self.to_local * temp_position,
+            self.handle_type,
         );
+        update_library(library, self.item_id, &self.shape_prototype, &self.edges);
     }
 
     fn complete_drag(&mut self, engine: &mut Engine) {
@@ -373,12 +552,111 @@ impl VertexScratchPad {
             &mut *engine.get_library_mut(),
             self.item_id,
             &self.shape_prototype,
-            mem::take(&mut self.edges),
+            &mem::take(&mut self.edges),
         );
         engine.refresh_bounds(&self.container_id);
     }
 }
 
+impl MorphScratchPad {
+    fn start(engine: &mut Engine, container_id: &ContainerId) -> Result<Self, String> {
+        let item_id = match engine.get_display(container_id) {
+            Some((item_id, DisplayKind::Vector)) => item_id,
+            _ => {
+                return Err(format!(
+                    "Container {:?} doesn't display a shape",
+                    container_id
+                ))
+            }
+        };
+        let start_shape = engine
+            .get_library()
+            .get_shape(&item_id)
+            .ok_or_else(|| format!("Could not find library item {:?}", item_id))?;
+        // Making a morph of a morph picks its end pose back up
+        let new_self = Self {
+            container_id: *container_id,
+            item_id,
+            start_shape: (*start_shape).clone(),
+            end_edges: start_shape.edge_list(1.0),
+            dragging: None,
+        };
+        new_self.update_library(&mut *engine.get_library_mut())?;
+        // Show the end pose, so the handles are the end pose's points
+        engine.set_morph_index(container_id, 1.0);
+        Ok(new_self)
+    }
+
+    fn update_library(&self, library: &mut Library) -> Result<(), String> {
+        library.add_shape(self.item_id, self.start_shape.morph_to(&self.end_edges)?);
+        Ok(())
+    }
+
+    // Points can only be moved. Adding, removing or converting them would leave the end pose
+    // with edges the start pose doesn't have.
+    fn apply_tool(
+        &mut self,
+        tool_message: &ToolMessage,
+        engine: &mut Engine,
+    ) -> Result<bool, String> {
+        match tool_message {
+            ToolMessage::MovePointStart {
+                selection_handle,
+                handle_type,
+            } => {
+                if *selection_handle.shape_id() != Some(self.item_id) {
+                    return Err("Only the shape being morphed can be edited".to_owned());
+                }
+                let vertex = selection_handle
+                    .min_vertex()
+                    .ok_or_else(|| "Selection contained 0 vertexes".to_owned())?;
+                let handle_type = handle_type
+                    .or_else(|| {
+                        Edge::handle_type(&self.end_edges, vertex.edge_id(), vertex.vertex_id())
+                    })
+                    .unwrap_or(HandleType::Corner);
+                self.dragging = Some(((vertex.edge_id(), vertex.vertex_id()), handle_type));
+                Ok(false)
+            }
+            ToolMessage::MovePointHover { hover_position } => {
+                if let Some(((edge_id, vertex_id), handle_type)) = self.dragging {
+                    Edge::move_point(
+                        &mut self.end_edges,
+                        edge_id,
+                        vertex_id,
+                        *hover_position,
+                        handle_type,
+                    );
+                    self.update_library(&mut *engine.get_library_mut())?;
+                    Ok(true)
+                } else {
+                    Err("Unexpected Message \"MovePointHover\"".to_owned())
+                }
+            }
+            ToolMessage::MovePointEnd => {
+                if self.dragging.take().is_some() {
+                    engine.refresh_bounds(&self.container_id);
+                    Ok(true)
+                } else {
+                    Err("Unexpected Message \"MovePointEnd\"".to_owned())
+                }
+            }
+            ToolMessage::InsertPoint { .. }
+            | ToolMessage::DeletePoint { .. }
+            | ToolMessage::ConvertEdge { .. } => {
+                Err("Points can't be added, removed or converted while making a morph".to_owned())
+            }
+            _ => Err("Attempting to draw a shape while making a morph".to_owned()),
+        }
+    }
+
+    fn complete(self, engine: &mut Engine) -> ContainerId {
+        engine.set_morph_index(&self.container_id, 0.0);
+        engine.refresh_bounds(&self.container_id);
+        self.container_id
+    }
+}
+
 impl TemplateShapeScratchpad {
     fn start(
         engine: &mut Engine,
@@ -407,6 +685,10 @@ impl TemplateShapeScratchpad {
             ToolOptionHandle::UseSuperEllipseApproximation,
             ToolOption::UseSuperEllipseApproximation(use_super_ellipse_approximation),
         );
+        // Custom templates can read any option their tool uses
+        for option in options {
+            template_options.entry(option.handle()).or_insert(*option);
+        }
         let item_id = LibraryId::new();
         let container_id = new_display_container_with_collision(
             engine,
@@ -428,12 +710,19 @@ impl TemplateShapeScratchpad {
             &mut *engine.get_library_mut(),
             new_self.item_id,
             &new_self.shape_prototype,
-            vec![],
+            &[],
         );
         new_self
     }
 
     fn compute_edge(&self) -> Result<Vec<Edge>, String> {
+        if let Template::Custom(template) = &self.template {
+            return template.edges(
+                self.start_position,
+                self.end_position,
+                &self.template_options,
+            );
+        }
         let corner_radius = if let Some(ToolOption::CornerRadius(corner_radius)) =
             self.template_options.get(&ToolOptionHandle::CornerRadius)
         {
@@ -443,7 +732,7 @@ impl TemplateShapeScratchpad {
                 "Attempting to draw a polygon without specifying the corner radius".to_owned(),
             );
         };
-        match self.template {
+        match &self.template {
             Template::Ellipse => Ok(Edge::new_ellipse(
                 self.end_position - self.start_position,
                 Transform2F::from_translation(self.start_position),
@@ -519,6 +808,7 @@ impl TemplateShapeScratchpad {
                     ))
                 }
             }
+            Template::Custom(..) => unreachable!(),
         }
     }
 
@@ -532,24 +822,26 @@ impl TemplateShapeScratchpad {
             library,
             self.item_id,
             &self.shape_prototype,
-            self.compute_edge()?,
+            &self.compute_edge()?,
         );
         Ok(())
     }
 
-    fn complete(&mut self, engine: &mut Engine) -> Result<(), String> {
+    // Returns the finished shape's container, or None if it was too small to keep
+    fn complete(&mut self, engine: &mut Engine) -> Result<Option<ContainerId>, String> {
         if (self.end_position - self.start_position).length() < std::f32::EPSILON {
             engine.get_library_mut().remove_shape(&self.item_id);
             engine.remove_container(&self.container_id).unwrap();
+            Ok(None)
         } else {
             update_library(
                 &mut *engine.get_library_mut(),
                 self.item_id,
                 &self.shape_prototype,
-                self.compute_edge()?,
+                &self.compute_edge()?,
             );
-            engine.refresh_bounds(&self.container_id)
+            engine.refresh_bounds(&self.container_id);
+            Ok(Some(self.container_id))
         }
-        Ok(())
     }
 }
@@ -1,36 +1,88 @@
-use crate::messages::EditMessage;
+use crate::messages::{EditMessage, ToolMessage};
 use crate::{
-    scratch_pad::{ScratchPad, EDIT_LAYER},
+    anchoring::AnchorPreset,
+    assets::ImportedAsset,
+    scratch_pad::{ScratchPad, EDIT_LAYER, EDIT_LAYER_NAME},
+    symbols::SymbolEditor,
     tools::SelectionShape,
 };
 use fluster_core::{
-    ecs::resources::{FrameTime, Library, QuadTreeLayerOptions, QuadTreeQuery, QuadTrees},
+    actions::{
+        Action, BoundsKindDefinition, ContainerCreationDefintition, ContainerCreationProperty,
+        ContainerUpdateDefintition, ContainerUpdateProperty, RectPoints,
+    },
+    baking::{Rasterizer, SpriteSheet, SpriteSheetBuilder, BAKED_TAG},
+    ecs::{
+        components::{Anchor, DisplayKind},
+        resources::{
+            Camera, DebugFlags, FrameTime, Library, LibraryItemMetadata, QuadTreeLayerOptions,
+            QuadTreeQuery, QuadTrees, RuntimePolicy, StageArea,
+        },
+    },
     engine::{Engine, SelectionHandle},
-    factories::new_display_container,
+    factories::{new_display_container, new_display_container_with_collision},
+    linking::{AssetResolver, LinkedAsset},
+    serialization::{remap_colliding_ids, DefinedIds},
+    tween::Easing,
     types::{
-        basic::{ContainerId, LibraryId},
-        shapes::{Edge, Shape},
+        basic::{ContainerId, LibraryId, ScaleRotationTranslation},
+        shapes::{AugmentedShape, Contour, Edge, PathTrim, Shape},
     },
 };
 use palette::{LinSrgb, LinSrgba};
+use pathfinder_content::pattern::Pattern;
 use pathfinder_content::stroke::{LineCap, LineJoin, StrokeStyle};
 use pathfinder_geometry::rect::RectF;
 use pathfinder_geometry::transform2d::Transform2F;
 use pathfinder_geometry::vector::{Vector2F, Vector2I};
-use std::collections::HashSet;
-use std::{mem, time::Duration};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::{
+    mem,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+#[derive(Clone, Debug)]
+struct CopiedContainer {
+    id: ContainerId,
+    // None for the container that was copied, which is pasted under the root
+    parent: Option<ContainerId>,
+    transform: Transform2F,
+    display: Option<LibraryId>,
+    view_rect: Option<RectF>,
+    anchor: Option<Anchor>,
+    path_trim: Option<PathTrim>,
+}
+
+/// A container, its children, and the library items they display, copied out of one document to
+/// paste into any document.
+// TODO: copy tweens, colorings and the rest of the components
+#[derive(Clone, Debug)]
+pub struct ContainerClipboard {
+    // Parents before their children
+    containers: Vec<CopiedContainer>,
+    shapes: Vec<(LibraryId, Arc<Shape>)>,
+    textures: Vec<(LibraryId, Arc<Pattern>)>,
+    metadata: Vec<(LibraryId, LibraryItemMetadata)>,
+    sprite_sheets: Vec<(LibraryId, SpriteSheet)>,
+}
 
-pub struct StageState<'a, 'b> {
+pub struct StageState {
     background_color: LinSrgb,
     root_container_id: ContainerId,
     handle_ids: (ContainerId, LibraryId),
+    selection_ids: (ContainerId, LibraryId),
     size: Vector2I,
     scale: f32,
     scratch_pad: ScratchPad,
-    engine: Engine<'a, 'b>,
+    symbol: Option<SymbolEditor>,
+    // Containers that aren't drawn, so can't be selected either
+    hidden: HashSet<ContainerId>,
+    engine: Engine,
 }
 
-impl<'a, 'b> StageState<'a, 'b> {
+impl StageState {
     pub fn new(stage_size: Vector2I, background_color: LinSrgb) -> Self {
         let root_container_id = ContainerId::new();
         let mut quad_trees = QuadTrees::default();
@@ -40,6 +92,13 @@ impl<'a, 'b> StageState<'a, 'b> {
             QuadTreeLayerOptions::new(12.0),
         );
         let mut engine = Engine::new(root_container_id, Library::default(), quad_trees);
+        engine.set_stage_area(StageArea::new(stage_size.to_f32()));
+        // Nothing reads the errors back, and a half edited scene shouldn't stop the stage
+        engine.set_runtime_policy(RuntimePolicy::Lenient);
+        engine
+            .get_layer_registry_mut()
+            .declare_reserved(EDIT_LAYER_NAME, EDIT_LAYER)
+            .unwrap();
         let handle_library_id = LibraryId::new();
         let handle_container_id = new_display_container(
             &mut engine,
@@ -47,17 +106,28 @@ impl<'a, 'b> StageState<'a, 'b> {
             Transform2F::default(),
             handle_library_id,
         );
+        let selection_library_id = LibraryId::new();
+        let selection_container_id = new_display_container(
+            &mut engine,
+            root_container_id,
+            Transform2F::default(),
+            selection_library_id,
+        );
         let mut new_self = Self {
             background_color,
             root_container_id,
             handle_ids: (handle_container_id, handle_library_id),
+            selection_ids: (selection_container_id, selection_library_id),
             size: stage_size,
             scale: 1.0,
             scratch_pad: ScratchPad::default(),
+            symbol: None,
+            hidden: HashSet::new(),
             engine,
         };
         // Need to init the draw_handle container before we init scene data so we don't compute_bounds doesn't throw because it can't find a library item
         new_self.update_draw_handle(vec![]);
+        new_self.update_selection_outline(vec![]);
         // NOTE: currently making edit collision 3x the stage size to allow for overdraw.
 
         // Need to init scene. Since StageState already knows how to set that up, just call into it
@@ -69,7 +139,7 @@ impl<'a, 'b> StageState<'a, 'b> {
         self.background_color
     }
 
-    pub fn engine(&self) -> &Engine<'a, 'b> {
+    pub fn engine(&self) -> &Engine {
         &self.engine
     }
 
@@ -77,17 +147,58 @@ impl<'a, 'b> StageState<'a, 'b> {
         &self.root_container_id
     }
 
+    /// Containers the editor uses to draw its own overlays. These are not part of the scene.
+    pub fn editor_containers(&self) -> [ContainerId; 2] {
+        [self.handle_ids.0, self.selection_ids.0]
+    }
+
     pub fn draw_handles(&mut self, handles: Vec<SelectionHandle>) -> bool {
         let mut edges = vec![];
         for handle in handles {
+            let shape_edges = handle
+                .shape_id()
+                .and_then(|shape_id| self.engine.get_library().get_shape(&shape_id))
+                .map(|shape| shape.edge_list(handle.morph()))
+                .unwrap_or_default();
+            let transform = *handle.world_transform();
             for vertex_handle in handle.handles() {
+                let position = vertex_handle.position();
+                let (edge_id, vertex_id) = (vertex_handle.edge_id(), vertex_handle.vertex_id());
+                let is_control = shape_edges
+                    .get(edge_id)
+                    .map(|edge| edge.is_control_point(vertex_id))
+                    .unwrap_or_default();
+                if !is_control {
+                    edges.extend(
+                        Edge::new_ellipse(
+                            Vector2F::splat(5.0),
+                            Transform2F::from_translation(position),
+                        )
+                        .into_iter(),
+                    );
+                    continue;
+                }
+                // Controls are squares, with tangent lines back to their anchor and out to the
+                // control on the other side of the anchor
                 edges.extend(
-                    Edge::new_ellipse(
-                        Vector2F::splat(5.0),
-                        Transform2F::from_translation(vertex_handle.position()),
+                    Edge::new_rect(
+                        Vector2F::splat(8.0),
+                        Transform2F::from_translation(position - Vector2F::splat(4.0)),
                     )
                     .into_iter(),
                 );
+                if let Some((anchor, opposite)) =
+                    Edge::handle_pair(&shape_edges, edge_id, vertex_id)
+                {
+                    let anchor = transform * anchor;
+                    edges.push(Edge::Move(position));
+                    edges.push(Edge::Line(anchor));
+                    if let Some(opposite) = opposite
+                        .and_then(|(edge_id, vertex_id)| shape_edges[edge_id].point(vertex_id))
+                    {
+                        edges.push(Edge::Line(transform * opposite));
+                    }
+                }
             }
         }
         let redraw_needed = edges.len() > 0
@@ -106,7 +217,7 @@ impl<'a, 'b> StageState<'a, 'b> {
             self.handle_ids.1,
             Shape::Path {
                 color: LinSrgba::new(0.3, 0.8, 0.7, 1.0),
-                edges,
+                contours: Contour::from_edges(&edges),
                 stroke_style: StrokeStyle {
                     line_width: 2.0,
                     line_cap: LineCap::default(),
@@ -116,11 +227,682 @@ impl<'a, 'b> StageState<'a, 'b> {
         );
     }
 
+    /// Outlines the bounds of the selected container.
+    pub fn draw_selection(&mut self, selected: Option<&ContainerId>) {
+        let edges = selected
+            .and_then(|container_id| self.engine.get_bounds(container_id))
+            .map(|bounds| {
+                Edge::new_rect(
+                    bounds.size(),
+                    Transform2F::from_translation(bounds.origin()),
+                )
+            })
+            .unwrap_or_default();
+        self.update_selection_outline(edges);
+    }
+
+    fn update_selection_outline(&mut self, edges: Vec<Edge>) {
+        self.engine.get_library_mut().add_shape(
+            self.selection_ids.1,
+            Shape::Path {
+                color: LinSrgba::new(0.2, 0.5, 1.0, 1.0),
+                contours: Contour::from_edges(&edges),
+                stroke_style: StrokeStyle {
+                    line_width: 1.0,
+                    line_cap: LineCap::default(),
+                    line_join: LineJoin::default(),
+                },
+            },
+        );
+    }
+
+    /// Moves `container_id` under `new_parent`, adjusting its local transform so it stays in the
+    /// same place on the stage.
+    pub fn reparent(
+        &mut self,
+        container_id: &ContainerId,
+        new_parent: &ContainerId,
+    ) -> Result<(), String> {
+        if *container_id == self.root_container_id {
+            return Err("The root container cannot be moved".to_owned());
+        }
+        if self.symbol.is_some() {
+            return Err("Containers cannot be moved while a symbol is open".to_owned());
+        }
+        // Walk up from the new parent so a container can't be moved under its own children
+        if self
+            .engine
+            .get_self_and_parents(new_parent)
+            .contains(container_id)
+        {
+            return Err(format!(
+                "Cannot move container {:?} under one of its own children",
+                container_id
+            ));
+        }
+        let world_transform = self
+            .engine
+            .get_world_transform(container_id)
+            .ok_or_else(|| format!("Container {:?} not found", container_id))?;
+        let parent_transform = self
+            .engine
+            .get_world_transform(new_parent)
+            .ok_or_else(|| format!("Container {:?} not found", new_parent))?;
+        let local_transform = parent_transform.inverse() * world_transform;
+        self.engine
+            .update_container(&ContainerUpdateDefintition::new(
+                *container_id,
+                vec![
+                    ContainerUpdateProperty::Parent(*new_parent),
+                    ContainerUpdateProperty::SetTransform(
+                        ScaleRotationTranslation::from_transform(local_transform),
+                    ),
+                ],
+            ));
+        self.update_scene();
+        Ok(())
+    }
+
+    /// Pins the container to a preset point on the stage, offset so it stays where it is. None
+    /// unpins it, also leaving it in place.
+    pub fn set_anchor(
+        &mut self,
+        container_id: &ContainerId,
+        preset: Option<AnchorPreset>,
+    ) -> Result<(), String> {
+        if *container_id == self.root_container_id {
+            return Err("The root container cannot be anchored".to_owned());
+        }
+        let property = match preset {
+            Some(preset) => {
+                let translation = self
+                    .engine
+                    .get_local_transform(container_id)
+                    .ok_or_else(|| format!("Container {:?} not found", container_id))?
+                    .translation();
+                let mut anchor = Anchor::new(preset.point(), Vector2F::zero());
+                anchor.offset =
+                    translation - anchor.position(self.engine.get_stage_area().anchor_rect());
+                ContainerUpdateProperty::Anchor(anchor)
+            }
+            None => ContainerUpdateProperty::RemoveAnchor,
+        };
+        self.engine
+            .update_container(&ContainerUpdateDefintition::new(
+                *container_id,
+                vec![property],
+            ));
+        self.update_scene();
+        Ok(())
+    }
+
+    /// Replaces the container's vector display with a baked sprite of it. The shape stays in the
+    /// library, and the stage keeps drawing it in place of the sprite so it can still be edited.
+    // TODO: bake a container and its children over several frames, for flipbooks
+    pub fn bake_to_sprite(
+        &mut self,
+        container_id: &ContainerId,
+        rasterizer: &mut impl Rasterizer,
+    ) -> Result<(), String> {
+        let shape_id = match self.engine.get_display(container_id) {
+            Some((shape_id, DisplayKind::Vector)) => shape_id,
+            _ => {
+                return Err(format!(
+                    "Container {:?} doesn't display a shape",
+                    container_id
+                ))
+            }
+        };
+        // Baking moves the container's origin, which would move its children too
+        if !self
+            .engine
+            .get_children(container_id)
+            .unwrap_or_default()
+            .is_empty()
+        {
+            return Err("Only containers without children can be baked".to_owned());
+        }
+        let morph = self
+            .engine
+            .get_drawable_items()
+            .into_iter()
+            .find(|drawable_item| drawable_item.container_id == *container_id)
+            .map_or(0.0, |drawable_item| drawable_item.morph);
+        let local_transform = self
+            .engine
+            .get_local_transform(container_id)
+            .ok_or_else(|| format!("Container {:?} not found", container_id))?;
+        let mut builder = SpriteSheetBuilder::default();
+        builder.add_shape(&self.engine.get_library(), &shape_id, morph)?;
+        let baked = builder.bake(rasterizer, LibraryId::new())?;
+        let frame = baked.sheet.frames[0];
+        {
+            let mut library = self.engine.get_library_mut();
+            let mut metadata = library
+                .get_metadata(&shape_id)
+                .map(|metadata| LibraryItemMetadata::new(format!("{} (baked)", metadata.name)))
+                .unwrap_or_default();
+            metadata.tags.push(BAKED_TAG.to_owned());
+            library.add_texture(baked.id, baked.bitmap.clone().pattern());
+            library.set_metadata(baked.id, metadata);
+            library.set_sprite_sheet(baked.id, baked.sheet.clone());
+        }
+        self.engine
+            .update_container(&ContainerUpdateDefintition::new(
+                *container_id,
+                vec![
+                    ContainerUpdateProperty::Display(baked.id),
+                    ContainerUpdateProperty::SetViewRect(frame.rect),
+                    ContainerUpdateProperty::SetTransform(
+                        ScaleRotationTranslation::from_transform(
+                            local_transform * frame.transform(baked.sheet.scale),
+                        ),
+                    ),
+                ],
+            ));
+        self.update_scene();
+        Ok(())
+    }
+
+    /// Copies `container_id` and its children. The copied container keeps its place on the stage,
+    /// not its parent.
+    pub fn copy(&self, container_id: &ContainerId) -> Result<ContainerClipboard, String> {
+        if *container_id == self.root_container_id {
+            return Err("The root container cannot be copied".to_owned());
+        }
+        // The open symbol's parts are the editor's, not the scene's
+        if self.symbol.is_some() {
+            return Err("Close the symbol before copying".to_owned());
+        }
+        let library = self.engine.get_library();
+        let mut clipboard = ContainerClipboard {
+            containers: vec![],
+            shapes: vec![],
+            textures: vec![],
+            metadata: vec![],
+            sprite_sheets: vec![],
+        };
+        let mut queue = VecDeque::new();
+        queue.push_back((*container_id, None));
+        while let Some((id, parent)) = queue.pop_front() {
+            let transform = match parent {
+                Some(..) => self.engine.get_local_transform(&id),
+                None => self.engine.get_world_transform(&id),
+            }
+            .ok_or_else(|| format!("Container {:?} not found", id))?;
+            let display = self.engine.get_display(&id).map(|(display, _)| display);
+            if let Some(display) = display {
+                // Shapes are copied resolved, so shapes they reference don't need copying too
+                if let Some(shape) = library.get_resolved_shape(&display) {
+                    clipboard.shapes.push((display, shape));
+                } else if let Some(texture) = library.get_texture(&display) {
+                    clipboard.textures.push((display, texture));
+                }
+                if let Some(metadata) = library.get_metadata(&display) {
+                    clipboard.metadata.push((display, metadata.clone()));
+                }
+                if let Some(sprite_sheet) = library.get_sprite_sheet(&display) {
+                    clipboard
+                        .sprite_sheets
+                        .push((display, sprite_sheet.clone()));
+                }
+            }
+            clipboard.containers.push(CopiedContainer {
+                id,
+                parent,
+                transform,
+                display,
+                view_rect: self.engine.get_view_rect(&id),
+                anchor: self.engine.get_anchor(&id),
+                path_trim: self.engine.get_path_trim(&id),
+            });
+            for child in self.engine.get_children(&id).unwrap_or_default() {
+                queue.push_back((child, Some(id)));
+            }
+        }
+        Ok(clipboard)
+    }
+
+    /// Pastes a copy of `clipboard` under the root, with new container ids. Library items this
+    /// document already has are shared rather than replaced. Returns the pasted container.
+    pub fn paste(&mut self, clipboard: &ContainerClipboard) -> Result<ContainerId, String> {
+        if self.symbol.is_some() {
+            return Err("Close the symbol before pasting".to_owned());
+        }
+        self.add_copied_library_items(clipboard);
+        let new_ids = clipboard
+            .containers
+            .iter()
+            .map(|copied| (copied.id, ContainerId::new()))
+            .collect::<HashMap<_, _>>();
+        self.create_copied_containers(clipboard, &new_ids);
+        self.update_scene();
+        clipboard
+            .containers
+            .first()
+            .map(|copied| new_ids[&copied.id])
+            .ok_or_else(|| "Nothing to paste".to_owned())
+    }
+
+    /// The containers under the root, less the editor's own, for `restore` to put back. Unlike
+    /// a paste, restoring keeps the containers' ids, so the timeline and outliner still find them.
+    pub fn snapshot(&self) -> Result<Vec<ContainerClipboard>, String> {
+        let editor_containers = self.editor_containers();
+        self.engine
+            .get_children(&self.root_container_id)
+            .unwrap_or_default()
+            .iter()
+            .filter(|container_id| !editor_containers.contains(container_id))
+            .map(|container_id| self.copy(container_id))
+            .collect()
+    }
+
+    /// Swaps the scene under the root for one taken by `snapshot`. Shapes edited since are put
+    /// back as they were, and anything being drawn is dropped.
+    pub fn restore(&mut self, snapshot: &[ContainerClipboard]) -> Result<(), String> {
+        if self.symbol.is_some() {
+            return Err("Close the symbol before undoing".to_owned());
+        }
+        let editor_containers = self.editor_containers();
+        for container_id in self
+            .engine
+            .get_children(&self.root_container_id)
+            .unwrap_or_default()
+            .iter()
+            .filter(|container_id| !editor_containers.contains(container_id))
+        {
+            self.engine
+                .remove_container_and_children(container_id)
+                .map_err(|error| format!("{:?}", error))?;
+        }
+        self.scratch_pad = ScratchPad::default();
+        for clipboard in snapshot {
+            for (id, shape) in clipboard.shapes.iter() {
+                let current = self.engine.get_library().get_resolved_shape(id);
+                if matches!(current, Some(current) if current != *shape) {
+                    self.engine.replace_shape(*id, shape.as_ref().clone())?;
+                }
+            }
+            self.add_copied_library_items(clipboard);
+            let ids = clipboard
+                .containers
+                .iter()
+                .map(|copied| (copied.id, copied.id))
+                .collect::<HashMap<_, _>>();
+            self.create_copied_containers(clipboard, &ids);
+        }
+        self.update_scene();
+        Ok(())
+    }
+
+    fn create_copied_containers(
+        &mut self,
+        clipboard: &ContainerClipboard,
+        new_ids: &HashMap<ContainerId, ContainerId>,
+    ) {
+        for copied in clipboard.containers.iter() {
+            let parent = match copied.parent {
+                Some(parent) => new_ids[&parent],
+                None => self.root_container_id,
+            };
+            let mut properties = vec![ContainerCreationProperty::Transform(
+                ScaleRotationTranslation::from_transform(copied.transform),
+            )];
+            if let Some(display) = copied.display {
+                properties.push(ContainerCreationProperty::Display(display));
+                properties.push(ContainerCreationProperty::Bounds(
+                    BoundsKindDefinition::Display,
+                ));
+                properties.push(ContainerCreationProperty::Layer(EDIT_LAYER));
+            }
+            if let Some(view_rect) = copied.view_rect {
+                properties.push(ContainerCreationProperty::ViewRect(RectPoints::from_rect(
+                    &view_rect,
+                )));
+            }
+            if let Some(anchor) = copied.anchor {
+                properties.push(ContainerCreationProperty::Anchor(anchor));
+            }
+            if let Some(path_trim) = copied.path_trim {
+                properties.push(ContainerCreationProperty::PathTrim(path_trim));
+            }
+            self.engine
+                .create_container(&ContainerCreationDefintition::new(
+                    parent,
+                    new_ids[&copied.id],
+                    properties,
+                ));
+        }
+    }
+
+    // Items the library already has are left alone
+    fn add_copied_library_items(&mut self, clipboard: &ContainerClipboard) {
+        let mut library = self.engine.get_library_mut();
+        for (id, shape) in clipboard.shapes.iter() {
+            if !library.contains_shape(id) {
+                library.add_shape(*id, shape.as_ref().clone());
+            }
+        }
+        for (id, texture) in clipboard.textures.iter() {
+            if !library.contains_texture(id) {
+                library.add_texture(*id, texture.as_ref().clone());
+            }
+        }
+        for (id, metadata) in clipboard.metadata.iter() {
+            if library.get_metadata(id).is_none() {
+                library.set_metadata(*id, metadata.clone());
+            }
+        }
+        for (id, sprite_sheet) in clipboard.sprite_sheets.iter() {
+            if library.get_sprite_sheet(id).is_none() {
+                library.set_sprite_sheet(*id, sprite_sheet.clone());
+            }
+        }
+    }
+
+    /// Imports the setup of another action stream, such as a clip saved from another document,
+    /// under the root. Ids that collide with this document's are renamed first, with
+    /// `remap_colliding_ids`. Returns the imported containers that were parented to the clip's
+    /// root. Linked items are loaded through `resolver`.
+    // TODO: backgrounds and the clip's frames are not imported yet
+    pub fn import_actions(
+        &mut self,
+        mut actions: Vec<Action>,
+        resolver: &dyn AssetResolver,
+    ) -> Result<Vec<ContainerId>, String> {
+        if self.symbol.is_some() {
+            return Err("Close the symbol before importing".to_owned());
+        }
+        let mut existing = DefinedIds::default();
+        let mut queue = VecDeque::new();
+        queue.push_back(self.root_container_id);
+        while let Some(id) = queue.pop_front() {
+            existing.containers.insert(id);
+            queue.extend(self.engine.get_children(&id).unwrap_or_default());
+        }
+        {
+            let library = self.engine.get_library();
+            existing.library_items.extend(
+                library
+                    .shapes()
+                    .map(|(id, _)| *id)
+                    .chain(library.textures().map(|(id, _)| *id)),
+            );
+        }
+        remap_colliding_ids(&existing, &mut actions);
+        let clip_root = actions.iter().find_map(|action| match action {
+            Action::CreateRoot(id) => Some(*id),
+            _ => None,
+        });
+        let mut imported = vec![];
+        for action in actions.iter() {
+            match action {
+                // Everything after the first frame is the clip's timeline
+                Action::PresentFrame(..) => break,
+                Action::DefineShape { id, shape } => {
+                    self.engine.get_library_mut().add_shape(*id, shape.clone())
+                }
+                Action::LoadBitmap { id, bitmap } => self
+                    .engine
+                    .get_library_mut()
+                    .add_texture(*id, bitmap.clone().pattern()),
+                Action::LoadTexture { id, texture } => self
+                    .engine
+                    .get_library_mut()
+                    .add_texture(*id, texture.to_pattern()),
+                Action::LibraryRef { id, location } => {
+                    let mut library = self.engine.get_library_mut();
+                    match resolver.resolve(location)? {
+                        LinkedAsset::Shape(shape) => library.add_shape(*id, shape),
+                        LinkedAsset::Bitmap(mut bitmap) => {
+                            library.add_texture(*id, bitmap.pattern())
+                        }
+                        LinkedAsset::Texture(texture) => {
+                            library.add_texture(*id, texture.to_pattern())
+                        }
+                    }
+                }
+                Action::SetLibraryMetadata { id, metadata } => self
+                    .engine
+                    .get_library_mut()
+                    .set_metadata(*id, metadata.clone()),
+                Action::DefineSpriteSheet { id, sheet } => self
+                    .engine
+                    .get_library_mut()
+                    .set_sprite_sheet(*id, sheet.clone()),
+                Action::CreateContainer(definition) => {
+                    let mut properties = definition.properties().clone();
+                    let displays = properties
+                        .iter()
+                        .any(|property| matches!(property, ContainerCreationProperty::Display(..)));
+                    // Imported shapes are selectable like drawn ones
+                    if displays {
+                        properties.retain(|property| {
+                            !matches!(property, ContainerCreationProperty::Layer(..))
+                        });
+                        properties.push(ContainerCreationProperty::Layer(EDIT_LAYER));
+                    }
+                    let parent = if Some(*definition.parent()) == clip_root {
+                        imported.push(*definition.id());
+                        self.root_container_id
+                    } else {
+                        *definition.parent()
+                    };
+                    self.engine
+                        .create_container(&ContainerCreationDefintition::new(
+                            parent,
+                            *definition.id(),
+                            properties,
+                        ));
+                }
+                _ => (),
+            }
+        }
+        self.update_scene();
+        Ok(imported)
+    }
+
+    /// See `ScratchPad::start_morph`.
+    pub fn start_morph(&mut self, container_id: &ContainerId) -> Result<(), String> {
+        self.scratch_pad
+            .start_morph(&mut self.engine, container_id)?;
+        self.update_scene();
+        Ok(())
+    }
+
+    /// See `ScratchPad::finish_morph`.
+    pub fn finish_morph(&mut self) -> Result<ContainerId, String> {
+        let container_id = self.scratch_pad.finish_morph(&mut self.engine)?;
+        self.sync_symbol();
+        self.update_scene();
+        Ok(container_id)
+    }
+
+    pub fn is_making_morph(&self) -> bool {
+        self.scratch_pad.is_making_morph()
+    }
+
+    /// Library items imported from a file, and the file.
+    pub fn library_origins(&self) -> Vec<(LibraryId, PathBuf)> {
+        let library = self.engine.get_library();
+        library
+            .shapes()
+            .map(|(id, _)| id)
+            .chain(library.textures().map(|(id, _)| id))
+            .filter_map(|id| {
+                let origin = library.get_metadata(id)?.origin.as_ref()?;
+                Some((*id, PathBuf::from(origin)))
+            })
+            .collect()
+    }
+
+    /// The actions that play the document: the background, the library as
+    /// `Library::to_actions` writes it, less the editor's own handle and selection shapes, then
+    /// the timeline's keyframes one frame at a time.
+    pub fn export_actions(&self, timeline_state: &TimelineState) -> Result<Vec<Action>, String> {
+        let editor_items = [self.handle_ids.1, self.selection_ids.1];
+        let mut actions = vec![
+            Action::CreateRoot(self.root_container_id),
+            Action::SetBackground {
+                color: self.background_color,
+            },
+        ];
+        actions.extend(
+            self.engine
+                .get_library()
+                .to_actions()?
+                .into_iter()
+                .filter(|action| {
+                    !matches!(action, Action::DefineShape { id, .. }
+                        | Action::SetLibraryMetadata { id, .. } if editor_items.contains(id))
+                }),
+        );
+        actions.push(Action::EndInitialization);
+        let mut keyframes = timeline_state
+            .keyframe_actions(&self.engine, &self.root_container_id)
+            .into_iter()
+            .peekable();
+        for frame in 0..timeline_state.frame_count() {
+            while let Some((start, _)) = keyframes.peek() {
+                if *start != frame {
+                    break;
+                }
+                if let Some((_, action)) = keyframes.next() {
+                    actions.push(action);
+                }
+            }
+            actions.push(Action::PresentFrame(frame, 1));
+        }
+        Ok(actions)
+    }
+
+    /// Adds an item read from a file to the library, recording the file as its origin so it is
+    /// reloaded when the file changes, and displays it in a new container under the root.
+    pub fn import_asset(
+        &mut self,
+        path: &Path,
+        asset: ImportedAsset,
+    ) -> Result<ContainerId, String> {
+        if self.symbol.is_some() {
+            return Err("Close the symbol before importing".to_owned());
+        }
+        let library_id = LibraryId::new();
+        {
+            let mut library = self.engine.get_library_mut();
+            match asset {
+                ImportedAsset::Shape(shape) => library.add_shape(library_id, shape),
+                ImportedAsset::Texture(pattern) => library.add_texture(library_id, pattern),
+            }
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut metadata = LibraryItemMetadata::new(name);
+            metadata.origin = Some(path.to_string_lossy().into_owned());
+            library.set_metadata(library_id, metadata);
+        }
+        let container_id = new_display_container_with_collision(
+            &mut self.engine,
+            self.root_container_id,
+            Transform2F::default(),
+            library_id,
+            vec![EDIT_LAYER],
+        );
+        self.update_scene();
+        Ok(container_id)
+    }
+
+    /// Swaps a library item for a version re-imported from its file. See `Engine::replace_shape`.
+    pub fn replace_library_item(
+        &mut self,
+        id: LibraryId,
+        asset: ImportedAsset,
+    ) -> Result<(), String> {
+        match asset {
+            ImportedAsset::Shape(shape) => self.engine.replace_shape(id, shape)?,
+            ImportedAsset::Texture(pattern) => self.engine.replace_texture(id, pattern)?,
+        }
+        self.update_scene();
+        Ok(())
+    }
+
+    /// See `ScratchPad::take_completed_shapes`.
+    pub fn take_completed_shapes(&mut self) -> Vec<ContainerId> {
+        self.scratch_pad.take_completed_shapes()
+    }
+
+    /// Opens the Group or Clip shape the container displays for editing in place. Until it's
+    /// closed only the symbol's shapes can be selected, and edits show on every instance.
+    pub fn open_symbol(&mut self, container_id: &ContainerId) -> Result<(), String> {
+        if self.symbol.is_some() {
+            return Err("A symbol is already open".to_owned());
+        }
+        if self.is_making_morph() {
+            return Err("Finish the morph before opening a symbol".to_owned());
+        }
+        if self.editor_containers().contains(container_id) {
+            return Err(format!("Container {:?} is not a symbol", container_id));
+        }
+        self.symbol = Some(SymbolEditor::open(&mut self.engine, container_id)?);
+        self.update_scene();
+        Ok(())
+    }
+
+    /// Returns the instance the symbol was opened from.
+    pub fn close_symbol(&mut self) -> Result<ContainerId, String> {
+        if self.is_making_morph() {
+            return Err("Finish the morph before closing the symbol".to_owned());
+        }
+        let symbol = self
+            .symbol
+            .take()
+            .ok_or_else(|| "No symbol is open".to_owned())?;
+        let instance = symbol.close(&mut self.engine);
+        self.update_scene();
+        instance
+    }
+
+    /// The instance the open symbol was opened from. It's hidden while the symbol is open, since
+    /// the symbol's parts draw in its place.
+    pub fn open_symbol_instance(&self) -> Option<&ContainerId> {
+        self.symbol.as_ref().map(SymbolEditor::instance)
+    }
+
+    /// While a symbol is open, the containers that are drawn normally. Everything else is dimmed.
+    pub fn symbol_focus(&self) -> Option<HashSet<ContainerId>> {
+        self.symbol.as_ref().map(|symbol| {
+            symbol
+                .parts()
+                .copied()
+                .chain(self.editor_containers().iter().copied())
+                .collect()
+        })
+    }
+
+    fn sync_symbol(&mut self) {
+        if let Some(symbol) = &self.symbol {
+            if let Err(error) = symbol.sync(&mut self.engine) {
+                println!("{:}", error);
+            }
+        }
+    }
+
     pub fn apply_edit(&mut self, edit_message: &EditMessage) -> bool {
+        // TODO: add shapes drawn while a symbol is open to the symbol
+        let starts_shape = matches!(
+            edit_message,
+            EditMessage::ToolUpdate(ToolMessage::PathStart { .. })
+                | EditMessage::ToolUpdate(ToolMessage::TemplateStart { .. })
+        );
+        if self.symbol.is_some() && starts_shape {
+            println!("Shapes cannot be drawn while a symbol is open");
+            return false;
+        }
         // TODO: return a proper message type!
         match self.scratch_pad.apply_edit(edit_message, &mut self.engine) {
             Ok(res) => {
                 if res {
+                    self.sync_symbol();
                     self.update_scene();
                     true
                 } else {
@@ -138,13 +920,34 @@ impl<'a, 'b> StageState<'a, 'b> {
         self.engine.update(FrameTime {
             delta_frame: 1,
             delta_time: Duration::from_secs_f64(1.0 / 60.0),
-        })
+        });
     }
 
     pub fn scale(&self) -> f32 {
         self.scale
     }
 
+    pub fn camera(&self) -> Camera {
+        self.engine.get_camera()
+    }
+
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.engine.set_camera(camera);
+    }
+
+    pub fn debug_flags(&self) -> DebugFlags {
+        self.engine.get_debug_flags()
+    }
+
+    pub fn set_debug_flags(&mut self, debug_flags: DebugFlags) {
+        self.engine.set_debug_flags(debug_flags);
+    }
+
+    /// The size of the stage in stage units.
+    pub fn stage_size(&self) -> Vector2F {
+        self.size.to_f32() * self.scale
+    }
+
     pub fn width(&self) -> i32 {
         self.size.x()
     }
@@ -153,8 +956,12 @@ impl<'a, 'b> StageState<'a, 'b> {
         self.size.y()
     }
 
+    pub fn set_hidden(&mut self, hidden: HashSet<ContainerId>) {
+        self.hidden = hidden;
+    }
+
     pub fn query_selection(&self, selection_shape: &SelectionShape) -> Vec<SelectionHandle> {
-        match selection_shape {
+        let selection = match selection_shape {
             SelectionShape::None => vec![],
             SelectionShape::Point(point) => self
                 .engine
@@ -162,13 +969,37 @@ impl<'a, 'b> StageState<'a, 'b> {
             SelectionShape::Area(rect) => self
                 .engine
                 .spatial_query(&QuadTreeQuery::Rect(EDIT_LAYER, *rect)),
-        }
+        };
+        selection
+            .into_iter()
+            .filter(|selection_handle| {
+                let container_id = selection_handle.container_id();
+                // The rest of the stage is locked while a symbol is open
+                !self.hidden.contains(container_id)
+                    && self
+                        .symbol
+                        .as_ref()
+                        .map_or(true, |symbol| symbol.is_part(container_id))
+            })
+            .collect()
     }
 }
 
+/// The morph index a container reaches on a frame. Keyframes tween linearly into the next one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MorphKeyframe {
+    pub frame: u32,
+    pub morph_index: f32,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct TimelineState {
     layers: Vec<LayerState>,
+    morph_keyframes: HashMap<ContainerId, Vec<MorphKeyframe>>,
+    current_frame: u32,
+    // The layer drawing adds shapes to
+    active_layer: usize,
+    auto_advance: bool,
 }
 
 impl TimelineState {
@@ -176,9 +1007,196 @@ impl TimelineState {
         let layer = LayerState::new(root_id);
         return Self {
             layers: vec![layer],
+            morph_keyframes: HashMap::new(),
+            current_frame: 0,
+            active_layer: 0,
+            auto_advance: false,
         };
     }
 
+    pub fn current_frame(&self) -> u32 {
+        self.current_frame
+    }
+
+    /// Frames up to the end of the last keyframe on any layer.
+    pub fn frame_count(&self) -> u32 {
+        self.layers
+            .iter()
+            .filter_map(|layer| layer.frames.last())
+            .map(|(_, (start, length))| start + length)
+            .max()
+            .unwrap_or_default()
+    }
+
+    pub fn set_current_frame(&mut self, frame: u32) {
+        self.current_frame = frame;
+        for layer in self.layers.iter_mut() {
+            layer.set_current_frame(frame);
+        }
+    }
+
+    pub fn next_frame(&mut self) {
+        self.set_current_frame(self.current_frame.saturating_add(1));
+    }
+
+    pub fn previous_frame(&mut self) {
+        self.set_current_frame(self.current_frame.saturating_sub(1));
+    }
+
+    /// Starts an empty keyframe on the active layer at the current frame.
+    pub fn insert_blank_keyframe(&mut self) -> Result<(), String> {
+        let layer = self
+            .layers
+            .get_mut(self.active_layer)
+            .ok_or_else(|| format!("No layer {}", self.active_layer))?;
+        layer.insert_blank_keyframe(self.current_frame)?;
+        layer.set_current_frame(self.current_frame);
+        Ok(())
+    }
+
+    pub fn auto_advance(&self) -> bool {
+        self.auto_advance
+    }
+
+    /// When on, every finished shape moves the timeline to the next frame.
+    pub fn set_auto_advance(&mut self, auto_advance: bool) {
+        self.auto_advance = auto_advance;
+    }
+
+    /// Puts a shape that was just drawn on the current frame of the active layer. Drawing past
+    /// the layer's last keyframe starts a new one.
+    pub fn add_drawn_shape(&mut self, container_id: &ContainerId) {
+        if let Some(layer) = self.layers.get_mut(self.active_layer) {
+            layer.add_entity(self.current_frame, container_id);
+        }
+        if self.auto_advance {
+            self.next_frame();
+        } else {
+            self.set_current_frame(self.current_frame);
+        }
+    }
+
+    /// Containers on keyframes other than the ones showing on the current frame.
+    pub fn hidden_containers(&self) -> HashSet<ContainerId> {
+        self.layers
+            .iter()
+            .flat_map(|layer| layer.entities_off_frame(self.current_frame))
+            .collect()
+    }
+
+    /// The actions that play the layers' keyframes, with the frame each is applied on. Each layer
+    /// becomes one container under `parent`, and each keyframe a shape the container's display
+    /// swaps to on the keyframe's first frame.
+    // TODO: containers that don't display a shape, and the rest of their components
+    pub fn keyframe_actions(&self, engine: &Engine, parent: &ContainerId) -> Vec<(u32, Action)> {
+        let library = engine.get_library();
+        let mut actions = vec![];
+        for layer in self.layers.iter() {
+            let layer_id = ContainerId::new();
+            for (index, (frame_state, (start, _))) in layer.frames.iter().enumerate() {
+                let shapes = frame_state
+                    .entities()
+                    .filter_map(|container_id| {
+                        let library_id = match engine.get_display(container_id) {
+                            Some((library_id, DisplayKind::Vector)) => library_id,
+                            _ => return None,
+                        };
+                        Some(AugmentedShape {
+                            shape: library.get_resolved_shape(&library_id)?.as_ref().clone(),
+                            transform: engine.get_world_transform(container_id)?,
+                        })
+                    })
+                    .collect();
+                let shape_id = LibraryId::new();
+                actions.push((
+                    *start,
+                    Action::DefineShape {
+                        id: shape_id,
+                        shape: Shape::Group { shapes },
+                    },
+                ));
+                actions.push((
+                    *start,
+                    if index == 0 {
+                        Action::CreateContainer(ContainerCreationDefintition::new(
+                            *parent,
+                            layer_id,
+                            vec![
+                                ContainerCreationProperty::Transform(
+                                    ScaleRotationTranslation::from_transform(
+                                        Transform2F::default(),
+                                    ),
+                                ),
+                                ContainerCreationProperty::Display(shape_id),
+                            ],
+                        ))
+                    } else {
+                        Action::UpdateContainer(ContainerUpdateDefintition::new(
+                            layer_id,
+                            vec![ContainerUpdateProperty::Display(shape_id)],
+                        ))
+                    },
+                ));
+            }
+        }
+        // Stable, so each shape is still defined before it's displayed
+        actions.sort_by_key(|(frame, _)| *frame);
+        actions
+    }
+
+    /// Keys the container to morph from its start pose to its end pose over `duration_frames`,
+    /// replacing any morph keyframes in between.
+    pub fn key_morph(
+        &mut self,
+        container_id: &ContainerId,
+        start_frame: u32,
+        duration_frames: u32,
+    ) {
+        let end_frame = start_frame + duration_frames;
+        let keyframes = self.morph_keyframes.entry(*container_id).or_default();
+        keyframes.retain(|keyframe| keyframe.frame < start_frame || keyframe.frame > end_frame);
+        keyframes.push(MorphKeyframe {
+            frame: start_frame,
+            morph_index: 0.0,
+        });
+        keyframes.push(MorphKeyframe {
+            frame: end_frame,
+            morph_index: 1.0,
+        });
+        keyframes.sort_by_key(|keyframe| keyframe.frame);
+    }
+
+    pub fn get_morph_keyframes(&self, container_id: &ContainerId) -> &[MorphKeyframe] {
+        self.morph_keyframes
+            .get(container_id)
+            .map_or(&[], |keyframes| keyframes.as_slice())
+    }
+
+    /// The updates that play the container's morph keyframes, with the frame each is applied on.
+    pub fn morph_updates(&self, container_id: &ContainerId) -> Vec<(u32, ContainerUpdateProperty)> {
+        let keyframes = self.get_morph_keyframes(container_id);
+        keyframes
+            .first()
+            .map(|first| {
+                (
+                    first.frame,
+                    ContainerUpdateProperty::SetMorphIndex(first.morph_index),
+                )
+            })
+            .into_iter()
+            .chain(keyframes.windows(2).map(|pair| {
+                (
+                    pair[0].frame,
+                    ContainerUpdateProperty::MorphIndex(
+                        pair[1].morph_index,
+                        Easing::Linear,
+                        pair[1].frame - pair[0].frame,
+                    ),
+                )
+            }))
+            .collect()
+    }
+
     pub fn can_show_entity(&self, id: &ContainerId) -> bool {
         self.layers.iter().any(|layer| layer.can_show_entity(id))
     }
@@ -233,13 +1251,63 @@ impl LayerState {
     }
 
     pub fn set_current_frame(&mut self, current_frame_index: u32) {
-        if let Some(frame_index) = self.frames.iter().position(|(_, range)| {
-            range.0 <= current_frame_index || range.0 + range.1 > current_frame_index
-        }) {
-            self.current_frame_index = frame_index;
-        } else {
-            self.current_frame_index = std::usize::MAX;
+        self.current_frame_index = self
+            .keyframe_at(current_frame_index)
+            .unwrap_or(std::usize::MAX);
+    }
+
+    // The keyframe showing on `frame`. Keyframes hold until the next one starts.
+    fn keyframe_at(&self, frame: u32) -> Option<usize> {
+        self.frames
+            .iter()
+            .position(|(_, (start, length))| *start <= frame && frame < start + length)
+    }
+
+    /// Starts an empty keyframe on `frame`, cutting short the keyframe before it or holding it
+    /// until `frame` if it ended sooner. Call `set_current_frame` after, keyframe indices move.
+    pub fn insert_blank_keyframe(&mut self, frame: u32) -> Result<(), String> {
+        if self.frames.iter().any(|(_, (start, _))| *start == frame) {
+            return Err(format!("Frame {} is already a keyframe", frame));
+        }
+        let index = self
+            .frames
+            .iter()
+            .position(|(_, (start, _))| *start > frame)
+            .unwrap_or_else(|| self.frames.len());
+        let length = match self.frames.get(index) {
+            Some((_, (next_start, _))) => next_start - frame,
+            None => 1,
+        };
+        if let Some((_, (start, previous_length))) = index
+            .checked_sub(1)
+            .and_then(|previous| self.frames.get_mut(previous))
+        {
+            *previous_length = frame - *start;
         }
+        self.frames
+            .insert(index, (FrameState::Empty, (frame, length)));
+        Ok(())
+    }
+
+    pub fn add_entity(&mut self, frame: u32, id: &ContainerId) {
+        let index = match self.keyframe_at(frame) {
+            Some(index) => index,
+            None => {
+                // Nothing shows on this frame, so no keyframe can start on it either
+                self.insert_blank_keyframe(frame).unwrap();
+                self.keyframe_at(frame).unwrap()
+            }
+        };
+        self.frames[index].0.add_entity(id);
+    }
+
+    pub fn entities_off_frame(&self, frame: u32) -> Vec<ContainerId> {
+        self.frames
+            .iter()
+            .filter(|(_, (start, length))| frame < *start || frame >= start + length)
+            .flat_map(|(frame_state, _)| frame_state.entities())
+            .copied()
+            .collect()
     }
 }
 
@@ -263,6 +1331,15 @@ impl FrameState {
         }
     }
 
+    pub fn entities(&self) -> impl Iterator<Item = &ContainerId> {
+        match self {
+            Self::Key { entities } => Some(entities.iter()),
+            Self::Empty => None,
+        }
+        .into_iter()
+        .flatten()
+    }
+
     pub fn add_entity(&mut self, id: &ContainerId) {
         match self {
             Self::Key { entities } => {
@@ -292,3 +1369,43 @@ impl Default for FrameState {
         Self::Empty
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_container(stage_state: &mut StageState, parent: ContainerId) -> ContainerId {
+        let container_id = ContainerId::new();
+        stage_state
+            .engine
+            .create_container(&ContainerCreationDefintition::new(
+                parent,
+                container_id,
+                vec![ContainerCreationProperty::Transform(
+                    ScaleRotationTranslation::new(Vector2F::splat(1.0), 0.0, Vector2F::splat(10.0)),
+                )],
+            ));
+        stage_state.update_scene();
+        container_id
+    }
+
+    #[test]
+    fn it_reparents_under_a_nested_container() {
+        let mut stage_state = StageState::new(Vector2I::new(100, 100), LinSrgb::new(1.0, 1.0, 1.0));
+        let root = *stage_state.root();
+        let outer = add_container(&mut stage_state, root);
+        let inner = add_container(&mut stage_state, outer);
+        let moved = add_container(&mut stage_state, root);
+        stage_state.reparent(&moved, &inner).unwrap();
+        assert_eq!(stage_state.engine().get_parent(&moved), Some(inner));
+        // It stays where it was on the stage
+        assert_eq!(
+            stage_state
+                .engine()
+                .get_world_transform(&moved)
+                .map(|transform| transform.translation()),
+            Some(Vector2F::splat(10.0))
+        );
+        assert!(stage_state.reparent(&outer, &inner).is_err());
+    }
+}
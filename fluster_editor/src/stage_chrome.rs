@@ -0,0 +1,534 @@
+use crate::messages::{AppMessage, ChromeMessage};
+use crate::simulation::StageState;
+use fluster_core::{
+    ecs::resources::{Camera, DebugFlags},
+    types::shapes::{Contour, Edge, Shape},
+};
+use iced::{
+    button::State as ButtonState, mouse, Button, Checkbox, Column, Element, Length, Row, Text,
+    TextInput,
+};
+use iced_graphics::{Backend, Defaults, Primitive, Renderer};
+use iced_native::{
+    layout, text_input::State as TextInputState, Background, Clipboard, Color, Event, Font, Hasher,
+    HorizontalAlignment, Layout, Point, Rectangle, Size, VerticalAlignment, Widget,
+};
+use palette::LinSrgba;
+use pathfinder_content::stroke::{LineCap, LineJoin, StrokeStyle};
+use pathfinder_geometry::vector::Vector2F;
+use std::{hash::Hash, sync::Arc};
+
+/// Thickness of the rulers in pixels.
+pub const RULER_SIZE: u16 = 20;
+// Stage units between ticks at 100% zoom
+const MINOR_TICK: f32 = 10.0;
+const TICKS_PER_MAJOR: i64 = 10;
+// How close, in stage units, a point has to be to a guide to snap to it or pick it up
+const SNAP_DISTANCE: f32 = 6.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GuideOrientation {
+    /// A line of constant y, dragged out of the top ruler.
+    Horizontal,
+    /// A line of constant x, dragged out of the side ruler.
+    Vertical,
+}
+
+impl GuideOrientation {
+    fn axis(&self, position: Vector2F) -> f32 {
+        match self {
+            Self::Horizontal => position.y(),
+            Self::Vertical => position.x(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Guide {
+    orientation: GuideOrientation,
+    position: f32,
+}
+
+impl Guide {
+    fn distance(&self, point: Vector2F) -> f32 {
+        (self.orientation.axis(point) - self.position).abs()
+    }
+
+    fn edges(&self, stage_size: Vector2F) -> Vec<Edge> {
+        match self.orientation {
+            GuideOrientation::Horizontal => vec![
+                Edge::Move(Vector2F::new(0.0, self.position)),
+                Edge::Line(Vector2F::new(stage_size.x(), self.position)),
+            ],
+            GuideOrientation::Vertical => vec![
+                Edge::Move(Vector2F::new(self.position, 0.0)),
+                Edge::Line(Vector2F::new(self.position, stage_size.y())),
+            ],
+        }
+    }
+}
+
+fn overlay_shape(color: LinSrgba, edges: Vec<Edge>) -> Arc<Shape> {
+    Arc::new(Shape::Path {
+        color,
+        contours: Contour::from_edges(&edges),
+        stroke_style: StrokeStyle {
+            line_width: 1.0,
+            line_cap: LineCap::default(),
+            line_join: LineJoin::default(),
+        },
+    })
+}
+
+/// Grid, guides and snapping for the stage. None of this lives in the Engine, it is drawn as an
+/// overlay after the scene so it can never end up in a saved or exported file.
+#[derive(Clone, Debug)]
+pub struct ChromeState {
+    show_grid: bool,
+    grid_spacing: f32,
+    snap_to_grid: bool,
+    snap_to_guides: bool,
+    guides: Vec<Guide>,
+    // The guide being dragged. The position is None while the cursor is off the stage, dropping
+    // it there removes the guide.
+    dragging: Option<(GuideOrientation, Option<f32>)>,
+}
+
+impl Default for ChromeState {
+    fn default() -> Self {
+        Self {
+            show_grid: false,
+            grid_spacing: 20.0,
+            snap_to_grid: false,
+            snap_to_guides: true,
+            guides: vec![],
+            dragging: None,
+        }
+    }
+}
+
+impl ChromeState {
+    /// Returns true if the stage needs to be redrawn.
+    pub fn update(&mut self, message: &ChromeMessage) -> bool {
+        match message {
+            ChromeMessage::ShowGrid(show_grid) => {
+                self.show_grid = *show_grid;
+                true
+            }
+            ChromeMessage::SnapToGrid(snap_to_grid) => {
+                self.snap_to_grid = *snap_to_grid;
+                false
+            }
+            ChromeMessage::SnapToGuides(snap_to_guides) => {
+                self.snap_to_guides = *snap_to_guides;
+                false
+            }
+            ChromeMessage::GridSpacing(grid_spacing) => {
+                if *grid_spacing > 0.0 {
+                    self.grid_spacing = *grid_spacing;
+                    self.show_grid
+                } else {
+                    false
+                }
+            }
+            ChromeMessage::StartGuide(orientation) => {
+                self.dragging = Some((*orientation, None));
+                false
+            }
+            ChromeMessage::PickUpGuide(index) => {
+                if *index < self.guides.len() {
+                    let guide = self.guides.remove(*index);
+                    self.dragging = Some((guide.orientation, Some(guide.position)));
+                }
+                false
+            }
+            ChromeMessage::MoveGuide(position) => {
+                let snapped = position.map(|position| self.snap_to_grid_only(position));
+                if let Some((orientation, guide_position)) = &mut self.dragging {
+                    *guide_position = snapped.map(|snapped| orientation.axis(snapped));
+                    true
+                } else {
+                    false
+                }
+            }
+            ChromeMessage::DropGuide => {
+                if let Some((orientation, Some(position))) = self.dragging.take() {
+                    self.guides.push(Guide {
+                        orientation,
+                        position,
+                    });
+                }
+                true
+            }
+            ChromeMessage::ClearGuides => {
+                self.guides.clear();
+                true
+            }
+            // Debug overlays are drawn by the engine, so the StageState applies these
+            ChromeMessage::DebugFlags(..) => false,
+        }
+    }
+
+    pub fn show_grid(&self) -> bool {
+        self.show_grid
+    }
+
+    pub fn snap_to_grid(&self) -> bool {
+        self.snap_to_grid
+    }
+
+    pub fn snap_to_guides(&self) -> bool {
+        self.snap_to_guides
+    }
+
+    pub fn is_dragging_guide(&self) -> bool {
+        self.dragging.is_some()
+    }
+
+    /// The closest guide within snapping distance of `position`.
+    pub fn guide_at(&self, position: Vector2F) -> Option<usize> {
+        self.guides
+            .iter()
+            .enumerate()
+            .map(|(index, guide)| (index, guide.distance(position)))
+            .filter(|(_, distance)| *distance <= SNAP_DISTANCE)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|(index, _)| index)
+    }
+
+    /// Snaps each axis to the nearest guide in range, or failing that to the grid.
+    pub fn snap(&self, position: Vector2F) -> Vector2F {
+        Vector2F::new(
+            self.snap_axis(GuideOrientation::Vertical, position.x()),
+            self.snap_axis(GuideOrientation::Horizontal, position.y()),
+        )
+    }
+
+    fn snap_axis(&self, orientation: GuideOrientation, value: f32) -> f32 {
+        if self.snap_to_guides {
+            let nearest_guide = self
+                .guides
+                .iter()
+                .filter(|guide| guide.orientation == orientation)
+                .map(|guide| guide.position)
+                .filter(|position| (position - value).abs() <= SNAP_DISTANCE)
+                .min_by(|a, b| (a - value).abs().partial_cmp(&(b - value).abs()).unwrap());
+            if let Some(position) = nearest_guide {
+                return position;
+            }
+        }
+        self.snap_value_to_grid(value)
+    }
+
+    fn snap_to_grid_only(&self, position: Vector2F) -> Vector2F {
+        Vector2F::new(
+            self.snap_value_to_grid(position.x()),
+            self.snap_value_to_grid(position.y()),
+        )
+    }
+
+    fn snap_value_to_grid(&self, value: f32) -> f32 {
+        if self.snap_to_grid {
+            (value / self.grid_spacing).round() * self.grid_spacing
+        } else {
+            value
+        }
+    }
+
+    /// Shapes to draw over the scene, in stage coordinates.
+    pub fn overlay(&self, stage_size: Vector2F) -> Vec<Arc<Shape>> {
+        let mut overlay = vec![];
+        if self.show_grid {
+            let mut edges = vec![];
+            let mut x = self.grid_spacing;
+            while x < stage_size.x() {
+                edges.push(Edge::Move(Vector2F::new(x, 0.0)));
+                edges.push(Edge::Line(Vector2F::new(x, stage_size.y())));
+                x += self.grid_spacing;
+            }
+            let mut y = self.grid_spacing;
+            while y < stage_size.y() {
+                edges.push(Edge::Move(Vector2F::new(0.0, y)));
+                edges.push(Edge::Line(Vector2F::new(stage_size.x(), y)));
+                y += self.grid_spacing;
+            }
+            overlay.push(overlay_shape(LinSrgba::new(0.5, 0.5, 0.5, 0.4), edges));
+        }
+        let dragged_guide = self
+            .dragging
+            .and_then(|(orientation, position)| {
+                position.map(|position| Guide {
+                    orientation,
+                    position,
+                })
+            })
+            .into_iter();
+        let guide_edges = self
+            .guides
+            .iter()
+            .copied()
+            .chain(dragged_guide)
+            .flat_map(|guide| guide.edges(stage_size))
+            .collect::<Vec<Edge>>();
+        if !guide_edges.is_empty() {
+            overlay.push(overlay_shape(
+                LinSrgba::new(0.0, 0.8, 0.9, 1.0),
+                guide_edges,
+            ));
+        }
+        overlay
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ChromeDisplayState {
+    grid_spacing: TextInputState,
+    clear_guides: ButtonState,
+}
+
+impl ChromeDisplayState {
+    pub fn chrome_pane(
+        &mut self,
+        chrome_state: &ChromeState,
+        debug_flags: DebugFlags,
+    ) -> Column<AppMessage> {
+        let grid_spacing = chrome_state.grid_spacing;
+        let debug_checkbox = |checked: bool, label: &str, set: fn(&mut DebugFlags, bool)| {
+            Checkbox::new(checked, label, move |value| {
+                let mut debug_flags = debug_flags;
+                set(&mut debug_flags, value);
+                AppMessage::ChromeMessage(ChromeMessage::DebugFlags(debug_flags))
+            })
+        };
+        Column::new()
+            .padding(20)
+            .spacing(3)
+            .push(Checkbox::new(
+                chrome_state.show_grid,
+                "Show Grid",
+                |value| AppMessage::ChromeMessage(ChromeMessage::ShowGrid(value)),
+            ))
+            .push(
+                Row::new().push(Text::new("Grid Spacing:").size(16)).push(
+                    TextInput::new(
+                        &mut self.grid_spacing,
+                        "",
+                        &format!("{}", grid_spacing),
+                        move |value| {
+                            AppMessage::ChromeMessage(ChromeMessage::GridSpacing(
+                                value.parse::<f32>().unwrap_or(grid_spacing),
+                            ))
+                        },
+                    )
+                    .width(Length::Fill),
+                ),
+            )
+            .push(Checkbox::new(
+                chrome_state.snap_to_grid,
+                "Snap to Grid",
+                |value| AppMessage::ChromeMessage(ChromeMessage::SnapToGrid(value)),
+            ))
+            .push(Checkbox::new(
+                chrome_state.snap_to_guides,
+                "Snap to Guides",
+                |value| AppMessage::ChromeMessage(ChromeMessage::SnapToGuides(value)),
+            ))
+            .push(
+                Button::new(&mut self.clear_guides, Text::new("Clear Guides").size(16))
+                    .on_press(AppMessage::ChromeMessage(ChromeMessage::ClearGuides)),
+            )
+            .push(Text::new("Debug:").size(16))
+            .push(debug_checkbox(
+                debug_flags.bounds,
+                "Bounds",
+                |debug_flags, value| debug_flags.bounds = value,
+            ))
+            .push(debug_checkbox(
+                debug_flags.quad_trees,
+                "Quad Trees",
+                |debug_flags, value| debug_flags.quad_trees = value,
+            ))
+            .push(debug_checkbox(
+                debug_flags.dirty_regions,
+                "Dirty Regions",
+                |debug_flags, value| debug_flags.dirty_regions = value,
+            ))
+            .push(debug_checkbox(
+                debug_flags.parent_links,
+                "Parent Links",
+                |debug_flags, value| debug_flags.parent_links = value,
+            ))
+    }
+}
+
+/// A ruler along the top or left edge of the stage, marked in stage units and following the
+/// camera. Pressing on a ruler drags a new guide out of it.
+pub struct Ruler {
+    orientation: GuideOrientation,
+    width: u16,
+    height: u16,
+    scale: f32,
+    camera: Camera,
+}
+
+impl Ruler {
+    pub fn new(orientation: GuideOrientation, stage_state: &StageState) -> Self {
+        Self {
+            orientation,
+            width: stage_state.width() as u16,
+            height: stage_state.height() as u16,
+            scale: stage_state.scale(),
+            camera: stage_state.camera(),
+        }
+    }
+
+    fn size(&self) -> Size {
+        match self.orientation {
+            GuideOrientation::Horizontal => Size::new(f32::from(self.width), f32::from(RULER_SIZE)),
+            GuideOrientation::Vertical => Size::new(f32::from(RULER_SIZE), f32::from(self.height)),
+        }
+    }
+
+    fn tick(&self, bounds: Rectangle, offset: f32, tick_length: f32) -> Rectangle {
+        match self.orientation {
+            GuideOrientation::Horizontal => Rectangle {
+                x: bounds.x + offset,
+                y: bounds.y + bounds.height - tick_length,
+                width: 1.0,
+                height: tick_length,
+            },
+            // Stage y runs bottom to top
+            GuideOrientation::Vertical => Rectangle {
+                x: bounds.x + bounds.width - tick_length,
+                y: bounds.y + bounds.height - offset,
+                width: tick_length,
+                height: 1.0,
+            },
+        }
+    }
+}
+
+impl<B> Widget<AppMessage, Renderer<B>> for Ruler
+where
+    B: Backend,
+{
+    fn width(&self) -> Length {
+        Length::Units(self.size().width as u16)
+    }
+
+    fn height(&self) -> Length {
+        Length::Units(self.size().height as u16)
+    }
+
+    fn layout(&self, _renderer: &Renderer<B>, _limits: &layout::Limits) -> layout::Node {
+        layout::Node::new(self.size())
+    }
+
+    fn hash_layout(&self, state: &mut Hasher) {
+        self.orientation.hash(state);
+        self.width.hash(state);
+        self.height.hash(state);
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Renderer<B>,
+        _defaults: &Defaults,
+        layout: Layout<'_>,
+        cursor_position: Point,
+    ) -> (Primitive, mouse::Interaction) {
+        let bounds = layout.bounds();
+        let tick_color = Color::from_rgb(0.3, 0.3, 0.3);
+        let mut primitives = vec![Primitive::Quad {
+            bounds,
+            background: Background::Color(Color::from_rgb(0.9, 0.9, 0.9)),
+            border_radius: 0,
+            border_width: 0,
+            border_color: Color::TRANSPARENT,
+        }];
+        let (view_extent, pan) = match self.orientation {
+            GuideOrientation::Horizontal => (bounds.width, self.camera.pan().x()),
+            GuideOrientation::Vertical => (bounds.height, self.camera.pan().y()),
+        };
+        let zoom = self.camera.zoom();
+        // Keep ticks a readable distance apart however far in or out the camera is
+        let mut step = MINOR_TICK;
+        while step * zoom / self.scale < 4.0 {
+            step *= 10.0;
+        }
+        while step * zoom / self.scale >= 40.0 {
+            step /= 10.0;
+        }
+        let first_value = (0.0 - pan) / zoom;
+        let last_value = (view_extent * self.scale - pan) / zoom;
+        let mut index = (first_value / step).ceil() as i64;
+        loop {
+            let stage_value = index as f32 * step;
+            if stage_value > last_value {
+                break;
+            }
+            let offset = (stage_value * zoom + pan) / self.scale;
+            let major = index % TICKS_PER_MAJOR == 0;
+            let tick_length = if major {
+                bounds.width.min(bounds.height)
+            } else {
+                f32::from(RULER_SIZE) / 4.0
+            };
+            primitives.push(Primitive::Quad {
+                bounds: self.tick(bounds, offset, tick_length),
+                background: Background::Color(tick_color),
+                border_radius: 0,
+                border_width: 0,
+                border_color: Color::TRANSPARENT,
+            });
+            if major {
+                let label_position = match self.orientation {
+                    GuideOrientation::Horizontal => Point::new(bounds.x + offset + 2.0, bounds.y),
+                    GuideOrientation::Vertical => {
+                        Point::new(bounds.x + 1.0, bounds.y + bounds.height - offset - 11.0)
+                    }
+                };
+                primitives.push(Primitive::Text {
+                    content: format!("{}", (stage_value * 100.0).round() / 100.0),
+                    bounds: Rectangle::new(label_position, Size::new(40.0, 10.0)),
+                    color: tick_color,
+                    size: 10.0,
+                    font: Font::Default,
+                    horizontal_alignment: HorizontalAlignment::Left,
+                    vertical_alignment: VerticalAlignment::Top,
+                });
+            }
+            index += 1;
+        }
+        let cursor = if bounds.contains(cursor_position) {
+            mouse::Interaction::Grab
+        } else {
+            mouse::Interaction::Idle
+        };
+        (Primitive::Group { primitives }, cursor)
+    }
+
+    fn on_event(
+        &mut self,
+        event: Event,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        messages: &mut Vec<AppMessage>,
+        _renderer: &Renderer<B>,
+        _clipboard: Option<&dyn Clipboard>,
+    ) {
+        if let Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) = event {
+            if layout.bounds().contains(cursor_position) {
+                messages.push(AppMessage::ChromeMessage(ChromeMessage::StartGuide(
+                    self.orientation,
+                )));
+            }
+        }
+    }
+}
+
+impl<'a> Into<Element<'a, AppMessage>> for Ruler {
+    fn into(self) -> Element<'a, AppMessage> {
+        Element::new(self)
+    }
+}
@@ -0,0 +1,158 @@
+use crate::scratch_pad::EDIT_LAYER;
+use fluster_core::{
+    ecs::components::DisplayKind,
+    engine::Engine,
+    factories::new_display_container_with_collision,
+    types::{
+        basic::{ContainerId, LibraryId},
+        shapes::{AugmentedShape, Shape},
+    },
+};
+use pathfinder_geometry::transform2d::Transform2F;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SymbolKind {
+    Group,
+    Clip,
+}
+
+/// A Group or Clip library item opened for editing in place. Each of the symbol's shapes is
+/// broken out into its own container under the instance that was opened, so the usual tools can
+/// edit them. Every edit is written back to the library item, so all its instances follow along.
+#[derive(Debug)]
+pub struct SymbolEditor {
+    instance: ContainerId,
+    library_id: LibraryId,
+    kind: SymbolKind,
+    // The containers showing the symbol's shapes, and the scratch library items they display
+    parts: Vec<(ContainerId, LibraryId)>,
+}
+
+impl SymbolEditor {
+    /// Opens the symbol `container_id` displays. The part containers are queued for creation, so
+    /// the scene needs an update before they can be selected.
+    pub fn open(engine: &mut Engine, container_id: &ContainerId) -> Result<Self, String> {
+        let library_id = match engine.get_display(container_id) {
+            Some((library_id, DisplayKind::Vector)) => library_id,
+            _ => {
+                return Err(format!(
+                    "Container {:?} doesn't display a shape",
+                    container_id
+                ))
+            }
+        };
+        let shape = engine
+            .get_library()
+            .get_shape(&library_id)
+            .ok_or_else(|| format!("Could not find library item {:?}", library_id))?;
+        let (kind, shapes) = match shape.as_ref() {
+            Shape::Group { shapes } => (SymbolKind::Group, shapes.clone()),
+            Shape::Clip { .. } => (
+                SymbolKind::Clip,
+                vec![AugmentedShape {
+                    shape: (*shape).clone(),
+                    transform: Transform2F::default(),
+                }],
+            ),
+            _ => {
+                return Err(format!(
+                    "Library item {:?} is not a Group or Clip",
+                    library_id
+                ))
+            }
+        };
+        let parts = shapes
+            .into_iter()
+            .map(|part| {
+                let part_library_id = LibraryId::new();
+                engine
+                    .get_library_mut()
+                    .add_shape(part_library_id, part.shape);
+                let part_id = new_display_container_with_collision(
+                    engine,
+                    *container_id,
+                    part.transform,
+                    part_library_id,
+                    vec![EDIT_LAYER],
+                );
+                (part_id, part_library_id)
+            })
+            .collect();
+        Ok(Self {
+            instance: *container_id,
+            library_id,
+            kind,
+            parts,
+        })
+    }
+
+    /// The container that was opened. It's hidden while its parts stand in for it.
+    pub fn instance(&self) -> &ContainerId {
+        &self.instance
+    }
+
+    pub fn is_part(&self, container_id: &ContainerId) -> bool {
+        self.parts
+            .iter()
+            .any(|(part_id, _)| part_id == container_id)
+    }
+
+    pub fn parts(&self) -> impl Iterator<Item = &ContainerId> {
+        self.parts.iter().map(|(part_id, _)| part_id)
+    }
+
+    /// Puts the parts back together and replaces the library item with them.
+    pub fn sync(&self, engine: &mut Engine) -> Result<(), String> {
+        let shapes = self
+            .parts
+            .iter()
+            .map(|(part_id, part_library_id)| {
+                let shape = engine
+                    .get_library()
+                    .get_shape(part_library_id)
+                    .ok_or_else(|| format!("Could not find library item {:?}", part_library_id))?;
+                let transform = engine
+                    .get_local_transform(part_id)
+                    .ok_or_else(|| format!("Container {:?} not found", part_id))?;
+                Ok(AugmentedShape {
+                    shape: (*shape).clone(),
+                    transform,
+                })
+            })
+            .collect::<Result<Vec<AugmentedShape>, String>>()?;
+        let shape = match self.kind {
+            SymbolKind::Group => Shape::Group { shapes },
+            SymbolKind::Clip => match shapes.into_iter().next() {
+                Some(AugmentedShape {
+                    shape: shape @ Shape::Clip { .. },
+                    ..
+                }) => shape,
+                _ => return Err("A Clip symbol must stay a single clip".to_owned()),
+            },
+        };
+        let instances = {
+            let mut library = engine.get_library_mut();
+            library.add_shape(self.library_id, shape);
+            library.usages(&self.library_id)
+        };
+        for instance in instances {
+            engine.refresh_bounds(&instance);
+        }
+        Ok(())
+    }
+
+    /// Writes the parts back to the library one last time, then removes them. The parts are
+    /// removed even if writing them back fails. Returns the instance that was opened.
+    pub fn close(self, engine: &mut Engine) -> Result<ContainerId, String> {
+        let synced = self.sync(engine);
+        for (part_id, part_library_id) in self.parts.iter() {
+            // A part deleted while the symbol was open is already gone
+            if let Err(error) = engine.remove_container(part_id) {
+                println!("Could not remove symbol part {:?}: {:?}", part_id, error);
+            }
+            engine.get_library_mut().remove_shape(part_library_id);
+        }
+        engine.refresh_bounds(&self.instance);
+        synced.map(|_| self.instance)
+    }
+}
@@ -0,0 +1,47 @@
+use crate::messages::{AppMessage, TimelineMessage};
+use crate::simulation::TimelineState;
+use iced::{button::State as ButtonState, Align, Button, Checkbox, Column, Row, Text};
+
+#[derive(Debug, Default)]
+pub struct TimelinePaneState {
+    previous_state: ButtonState,
+    next_state: ButtonState,
+    keyframe_state: ButtonState,
+}
+
+impl TimelinePaneState {
+    // TODO: a real timeline, with a row of frames per layer
+    pub fn timeline_pane(&mut self, timeline_state: &TimelineState) -> Column<AppMessage> {
+        Column::new()
+            .padding(20)
+            .spacing(3)
+            .push(
+                Row::new()
+                    .spacing(3)
+                    .align_items(Align::Center)
+                    .push(
+                        Button::new(&mut self.previous_state, Text::new("<").size(14))
+                            .on_press(AppMessage::TimelineMessage(TimelineMessage::PreviousFrame)),
+                    )
+                    .push(Text::new(format!("Frame {}", timeline_state.current_frame())).size(16))
+                    .push(
+                        Button::new(&mut self.next_state, Text::new(">").size(14))
+                            .on_press(AppMessage::TimelineMessage(TimelineMessage::NextFrame)),
+                    ),
+            )
+            .push(
+                Button::new(
+                    &mut self.keyframe_state,
+                    Text::new("Blank Keyframe").size(14),
+                )
+                .on_press(AppMessage::TimelineMessage(
+                    TimelineMessage::InsertBlankKeyframe,
+                )),
+            )
+            .push(Checkbox::new(
+                timeline_state.auto_advance(),
+                "Auto Advance",
+                |value| AppMessage::TimelineMessage(TimelineMessage::AutoAdvance(value)),
+            ))
+    }
+}
@@ -1,14 +1,131 @@
-use crate::messages::{AppMessage, EditMessage, Template, ToolMessage};
-use fluster_core::engine::SelectionHandle;
-use iced::{mouse, Checkbox, Column, Length, Row, Text, TextInput};
+use crate::messages::{AppMessage, EditMessage, Template, ToolMessage, ViewportMessage};
+use fluster_core::{
+    ecs::resources::Camera,
+    engine::{Engine, SelectionHandle},
+    types::shapes::{Edge, EdgeKind, HandleType},
+};
+use iced::{keyboard, mouse, Checkbox, Column, Length, Radio, Row, Text, TextInput};
 use iced_native::{image::Handle as ImageHandle, text_input::State as TextInputState};
 use palette::LinSrgba;
 use pathfinder_content::stroke::{LineCap, LineJoin};
-use pathfinder_geometry::{rect::RectF, vector::Vector2F};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
 use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Something that edits the stage with the mouse. Tools are registered with a `ToolRegistry`,
+/// which gives them a button in the tool pane, so new tools don't need changes to the editor
+/// itself. Tools edit the scene by sending `ToolMessage`s to the scratch pad. Tools that draw a
+/// shape by dragging can send `ToolMessage::TemplateStart` with a `Template::Custom`.
+pub trait Tool: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    fn image_handle(&self) -> ImageHandle;
+
+    /// True if the tool reads `option`. Only these options show in the options pane, and only
+    /// these are passed to `on_mouse_event`.
+    fn uses_option(&self, option: ToolOptionHandle) -> bool;
+
+    /// What to hit test under the cursor. Hits are passed to `on_mouse_event` as the selection.
+    fn selection_shape(&self, _stage_position: Vector2F) -> SelectionShape {
+        SelectionShape::None
+    }
+
+    fn mouse_cursor(&self, placement_state: PlacementState) -> mouse::Interaction {
+        match placement_state {
+            PlacementState::None => mouse::Interaction::Pointer,
+            PlacementState::Placing => mouse::Interaction::Crosshair,
+        }
+    }
+
+    fn on_mouse_event(
+        &self,
+        mouse_event: mouse::Event,
+        selection: Vec<SelectionHandle>,
+        stage_position: Vector2F,
+        tool_options: Vec<ToolOption>,
+        placement_state: PlacementState,
+    ) -> Option<ToolMessage>;
+}
+
+/// Turns a drag on the stage into edges, for tools that draw a shape from a template. See
+/// `Template::Custom`.
+pub trait ShapeTemplate: Debug + Send + Sync {
+    fn edges(
+        &self,
+        start_position: Vector2F,
+        end_position: Vector2F,
+        options: &HashMap<ToolOptionHandle, ToolOption>,
+    ) -> Result<Vec<Edge>, String>;
+}
+
+/// Identifies a tool in a `ToolRegistry`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ToolId(usize);
+
+/// The tools the editor offers, in tool pane order.
+#[derive(Clone, Debug)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        for tool in BuiltinTool::ALL.iter() {
+            registry.register(Arc::new(*tool));
+        }
+        registry.register(Arc::new(TemplateTool::new(
+            "Star",
+            "assets/icons/020-graphic tool.png",
+            Arc::new(StarTemplate),
+            vec![
+                ToolOptionHandle::LineColor,
+                ToolOptionHandle::StrokeWidth,
+                ToolOptionHandle::LineJoin,
+                ToolOptionHandle::FillColor,
+                ToolOptionHandle::NumEdges,
+            ],
+        )));
+        registry
+    }
+}
+
+impl ToolRegistry {
+    /// A registry without even the built in tools.
+    pub fn new() -> Self {
+        Self { tools: vec![] }
+    }
+
+    pub fn register(&mut self, tool: Arc<dyn Tool>) -> ToolId {
+        self.tools.push(tool);
+        ToolId(self.tools.len() - 1)
+    }
+
+    pub fn get(&self, tool_id: ToolId) -> Option<&dyn Tool> {
+        self.tools.get(tool_id.0).map(|tool| tool.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ToolId, &dyn Tool)> {
+        self.tools
+            .iter()
+            .enumerate()
+            .map(|(index, tool)| (ToolId(index), tool.as_ref()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+}
+
+// TODO: uh... an actual real asset pipeline?????
+fn icon(path: &str) -> ImageHandle {
+    ImageHandle::from_path(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), path))
+}
 
 #[derive(Clone, Copy, Debug)]
-pub enum Tool {
+enum BuiltinTool {
     Pointer,
     Path,
     Rect,
@@ -18,32 +135,37 @@ pub enum Tool {
     Eyedropper,
 }
 
-impl Default for Tool {
-    fn default() -> Self {
-        Tool::Pointer
-    }
+impl BuiltinTool {
+    const ALL: [BuiltinTool; 7] = [
+        BuiltinTool::Pointer,
+        BuiltinTool::Path,
+        BuiltinTool::Rect,
+        BuiltinTool::Polygon,
+        BuiltinTool::Ellipse,
+        BuiltinTool::Fill,
+        BuiltinTool::Eyedropper,
+    ];
 }
 
-// TODO: uh... an actual real asset pipeline?????
-impl Tool {
-    pub fn image_handle(&self) -> ImageHandle {
-        ImageHandle::from_path(format!(
-            "{}/{}",
-            env!("CARGO_MANIFEST_DIR"),
-            match self {
-                Self::Pointer => "assets/icons/030-mouse.png",
-                Self::Path => "assets/icons/033-pen tool.png",
-                _ => "assets/icons/020-graphic tool.png",
-            }
-        ))
-    }
-
-    pub fn name(&self) -> String {
-        format!("{:?}", self)
+impl Tool for BuiltinTool {
+    fn name(&self) -> &str {
+        match self {
+            Self::Pointer => "Pointer",
+            Self::Path => "Path",
+            Self::Rect => "Rect",
+            Self::Polygon => "Polygon",
+            Self::Ellipse => "Ellipse",
+            Self::Fill => "Fill",
+            Self::Eyedropper => "Eyedropper",
+        }
     }
 
-    pub fn change_message(&self) -> EditMessage {
-        EditMessage::ToolChange(*self)
+    fn image_handle(&self) -> ImageHandle {
+        icon(match self {
+            Self::Pointer => "assets/icons/030-mouse.png",
+            Self::Path => "assets/icons/033-pen tool.png",
+            _ => "assets/icons/020-graphic tool.png",
+        })
     }
 
     fn selection_shape(&self, stage_position: Vector2F) -> SelectionShape {
@@ -59,7 +181,7 @@ impl Tool {
     fn mouse_cursor(&self, placement_state: PlacementState) -> mouse::Interaction {
         match (placement_state, self) {
             (PlacementState::None, _) => mouse::Interaction::Pointer,
-            (PlacementState::Placing, Tool::Pointer) => mouse::Interaction::Grabbing,
+            (PlacementState::Placing, BuiltinTool::Pointer) => mouse::Interaction::Grabbing,
             (PlacementState::Placing, _) => mouse::Interaction::Crosshair,
         }
     }
@@ -72,18 +194,51 @@ impl Tool {
         tool_options: Vec<ToolOption>,
         placement_state: PlacementState,
     ) -> Option<ToolMessage> {
-        let tool_options = self.get_options(tool_options);
         match self {
             Self::Pointer => match placement_state {
                 PlacementState::None => match mouse_event {
                     mouse::Event::ButtonPressed(mouse::Button::Left) => {
-                        // TODO: Pick first selection w/ valid vertex
-                        if selection.len() > 0 && selection[0].handles().len() > 0 {
-                            Some(ToolMessage::MovePointStart {
-                                selection_handle: selection.swap_remove(0),
+                        let operation = tool_options
+                            .iter()
+                            .find_map(|option| match option {
+                                ToolOption::PointerOperation(operation) => Some(*operation),
+                                _ => None,
+                            })
+                            .unwrap_or_default();
+                        let handle_type = tool_options
+                            .iter()
+                            .find_map(|option| match option {
+                                ToolOption::HandleType(handle_type) => Some(*handle_type),
+                                _ => None,
                             })
-                        } else {
-                            None
+                            .flatten();
+                        // TODO: Pick first selection w/ valid vertex
+                        let has_vertex = selection.len() > 0 && selection[0].handles().len() > 0;
+                        match operation {
+                            PointerOperation::MovePoint if has_vertex => {
+                                Some(ToolMessage::MovePointStart {
+                                    selection_handle: selection.swap_remove(0),
+                                    handle_type,
+                                })
+                            }
+                            PointerOperation::InsertPoint if selection.len() > 0 => {
+                                Some(ToolMessage::InsertPoint {
+                                    selection_handle: selection.swap_remove(0),
+                                    position: stage_position,
+                                })
+                            }
+                            PointerOperation::DeletePoint if has_vertex => {
+                                Some(ToolMessage::DeletePoint {
+                                    selection_handle: selection.swap_remove(0),
+                                })
+                            }
+                            PointerOperation::ConvertEdge(kind) if has_vertex => {
+                                Some(ToolMessage::ConvertEdge {
+                                    selection_handle: selection.swap_remove(0),
+                                    kind,
+                                })
+                            }
+                            _ => None,
                         }
                     }
                     _ => None,
@@ -149,55 +304,299 @@ impl Tool {
                     _ => None,
                 },
             },
-            _ => todo!(),
+            // TODO: eyedropper, floodfill
+            Self::Fill | Self::Eyedropper => None,
         }
     }
 
-    fn get_options(&self, mut tool_options: Vec<ToolOption>) -> Vec<ToolOption> {
-        tool_options
-            .drain(..)
-            .filter(|option| match option {
-                ToolOption::LineColor(..) => match self {
-                    Self::Path | Self::Ellipse | Self::Rect | Self::Polygon => true,
-                    _ => false,
-                },
-                ToolOption::StrokeWidth(..) => match self {
-                    Self::Path | Self::Ellipse | Self::Rect | Self::Polygon => true,
-                    _ => false,
-                },
-                ToolOption::LineCap(..) => match self {
-                    Self::Path => true,
-                    _ => false,
-                },
-                ToolOption::LineJoin(..) => match self {
-                    Self::Path | Self::Rect | Self::Polygon => true,
-                    _ => false,
-                },
-                ToolOption::FillColor(..) => match self {
-                    Self::Path | Self::Ellipse | Self::Rect | Self::Polygon => true,
-                    _ => false,
-                },
-                ToolOption::NumEdges(..) => match self {
-                    Self::Polygon => true,
-                    _ => false,
-                },
-                ToolOption::ClosedPath(..) => match self {
-                    Self::Path => true,
-                    _ => false,
-                },
-                ToolOption::CornerRadius(..) => match self {
-                    Self::Rect | Self::Polygon => true,
-                    _ => false,
-                },
-                ToolOption::UseSuperEllipseApproximation(..) => match self {
-                    Self::Rect => true,
-                    _ => false,
-                },
-            })
-            .collect::<Vec<ToolOption>>()
+    fn uses_option(&self, option: ToolOptionHandle) -> bool {
+        match option {
+            ToolOptionHandle::LineColor => match self {
+                Self::Path | Self::Ellipse | Self::Rect | Self::Polygon => true,
+                _ => false,
+            },
+            ToolOptionHandle::StrokeWidth => match self {
+                Self::Path | Self::Ellipse | Self::Rect | Self::Polygon => true,
+                _ => false,
+            },
+            ToolOptionHandle::LineCap => match self {
+                Self::Path => true,
+                _ => false,
+            },
+            ToolOptionHandle::LineJoin => match self {
+                Self::Path | Self::Rect | Self::Polygon => true,
+                _ => false,
+            },
+            ToolOptionHandle::FillColor => match self {
+                Self::Path | Self::Ellipse | Self::Rect | Self::Polygon => true,
+                _ => false,
+            },
+            ToolOptionHandle::NumEdges => match self {
+                Self::Polygon => true,
+                _ => false,
+            },
+            ToolOptionHandle::ClosedPath => match self {
+                Self::Path => true,
+                _ => false,
+            },
+            ToolOptionHandle::CornerRadius => match self {
+                Self::Rect | Self::Polygon => true,
+                _ => false,
+            },
+            ToolOptionHandle::UseSuperEllipseApproximation => match self {
+                Self::Rect => true,
+                _ => false,
+            },
+            ToolOptionHandle::PointerOperation | ToolOptionHandle::HandleType => match self {
+                Self::Pointer => true,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// A tool that drags out a shape from a `ShapeTemplate`, like the rect and ellipse tools do.
+#[derive(Debug)]
+pub struct TemplateTool {
+    name: String,
+    icon_path: String,
+    template: Arc<dyn ShapeTemplate>,
+    options: Vec<ToolOptionHandle>,
+}
+
+impl TemplateTool {
+    /// `icon_path` is relative to the editor's crate. `options` are the options `template` reads,
+    /// along with any of the stroke and fill options the shape should use.
+    pub fn new(
+        name: &str,
+        icon_path: &str,
+        template: Arc<dyn ShapeTemplate>,
+        options: Vec<ToolOptionHandle>,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            icon_path: icon_path.to_owned(),
+            template,
+            options,
+        }
+    }
+}
+
+impl Tool for TemplateTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn image_handle(&self) -> ImageHandle {
+        icon(&self.icon_path)
+    }
+
+    fn uses_option(&self, option: ToolOptionHandle) -> bool {
+        self.options.contains(&option)
     }
+
+    fn on_mouse_event(
+        &self,
+        mouse_event: mouse::Event,
+        _selection: Vec<SelectionHandle>,
+        stage_position: Vector2F,
+        tool_options: Vec<ToolOption>,
+        placement_state: PlacementState,
+    ) -> Option<ToolMessage> {
+        match (placement_state, mouse_event) {
+            (PlacementState::None, mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                Some(ToolMessage::TemplateStart {
+                    start_position: stage_position,
+                    options: tool_options,
+                    template: Template::Custom(self.template.clone()),
+                })
+            }
+            (PlacementState::Placing, mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                Some(ToolMessage::TemplateEnd)
+            }
+            (PlacementState::Placing, mouse::Event::CursorMoved { .. }) => {
+                Some(ToolMessage::TemplatePlaceHover {
+                    hover_position: stage_position,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+// Inner points sit at this fraction of the outer radius
+const STAR_INNER_RATIO: f32 = 0.5;
+
+/// A star with `NumEdges` points, centered on the drag start with a point under the cursor.
+#[derive(Debug)]
+struct StarTemplate;
+
+impl ShapeTemplate for StarTemplate {
+    fn edges(
+        &self,
+        start_position: Vector2F,
+        end_position: Vector2F,
+        options: &HashMap<ToolOptionHandle, ToolOption>,
+    ) -> Result<Vec<Edge>, String> {
+        let points = match options.get(&ToolOptionHandle::NumEdges) {
+            Some(ToolOption::NumEdges(points)) => (*points).max(3),
+            _ => return Err("Attempting to draw a star without specifying its points".to_owned()),
+        };
+        let outer = end_position - start_position;
+        let step = Transform2F::from_rotation(PI / points as f32);
+        let mut spoke = outer;
+        let mut edges = vec![Edge::Move(start_position + spoke)];
+        for index in 1..(points as usize * 2) {
+            spoke = step * spoke;
+            let radius = if index % 2 == 1 {
+                STAR_INNER_RATIO
+            } else {
+                1.0
+            };
+            edges.push(Edge::Line(start_position + spoke * radius));
+        }
+        edges.push(Edge::Close);
+        Ok(edges)
+    }
+}
+
+/// Converts a cursor offset within the stage widget into view space.
+pub fn cursor_to_view(cursor_offset: Vector2F, view_height: f32, scale: f32) -> Vector2F {
+    Vector2F::new(
+        cursor_offset.x() * scale,
+        //TODO: Potential BUG: Is the inversion of the Y-axis here at all correct or is it making up for another issue elsewhere
+        (view_height - cursor_offset.y()) * scale,
+    )
 }
 
+/// Converts a view space position into stage space by undoing the camera and device pixel ratio.
+/// Everything that hit tests or edits the scene needs stage space.
+pub fn view_to_stage(view_position: Vector2F, engine: &Engine) -> Vector2F {
+    engine.screen_to_stage(view_position, &engine.get_camera())
+}
+
+// Zoom per line scrolled
+const ZOOM_STEP: f32 = 1.1;
+// Pixels scrolled per line on devices that scroll by pixel
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// Zooming and panning the stage. Panning is done by holding space and dragging.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ViewportState {
+    pan_mode: bool,
+    // Where the last pan drag event was, in view space
+    pan_anchor: Option<Vector2F>,
+}
+
+impl ViewportState {
+    pub fn is_panning(&self) -> bool {
+        self.pan_mode || self.pan_anchor.is_some()
+    }
+
+    pub fn mouse_cursor(&self) -> mouse::Interaction {
+        if self.pan_anchor.is_some() {
+            mouse::Interaction::Grabbing
+        } else {
+            mouse::Interaction::Grab
+        }
+    }
+
+    pub fn on_mouse_event(
+        &self,
+        mouse_event: mouse::Event,
+        view_position: Vector2F,
+        in_bounds: bool,
+    ) -> Option<ViewportMessage> {
+        match mouse_event {
+            mouse::Event::WheelScrolled { delta } if in_bounds => {
+                let lines = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => y,
+                    mouse::ScrollDelta::Pixels { y, .. } => y / PIXELS_PER_LINE,
+                };
+                Some(ViewportMessage::Zoom {
+                    view_position,
+                    factor: ZOOM_STEP.powf(lines),
+                })
+            }
+            mouse::Event::ButtonPressed(mouse::Button::Left) if self.pan_mode && in_bounds => {
+                Some(ViewportMessage::PanStart(view_position))
+            }
+            mouse::Event::CursorMoved { .. } if self.pan_anchor.is_some() => {
+                Some(ViewportMessage::PanTo(view_position))
+            }
+            mouse::Event::ButtonReleased(mouse::Button::Left) if self.pan_anchor.is_some() => {
+                Some(ViewportMessage::PanEnd)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn on_keyboard_event(&self, keyboard_event: keyboard::Event) -> Option<ViewportMessage> {
+        match keyboard_event {
+            keyboard::Event::KeyPressed {
+                key_code: keyboard::KeyCode::Space,
+                ..
+            } if !self.pan_mode => Some(ViewportMessage::PanMode(true)),
+            keyboard::Event::KeyReleased {
+                key_code: keyboard::KeyCode::Space,
+                ..
+            } => Some(ViewportMessage::PanMode(false)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the stage needs to be redrawn.
+    pub fn update(
+        &mut self,
+        message: &ViewportMessage,
+        camera: &mut Camera,
+        stage_size: Vector2F,
+    ) -> bool {
+        match message {
+            ViewportMessage::Zoom {
+                view_position,
+                factor,
+            } => {
+                camera.zoom_around(*view_position, *factor);
+                true
+            }
+            ViewportMessage::PanMode(pan_mode) => {
+                self.pan_mode = *pan_mode;
+                false
+            }
+            ViewportMessage::PanStart(view_position) => {
+                self.pan_anchor = Some(*view_position);
+                false
+            }
+            ViewportMessage::PanTo(view_position) => {
+                if let Some(pan_anchor) = self.pan_anchor {
+                    camera.pan_by(*view_position - pan_anchor);
+                    self.pan_anchor = Some(*view_position);
+                    true
+                } else {
+                    false
+                }
+            }
+            ViewportMessage::PanEnd => {
+                self.pan_anchor = None;
+                false
+            }
+            ViewportMessage::FitToStage => {
+                // The view is the same size as the stage, so fitting leaves a margin to show the
+                // stage edges against the overdraw area
+                camera.fit(stage_size, stage_size, FIT_MARGIN);
+                true
+            }
+            ViewportMessage::ActualSize => {
+                camera.reset();
+                true
+            }
+        }
+    }
+}
+
+const FIT_MARGIN: f32 = 20.0;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SelectionShape {
     None,
@@ -206,11 +605,30 @@ pub enum SelectionShape {
 }
 
 #[derive(Clone, Copy, Debug)]
-enum PlacementState {
+pub enum PlacementState {
     None,
     Placing,
 }
 
+/// What clicking on a shape with the pointer tool does.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PointerOperation {
+    /// Drag the vertex under the cursor.
+    MovePoint,
+    /// Split the edge under the cursor, adding a point.
+    InsertPoint,
+    /// Delete the vertex under the cursor.
+    DeletePoint,
+    /// Convert the edge ending at the vertex under the cursor.
+    ConvertEdge(EdgeKind),
+}
+
+impl Default for PointerOperation {
+    fn default() -> Self {
+        Self::MovePoint
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum ToolOptionHandle {
     LineColor,
@@ -222,6 +640,8 @@ pub enum ToolOptionHandle {
     ClosedPath,
     CornerRadius,
     UseSuperEllipseApproximation,
+    PointerOperation,
+    HandleType,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -235,6 +655,9 @@ pub enum ToolOption {
     ClosedPath(bool),
     CornerRadius(f32),
     UseSuperEllipseApproximation(bool),
+    PointerOperation(PointerOperation),
+    /// None derives the handle type from the handles' positions when a drag starts.
+    HandleType(Option<HandleType>),
 }
 
 impl ToolOption {
@@ -251,6 +674,8 @@ impl ToolOption {
             Self::UseSuperEllipseApproximation(..) => {
                 ToolOptionHandle::UseSuperEllipseApproximation
             }
+            Self::PointerOperation(..) => ToolOptionHandle::PointerOperation,
+            Self::HandleType(..) => ToolOptionHandle::HandleType,
         }
     }
 }
@@ -266,11 +691,14 @@ struct Options {
     closed_path: bool,
     corner_radius: f32,
     use_super_ellipse_approximation: bool,
+    pointer_operation: PointerOperation,
+    handle_type: Option<HandleType>,
 }
 
 #[derive(Clone, Debug)]
 pub struct EditState {
-    tool: Tool,
+    tools: ToolRegistry,
+    tool: ToolId,
     placement_state: PlacementState,
     options: Options,
 }
@@ -278,7 +706,8 @@ pub struct EditState {
 impl Default for EditState {
     fn default() -> Self {
         EditState {
-            tool: Tool::default(),
+            tools: ToolRegistry::default(),
+            tool: ToolId(0),
             placement_state: PlacementState::None,
             //TODO: configure/persist defaults
             options: Options {
@@ -291,23 +720,43 @@ impl Default for EditState {
                 closed_path: false,
                 corner_radius: 0.0,
                 use_super_ellipse_approximation: false,
+                pointer_operation: PointerOperation::default(),
+                handle_type: None,
             },
         }
     }
 }
 
 impl EditState {
-    pub fn switch_tool(&mut self, tool: &Tool) {
-        self.tool = *tool;
-        self.placement_state = PlacementState::None;
+    pub fn switch_tool(&mut self, tool: &ToolId) {
+        if self.tools.get(*tool).is_some() {
+            self.tool = *tool;
+            self.placement_state = PlacementState::None;
+        }
+    }
+
+    pub fn tools(&self) -> &ToolRegistry {
+        &self.tools
+    }
+
+    /// Adds a tool to the tool pane.
+    pub fn register_tool(&mut self, tool: Arc<dyn Tool>) -> ToolId {
+        self.tools.register(tool)
+    }
+
+    fn current_tool(&self) -> &dyn Tool {
+        // switch_tool only accepts registered tools, and tools are never unregistered
+        self.tools
+            .get(self.tool)
+            .expect("Current tool not registered")
     }
 
     pub fn mouse_cursor(&self) -> mouse::Interaction {
-        self.tool.mouse_cursor(self.placement_state)
+        self.current_tool().mouse_cursor(self.placement_state)
     }
 
     pub fn selection_shape(&self, stage_position: Vector2F) -> SelectionShape {
-        self.tool.selection_shape(stage_position)
+        self.current_tool().selection_shape(stage_position)
     }
 
     pub fn on_mouse_event(
@@ -323,11 +772,11 @@ impl EditState {
                 _ => None,
             }
         } else {
-            let tool_message = self.tool.on_mouse_event(
+            let tool_message = self.current_tool().on_mouse_event(
                 mouse_event,
                 selection,
                 stage_position,
-                self.tool_options(),
+                self.enabled_options().values().copied().collect(),
                 self.placement_state,
             )?;
             Some(EditMessage::ToolUpdate(tool_message))
@@ -345,13 +794,16 @@ impl EditState {
             ToolOption::ClosedPath(self.options.closed_path),
             ToolOption::CornerRadius(self.options.corner_radius),
             ToolOption::UseSuperEllipseApproximation(self.options.use_super_ellipse_approximation),
+            ToolOption::PointerOperation(self.options.pointer_operation),
+            ToolOption::HandleType(self.options.handle_type),
         ]
     }
 
     fn enabled_options(&self) -> HashMap<ToolOptionHandle, ToolOption> {
-        self.tool
-            .get_options(self.tool_options())
+        let tool = self.current_tool();
+        self.tool_options()
             .drain(..)
+            .filter(|o| tool.uses_option(o.handle()))
             .map(|o| (o.handle(), o))
             .collect::<HashMap<ToolOptionHandle, ToolOption>>()
     }
@@ -391,6 +843,12 @@ impl EditState {
                 ToolOption::UseSuperEllipseApproximation(use_approximation) => {
                     self.options.use_super_ellipse_approximation = *use_approximation;
                 }
+                ToolOption::PointerOperation(pointer_operation) => {
+                    self.options.pointer_operation = *pointer_operation;
+                }
+                ToolOption::HandleType(handle_type) => {
+                    self.options.handle_type = *handle_type;
+                }
             },
         }
     }
@@ -502,6 +960,61 @@ impl EditDisplayState {
                 AppMessage::from_tool_option(ToolOption::ClosedPath(value))
             }))
         }
+        if let Some(ToolOption::PointerOperation(pointer_operation)) =
+            enabled_options.get(&ToolOptionHandle::PointerOperation)
+        {
+            let operations = [
+                (PointerOperation::MovePoint, "Move Point"),
+                (PointerOperation::InsertPoint, "Add Point"),
+                (PointerOperation::DeletePoint, "Delete Point"),
+                (PointerOperation::ConvertEdge(EdgeKind::Line), "Make Line"),
+                (
+                    PointerOperation::ConvertEdge(EdgeKind::Quadratic),
+                    "Make Quadratic",
+                ),
+                (
+                    PointerOperation::ConvertEdge(EdgeKind::Bezier),
+                    "Make Bezier",
+                ),
+                (PointerOperation::ConvertEdge(EdgeKind::ArcTo), "Make Arc"),
+            ];
+            column = operations.iter().fold(
+                column.push(Text::new("On Click:").size(16)),
+                |column, (operation, label)| {
+                    column.push(Radio::new(
+                        *operation,
+                        *label,
+                        Some(*pointer_operation),
+                        |operation| {
+                            AppMessage::from_tool_option(ToolOption::PointerOperation(operation))
+                        },
+                    ))
+                },
+            );
+        }
+        if let Some(ToolOption::HandleType(handle_type)) =
+            enabled_options.get(&ToolOptionHandle::HandleType)
+        {
+            let handle_types = [
+                (None, "Auto"),
+                (Some(HandleType::Corner), "Corner"),
+                (Some(HandleType::Smooth), "Smooth"),
+                (Some(HandleType::Symmetric), "Symmetric"),
+            ];
+            column = handle_types.iter().fold(
+                column.push(Text::new("Bezier Handles:").size(16)),
+                |column, (value, label)| {
+                    column.push(Radio::new(
+                        *value,
+                        *label,
+                        Some(*handle_type),
+                        |handle_type| {
+                            AppMessage::from_tool_option(ToolOption::HandleType(handle_type))
+                        },
+                    ))
+                },
+            );
+        }
         column
     }
 }
@@ -1,24 +1,36 @@
 #![deny(clippy::all)]
-use fluster_core::rendering::{lin_srgba_to_coloru, Renderer};
-use fluster_core::types::{
-    coloring::Coloring,
-    shapes::{Edge, Shape},
+pub mod outline_cache;
+pub mod stage;
+
+use fluster_core::ecs::resources::RenderQuality;
+use fluster_core::rendering::{
+    BackgroundLayer, ClipRect, OutputColorSpace, RasterInstance, Renderer, RgbaBuffer,
+    ShapeInstance,
+};
+use fluster_core::types::{coloring::Coloring, shapes::Shape};
+use outline_cache::OutlineCache;
+use palette::LinSrgba;
+use pathfinder_canvas::{
+    Canvas, CanvasFontContext, CanvasRenderingContext2D, FillStyle, LineJoin, Path2D,
 };
-use pathfinder_canvas::{Canvas, CanvasFontContext, CanvasRenderingContext2D, FillStyle, LineJoin};
 use pathfinder_color::ColorU;
 use pathfinder_content::fill::FillRule;
+use pathfinder_content::gradient::Gradient;
 use pathfinder_content::pattern::Pattern;
 use pathfinder_content::stroke::{LineJoin as StrokeLineJoin, StrokeStyle};
 use pathfinder_geometry::rect::RectF;
+use pathfinder_geometry::rect::RectI;
 use pathfinder_geometry::transform2d::Transform2F;
-use pathfinder_geometry::vector::Vector2F;
-use pathfinder_gpu::Device;
+use pathfinder_geometry::vector::{Vector2F, Vector2I};
+use pathfinder_gpu::{Device, RenderTarget, TextureData};
 use pathfinder_renderer::concurrent::rayon::RayonExecutor;
 use pathfinder_renderer::concurrent::scene_proxy::SceneProxy;
-use pathfinder_renderer::gpu::options::RendererLevel;
+use pathfinder_renderer::gpu::options::{DestFramebuffer, RendererLevel};
 use pathfinder_renderer::gpu::renderer::Renderer as PathfinderRenderer;
 use pathfinder_renderer::options::BuildOptions;
-use std::{mem, sync::Arc};
+use pathfinder_simd::default::F32x2;
+use stage::{Stage, StageHandle, StageRenderer};
+use std::{collections::BTreeMap, mem, sync::Arc};
 
 fn patch_line_join(j: StrokeLineJoin) -> LineJoin {
     match j {
@@ -30,12 +42,11 @@ fn patch_line_join(j: StrokeLineJoin) -> LineJoin {
 
 fn stroke_path(
     canvas: &mut CanvasRenderingContext2D,
-    edges: impl Iterator<Item = Edge>,
+    path: Path2D,
     stroke_style: &StrokeStyle,
     transform: &Transform2F,
     color: ColorU,
 ) {
-    let path = Edge::edges_to_path(edges);
     canvas.set_transform(transform);
     canvas.set_line_width(stroke_style.line_width);
     canvas.set_line_cap(stroke_style.line_cap);
@@ -44,14 +55,146 @@ fn stroke_path(
     canvas.stroke_path(path);
 }
 
+#[derive(Clone, Debug)]
+enum DisplayCommand {
+    Shapes(Arc<Shape>, Vec<ShapeInstance>),
+    Raster(Arc<Pattern>, RasterInstance),
+    /// Clips everything up to the matching `PopViewport` to a stage's viewport, after clearing it
+    /// to the stage's background.
+    PushViewport(RectF, Option<LinSrgba>),
+    PopViewport,
+    /// Clips everything up to the matching `PopClip`, on top of any outer clips.
+    PushClip(ClipRect),
+    PopClip,
+    /// Fills the area, given in stage units, before any containers are drawn.
+    Background(BackgroundLayer, RectF, Transform2F),
+}
+
+impl DisplayCommand {
+    fn transformed(&self, transform: Transform2F) -> Self {
+        match self {
+            Self::Shapes(shape, instances) => Self::Shapes(
+                shape.clone(),
+                instances
+                    .iter()
+                    .map(|instance| ShapeInstance {
+                        transform: transform * instance.transform,
+                        ..instance.clone()
+                    })
+                    .collect(),
+            ),
+            Self::Raster(pattern, instance) => Self::Raster(
+                pattern.clone(),
+                RasterInstance {
+                    transform: transform * instance.transform,
+                    ..instance.clone()
+                },
+            ),
+            Self::Background(layer, area, background_transform) => {
+                Self::Background(layer.clone(), *area, transform * *background_transform)
+            }
+            Self::PushClip(clip) => Self::PushClip(ClipRect {
+                transform: transform * clip.transform,
+                ..*clip
+            }),
+            Self::PushViewport(..) | Self::PopViewport | Self::PopClip => self.clone(),
+        }
+    }
+}
+
+impl PartialEq for DisplayCommand {
+    // Library items are compared by identity. A replaced library item is a new allocation, and
+    // deep comparison of every shape each frame would cost as much as just rebuilding.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Shapes(shape, instances), Self::Shapes(other_shape, other_instances)) => {
+                Arc::ptr_eq(shape, other_shape) && instances == other_instances
+            }
+            (Self::Raster(pattern, instance), Self::Raster(other_pattern, other_instance)) => {
+                Arc::ptr_eq(pattern, other_pattern) && instance == other_instance
+            }
+            (
+                Self::PushViewport(viewport, color),
+                Self::PushViewport(other_viewport, other_color),
+            ) => viewport == other_viewport && color == other_color,
+            (Self::PopViewport, Self::PopViewport) => true,
+            (Self::PushClip(clip), Self::PushClip(other_clip)) => clip == other_clip,
+            (Self::PopClip, Self::PopClip) => true,
+            (
+                Self::Background(layer, area, transform),
+                Self::Background(other_layer, other_area, other_transform),
+            ) => layer == other_layer && area == other_area && transform == other_transform,
+            _ => false,
+        }
+    }
+}
+
+fn override_color(color: LinSrgba, color_override: &Option<Coloring>) -> LinSrgba {
+    if let Some(Coloring::Color(color_override)) = color_override {
+        *color_override
+    } else {
+        color
+    }
+}
+
+/// Where the clear color for each frame comes from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Background {
+    /// Use the color the engine sets with `set_background`.
+    Stage,
+    /// Ignore the engine and always clear to this color, in linear light.
+    Color(LinSrgba),
+    /// Do not clear, so the frame is composited over whatever is already in the framebuffer.
+    Transparent,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FlusterRendererOptions {
+    /// Must be supported by the pathfinder renderer's `RendererMode`. `D3D11` uses compute shaders
+    /// and needs GL 4.3 or Metal.
+    pub level: RendererLevel,
+    /// Grows every path by this amount, in pixels. Useful for faking bold or for hairlines.
+    pub dilation: Vector2F,
+    pub subpixel_aa_enabled: bool,
+    pub background: Background,
+    /// How colors are encoded for the framebuffer. Everything drawn is linear until this point.
+    pub output_color_space: OutputColorSpace,
+}
+
+impl Default for FlusterRendererOptions {
+    fn default() -> Self {
+        Self {
+            level: RendererLevel::D3D9,
+            dilation: Vector2F::zero(),
+            subpixel_aa_enabled: false,
+            background: Background::Stage,
+            output_color_space: OutputColorSpace::default(),
+        }
+    }
+}
+
 pub struct FlusterRendererImpl<D>
 where
     D: Device,
 {
     font_context: CanvasFontContext,
     renderer: PathfinderRenderer<D>,
+    options: FlusterRendererOptions,
+    stage_background: Option<LinSrgba>,
     canvas: Option<CanvasRenderingContext2D>,
-    on_frame_end: Box<dyn Fn() -> ()>,
+    outline_cache: OutlineCache,
+    scene: Option<SceneProxy>,
+    stage_size: Option<Vector2F>,
+    retained: bool,
+    needs_rebuild: bool,
+    display_list: Vec<DisplayCommand>,
+    last_display_list: Vec<DisplayCommand>,
+    skipped_frames: usize,
+    stages: BTreeMap<StageHandle, Stage>,
+    next_stage: usize,
+    read_back_requested: bool,
+    read_back: Option<Result<RgbaBuffer, String>>,
+    on_frame_end: Box<dyn FnMut(&mut PathfinderRenderer<D>)>,
 }
 
 impl<D> FlusterRendererImpl<D>
@@ -61,13 +204,155 @@ where
     pub fn new(
         font_context: CanvasFontContext,
         renderer: PathfinderRenderer<D>,
-        on_frame_end: Box<dyn Fn() -> ()>,
+        options: FlusterRendererOptions,
+        on_frame_end: Box<dyn FnMut(&mut PathfinderRenderer<D>)>,
     ) -> FlusterRendererImpl<D> {
-        FlusterRendererImpl {
+        let mut fluster_renderer = FlusterRendererImpl {
             font_context,
+            options,
+            stage_background: None,
             canvas: None,
+            outline_cache: OutlineCache::new(),
+            scene: None,
+            stage_size: None,
+            retained: false,
+            needs_rebuild: true,
+            display_list: vec![],
+            last_display_list: vec![],
+            skipped_frames: 0,
+            stages: BTreeMap::new(),
+            next_stage: 0,
+            read_back_requested: false,
+            read_back: None,
             renderer,
             on_frame_end,
+        };
+        fluster_renderer.apply_background();
+        fluster_renderer
+    }
+
+    pub fn options(&self) -> &FlusterRendererOptions {
+        &self.options
+    }
+
+    /// Takes effect on the next frame. Changing the level rebuilds the scene from scratch.
+    pub fn set_options(&mut self, options: FlusterRendererOptions) {
+        if options.level != self.options.level {
+            self.scene = None;
+        }
+        self.options = options;
+        self.apply_background();
+        self.needs_rebuild = true;
+    }
+
+    fn apply_background(&mut self) {
+        let background_color = match self.options.background {
+            // Until the engine sets a color, keep whatever the host configured on the renderer
+            Background::Stage => match self.stage_background {
+                Some(color) => Some(self.options.output_color_space.encode(color).to_f32()),
+                None => self.renderer.options().background_color,
+            },
+            Background::Color(color) => {
+                Some(self.options.output_color_space.encode(color).to_f32())
+            }
+            Background::Transparent => None,
+        };
+        if self.renderer.options().background_color != background_color {
+            self.renderer.options_mut().background_color = background_color;
+            self.needs_rebuild = true;
+        }
+    }
+
+    /// In retained mode draw calls are recorded instead of painted immediately. At the end of the
+    /// frame the recording is diffed against the previous frame, and if nothing changed the scene
+    /// is neither rebuilt nor rendered, and `on_frame_end` is not called. The previous frame must
+    /// still be on screen for that to be correct, so hosts that lose their surface contents
+    /// (resizes, expose events) should call `invalidate`.
+    ///
+    /// Frames are skipped or rebuilt whole. A pathfinder scene can't be patched a path at a time,
+    /// so any change rebuilds everything, with only the outline cache carried over.
+    pub fn set_retained_mode(&mut self, retained: bool) {
+        if self.retained != retained {
+            self.retained = retained;
+            self.display_list.clear();
+            self.last_display_list.clear();
+            self.needs_rebuild = true;
+        }
+    }
+
+    pub fn retained_mode(&self) -> bool {
+        self.retained
+    }
+
+    /// Resizes the framebuffer drawn to, in device pixels. Call when the window is resized or
+    /// moves to a display with a different scale factor.
+    pub fn resize(&mut self, framebuffer_size: Vector2I) {
+        self.renderer.options_mut().dest = DestFramebuffer::full_window(framebuffer_size);
+        self.renderer.dest_framebuffer_size_changed();
+        self.needs_rebuild = true;
+    }
+
+    /// Forces the next frame to be fully rebuilt and rendered.
+    pub fn invalidate(&mut self) {
+        self.needs_rebuild = true;
+    }
+
+    /// Number of frames retained mode has skipped because nothing changed.
+    pub fn skipped_frames(&self) -> usize {
+        self.skipped_frames
+    }
+
+    pub fn outline_cache(&self) -> &OutlineCache {
+        &self.outline_cache
+    }
+
+    /// Adds a stage drawn into `viewport` of the renderer's canvas. Each stage is scaled to fit
+    /// its viewport and clipped to it, so several `Runner`s can share one renderer. Stages are
+    /// composited in creation order when the host calls `end_frame`, between its own
+    /// `start_frame` and `end_frame` calls on the renderer.
+    pub fn create_stage(&mut self, viewport: RectF) -> StageHandle {
+        let handle = StageHandle::new(self.next_stage);
+        self.next_stage += 1;
+        self.stages.insert(handle, Stage::new(viewport));
+        self.needs_rebuild = true;
+        handle
+    }
+
+    pub fn destroy_stage(&mut self, stage: StageHandle) -> Result<(), String> {
+        match self.stages.remove(&stage) {
+            Some(_) => {
+                self.needs_rebuild = true;
+                Ok(())
+            }
+            None => Err(format!("Stage {:?} does not exist", stage)),
+        }
+    }
+
+    pub fn set_stage_viewport(
+        &mut self,
+        stage: StageHandle,
+        viewport: RectF,
+    ) -> Result<(), String> {
+        match self.stages.get_mut(&stage) {
+            Some(stage) => {
+                stage.viewport = viewport;
+                self.needs_rebuild = true;
+                Ok(())
+            }
+            None => Err(format!("Stage {:?} does not exist", stage)),
+        }
+    }
+
+    pub fn get_stage_viewport(&self, stage: StageHandle) -> Option<RectF> {
+        self.stages.get(&stage).map(|stage| stage.viewport)
+    }
+
+    /// A `Renderer` that draws into the given stage.
+    pub fn stage(&mut self, stage: StageHandle) -> Option<StageRenderer<'_, D>> {
+        if self.stages.contains_key(&stage) {
+            Some(StageRenderer::new(self, stage))
+        } else {
+            None
         }
     }
 }
@@ -78,124 +363,390 @@ where
 {
     fn handle_draw_shape(
         &mut self,
+        owner: &Arc<Shape>,
         shape: &Shape,
         transform: Transform2F,
         color_override: Option<Coloring>,
         morph_index: f32,
+        morph_indices: &[f32],
     ) {
+        if let Shape::Group { shapes } = shape {
+            // Only the outermost group's shapes have their own morph index
+            let shape_morph =
+                |index: usize| morph_indices.get(index).copied().unwrap_or(morph_index);
+            if let Some(Coloring::Colorings(mut color_overrides)) = color_override {
+                for (index, (color, shape)) in color_overrides.drain(..).zip(shapes).enumerate() {
+                    self.handle_draw_shape(
+                        owner,
+                        &shape.shape,
+                        transform * shape.transform,
+                        Some(color),
+                        shape_morph(index),
+                        &[],
+                    )
+                }
+            } else {
+                for (index, shape) in shapes.iter().enumerate() {
+                    self.handle_draw_shape(
+                        owner,
+                        &shape.shape,
+                        transform * shape.transform,
+                        None,
+                        shape_morph(index),
+                        &[],
+                    )
+                }
+            }
+            return;
+        }
         if let Some(canvas) = &mut self.canvas {
+            let path = match self.outline_cache.get_or_build(owner, shape, morph_index) {
+                Some(path) => path,
+                None => return,
+            };
             match shape {
                 Shape::Path {
-                    edges,
                     color,
                     stroke_style,
+                    ..
+                }
+                | Shape::MorphPath {
+                    color,
+                    stroke_style,
+                    ..
                 } => {
-                    if edges.len() > 1 {
-                        let color = if let Some(Coloring::Color(color_override)) = color_override {
-                            color_override
-                        } else {
-                            *color
-                        };
-                        stroke_path(
-                            canvas,
-                            edges.iter().map(|e| *e),
-                            stroke_style,
-                            &transform,
-                            lin_srgba_to_coloru(color),
-                        );
-                    }
-                }
-                Shape::Fill { edges, color } => {
-                    if edges.len() > 2 {
-                        let color = if let Some(Coloring::Color(color_override)) = color_override {
-                            color_override
-                        } else {
-                            *color
-                        };
-                        let path = Edge::edges_to_path(edges.iter().map(|e| *e));
-                        canvas.set_transform(&transform);
-                        canvas.set_fill_style(FillStyle::Color(lin_srgba_to_coloru(color)));
-                        canvas.fill_path(path, FillRule::Winding);
-                    }
-                }
-                Shape::MorphPath {
-                    edges,
+                    let color = override_color(*color, &color_override);
+                    stroke_path(
+                        canvas,
+                        path,
+                        stroke_style,
+                        &transform,
+                        self.options.output_color_space.encode(color),
+                    );
+                }
+                Shape::Fill { color, .. } | Shape::MorphFill { color, .. } => {
+                    let color = override_color(*color, &color_override);
+                    canvas.set_transform(&transform);
+                    canvas.set_fill_style(FillStyle::Color(
+                        self.options.output_color_space.encode(color),
+                    ));
+                    canvas.fill_path(path, FillRule::Winding);
+                }
+                Shape::Clip { .. } => {
+                    canvas.set_transform(&transform);
+                    canvas.clip_path(path, FillRule::Winding);
+                }
+                // Groups are split up above, and the library resolves references and swatches
+                Shape::Group { .. } | Shape::Reference(..) | Shape::Swatch(..) => unreachable!(),
+            }
+        }
+    }
+
+    fn paint_shape_instanced(&mut self, shape: Arc<Shape>, instances: Vec<ShapeInstance>) {
+        // Only plain shapes have geometry that is identical between instances. Morphs depend on
+        // each instance's morph index and groups recurse, so those take the slow path.
+        let path = match &*shape {
+            Shape::Path { .. } | Shape::Fill { .. } => {
+                self.outline_cache.get_or_build(&shape, &*shape, 0.0)
+            }
+            _ => None,
+        };
+        match (&*shape, path, &mut self.canvas) {
+            (
+                Shape::Path {
                     color,
                     stroke_style,
+                    ..
+                },
+                Some(path),
+                Some(canvas),
+            ) => {
+                canvas.set_line_width(stroke_style.line_width);
+                canvas.set_line_cap(stroke_style.line_cap);
+                canvas.set_line_join(patch_line_join(stroke_style.line_join));
+                for instance in instances {
+                    let color = override_color(*color, &instance.color_override);
+                    canvas.set_transform(&instance.transform);
+                    canvas.set_stroke_style(FillStyle::Color(
+                        self.options.output_color_space.encode(color),
+                    ));
+                    canvas.stroke_path(path.clone());
+                }
+            }
+            (Shape::Fill { color, .. }, Some(path), Some(canvas)) => {
+                for instance in instances {
+                    let color = override_color(*color, &instance.color_override);
+                    canvas.set_transform(&instance.transform);
+                    canvas.set_fill_style(FillStyle::Color(
+                        self.options.output_color_space.encode(color),
+                    ));
+                    canvas.fill_path(path.clone(), FillRule::Winding);
+                }
+            }
+            (Shape::Path { .. }, None, _) | (Shape::Fill { .. }, None, _) => {
+                // Too few edges to draw anything
+            }
+            _ => {
+                for instance in instances {
+                    self.handle_draw_shape(
+                        &shape,
+                        &*shape,
+                        instance.transform,
+                        instance.color_override,
+                        instance.morph_index,
+                        &instance.morph_indices,
+                    );
+                }
+            }
+        }
+    }
+
+    fn paint_raster(
+        &mut self,
+        pattern: Arc<Pattern>,
+        view_rect: Option<RectF>,
+        transform: Transform2F,
+        tint: Option<Coloring>, //TODO: tinting, only the alpha of a flat tint is applied
+    ) {
+        let view_rect = view_rect
+            .and_then(|rect| Some(rect))
+            .unwrap_or(RectF::from_points(
+                Vector2F::zero(),
+                pattern.size().to_f32(),
+            ));
+        let alpha = match tint {
+            Some(Coloring::Color(color)) => color.alpha,
+            _ => 1.0,
+        };
+        if let Some(canvas) = &mut self.canvas {
+            canvas.set_transform(&transform);
+            canvas.set_global_alpha(alpha);
+            canvas.draw_subimage(
+                (*pattern).clone(),
+                view_rect,
+                RectF::new(Vector2F::zero(), view_rect.size()),
+            );
+            canvas.set_global_alpha(1.0);
+        }
+    }
+
+    fn push_viewport(&mut self, viewport: RectF, background: Option<LinSrgba>) {
+        let output_color_space = self.options.output_color_space;
+        if let Some(canvas) = &mut self.canvas {
+            canvas.save();
+            canvas.set_transform(&Transform2F::default());
+            if let Some(color) = background {
+                canvas.set_fill_style(FillStyle::Color(output_color_space.encode(color)));
+                canvas.fill_rect(viewport);
+            }
+            let mut clip = Path2D::new();
+            clip.rect(viewport);
+            canvas.clip_path(clip, FillRule::Winding);
+        }
+    }
+
+    fn paint_background(&mut self, layer: &BackgroundLayer, area: RectF, transform: Transform2F) {
+        let output_color_space = self.options.output_color_space;
+        let encode = |color: &LinSrgba| output_color_space.encode(*color);
+        if let Some(canvas) = &mut self.canvas {
+            canvas.set_transform(&transform);
+            match layer {
+                BackgroundLayer::Solid(color) => {
+                    canvas.set_fill_style(FillStyle::Color(encode(color)));
+                    canvas.fill_rect(area);
+                }
+                BackgroundLayer::VerticalGradient { top, bottom } => {
+                    let mut gradient =
+                        Gradient::linear_from_points(area.origin(), area.lower_left());
+                    // TODO: stops are interpolated after encoding, not in linear light
+                    gradient.add_color_stop(encode(top), 0.0);
+                    gradient.add_color_stop(encode(bottom), 1.0);
+                    canvas.set_fill_style(FillStyle::Gradient(gradient));
+                    canvas.fill_rect(area);
+                }
+                BackgroundLayer::RadialGradient { inner, outer } => {
+                    let radius = (area.size() * 0.5).length();
+                    let mut gradient = Gradient::radial(area.center(), F32x2::new(0.0, radius));
+                    gradient.add_color_stop(encode(inner), 0.0);
+                    gradient.add_color_stop(encode(outer), 1.0);
+                    canvas.set_fill_style(FillStyle::Gradient(gradient));
+                    canvas.fill_rect(area);
+                }
+                BackgroundLayer::Raster {
+                    pattern,
+                    tiled: true,
                 } => {
-                    if edges.len() > 1 {
-                        let color = if let Some(Coloring::Color(color_override)) = color_override {
-                            color_override
-                        } else {
-                            *color
-                        };
-                        let edges = edges.iter().map(|mp| mp.to_edge(morph_index));
-                        stroke_path(
-                            canvas,
-                            edges,
-                            stroke_style,
-                            &transform,
-                            lin_srgba_to_coloru(color),
-                        );
-                    }
-                }
-                Shape::MorphFill { edges, color } => {
-                    if edges.len() > 2 {
-                        let color = if let Some(Coloring::Color(color_override)) = color_override {
-                            color_override
-                        } else {
-                            *color
-                        };
-                        let edges = edges.iter().map(|mp| mp.to_edge(morph_index));
-                        let path = Edge::edges_to_path(edges);
-                        canvas.set_transform(&transform);
-                        canvas.set_fill_style(FillStyle::Color(lin_srgba_to_coloru(color)));
-                        canvas.fill_path(path, FillRule::Winding);
-                    }
-                }
-                Shape::Clip { edges } => {
-                    if edges.len() > 2 {
-                        let path = Edge::edges_to_path(edges.iter().map(|e| *e));
-                        canvas.set_transform(&transform);
-                        canvas.clip_path(path, FillRule::Winding);
-                    }
-                }
-                Shape::Group { shapes } => {
-                    if let Some(Coloring::Colorings(mut color_overrides)) = color_override {
-                        for (color, shape) in color_overrides.drain(..).zip(shapes) {
-                            self.handle_draw_shape(
-                                &shape.shape,
-                                transform * shape.transform,
-                                Some(color),
-                                morph_index,
-                            )
-                        }
-                    } else {
-                        for shape in shapes {
-                            self.handle_draw_shape(
-                                &shape.shape,
-                                transform * shape.transform,
-                                None,
-                                morph_index,
-                            )
-                        }
-                    }
+                    let mut pattern = (**pattern).clone();
+                    pattern.set_repeat_x(true);
+                    pattern.set_repeat_y(true);
+                    // Tile from the top left of the stage
+                    pattern.apply_transform(Transform2F::from_translation(area.origin()));
+                    canvas.set_fill_style(FillStyle::Pattern(pattern));
+                    canvas.fill_rect(area);
+                }
+                BackgroundLayer::Raster {
+                    pattern,
+                    tiled: false,
+                } => {
+                    canvas.draw_subimage(
+                        (**pattern).clone(),
+                        RectF::new(Vector2F::zero(), pattern.size().to_f32()),
+                        area,
+                    );
                 }
             }
         }
     }
+
+    fn pop_viewport(&mut self) {
+        if let Some(canvas) = &mut self.canvas {
+            canvas.restore();
+        }
+    }
+
+    fn paint_push_clip(&mut self, clip: &ClipRect) {
+        if let Some(canvas) = &mut self.canvas {
+            canvas.save();
+            canvas.set_transform(&clip.transform);
+            let mut path = Path2D::new();
+            path.rect(clip.rect);
+            canvas.clip_path(path, FillRule::Winding);
+        }
+    }
+
+    fn paint_pop_clip(&mut self) {
+        if let Some(canvas) = &mut self.canvas {
+            canvas.restore();
+        }
+    }
+
+    // Every stage's last complete frame, mapped into its viewport
+    fn stage_display_list(&self) -> Vec<DisplayCommand> {
+        let mut display_list = vec![];
+        for stage in self.stages.values() {
+            let background = match self.options.background {
+                Background::Stage => stage.background,
+                Background::Color(color) => Some(color),
+                Background::Transparent => None,
+            };
+            let transform = stage.transform();
+            display_list.push(DisplayCommand::PushViewport(stage.viewport, background));
+            display_list.extend(
+                stage
+                    .display_list
+                    .iter()
+                    .map(|command| command.transformed(transform)),
+            );
+            display_list.push(DisplayCommand::PopViewport);
+        }
+        display_list
+    }
+
+    fn replay(&mut self, display_list: &[DisplayCommand]) {
+        for command in display_list {
+            match command {
+                DisplayCommand::Shapes(shape, instances) => {
+                    self.paint_shape_instanced(shape.clone(), instances.clone())
+                }
+                DisplayCommand::Raster(pattern, instance) => self.paint_raster(
+                    pattern.clone(),
+                    instance.view_rect,
+                    instance.transform,
+                    instance.tint.clone(),
+                ),
+                DisplayCommand::PushViewport(viewport, background) => {
+                    self.push_viewport(*viewport, *background)
+                }
+                DisplayCommand::PopViewport => self.pop_viewport(),
+                DisplayCommand::PushClip(clip) => self.paint_push_clip(clip),
+                DisplayCommand::PopClip => self.paint_pop_clip(),
+                DisplayCommand::Background(layer, area, transform) => {
+                    self.paint_background(layer, *area, *transform)
+                }
+            }
+        }
+    }
+
+    fn build_and_render_scene(&mut self, canvas: CanvasRenderingContext2D) {
+        let scene = canvas.into_canvas().into_scene();
+        match &mut self.scene {
+            Some(scene_proxy) => scene_proxy.replace_scene(scene),
+            None => {
+                self.scene = Some(SceneProxy::from_scene(
+                    scene,
+                    self.options.level,
+                    RayonExecutor,
+                ))
+            }
+        }
+        if let Some(scene_proxy) = &mut self.scene {
+            let build_options = BuildOptions {
+                dilation: self.options.dilation,
+                subpixel_aa_enabled: self.options.subpixel_aa_enabled,
+                ..BuildOptions::default()
+            };
+            scene_proxy.build_and_render(&mut self.renderer, build_options);
+        }
+    }
+
+    /// Reads the framebuffer just rendered to. Has to happen before `on_frame_end` presents it.
+    fn read_pixels(&self) -> Result<RgbaBuffer, String> {
+        let device = self.renderer.device();
+        let (target, rect) = match &self.renderer.options().dest {
+            DestFramebuffer::Default { viewport, .. } => (RenderTarget::Default, *viewport),
+            DestFramebuffer::Other(framebuffer) => (
+                RenderTarget::Framebuffer(framebuffer),
+                RectI::new(
+                    Vector2I::zero(),
+                    device.texture_size(device.framebuffer_texture(framebuffer)),
+                ),
+            ),
+        };
+        let receiver = device.read_pixels(&target, rect);
+        match device.recv_texture_data(&receiver) {
+            // Already top row first, pathfinder's GL device flips what glReadPixels returns.
+            // The headless player's tests check this
+            TextureData::U8(pixels) => Ok(RgbaBuffer {
+                size: rect.size(),
+                pixels,
+            }),
+            _ => Err("Unexpected framebuffer format".to_string()),
+        }
+    }
 }
 
 impl<D> Renderer for FlusterRendererImpl<D>
 where
     D: Device,
 {
-    //TODO: handle stage_size changing
     fn start_frame(&mut self, stage_size: Vector2F) {
+        if self.stage_size != Some(stage_size) {
+            self.stage_size = Some(stage_size);
+            self.needs_rebuild = true;
+        }
         self.canvas = Some(Canvas::new(stage_size).get_context_2d(self.font_context.clone()))
     }
-    fn set_background(&mut self, color: ColorU) {
-        self.renderer.options_mut().background_color = Some(color.to_f32());
+    // Stages share this cache, so only the main engine's quality applies
+    fn set_render_quality(&mut self, render_quality: RenderQuality) {
+        if self.outline_cache.curve_tolerance() != render_quality.curve_tolerance {
+            self.outline_cache
+                .set_curve_tolerance(render_quality.curve_tolerance);
+            self.needs_rebuild = true;
+        }
+    }
+    fn set_background(&mut self, color: LinSrgba) {
+        self.stage_background = Some(color);
+        self.apply_background();
+    }
+
+    fn draw_background(&mut self, layer: BackgroundLayer) {
+        let area = RectF::new(Vector2F::zero(), self.stage_size.unwrap_or_default());
+        let command = DisplayCommand::Background(layer, area, Transform2F::default());
+        if self.retained {
+            self.display_list.push(command);
+        } else {
+            self.replay(&[command]);
+        }
     }
 
     fn draw_shape(
@@ -205,7 +756,24 @@ where
         color_override: Option<Coloring>,
         morph_index: f32,
     ) {
-        self.handle_draw_shape(&*shape, transform, color_override, morph_index);
+        self.draw_shape_instanced(
+            shape,
+            vec![ShapeInstance {
+                transform,
+                color_override,
+                morph_index,
+                morph_indices: vec![],
+            }],
+        );
+    }
+
+    fn draw_shape_instanced(&mut self, shape: Arc<Shape>, instances: Vec<ShapeInstance>) {
+        if self.retained {
+            self.display_list
+                .push(DisplayCommand::Shapes(shape, instances));
+        } else {
+            self.paint_shape_instanced(shape, instances);
+        }
     }
 
     fn draw_raster(
@@ -213,35 +781,146 @@ where
         pattern: Arc<Pattern>,
         view_rect: Option<RectF>,
         transform: Transform2F,
-        _tint: Option<Coloring>, //TODO: tinting
+        tint: Option<Coloring>,
     ) {
-        let view_rect = view_rect
-            .and_then(|rect| Some(rect))
-            .unwrap_or(RectF::from_points(
-                Vector2F::zero(),
-                pattern.size().to_f32(),
+        if self.retained {
+            self.display_list.push(DisplayCommand::Raster(
+                pattern,
+                RasterInstance {
+                    view_rect,
+                    transform,
+                    tint,
+                },
             ));
-        if let Some(canvas) = &mut self.canvas {
-            canvas.set_transform(&transform);
-            canvas.draw_subimage(
-                (*pattern).clone(),
-                view_rect,
-                RectF::new(Vector2F::zero(), view_rect.size()),
-            );
+        } else {
+            self.paint_raster(pattern, view_rect, transform, tint);
+        }
+    }
+
+    fn push_clip(&mut self, clip: ClipRect) {
+        if self.retained {
+            self.display_list.push(DisplayCommand::PushClip(clip));
+        } else {
+            self.paint_push_clip(&clip);
         }
     }
+
+    fn pop_clip(&mut self) {
+        if self.retained {
+            self.display_list.push(DisplayCommand::PopClip);
+        } else {
+            self.paint_pop_clip();
+        }
+    }
+
     fn end_frame(&mut self) {
-        if self.canvas.is_some() {
-            let canvas = mem::replace(&mut self.canvas, None).unwrap();
-            let mut scene = SceneProxy::from_scene(
-                canvas.into_canvas().into_scene(),
-                RendererLevel::D3D9,
-                RayonExecutor,
-            );
-            scene.build_and_render(&mut self.renderer, BuildOptions::default());
-            (self.on_frame_end)();
+        if self.canvas.is_none() {
+            return;
+        }
+        let stage_display_list = self.stage_display_list();
+        if self.retained {
+            let mut display_list = mem::replace(&mut self.display_list, vec![]);
+            display_list.extend(stage_display_list);
+            if !self.needs_rebuild && self.scene.is_some() && display_list == self.last_display_list
+            {
+                // Nothing changed, so the last presented frame is still correct
+                self.canvas = None;
+                self.skipped_frames += 1;
+                return;
+            }
+            self.replay(&display_list);
+            self.last_display_list = display_list;
+        } else {
+            self.replay(&stage_display_list);
         }
+        let canvas = mem::replace(&mut self.canvas, None).unwrap();
+        self.build_and_render_scene(canvas);
+        self.needs_rebuild = false;
+        self.outline_cache.end_frame();
+        if mem::replace(&mut self.read_back_requested, false) {
+            self.read_back = Some(self.read_pixels());
+        }
+        (self.on_frame_end)(&mut self.renderer);
+    }
+
+    fn request_read_back(&mut self) {
+        self.read_back_requested = true;
+        // Skipped frames are never rendered, so there'd be nothing to read
+        self.needs_rebuild = true;
+    }
+
+    fn take_read_back(&mut self) -> Option<Result<RgbaBuffer, String>> {
+        self.read_back.take()
     }
 }
 
-//TODO: how do I test this?
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluster_core::types::shapes::{Contour, Edge};
+
+    fn square() -> Arc<Shape> {
+        Arc::new(Shape::Fill {
+            contours: Contour::from_edges(&Edge::new_rect(
+                Vector2F::splat(10.0),
+                Transform2F::default(),
+            )),
+            color: LinSrgba::new(1.0, 1.0, 1.0, 1.0),
+        })
+    }
+
+    fn frame(shape: &Arc<Shape>, position: Vector2F) -> Vec<DisplayCommand> {
+        let viewport = RectF::new(Vector2F::zero(), Vector2F::splat(100.0));
+        vec![
+            DisplayCommand::PushViewport(viewport, None),
+            DisplayCommand::Shapes(
+                shape.clone(),
+                vec![ShapeInstance {
+                    transform: Transform2F::from_translation(position),
+                    color_override: None,
+                    morph_index: 0.0,
+                    morph_indices: vec![],
+                }],
+            ),
+            DisplayCommand::PopViewport,
+        ]
+    }
+
+    #[test]
+    fn it_matches_an_unchanged_frame() {
+        let shape = square();
+        assert_eq!(
+            frame(&shape, Vector2F::zero()),
+            frame(&shape, Vector2F::zero())
+        );
+    }
+
+    #[test]
+    fn it_notices_a_moved_instance() {
+        let shape = square();
+        assert_ne!(
+            frame(&shape, Vector2F::zero()),
+            frame(&shape, Vector2F::splat(1.0))
+        );
+    }
+
+    #[test]
+    fn it_notices_a_replaced_library_item() {
+        // Same contents, but replacing an item always makes a new allocation
+        assert_ne!(
+            frame(&square(), Vector2F::zero()),
+            frame(&square(), Vector2F::zero())
+        );
+    }
+
+    #[test]
+    fn it_moves_stage_commands_into_the_viewport() {
+        let shape = square();
+        let transform = Transform2F::from_translation(Vector2F::splat(5.0));
+        let moved = frame(&shape, Vector2F::zero())
+            .iter()
+            .map(|command| command.transformed(transform))
+            .collect::<Vec<_>>();
+        assert_eq!(moved, frame(&shape, Vector2F::splat(5.0)));
+    }
+}
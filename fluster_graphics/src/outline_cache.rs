@@ -0,0 +1,274 @@
+use fluster_core::types::shapes::{Contour, Shape};
+use pathfinder_canvas::Path2D;
+use pathfinder_content::{outline::ContourIterFlags, segment::Segment};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
+
+// Entries that have not been drawn for this many frames are dropped
+const MAX_IDLE_FRAMES: u64 = 120;
+// Cap on the lines a single curve is flattened into, however tight the tolerance
+const MAX_FLATTEN_STEPS: f32 = 64.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct OutlineKey {
+    shape: usize,
+    // The exact bits of the morph index, for morph shapes only
+    morph: Option<u32>,
+}
+
+struct CachedOutline {
+    // Held to detect when the library replaces or drops the shape this outline was built from
+    owner: Weak<Shape>,
+    path: Path2D,
+    last_used: u64,
+}
+
+/// Caches the `Path2D` built for each shape so static artwork is only tessellated once.
+///
+/// Entries are keyed on the identity of the shape, not its contents. When a shape is replaced in
+/// the `Library` the old `Arc` is dropped and its entries become stale, so no explicit
+/// invalidation is needed.
+#[derive(Default)]
+pub struct OutlineCache {
+    entries: HashMap<OutlineKey, CachedOutline>,
+    frame: u64,
+    hits: usize,
+    misses: usize,
+    curve_tolerance: Option<f32>,
+}
+
+impl OutlineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the outline of `shape`, which must live inside `owner`. Group children share their
+    /// root shape's owner. Returns `None` for shapes that have no single outline.
+    ///
+    /// Morphs are keyed on their exact morph index, so a hit is always the outline for that index.
+    pub fn get_or_build(
+        &mut self,
+        owner: &Arc<Shape>,
+        shape: &Shape,
+        morph_index: f32,
+    ) -> Option<Path2D> {
+        let key = OutlineKey {
+            shape: shape as *const Shape as usize,
+            morph: match shape {
+                Shape::MorphPath { .. } | Shape::MorphFill { .. } => Some(morph_index.to_bits()),
+                _ => None,
+            },
+        };
+        let frame = self.frame;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            // A dead owner means the address was reused by a different shape
+            if entry
+                .owner
+                .upgrade()
+                .map_or(false, |live| Arc::ptr_eq(&live, owner))
+            {
+                entry.last_used = frame;
+                self.hits += 1;
+                return Some(entry.path.clone());
+            }
+        }
+        let path = build_outline(shape, morph_index)?;
+        let path = match self.curve_tolerance {
+            Some(tolerance) => flatten(path, tolerance),
+            None => path,
+        };
+        self.misses += 1;
+        self.entries.insert(
+            key,
+            CachedOutline {
+                owner: Arc::downgrade(owner),
+                path: path.clone(),
+                last_used: frame,
+            },
+        );
+        Some(path)
+    }
+
+    /// Drops entries for shapes that no longer exist or have not been drawn recently. Morph
+    /// entries are dropped as soon as a frame doesn't draw them, since an animated morph leaves
+    /// one behind for every index it passes through.
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.entries.retain(|key, entry| {
+            let max_idle_frames = if key.morph.is_some() {
+                0
+            } else {
+                MAX_IDLE_FRAMES
+            };
+            entry.owner.strong_count() > 0 && frame - entry.last_used <= max_idle_frames
+        });
+        self.frame += 1;
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// Outlines are flattened to lines within `tolerance` shape units of their curves, or left to
+    /// pathfinder with `None`. Changing it drops every entry.
+    pub fn set_curve_tolerance(&mut self, tolerance: Option<f32>) {
+        if self.curve_tolerance != tolerance {
+            self.curve_tolerance = tolerance;
+            self.clear();
+        }
+    }
+
+    pub fn curve_tolerance(&self) -> Option<f32> {
+        self.curve_tolerance
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Cache hits and misses since the last `end_frame`.
+    pub fn frame_hits_and_misses(&self) -> (usize, usize) {
+        (self.hits, self.misses)
+    }
+}
+
+fn build_outline(shape: &Shape, morph_index: f32) -> Option<Path2D> {
+    // Counted as a flat edge list, including each contour's move
+    let min_edges = match shape {
+        Shape::Path { .. } | Shape::MorphPath { .. } => 2,
+        Shape::Fill { .. } | Shape::MorphFill { .. } | Shape::Clip { .. } => 3,
+        _ => return None,
+    };
+    if shape.len() < min_edges {
+        return None;
+    }
+    match shape {
+        Shape::Path { contours, .. } | Shape::Fill { contours, .. } | Shape::Clip { contours } => {
+            Some(Contour::to_path(contours))
+        }
+        _ => Some(Contour::to_path(&shape.contours(morph_index))),
+    }
+}
+
+// Replaces every curve in `path` with lines no further than `tolerance` from it
+fn flatten(path: Path2D, tolerance: f32) -> Path2D {
+    let mut flattened = Path2D::new();
+    for contour in path.into_outline().contours() {
+        let mut started = false;
+        for segment in contour.iter(ContourIterFlags::empty()) {
+            if !started {
+                flattened.move_to(segment.baseline.from());
+                started = true;
+            }
+            if segment.is_line() {
+                flattened.line_to(segment.baseline.to());
+            } else {
+                let segment = segment.to_cubic();
+                let steps = flatten_steps(&segment, tolerance);
+                let cubic = segment.as_cubic_segment();
+                for step in 1..=steps {
+                    flattened.line_to(cubic.sample(step as f32 / steps as f32));
+                }
+            }
+        }
+        if contour.is_closed() {
+            flattened.close_path();
+        }
+    }
+    flattened
+}
+
+// Lines needed to keep a cubic within `tolerance`, from the size of its second differences
+fn flatten_steps(cubic: &Segment, tolerance: f32) -> u32 {
+    let (p0, p1) = (cubic.baseline.from(), cubic.ctrl.from());
+    let (p2, p3) = (cubic.ctrl.to(), cubic.baseline.to());
+    let bend = (p0 - p1 * 2.0 + p2)
+        .length()
+        .max((p1 - p2 * 2.0 + p3).length());
+    (0.75 * bend / tolerance.max(std::f32::EPSILON))
+        .sqrt()
+        .ceil()
+        .max(1.0)
+        .min(MAX_FLATTEN_STEPS) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluster_core::types::shapes::{MorphContour, MorphEdge};
+    use palette::LinSrgba;
+    use pathfinder_geometry::vector::Vector2F;
+
+    // Grows from 10x10 to 20x20 as it morphs
+    fn morph_square() -> Arc<Shape> {
+        Arc::new(Shape::MorphFill {
+            contours: MorphContour::from_edges(&[
+                MorphEdge::Move(Vector2F::zero(), Vector2F::zero()),
+                MorphEdge::Line(Vector2F::new(10.0, 0.0), Vector2F::new(20.0, 0.0)),
+                MorphEdge::Line(Vector2F::splat(10.0), Vector2F::splat(20.0)),
+                MorphEdge::Line(Vector2F::new(0.0, 10.0), Vector2F::new(0.0, 20.0)),
+            ]),
+            color: LinSrgba::new(1.0, 1.0, 1.0, 1.0),
+        })
+    }
+
+    #[test]
+    fn it_builds_morphs_from_the_exact_morph_index() {
+        let shape = morph_square();
+        let mut cache = OutlineCache::new();
+        let morph_index = 0.3;
+        let uncached = build_outline(&shape, morph_index).unwrap().into_outline();
+        let built = cache.get_or_build(&shape, &shape, morph_index).unwrap();
+        let cached = cache.get_or_build(&shape, &shape, morph_index).unwrap();
+        assert_eq!(cache.frame_hits_and_misses(), (1, 1));
+        assert_eq!(built.into_outline(), uncached);
+        assert_eq!(cached.into_outline(), uncached);
+    }
+
+    #[test]
+    fn it_keys_morphs_on_the_exact_morph_index() {
+        let shape = morph_square();
+        let mut cache = OutlineCache::new();
+        // Much closer together than any animation step
+        let (morph_index, nearby) = (0.5, 0.5 + std::f32::EPSILON);
+        cache.get_or_build(&shape, &shape, morph_index).unwrap();
+        let built = cache.get_or_build(&shape, &shape, nearby).unwrap();
+        assert_eq!(cache.frame_hits_and_misses(), (0, 2));
+        assert_eq!(
+            built.into_outline(),
+            build_outline(&shape, nearby).unwrap().into_outline()
+        );
+    }
+
+    #[test]
+    fn it_drops_morph_entries_not_drawn_this_frame() {
+        let shape = morph_square();
+        let mut cache = OutlineCache::new();
+        cache.get_or_build(&shape, &shape, 0.25).unwrap();
+        cache.get_or_build(&shape, &shape, 0.5).unwrap();
+        cache.end_frame();
+        assert_eq!(cache.len(), 2);
+        // The morph moved on, so only the index still being drawn is kept
+        cache.get_or_build(&shape, &shape, 0.5).unwrap();
+        cache.end_frame();
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn it_drops_entries_for_dropped_shapes() {
+        let mut cache = OutlineCache::new();
+        let shape = morph_square();
+        cache.get_or_build(&shape, &shape, 0.0).unwrap();
+        drop(shape);
+        cache.end_frame();
+        assert!(cache.is_empty());
+    }
+}
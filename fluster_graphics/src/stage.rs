@@ -0,0 +1,165 @@
+use super::{DisplayCommand, FlusterRendererImpl};
+use fluster_core::rendering::{BackgroundLayer, ClipRect, RasterInstance, Renderer, ShapeInstance};
+use fluster_core::types::{coloring::Coloring, shapes::Shape};
+use palette::LinSrgba;
+use pathfinder_content::pattern::Pattern;
+use pathfinder_geometry::rect::RectF;
+use pathfinder_geometry::transform2d::Transform2F;
+use pathfinder_geometry::vector::Vector2F;
+use pathfinder_gpu::Device;
+use std::{mem, sync::Arc};
+
+/// Identifies a stage created with `FlusterRendererImpl::create_stage`. Handles are never reused,
+/// so a handle to a destroyed stage stays invalid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StageHandle(usize);
+
+impl StageHandle {
+    pub(crate) fn new(id: usize) -> Self {
+        Self(id)
+    }
+}
+
+pub(crate) struct Stage {
+    pub(crate) viewport: RectF,
+    stage_size: Vector2F,
+    pub(crate) background: Option<LinSrgba>,
+    recording: Vec<DisplayCommand>,
+    // The last complete frame. Replayed every time the renderer presents, so stages that run at
+    // different rates, or are paused, don't disappear from frames they didn't draw.
+    pub(crate) display_list: Vec<DisplayCommand>,
+}
+
+impl Stage {
+    pub(crate) fn new(viewport: RectF) -> Self {
+        Self {
+            viewport,
+            stage_size: viewport.size(),
+            background: None,
+            recording: vec![],
+            display_list: vec![],
+        }
+    }
+
+    /// Maps stage coordinates onto the viewport.
+    pub(crate) fn transform(&self) -> Transform2F {
+        let scale = if self.stage_size.x() > 0.0 && self.stage_size.y() > 0.0 {
+            self.viewport.size() / self.stage_size
+        } else {
+            Vector2F::splat(1.0)
+        };
+        Transform2F::from_translation(self.viewport.origin()) * Transform2F::from_scale(scale)
+    }
+}
+
+/// Draws one stage into its viewport of a shared `FlusterRendererImpl`. Pass this to
+/// `Runner::next_frame` in place of the renderer. Nothing is presented until the host calls
+/// `end_frame` on the renderer itself, which composites every stage in creation order.
+pub struct StageRenderer<'a, D>
+where
+    D: Device,
+{
+    renderer: &'a mut FlusterRendererImpl<D>,
+    handle: StageHandle,
+}
+
+impl<'a, D> StageRenderer<'a, D>
+where
+    D: Device,
+{
+    pub(crate) fn new(renderer: &'a mut FlusterRendererImpl<D>, handle: StageHandle) -> Self {
+        Self { renderer, handle }
+    }
+
+    pub fn handle(&self) -> StageHandle {
+        self.handle
+    }
+
+    fn stage(&mut self) -> &mut Stage {
+        // The handle is checked when the StageRenderer is created, and the renderer is borrowed
+        // for the StageRenderer's lifetime, so the stage can't have been destroyed since.
+        self.renderer
+            .stages
+            .get_mut(&self.handle)
+            .expect("Stage destroyed while drawing")
+    }
+}
+
+impl<'a, D> Renderer for StageRenderer<'a, D>
+where
+    D: Device,
+{
+    fn start_frame(&mut self, stage_size: Vector2F) {
+        let stage = self.stage();
+        stage.stage_size = stage_size;
+        stage.recording.clear();
+    }
+
+    fn set_background(&mut self, color: LinSrgba) {
+        self.stage().background = Some(color);
+    }
+
+    fn draw_background(&mut self, layer: BackgroundLayer) {
+        let stage = self.stage();
+        let area = RectF::new(Vector2F::zero(), stage.stage_size);
+        stage.recording.push(DisplayCommand::Background(
+            layer,
+            area,
+            Transform2F::default(),
+        ));
+    }
+
+    fn draw_shape(
+        &mut self,
+        shape: Arc<Shape>,
+        transform: Transform2F,
+        color_override: Option<Coloring>,
+        morph_index: f32,
+    ) {
+        self.draw_shape_instanced(
+            shape,
+            vec![ShapeInstance {
+                transform,
+                color_override,
+                morph_index,
+                morph_indices: vec![],
+            }],
+        );
+    }
+
+    fn draw_shape_instanced(&mut self, shape: Arc<Shape>, instances: Vec<ShapeInstance>) {
+        self.stage()
+            .recording
+            .push(DisplayCommand::Shapes(shape, instances));
+    }
+
+    fn draw_raster(
+        &mut self,
+        pattern: Arc<Pattern>,
+        view_rect: Option<RectF>,
+        transform: Transform2F,
+        tint: Option<Coloring>,
+    ) {
+        self.stage().recording.push(DisplayCommand::Raster(
+            pattern,
+            RasterInstance {
+                view_rect,
+                transform,
+                tint,
+            },
+        ));
+    }
+
+    fn push_clip(&mut self, clip: ClipRect) {
+        self.stage().recording.push(DisplayCommand::PushClip(clip));
+    }
+
+    fn pop_clip(&mut self) {
+        self.stage().recording.push(DisplayCommand::PopClip);
+    }
+
+    fn end_frame(&mut self) {
+        let stage = self.stage();
+        stage.display_list = mem::replace(&mut stage.recording, vec![]);
+    }
+}
@@ -0,0 +1,293 @@
+use fluster_graphics::{FlusterRendererImpl, FlusterRendererOptions};
+use glutin::{
+    dpi::PhysicalSize,
+    event_loop::EventLoop,
+    window::{WindowBuilder, WindowId},
+};
+use pathfinder_canvas::CanvasFontContext;
+use pathfinder_color::ColorF;
+use pathfinder_geometry::vector::Vector2I;
+use pathfinder_gpu::Device;
+use pathfinder_renderer::gpu::options::{DestFramebuffer, RendererMode, RendererOptions};
+use pathfinder_renderer::gpu::renderer::Renderer;
+use pathfinder_resources::embedded::EmbeddedResourceLoader;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    #[cfg(feature = "backend-gl")]
+    Gl,
+    #[cfg(all(feature = "backend-metal", target_os = "macos"))]
+    Metal,
+    /// OpenGL ES through ANGLE, which translates to Direct3D on Windows.
+    #[cfg(feature = "backend-angle")]
+    Angle,
+}
+
+impl Backend {
+    /// Backends compiled into this build, in order of preference.
+    pub fn available() -> Vec<Backend> {
+        vec![
+            #[cfg(all(feature = "backend-metal", target_os = "macos"))]
+            Backend::Metal,
+            #[cfg(feature = "backend-gl")]
+            Backend::Gl,
+            #[cfg(feature = "backend-angle")]
+            Backend::Angle,
+        ]
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let backend = match name.to_lowercase().as_str() {
+            #[cfg(feature = "backend-gl")]
+            "gl" => Backend::Gl,
+            #[cfg(all(feature = "backend-metal", target_os = "macos"))]
+            "metal" => Backend::Metal,
+            #[cfg(feature = "backend-angle")]
+            "angle" => Backend::Angle,
+            _ => {
+                return Err(format!(
+                    "Unknown or disabled backend {}. Available backends are {:?}",
+                    name,
+                    Backend::available()
+                ))
+            }
+        };
+        Ok(backend)
+    }
+}
+
+/// The window surface a backend draws to.
+pub struct Surface {
+    pub window_id: WindowId,
+    /// Device pixels per logical pixel.
+    pub scale_factor: f64,
+    /// The size of the drawable in device pixels.
+    pub physical_size: Vector2I,
+    /// Resizes the drawable, in device pixels. The renderer has to be resized separately.
+    pub resize: Box<dyn FnMut(Vector2I)>,
+    /// Points the graphics API at this surface. Has to be called before drawing to it whenever
+    /// there is more than one surface.
+    pub make_current: Box<dyn FnMut()>,
+}
+
+/// Everything a backend has to provide. Presenting the frame is left to `on_frame_end`.
+pub trait BackendRunner {
+    /// `window_size` is in logical pixels, and is the size of the stage. There is one output per
+    /// window builder passed to `start`, in the same order.
+    fn run<D: Device + 'static>(
+        self,
+        event_loop: EventLoop<()>,
+        outputs: Vec<(FlusterRendererImpl<D>, Surface)>,
+        window_size: Vector2I,
+    ) -> !;
+}
+
+pub fn to_vector(size: PhysicalSize<u32>) -> Vector2I {
+    Vector2I::new(size.width as i32, size.height as i32)
+}
+
+fn build_renderer<D: Device>(
+    device: D,
+    physical_size: Vector2I,
+    on_frame_end: Box<dyn FnMut(&mut Renderer<D>)>,
+) -> FlusterRendererImpl<D> {
+    let mode = RendererMode::default_for_device(&device);
+    let options = RendererOptions {
+        background_color: Some(ColorF::white()),
+        dest: DestFramebuffer::full_window(physical_size),
+        ..RendererOptions::default()
+    };
+    let renderer = Renderer::new(device, &EmbeddedResourceLoader, mode, options);
+    let font_context = CanvasFontContext::from_system_source();
+    FlusterRendererImpl::new(
+        font_context,
+        renderer,
+        FlusterRendererOptions {
+            level: mode.level,
+            ..FlusterRendererOptions::default()
+        },
+        on_frame_end,
+    )
+}
+
+/// Opens a window per builder, each with its own renderer.
+pub fn start<R: BackendRunner>(
+    backend: Backend,
+    event_loop: EventLoop<()>,
+    window_builders: Vec<WindowBuilder>,
+    window_size: Vector2I,
+    runner: R,
+) -> ! {
+    match backend {
+        #[cfg(feature = "backend-gl")]
+        Backend::Gl => {
+            let outputs = window_builders
+                .into_iter()
+                .map(|window_builder| gl_backend::create(&event_loop, window_builder, false))
+                .collect();
+            runner.run(event_loop, outputs, window_size)
+        }
+        #[cfg(feature = "backend-angle")]
+        Backend::Angle => {
+            let outputs = window_builders
+                .into_iter()
+                .map(|window_builder| gl_backend::create(&event_loop, window_builder, true))
+                .collect();
+            runner.run(event_loop, outputs, window_size)
+        }
+        #[cfg(all(feature = "backend-metal", target_os = "macos"))]
+        Backend::Metal => {
+            let outputs = window_builders
+                .into_iter()
+                .map(|window_builder| metal_backend::create(&event_loop, window_builder))
+                .collect();
+            runner.run(event_loop, outputs, window_size)
+        }
+    }
+}
+
+#[cfg(any(feature = "backend-gl", feature = "backend-angle"))]
+mod gl_backend {
+    use super::{build_renderer, to_vector, Surface};
+    use fluster_graphics::FlusterRendererImpl;
+    use glutin::{
+        dpi::PhysicalSize, event_loop::EventLoop, window::WindowBuilder, Api, ContextBuilder,
+        GlProfile, GlRequest,
+    };
+    use pathfinder_gl::{GLDevice, GLVersion};
+    use std::{cell::RefCell, rc::Rc};
+
+    pub fn create(
+        event_loop: &EventLoop<()>,
+        window_builder: WindowBuilder,
+        gles: bool,
+    ) -> (FlusterRendererImpl<GLDevice>, Surface) {
+        // ANGLE only exposes GLES, and is picked up by glutin's EGL loader when its libEGL is
+        // next to the executable
+        let (gl_request, gl_version) = if gles {
+            (GlRequest::Specific(Api::OpenGlEs, (3, 0)), GLVersion::GLES3)
+        } else {
+            (GlRequest::Latest, GLVersion::GL3)
+        };
+        let gl_context = ContextBuilder::new()
+            .with_gl(gl_request)
+            .with_gl_profile(GlProfile::Core)
+            .build_windowed(window_builder, event_loop)
+            .unwrap();
+
+        let gl_context = unsafe { gl_context.make_current().unwrap() };
+        gl::load_with(|name| gl_context.get_proc_address(name) as *const _);
+        let window_id = gl_context.window().id();
+        let physical_size = to_vector(gl_context.window().inner_size());
+        let scale_factor = gl_context.window().scale_factor();
+
+        // Each window has its own context and device, created while its context is current
+        let device = GLDevice::new(gl_version, 0);
+        // Making a context current consumes it, so it's taken out and put back
+        let gl_context = Rc::new(RefCell::new(Some(gl_context)));
+        let swap_context = gl_context.clone();
+        let resize_context = gl_context.clone();
+        // TODO: Is there benefit using swap_buffers_with_damage here? Investigate and possibly add it to display data generated from Engine
+        let fluster_renderer = build_renderer(
+            device,
+            physical_size,
+            Box::new(move |_| {
+                if let Some(swap_context) = swap_context.borrow().as_ref() {
+                    swap_context.swap_buffers().unwrap();
+                }
+            }),
+        );
+        let surface = Surface {
+            window_id,
+            scale_factor,
+            physical_size,
+            resize: Box::new(move |size| {
+                if let Some(resize_context) = resize_context.borrow().as_ref() {
+                    resize_context.resize(PhysicalSize::new(size.x() as u32, size.y() as u32))
+                }
+            }),
+            make_current: Box::new(move || {
+                let mut gl_context = gl_context.borrow_mut();
+                if let Some(context) = gl_context.take() {
+                    if context.is_current() {
+                        *gl_context = Some(context);
+                    } else {
+                        *gl_context = Some(unsafe { context.make_current().unwrap() });
+                    }
+                }
+            }),
+        };
+        (fluster_renderer, surface)
+    }
+}
+
+#[cfg(all(feature = "backend-metal", target_os = "macos"))]
+mod metal_backend {
+    use super::{build_renderer, to_vector, Surface};
+    use cocoa::{appkit::NSView, base::id as cocoa_id};
+    use core_graphics::geometry::CGSize;
+    use fluster_graphics::FlusterRendererImpl;
+    use glutin::{event_loop::EventLoop, platform::macos::WindowExtMacOS, window::WindowBuilder};
+    use metal::{CoreAnimationLayer, Device as MTLDevice, MTLPixelFormat};
+    use objc::runtime::YES;
+    use pathfinder_metal::MetalDevice;
+    use std::mem;
+
+    pub fn create(
+        event_loop: &EventLoop<()>,
+        window_builder: WindowBuilder,
+    ) -> (FlusterRendererImpl<MetalDevice>, Surface) {
+        let window = window_builder.build(event_loop).unwrap();
+        let window_id = window.id();
+        let physical_size = to_vector(window.inner_size());
+        let scale_factor = window.scale_factor();
+
+        let metal_device = MTLDevice::system_default().expect("No Metal device found");
+        let metal_layer = CoreAnimationLayer::new();
+        metal_layer.set_device(&metal_device);
+        metal_layer.set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+        metal_layer.set_drawable_size(CGSize::new(
+            physical_size.x() as f64,
+            physical_size.y() as f64,
+        ));
+        metal_layer.set_contents_scale(scale_factor);
+        unsafe {
+            let view = window.ns_view() as cocoa_id;
+            view.setWantsLayer(YES);
+            view.setLayer(mem::transmute(metal_layer.as_ref()));
+        }
+
+        let mut drawable = metal_layer.next_drawable().unwrap().to_owned();
+        let device = unsafe { MetalDevice::new(&metal_device, drawable.clone()) };
+        let resize_layer = metal_layer.clone();
+        let surface = Surface {
+            window_id,
+            scale_factor,
+            physical_size,
+            resize: Box::new(move |size| {
+                resize_layer.set_drawable_size(CGSize::new(size.x() as f64, size.y() as f64))
+            }),
+            // Every layer has its own device, so there is nothing to switch
+            make_current: Box::new(|| {}),
+        };
+        let fluster_renderer = build_renderer(
+            device,
+            physical_size,
+            Box::new(move |renderer| {
+                // The window has to outlive the layer it owns
+                let _ = &window;
+                let next_drawable = metal_layer.next_drawable().unwrap().to_owned();
+                renderer.device_mut().swap_texture(next_drawable.clone());
+                renderer
+                    .device_mut()
+                    .present_drawable(mem::replace(&mut drawable, next_drawable));
+            }),
+        );
+        (fluster_renderer, surface)
+    }
+}
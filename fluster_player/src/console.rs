@@ -0,0 +1,263 @@
+use fluster_core::actions::{ActionList, ContainerUpdateDefintition, ContainerUpdateProperty};
+use fluster_core::engine::Engine;
+use fluster_core::runner::Runner;
+use fluster_core::types::basic::{ContainerId, ScaleRotationTranslation};
+use pathfinder_geometry::vector::Vector2F;
+use std::io::{self, Write};
+
+const HELP: &str = "\
+list                          the scene graph, numbering each container
+inspect <container>           the components of a container
+set <container> <property>    position <x> <y>, scale <x> [<y>], rotation <degrees>,
+                              morph <index>, time-scale <scale> or snap <true|false>
+goto <label>                  continue playback from a label
+frame <frame>                 continue playback from a frame
+pause, play
+debug <overlay>               toggles bounds, quad-trees, dirty-regions or parent-links
+help
+
+Containers are given by their number in the last list, or as root.";
+
+/// A line based console for poking at the scene while it plays, toggled with the backtick key.
+/// Commands are typed into the player window, and both the line being typed and the output go
+/// to stdout. Changes are queued like any other update, so they show up on the next frame the
+/// runner steps.
+// TODO: draw the console over the stage instead of writing to stdout
+#[derive(Default)]
+pub struct Console {
+    open: bool,
+    line: String,
+    // Numbered by the last `list`
+    listed: Vec<ContainerId>,
+}
+
+impl Console {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.line.clear();
+        if self.open {
+            println!("Console open, type help for commands");
+            prompt("");
+        } else {
+            println!();
+        }
+    }
+
+    /// Takes a character typed while the console is open. Enter runs the line.
+    pub fn received_character(
+        &mut self,
+        character: char,
+        runner: &mut Runner,
+        actions: &mut ActionList,
+    ) {
+        match character {
+            // The toggle key arrives as a character too
+            '`' => return,
+            '\r' | '\n' => {
+                println!();
+                let line = std::mem::take(&mut self.line);
+                if let Err(error) = self.run(&line, runner, actions) {
+                    println!("{}", error);
+                }
+            }
+            '\u{8}' | '\u{7f}' => {
+                self.line.pop();
+            }
+            _ if character.is_control() => return,
+            _ => self.line.push(character),
+        }
+        prompt(&self.line);
+    }
+
+    fn run(
+        &mut self,
+        line: &str,
+        runner: &mut Runner,
+        actions: &mut ActionList,
+    ) -> Result<(), String> {
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        match words.as_slice() {
+            [] => {}
+            ["help"] => println!("{}", HELP),
+            ["list"] => self.list(runner.get_engine()),
+            ["inspect", container] => {
+                let container_id = self.find_container(runner.get_engine(), container)?;
+                inspect(runner.get_engine(), &container_id);
+            }
+            ["set", container, property, values @ ..] => {
+                let container_id = self.find_container(runner.get_engine(), container)?;
+                set_property(runner.get_engine_mut(), &container_id, property, values)?;
+                runner.request_redraw();
+            }
+            ["goto", label] => runner.goto_label(actions, label)?,
+            ["frame", frame] => runner.seek_to_frame(actions, parse(frame)?)?,
+            ["pause"] => runner.set_paused(true),
+            ["play"] => runner.set_paused(false),
+            ["debug", overlay] => {
+                let mut debug_flags = runner.get_debug_flags();
+                let flag = match *overlay {
+                    "bounds" => &mut debug_flags.bounds,
+                    "quad-trees" => &mut debug_flags.quad_trees,
+                    "dirty-regions" => &mut debug_flags.dirty_regions,
+                    "parent-links" => &mut debug_flags.parent_links,
+                    _ => return Err(format!("Unknown overlay {}", overlay)),
+                };
+                *flag = !*flag;
+                println!("{} {}", overlay, if *flag { "on" } else { "off" });
+                runner.set_debug_flags(debug_flags);
+            }
+            _ => return Err(format!("Can't run {}, type help for commands", line.trim())),
+        }
+        Ok(())
+    }
+
+    fn list(&mut self, engine: &Engine) {
+        self.listed.clear();
+        let mut stack = vec![(engine.get_root_container_id(), 0)];
+        while let Some((container_id, depth)) = stack.pop() {
+            let display = match engine.get_display(&container_id) {
+                Some((library_id, kind)) => format!(" displays {:?} ({:?})", library_id, kind),
+                None => String::new(),
+            };
+            println!(
+                "{:>4} {}{:?}{}",
+                self.listed.len(),
+                "  ".repeat(depth),
+                container_id,
+                display
+            );
+            self.listed.push(container_id);
+            let mut children = engine.get_children(&container_id).unwrap_or_default();
+            // Children come back in no particular order, so sort them to keep the numbering
+            // stable between lists
+            children.sort();
+            stack.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+        }
+    }
+
+    fn find_container(&self, engine: &Engine, name: &str) -> Result<ContainerId, String> {
+        if name == "root" {
+            return Ok(engine.get_root_container_id());
+        }
+        let index = parse::<usize>(name)?;
+        let container_id = self
+            .listed
+            .get(index)
+            .copied()
+            .ok_or_else(|| format!("No container {}, list them first", index))?;
+        // It may have been removed since the list
+        match engine.get_local_transform(&container_id) {
+            Some(_) => Ok(container_id),
+            None => Err(format!("Container {} no longer exists", index)),
+        }
+    }
+}
+
+fn prompt(line: &str) {
+    print!("\r> {}", line);
+    // Nothing to be done if stdout is gone
+    let _ = io::stdout().flush();
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {}", value))
+}
+
+fn inspect(engine: &Engine, container_id: &ContainerId) {
+    println!("{:?}", container_id);
+    if let Some(parent) = engine.get_parent(container_id) {
+        println!("  parent: {:?}", parent);
+    }
+    let children = engine.get_children(container_id).unwrap_or_default();
+    println!("  children: {}", children.len());
+    if let Some(transform) = engine.get_local_transform(container_id) {
+        println!(
+            "  local transform: {:?}",
+            ScaleRotationTranslation::from_transform(transform)
+        );
+    }
+    if let Some(transform) = engine.get_world_transform(container_id) {
+        println!("  world transform: {:?}", transform);
+    }
+    if let Some(bounds) = engine.get_bounds(container_id) {
+        println!("  bounds: {:?}", bounds);
+    }
+    if let Some((library_id, kind)) = engine.get_display(container_id) {
+        println!("  display: {:?} ({:?})", library_id, kind);
+    }
+    if let Some(view_rect) = engine.get_view_rect(container_id) {
+        println!("  view rect: {:?}", view_rect);
+    }
+    if let Some(time_scale) = engine.get_time_scale(container_id) {
+        println!("  time scale: {}", time_scale);
+    }
+    if let Some(path_trim) = engine.get_path_trim(container_id) {
+        println!("  path trim: {:?}", path_trim);
+    }
+    if let Some(scroll_view) = engine.get_scroll_view(container_id) {
+        println!("  scroll view: {:?}", scroll_view);
+    }
+    if let Some(anchor) = engine.get_anchor(container_id) {
+        println!("  anchor: {:?}", anchor);
+    }
+    if let Some(draggable) = engine.get_draggable(container_id) {
+        println!("  draggable: {:?}", draggable);
+    }
+}
+
+fn set_property(
+    engine: &mut Engine,
+    container_id: &ContainerId,
+    property: &str,
+    values: &[&str],
+) -> Result<(), String> {
+    let numbers = values
+        .iter()
+        .map(|value| parse::<f32>(value))
+        .collect::<Result<Vec<f32>, String>>();
+    let srt = engine
+        .get_local_transform(container_id)
+        .map(ScaleRotationTranslation::from_transform)
+        .unwrap_or_default();
+    match (property, numbers.as_deref()) {
+        ("position", Ok([x, y])) => engine.set_transform(
+            container_id,
+            ScaleRotationTranslation::new(srt.scale, srt.theta, Vector2F::new(*x, *y)),
+        ),
+        ("scale", Ok([scale])) => engine.set_transform(
+            container_id,
+            ScaleRotationTranslation::new(Vector2F::splat(*scale), srt.theta, srt.translation),
+        ),
+        ("scale", Ok([x, y])) => engine.set_transform(
+            container_id,
+            ScaleRotationTranslation::new(Vector2F::new(*x, *y), srt.theta, srt.translation),
+        ),
+        ("rotation", Ok([degrees])) => engine.set_transform(
+            container_id,
+            ScaleRotationTranslation::new(srt.scale, degrees.to_radians(), srt.translation),
+        ),
+        ("morph", Ok([morph_index])) => engine.set_morph_index(container_id, *morph_index),
+        ("time-scale", Ok([time_scale])) => engine.set_time_scale(container_id, *time_scale),
+        // Parsed on its own, since it isn't a number
+        ("snap", Err(_)) => {
+            let snap = match values {
+                ["true"] => true,
+                ["false"] => false,
+                _ => return Err("Expected true or false".to_string()),
+            };
+            engine.update_container(&ContainerUpdateDefintition::new(
+                *container_id,
+                vec![ContainerUpdateProperty::PixelSnap(snap)],
+            ));
+        }
+        (_, Err(error)) => return Err(error.clone()),
+        _ => return Err(format!("Can't set {} to {}", property, values.join(" "))),
+    }
+    Ok(())
+}
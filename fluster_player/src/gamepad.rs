@@ -0,0 +1,136 @@
+use fluster_core::{ecs::components::KeyModifiers, runner::Runner};
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use std::collections::HashMap;
+
+/// How far a stick has to be pushed before it counts as pressing a direction.
+const AXIS_THRESHOLD: f32 = 0.5;
+
+/// Maps controller input onto key names, so scenes can use the same `KeyBindings` for
+/// controllers as for keyboards.
+#[derive(Clone, Debug)]
+pub struct GamepadBindings {
+    pub buttons: HashMap<Button, String>,
+    /// The keys pressed when an axis is pushed past the threshold in its negative and positive
+    /// directions.
+    pub axes: HashMap<Axis, (String, String)>,
+}
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        let buttons = vec![
+            (Button::DPadUp, "ArrowUp"),
+            (Button::DPadDown, "ArrowDown"),
+            (Button::DPadLeft, "ArrowLeft"),
+            (Button::DPadRight, "ArrowRight"),
+            (Button::South, "Enter"),
+            (Button::East, "Escape"),
+            (Button::West, " "),
+            (Button::North, "Tab"),
+            (Button::LeftTrigger, "PageUp"),
+            (Button::RightTrigger, "PageDown"),
+            (Button::Start, "Pause"),
+        ];
+        let axes = vec![
+            (Axis::LeftStickX, ("ArrowLeft", "ArrowRight")),
+            // Up is positive
+            (Axis::LeftStickY, ("ArrowDown", "ArrowUp")),
+        ];
+        Self {
+            buttons: buttons
+                .into_iter()
+                .map(|(button, key)| (button, key.to_string()))
+                .collect(),
+            axes: axes
+                .into_iter()
+                .map(|(axis, (negative, positive))| {
+                    (axis, (negative.to_string(), positive.to_string()))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Routes controller events to the `Runner` as key presses.
+pub struct GamepadInput {
+    gilrs: Gilrs,
+    bindings: GamepadBindings,
+    // -1, 0 or 1 for each axis that has moved, so a held stick is one press
+    axis_directions: HashMap<(GamepadId, Axis), i8>,
+}
+
+impl GamepadInput {
+    pub fn new(bindings: GamepadBindings) -> Result<Self, String> {
+        let gilrs = Gilrs::new().map_err(|error| error.to_string())?;
+        Ok(Self {
+            gilrs,
+            bindings,
+            axis_directions: HashMap::new(),
+        })
+    }
+
+    /// Sends every controller event since the last call to the runner.
+    pub fn poll(&mut self, runner: &mut Runner) {
+        // TODO: map shoulder buttons to modifiers
+        let modifiers = KeyModifiers::default();
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    if let Some(key) = self.bindings.buttons.get(&button) {
+                        runner.key_down(key, modifiers);
+                    }
+                }
+                EventType::ButtonReleased(button, _) => {
+                    if let Some(key) = self.bindings.buttons.get(&button) {
+                        runner.key_up(key, modifiers);
+                    }
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    let direction = if value <= -AXIS_THRESHOLD {
+                        -1
+                    } else if value >= AXIS_THRESHOLD {
+                        1
+                    } else {
+                        0
+                    };
+                    let previous = self
+                        .axis_directions
+                        .insert((event.id, axis), direction)
+                        .unwrap_or_default();
+                    if direction != previous {
+                        if let Some(key) = self.axis_key(axis, previous) {
+                            runner.key_up(key, modifiers);
+                        }
+                        if let Some(key) = self.axis_key(axis, direction) {
+                            runner.key_down(key, modifiers);
+                        }
+                    }
+                }
+                EventType::Disconnected => {
+                    // Release anything the stick was holding down
+                    let held = self
+                        .axis_directions
+                        .iter()
+                        .filter(|((id, _), direction)| *id == event.id && **direction != 0)
+                        .map(|((_, axis), direction)| (*axis, *direction))
+                        .collect::<Vec<(Axis, i8)>>();
+                    for (axis, direction) in held {
+                        if let Some(key) = self.axis_key(axis, direction) {
+                            runner.key_up(key, modifiers);
+                        }
+                    }
+                    self.axis_directions.retain(|(id, _), _| *id != event.id);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn axis_key(&self, axis: Axis, direction: i8) -> Option<&str> {
+        let (negative, positive) = self.bindings.axes.get(&axis)?;
+        match direction {
+            -1 => Some(negative),
+            1 => Some(positive),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::images::write_png;
+use fluster_core::actions::ActionList;
+use fluster_core::ecs::resources::RenderQuality;
+use fluster_core::runner::{FrameResult, Runner};
+use fluster_core::watermark::Watermark;
+use fluster_graphics::{FlusterRendererImpl, FlusterRendererOptions};
+use glutin::{dpi::PhysicalSize, Context, ContextBuilder, GlProfile, GlRequest, PossiblyCurrent};
+use log::{error, info};
+use pathfinder_canvas::CanvasFontContext;
+use pathfinder_color::ColorF;
+use pathfinder_geometry::rect::RectI;
+use pathfinder_geometry::vector::Vector2I;
+use pathfinder_gl::{GLDevice, GLVersion};
+use pathfinder_gpu::{Device, RenderTarget, TextureData, TextureFormat};
+use pathfinder_renderer::gpu::options::{DestFramebuffer, RendererMode, RendererOptions};
+use pathfinder_renderer::gpu::renderer::Renderer;
+use pathfinder_resources::embedded::EmbeddedResourceLoader;
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+pub struct HeadlessOptions {
+    /// Stop after this many frames have been presented.
+    pub frames: usize,
+    /// When set, every presented frame is written here as `frame_00000.png`.
+    pub output: Option<PathBuf>,
+    pub watermark: Option<Watermark>,
+    pub render_quality: RenderQuality,
+}
+
+// A renderer drawing to an offscreen framebuffer, and the context it draws with
+struct HeadlessRenderer {
+    renderer: FlusterRendererImpl<GLDevice>,
+    frames_presented: Rc<Cell<usize>>,
+    // Dropped after the renderer, which still needs it current
+    _gl_context: Context<PossiblyCurrent>,
+}
+
+/// Renders without a window or display server. On Linux this uses an OSMesa context, so it
+/// works on CI machines with no GPU at all, other platforms fall back to a hidden pbuffer.
+pub fn run(
+    window_size: Vector2I,
+    options: HeadlessOptions,
+    mut action_list: ActionList,
+) -> Result<(), String> {
+    let mut headless_renderer = create_renderer(window_size, options.output.clone())?;
+    let mut runner = Runner::initialize(
+        &mut action_list,
+        Duration::from_secs_f64(1.0 / 60.0),
+        window_size.to_f32(),
+    )?;
+    runner.set_watermark(options.watermark.as_ref())?;
+    runner.set_render_quality(options.render_quality);
+    while headless_renderer.frames_presented.get() < options.frames {
+        match runner.next_frame(&mut headless_renderer.renderer, &mut action_list)? {
+            FrameResult::Wait(until) => {
+                let now = Instant::now();
+                if until > now {
+                    thread::sleep(until - now);
+                }
+            }
+            FrameResult::Continue => {}
+            FrameResult::Quit => break,
+        }
+    }
+    info!(
+        "Rendered {} frames headless",
+        headless_renderer.frames_presented.get()
+    );
+    Ok(())
+}
+
+// When `output` is set, every presented frame is written there
+fn create_renderer(
+    window_size: Vector2I,
+    output: Option<PathBuf>,
+) -> Result<HeadlessRenderer, String> {
+    let size = PhysicalSize::new(window_size.x() as u32, window_size.y() as u32);
+    let context_builder = ContextBuilder::new()
+        .with_gl(GlRequest::Latest)
+        .with_gl_profile(GlProfile::Core);
+    #[cfg(target_os = "linux")]
+    let gl_context = {
+        use glutin::platform::unix::OsMesaContextExt;
+        context_builder.build_osmesa(size)
+    };
+    #[cfg(not(target_os = "linux"))]
+    let gl_context = {
+        let event_loop = glutin::event_loop::EventLoop::new();
+        context_builder.build_headless(&event_loop, size)
+    };
+    let gl_context = gl_context.map_err(|error| format!("{:?}", error))?;
+    let gl_context =
+        unsafe { gl_context.make_current() }.map_err(|(_, error)| format!("{:?}", error))?;
+    gl::load_with(|name| gl_context.get_proc_address(name) as *const _);
+
+    let device = GLDevice::new(GLVersion::GL3, 0);
+    let texture = device.create_texture(TextureFormat::RGBA8, window_size);
+    let framebuffer = device.create_framebuffer(texture);
+    let mode = RendererMode::default_for_device(&device);
+    let renderer_options = RendererOptions {
+        background_color: Some(ColorF::white()),
+        dest: DestFramebuffer::Other(framebuffer),
+        ..RendererOptions::default()
+    };
+    let renderer = Renderer::new(device, &EmbeddedResourceLoader, mode, renderer_options);
+
+    let frames_presented = Rc::new(Cell::new(0));
+    let on_frame_end = {
+        let frames_presented = frames_presented.clone();
+        Box::new(move |renderer: &mut Renderer<GLDevice>| {
+            let frame = frames_presented.get();
+            frames_presented.set(frame + 1);
+            if let Some(output) = &output {
+                let path = output.join(format!("frame_{:05}.png", frame));
+                if let Err(error) = write_frame(renderer, window_size, &path) {
+                    error!("Failed to write {}: {}", path.display(), error);
+                }
+            }
+        })
+    };
+    let renderer = FlusterRendererImpl::new(
+        CanvasFontContext::from_system_source(),
+        renderer,
+        FlusterRendererOptions {
+            level: mode.level,
+            ..FlusterRendererOptions::default()
+        },
+        on_frame_end,
+    );
+    Ok(HeadlessRenderer {
+        renderer,
+        frames_presented,
+        _gl_context: gl_context,
+    })
+}
+
+fn write_frame(
+    renderer: &mut Renderer<GLDevice>,
+    size: Vector2I,
+    path: &Path,
+) -> Result<(), String> {
+    let device = renderer.device();
+    let pixels = match &renderer.options().dest {
+        DestFramebuffer::Other(framebuffer) => {
+            let receiver = device.read_pixels(
+                &RenderTarget::Framebuffer(framebuffer),
+                RectI::new(Vector2I::zero(), size),
+            );
+            device.recv_texture_data(&receiver)
+        }
+        DestFramebuffer::Default { .. } => return Err("Nothing to read back".to_string()),
+    };
+    let pixels = match pixels {
+        TextureData::U8(pixels) => pixels,
+        _ => return Err("Unexpected framebuffer format".to_string()),
+    };
+    // glReadPixels returns the bottom row first, but pathfinder's GL device flips the rows before
+    // handing them over, so they're already in the top down order PNGs want
+    write_png(path, size, &pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::images::read_png;
+    use fluster_core::actions::{Action, ContainerCreationDefintition, ContainerCreationProperty};
+    use fluster_core::types::basic::{ContainerId, LibraryId, ScaleRotationTranslation};
+    use fluster_core::types::shapes::{Contour, Edge, Shape};
+    use palette::LinSrgba;
+    use pathfinder_color::ColorU;
+    use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+    use std::{env, fs};
+
+    // A red bar over the top half of an 8 by 8 stage
+    fn top_half_actions() -> Vec<Action> {
+        let root_id = ContainerId::new();
+        let shape_id = LibraryId::new();
+        vec![
+            Action::CreateRoot(root_id),
+            Action::DefineShape {
+                id: shape_id,
+                shape: Shape::Fill {
+                    contours: Contour::from_edges(&Edge::new_rect(
+                        Vector2F::new(8.0, 4.0),
+                        Transform2F::default(),
+                    )),
+                    color: LinSrgba::new(1.0, 0.0, 0.0, 1.0),
+                },
+            },
+            Action::CreateContainer(ContainerCreationDefintition::new(
+                root_id,
+                ContainerId::new(),
+                vec![
+                    ContainerCreationProperty::Transform(ScaleRotationTranslation::default()),
+                    ContainerCreationProperty::Display(shape_id),
+                ],
+            )),
+            Action::EndInitialization,
+            Action::PresentFrame(0, 1),
+        ]
+    }
+
+    // Renders through OSMesa, which the default test run can't count on. Run with
+    // `cargo test -p fluster_player -- --ignored` where it's installed
+    #[test]
+    #[ignore = "needs OSMesa"]
+    fn it_writes_frames_top_row_first() {
+        let output = env::temp_dir().join("fluster_headless_rows");
+        fs::create_dir_all(&output).unwrap();
+        let actions = top_half_actions();
+        run(
+            Vector2I::splat(8),
+            HeadlessOptions {
+                frames: 1,
+                output: Some(output.clone()),
+                watermark: None,
+                render_quality: RenderQuality::default(),
+            },
+            ActionList::new(Box::new(|| None), Some(&actions)),
+        )
+        .unwrap();
+        let bitmap = read_png(&output.join("frame_00000.png")).unwrap();
+        let red = ColorU::new(255, 0, 0, 255);
+        assert_eq!(bitmap.colors[0], red);
+        assert_ne!(bitmap.colors[bitmap.colors.len() - 1], red);
+    }
+
+    #[test]
+    #[ignore = "needs OSMesa"]
+    fn it_captures_frames_top_row_first() {
+        let mut headless_renderer = create_renderer(Vector2I::splat(8), None).unwrap();
+        let actions = top_half_actions();
+        let mut action_list = ActionList::new(Box::new(|| None), Some(&actions));
+        let mut runner = Runner::initialize(
+            &mut action_list,
+            Duration::from_secs_f64(1.0 / 60.0),
+            Vector2F::splat(8.0),
+        )
+        .unwrap();
+        runner.capture_next_frame();
+        let capture = loop {
+            if let Some(capture) = runner.take_capture() {
+                break capture.unwrap();
+            }
+            if let FrameResult::Quit = runner
+                .next_frame(&mut headless_renderer.renderer, &mut action_list)
+                .unwrap()
+            {
+                panic!("Quit before the frame was captured");
+            }
+        };
+        let pixels = &capture.buffer.pixels;
+        assert_eq!(&pixels[..4], &[255, 0, 0, 255]);
+        assert_ne!(&pixels[pixels.len() - 4..], &[255, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,49 @@
+use fluster_core::types::basic::Bitmap;
+use pathfinder_color::ColorU;
+use pathfinder_geometry::vector::Vector2I;
+use std::{fs::File, io::BufWriter, path::Path, sync::Arc};
+
+/// Writes RGBA8 pixels to `path` as a PNG.
+pub fn write_png(path: &Path, size: Vector2I, pixels: &[u8]) -> Result<(), String> {
+    let file = File::create(path).map_err(|error| error.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), size.x() as u32, size.y() as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(|error| error.to_string())
+}
+
+/// Reads an 8 bit RGB or RGBA PNG.
+pub fn read_png(path: &Path) -> Result<Bitmap, String> {
+    let file = File::open(path).map_err(|error| error.to_string())?;
+    let (info, mut reader) = png::Decoder::new(file)
+        .read_info()
+        .map_err(|error| error.to_string())?;
+    let mut pixels = vec![0; info.buffer_size()];
+    reader
+        .next_frame(&mut pixels)
+        .map_err(|error| error.to_string())?;
+    let colors = match (info.color_type, info.bit_depth) {
+        (png::ColorType::RGBA, png::BitDepth::Eight) => pixels
+            .chunks_exact(4)
+            .map(|pixel| ColorU::new(pixel[0], pixel[1], pixel[2], pixel[3]))
+            .collect(),
+        (png::ColorType::RGB, png::BitDepth::Eight) => pixels
+            .chunks_exact(3)
+            .map(|pixel| ColorU::new(pixel[0], pixel[1], pixel[2], 255))
+            .collect(),
+        (color_type, bit_depth) => {
+            return Err(format!(
+                "Unsupported PNG format {:?} {:?}",
+                color_type, bit_depth
+            ))
+        }
+    };
+    Ok(Bitmap {
+        size_x: info.width as i32,
+        size_y: info.height as i32,
+        colors: Arc::new(colors),
+    })
+}
@@ -1,37 +1,63 @@
 #![deny(clippy::all)]
+mod backend;
+mod console;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+#[cfg(feature = "backend-gl")]
+mod headless;
+#[cfg(feature = "png")]
+mod images;
+#[cfg(feature = "remote")]
+mod remote;
+
+#[cfg(not(any(
+    feature = "backend-gl",
+    feature = "backend-metal",
+    feature = "backend-angle"
+)))]
+compile_error!(
+    "fluster_player needs at least one of the backend-gl, backend-metal or backend-angle features"
+);
+
+use backend::{to_vector, Backend, BackendRunner, Surface};
 use fluster_core::actions::{
     Action, ActionList, ContainerCreationDefintition, ContainerCreationProperty,
     ContainerUpdateDefintition, ContainerUpdateProperty,
 };
+use fluster_core::ecs::{
+    components::KeyModifiers,
+    resources::{DebugFlags, RenderQuality},
+};
+use fluster_core::pacing::{FramePacing, PacingMode};
 use fluster_core::runner;
 use fluster_core::tween::Easing;
 use fluster_core::types::{
     basic::{ContainerId, LibraryId, ScaleRotationTranslation},
     coloring::{ColorSpace, Coloring},
-    shapes::{AugmentedShape, Edge, MorphEdge, Shape},
+    shapes::{AugmentedShape, Contour, Edge, MorphContour, MorphEdge, Shape},
 };
+use fluster_core::watermark::{Corner, Watermark};
 use fluster_graphics::FlusterRendererImpl;
 use glutin::{
-    dpi::PhysicalSize,
-    event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    dpi::LogicalSize,
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
-    ContextBuilder, GlProfile, GlRequest,
+    monitor::MonitorHandle,
+    window::{Fullscreen, WindowBuilder, WindowId},
 };
+use log::{debug, error, info};
 use palette::{LinSrgba, Srgb, Srgba};
-use pathfinder_canvas::CanvasFontContext;
-use pathfinder_color::ColorF;
 use pathfinder_content::stroke::{LineCap, LineJoin, StrokeStyle};
+use pathfinder_geometry::rect::RectF;
 use pathfinder_geometry::transform2d::Transform2F;
 use pathfinder_geometry::vector::{Vector2F, Vector2I};
-use pathfinder_gl::{GLDevice, GLVersion};
-use pathfinder_renderer::gpu::options::{DestFramebuffer, RendererMode, RendererOptions};
-use pathfinder_renderer::gpu::renderer::Renderer;
-use pathfinder_resources::embedded::EmbeddedResourceLoader;
-use runner::{FrameResult, Runner};
-use std::{f32::consts::PI, time::Duration};
+use pathfinder_gpu::Device;
+use runner::{FrameCapture, FrameResult, Runner, Viewport};
+#[cfg(feature = "backend-gl")]
+use std::fs;
+use std::{env, f32::consts::PI, path::PathBuf, process, time::Duration};
 
-fn build_action_list() -> ActionList {
+fn build_action_list(render_quality: &RenderQuality) -> ActionList {
     let shape_id = LibraryId::new();
     let shape2_id = LibraryId::new();
     let shape3_id = LibraryId::new();
@@ -49,10 +75,10 @@ fn build_action_list() -> ActionList {
         Action::DefineShape {
             id: shape_id,
             shape: Shape::Fill {
-                edges: Edge::new_rect(
+                contours: Contour::from_edges(&Edge::new_rect(
                     Vector2F::splat(30.0),
                     Transform2F::from_translation(Vector2F::splat(-15.0)),
-                ),
+                )),
                 color: Srgba::<f32>::from_format(Srgba::<u8>::new(149, 125, 173, 255))
                     .into_linear(),
             },
@@ -60,10 +86,10 @@ fn build_action_list() -> ActionList {
         Action::DefineShape {
             id: shape2_id,
             shape: Shape::Path {
-                edges: Edge::new_rect(
+                contours: Contour::from_edges(&Edge::new_rect(
                     Vector2F::splat(30.0),
                     Transform2F::from_translation(Vector2F::splat(-15.0)),
-                ),
+                )),
                 stroke_style: StrokeStyle {
                     line_width: 3.0,
                     line_cap: LineCap::Square,
@@ -78,10 +104,10 @@ fn build_action_list() -> ActionList {
                 shapes: vec![
                     AugmentedShape {
                         shape: Shape::Fill {
-                            edges: Edge::new_rect(
+                            contours: Contour::from_edges(&Edge::new_rect(
                                 Vector2F::splat(30.0),
                                 Transform2F::from_translation(Vector2F::splat(-15.0)),
-                            ),
+                            )),
                             color: Srgba::<f32>::from_format(Srgba::<u8>::new(149, 125, 173, 255))
                                 .into_linear(),
                         },
@@ -93,11 +119,11 @@ fn build_action_list() -> ActionList {
                     },
                     AugmentedShape {
                         shape: Shape::Fill {
-                            edges: Edge::new_polygon(
+                            contours: Contour::from_edges(&Edge::new_polygon(
                                 5,
                                 30.0,
                                 Transform2F::from_translation(Vector2F::splat(-15.0)),
-                            ),
+                            )),
                             color: Srgba::<f32>::from_format(Srgba::<u8>::new(149, 125, 173, 255))
                                 .into_linear(),
                         },
@@ -109,11 +135,12 @@ fn build_action_list() -> ActionList {
                     },
                     AugmentedShape {
                         shape: Shape::Fill {
-                            edges: Edge::new_superellipse(
+                            contours: Contour::from_edges(&Edge::new_superellipse_with_steps(
                                 Vector2F::splat(30.0),
                                 4.0,
+                                render_quality.procedural_steps,
                                 Transform2F::from_translation(Vector2F::splat(-15.0)),
-                            ),
+                            )),
                             color: Srgba::<f32>::from_format(Srgba::<u8>::new(149, 125, 173, 255))
                                 .into_linear(),
                         },
@@ -125,10 +152,10 @@ fn build_action_list() -> ActionList {
                     },
                     AugmentedShape {
                         shape: Shape::Fill {
-                            edges: Edge::new_ellipse(
+                            contours: Contour::from_edges(&Edge::new_ellipse(
                                 Vector2F::splat(15.0),
                                 Transform2F::from_translation(Vector2F::splat(-15.0)),
-                            ),
+                            )),
                             color: Srgba::<f32>::from_format(Srgba::<u8>::new(149, 125, 173, 255))
                                 .into_linear(),
                         },
@@ -144,7 +171,7 @@ fn build_action_list() -> ActionList {
         Action::DefineShape {
             id: shape4_id,
             shape: Shape::Path {
-                edges: vec![
+                contours: Contour::from_edges(&[
                     Edge::Move(Vector2F::new(300.0, 100.0)),
                     Edge::Line(Vector2F::new(258.0, 142.0)),
                     Edge::Bezier {
@@ -162,7 +189,7 @@ fn build_action_list() -> ActionList {
                         to: Vector2F::new(360.0, 92.0),
                         radius: 21.0,
                     },
-                ],
+                ]),
                 stroke_style: StrokeStyle {
                     line_width: 3.0,
                     line_cap: LineCap::Square,
@@ -174,13 +201,13 @@ fn build_action_list() -> ActionList {
         Action::DefineShape {
             id: shape5_id,
             shape: Shape::MorphPath {
-                edges: vec![
+                contours: MorphContour::from_edges(&[
                     MorphEdge::Line(Vector2F::new(-15.0, -15.0), Vector2F::new(-18.0, -12.0)),
                     MorphEdge::Line(Vector2F::new(15.0, -15.0), Vector2F::new(0.0, -22.0)),
                     MorphEdge::Line(Vector2F::new(15.0, 15.0), Vector2F::new(30.0, 15.0)),
                     MorphEdge::Line(Vector2F::new(-15.0, 15.0), Vector2F::new(-11.0, 33.0)),
                     MorphEdge::Close,
-                ],
+                ]),
                 stroke_style: StrokeStyle {
                     line_width: 3.0,
                     line_cap: LineCap::Square,
@@ -308,79 +335,548 @@ fn build_action_list() -> ActionList {
     ActionList::new(Box::new(|| None), Some(&actions))
 }
 
-fn main() {
-    let event_loop = EventLoop::new();
-    let window_size = Vector2I::new(800, 600);
-    let physical_window_size = PhysicalSize::new(window_size.x() as f64, window_size.y() as f64);
+/// A window and the part of the stage it shows.
+struct Output<D: Device> {
+    renderer: FlusterRendererImpl<D>,
+    surface: Surface,
+    stage_rect: RectF,
+}
 
-    let window_builder = WindowBuilder::new()
-        .with_title("Fluster Player")
-        .with_inner_size(physical_window_size);
+struct Player {
+    /// The part of the stage each window shows. With none, a single window shows the whole
+    /// stage, and resizing the window resizes the stage.
+    viewports: Option<Vec<RectF>>,
+    watermark: Option<Watermark>,
+}
 
-    let gl_context = ContextBuilder::new()
-        .with_gl(GlRequest::Latest)
-        .with_gl_profile(GlProfile::Core)
-        .build_windowed(window_builder, &event_loop)
+impl BackendRunner for Player {
+    fn run<D: Device + 'static>(
+        self,
+        event_loop: EventLoop<()>,
+        outputs: Vec<(FlusterRendererImpl<D>, Surface)>,
+        window_size: Vector2I,
+    ) -> ! {
+        let stage_size = window_size.to_f32();
+        let follow_window = self.viewports.is_none();
+        let viewports = self
+            .viewports
+            .unwrap_or_else(|| vec![RectF::new(Vector2F::zero(), stage_size)]);
+        let mut outputs = outputs
+            .into_iter()
+            .zip(viewports.into_iter())
+            .map(|((renderer, surface), stage_rect)| Output {
+                renderer,
+                surface,
+                stage_rect,
+            })
+            .collect::<Vec<Output<D>>>();
+        let render_quality = render_quality_from_args();
+        let mut action_list = build_action_list(&render_quality);
+        let mut runner = Runner::initialize(
+            &mut action_list,
+            Duration::from_secs_f64(1.0 / 60.0),
+            stage_size,
+        )
         .unwrap();
+        runner.set_device_pixel_ratio(outputs[0].surface.scale_factor as f32);
+        if let Err(error) = runner.set_watermark(self.watermark.as_ref()) {
+            error!("Failed to add the watermark: {}", error);
+        }
+        runner.set_pacing(pacing_from_args());
+        runner.set_render_quality(render_quality);
+        #[cfg(feature = "gamepad")]
+        let mut gamepad_input = match gamepad::GamepadInput::new(Default::default()) {
+            Ok(gamepad_input) => Some(gamepad_input),
+            Err(error) => {
+                log::warn!("Controllers unavailable: {}", error);
+                None
+            }
+        };
+        #[cfg(feature = "remote")]
+        let mut remote_control = match remote::RemoteControl::bind(
+            find_arg("--remote-port=")
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(remote::DEFAULT_PORT),
+        ) {
+            Ok(remote_control) => Some(remote_control),
+            Err(error) => {
+                log::warn!("Remote control unavailable: {}", error);
+                None
+            }
+        };
+        let mut console = console::Console::default();
+        event_loop.run(move |event, _, control_flow| {
+            match event {
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(physical_size),
+                    window_id,
+                } => {
+                    if let Some(output) = find_output(&mut outputs, window_id) {
+                        output.resize(to_vector(physical_size));
+                        if follow_window {
+                            let stage_size = to_vector(physical_size).to_f32()
+                                * (1.0 / runner.get_device_pixel_ratio());
+                            output.stage_rect = RectF::new(Vector2F::zero(), stage_size);
+                            runner.set_stage_size(stage_size);
+                        }
+                    }
+                    runner.request_redraw();
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::ScaleFactorChanged {
+                            scale_factor,
+                            new_inner_size,
+                        },
+                    window_id,
+                } => {
+                    if let Some(output) = find_output(&mut outputs, window_id) {
+                        output.surface.scale_factor = scale_factor;
+                        output.resize(to_vector(*new_inner_size));
+                        if follow_window {
+                            let stage_size =
+                                to_vector(*new_inner_size).to_f32() * (1.0 / scale_factor as f32);
+                            output.stage_rect = RectF::new(Vector2F::zero(), stage_size);
+                            runner.set_device_pixel_ratio(scale_factor as f32);
+                            runner.set_stage_size(stage_size);
+                        }
+                    }
+                    runner.request_redraw();
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(VirtualKeyCode::Grave),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => console.toggle(),
+                // Typing into the console shouldn't reach the scene, or quit
+                Event::WindowEvent {
+                    event: WindowEvent::KeyboardInput { .. },
+                    ..
+                } if console.is_open() => {}
+                Event::WindowEvent {
+                    event: WindowEvent::ReceivedCharacter(character),
+                    ..
+                } => {
+                    if console.is_open() {
+                        console.received_character(character, &mut runner, &mut action_list);
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    ..
+                }
+                | Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::Escape),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    *control_flow = ControlFlow::Exit;
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state,
+                                    virtual_keycode: Some(keycode),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    // TODO: track modifier keys
+                    let modifiers = KeyModifiers::default();
+                    let handled = key_name(keycode).map_or(false, |key| match state {
+                        ElementState::Pressed => runner.key_down(key, modifiers),
+                        ElementState::Released => runner.key_up(key, modifiers),
+                    });
+                    if !handled && state == ElementState::Pressed {
+                        if keycode == VirtualKeyCode::F12 {
+                            runner.capture_next_frame();
+                        } else {
+                            let debug_flags = toggle_debug_flag(runner.get_debug_flags(), keycode);
+                            runner.set_debug_flags(debug_flags);
+                        }
+                    }
+                }
+                _ => {
+                    // Controllers are only read when the event loop wakes, which is at least once
+                    // a frame while anything is animating
+                    #[cfg(feature = "gamepad")]
+                    {
+                        if let Some(gamepad_input) = &mut gamepad_input {
+                            gamepad_input.poll(&mut runner);
+                        }
+                    }
+                    #[cfg(feature = "remote")]
+                    {
+                        if let Some(remote_control) = &mut remote_control {
+                            remote_control.poll(&mut runner, &mut action_list);
+                        }
+                    }
+                    // Taken lazily, so each window's context is made current just before it's drawn
+                    let frame_outputs = outputs.iter_mut().map(|output| {
+                        (output.surface.make_current)();
+                        let viewport = Viewport {
+                            stage_rect: output.stage_rect,
+                            output_size: output.surface.physical_size.to_f32(),
+                        };
+                        (&mut output.renderer, viewport)
+                    });
+                    *control_flow =
+                        match runner.next_frame_viewports(frame_outputs, &mut action_list) {
+                            Ok(FrameResult::Wait(until)) => ControlFlow::WaitUntil(until),
+                            Ok(FrameResult::Continue) => ControlFlow::Poll,
+                            Ok(FrameResult::Quit) => ControlFlow::Exit,
+                            Err(error) => {
+                                error!("{}", error);
+                                ControlFlow::Exit
+                            }
+                        };
+                    match runner.take_capture() {
+                        Some(Ok(capture)) => save_capture(&capture),
+                        Some(Err(error)) => error!("Screenshot failed: {}", error),
+                        None => {}
+                    }
+                    for marker in runner.poll_markers() {
+                        info!("marker {} at frame {}", marker.name, marker.frame);
+                    }
+                    let stats = runner.stats();
+                    debug!(
+                        "frame {} time {:?}, {:.1}% of target, {} drawables",
+                        stats.frame,
+                        stats.cpu_time,
+                        stats.load() * 100.0,
+                        stats.drawable_count
+                    );
+                }
+            };
+        })
+    }
+}
 
-    let gl_context = unsafe { gl_context.make_current().unwrap() };
-    gl::load_with(|name| gl_context.get_proc_address(name) as *const _);
+impl<D: Device> Output<D> {
+    fn resize(&mut self, physical_size: Vector2I) {
+        (self.surface.make_current)();
+        (self.surface.resize)(physical_size);
+        self.surface.physical_size = physical_size;
+        self.renderer.resize(physical_size);
+    }
+}
 
-    let device = GLDevice::new(GLVersion::GL3, 0);
-    let mode = RendererMode::default_for_device(&device);
-    let options = RendererOptions {
-        background_color: Some(ColorF::white()),
-        dest: DestFramebuffer::full_window(window_size),
-        ..RendererOptions::default()
+fn find_output<D: Device>(
+    outputs: &mut [Output<D>],
+    window_id: WindowId,
+) -> Option<&mut Output<D>> {
+    outputs
+        .iter_mut()
+        .find(|output| output.surface.window_id == window_id)
+}
+
+// F12 saves the next frame to the working directory, named after its frame index
+fn save_capture(capture: &FrameCapture) {
+    let path = PathBuf::from(format!("frame_{:05}.png", capture.frame));
+    #[cfg(feature = "png")]
+    let result = images::write_png(&path, capture.buffer.size, &capture.buffer.pixels);
+    #[cfg(not(feature = "png"))]
+    let result: Result<(), String> = Err("Saving screenshots requires the png feature".into());
+    match result {
+        Ok(()) => info!("Saved {}", path.display()),
+        Err(error) => error!("Failed to write {}: {}", path.display(), error),
+    }
+}
+
+// F1 bounds, F2 quad trees, F3 dirty regions, F4 parent links
+fn toggle_debug_flag(mut debug_flags: DebugFlags, keycode: VirtualKeyCode) -> DebugFlags {
+    match keycode {
+        VirtualKeyCode::F1 => debug_flags.bounds = !debug_flags.bounds,
+        VirtualKeyCode::F2 => debug_flags.quad_trees = !debug_flags.quad_trees,
+        VirtualKeyCode::F3 => debug_flags.dirty_regions = !debug_flags.dirty_regions,
+        VirtualKeyCode::F4 => debug_flags.parent_links = !debug_flags.parent_links,
+        _ => {}
+    }
+    debug_flags
+}
+
+// Names from the W3C KeyboardEvent.key values, for the keys scenes are likely to bind
+fn key_name(keycode: VirtualKeyCode) -> Option<&'static str> {
+    let key = match keycode {
+        VirtualKeyCode::Left => "ArrowLeft",
+        VirtualKeyCode::Right => "ArrowRight",
+        VirtualKeyCode::Up => "ArrowUp",
+        VirtualKeyCode::Down => "ArrowDown",
+        VirtualKeyCode::Tab => "Tab",
+        VirtualKeyCode::Return => "Enter",
+        VirtualKeyCode::Space => " ",
+        VirtualKeyCode::Back => "Backspace",
+        VirtualKeyCode::Home => "Home",
+        VirtualKeyCode::End => "End",
+        VirtualKeyCode::PageUp => "PageUp",
+        VirtualKeyCode::PageDown => "PageDown",
+        VirtualKeyCode::A => "a",
+        VirtualKeyCode::D => "d",
+        VirtualKeyCode::S => "s",
+        VirtualKeyCode::W => "w",
+        _ => return None,
     };
-    let renderer = Renderer::new(device, &EmbeddedResourceLoader, mode, options);
+    Some(key)
+}
 
-    let font_context = CanvasFontContext::from_system_source();
+// --vsync, --max-fps=<fps> and --on-demand
+fn pacing_from_args() -> FramePacing {
+    let has_flag = |flag: &str| env::args().skip(1).any(|arg| arg == flag);
+    FramePacing {
+        mode: if has_flag("--vsync") {
+            PacingMode::Vsync
+        } else {
+            PacingMode::Timed
+        },
+        max_fps: find_arg("--max-fps=").and_then(|max_fps| max_fps.parse().ok()),
+        render_on_demand: has_flag("--on-demand"),
+    }
+}
 
-    // TODO: Is there benefit using swap_buffers_with_damage here? Investigate and possibly add it to display data generated from Engine
-    let mut fluster_renderer = FlusterRendererImpl::new(
-        font_context,
-        renderer,
-        Box::new(move || gl_context.swap_buffers().unwrap()),
-    );
-    let mut action_list = build_action_list();
-    let mut runner = Runner::initialize(
-        &mut action_list,
-        Duration::from_secs_f64(1.0 / 60.0),
-        window_size.to_f32(),
+/// `--low-quality` flattens curves coarsely and builds procedural shapes from fewer points, for
+/// low power devices.
+fn render_quality_from_args() -> RenderQuality {
+    if env::args().skip(1).any(|arg| arg == "--low-quality") {
+        RenderQuality::low()
+    } else {
+        RenderQuality::default()
+    }
+}
+
+fn find_arg(prefix: &str) -> Option<String> {
+    env::args()
+        .skip(1)
+        .find_map(|arg| arg.strip_prefix(prefix).map(|value| value.to_string()))
+}
+
+#[cfg(feature = "backend-gl")]
+fn run_headless(window_size: Vector2I) -> Result<(), String> {
+    let frames = match find_arg("--frames=") {
+        Some(frames) => frames
+            .parse()
+            .map_err(|_| format!("Invalid frame count {}", frames))?,
+        None => 60,
+    };
+    let output = find_arg("--output=").map(PathBuf::from);
+    if let Some(output) = &output {
+        fs::create_dir_all(output).map_err(|error| error.to_string())?;
+    }
+    let render_quality = render_quality_from_args();
+    headless::run(
+        window_size,
+        headless::HeadlessOptions {
+            frames,
+            output,
+            watermark: watermark_from_args()?,
+            render_quality,
+        },
+        build_action_list(&render_quality),
     )
-    .unwrap();
-    event_loop.run(move |event, _, control_flow| {
-        match event {
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            }
-            | Event::WindowEvent {
-                event:
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    },
-                ..
-            } => {
-                *control_flow = ControlFlow::Exit;
+}
+
+fn parse_backend() -> Result<Backend, String> {
+    match find_arg("--backend=") {
+        Some(name) => name.parse(),
+        None => Ok(Backend::available()[0]),
+    }
+}
+
+/// `--watermark=<png>` draws the image over the scene, in the corner set by
+/// `--watermark-corner=<top-left|top-right|bottom-left|bottom-right>` and at
+/// `--watermark-opacity=<0 to 1>`.
+fn watermark_from_args() -> Result<Option<Watermark>, String> {
+    let path = match find_arg("--watermark=") {
+        Some(path) => PathBuf::from(path),
+        None => return Ok(None),
+    };
+    #[cfg(feature = "png")]
+    let image = images::read_png(&path);
+    #[cfg(not(feature = "png"))]
+    let image: Result<fluster_core::types::basic::Bitmap, String> =
+        Err("Watermarks require the png feature".into());
+    let image = image.map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+    let mut watermark = Watermark::new(image);
+    if let Some(corner) = find_arg("--watermark-corner=") {
+        watermark.corner = match corner.as_str() {
+            "top-left" => Corner::TopLeft,
+            "top-right" => Corner::TopRight,
+            "bottom-left" => Corner::BottomLeft,
+            "bottom-right" => Corner::BottomRight,
+            _ => return Err(format!("Unknown watermark corner {}", corner)),
+        };
+    }
+    if let Some(opacity) = find_arg("--watermark-opacity=") {
+        watermark.opacity = opacity
+            .parse()
+            .map_err(|_| format!("Invalid watermark opacity {}", opacity))?;
+    }
+    Ok(Some(watermark))
+}
+
+fn find_args(prefix: &str) -> Vec<String> {
+    env::args()
+        .skip(1)
+        .filter_map(|arg| arg.strip_prefix(prefix).map(|value| value.to_string()))
+        .collect()
+}
+
+/// `--mirror=<count>` shows the whole stage in every window, `--span=<columns>x<rows>` splits it
+/// into a grid of windows, and each `--viewport=<x>,<y>,<width>,<height>` opens a window showing
+/// that rect of the stage.
+fn viewports_from_args(stage_size: Vector2F) -> Result<Option<Vec<RectF>>, String> {
+    let parse_count = |value: &str| {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|count| *count > 0)
+            .ok_or_else(|| format!("Invalid window count {}", value))
+    };
+    if let Some(count) = find_arg("--mirror=") {
+        let count = parse_count(&count)?;
+        return Ok(Some(
+            (0..count)
+                .map(|_| RectF::new(Vector2F::zero(), stage_size))
+                .collect(),
+        ));
+    }
+    if let Some(grid) = find_arg("--span=") {
+        let (columns, rows) = match grid.splitn(2, 'x').collect::<Vec<&str>>().as_slice() {
+            [columns, rows] => (parse_count(columns)?, parse_count(rows)?),
+            _ => return Err(format!("Invalid grid {}, expected <columns>x<rows>", grid)),
+        };
+        let size = stage_size / Vector2F::new(columns as f32, rows as f32);
+        return Ok(Some(
+            (0..rows)
+                .flat_map(|row| {
+                    (0..columns).map(move |column| {
+                        RectF::new(size * Vector2F::new(column as f32, row as f32), size)
+                    })
+                })
+                .collect(),
+        ));
+    }
+    let viewports = find_args("--viewport=")
+        .iter()
+        .map(|rect| {
+            let values = rect
+                .split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|_| format!("Invalid viewport {}", rect))?;
+            match values.as_slice() {
+                [x, y, width, height] if *width > 0.0 && *height > 0.0 => Ok(RectF::new(
+                    Vector2F::new(*x, *y),
+                    Vector2F::new(*width, *height),
+                )),
+                _ => Err(format!(
+                    "Invalid viewport {}, expected <x>,<y>,<width>,<height>",
+                    rect
+                )),
             }
-            _ => {
-                *control_flow = match runner.next_frame(&mut fluster_renderer, &mut action_list) {
-                    Ok(FrameResult::Wait(until)) => ControlFlow::WaitUntil(until),
-                    Ok(FrameResult::Continue) => ControlFlow::Poll,
-                    Ok(FrameResult::Quit) => ControlFlow::Exit,
-                    Err(error) => {
-                        println!("{}", error);
-                        ControlFlow::Exit
-                    }
-                };
+        })
+        .collect::<Result<Vec<RectF>, String>>()?;
+    Ok(if viewports.is_empty() {
+        None
+    } else {
+        Some(viewports)
+    })
+}
+
+fn main() {
+    env_logger::init();
+    let backend = match parse_backend() {
+        Ok(backend) => backend,
+        Err(error) => {
+            error!("{}", error);
+            return;
+        }
+    };
+    let window_size = Vector2I::new(800, 600);
+    if env::args().any(|arg| arg == "--headless") {
+        #[cfg(feature = "backend-gl")]
+        let result = run_headless(window_size);
+        #[cfg(not(feature = "backend-gl"))]
+        let result: Result<(), String> = Err("--headless requires the backend-gl feature".into());
+        if let Err(error) = result {
+            error!("{}", error);
+            process::exit(1);
+        }
+        return;
+    }
+    let viewports = match viewports_from_args(window_size.to_f32()) {
+        Ok(viewports) => viewports,
+        Err(error) => {
+            error!("{}", error);
+            return;
+        }
+    };
+    let watermark = match watermark_from_args() {
+        Ok(watermark) => watermark,
+        Err(error) => {
+            error!("{}", error);
+            return;
+        }
+    };
+    info!("Using {:?} backend", backend);
+    let event_loop = EventLoop::new();
+    // Fullscreen windows are placed on the monitors in order, wrapping around
+    let monitors = if env::args().any(|arg| arg == "--fullscreen") {
+        event_loop
+            .available_monitors()
+            .collect::<Vec<MonitorHandle>>()
+    } else {
+        vec![]
+    };
+    let stage_rects = viewports
+        .clone()
+        .unwrap_or_else(|| vec![RectF::new(Vector2F::zero(), window_size.to_f32())]);
+    let window_builders = stage_rects
+        .iter()
+        .enumerate()
+        .map(|(index, stage_rect)| {
+            // The stage is sized in logical pixels, and drawn at the display's scale factor
+            let window_builder = WindowBuilder::new()
+                .with_title("Fluster Player")
+                .with_inner_size(LogicalSize::new(
+                    stage_rect.width() as f64,
+                    stage_rect.height() as f64,
+                ));
+            if monitors.is_empty() {
+                window_builder
+            } else {
+                let monitor = monitors[index % monitors.len()].clone();
+                window_builder.with_fullscreen(Some(Fullscreen::Borderless(monitor)))
             }
-        };
-    });
+        })
+        .collect();
+
+    backend::start(
+        backend,
+        event_loop,
+        window_builders,
+        window_size,
+        Player {
+            viewports,
+            watermark,
+        },
+    );
 }
@@ -0,0 +1,113 @@
+use fluster_core::{actions::ActionList, runner::Runner};
+use log::{info, warn};
+use rosc::{decoder, OscMessage, OscPacket, OscType};
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+};
+
+/// Port the control server listens on when `--remote-port=` isn't given.
+pub const DEFAULT_PORT: u16 = 9000;
+// Large enough for any message the server understands
+const MAX_PACKET_SIZE: usize = 1536;
+
+/// Lets external software drive playback over OSC, for live performances and installations.
+/// Understands:
+///
+/// - `/fluster/play`
+/// - `/fluster/pause`
+/// - `/fluster/seek <frame: int>`
+/// - `/fluster/label <label: string>`
+/// - `/fluster/flag <flag: string> <value: bool or int>`
+/// - `/fluster/event <event: string>`
+pub struct RemoteControl {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+impl RemoteControl {
+    pub fn bind(port: u16) -> Result<Self, String> {
+        let address = SocketAddr::from(([0, 0, 0, 0], port));
+        let socket = UdpSocket::bind(address).map_err(|error| error.to_string())?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|error| error.to_string())?;
+        info!("Listening for OSC on {}", address);
+        Ok(Self {
+            socket,
+            buffer: vec![0; MAX_PACKET_SIZE],
+        })
+    }
+
+    /// Applies every message received since the last call. Malformed messages are logged and
+    /// dropped, they never stop playback.
+    pub fn poll(&mut self, runner: &mut Runner, actions: &mut ActionList) {
+        loop {
+            let size = match self.socket.recv_from(&mut self.buffer) {
+                Ok((size, _)) => size,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+                Err(error) => {
+                    warn!("Remote control stopped receiving: {}", error);
+                    return;
+                }
+            };
+            match decoder::decode(&self.buffer[..size]) {
+                Ok(packet) => apply_packet(packet, runner, actions),
+                Err(error) => warn!("Dropped malformed OSC packet: {:?}", error),
+            }
+        }
+    }
+}
+
+fn apply_packet(packet: OscPacket, runner: &mut Runner, actions: &mut ActionList) {
+    match packet {
+        OscPacket::Message(message) => {
+            if let Err(error) = apply_message(&message, runner, actions) {
+                warn!("{} failed: {}", message.addr, error);
+            }
+        }
+        // TODO: honor bundle time tags
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                apply_packet(packet, runner, actions);
+            }
+        }
+    }
+}
+
+fn apply_message(
+    message: &OscMessage,
+    runner: &mut Runner,
+    actions: &mut ActionList,
+) -> Result<(), String> {
+    let args = &message.args;
+    match message.addr.as_str() {
+        "/fluster/play" => runner.set_paused(false),
+        "/fluster/pause" => runner.set_paused(true),
+        "/fluster/seek" => match args.get(0) {
+            Some(OscType::Int(frame)) if *frame >= 0 => {
+                runner.seek_to_frame(actions, *frame as u32)?
+            }
+            _ => return Err("expected a frame".to_string()),
+        },
+        "/fluster/label" => match args.get(0) {
+            Some(OscType::String(label)) => runner.goto_label(actions, label)?,
+            _ => return Err("expected a label".to_string()),
+        },
+        "/fluster/flag" => match (args.get(0), args.get(1)) {
+            (Some(OscType::String(flag)), Some(OscType::Bool(value))) => {
+                runner.set_state_flag(flag, *value)
+            }
+            (Some(OscType::String(flag)), Some(OscType::Int(value))) => {
+                runner.set_state_flag(flag, *value != 0)
+            }
+            _ => return Err("expected a flag and a value".to_string()),
+        },
+        "/fluster/event" => match args.get(0) {
+            Some(OscType::String(event)) => runner.fire_state_event(event),
+            _ => return Err("expected an event".to_string()),
+        },
+        _ => return Err("unknown address".to_string()),
+    }
+    Ok(())
+}